@@ -0,0 +1,29 @@
+// Also compiled with depth testing by `composite_overlay_depth_test.hlsl`
+#ifndef COMPOSITE_OVERLAY_DEPTH_TEST
+    #define COMPOSITE_OVERLAY_DEPTH_TEST 0
+#endif
+
+[[vk::binding(0)]] Texture2D<float4> main_tex;
+[[vk::binding(1)]] Texture2D<float4> overlay_tex;
+[[vk::binding(2)]] RWTexture2D<float4> output_tex;
+
+#if COMPOSITE_OVERLAY_DEPTH_TEST
+    [[vk::binding(3)]] Texture2D<float> main_depth_tex;
+    [[vk::binding(4)]] Texture2D<float> overlay_depth_tex;
+#endif
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float4 main = main_tex[px];
+    float4 overlay = overlay_tex[px];
+
+    // Reverse-Z: larger is closer. Overlay pixels without depth are always visible.
+    #if COMPOSITE_OVERLAY_DEPTH_TEST
+        const float overlay_depth = overlay_depth_tex[px];
+        if (overlay_depth != 0.0 && overlay_depth < main_depth_tex[px]) {
+            overlay = 0.0;
+        }
+    #endif
+
+    output_tex[px] = float4(main.rgb * (1.0 - overlay.a) + overlay.rgb, main.a);
+}
@@ -0,0 +1,2 @@
+#define COMPOSITE_OVERLAY_DEPTH_TEST 1
+#include "composite_overlay.hlsl"
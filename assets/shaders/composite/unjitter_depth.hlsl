@@ -0,0 +1,21 @@
+#include "../inc/frame_constants.hlsl"
+
+[[vk::binding(0)]] Texture2D<float> depth_tex;
+[[vk::binding(1)]] RWTexture2D<float> output_tex;
+[[vk::binding(2)]] cbuffer _ {
+    float4 depth_tex_size;
+    float4 output_tex_size;
+};
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float2 uv = (px + 0.5) * output_tex_size.zw;
+
+    // The jittered texel covering this point of the un-jittered image.
+    // Same convention as in `sample_image_unjitter_taa`.
+    const float2 src_pos = uv * depth_tex_size.xy
+        - frame_constants.view_constants.sample_offset_pixels * float2(1, -1);
+    const int2 src_px = clamp(int2(floor(src_pos)), 0, int2(depth_tex_size.xy) - 1);
+
+    output_tex[px] = depth_tex[src_px];
+}
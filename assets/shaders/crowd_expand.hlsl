@@ -0,0 +1,53 @@
+// Writes the instances of a crowd into its range of the instance batch transforms.
+
+#include "inc/frame_constants.hlsl"
+
+// Must match `CrowdInstance` in `crowd.rs`
+struct CrowdInstance {
+    row_major float3x4 transform;
+    float3 velocity;
+    uint pad;
+};
+
+struct InstanceTransform {
+    row_major float3x4 current;
+    row_major float3x4 previous;
+};
+
+[[vk::binding(0)]] StructuredBuffer<CrowdInstance> crowd_instances;
+[[vk::binding(1)]] RWStructuredBuffer<InstanceTransform> instance_batch_transforms;
+[[vk::binding(2)]] cbuffer _ {
+    uint transform_offset;
+    uint capacity;
+    uint count;
+    uint moved;
+};
+
+[numthreads(64, 1, 1)]
+void main(uint idx: SV_DispatchThreadID) {
+    if (idx >= capacity) {
+        return;
+    }
+
+    InstanceTransform res;
+
+    if (idx < count) {
+        const CrowdInstance inst = crowd_instances[idx];
+        res.current = inst.transform;
+        res.previous = inst.transform;
+
+        // Only the translation is extrapolated back; rotation doesn't contribute to motion vectors.
+        if (moved) {
+            const float3 prev_offset = -inst.velocity * frame_constants.delta_time_seconds;
+            res.previous[0][3] += prev_offset.x;
+            res.previous[1][3] += prev_offset.y;
+            res.previous[2][3] += prev_offset.z;
+        }
+    } else {
+        // Hidden: degenerate, so nothing gets rasterized, and rays miss.
+        res.current = (float3x4)0;
+        res.previous = (float3x4)0;
+    }
+
+    instance_batch_transforms[transform_offset + idx] = res;
+}
@@ -4,27 +4,54 @@
 [[vk::binding(0)]] Texture2D<float> depth_tex;
 [[vk::binding(1)]] RWTexture2D<float> output_tex;
 [[vk::binding(2)]] RWTexture2D<float> tile_output_tex;
+[[vk::binding(3)]] cbuffer _ {
+    float4 output_tex_size;
+    float max_coc_px;
+    uint autofocus;
+};
 
 groupshared uint max_abs_coc_asuint;
 
-float coc_size(float depth, float focal_point, float focus_scale) {
-    //return 0;
-	float coc = clamp((1.0 / focal_point - 1.0 / depth) * focus_scale, -0.3, 0.3);
-	return coc * 20.0;
+// Reciprocal of the view-space distance for a depth buffer value; zero for the sky.
+float depth_to_inv_view_distance(float depth) {
+    return depth * -frame_constants.view_constants.clip_to_view._43;
+}
+
+// Signed radius of the circle of confusion of a thin lens, in output pixels.
+// Negative in front of the focus plane, and positive behind it.
+float coc_radius_px(float inv_depth, float focus_distance) {
+    const ViewConstants view_constants = frame_constants.view_constants;
+
+    // Diameter on the sensor: A * f * (d - s) / (d * (s - f))
+    // The sensor height is `2 * f * tan(fov / 2)`; dividing by it cancels out `f`.
+    const float coc_over_sensor_height =
+        view_constants.aperture_diameter * (1.0 - focus_distance * inv_depth)
+        / (focus_distance - view_constants.focal_length)
+        * 0.5 * view_constants.view_to_clip[1][1];
+
+    return clamp(0.5 * coc_over_sensor_height * output_tex_size.y, -max_coc_px, max_coc_px);
 }
 
 [numthreads(8, 8, 1)]
 void main(uint2 px: SV_DispatchThreadID, uint idx_within_group: SV_GroupIndex) {
-    max_abs_coc_asuint = asuint(0.0);
+    if (0 == idx_within_group) {
+        max_abs_coc_asuint = asuint(0.0);
+    }
     GroupMemoryBarrierWithGroupSync();
 
-    float linear_depth = -depth_to_view_z(depth_tex[px]);
-    float max_coc = 20.0;
+    // The depth buffer is at render resolution, and this runs after upsampling.
+    const float2 uv = (px + 0.5) * output_tex_size.zw;
+    const float inv_depth = depth_to_inv_view_distance(depth_tex.SampleLevel(sampler_nnc, uv, 0));
+
+    float focus_distance = frame_constants.view_constants.focus_distance;
+    if (autofocus) {
+        const float focus_inv_depth = depth_to_inv_view_distance(depth_tex.SampleLevel(sampler_nnc, 0.5, 0));
+        focus_distance = max(
+            frame_constants.view_constants.focal_length * 1.001,
+            1.0 / max(1e-10, focus_inv_depth));
+    }
 
-    float focus = -depth_to_view_z(depth_tex.SampleLevel(sampler_nnc, 0.5, 0));
-    
-    //float coc = clamp((linear_depth - 1) * 20.0, -max_coc, max_coc);
-    float coc = coc_size(linear_depth, focus, 0.7);
+    const float coc = coc_radius_px(inv_depth, focus_distance);
 
     InterlockedMax(max_abs_coc_asuint, asuint(abs(coc)));
     GroupMemoryBarrierWithGroupSync();
@@ -33,5 +60,7 @@ void main(uint2 px: SV_DispatchThreadID, uint idx_within_group: SV_GroupIndex) {
         tile_output_tex[px / 8] = asfloat(max_abs_coc_asuint);
     }
 
-    output_tex[px] = coc;
+    if (all(px < uint2(output_tex_size.xy))) {
+        output_tex[px] = coc;
+    }
 }
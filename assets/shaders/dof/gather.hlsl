@@ -1,83 +1,87 @@
-#include "../inc/frame_constants.hlsl"
+#include "../inc/math_const.hlsl"
 #include "../inc/samplers.hlsl"
 
-[[vk::binding(0)]] Texture2D<float> depth_tex;
-[[vk::binding(1)]] Texture2D<float3> color_tex;
-[[vk::binding(2)]] Texture2D<float> coc_tex;
-[[vk::binding(3)]] Texture2D<float> coc_tiles_tex;
-[[vk::binding(4)]] RWTexture2D<float3> output_tex;
-[[vk::binding(5)]] cbuffer _ {
+[[vk::binding(0)]] Texture2D<float3> color_tex;
+[[vk::binding(1)]] Texture2D<float> coc_tex;
+[[vk::binding(2)]] Texture2D<float> coc_tiles_tex;
+[[vk::binding(3)]] RWTexture2D<float3> output_tex;
+[[vk::binding(4)]] cbuffer _ {
     float4 output_tex_size;
+    float near_field_bleed;
+    uint bokeh_blade_count;
+    float bokeh_rotation;
+    float bokeh_aspect_ratio;
 };
 
-static const float GOLDEN_ANGLE = 2.39996323; 
-static const float MAX_BLUR_SIZE = 20.0; 
-static const float RAD_SCALE = 0.4; // Smaller = nicer blur, larger = faster
+static const float GOLDEN_ANGLE = 2.39996323;
+static const uint MIN_SAMPLE_COUNT = 8;
+static const uint MAX_SAMPLE_COUNT = 160;
 
+// Distance to the edge of the bokeh along `dir`, relative to the radius of its circumcircle.
+float bokeh_edge_distance(float2 dir) {
+    if (bokeh_blade_count < 3) {
+        return 1.0;
+    }
+
+    const float blade_angle = 2.0 * M_PI / bokeh_blade_count;
+    const float angle = atan2(dir.y, dir.x) - bokeh_rotation;
+    const float angle_within_blade = angle - blade_angle * floor(angle / blade_angle);
+
+    return cos(0.5 * blade_angle) / cos(angle_within_blade - 0.5 * blade_angle);
+}
+
+// Scatter-as-gather: each pixel accumulates the neighbors whose circles of confusion
+// (shaped by the aperture) reach it.
 [numthreads(8, 8, 1)]
 void main(uint2 px: SV_DispatchThreadID) {
-    float2 uv = float2(px + 0.5) * output_tex_size.zw;
-    float3 color = color_tex[px];
-
-	float center_depth = depth_tex[px];
-	float center_size = abs(coc_tex[px]);
-	float tot = 1.0;
-	float radius = RAD_SCALE;
-
-    //float max_blur_size = MAX_BLUR_SIZE;
-    float max_blur_size = 0;
-    int k = 2;
-    for (int y = -k; y <= k; ++y) {
-        for (int x = -k; x <= k; ++x) {
-            max_blur_size = max(max_blur_size, coc_tiles_tex[px / 8 + int2(x, y)]);
-        }
+    const float2 uv = float2(px + 0.5) * output_tex_size.zw;
+    const float3 center_color = color_tex[px];
+    const float center_coc = coc_tex[px];
+    const float center_size = abs(center_coc);
+
+    const float max_blur_size = coc_tiles_tex[px / 8];
+
+    if (max_blur_size < 0.5) {
+        output_tex[px] = center_color;
+        return;
     }
-    
-#if 1
-	for (float ang = 0.0; radius < max_blur_size; ang += GOLDEN_ANGLE) {
-		float2 tc = uv + float2(cos(ang), sin(ang)) * output_tex_size.zw * radius;
-		float3 sampleColor = color_tex.SampleLevel(sampler_lnc, tc, 0);
-		float sampleDepth = depth_tex.SampleLevel(sampler_lnc, tc, 0);
-		float sampleSize = abs(coc_tex.SampleLevel(sampler_lnc, tc, 0));
-
-		if (sampleDepth < center_depth) {
-			sampleSize = clamp(sampleSize, 0.0, center_size*2.0);
-        }
 
-		float m = smoothstep(radius-0.5, radius+0.5, sampleSize);
-		color += lerp(color/tot, sampleColor, m);
-		tot += 1.0;
-        radius += RAD_SCALE/radius;
-	}
-#else
-    static const int sample_count = int(max_blur_size) * 6;
-
-    float ang = 0.0;
-	for (float i = 0; i < sample_count; ++i) {
-        float r = (i + 0.5) / sample_count;
-        r = sqrt(r);
-        r *= max_blur_size;
-
-		float2 tc = uv + float2(cos(ang), sin(ang)) * output_tex_size.zw * r;
-		float3 sampleColor = color_tex.SampleLevel(sampler_lnc, tc, 0);
-		float sampleDepth = depth_tex.SampleLevel(sampler_lnc, tc, 0);
-		float sampleSize = abs(coc_tex.SampleLevel(sampler_lnc, tc, 0));
-
-		if (sampleDepth < center_depth) {
-			sampleSize = clamp(sampleSize, 0.0, center_size*2.0);
+    // Vogel disk over the largest circle of confusion in the neighborhood,
+    // with about one sample per pixel of area, within budget.
+    const uint sample_count = clamp(uint(max_blur_size * max_blur_size * 0.75), MIN_SAMPLE_COUNT, MAX_SAMPLE_COUNT);
+    const float sample_spacing = max_blur_size * rsqrt(float(sample_count));
+
+    float3 color_sum = center_color;
+    float weight_sum = 1.0;
+
+    for (uint i = 0; i < sample_count; ++i) {
+        const float r = sqrt((i + 0.5) / sample_count) * max_blur_size;
+        const float ang = i * GOLDEN_ANGLE;
+        const float2 offset = float2(cos(ang), sin(ang)) * r;
+
+        const float2 tc = uv + offset * output_tex_size.zw;
+        const float3 sample_color = color_tex.SampleLevel(sampler_lnc, tc, 0);
+        const float sample_coc = coc_tex.SampleLevel(sampler_lnc, tc, 0);
+        float sample_size = abs(sample_coc);
+
+        // Content behind the center can't blur over it any more than the center itself is blurred;
+        // content in front can, by as much as `near_field_bleed` allows.
+        const float occluded_size = min(sample_size, center_size * 2.0);
+        if (sample_coc > center_coc) {
+            sample_size = occluded_size;
+        } else {
+            sample_size = lerp(occluded_size, sample_size, near_field_bleed);
         }
 
-		float m = smoothstep(r-0.5, r+0.5, sampleSize);
-		color += lerp(color/tot, sampleColor, m);
-		tot += 1.0;
-        ang += GOLDEN_ANGLE;
-	}
-#endif
+        // Distance from the sample, measured in the shape of its bokeh.
+        // Anamorphic bokeh are squeezed vertically, to stay within the gather radius.
+        const float2 shape_offset = offset * float2(1.0, bokeh_aspect_ratio);
+        const float shape_dist = length(shape_offset) / bokeh_edge_distance(shape_offset);
 
-    color /= tot;
-    //color = neutral_tonemap(color);
-    //color = 1 - exp(-color);
-    //color = pow(color, 1.05);
+        const float m = smoothstep(shape_dist - sample_spacing * 0.5, shape_dist + sample_spacing * 0.5, sample_size);
+        color_sum += lerp(color_sum / weight_sum, sample_color, m);
+        weight_sum += 1.0;
+    }
 
-    output_tex[px] = color;
+    output_tex[px] = color_sum / weight_sum;
 }
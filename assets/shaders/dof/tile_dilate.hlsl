@@ -0,0 +1,22 @@
+[[vk::binding(0)]] Texture2D<float> input_tex;
+[[vk::binding(1)]] RWTexture2D<float> output_tex;
+[[vk::binding(2)]] cbuffer _ {
+    int2 tile_count;
+    int dilation_radius;
+};
+
+// Spreads the largest circle of confusion to all tiles it can reach,
+// so that the gather knows how far out to look for blurry neighbors.
+[numthreads(8, 8, 1)]
+void main(int2 px: SV_DispatchThreadID) {
+    float max_coc = 0.0;
+
+    for (int y = -dilation_radius; y <= dilation_radius; ++y) {
+        for (int x = -dilation_radius; x <= dilation_radius; ++x) {
+            const int2 src_px = clamp(px + int2(x, y), 0, tile_count - 1);
+            max_coc = max(max_coc, input_tex[src_px]);
+        }
+    }
+
+    output_tex[px] = max_coc;
+}
@@ -4,10 +4,21 @@
 [[vk::binding(3)]] cbuffer _ {
     float4 main_tex_size;
     float4 output_tex_size;
+    uint output_color_space;
+    float paper_white_nits;
 };
 
 #include "inc/image.hlsl"
 #include "inc/color/srgb.hlsl"
+#include "inc/color/ictcp.hlsl"
+
+// Must match `post::output_transform`
+#define OUTPUT_COLOR_SPACE_SRGB 0
+#define OUTPUT_COLOR_SPACE_SCRGB 1
+#define OUTPUT_COLOR_SPACE_HDR10 2
+
+// scRGB has 1.0 at 80 nits.
+static const float SCRGB_WHITE_NITS = 80.0;
 
 struct LinearToSrgbRemap {
     static LinearToSrgbRemap create() {
@@ -20,23 +31,54 @@ struct LinearToSrgbRemap {
     }
 };
 
+// Takes linear Rec. 709 color, with 1.0 at paper white.
+float3 encode_hdr_output(float3 col) {
+    if (OUTPUT_COLOR_SPACE_SCRGB == output_color_space) {
+        return col * (paper_white_nits / SCRGB_WHITE_NITS);
+    } else {
+        const float3 rec2020 = max(0.0, BT709_to_BT2020(col));
+        return linear_to_PQ(rec2020 * paper_white_nits);
+    }
+}
+
 [numthreads(8, 8, 1)]
 void main(in uint2 px : SV_DispatchThreadID) {
     #if 1
-    float3 main;
-    if (any(main_tex_size.xy != output_tex_size.xy)) {
-        main = image_sample_catmull_rom(
-            TextureImage::from_parts(main_tex, main_tex_size.xy),
-            (px + 0.5) / output_tex_size.xy,
-            LinearToSrgbRemap::create()
-        ).rgb;
-    } else {
-        main = sRGB_OETF(saturate(main_tex[px].rgb));
-    }
+    const bool resample = any(main_tex_size.xy != output_tex_size.xy);
     float4 gui = gui_tex[px];
+    float3 result;
+
+    [branch]
+    if (OUTPUT_COLOR_SPACE_SRGB == output_color_space) {
+        float3 main;
+        if (resample) {
+            main = image_sample_catmull_rom(
+                TextureImage::from_parts(main_tex, main_tex_size.xy),
+                (px + 0.5) / output_tex_size.xy,
+                LinearToSrgbRemap::create()
+            ).rgb;
+        } else {
+            main = sRGB_OETF(saturate(main_tex[px].rgb));
+        }
 
-    float3 result = main.rgb * (1.0 - gui.a) + gui.rgb;
-    //float3 result = lerp(main, gui.rgb, gui.a);
+        result = main.rgb * (1.0 - gui.a) + gui.rgb;
+        //float3 result = lerp(main, gui.rgb, gui.a);
+    } else {
+        // Resampled and composited in linear, as the main image is not limited to `[0, 1]`.
+        float3 main;
+        if (resample) {
+            main = max(0.0, image_sample_catmull_rom(
+                TextureImage::from_parts(main_tex, main_tex_size.xy),
+                (px + 0.5) / output_tex_size.xy,
+                IdentityImageRemap::create()
+            ).rgb);
+        } else {
+            main = main_tex[px].rgb;
+        }
+
+        // The GUI is premultiplied and sRGB-encoded, and shown at paper white.
+        result = encode_hdr_output(main * (1.0 - gui.a) + sRGB_EOTF(gui.rgb));
+    }
     #else
     float3 result = float3(0.7, 0.4, 0.1);
     #endif
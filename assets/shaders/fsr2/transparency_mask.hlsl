@@ -0,0 +1,19 @@
+#include "../inc/gbuffer.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> gbuffer_tex;
+[[vk::binding(1)]] RWTexture2D<float> output_tex;
+
+// FSR 2's transparency and composition mask: surfaces whose shading includes light
+// from behind them, and which shouldn't be reconstructed as fully opaque.
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float4 gbuffer_packed = gbuffer_tex[px];
+
+    if (all(gbuffer_packed == 0.0)) {
+        output_tex[px] = 0.0;
+        return;
+    }
+
+    const GbufferData gbuffer = GbufferDataPacked::from_uint4(asuint(gbuffer_packed)).unpack();
+    output_tex[px] = saturate(gbuffer.transmission);
+}
@@ -0,0 +1,23 @@
+[[vk::binding(0)]] RWStructuredBuffer<uint2> entries;
+[[vk::binding(1)]] RWStructuredBuffer<uint2> tile_ranges;
+[[vk::binding(2)]] RWStructuredBuffer<uint> entry_counter;
+[[vk::binding(3)]] cbuffer _ {
+    uint entry_capacity;
+    uint tile_count;
+};
+
+[numthreads(64, 1, 1)]
+void main(uint idx: SV_DispatchThreadID) {
+    // Unused entries sort to the end.
+    if (idx < entry_capacity) {
+        entries[idx] = uint2(0xffffffff, 0);
+    }
+
+    if (idx < tile_count) {
+        tile_ranges[idx] = 0;
+    }
+
+    if (idx == 0) {
+        entry_counter[0] = 0;
+    }
+}
@@ -0,0 +1,132 @@
+// Projects Gaussians to screen-space ellipses, following "3D Gaussian Splatting
+// for Real-Time Radiance Field Rendering" by Kerbl et al., and emits a sort entry
+// for every tile each of them overlaps.
+
+#include "../inc/frame_constants.hlsl"
+#include "../inc/uv.hlsl"
+#include "splat.hlsl"
+
+[[vk::binding(0)]] StructuredBuffer<GaussianSplatPacked> splats;
+[[vk::binding(1)]] RWStructuredBuffer<ProjectedSplat> projected_splats;
+[[vk::binding(2)]] RWStructuredBuffer<uint2> entries;
+[[vk::binding(3)]] RWStructuredBuffer<uint> entry_counter;
+[[vk::binding(4)]] cbuffer _ {
+    // Object to world, row-major
+    float4 transform_rows[3];
+    uint2 output_extent;
+    uint2 tile_count;
+    uint splat_count;
+    uint output_offset;
+    uint entry_capacity;
+    float brightness;
+};
+
+static const float NEAR_CLIP = 0.01;
+
+// Sorts by view depth within each tile; logarithmic to keep precision up close.
+uint depth_sort_key(float view_depth) {
+    return uint(saturate(log2(1.0 + view_depth) / 24.0) * 65535.0);
+}
+
+[numthreads(64, 1, 1)]
+void main(uint idx: SV_DispatchThreadID) {
+    if (idx >= splat_count) {
+        return;
+    }
+
+    const GaussianSplatPacked splat = splats[idx];
+    const float3x4 xform = float3x4(transform_rows[0], transform_rows[1], transform_rows[2]);
+
+    const float3 pos_ws = mul(xform, float4(splat.position_opacity.xyz, 1));
+    const float3 pos_vs = position_world_to_view(pos_ws);
+    const float view_depth = -pos_vs.z;
+
+    if (view_depth < NEAR_CLIP) {
+        return;
+    }
+
+    const float4x4 view_to_clip = frame_constants.view_constants.view_to_clip;
+    const float4 pos_cs = mul(view_to_clip, float4(pos_vs, 1));
+    const float2 pos_ndc = pos_cs.xy / pos_cs.w;
+
+    // Generous, since large splats centered off-screen can still cover it.
+    if (any(abs(pos_ndc) > 1.3)) {
+        return;
+    }
+
+    const float2 center = cs_to_uv(pos_ndc) * output_extent;
+
+    const float3x3 cov_os = float3x3(
+        splat.cov_xx_xy_xz_yy.x, splat.cov_xx_xy_xz_yy.y, splat.cov_xx_xy_xz_yy.z,
+        splat.cov_xx_xy_xz_yy.y, splat.cov_xx_xy_xz_yy.w, splat.cov_yz_zz.x,
+        splat.cov_xx_xy_xz_yy.z, splat.cov_yz_zz.x, splat.cov_yz_zz.y
+    );
+
+    const float3x3 object_to_view = mul(
+        (float3x3)frame_constants.view_constants.world_to_view,
+        (float3x3)xform
+    );
+
+    // Jacobian of the projection to pixels, linearized at the center
+    const float2 focal = float2(view_to_clip[0][0], view_to_clip[1][1])
+        * float2(output_extent) * float2(0.5, -0.5);
+    const float3x3 jacobian = float3x3(
+        focal.x / view_depth, 0, focal.x * pos_vs.x / (view_depth * view_depth),
+        0, focal.y / view_depth, focal.y * pos_vs.y / (view_depth * view_depth),
+        0, 0, 0
+    );
+
+    const float3x3 to_screen = mul(jacobian, object_to_view);
+    const float3x3 cov_ss = mul(to_screen, mul(cov_os, transpose(to_screen)));
+
+    // Dilate by a pixel, so that tiny splats don't alias.
+    const float3 cov2d = float3(cov_ss[0][0] + 0.3, cov_ss[0][1], cov_ss[1][1] + 0.3);
+    const float det = cov2d.x * cov2d.z - cov2d.y * cov2d.y;
+
+    if (det <= 0.0) {
+        return;
+    }
+
+    const float3 conic = float3(cov2d.z, -cov2d.y, cov2d.x) / det;
+
+    // Three standard deviations along the major axis
+    const float mid = 0.5 * (cov2d.x + cov2d.z);
+    const float major_variance = mid + sqrt(max(0.1, mid * mid - det));
+    const float radius = ceil(3.0 * sqrt(major_variance));
+
+    const int2 tile_min = max(0, int2(floor((center - radius) / GAUSSIAN_SPLAT_TILE_SIZE)));
+    const int2 tile_max = min(
+        int2(tile_count) - 1,
+        int2(floor((center + radius) / GAUSSIAN_SPLAT_TILE_SIZE))
+    );
+
+    if (any(tile_max < tile_min)) {
+        return;
+    }
+
+    const uint2 tiles = uint2(tile_max - tile_min + 1);
+    const uint entry_count = tiles.x * tiles.y;
+
+    uint first_entry;
+    InterlockedAdd(entry_counter[0], entry_count, first_entry);
+
+    if (first_entry + entry_count > entry_capacity) {
+        return;
+    }
+
+    const uint projected_idx = output_offset + idx;
+
+    ProjectedSplat projected;
+    projected.center_depth_opacity = float4(center, pos_cs.z / pos_cs.w, splat.position_opacity.w);
+    projected.conic = float4(conic, 0);
+    projected.color = float4(splat.color.rgb * brightness * frame_constants.pre_exposure, 0);
+    projected_splats[projected_idx] = projected;
+
+    const uint depth_key = depth_sort_key(view_depth);
+
+    for (uint i = 0; i < entry_count; ++i) {
+        const uint2 tile = uint2(tile_min) + uint2(i % tiles.x, i / tiles.x);
+        const uint tile_idx = tile.x + tile.y * tile_count.x;
+        entries[first_entry + i] = uint2((tile_idx << 16) | depth_key, projected_idx);
+    }
+}
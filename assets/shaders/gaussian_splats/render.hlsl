@@ -0,0 +1,64 @@
+// Blends the splats of each tile front to back over the lit frame, stopping at the first one
+// behind the opaque geometry, or once the pixel is opaque.
+
+#include "splat.hlsl"
+
+[[vk::binding(0)]] StructuredBuffer<ProjectedSplat> projected_splats;
+[[vk::binding(1)]] StructuredBuffer<uint2> entries;
+[[vk::binding(2)]] StructuredBuffer<uint2> tile_ranges;
+[[vk::binding(3)]] Texture2D<float> depth_tex;
+[[vk::binding(4)]] RWTexture2D<float4> output_tex;
+[[vk::binding(5)]] cbuffer _ {
+    uint2 output_extent;
+    uint tile_count_x;
+};
+
+[numthreads(GAUSSIAN_SPLAT_TILE_SIZE, GAUSSIAN_SPLAT_TILE_SIZE, 1)]
+void main(uint2 px: SV_DispatchThreadID, uint2 tile: SV_GroupID) {
+    if (any(px >= output_extent)) {
+        return;
+    }
+
+    const uint2 range = tile_ranges[tile.x + tile.y * tile_count_x];
+    if (range.x == range.y) {
+        return;
+    }
+
+    const float scene_depth = depth_tex[px];
+    const float2 pos = px + 0.5;
+
+    float3 radiance = 0;
+    float transmittance = 1;
+
+    for (uint i = range.x; i < range.y; ++i) {
+        const ProjectedSplat splat = projected_splats[entries[i].y];
+
+        // Reverse-Z; the rest of the splats are further away still.
+        if (splat.center_depth_opacity.z < scene_depth) {
+            break;
+        }
+
+        const float2 offset = pos - splat.center_depth_opacity.xy;
+        const float power = -0.5 * (splat.conic.x * offset.x * offset.x + splat.conic.z * offset.y * offset.y)
+            - splat.conic.y * offset.x * offset.y;
+
+        if (power > 0.0) {
+            continue;
+        }
+
+        const float alpha = min(0.99, splat.center_depth_opacity.w * exp(power));
+        if (alpha < 1.0 / 255.0) {
+            continue;
+        }
+
+        radiance += splat.color.rgb * alpha * transmittance;
+        transmittance *= 1.0 - alpha;
+
+        if (transmittance < 1e-4) {
+            break;
+        }
+    }
+
+    const float4 scene = output_tex[px];
+    output_tex[px] = float4(radiance + scene.rgb * transmittance, scene.a);
+}
@@ -0,0 +1,22 @@
+// One step of a bitonic merge, for strides too large for `sort_local.hlsl`.
+
+[[vk::binding(0)]] RWStructuredBuffer<uint2> entries;
+[[vk::binding(1)]] cbuffer _ {
+    uint merge_size;
+    uint stride;
+};
+
+[numthreads(256, 1, 1)]
+void main(uint idx: SV_DispatchThreadID) {
+    const uint i = ((idx & ~(stride - 1)) << 1) | (idx & (stride - 1));
+    const uint j = i + stride;
+    const bool ascending = (i & merge_size) == 0;
+
+    const uint2 a = entries[i];
+    const uint2 b = entries[j];
+
+    if ((a.x > b.x) == ascending && a.x != b.x) {
+        entries[i] = b;
+        entries[j] = a;
+    }
+}
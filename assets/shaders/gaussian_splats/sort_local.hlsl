@@ -0,0 +1,47 @@
+// Bitonic sort of blocks of entries in group shared memory. With `merge_size` of zero,
+// sorts each block in alternating directions; otherwise finishes merging sequences of
+// `merge_size`, once `sort_global.hlsl` has brought the strides down to the block size.
+
+#define THREAD_GROUP_SIZE 512
+#define BLOCK_SIZE (THREAD_GROUP_SIZE * 2)
+
+[[vk::binding(0)]] RWStructuredBuffer<uint2> entries;
+[[vk::binding(1)]] cbuffer _ {
+    uint merge_size;
+};
+
+groupshared uint2 shared_entries[BLOCK_SIZE];
+
+[numthreads(THREAD_GROUP_SIZE, 1, 1)]
+void main(uint idx: SV_GroupThreadID, uint block: SV_GroupID) {
+    const uint base = block * BLOCK_SIZE;
+
+    shared_entries[idx] = entries[base + idx];
+    shared_entries[idx + THREAD_GROUP_SIZE] = entries[base + idx + THREAD_GROUP_SIZE];
+
+    GroupMemoryBarrierWithGroupSync();
+
+    const uint first_size = merge_size == 0 ? 2 : merge_size;
+    const uint last_size = merge_size == 0 ? BLOCK_SIZE : merge_size;
+
+    for (uint size = first_size; size <= last_size; size *= 2) {
+        for (uint stride = min(size, BLOCK_SIZE) / 2; stride > 0; stride /= 2) {
+            const uint i = ((idx & ~(stride - 1)) << 1) | (idx & (stride - 1));
+            const uint j = i + stride;
+            const bool ascending = ((base + i) & size) == 0;
+
+            const uint2 a = shared_entries[i];
+            const uint2 b = shared_entries[j];
+
+            if ((a.x > b.x) == ascending && a.x != b.x) {
+                shared_entries[i] = b;
+                shared_entries[j] = a;
+            }
+
+            GroupMemoryBarrierWithGroupSync();
+        }
+    }
+
+    entries[base + idx] = shared_entries[idx];
+    entries[base + idx + THREAD_GROUP_SIZE] = shared_entries[idx + THREAD_GROUP_SIZE];
+}
@@ -0,0 +1,24 @@
+#ifndef GAUSSIAN_SPLATS_SPLAT_HLSL
+#define GAUSSIAN_SPLATS_SPLAT_HLSL
+
+#define GAUSSIAN_SPLAT_TILE_SIZE 16
+
+// Uploaded by `renderers/gaussian_splats.rs`
+struct GaussianSplatPacked {
+    float4 position_opacity;
+    // Object-space covariance
+    float4 cov_xx_xy_xz_yy;
+    float4 cov_yz_zz;
+    float4 color;
+};
+
+struct ProjectedSplat {
+    // Pixel position of the center, reverse-Z depth, and opacity
+    float4 center_depth_opacity;
+    // Inverse of the 2D covariance, as `xx, xy, yy`
+    float4 conic;
+    // Pre-exposed radiance
+    float4 color;
+};
+
+#endif  // GAUSSIAN_SPLATS_SPLAT_HLSL
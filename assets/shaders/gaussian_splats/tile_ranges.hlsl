@@ -0,0 +1,32 @@
+// Finds the range of sorted entries belonging to each tile.
+
+[[vk::binding(0)]] StructuredBuffer<uint2> entries;
+[[vk::binding(1)]] StructuredBuffer<uint> entry_counter;
+[[vk::binding(2)]] RWStructuredBuffer<uint2> tile_ranges;
+[[vk::binding(3)]] cbuffer _ {
+    uint entry_capacity;
+    uint tile_count;
+};
+
+[numthreads(64, 1, 1)]
+void main(uint idx: SV_DispatchThreadID) {
+    const uint entry_count = min(entry_counter[0], entry_capacity);
+
+    if (idx >= entry_count) {
+        return;
+    }
+
+    // Splats which overflowed the capacity leave unused entries behind.
+    const uint tile = entries[idx].x >> 16;
+    if (tile >= tile_count) {
+        return;
+    }
+
+    if (idx == 0 || (entries[idx - 1].x >> 16) != tile) {
+        tile_ranges[tile].x = idx;
+    }
+
+    if (idx + 1 == entry_count || (entries[idx + 1].x >> 16) != tile) {
+        tile_ranges[tile].y = idx + 1;
+    }
+}
@@ -0,0 +1,21 @@
+#include "../inc/environment_map.hlsl"
+
+[[vk::binding(0)]] RWStructuredBuffer<float> cdf_buf;
+
+// Builds the CDF over rows from their total weights. Tiny, so done by a single thread.
+[numthreads(1, 1, 1)]
+void main() {
+    float sum = 0.0;
+    for (uint y = 0; y < ENVIRONMENT_MAP_CDF_HEIGHT; ++y) {
+        sum += cdf_buf[ENVIRONMENT_MAP_CDF_MARGINAL_OFFSET + y];
+        cdf_buf[ENVIRONMENT_MAP_CDF_MARGINAL_OFFSET + y] = sum;
+    }
+
+    for (uint y = 0; y < ENVIRONMENT_MAP_CDF_HEIGHT; ++y) {
+        cdf_buf[ENVIRONMENT_MAP_CDF_MARGINAL_OFFSET + y] = sum > 0.0
+            ? cdf_buf[ENVIRONMENT_MAP_CDF_MARGINAL_OFFSET + y] / sum
+            : float(y + 1) / ENVIRONMENT_MAP_CDF_HEIGHT;
+    }
+
+    cdf_buf[ENVIRONMENT_MAP_CDF_TOTAL_OFFSET] = sum;
+}
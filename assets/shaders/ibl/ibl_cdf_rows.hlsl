@@ -0,0 +1,29 @@
+#include "../inc/environment_map.hlsl"
+
+[[vk::binding(0)]] RWStructuredBuffer<float> cdf_buf;
+
+// Turns the weights of each row into a normalized CDF,
+// and stores the row's total weight for `ibl_cdf_marginal.hlsl`.
+[numthreads(64, 1, 1)]
+void main(in uint y : SV_DispatchThreadID) {
+    if (y >= ENVIRONMENT_MAP_CDF_HEIGHT) {
+        return;
+    }
+
+    const uint row_offset = y * ENVIRONMENT_MAP_CDF_WIDTH;
+
+    float sum = 0.0;
+    for (uint x = 0; x < ENVIRONMENT_MAP_CDF_WIDTH; ++x) {
+        sum += cdf_buf[row_offset + x];
+        cdf_buf[row_offset + x] = sum;
+    }
+
+    for (uint x = 0; x < ENVIRONMENT_MAP_CDF_WIDTH; ++x) {
+        // Black rows are never picked, but keep their CDFs valid regardless.
+        cdf_buf[row_offset + x] = sum > 0.0
+            ? cdf_buf[row_offset + x] / sum
+            : float(x + 1) / ENVIRONMENT_MAP_CDF_WIDTH;
+    }
+
+    cdf_buf[ENVIRONMENT_MAP_CDF_MARGINAL_OFFSET + y] = sum;
+}
@@ -0,0 +1,34 @@
+#include "../inc/color.hlsl"
+#include "../inc/environment_map.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> input_tex;
+[[vk::binding(1)]] RWStructuredBuffer<float> cdf_buf;
+
+[numthreads(8, 8, 1)]
+void main(in uint2 px : SV_DispatchThreadID) {
+    const uint2 cdf_dims = uint2(ENVIRONMENT_MAP_CDF_WIDTH, ENVIRONMENT_MAP_CDF_HEIGHT);
+    if (any(px >= cdf_dims)) {
+        return;
+    }
+
+    uint2 input_dims;
+    input_tex.GetDimensions(input_dims.x, input_dims.y);
+
+    // Average all the texels under the cell, so that small and bright features
+    // such as the sun don't slip between the cells.
+    const uint2 begin = px * input_dims / cdf_dims;
+    const uint2 end = max(begin + 1, (px + 1) * input_dims / cdf_dims);
+
+    float luminance = 0.0;
+    for (uint y = begin.y; y < end.y; ++y) {
+        for (uint x = begin.x; x < end.x; ++x) {
+            luminance += sRGB_to_luminance(max(0.0, input_tex.Load(uint3(x, y, 0)).rgb));
+        }
+    }
+    luminance /= (end.x - begin.x) * (end.y - begin.y);
+
+    // Rows near the poles cover less solid angle.
+    const float sin_theta = sin(M_PI * (px.y + 0.5) / ENVIRONMENT_MAP_CDF_HEIGHT);
+
+    cdf_buf[px.y * ENVIRONMENT_MAP_CDF_WIDTH + px.x] = luminance * sin_theta;
+}
@@ -0,0 +1,49 @@
+#include "../inc/flip.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> input_tex;
+[[vk::binding(1)]] RWTexture2D<float4> color_output_tex;
+[[vk::binding(2)]] RWTexture2D<float4> feature_output_tex;
+[[vk::binding(3)]] cbuffer _ {
+    uint2 extent;
+};
+
+float3 load_linear(int2 px) {
+    px = clamp(px, int2(0, 0), int2(extent) - 1);
+    return saturate(input_tex[px].rgb);
+}
+
+// Horizontal half of the separable FLIP filters.
+//
+// `color_output_tex`: YCxCz filtered with the achromatic, red-green, and both blue-yellow CSF Gaussians.
+// `feature_output_tex`: luminance filtered with the feature Gaussian, and its first and second derivative.
+[numthreads(8, 8, 1)]
+void main(in uint2 px : SV_DispatchThreadID) {
+    if (any(px >= extent)) {
+        return;
+    }
+
+    const float a_norm = 1.0 / flip_csf_gaussian_sum(FLIP_CSF_A_B);
+    const float rg_norm = 1.0 / flip_csf_gaussian_sum(FLIP_CSF_RG_B);
+    const float by1_norm = 1.0 / flip_csf_gaussian_sum(FLIP_CSF_BY1_B);
+    const float by2_norm = 1.0 / flip_csf_gaussian_sum(FLIP_CSF_BY2_B);
+
+    float4 color = 0.0;
+    for (int x = -FLIP_CSF_RADIUS; x <= FLIP_CSF_RADIUS; ++x) {
+        const float3 ycxcz = flip_linear_rgb_to_ycxcz(load_linear(int2(px) + int2(x, 0)));
+        color += float4(
+            ycxcz.x * flip_csf_gaussian(FLIP_CSF_A_B, x) * a_norm,
+            ycxcz.y * flip_csf_gaussian(FLIP_CSF_RG_B, x) * rg_norm,
+            ycxcz.z * flip_csf_gaussian(FLIP_CSF_BY1_B, x) * by1_norm,
+            ycxcz.z * flip_csf_gaussian(FLIP_CSF_BY2_B, x) * by2_norm
+        );
+    }
+
+    float3 feature = 0.0;
+    for (int x = -FLIP_FEATURE_RADIUS; x <= FLIP_FEATURE_RADIUS; ++x) {
+        const float luminance = (flip_linear_rgb_to_ycxcz(load_linear(int2(px) + int2(x, 0))).x + 16.0) / 116.0;
+        feature += luminance * flip_feature_filter(x);
+    }
+
+    color_output_tex[px] = color;
+    feature_output_tex[px] = float4(feature, 0.0);
+}
@@ -0,0 +1,80 @@
+#include "../inc/flip.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> test_color_tex;
+[[vk::binding(1)]] Texture2D<float4> test_feature_tex;
+[[vk::binding(2)]] Texture2D<float4> reference_color_tex;
+[[vk::binding(3)]] Texture2D<float4> reference_feature_tex;
+[[vk::binding(4)]] Texture2D<float4> test_tex;
+[[vk::binding(5)]] Texture2D<float4> reference_tex;
+[[vk::binding(6)]] RWTexture2D<float2> error_output_tex;
+[[vk::binding(7)]] cbuffer _ {
+    uint2 extent;
+};
+
+struct FilteredPixel {
+    float3 hunt_lab;
+    float edge;
+    float point_;
+};
+
+FilteredPixel filter_y(Texture2D<float4> color_tex, Texture2D<float4> feature_tex, int2 px, float2 by_weights) {
+    const float a_norm = 1.0 / flip_csf_gaussian_sum(FLIP_CSF_A_B);
+    const float rg_norm = 1.0 / flip_csf_gaussian_sum(FLIP_CSF_RG_B);
+    const float by1_norm = 1.0 / flip_csf_gaussian_sum(FLIP_CSF_BY1_B);
+    const float by2_norm = 1.0 / flip_csf_gaussian_sum(FLIP_CSF_BY2_B);
+
+    float3 ycxcz = 0.0;
+    for (int y = -FLIP_CSF_RADIUS; y <= FLIP_CSF_RADIUS; ++y) {
+        const float4 c = color_tex[clamp(px + int2(0, y), int2(0, 0), int2(extent) - 1)];
+        ycxcz += float3(
+            c.x * flip_csf_gaussian(FLIP_CSF_A_B, y) * a_norm,
+            c.y * flip_csf_gaussian(FLIP_CSF_RG_B, y) * rg_norm,
+            c.z * flip_csf_gaussian(FLIP_CSF_BY1_B, y) * by1_norm * by_weights.x
+                + c.w * flip_csf_gaussian(FLIP_CSF_BY2_B, y) * by2_norm * by_weights.y
+        );
+    }
+
+    // Horizontal pass: x = g, y = g', z = g''; combined here with the vertical
+    // filter to get both partial derivatives.
+    float2 edge = 0.0;
+    float2 point_ = 0.0;
+    for (int y = -FLIP_FEATURE_RADIUS; y <= FLIP_FEATURE_RADIUS; ++y) {
+        const float3 f = feature_tex[clamp(px + int2(0, y), int2(0, 0), int2(extent) - 1)].xyz;
+        const float3 filter = flip_feature_filter(y);
+        edge += float2(f.y * filter.x, f.x * filter.y);
+        point_ += float2(f.z * filter.x, f.x * filter.z);
+    }
+
+    FilteredPixel res;
+    res.hunt_lab = flip_linear_rgb_to_hunt_lab(saturate(flip_ycxcz_to_linear_rgb(ycxcz)));
+    res.edge = length(edge);
+    res.point_ = length(point_);
+    return res;
+}
+
+// Vertical half of the separable FLIP filters, followed by the per-pixel error.
+// Outputs FLIP in `x`, and the squared error of the sRGB-encoded values in `y`.
+[numthreads(8, 8, 1)]
+void main(in uint2 px : SV_DispatchThreadID) {
+    if (any(px >= extent)) {
+        return;
+    }
+
+    const float2 by_weights = flip_csf_by_weights();
+    const FilteredPixel test = filter_y(test_color_tex, test_feature_tex, int2(px), by_weights);
+    const FilteredPixel reference = filter_y(reference_color_tex, reference_feature_tex, int2(px), by_weights);
+
+    const float color_error = flip_color_error(reference.hunt_lab, test.hunt_lab);
+    const float feature_error = pow(
+        max(abs(reference.edge - test.edge), abs(reference.point_ - test.point_)) / sqrt(2.0),
+        FLIP_QF
+    );
+    const float flip = pow(color_error, 1.0 - feature_error);
+
+    const float3 test_encoded = sRGB_OETF(saturate(test_tex[px].rgb));
+    const float3 reference_encoded = sRGB_OETF(saturate(reference_tex[px].rgb));
+    const float3 diff = test_encoded - reference_encoded;
+    const float squared_error = dot(diff, diff) / 3.0;
+
+    error_output_tex[px] = float2(flip, squared_error);
+}
@@ -0,0 +1,28 @@
+[[vk::binding(0)]] Texture2D<float2> error_tex;
+[[vk::binding(1)]] RWStructuredBuffer<float2> partial_sums_buf;
+[[vk::binding(2)]] cbuffer _ {
+    uint2 extent;
+};
+
+#define GROUP_SIZE 16
+
+groupshared float2 sums[GROUP_SIZE * GROUP_SIZE];
+
+// Sums the per-pixel errors of each 16x16 tile.
+[numthreads(GROUP_SIZE, GROUP_SIZE, 1)]
+void main(uint2 px: SV_DispatchThreadID, uint2 group_id: SV_GroupID, uint idx_within_group: SV_GroupIndex) {
+    sums[idx_within_group] = all(px < extent) ? error_tex[px] : 0.0;
+    GroupMemoryBarrierWithGroupSync();
+
+    for (uint stride = GROUP_SIZE * GROUP_SIZE / 2; stride > 0; stride /= 2) {
+        if (idx_within_group < stride) {
+            sums[idx_within_group] += sums[idx_within_group + stride];
+        }
+        GroupMemoryBarrierWithGroupSync();
+    }
+
+    if (0 == idx_within_group) {
+        const uint group_count_x = (extent.x + GROUP_SIZE - 1) / GROUP_SIZE;
+        partial_sums_buf[group_id.y * group_count_x + group_id.x] = sums[0];
+    }
+}
@@ -0,0 +1,42 @@
+[[vk::binding(0)]] StructuredBuffer<float2> partial_sums_buf;
+[[vk::binding(1)]] RWStructuredBuffer<uint> output_buf;
+[[vk::binding(2)]] cbuffer _ {
+    uint2 extent;
+    uint partial_sum_count;
+    uint frame_index;
+};
+
+// Must match `IMAGE_METRICS_READBACK_MAGIC` in `image_metrics.rs`
+#define READBACK_MAGIC 0x464c4950
+
+#define GROUP_SIZE 256
+
+groupshared float2 sums[GROUP_SIZE];
+
+// Layout of `output_buf`: magic, frame index, mean FLIP, RMSE.
+// The magic tells results apart from the uninitialized buffer before the first frame.
+[numthreads(GROUP_SIZE, 1, 1)]
+void main(uint idx_within_group: SV_GroupIndex) {
+    float2 sum = 0.0;
+    for (uint i = idx_within_group; i < partial_sum_count; i += GROUP_SIZE) {
+        sum += partial_sums_buf[i];
+    }
+    sums[idx_within_group] = sum;
+    GroupMemoryBarrierWithGroupSync();
+
+    for (uint stride = GROUP_SIZE / 2; stride > 0; stride /= 2) {
+        if (idx_within_group < stride) {
+            sums[idx_within_group] += sums[idx_within_group + stride];
+        }
+        GroupMemoryBarrierWithGroupSync();
+    }
+
+    if (0 == idx_within_group) {
+        const float2 mean = sums[0] / float(extent.x * extent.y);
+
+        output_buf[0] = READBACK_MAGIC;
+        output_buf[1] = frame_index;
+        output_buf[2] = asuint(mean.x);
+        output_buf[3] = asuint(sqrt(mean.y));
+    }
+}
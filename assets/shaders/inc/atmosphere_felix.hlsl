@@ -22,6 +22,8 @@
 #ifndef ATMOSPHERE_INCLUDED
 #define ATMOSPHERE_INCLUDED
 
+#include "units.hlsl"
+
 // -------------------------------------
 // Defines
 #define EPS                 1e-6
@@ -46,7 +48,8 @@
 #endif
 
 #define ATMOSPHERE_DENSITY  1
-#define EXPOSURE            20
+// Solar illuminance in radiance units; see `units.hlsl`
+#define EXPOSURE            (SOLAR_ILLUMINANCE_LUX / NITS_PER_RADIANCE_UNIT)
 
 // -------------------------------------
 // Math
@@ -0,0 +1,106 @@
+#ifndef TONEMAPPERS_HLSL
+#define TONEMAPPERS_HLSL
+
+// Alternatives to `display_transform_sRGB`, for matching other engines.
+// All take linear sRGB after exposure, and return linear sRGB in [0, 1].
+
+#include "srgb.hlsl"
+
+// Must match `Tonemapper` in `world_renderer.rs`
+#define TONEMAPPER_FILMIC 0
+#define TONEMAPPER_ACES 1
+#define TONEMAPPER_AGX 2
+#define TONEMAPPER_KHRONOS_PBR_NEUTRAL 3
+#define TONEMAPPER_REINHARD 4
+
+// Stephen Hill's fit of the ACES RRT and sRGB ODT, via BakingLab:
+// https://github.com/TheRealMJP/BakingLab/blob/master/BakingLab/ACES.hlsl
+float3 tonemap_aces(float3 col) {
+    // sRGB => XYZ => D65_2_D60 => AP1 => RRT_SAT
+    const float3x3 input_mat = float3x3(
+        0.59719, 0.35458, 0.04823,
+        0.07600, 0.90834, 0.01566,
+        0.02840, 0.13383, 0.83777
+    );
+
+    // ODT_SAT => XYZ => D60_2_D65 => sRGB
+    const float3x3 output_mat = float3x3(
+        1.60475, -0.53108, -0.07367,
+        -0.10208, 1.10813, -0.00605,
+        -0.00327, -0.07276, 1.07602
+    );
+
+    col = mul(input_mat, col);
+
+    const float3 a = col * (col + 0.0245786) - 0.000090537;
+    const float3 b = col * (0.983729 * col + 0.4329510) + 0.238081;
+    col = a / b;
+
+    return saturate(mul(output_mat, col));
+}
+
+// AgX with the default contrast look, after Benjamin Wrensch's approximation:
+// https://iolite-engine.com/blog_posts/minimal_agx_implementation
+float3 tonemap_agx(float3 col) {
+    const float3x3 agx_mat = float3x3(
+        0.842479062253094, 0.0423282422610123, 0.0423756549057051,
+        0.0784335999999992, 0.878468636469772, 0.0784336,
+        0.0792237451477643, 0.0791661274605434, 0.879142973793104
+    );
+    const float3x3 agx_mat_inv = float3x3(
+        1.19687900512017, -0.0528968517574562, -0.0529716355144438,
+        -0.0980208811401368, 1.15190312990417, -0.0980434501171241,
+        -0.0990297440797205, -0.0989611768448433, 1.15107367264116
+    );
+
+    const float min_ev = -12.47393;
+    const float max_ev = 4.026069;
+
+    col = mul(col, agx_mat);
+    col = clamp(log2(max(1e-10, col)), min_ev, max_ev);
+    col = (col - min_ev) / (max_ev - min_ev);
+
+    // Sigmoid, fitted with a 6th order polynomial.
+    const float3 x2 = col * col;
+    const float3 x4 = x2 * x2;
+    col = 15.5 * x4 * x2
+        - 40.14 * x4 * col
+        + 31.96 * x4
+        - 6.868 * x2 * col
+        + 0.4298 * x2
+        + 0.1191 * col
+        - 0.00232;
+
+    col = mul(col, agx_mat_inv);
+
+    // The curve outputs display-encoded values.
+    return pow(saturate(col), 2.2);
+}
+
+// https://github.com/KhronosGroup/ToneMapping/blob/main/PBR_Neutral/README.md
+float3 tonemap_khronos_pbr_neutral(float3 col) {
+    const float start_compression = 0.8 - 0.04;
+    const float desaturation = 0.15;
+
+    const float x = min(col.r, min(col.g, col.b));
+    const float offset = x < 0.08 ? x - 6.25 * x * x : 0.04;
+    col -= offset;
+
+    const float peak = max(col.r, max(col.g, col.b));
+    if (peak < start_compression) {
+        return saturate(col);
+    }
+
+    const float d = 1.0 - start_compression;
+    const float new_peak = 1.0 - d * d / (peak + d - start_compression);
+    col *= new_peak / peak;
+
+    const float g = 1.0 - 1.0 / (desaturation * (peak - new_peak) + 1.0);
+    return saturate(lerp(col, new_peak.xxx, g));
+}
+
+float3 tonemap_reinhard(float3 col) {
+    return saturate(col / (1.0 + sRGB_to_luminance(col)));
+}
+
+#endif  // TONEMAPPERS_HLSL
@@ -0,0 +1,22 @@
+#ifndef ENVIRONMENT_CAPTURE_HLSL
+#define ENVIRONMENT_CAPTURE_HLSL
+
+#include "samplers.hlsl"
+
+// Must match `CAPTURE_MIP_COUNT` in `environment_capture.rs`
+#define ENVIRONMENT_CAPTURE_MIP_COUNT 6
+
+// Prefiltered radiance from `EnvironmentCaptureOutput::cube`, stored without pre-exposure;
+// multiply by `ForwardPassData::pre_exposure` before writing to the scene color.
+// The cube is treated as infinitely distant, which holds up for rough or small surfaces
+// near the capture position.
+float3 sample_environment_capture(
+    TextureCubeArray<float4> cube,
+    float3 dir_ws,
+    float perceptual_roughness
+) {
+    const float lod = perceptual_roughness * (ENVIRONMENT_CAPTURE_MIP_COUNT - 1);
+    return cube.SampleLevel(sampler_llr, float4(dir_ws, 0), lod).rgb;
+}
+
+#endif  // ENVIRONMENT_CAPTURE_HLSL
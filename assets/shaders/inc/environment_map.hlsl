@@ -0,0 +1,132 @@
+#ifndef ENVIRONMENT_MAP_HLSL
+#define ENVIRONMENT_MAP_HLSL
+
+#include "math.hlsl"
+#include "frame_constants.hlsl"
+
+// Must match `ENVIRONMENT_MAP_CDF_*` in `ibl.rs`
+static const uint ENVIRONMENT_MAP_CDF_WIDTH = 512;
+static const uint ENVIRONMENT_MAP_CDF_HEIGHT = 256;
+
+// The sampling buffer holds a CDF over the columns of every row, followed by
+// a CDF over the rows, and finally the total (unnormalized) weight of the map.
+static const uint ENVIRONMENT_MAP_CDF_MARGINAL_OFFSET = ENVIRONMENT_MAP_CDF_WIDTH * ENVIRONMENT_MAP_CDF_HEIGHT;
+static const uint ENVIRONMENT_MAP_CDF_TOTAL_OFFSET = ENVIRONMENT_MAP_CDF_MARGINAL_OFFSET + ENVIRONMENT_MAP_CDF_HEIGHT;
+static const uint ENVIRONMENT_MAP_CDF_SIZE = ENVIRONMENT_MAP_CDF_TOTAL_OFFSET + 1;
+
+float3 environment_map_rotate_y(float3 v, float angle) {
+    float s, c;
+    sincos(angle, s, c);
+    return float3(c * v.x + s * v.z, v.y, -s * v.x + c * v.z);
+}
+
+// Lat-long mapping, with `uv.y == 0` straight up.
+float2 direction_to_environment_map_uv(float3 dir) {
+    dir = environment_map_rotate_y(dir, -frame_constants.environment_map_rotation);
+
+    float2 uv = float2(atan2(dir.z, dir.x) / M_TAU, asin(clamp(dir.y, -1.0, 1.0)) * M_FRAC_1_PI);
+    uv += 0.5;
+    uv.y = 1 - uv.y;
+    return uv;
+}
+
+float3 environment_map_uv_to_direction(float2 uv) {
+    float sin_theta, cos_theta;
+    sincos(uv.y * M_PI, sin_theta, cos_theta);
+
+    float sin_phi, cos_phi;
+    sincos((uv.x - 0.5) * M_TAU, sin_phi, cos_phi);
+
+    return environment_map_rotate_y(
+        float3(sin_theta * cos_phi, cos_theta, sin_theta * sin_phi),
+        frame_constants.environment_map_rotation
+    );
+}
+
+struct EnvironmentMapSample {
+    float3 dir;
+    // Solid angle measure
+    float pdf;
+};
+
+// Index of the first entry in `cdf[offset..offset+count]` greater than `u`.
+uint environment_map_cdf_search(StructuredBuffer<float> cdf, uint offset, uint count, float u) {
+    uint lo = 0;
+    uint hi = count - 1;
+
+    while (lo < hi) {
+        const uint mid = (lo + hi) / 2;
+        if (cdf[offset + mid] > u) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+
+    return lo;
+}
+
+float environment_map_cdf_start(StructuredBuffer<float> cdf, uint offset, uint idx) {
+    return idx > 0 ? cdf[offset + idx - 1] : 0.0;
+}
+
+float environment_map_uv_pdf_to_solid_angle(float uv_pdf, float sin_theta) {
+    return sin_theta > 0.0 ? uv_pdf / (2.0 * M_PI * M_PI * sin_theta) : 0.0;
+}
+
+// Picks a direction proportionally to the luminance of the environment map.
+EnvironmentMapSample sample_environment_map(StructuredBuffer<float> cdf, float2 urand) {
+    const uint y = environment_map_cdf_search(
+        cdf, ENVIRONMENT_MAP_CDF_MARGINAL_OFFSET, ENVIRONMENT_MAP_CDF_HEIGHT, urand.y);
+    const float row_start = environment_map_cdf_start(cdf, ENVIRONMENT_MAP_CDF_MARGINAL_OFFSET, y);
+    const float row_pmf = cdf[ENVIRONMENT_MAP_CDF_MARGINAL_OFFSET + y] - row_start;
+
+    const uint row_offset = y * ENVIRONMENT_MAP_CDF_WIDTH;
+    const uint x = environment_map_cdf_search(cdf, row_offset, ENVIRONMENT_MAP_CDF_WIDTH, urand.x);
+    const float col_start = environment_map_cdf_start(cdf, row_offset, x);
+    const float col_pmf = cdf[row_offset + x] - col_start;
+
+    // Reuse what's left of the random numbers to place the sample within the cell.
+    const float2 cell_offset = saturate(float2(
+        (urand.x - col_start) / max(1e-20, col_pmf),
+        (urand.y - row_start) / max(1e-20, row_pmf)
+    ));
+    const float2 uv = (float2(x, y) + cell_offset)
+        / float2(ENVIRONMENT_MAP_CDF_WIDTH, ENVIRONMENT_MAP_CDF_HEIGHT);
+
+    EnvironmentMapSample res;
+    res.dir = environment_map_uv_to_direction(uv);
+    res.pdf = environment_map_uv_pdf_to_solid_angle(
+        row_pmf * col_pmf * ENVIRONMENT_MAP_CDF_WIDTH * ENVIRONMENT_MAP_CDF_HEIGHT,
+        sin(uv.y * M_PI)
+    );
+    return res;
+}
+
+// Solid angle pdf of `sample_environment_map` returning `dir`.
+float environment_map_pdf(StructuredBuffer<float> cdf, float3 dir) {
+    const float2 uv = direction_to_environment_map_uv(dir);
+    const uint2 cell = min(
+        uint2(uv * float2(ENVIRONMENT_MAP_CDF_WIDTH, ENVIRONMENT_MAP_CDF_HEIGHT)),
+        uint2(ENVIRONMENT_MAP_CDF_WIDTH, ENVIRONMENT_MAP_CDF_HEIGHT) - 1
+    );
+
+    const float row_pmf =
+        cdf[ENVIRONMENT_MAP_CDF_MARGINAL_OFFSET + cell.y]
+        - environment_map_cdf_start(cdf, ENVIRONMENT_MAP_CDF_MARGINAL_OFFSET, cell.y);
+
+    const uint row_offset = cell.y * ENVIRONMENT_MAP_CDF_WIDTH;
+    const float col_pmf =
+        cdf[row_offset + cell.x]
+        - environment_map_cdf_start(cdf, row_offset, cell.x);
+
+    // Rotation is about +Y, so it doesn't change the polar angle.
+    const float sin_theta = sqrt(max(0.0, 1.0 - dir.y * dir.y));
+
+    return environment_map_uv_pdf_to_solid_angle(
+        row_pmf * col_pmf * ENVIRONMENT_MAP_CDF_WIDTH * ENVIRONMENT_MAP_CDF_HEIGHT,
+        sin_theta
+    );
+}
+
+#endif  // ENVIRONMENT_MAP_HLSL
@@ -0,0 +1,149 @@
+// Building blocks of the LDR-FLIP image difference metric:
+// "FLIP: A Difference Evaluator for Alternating Images", Andersson et al. 2020.
+// https://research.nvidia.com/publication/2020-07_FLIP
+//
+// Inputs are linear sRGB in [0, 1], i.e. what ends up on screen before the OETF.
+
+#ifndef FLIP_HLSL
+#define FLIP_HLSL
+
+#include "math_const.hlsl"
+#include "color/srgb.hlsl"
+
+// Pixels per degree of visual angle: a 0.7 m wide 4K monitor viewed from 0.7 m.
+static const float FLIP_PIXELS_PER_DEGREE = 67.0206;
+
+// Contrast sensitivity functions for the achromatic, red-green, and two blue-yellow Gaussians.
+static const float FLIP_CSF_A_B = 0.0047;
+static const float FLIP_CSF_RG_B = 0.0053;
+static const float FLIP_CSF_BY1_A = 34.1;
+static const float FLIP_CSF_BY1_B = 0.04;
+static const float FLIP_CSF_BY2_A = 13.5;
+static const float FLIP_CSF_BY2_B = 0.025;
+
+// Enough to cover three standard deviations of the widest CSF Gaussian.
+static const int FLIP_CSF_RADIUS = int(ceil(3.0 * sqrt(FLIP_CSF_BY1_B / (2.0 * M_PI * M_PI)) * FLIP_PIXELS_PER_DEGREE));
+
+static const float FLIP_FEATURE_SIGMA = 0.5 * 0.082 * FLIP_PIXELS_PER_DEGREE;
+static const int FLIP_FEATURE_RADIUS = int(ceil(3.0 * FLIP_FEATURE_SIGMA));
+
+static const float FLIP_QC = 0.7;
+static const float FLIP_QF = 0.5;
+static const float FLIP_PC = 0.4;
+static const float FLIP_PT = 0.95;
+
+static const float3 FLIP_D65_WHITE = float3(0.950428545, 1.0, 1.088900371);
+
+// Unnormalized 1D CSF Gaussian for a pixel offset of `x`.
+float flip_csf_gaussian(float b, int x) {
+    const float deg = x / FLIP_PIXELS_PER_DEGREE;
+    return exp(-M_PI * M_PI * deg * deg / b);
+}
+
+float flip_csf_gaussian_sum(float b) {
+    float sum = 0.0;
+    for (int x = -FLIP_CSF_RADIUS; x <= FLIP_CSF_RADIUS; ++x) {
+        sum += flip_csf_gaussian(b, x);
+    }
+    return sum;
+}
+
+// Relative weights of the two blue-yellow Gaussians once each is normalized separately.
+float2 flip_csf_by_weights() {
+    const float s1 = flip_csf_gaussian_sum(FLIP_CSF_BY1_B);
+    const float s2 = flip_csf_gaussian_sum(FLIP_CSF_BY2_B);
+    const float2 w = float2(
+        FLIP_CSF_BY1_A * M_PI / FLIP_CSF_BY1_B * s1 * s1,
+        FLIP_CSF_BY2_A * M_PI / FLIP_CSF_BY2_B * s2 * s2
+    );
+    return w / (w.x + w.y);
+}
+
+// Normalized 1D feature filters for an offset of `x`: the Gaussian,
+// its first derivative (edges), and its second derivative (points).
+// The derivatives have positive and negative lobes normalized to 1 and -1.
+float3 flip_feature_filter(int x) {
+    const float sigma2 = FLIP_FEATURE_SIGMA * FLIP_FEATURE_SIGMA;
+
+    float g_sum = 0.0;
+    float edge_pos_sum = 0.0;
+    float point_pos_sum = 0.0;
+    float point_neg_sum = 0.0;
+
+    for (int i = -FLIP_FEATURE_RADIUS; i <= FLIP_FEATURE_RADIUS; ++i) {
+        const float g = exp(-0.5 * i * i / sigma2);
+        const float edge = -i * g;
+        const float pt = (i * i / sigma2 - 1.0) * g;
+
+        g_sum += g;
+        edge_pos_sum += max(0.0, edge);
+        point_pos_sum += max(0.0, pt);
+        point_neg_sum += max(0.0, -pt);
+    }
+
+    const float g = exp(-0.5 * x * x / sigma2);
+    const float edge = -x * g;
+    const float pt = (x * x / sigma2 - 1.0) * g;
+
+    return float3(
+        g / g_sum,
+        edge / edge_pos_sum,
+        pt > 0.0 ? pt / point_pos_sum : pt / point_neg_sum
+    );
+}
+
+float3 flip_linear_rgb_to_ycxcz(float3 rgb) {
+    const float3 xyz = sRGB_to_XYZ(rgb) / FLIP_D65_WHITE;
+    return float3(
+        116.0 * xyz.y - 16.0,
+        500.0 * (xyz.x - xyz.y),
+        200.0 * (xyz.y - xyz.z)
+    );
+}
+
+float3 flip_ycxcz_to_linear_rgb(float3 ycxcz) {
+    const float y = (ycxcz.x + 16.0) / 116.0;
+    const float3 xyz = float3(
+        ycxcz.y / 500.0 + y,
+        y,
+        y - ycxcz.z / 200.0
+    ) * FLIP_D65_WHITE;
+    return XYZ_to_sRGB(xyz);
+}
+
+float flip_lab_f(float t) {
+    const float delta = 6.0 / 29.0;
+    return t > delta * delta * delta ? pow(t, 1.0 / 3.0) : t / (3.0 * delta * delta) + 4.0 / 29.0;
+}
+
+// CIELAB with the Hunt effect applied to the chromatic channels.
+float3 flip_linear_rgb_to_hunt_lab(float3 rgb) {
+    const float3 xyz = sRGB_to_XYZ(rgb) / FLIP_D65_WHITE;
+    const float3 f = float3(flip_lab_f(xyz.x), flip_lab_f(xyz.y), flip_lab_f(xyz.z));
+    const float l = 116.0 * f.y - 16.0;
+    const float2 ab = float2(500.0 * (f.x - f.y), 200.0 * (f.y - f.z));
+    return float3(l, 0.01 * l * ab);
+}
+
+float flip_hyab(float3 a, float3 b) {
+    const float3 d = a - b;
+    return abs(d.x) + length(d.yz);
+}
+
+// Color difference of two CSF-filtered pixels, remapped to [0, 1].
+float flip_color_error(float3 reference_lab, float3 test_lab) {
+    const float cmax = pow(flip_hyab(
+        flip_linear_rgb_to_hunt_lab(float3(0.0, 1.0, 0.0)),
+        flip_linear_rgb_to_hunt_lab(float3(0.0, 0.0, 1.0))
+    ), FLIP_QC);
+
+    const float err = pow(flip_hyab(reference_lab, test_lab), FLIP_QC);
+
+    if (err < FLIP_PC * cmax) {
+        return FLIP_PT / (FLIP_PC * cmax) * err;
+    } else {
+        return FLIP_PT + (err - FLIP_PC * cmax) / (cmax - FLIP_PC * cmax) * (1.0 - FLIP_PT);
+    }
+}
+
+#endif  // FLIP_HLSL
@@ -22,6 +22,12 @@ struct ViewConstants {
 
     float2 sample_offset_pixels;
     float2 sample_offset_clip;
+
+    // Thin lens of the physical camera, in scene units
+    float focal_length;
+    float aperture_diameter;
+    float focus_distance;
+    float _pad;
 };
 
 struct GiCascadeConstants {
@@ -43,6 +49,7 @@ enum RenderOverrideFlags {
     NO_NORMAL_MAPS = 1u << 1,
     FLIP_NORMAL_MAP_YZ = 1u << 2,
     NO_METAL = 1u << 3,
+    NO_IRCACHE = 1u << 4,
 };
 
 struct RenderOverrides {
@@ -57,6 +64,18 @@ struct RenderOverrides {
     }
 };
 
+// Tuning shared by all materials of a `MATERIAL_CATEGORY_*`
+struct MaterialCategoryConstants {
+    float gi_strength;
+    float transmission_scale;
+    float sheen;
+    uint pad;
+};
+
+// Must match `CONSTANTS_LAYOUT_VERSION` in `constants_layout.rs`. Bumped whenever
+// `FrameConstants` or `InstanceDynamicConstants` change.
+#define FRAME_CONSTANTS_LAYOUT_VERSION 2
+
 struct FrameConstants {
     ViewConstants view_constants;
 
@@ -73,22 +92,107 @@ struct FrameConstants {
     float pre_exposure;
     float pre_exposure_prev;
     float pre_exposure_delta;
-    float pad0;
+
+    // In (0, 1]; shortens GI temporal accumulation while the sun is moving.
+    float gi_history_scale;
 
     RenderOverrides render_overrides;
 
+    uint point_light_count;
+    float world_units_per_meter;
+    // Radians about +Y, and a linear multiplier; see `IblRenderer`.
+    float environment_map_rotation;
+    float environment_map_intensity;
+
     float4 ircache_grid_center;
     IrcacheCascadeConstants ircache_cascades[12];
+
+    MaterialCategoryConstants material_categories[4];
+
+    // See `TONEMAPPER_*` in `color/tonemappers.hlsl`
+    uint tonemapper;
+
+    // Sampled with the helpers in `wind.hlsl`
+    uint wind_gust_texture;
+    float wind_gust_strength;
+    // Meters per tile of the gust texture
+    float wind_gust_scale;
+    // xyz: unit direction, or zero; w: meters per second
+    float4 wind_direction_speed;
+    // xyz: how far the air has moved, in world units; w: seconds, wrapping around
+    float4 wind_offset_time;
+
+    // Entries of `instance_dynamic_parameters_dyn`: one per regular instance, then one per batch,
+    // and one per ray tracing proxy, which are counted as batches of a single instance.
+    uint instance_count;
+    uint instance_batch_count;
+    uint2 pad3;
 };
 
 [[vk::binding(0, 2)]] ConstantBuffer<FrameConstants> frame_constants;
 
+// Converts a length tuned in meters, such as a ray bias, to the units of the scene.
+float world_units(float meters) {
+    return meters * frame_constants.world_units_per_meter;
+}
+
+// Must match `InstanceFlags` in `world_renderer.rs`
+static const uint INSTANCE_FLAG_EMISSIVE_ONLY = 1;
+static const uint INSTANCE_FLAG_VIEWMODEL = 2;
+// Only affects the TLAS instance mask; see `TLAS_MASK_GI`.
+static const uint INSTANCE_FLAG_NO_GI_CONTRIBUTION = 4;
+
 struct InstanceDynamicConstants {
     float emissive_multiplier;
+    float mip_bias;
+    uint max_anisotropy;
+    uint flags;
+    float gi_reception;
+
+    bool is_emissive_only() {
+        return (flags & INSTANCE_FLAG_EMISSIVE_ONLY) != 0;
+    }
+
+    bool is_viewmodel() {
+        return (flags & INSTANCE_FLAG_VIEWMODEL) != 0;
+    }
 };
 
 [[vk::binding(1, 2)]] StructuredBuffer<InstanceDynamicConstants> instance_dynamic_parameters_dyn;
 [[vk::binding(2, 2)]] StructuredBuffer<TriangleLightPacked> triangle_lights_dyn;
+[[vk::binding(3, 2)]] StructuredBuffer<PointLightPacked> point_lights_dyn;
+
+// Exclusive end of each instance batch, counted in batched instances
+[[vk::binding(4, 2)]] StructuredBuffer<uint> instance_batch_ends_dyn;
+
+// Index into `instance_dynamic_parameters_dyn` of a TLAS instance, as seen by `InstanceIndex()`.
+// Batched instances follow the regular ones in the TLAS, and share the entry of their batch,
+// which is found with a binary search.
+uint instance_dynamic_parameters_index(uint instance_index) {
+    if (instance_index < frame_constants.instance_count) {
+        return instance_index;
+    }
+
+    const uint batched_index = instance_index - frame_constants.instance_count;
+    uint first = 0;
+    uint count = frame_constants.instance_batch_count;
+
+    while (count > 0) {
+        const uint half_count = count / 2;
+        if (instance_batch_ends_dyn[first + half_count] <= batched_index) {
+            first += half_count + 1;
+            count -= half_count + 1;
+        } else {
+            count = half_count;
+        }
+    }
+
+    return frame_constants.instance_count + first;
+}
+
+InstanceDynamicConstants instance_dynamic_parameters(uint instance_index) {
+    return instance_dynamic_parameters_dyn[instance_dynamic_parameters_index(instance_index)];
+}
 
 struct ViewRayContext {
     float4 ray_dir_cs;
@@ -137,7 +241,7 @@ struct ViewRayContext {
     float3 biased_secondary_ray_origin_ws_with_normal(float3 normal) {
         float3 ws_abs = abs(ray_hit_ws());
         float max_comp = max(max(ws_abs.x, ws_abs.y), max(ws_abs.z, -ray_hit_vs().z));
-        return ray_hit_ws() + (normal - ray_dir_ws()) * max(1e-4, max_comp * 1e-6);
+        return ray_hit_ws() + (normal - ray_dir_ws()) * max(world_units(1e-4), max_comp * 1e-6);
     }
 
     static ViewRayContext from_uv(float2 uv) {
@@ -18,6 +18,8 @@ struct GbufferDataPacked {
     float3 unpack_normal();
     float3 unpack_albedo();
     float3 unpack_emissive();
+    uint unpack_material_category();
+    float unpack_gi_reception();
 };
 
 struct GbufferData {
@@ -27,6 +29,15 @@ struct GbufferData {
     float roughness;
     float metalness;
 
+    // Thin-surface diffuse transmission (leaves, paper); see `LayeredBrdf::evaluate_transmission`.
+    float transmission;
+
+    // `MATERIAL_CATEGORY_*`; selects the per-category tuning in `FrameConstants::material_categories`.
+    uint material_category;
+
+    // Multiplier of the diffuse GI the surface receives; `InstanceDynamicConstants::gi_reception`.
+    float gi_reception;
+
     static GbufferData create_zero() {
         GbufferData res;
         res.albedo = 0;
@@ -34,6 +45,9 @@ struct GbufferData {
         res.normal = 0;
         res.roughness = 0;
         res.metalness = 0;
+        res.transmission = 0;
+        res.material_category = 0;
+        res.gi_reception = 1;
         return res;
     }
 
@@ -50,11 +64,21 @@ float perceptual_roughness_to_roughness(float r) {
 
 GbufferDataPacked GbufferData::pack() {
     float4 res = 0.0.xxxx;
-    res.x = asfloat(pack_color_888(albedo));
+    // Albedo takes the low 24 bits; the material category goes above it, followed by
+    // 6 bits of GI reception. The latter is stored inverted, so that zeroed texels receive GI.
+    res.x = asfloat(
+        pack_color_888(albedo)
+        | ((material_category & 3u) << 24u)
+        | (uint((1.0 - saturate(gi_reception)) * 63.0 + 0.5) << 26u)
+    );
     res.y = pack_normal_11_10_11(normal);
 
-    float2 roughness_metalness = float2(roughness_to_perceptual_roughness(roughness), metalness);
-    res.z = asfloat(pack_2x16f_uint(roughness_metalness));
+    // Roughness gets 16 bits of half float; metalness and transmission get 8 bits of unorm each.
+    res.z = asfloat(
+        f32tof16(roughness_to_perceptual_roughness(roughness))
+        | (uint(saturate(metalness) * 255.0 + 0.5) << 16u)
+        | (uint(saturate(transmission) * 255.0 + 0.5) << 24u)
+    );
     res.w = asfloat(float3_to_rgb9e5(emissive));
 
    GbufferDataPacked packed;
@@ -67,10 +91,12 @@ GbufferData GbufferDataPacked::unpack() {
     res.albedo = unpack_albedo();
     res.normal = unpack_normal();
 
-    float2 roughness_metalness = unpack_2x16f_uint(data0.z);
-    res.roughness = perceptual_roughness_to_roughness(roughness_metalness.x);
-    res.metalness = roughness_metalness.y;
+    res.roughness = perceptual_roughness_to_roughness(f16tof32(data0.z & 0xffff));
+    res.metalness = float((data0.z >> 16u) & 0xff) / 255.0;
+    res.transmission = float(data0.z >> 24u) / 255.0;
     res.emissive = unpack_emissive();
+    res.material_category = unpack_material_category();
+    res.gi_reception = unpack_gi_reception();
 
     return res;
 }
@@ -87,4 +113,12 @@ float3 GbufferDataPacked::unpack_emissive() {
     return rgb9e5_to_float3(data0.w);
 }
 
+uint GbufferDataPacked::unpack_material_category() {
+    return (data0.x >> 24u) & 3u;
+}
+
+float GbufferDataPacked::unpack_gi_reception() {
+    return 1.0 - float(data0.x >> 26u) / 63.0;
+}
+
 #endif
\ No newline at end of file
@@ -42,12 +42,17 @@ struct LayeredBrdf {
     DiffuseBrdf diffuse_brdf;
     SpecularBrdfEnergyPreservation energy_preservation;
 
-    static LayeredBrdf from_gbuffer_ndotv(
+    // Albedo of the thin-surface diffuse transmission lobe; zero for opaque surfaces.
+    float3 transmission_albedo;
+
+    // `dielectric_f0` is the specular reflectance at normal incidence of the non-metallic part.
+    static LayeredBrdf from_gbuffer_ndotv_dielectric_f0(
         GbufferData gbuffer,
-        float ndotv
+        float ndotv,
+        float3 dielectric_f0
     ) {
         SpecularBrdf specular_brdf;
-        specular_brdf.albedo = 0.04;
+        specular_brdf.albedo = dielectric_f0;
         specular_brdf.roughness = gbuffer.roughness;
 
         DiffuseBrdf diffuse_brdf;
@@ -61,9 +66,29 @@ struct LayeredBrdf {
 
         res.specular_brdf = specular_brdf;
         res.diffuse_brdf = diffuse_brdf;
+        res.transmission_albedo = diffuse_brdf.albedo * gbuffer.transmission;
         return res;
     }
 
+    static LayeredBrdf from_gbuffer_ndotv(
+        GbufferData gbuffer,
+        float ndotv
+    ) {
+        return from_gbuffer_ndotv_dielectric_f0(gbuffer, ndotv, 0.04);
+    }
+
+    // Thin-surface diffuse transmission: light arriving from below the surface (`wi.z < 0`)
+    // scatters out of its front, tinted by the diffuse albedo. This is what makes
+    // backlit leaves glow. As with `evaluate`, the result needs to be scaled by
+    // the cosine term, which here is `-wi.z`.
+    float3 evaluate_transmission(float3 wi) {
+        if (wi.z >= 0) {
+            return 0;
+        }
+
+        return transmission_albedo * M_FRAC_1_PI;
+    }
+
     float3 evaluate(float3 wo, float3 wi) {
         if (wo.z <= 0 || wi.z <= 0) {
             return 0;
@@ -119,6 +144,46 @@ struct LayeredBrdf {
         );
     }
 
+    // The diffuse part of `evaluate` and `evaluate_directional_light`, without the specular lobe.
+    float3 evaluate_diffuse_lobe(float3 wo, float3 wi) {
+        if (wo.z <= 0 || wi.z <= 0) {
+            return 0;
+        }
+
+        const BrdfValue spec = specular_brdf.evaluate(wo, wi);
+        return diffuse_brdf.evaluate(wo, wi).value * spec.transmission_fraction;
+    }
+
+    // Probability of `sample` picking the diffuse lobe over the specular one.
+    float diffuse_lobe_probability() {
+        const float spec_wt = sRGB_to_luminance(energy_preservation.preintegrated_reflection);
+        const float diffuse_wt = sRGB_to_luminance(energy_preservation.preintegrated_transmission_fraction * diffuse_brdf.albedo);
+        return diffuse_wt / (spec_wt + diffuse_wt);
+    }
+
+    // Projected solid angle pdf of `sample` returning `wi`, accounting for both lobes.
+    // Unlike `BrdfSample::pdf`, this is suitable for multiple importance sampling.
+    float pdf(float3 wo, float3 wi) {
+        if (wo.z <= 0 || wi.z <= 0) {
+            return 0;
+        }
+
+        #if LAYERED_BRDF_FORCE_DIFFUSE_ONLY
+            return diffuse_brdf.evaluate(wo, wi).pdf;
+        #endif
+
+        #if LAYERED_BRDF_FORCE_SPECULAR_ONLY
+            return specular_brdf.evaluate(wo, wi).pdf;
+        #endif
+
+        const float transmission_p = diffuse_lobe_probability();
+        return lerp(
+            specular_brdf.evaluate(wo, wi).pdf,
+            diffuse_brdf.evaluate(wo, wi).pdf,
+            transmission_p
+        );
+    }
+
     BrdfSample sample(float3 wo, float3 urand) {
         #if LAYERED_BRDF_FORCE_DIFFUSE_ONLY
             return diffuse_brdf.sample(wo, urand.xy);
@@ -134,9 +199,7 @@ struct LayeredBrdf {
         // and reflect with the complement of that. However since we use a single ray,
         // we toss a coin, and choose between reflection and transmission.
 
-        const float spec_wt = sRGB_to_luminance(energy_preservation.preintegrated_reflection);
-        const float diffuse_wt = sRGB_to_luminance(energy_preservation.preintegrated_transmission_fraction * diffuse_brdf.albedo);
-        const float transmission_p = diffuse_wt / (spec_wt + diffuse_wt);
+        const float transmission_p = diffuse_lobe_probability();
 
         const float lobe_xi = urand.z;
         if (lobe_xi < transmission_p) {
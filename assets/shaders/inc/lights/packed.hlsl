@@ -1,8 +1,29 @@
 #ifndef LIGHTS_PACKED_HLSL
 #define LIGHTS_PACKED_HLSL
 
+// Must match `GpuTriangleLight` in `world_renderer.rs`
 struct TriangleLightPacked {
     float packed[12];
+    float alias_prob;
+    uint alias_idx;
+    float selection_pmf;
+    uint pad;
+};
+
+// Must match `GpuPointLight` in `world_renderer.rs`
+struct PointLightPacked {
+    float4 position_radius;
+    float4 intensity_spot_cos_outer;
+    float4 spot_direction_cos_inner;
+    float4 volumetric_intensity_range;
+};
+
+// Must match `GpuRectLight` in `world_renderer.rs`
+struct RectLightPacked {
+    float4 center_emission_map;
+    float4 half_extent_x;
+    float4 half_extent_y;
+    float4 radiance;
 };
 
 #endif
\ No newline at end of file
@@ -0,0 +1,73 @@
+#ifndef LIGHTS_POINT_HLSL
+#define LIGHTS_POINT_HLSL
+
+#include "../math.hlsl"
+#include "../frame_constants.hlsl"
+
+struct PointLight {
+    float3 position;
+    float radius;
+    float3 intensity;
+
+    // Spot lights only; `spot_cos_outer` is below -1 for omnidirectional lights.
+    float3 spot_direction;
+    float spot_cos_inner;
+    float spot_cos_outer;
+
+    // Scale of the light scattered in volumetric fog
+    float volumetric_intensity;
+
+    // Distance past which the light is culled
+    float range;
+
+    static PointLight from_packed(PointLightPacked p) {
+        PointLight res;
+        res.position = p.position_radius.xyz;
+        res.radius = p.position_radius.w;
+        res.intensity = p.intensity_spot_cos_outer.rgb * frame_constants.pre_exposure;
+        res.spot_direction = p.spot_direction_cos_inner.xyz;
+        res.spot_cos_inner = p.spot_direction_cos_inner.w;
+        res.spot_cos_outer = p.intensity_spot_cos_outer.w;
+        res.volumetric_intensity = p.volumetric_intensity_range.x;
+        res.range = p.volumetric_intensity_range.y;
+        return res;
+    }
+
+    // `dir` points away from the light
+    float spot_attenuation(float3 dir) {
+        if (spot_cos_outer < -1.0) {
+            return 1.0;
+        }
+
+        return smoothstep(spot_cos_outer, spot_cos_inner, dot(dir, spot_direction));
+    }
+};
+
+struct PointLightSample {
+    // Normalized direction from the shaded point towards the light
+    float3 dir;
+    float dist;
+
+    // Irradiance on a surface facing the light, before shadowing. Zero outside of spot cones.
+    float3 irradiance;
+};
+
+// Jitters the shadow ray target over the light's sphere, so that `radius` yields soft shadows.
+// The irradiance is clamped inside the sphere to avoid blowing up near the light.
+PointLightSample sample_point_light(PointLight light, float3 pt, float2 urand) {
+    const float3 target = light.position + uniform_sample_sphere(urand) * light.radius;
+    const float3 to_light = target - pt;
+    const float dist2 = max(1e-8, dot(to_light, to_light));
+
+    const float3 to_center = light.position - pt;
+    const float center_dist2 = max(dot(to_center, to_center), max(1e-4, light.radius * light.radius));
+    const float spot = light.spot_attenuation(-to_center * rsqrt(max(1e-8, dot(to_center, to_center))));
+
+    PointLightSample res;
+    res.dist = sqrt(dist2);
+    res.dir = to_light / res.dist;
+    res.irradiance = light.intensity / center_dist2 * spot;
+    return res;
+}
+
+#endif  // LIGHTS_POINT_HLSL
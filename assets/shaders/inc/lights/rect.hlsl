@@ -0,0 +1,204 @@
+#ifndef LIGHTS_RECT_HLSL
+#define LIGHTS_RECT_HLSL
+
+#include "../math.hlsl"
+#include "../frame_constants.hlsl"
+#include "../samplers.hlsl"
+#include "../bindless_textures.hlsl"
+#include "packed.hlsl"
+
+// Shading needs `brdf.hlsl`, `brdf_lut.hlsl` and `layered_brdf.hlsl` to be included first;
+// they lack include guards.
+
+static const uint RECT_LIGHT_NO_EMISSION_MAP = 0xffffffff;
+
+struct RectLight {
+    float3 center;
+    float3 half_extent_x;
+    float3 half_extent_y;
+
+    // Emission is towards the front, along `cross(half_extent_x, half_extent_y)`.
+    float3 normal;
+    float3 radiance;
+    uint emission_map;
+
+    static RectLight from_packed(RectLightPacked p) {
+        RectLight res;
+        res.center = p.center_emission_map.xyz;
+        res.emission_map = asuint(p.center_emission_map.w);
+        res.half_extent_x = p.half_extent_x.xyz;
+        res.half_extent_y = p.half_extent_y.xyz;
+        res.normal = normalize(cross(res.half_extent_x, res.half_extent_y));
+        res.radiance = p.radiance.rgb * frame_constants.pre_exposure;
+        return res;
+    }
+
+    float area() {
+        return 4.0 * length(half_extent_x) * length(half_extent_y);
+    }
+
+    bool is_facing(float3 pt) {
+        return dot(pt - center, normal) > 0.0;
+    }
+
+    // `urand` in [0, 1)
+    float3 sample_point(float2 urand) {
+        return center + half_extent_x * (urand.x * 2.0 - 1.0) + half_extent_y * (urand.y * 2.0 - 1.0);
+    }
+
+    // Point on the light closest to the ray, roughly; clamps the intersection
+    // with the light's plane, or the point of closest approach to the center.
+    float3 representative_point(float3 origin, float3 dir) {
+        const float3 to_center = center - origin;
+        const float dir_dot_normal = dot(dir, normal);
+
+        float t = dot(to_center, normal) / dir_dot_normal;
+        if (!(dir_dot_normal < -1e-4 && t > 0.0)) {
+            t = max(0.0, dot(to_center, dir));
+        }
+
+        const float3 rel = origin + dir * t - center;
+        const float x = clamp(dot(rel, half_extent_x) / dot(half_extent_x, half_extent_x), -1.0, 1.0);
+        const float y = clamp(dot(rel, half_extent_y) / dot(half_extent_y, half_extent_y), -1.0, 1.0);
+
+        return center + half_extent_x * x + half_extent_y * y;
+    }
+
+    // UVs run along +X and -Y from the top left corner, as with `RectLight::emission_map`.
+    float2 point_to_uv(float3 pt) {
+        const float3 rel = pt - center;
+        const float x = dot(rel, half_extent_x) / dot(half_extent_x, half_extent_x);
+        const float y = dot(rel, half_extent_y) / dot(half_extent_y, half_extent_y);
+        return float2(x, -y) * 0.5 + 0.5;
+    }
+
+    // Radiance filtered over `footprint`, in UV units
+    float3 emission(float2 uv, float footprint) {
+        if (emission_map == RECT_LIGHT_NO_EMISSION_MAP) {
+            return radiance;
+        }
+
+        const float2 size = bindless_texture_sizes[emission_map].xy;
+        const float lod = log2(max(1.0, footprint * max(size.x, size.y)));
+
+        return radiance * bindless_textures[NonUniformResourceIndex(emission_map)]
+            .SampleLevel(sampler_llc, uv, lod).rgb;
+    }
+};
+
+// `cross(v0, v1) * acos(dot(v0, v1)) / sin(acos(dot(v0, v1)))`, with the fit from
+// "Real-Time Line- and Disk-Light Shading with Linearly Transformed Cosines" by Heitz and Hill.
+float3 rect_light_edge_integral(float3 v0, float3 v1) {
+    const float x = dot(v0, v1);
+    const float y = abs(x);
+
+    const float a = 0.8543985 + (0.4965155 + 0.0145206 * y) * y;
+    const float b = 3.4175940 + (4.1616724 + y) * y;
+    const float v = a / b;
+
+    const float theta_over_sin_theta = x > 0.0 ? v : 0.5 * rsqrt(max(1.0 - x * x, 1e-7)) - v;
+    return cross(v0, v1) * theta_over_sin_theta;
+}
+
+// Points towards the light, with a length equal to its unclipped form factor.
+float3 rect_light_vector_form_factor(RectLight light, float3 pt) {
+    const float3 rel = light.center - pt;
+    const float3 v0 = normalize(rel - light.half_extent_x - light.half_extent_y);
+    const float3 v1 = normalize(rel + light.half_extent_x - light.half_extent_y);
+    const float3 v2 = normalize(rel + light.half_extent_x + light.half_extent_y);
+    const float3 v3 = normalize(rel - light.half_extent_x + light.half_extent_y);
+
+    const float3 res = (
+        rect_light_edge_integral(v0, v1)
+        + rect_light_edge_integral(v1, v2)
+        + rect_light_edge_integral(v2, v3)
+        + rect_light_edge_integral(v3, v0)
+    ) / M_TAU;
+
+    return dot(res, rel) < 0.0 ? -res : res;
+}
+
+// Form factor of a sphere of `sin_sigma_sq` unclipped form factor, at an elevation of `cos_theta`,
+// clipped by the horizon. From "Moving Frostbite to Physically Based Rendering" by Lagarde and de Rousiers.
+float horizon_clipped_sphere_form_factor(float cos_theta, float sin_sigma_sq) {
+    sin_sigma_sq = min(sin_sigma_sq, 0.9999);
+
+    if (cos_theta * cos_theta > sin_sigma_sq) {
+        return sin_sigma_sq * saturate(cos_theta);
+    }
+
+    const float sin_theta = sqrt(max(1e-8, 1.0 - cos_theta * cos_theta));
+    const float x = sqrt(1.0 / sin_sigma_sq - 1.0);
+    const float y = clamp(-x * cos_theta / sin_theta, -1.0, 1.0);
+    const float sin_theta_sqrt_y = sin_theta * sqrt(1.0 - y * y);
+
+    return max(0.0, (cos_theta * acos(y) - x * sin_theta_sqrt_y) * sin_sigma_sq + atan(sin_theta_sqrt_y / x)) * M_FRAC_1_PI;
+}
+
+// Unshadowed radiance reflected towards `wo` (in tangent space).
+//
+// The diffuse part integrates the light's polygon, with the average of its emission map.
+// The specular part evaluates the BRDF at the light's point closest to the reflection,
+// with the lobe widened to cover the light, as per "Real Shading in Unreal Engine 4" by Karis.
+float3 shade_rect_light_unshadowed(
+    RectLight light,
+    float3 pt,
+    float3 normal,
+    float3x3 tangent_to_world,
+    float3 wo,
+    LayeredBrdf brdf
+) {
+    if (!light.is_facing(pt)) {
+        return 0.0;
+    }
+
+    float3 res = 0.0;
+
+    const float3 vector_form_factor = rect_light_vector_form_factor(light, pt);
+    const float vector_form_factor_len = length(vector_form_factor);
+
+    if (vector_form_factor_len > 0.0) {
+        const float3 dir = vector_form_factor / vector_form_factor_len;
+        const float form_factor = horizon_clipped_sphere_form_factor(dot(dir, normal), vector_form_factor_len);
+
+        float3 wi = mul(dir, tangent_to_world);
+        wi.z = max(wi.z, 1e-3);
+        wi = normalize(wi);
+
+        const BrdfValue diff = brdf.diffuse_brdf.evaluate(wo, wi);
+        const BrdfValue spec = brdf.specular_brdf.evaluate(wo, wi);
+
+        // The emission's coarsest mip
+        const float3 emission = light.emission(0.5.xx, 1.0);
+        res += diff.value * spec.transmission_fraction * M_PI * form_factor * emission;
+    }
+
+    const float3 wo_ws = mul(tangent_to_world, wo);
+    const float3 target = light.representative_point(pt, reflect(-wo_ws, normal));
+    const float3 to_target = target - pt;
+    const float dist2 = max(1e-8, dot(to_target, to_target));
+    const float dist = sqrt(dist2);
+    const float3 wi_ws = to_target / dist;
+    const float3 wi = mul(wi_ws, tangent_to_world);
+
+    if (wi.z > 0.0 && wo.z > 0.0) {
+        const float area = light.area();
+        const float solid_angle = min(M_TAU, area * saturate(dot(-wi_ws, light.normal)) / (dist2 + area * M_FRAC_1_PI));
+
+        const float light_radius = sqrt(area * M_FRAC_1_PI);
+        const float a = max(1e-3, brdf.specular_brdf.roughness);
+        const float a_wide = saturate(a + light_radius / (2.0 * dist));
+        const float normalization = (a / a_wide) * (a / a_wide);
+
+        const float footprint = a_wide * dist / (2.0 * max(length(light.half_extent_x), length(light.half_extent_y)));
+        const float3 emission = light.emission(light.point_to_uv(target), footprint);
+
+        const BrdfValue spec = brdf.specular_brdf.evaluate(wo, wi);
+        res += spec.value * brdf.energy_preservation.preintegrated_reflection_mult
+            * wi.z * solid_angle * normalization * emission;
+    }
+
+    return res;
+}
+
+#endif  // LIGHTS_RECT_HLSL
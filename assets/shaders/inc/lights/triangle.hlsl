@@ -38,6 +38,24 @@ struct TriangleLight {
     }
 };
 
+struct TriangleLightSelection {
+    uint light_idx;
+    float pmf;
+};
+
+// Picks one of the triangle lights proportionally to its power, using the alias table
+// built in `world_renderer.rs`. Requires `frame_constants.triangle_light_count > 0`.
+TriangleLightSelection select_triangle_light(float2 urand) {
+    const uint light_count = frame_constants.triangle_light_count;
+    const uint bucket = min(uint(urand.x * light_count), light_count - 1);
+    const TriangleLightPacked entry = triangle_lights_dyn[bucket];
+
+    TriangleLightSelection res;
+    res.light_idx = urand.y < entry.alias_prob ? bucket : entry.alias_idx;
+    res.pmf = triangle_lights_dyn[res.light_idx].selection_pmf;
+    return res;
+}
+
 float3 sample_point_on_triangle(Triangle tri, float2 urand) {
     float su0 = sqrt(urand.x);
     float b0 = 1.0 - su0;
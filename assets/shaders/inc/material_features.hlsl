@@ -0,0 +1,15 @@
+#ifndef MATERIAL_FEATURES_HLSL
+#define MATERIAL_FEATURES_HLSL
+
+// Set by `MaterialFeatures` in `world_renderer.rs` for raster and ray tracing pipelines.
+// Everything is compiled in when they're not defined, e.g. in compute shaders.
+
+#ifndef MATERIAL_FEATURE_VERTEX_COLORS
+    #define MATERIAL_FEATURE_VERTEX_COLORS 1
+#endif
+
+#ifndef MATERIAL_FEATURE_NORMAL_MAPS
+    #define MATERIAL_FEATURE_NORMAL_MAPS 1
+#endif
+
+#endif  // MATERIAL_FEATURES_HLSL
@@ -191,4 +191,17 @@ float inverse_lerp(float minv, float maxv, float v) {
     return (v - minv) / (maxv - minv);
 }
 
+// Transforms a normal by the inverse transpose of the linear part of `xform`, so that it stays
+// perpendicular to the surface under non-uniform scale. Not normalized.
+float3 transform_normal(float3x4 xform, float3 n) {
+    const float3 c0 = float3(xform[0][0], xform[1][0], xform[2][0]);
+    const float3 c1 = float3(xform[0][1], xform[1][1], xform[2][1]);
+    const float3 c2 = float3(xform[0][2], xform[1][2], xform[2][2]);
+
+    // Cofactor matrix, i.e. the inverse transpose scaled by the determinant.
+    // Mirroring transforms flip the result back, to keep normals facing the same way.
+    const float3 res = n.x * cross(c1, c2) + n.y * cross(c2, c0) + n.z * cross(c0, c1);
+    return dot(c0, cross(c1, c2)) < 0.0 ? -res : res;
+}
+
 #endif
@@ -13,8 +13,10 @@ struct Mesh {
     uint vertex_mat_offset;
     uint vertex_aux_offset;
     uint vertex_tangent_offset;
-    uint mat_data_offset;
+    uint material_offset;
     uint index_offset;
+    float ray_bias;
+    uint opaque_triangle_count;
 };
 
 struct Vertex {
@@ -46,6 +48,14 @@ VertexPacked pack_vertex(Vertex v) {
 }
 
 static const uint MESH_MATERIAL_FLAG_EMISSIVE_USED_AS_LIGHT = 1;
+static const uint MESH_MATERIAL_CATEGORY_SHIFT = 3;
+static const uint MESH_MATERIAL_CATEGORY_MASK = 3u << MESH_MATERIAL_CATEGORY_SHIFT;
+
+// Must match `MaterialCategory` in `kajiya-asset/src/mesh.rs`
+static const uint MATERIAL_CATEGORY_DEFAULT = 0;
+static const uint MATERIAL_CATEGORY_SKIN = 1;
+static const uint MATERIAL_CATEGORY_FOLIAGE = 2;
+static const uint MATERIAL_CATEGORY_CLOTH = 3;
 
 struct MeshMaterial {
     float base_color_mult[4];
@@ -53,11 +63,15 @@ struct MeshMaterial {
     uint spec_map;
     uint albedo_map;
     uint emissive_map;
+    uint transmission_map;
     float roughness_mult;
     float metalness_factor;
     float emissive[3];
     uint flags;
     float map_transforms[6 * 4];
+    float ao_strength;
+    uint map_samplers;
+    float transmission;
 };
 
 float2 transform_material_uv(MeshMaterial mat, float2 uv, uint map_idx) {
@@ -67,5 +81,14 @@ float2 transform_material_uv(MeshMaterial mat, float2 uv, uint map_idx) {
     return mul(rot_scl, uv) + offset;
 }
 
+// Index into `material_samplers`; uses the same map indices as `transform_material_uv`.
+uint material_sampler_index(MeshMaterial mat, uint map_idx) {
+    return (mat.map_samplers >> (map_idx * 8)) & 0xff;
+}
+
+uint material_category(MeshMaterial mat) {
+    return (mat.flags & MESH_MATERIAL_CATEGORY_MASK) >> MESH_MATERIAL_CATEGORY_SHIFT;
+}
+
 
 #endif
\ No newline at end of file
@@ -0,0 +1,64 @@
+#ifndef REFLECTION_PROBES_HLSL
+#define REFLECTION_PROBES_HLSL
+
+#include "math.hlsl"
+#include "samplers.hlsl"
+
+// Must match `MAX_REFLECTION_PROBES` in `reflection_probes.rs`
+#define MAX_REFLECTION_PROBES 8
+
+// Mirrors `ReflectionProbeConstants` in `reflection_probes.rs`
+struct ReflectionProbeConstants {
+    // World-space position in `xyz`, and radius of influence in `w`; zero for unused slots.
+    float4 position_radius[MAX_REFLECTION_PROBES];
+    uint probe_count;
+    uint mip_count;
+    uint2 _pad;
+};
+
+// Fraction of the probe radius over which its influence fades out.
+static const float REFLECTION_PROBE_FADE_FRACTION = 0.2;
+
+// Blends the probes influencing `origin_ws`, in the direction `dir_ws`.
+// Returns radiance in `rgb`, and the total influence of the probes in `a`,
+// for blending with a more distant fallback.
+float4 sample_reflection_probes(
+    TextureCubeArray<float4> cubes,
+    ReflectionProbeConstants probes,
+    float3 origin_ws,
+    float3 dir_ws,
+    float perceptual_roughness
+) {
+    const float lod = perceptual_roughness * (probes.mip_count - 1);
+
+    float4 radiance_weight_sum = 0.0.xxxx;
+
+    for (uint probe_idx = 0; probe_idx < probes.probe_count; ++probe_idx) {
+        const float4 position_radius = probes.position_radius[probe_idx];
+        const float radius = position_radius.w;
+        const float dist = length(origin_ws - position_radius.xyz);
+
+        [branch]
+        if (dist < radius) {
+            const float weight = saturate((radius - dist) / (radius * REFLECTION_PROBE_FADE_FRACTION));
+
+            // Reproject onto a sphere of the probe's radius.
+            const RaySphereIntersection hit = Sphere::from_center_radius(position_radius.xyz, radius)
+                .intersect_ray_inside(origin_ws, dir_ws);
+            const float3 lookup_dir = origin_ws + dir_ws * hit.t - position_radius.xyz;
+
+            const float3 radiance = cubes.SampleLevel(sampler_llr, float4(lookup_dir, probe_idx), lod).rgb;
+            radiance_weight_sum += float4(radiance, 1.0) * weight;
+        }
+    }
+
+    if (radiance_weight_sum.a > 0.0) {
+        return float4(
+            radiance_weight_sum.rgb / radiance_weight_sum.a,
+            min(1.0, radiance_weight_sum.a));
+    } else {
+        return 0.0.xxxx;
+    }
+}
+
+#endif  // REFLECTION_PROBES_HLSL
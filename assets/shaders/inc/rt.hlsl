@@ -5,17 +5,71 @@
 #include "gbuffer.hlsl"
 #include "ray_cone.hlsl"
 
+// Instances are only visible to rays whose inclusion mask shares a bit with their TLAS mask.
+// Scene instances use 0x7f, and those being captured by `renderers/thumbnail.rs` have 0xff.
+// Instances which don't contribute to GI lack 0x01, which diffuse GI rays are limited to.
+// Ray tracing proxies lack 0x02, and the instances they stand in for only have 0x02,
+// so that other rays see the instance's own mesh, while shadows and GI see the proxy.
+#ifndef RT_INSTANCE_INCLUSION_MASK
+    #define RT_INSTANCE_INCLUSION_MASK 0x82
+    #define RT_SHADOW_INCLUSION_MASK 0xfd
+#endif
+
+#ifndef RT_SHADOW_INCLUSION_MASK
+    #define RT_SHADOW_INCLUSION_MASK RT_INSTANCE_INCLUSION_MASK
+#endif
+
+// Set by `SimpleRenderPass::new_rt_with_ray_query_fallback` in `RayTracingMode::RayQuery`.
+// The ray generation shader, whose entry point is declared with `RAYGEN_MAIN`, is then
+// compiled as a compute shader, and the helpers below trace rays inline with `RayQuery`.
+// Hits are shaded by `rt_shade_gbuffer_hit` rather than the pipeline's hit groups,
+// so custom hit groups, and their any-hit shaders, don't apply; triangles outside of
+// the opaque BLAS geometries get the default alpha test, `rt_is_alpha_cut_out`, instead.
+#ifndef RT_USE_RAY_QUERY
+    #define RT_USE_RAY_QUERY 0
+#endif
+
+#if RT_USE_RAY_QUERY
+    [[vk::push_constant]]
+    struct {
+        uint4 dispatch_rays_dimensions;
+    } rt_dispatch_constants;
+
+    static uint3 rt_dispatch_rays_index;
+
+    #define DispatchRaysIndex() (rt_dispatch_rays_index)
+    #define DispatchRaysDimensions() (rt_dispatch_constants.dispatch_rays_dimensions.xyz)
+
+    #define RAYGEN_MAIN \
+        void rt_raygen_main(); \
+        [numthreads(8, 8, 1)] \
+        void main(uint3 thread_id: SV_DispatchThreadID) { \
+            if (all(thread_id < DispatchRaysDimensions())) { \
+                rt_dispatch_rays_index = thread_id; \
+                rt_raygen_main(); \
+            } \
+        } \
+        void rt_raygen_main()
+#else
+    #define RAYGEN_MAIN [shader("raygeneration")] void main()
+#endif
+
 struct GbufferRayPayload {
     GbufferDataPacked gbuffer_packed;
     float t;
     RayCone ray_cone;
     uint path_length;
 
+    // Moves the hit off the surface, towards the side the ray came from, so that rays
+    // leaving it don't intersect it again. Sized by the geometry of the mesh hit.
+    float3 hit_offset;
+
     static GbufferRayPayload new_miss() {
         GbufferRayPayload res;
         res.t = FLT_MAX;
         res.ray_cone = RayCone::from_spread_angle(0.0);
         res.path_length = 0;
+        res.hit_offset = 0.0.xxx;
         return res;
     }
 
@@ -28,6 +82,8 @@ struct GbufferRayPayload {
     }
 };
 
+#include "rt_gbuffer_hit.hlsl"
+
 struct ShadowRayPayload {
     bool is_shadowed;
 
@@ -46,6 +102,21 @@ struct ShadowRayPayload {
     }
 };
 
+// In a `RayQuery::Proceed` loop, commits a candidate hit on a non-opaque triangle
+// if it passes the alpha test, as the pipeline's default any-hit shader would.
+#define RT_COMMIT_CANDIDATE_IF_NOT_CUT_OUT(query) \
+    if (query.CandidateType() == CANDIDATE_NON_OPAQUE_TRIANGLE) { \
+        const RtHitInstance candidate_hit = RtHitInstance::from_ids( \
+            query.CandidateInstanceID(), \
+            query.CandidateInstanceIndex(), \
+            query.CandidateGeometryIndex(), \
+            query.CandidateObjectToWorld3x4() \
+        ); \
+        if (!rt_is_alpha_cut_out(candidate_hit, query.CandidatePrimitiveIndex(), query.CandidateTriangleBarycentrics())) { \
+            query.CommitNonOpaqueTriangleHit(); \
+        } \
+    }
+
 RayDesc new_ray(float3 origin, float3 direction, float tmin, float tmax) {
     RayDesc ray;
     ray.Origin = origin;
@@ -59,14 +130,24 @@ bool rt_is_shadowed(
     RaytracingAccelerationStructure acceleration_structure,
     RayDesc ray
 ) {
+#if RT_USE_RAY_QUERY
+    RayQuery<RAY_FLAG_ACCEPT_FIRST_HIT_AND_END_SEARCH> query;
+    query.TraceRayInline(acceleration_structure, RAY_FLAG_NONE, RT_SHADOW_INCLUSION_MASK, ray);
+    while (query.Proceed()) {
+        RT_COMMIT_CANDIDATE_IF_NOT_CUT_OUT(query);
+    }
+
+    return query.CommittedStatus() == COMMITTED_TRIANGLE_HIT;
+#else
     ShadowRayPayload shadow_payload = ShadowRayPayload::new_hit();
     TraceRay(
         acceleration_structure,
         RAY_FLAG_ACCEPT_FIRST_HIT_AND_END_SEARCH | RAY_FLAG_SKIP_CLOSEST_HIT_SHADER,
-        0xff, 0, 0, 1, ray, shadow_payload
+        RT_SHADOW_INCLUSION_MASK, 0, 0, 1, ray, shadow_payload
     );
 
     return shadow_payload.is_shadowed;
+#endif
 }
 
 struct GbufferPathVertex {
@@ -119,12 +200,37 @@ struct GbufferRaytrace {
             trace_flags |= RAY_FLAG_CULL_BACK_FACING_TRIANGLES;
         }
 
-        TraceRay(acceleration_structure, trace_flags, 0xff, 0, 0, 0, this.ray, payload);
+#if RT_USE_RAY_QUERY
+        RayQuery<RAY_FLAG_NONE> query;
+        query.TraceRayInline(acceleration_structure, trace_flags, RT_INSTANCE_INCLUSION_MASK, this.ray);
+        while (query.Proceed()) {
+        RT_COMMIT_CANDIDATE_IF_NOT_CUT_OUT(query);
+    }
+
+        if (query.CommittedStatus() == COMMITTED_TRIANGLE_HIT) {
+            rt_shade_gbuffer_hit(
+                payload,
+                RtHitInstance::from_ids(
+                    query.CommittedInstanceID(),
+                    query.CommittedInstanceIndex(),
+                    query.CommittedGeometryIndex(),
+                    query.CommittedObjectToWorld3x4()
+                ),
+                query.CommittedPrimitiveIndex(),
+                query.CommittedTriangleBarycentrics(),
+                this.ray.Origin,
+                this.ray.Direction,
+                query.CommittedRayT()
+            );
+        }
+#else
+        TraceRay(acceleration_structure, trace_flags, RT_INSTANCE_INCLUSION_MASK, 0, 0, 0, this.ray, payload);
+#endif
 
         if (payload.is_hit()) {
             GbufferPathVertex res;
             res.is_hit = true;
-            res.position = ray.Origin + ray.Direction * payload.t;
+            res.position = ray.Origin + ray.Direction * payload.t + payload.hit_offset;
             res.gbuffer_packed = payload.gbuffer_packed;
             res.ray_t = payload.t;
             return res;
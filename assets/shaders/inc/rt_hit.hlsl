@@ -0,0 +1,65 @@
+#ifndef RT_HIT_HLSL
+#define RT_HIT_HLSL
+
+#include "bindless.hlsl"
+
+// Set in the custom index of the TLAS instance holding the combined BLAS
+// of static instances. See `instance_aggregation.rs`.
+#define RT_INSTANCE_ID_STATIC_AGGREGATE (1u << 23)
+#define RT_STATIC_AGGREGATE_TABLE_ALIGNMENT 128
+#define RT_STATIC_AGGREGATE_GEOMETRY_STRIDE 64
+
+// The scene instance a closest-hit shader is running for. Instances merged into
+// the combined BLAS are looked up from its geometry table by the geometry index.
+struct RtHitInstance {
+    uint mesh_index;
+    uint instance_index;
+    float3x4 object_to_world;
+
+    // Added to the primitive index to get the triangle in the mesh's index buffer,
+    // as the opaque and alpha-tested triangles are separate BLAS geometries.
+    uint primitive_offset;
+
+    // `from_intrinsics` for inline ray queries, which get these from the committed hit.
+    static RtHitInstance from_ids(
+        uint instance_id,
+        uint instance_index,
+        uint geometry_index,
+        float3x4 object_to_world
+    ) {
+        RtHitInstance res;
+
+        [branch]
+        if (instance_id & RT_INSTANCE_ID_STATIC_AGGREGATE) {
+            // Matches `GpuAggregateGeometry`
+            const uint entry_offset =
+                (instance_id & ~RT_INSTANCE_ID_STATIC_AGGREGATE) * RT_STATIC_AGGREGATE_TABLE_ALIGNMENT
+                + geometry_index * RT_STATIC_AGGREGATE_GEOMETRY_STRIDE;
+
+            const uint3 ids = vertices.Load3(entry_offset);
+            res.mesh_index = ids.x;
+            res.instance_index = ids.y;
+            res.primitive_offset = ids.z;
+            res.object_to_world = float3x4(
+                asfloat(vertices.Load4(entry_offset + 16)),
+                asfloat(vertices.Load4(entry_offset + 32)),
+                asfloat(vertices.Load4(entry_offset + 48))
+            );
+        } else {
+            res.mesh_index = instance_id;
+            res.instance_index = instance_index;
+            res.object_to_world = object_to_world;
+
+            // Matches `mesh_rt_geometries`: the opaque geometry, if any, comes first.
+            res.primitive_offset = geometry_index > 0 ? meshes[instance_id].opaque_triangle_count : 0;
+        }
+
+        return res;
+    }
+
+    static RtHitInstance from_intrinsics() {
+        return RtHitInstance::from_ids(InstanceID(), InstanceIndex(), GeometryIndex(), ObjectToWorld3x4());
+    }
+};
+
+#endif  // RT_HIT_HLSL
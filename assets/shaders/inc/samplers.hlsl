@@ -6,4 +6,15 @@
 [[vk::binding(34)]] SamplerState sampler_nnc;
 [[vk::binding(35)]] SamplerState sampler_llc;
 
+// `sampler_llr` with reduced anisotropic filtering; `sampler_llr` itself uses 16x.
+[[vk::binding(36)]] SamplerState sampler_llr_a1;
+[[vk::binding(37)]] SamplerState sampler_llr_a2;
+[[vk::binding(38)]] SamplerState sampler_llr_a4;
+[[vk::binding(39)]] SamplerState sampler_llr_a8;
+
+// Per-material sampler states, indexed via `material_sampler_index`.
+// See `MATERIAL_SAMPLER_DESCS` in `device.rs`. Index zero is equivalent to `sampler_llr`.
+static const uint MATERIAL_SAMPLER_COUNT = 6;
+[[vk::binding(40)]] SamplerState material_samplers[MATERIAL_SAMPLER_COUNT];
+
 #endif
\ No newline at end of file
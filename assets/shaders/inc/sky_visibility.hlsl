@@ -0,0 +1,98 @@
+#ifndef SKY_VISIBILITY_HLSL
+#define SKY_VISIBILITY_HLSL
+
+// Must match `SKY_VISIBILITY_RESOLUTION` in `sky_visibility.rs`
+#define SKY_VISIBILITY_RESOLUTION 256
+
+// Heightmap value of cells where the top-down ray found no geometry.
+static const float SKY_VISIBILITY_NO_HIT = -1e30;
+
+// Mirrors `SkyVisibilityConstants` in `sky_visibility.rs`
+struct SkyVisibilityConstants {
+    // Integer XZ coordinates of the field's first cell in `xy`, cell size in `z`,
+    // and `1` in `w` if the field is valid.
+    float4 origin_cell_size;
+    float fade_start;
+    float fade_inv_length;
+    float strength;
+    uint _pad;
+};
+
+// Cells are stored at their coordinates modulo the resolution, so that the field
+// can follow the camera without moving the cells already traced.
+uint2 sky_visibility_cell_to_texel(int2 cell) {
+    const int res = SKY_VISIBILITY_RESOLUTION;
+    return uint2(((cell % res) + res) % res);
+}
+
+// The cell currently stored at `texel`, for a field starting at `origin_cell`.
+int2 sky_visibility_texel_to_cell(uint2 texel, int2 origin_cell) {
+    const int res = SKY_VISIBILITY_RESOLUTION;
+    const int2 offset = ((int2(texel) - origin_cell) % res + res) % res;
+    return origin_cell + offset;
+}
+
+float sky_visibility_cell(
+    Texture2D<float> heightmap,
+    Texture2D<float> visibility,
+    float cell_size,
+    int2 cell,
+    float height
+) {
+    const uint2 texel = sky_visibility_cell_to_texel(cell);
+    const float top = heightmap[texel];
+
+    if (top == SKY_VISIBILITY_NO_HIT) {
+        return 1.0;
+    }
+
+    // Points well below the top-most surface of their cell are under an overhang or a canopy;
+    // fade towards full occlusion a few cells below it.
+    const float depth_below_top = max(0.0, top - height - cell_size);
+    return visibility[texel] * saturate(1.0 - depth_below_top / (4.0 * cell_size));
+}
+
+// Sky visibility multiplier for sky lighting at `pt_ws`, seen from `eye_ws`.
+// Returns 1 near the camera, outside the field, and when the field is disabled.
+float sample_sky_visibility(
+    Texture2D<float> heightmap,
+    Texture2D<float> visibility,
+    SkyVisibilityConstants consts,
+    float3 eye_ws,
+    float3 pt_ws
+) {
+    if (consts.origin_cell_size.w == 0.0) {
+        return 1.0;
+    }
+
+    const float fade = saturate((length(pt_ws - eye_ws) - consts.fade_start) * consts.fade_inv_length);
+    if (fade == 0.0) {
+        return 1.0;
+    }
+
+    const float cell_size = consts.origin_cell_size.z;
+    const float2 field_pos = pt_ws.xz / cell_size - consts.origin_cell_size.xy - 0.5;
+
+    // Fade out over the last few cells at the edges of the field.
+    const float edge_dist = min(
+        min(field_pos.x, field_pos.y),
+        SKY_VISIBILITY_RESOLUTION - 2 - max(field_pos.x, field_pos.y));
+    const float edge_fade = saturate(edge_dist / 8.0);
+    if (edge_fade == 0.0) {
+        return 1.0;
+    }
+
+    // Bilinear, with the toroidal addressing done per cell.
+    const int2 cell0 = int2(floor(field_pos)) + int2(consts.origin_cell_size.xy);
+    const float2 t = frac(field_pos);
+
+    const float v00 = sky_visibility_cell(heightmap, visibility, cell_size, cell0 + int2(0, 0), pt_ws.y);
+    const float v10 = sky_visibility_cell(heightmap, visibility, cell_size, cell0 + int2(1, 0), pt_ws.y);
+    const float v01 = sky_visibility_cell(heightmap, visibility, cell_size, cell0 + int2(0, 1), pt_ws.y);
+    const float v11 = sky_visibility_cell(heightmap, visibility, cell_size, cell0 + int2(1, 1), pt_ws.y);
+    const float vis = lerp(lerp(v00, v10, t.x), lerp(v01, v11, t.x), t.y);
+
+    return lerp(1.0, vis, fade * edge_fade * consts.strength);
+}
+
+#endif  // SKY_VISIBILITY_HLSL
@@ -0,0 +1,106 @@
+#ifndef SPECTRAL_HLSL
+#define SPECTRAL_HLSL
+
+#include "color/standard_observer.hlsl"
+#include "color/srgb.hlsl"
+
+// Hero wavelength spectral sampling (Wilkie et al. 2014, "Hero Wavelength Spectral Sampling")
+// for the reference path tracer. Each path carries three wavelengths, equally spaced across
+// the visible range from a random hero one. Quantities which are RGB elsewhere hold
+// the spectrum at those wavelengths in their lanes instead, so the BRDFs work unchanged.
+
+static const float SPECTRAL_WAVELENGTH_MIN = 380.0;
+static const float SPECTRAL_WAVELENGTH_MAX = 780.0;
+
+float3 spectral_sample_wavelengths(float urand) {
+    return lerp(
+        SPECTRAL_WAVELENGTH_MIN,
+        SPECTRAL_WAVELENGTH_MAX,
+        frac(urand + float3(0.0, 1.0 / 3.0, 2.0 / 3.0))
+    );
+}
+
+// CIE standard illuminant D65, from 380 to 780 nm in steps of 10 nm
+#define CIE_D65_LENGTH 41
+static const float CIE_D65[CIE_D65_LENGTH] = {
+    49.98, 54.65, 82.75, 91.49, 93.43, 86.68, 104.86, 117.01, 117.81, 114.86,
+    115.92, 108.81, 109.35, 107.80, 104.79, 107.69, 104.41, 104.05, 100.00, 96.33,
+    95.79, 88.69, 90.01, 89.60, 88.65, 83.29, 83.70, 80.03, 80.21, 82.28,
+    78.28, 69.72, 71.61, 74.35, 61.60, 69.89, 75.09, 63.59, 46.42, 66.81,
+    63.38
+};
+
+// Integral of `cie_d65` times the luminance matching function over the sampled range
+static const float CIE_D65_LUMINANCE = 10570.58;
+
+float cie_d65(float wavelength) {
+    const float index = clamp((wavelength - 380.0) / 10.0, 0.0, CIE_D65_LENGTH - 1.0);
+    const uint index0 = min(uint(index), CIE_D65_LENGTH - 2);
+    return lerp(CIE_D65[index0], CIE_D65[index0 + 1], index - index0);
+}
+
+float3 cie_xyz_matching_functions(float wavelength) {
+    const float index = clamp(
+        (wavelength - standard_observer_1931_w_min) / 5.0,
+        0.0,
+        standard_observer_1931_length - 1.0
+    );
+    const uint index0 = min(uint(index), standard_observer_1931_length - 2);
+    return lerp(standard_observer_1931[index0], standard_observer_1931[index0 + 1], index - index0);
+}
+
+// Linear sRGB reflectance to a smooth spectrum: the components weigh three basis spectra,
+// with sigmoid crossovers at 488 and 591 nm. The bases sum to one, so reflectances in [0, 1]
+// stay within it, and white is flat. Primaries come back within 2.5% of themselves.
+float3 rgb_to_spectral_reflectance(float3 rgb, float3 wavelengths) {
+    const float3 blue_weight = 1.0 - 1.0 / (1.0 + exp(-(wavelengths - 488.0) / 8.0));
+    const float3 red_weight = 1.0 / (1.0 + exp(-(wavelengths - 591.0) / 8.0));
+    const float3 green_weight = 1.0 - blue_weight - red_weight;
+
+    return rgb.r * red_weight + rgb.g * green_weight + rgb.b * blue_weight;
+}
+
+// Emission with a linear sRGB color; white is D65, as the sRGB white point.
+float3 rgb_to_spectral_radiance(float3 rgb, float3 wavelengths) {
+    const float3 illuminant = float3(
+        cie_d65(wavelengths.x),
+        cie_d65(wavelengths.y),
+        cie_d65(wavelengths.z)
+    ) / CIE_D65_LUMINANCE;
+
+    return rgb_to_spectral_reflectance(rgb, wavelengths) * illuminant;
+}
+
+// Monte Carlo estimate of the linear sRGB color of a spectrum sampled at `wavelengths`.
+// Out-of-gamut spectra, and single samples of most, can have negative components.
+float3 spectral_radiance_to_rgb(float3 radiance, float3 wavelengths) {
+    const float3 xyz =
+        radiance.x * cie_xyz_matching_functions(wavelengths.x)
+        + radiance.y * cie_xyz_matching_functions(wavelengths.y)
+        + radiance.z * cie_xyz_matching_functions(wavelengths.z);
+
+    return XYZ_to_sRGB(xyz * (SPECTRAL_WAVELENGTH_MAX - SPECTRAL_WAVELENGTH_MIN) / 3.0);
+}
+
+// Index of refraction of a dielectric with an index of 1.5 at the sodium d line (587.6 nm),
+// following Cauchy's equation fit to its Abbe number. Zero disables dispersion.
+float3 dispersive_ior(float abbe_number, float3 wavelengths) {
+    static const float IOR_D = 1.5;
+
+    if (abbe_number <= 0.0) {
+        return IOR_D;
+    }
+
+    // Hydrogen F and C lines
+    const float b = (IOR_D - 1.0) / (abbe_number * (1.0 / (486.1 * 486.1) - 1.0 / (656.3 * 656.3)));
+    const float a = IOR_D - b / (587.6 * 587.6);
+    return a + b / (wavelengths * wavelengths);
+}
+
+// Specular reflectance at normal incidence of a dielectric with the index of refraction `ior`
+float3 dielectric_f0(float3 ior) {
+    const float3 r = (ior - 1.0) / (ior + 1.0);
+    return r * r;
+}
+
+#endif  // SPECTRAL_HLSL
@@ -0,0 +1,12 @@
+#ifndef UNITS_HLSL
+#define UNITS_HLSL
+
+// Shading is done in radiance units of this many nits (cd/m^2); see `kajiya::units`.
+// Must match `NITS_PER_RADIANCE_UNIT` there.
+static const float NITS_PER_RADIANCE_UNIT = 6400.0;
+
+// Illuminance of the sun outside the atmosphere, at a `sun_color_multiplier` of one.
+// Must match `SOLAR_ILLUMINANCE_LUX` in `kajiya::units`.
+static const float SOLAR_ILLUMINANCE_LUX = 128000.0;
+
+#endif
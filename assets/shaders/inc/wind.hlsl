@@ -0,0 +1,59 @@
+#ifndef WIND_HLSL
+#define WIND_HLSL
+
+// Global wind, set with `WorldRenderer::wind`. Meant for vertex animation; the gust
+// texture comes from the bindless set, so the shaders need it bound as set 1.
+
+#include "frame_constants.hlsl"
+#include "bindless_textures.hlsl"
+#include "samplers.hlsl"
+
+// `FrameConstants::wind_gust_texture` without a gust texture
+#define WIND_GUST_TEXTURE_NONE 0xffffffff
+
+// Must match `WIND_TIME_WRAP_SECONDS` in `wind.rs`
+#define WIND_TIME_WRAP_SECONDS 3600.0
+
+// Unit vector the wind blows towards, or zero without wind
+float3 wind_direction_ws() {
+    return frame_constants.wind_direction_speed.xyz;
+}
+
+// Seconds since the renderer started, wrapping around every `WIND_TIME_WRAP_SECONDS`;
+// for flutter and other periodic motion.
+float wind_time_seconds() {
+    return frame_constants.wind_offset_time.w;
+}
+
+// Wind strength at `pos_ws` relative to `Wind::speed`: 1 without gusts,
+// and up to `1 + Wind::gust_strength` in them.
+float wind_gust_factor(float3 pos_ws) {
+    if (frame_constants.wind_gust_texture == WIND_GUST_TEXTURE_NONE) {
+        return 1.0;
+    }
+
+    // The gusts move with the air.
+    const float2 uv =
+        (pos_ws.xz - frame_constants.wind_offset_time.xz)
+        / world_units(frame_constants.wind_gust_scale);
+    const float gust =
+        bindless_textures[frame_constants.wind_gust_texture].SampleLevel(sampler_llr, uv, 0).r;
+
+    return 1.0 + frame_constants.wind_gust_strength * saturate(gust);
+}
+
+// Wind velocity at `pos_ws`, gusts included, in world units per second
+float3 wind_velocity_ws(float3 pos_ws) {
+    return wind_direction_ws()
+        * world_units(frame_constants.wind_direction_speed.w)
+        * wind_gust_factor(pos_ws);
+}
+
+// Displacement of a flexible point, such as the tip of a blade of grass or a corner
+// of a flag, bent downwind by `flexibility` seconds worth of the wind velocity.
+// Vertex shaders should apply it to the previous frame's position too, for motion vectors.
+float3 wind_sway_ws(float3 pos_ws, float flexibility) {
+    return wind_velocity_ws(pos_ws) * flexibility;
+}
+
+#endif  // WIND_HLSL
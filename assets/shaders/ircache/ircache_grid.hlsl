@@ -1,8 +1,8 @@
 #include "../inc/hash.hlsl"
 #include "ircache_constants.hlsl"
 
-// Must match CPU side
-static const float IRCACHE_GRID_CELL_DIAMETER = 0.16 * 0.125;
+// Must match CPU side. In meters; see `world_units`.
+static const float IRCACHE_GRID_CELL_DIAMETER_METERS = 0.16 * 0.125;
 
 static const uint IRCACHE_CASCADE_SIZE = 32;
 static const uint IRCACHE_CASCADE_COUNT = 12;
@@ -32,7 +32,7 @@ struct IrcacheCoord {
 };
 
 uint ws_local_pos_to_cascade_idx(float3 local_pos, uint reserved_cells) {
-    const float3 fcoord = local_pos / IRCACHE_GRID_CELL_DIAMETER;
+    const float3 fcoord = local_pos / world_units(IRCACHE_GRID_CELL_DIAMETER_METERS);
     const float max_coord = max(abs(fcoord.x), max(abs(fcoord.y), abs(fcoord.z)));
     const float cascade_float = log2(max_coord / (IRCACHE_CASCADE_SIZE / 2 - reserved_cells));
     return uint(clamp(ceil(max(0.0, cascade_float)), 0, IRCACHE_CASCADE_COUNT - 1));
@@ -53,12 +53,12 @@ IrcacheCoord ws_pos_to_ircache_coord(float3 pos, float3 normal, float3 jitter) {
     // Stochastic interpolation (no-op if jitter is zero)
     {
         const uint cascade = ws_local_pos_to_cascade_idx(pos - center, reserved_cells);
-        const float cell_diameter = (IRCACHE_GRID_CELL_DIAMETER * (1u << cascade));
+        const float cell_diameter = (world_units(IRCACHE_GRID_CELL_DIAMETER_METERS) * (1u << cascade));
         pos += cell_diameter * jitter;
     }
 
     const uint cascade = ws_local_pos_to_cascade_idx(pos - center, reserved_cells);
-    const float cell_diameter = (IRCACHE_GRID_CELL_DIAMETER * (1u << cascade));
+    const float cell_diameter = (world_units(IRCACHE_GRID_CELL_DIAMETER_METERS) * (1u << cascade));
     
     const int3 cascade_origin = frame_constants.ircache_cascades[cascade].origin.xyz;
 
@@ -76,5 +76,5 @@ IrcacheCoord ws_pos_to_ircache_coord(float3 pos, float3 normal, float3 jitter) {
 }
 
 float ircache_grid_cell_diameter_in_cascade(uint cascade) {
-    return IRCACHE_GRID_CELL_DIAMETER * (1u << uint(cascade));
+    return world_units(IRCACHE_GRID_CELL_DIAMETER_METERS) * (1u << uint(cascade));
 }
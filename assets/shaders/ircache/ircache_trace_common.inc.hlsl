@@ -97,7 +97,7 @@ IrcacheTraceResult ircache_trace(Vertex entry, DiffuseBrdf brdf, SampleParams sa
                 new_ray(
                     primary_hit.position,
                     to_light_norm,
-                    1e-4,
+                    world_units(1e-4),
                     FLT_MAX
             ));
 
@@ -129,16 +129,19 @@ IrcacheTraceResult ircache_trace(Vertex entry, DiffuseBrdf brdf, SampleParams sa
             const float3 brdf_value = brdf.evaluate_directional_light(wo, wi);
             const float3 light_radiance = select(is_shadowed, 0.0, SUN_COLOR);
             irradiance_sum += throughput * brdf_value * light_radiance * max(0.0, wi.z);
+            irradiance_sum += throughput * brdf.evaluate_transmission(wi) * light_radiance * max(0.0, -wi.z);
 
             if (USE_EMISSIVE) {
                 irradiance_sum += gbuffer.emissive * throughput;
             }
 
             if (USE_LIGHTS && frame_constants.triangle_light_count > 0/* && path_length > 0*/) {   // rtr comp
-                const float light_selection_pmf = 1.0 / frame_constants.triangle_light_count;
-                const uint light_idx = hash1_mut(rng) % frame_constants.triangle_light_count;
-                //const float light_selection_pmf = 1;
-                //for (uint light_idx = 0; light_idx < frame_constants.triangle_light_count; light_idx += 1)
+                const TriangleLightSelection light_selection = select_triangle_light(float2(
+                    uint_to_u01_float(hash1_mut(rng)),
+                    uint_to_u01_float(hash1_mut(rng))
+                ));
+                const float light_selection_pmf = light_selection.pmf;
+                const uint light_idx = light_selection.light_idx;
                 {
                     const float2 urand = float2(
                         uint_to_u01_float(hash1_mut(rng)),
@@ -166,8 +169,8 @@ IrcacheTraceResult ircache_trace(Vertex entry, DiffuseBrdf brdf, SampleParams sa
                                 new_ray(
                                     shadow_ray_origin,
                                     to_light_norm_ws,
-                                    1e-3,
-                                    sqrt(dist_to_light2) - 2e-3
+                                    world_units(1e-3),
+                                    sqrt(dist_to_light2) - world_units(2e-3)
                             ));
 
                         irradiance_sum +=
@@ -176,13 +179,46 @@ IrcacheTraceResult ircache_trace(Vertex entry, DiffuseBrdf brdf, SampleParams sa
                     }
                 }
             }
+
+            if (USE_LIGHTS && frame_constants.point_light_count > 0) {
+                const float light_selection_pmf = 1.0 / frame_constants.point_light_count;
+                const uint light_idx = hash1_mut(rng) % frame_constants.point_light_count;
+
+                const float2 urand = float2(
+                    uint_to_u01_float(hash1_mut(rng)),
+                    uint_to_u01_float(hash1_mut(rng))
+                );
+
+                const PointLight point_light = PointLight::from_packed(point_lights_dyn[light_idx]);
+                const PointLightSample light_sample = sample_point_light(point_light, primary_hit.position, urand);
+                const float3 wi = mul(light_sample.dir, tangent_to_world);
+
+                if (wi.z != 0.0 && any(light_sample.irradiance > 0.0)) {
+                    const bool is_shadowed =
+                        rt_is_shadowed(
+                            acceleration_structure,
+                            new_ray(
+                                primary_hit.position,
+                                light_sample.dir,
+                                world_units(1e-3),
+                                light_sample.dist - world_units(2e-3)
+                        ));
+
+                    const float3 brdf_value = brdf.evaluate_directional_light(wo, wi) * max(0.0, wi.z)
+                        + brdf.evaluate_transmission(wi) * max(0.0, -wi.z);
+
+                    irradiance_sum +=
+                        select(is_shadowed, 0,
+                            throughput * brdf_value * light_sample.irradiance / light_selection_pmf);
+                }
+            }
             
             if (SAMPLE_IRCACHE_AT_LAST_VERTEX && path_length + 1 == MAX_PATH_LENGTH) {
                 irradiance_sum +=
                     IrcacheLookupParams::create(entry.position, primary_hit.position, gbuffer.normal)
                         .with_query_rank(1 + ircache_entry_life_to_rank(life))
                         .lookup(rng)
-                        * throughput * gbuffer.albedo;
+                        * throughput * gbuffer.albedo * gbuffer.gi_reception;
             }
 
             const float3 urand = float3(
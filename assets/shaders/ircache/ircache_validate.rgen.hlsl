@@ -1,3 +1,6 @@
+// Diffuse GI rays skip instances flagged with `InstanceFlags::NO_GI_CONTRIBUTION`.
+#define RT_INSTANCE_INCLUSION_MASK 0x01
+
 #include "../inc/uv.hlsl"
 #include "../inc/pack_unpack.hlsl"
 #include "../inc/frame_constants.hlsl"
@@ -13,6 +16,7 @@
 #include "../inc/atmosphere.hlsl"
 #include "../inc/mesh.hlsl"
 #include "../inc/lights/triangle.hlsl"
+#include "../inc/lights/point.hlsl"
 #include "../wrc/bindings.hlsl"
 #include "../inc/color.hlsl"
 
@@ -217,6 +217,11 @@ float eval_sh_nope(float4 sh, float3 normal) {
 
 
 float3 IrcacheLookupParams::lookup(inout uint rng) {
+    // The cache isn't being traced; don't allocate entries, nor return stale irradiance.
+    if (frame_constants.render_overrides.has_flag(RenderOverrideFlags::NO_IRCACHE)) {
+        return 0.0.xxx;
+    }
+
     IrcacheLookupMaybeAllocate lookup = lookup_maybe_allocate(rng);
 
     if (lookup.just_allocated) {
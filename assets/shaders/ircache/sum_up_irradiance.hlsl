@@ -80,7 +80,8 @@ void main(uint dispatch_idx: SV_DispatchThreadID) {
             prev_value = new_value;
         }
 
-        float blend_factor_new = 0.25;
+        // Blend faster while the sun is moving, so that the cache doesn't lag behind it.
+        float blend_factor_new = 0.25 / max(0.25, frame_constants.gi_history_scale);
         //float blend_factor_new = 1;
         const float4 blended_value = lerp(prev_value, new_value, blend_factor_new);
 
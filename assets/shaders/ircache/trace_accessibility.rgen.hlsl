@@ -3,6 +3,9 @@
 //
 // This speeds up transitions between indoors/outdoors for cache entries which span both sides.
 
+// Diffuse GI rays skip instances flagged with `InstanceFlags::NO_GI_CONTRIBUTION`.
+#define RT_INSTANCE_INCLUSION_MASK 0x01
+
 #include "../inc/frame_constants.hlsl"
 #include "../inc/rt.hlsl"
 #include "../inc/reservoir.hlsl"
@@ -7,6 +7,7 @@
 #include "inc/layered_brdf.hlsl"
 #include "inc/uv.hlsl"
 #include "inc/bindless_textures.hlsl"
+#include "inc/sky_visibility.hlsl"
 #include "rtr/rtr_settings.hlsl"
 #include "wrc/wrc_settings.hlsl"
 #include "ircache/bindings.hlsl"
@@ -36,10 +37,16 @@ DEFINE_WRC_BINDINGS(14)
 [[vk::binding(16)]] RWTexture2D<float4> output_tex;
 [[vk::binding(17)]] TextureCube<float4> unconvolved_sky_cube_tex;
 [[vk::binding(18)]] TextureCube<float4> sky_cube_tex;
-[[vk::binding(19)]] cbuffer _ {
+[[vk::binding(19)]] Texture2D<float> ao_tex;
+[[vk::binding(20)]] Texture2D<float4> local_lights_tex;
+[[vk::binding(21)]] Texture2D<float> sky_visibility_heightmap_tex;
+[[vk::binding(22)]] Texture2D<float> sky_visibility_tex;
+[[vk::binding(23)]] cbuffer _ {
     float4 output_tex_size;
     uint debug_shading_mode;
     uint debug_show_wrc;
+    uint2 _pad;
+    SkyVisibilityConstants sky_visibility;
 };
 
 #define IRCACHE_LOOKUP_DONT_KEEP_ALIVE
@@ -53,6 +60,8 @@ DEFINE_WRC_BINDINGS(14)
 #define SHADING_MODE_REFLECTIONS 3
 #define SHADING_MODE_RTX_OFF 4
 #define SHADING_MODE_IRCACHE 5
+#define SHADING_MODE_AMBIENT_OCCLUSION 6
+#define SHADING_MODE_MATERIAL_CATEGORIES 7
 
 #include "inc/atmosphere.hlsl"
 #include "inc/sun.hlsl"
@@ -148,18 +157,49 @@ void main(in uint2 px : SV_DispatchThreadID) {
         wo = normalize(wo);
     }
 
+    const MaterialCategoryConstants category = frame_constants.material_categories[gbuffer.material_category];
+
     LayeredBrdf brdf = LayeredBrdf::from_gbuffer_ndotv(gbuffer, wo.z);
     const float3 brdf_value = brdf.evaluate_directional_light(wo, wi) * max(0.0, wi.z);
     const float3 light_radiance = shadow_mask * SUN_COLOR;
     float3 total_radiance = brdf_value * light_radiance;
 
+    // Cheap sheen of fabric fibers and fuzz: an albedo-tinted lobe rising at grazing view angles.
+    const float3 sheen_albedo = category.sheen * gbuffer.albedo * pow(1.0 - saturate(wo.z), 5.0);
+    total_radiance += sheen_albedo / M_PI * max(0.0, wi.z) * light_radiance;
+
+    // Thin-surface transmission of light arriving at the back of the surface.
+    // `trace_sun_shadow_mask` traces from the sun-facing side for translucent surfaces.
+    total_radiance += brdf.evaluate_transmission(wi) * max(0.0, -wi.z) * light_radiance;
+
+    // Direct lighting from point and rect lights; see `lighting/point_lights.rgen.hlsl`
+    // and `lighting/rect_lights.hlsl`.
+    total_radiance += local_lights_tex[px].rgb;
+
     total_radiance += gbuffer.emissive;
 
     float3 gi_irradiance = 0.0.xxx;
 
     if (debug_shading_mode != SHADING_MODE_RTX_OFF) {
         if (USE_RTDGI) {
-            gi_irradiance = rtdgi_tex[px].rgb;
+            gi_irradiance = rtdgi_tex[px].rgb * category.gi_strength * gbuffer.gi_reception;
+        }
+
+        // Far away, diffuse GI is mostly sky light, and too coarse to show large-scale occlusion.
+        gi_irradiance *= sample_sky_visibility(
+            sky_visibility_heightmap_tex,
+            sky_visibility_tex,
+            sky_visibility,
+            get_eye_position(),
+            pt_ws.xyz);
+
+        // Diffuse GI only gathers the front hemisphere; get the back side's irradiance
+        // for the transmission lobe from the irradiance cache instead.
+        [branch]
+        if (gbuffer.transmission > 0.0) {
+            const float3 back_irradiance =
+                IrcacheLookupParams::create(get_eye_position(), pt_ws.xyz, -gbuffer.normal).lookup(rng);
+            total_radiance += back_irradiance * category.gi_strength * gbuffer.gi_reception * brdf.transmission_albedo;
         }
     }
 
@@ -169,6 +209,7 @@ void main(in uint2 px : SV_DispatchThreadID) {
             * brdf.energy_preservation.preintegrated_transmission_fraction
         #endif
         ;
+    total_radiance += gi_irradiance * sheen_albedo;
 
     if (USE_RTR && !LAYERED_BRDF_FORCE_DIFFUSE_ONLY && debug_shading_mode != SHADING_MODE_RTX_OFF) {
         float3 rtr_radiance;
@@ -225,6 +266,23 @@ void main(in uint2 px : SV_DispatchThreadID) {
         output = gi_irradiance;
     }
 
+    [branch]
+    if (debug_shading_mode == SHADING_MODE_AMBIENT_OCCLUSION) {
+        output = ao_tex[px];
+    }
+
+    [branch]
+    if (debug_shading_mode == SHADING_MODE_MATERIAL_CATEGORIES) {
+        // Default: gray, skin: orange, foliage: green, cloth: blue; shaded by the sun.
+        static const float3 CATEGORY_COLORS[4] = {
+            float3(0.5, 0.5, 0.5),
+            float3(1.0, 0.45, 0.2),
+            float3(0.2, 0.8, 0.15),
+            float3(0.2, 0.35, 1.0),
+        };
+        output = CATEGORY_COLORS[gbuffer.material_category] * (0.2 + 0.8 * max(0.0, wi.z));
+    }
+
     [branch]
     if (debug_shading_mode == SHADING_MODE_IRCACHE) {
         output = brdf_value * light_radiance * 0;
@@ -0,0 +1,129 @@
+// Lists the point lights reaching the opaque geometry of each screen tile, ordered by their
+// estimated irradiance there, so that `point_lights.rgen.hlsl` can spend shadow rays on the
+// most important ones only. Lights past `MAX_POINT_LIGHTS_PER_TILE` in a tile are dropped
+// in no particular order.
+
+#include "../inc/lights/point.hlsl"
+#include "point_light_tiles.hlsl"
+
+[[vk::binding(0)]] Texture2D<float> depth_tex;
+[[vk::binding(1)]] RWStructuredBuffer<uint> tile_lights;
+[[vk::binding(2)]] cbuffer _ {
+    uint2 output_extent;
+    uint2 tile_count;
+};
+
+#define THREAD_COUNT (POINT_LIGHT_TILE_SIZE * POINT_LIGHT_TILE_SIZE)
+
+// Positive view depths, compared as uints
+groupshared uint gs_min_depth;
+groupshared uint gs_max_depth;
+
+groupshared uint gs_light_count;
+groupshared uint gs_lights[MAX_POINT_LIGHTS_PER_TILE];
+groupshared float gs_importance[MAX_POINT_LIGHTS_PER_TILE];
+
+[numthreads(POINT_LIGHT_TILE_SIZE, POINT_LIGHT_TILE_SIZE, 1)]
+void main(uint2 px: SV_DispatchThreadID, uint2 tile: SV_GroupID, uint thread_idx: SV_GroupIndex) {
+    if (0 == thread_idx) {
+        gs_min_depth = asuint(1e30);
+        gs_max_depth = 0;
+        gs_light_count = 0;
+    }
+    GroupMemoryBarrierWithGroupSync();
+
+    if (all(px < output_extent)) {
+        const float z_over_w = depth_tex[px];
+        if (z_over_w != 0.0) {
+            const uint view_depth = asuint(-depth_to_view_z(z_over_w));
+            InterlockedMin(gs_min_depth, view_depth);
+            InterlockedMax(gs_max_depth, view_depth);
+        }
+    }
+    GroupMemoryBarrierWithGroupSync();
+
+    const uint base = (tile.x + tile.y * tile_count.x) * POINT_LIGHT_TILE_STRIDE;
+
+    // Only sky in this tile
+    if (gs_min_depth > gs_max_depth) {
+        if (0 == thread_idx) {
+            tile_lights[base] = 0;
+        }
+        return;
+    }
+
+    const float min_depth = asfloat(gs_min_depth);
+    const float max_depth = asfloat(gs_max_depth);
+
+    const float2 uv_min = float2(tile * POINT_LIGHT_TILE_SIZE) / output_extent;
+    const float2 uv_max = min(1.0, float2((tile + 1) * POINT_LIGHT_TILE_SIZE) / output_extent);
+
+    const float3 corner_rays[4] = {
+        point_light_tile_view_ray(uv_min),
+        point_light_tile_view_ray(float2(uv_max.x, uv_min.y)),
+        point_light_tile_view_ray(float2(uv_min.x, uv_max.y)),
+        point_light_tile_view_ray(uv_max),
+    };
+
+    // View-space bounding box of the tile's frustum between the depth bounds
+    float3 aabb_min = 1e30;
+    float3 aabb_max = -1e30;
+    for (uint i = 0; i < 4; ++i) {
+        aabb_min = min(aabb_min, min(corner_rays[i] * min_depth, corner_rays[i] * max_depth));
+        aabb_max = max(aabb_max, max(corner_rays[i] * min_depth, corner_rays[i] * max_depth));
+    }
+    const float3 aabb_center = (aabb_min + aabb_max) * 0.5;
+
+    for (uint light_idx = thread_idx; light_idx < frame_constants.point_light_count; light_idx += THREAD_COUNT) {
+        const PointLight light = PointLight::from_packed(point_lights_dyn[light_idx]);
+        const float3 center_vs = position_world_to_view(light.position);
+
+        const float3 offset = max(0.0, max(aabb_min - center_vs, center_vs - aabb_max));
+        if (dot(offset, offset) > light.range * light.range) {
+            continue;
+        }
+
+        // Irradiance at the middle of the tile, clamped inside the light like in `sample_point_light`
+        const float3 to_center = aabb_center - center_vs;
+        const float importance = max3(light.intensity.r, light.intensity.g, light.intensity.b)
+            / max(dot(to_center, to_center), max(1e-4, light.radius * light.radius));
+
+        uint slot;
+        InterlockedAdd(gs_light_count, 1, slot);
+
+        if (slot < MAX_POINT_LIGHTS_PER_TILE) {
+            gs_lights[slot] = light_idx;
+            gs_importance[slot] = importance;
+        }
+    }
+    GroupMemoryBarrierWithGroupSync();
+
+    if (0 == thread_idx) {
+        const uint count = min(gs_light_count, MAX_POINT_LIGHTS_PER_TILE);
+
+        // Insertion sort, most important first
+        for (uint i = 1; i < count; ++i) {
+            const uint light_idx = gs_lights[i];
+            const float importance = gs_importance[i];
+
+            uint j = i;
+            while (j > 0) {
+                if (gs_importance[j - 1] >= importance) {
+                    break;
+                }
+
+                gs_lights[j] = gs_lights[j - 1];
+                gs_importance[j] = gs_importance[j - 1];
+                j -= 1;
+            }
+
+            gs_lights[j] = light_idx;
+            gs_importance[j] = importance;
+        }
+
+        tile_lights[base] = count;
+        for (uint i = 0; i < count; ++i) {
+            tile_lights[base + 1 + i] = gs_lights[i];
+        }
+    }
+}
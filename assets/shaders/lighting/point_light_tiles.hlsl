@@ -0,0 +1,26 @@
+#ifndef LIGHTING_POINT_LIGHT_TILES_HLSL
+#define LIGHTING_POINT_LIGHT_TILES_HLSL
+
+#include "../inc/frame_constants.hlsl"
+#include "../inc/uv.hlsl"
+
+// Must match `renderers/lighting.rs`
+#define POINT_LIGHT_TILE_SIZE 16
+
+// A count followed by light indices, most important first
+#define POINT_LIGHT_TILE_STRIDE 64
+#define MAX_POINT_LIGHTS_PER_TILE (POINT_LIGHT_TILE_STRIDE - 1)
+
+uint point_light_tile_base(uint2 px, uint tile_count_x) {
+    const uint2 tile = px / POINT_LIGHT_TILE_SIZE;
+    return (tile.x + tile.y * tile_count_x) * POINT_LIGHT_TILE_STRIDE;
+}
+
+// Direction of the un-jittered view ray through `uv`, in view space, scaled to unit view depth.
+float3 point_light_tile_view_ray(float2 uv) {
+    // The far plane is at infinity with reverse-Z, so this is already a direction.
+    const float3 dir = mul(frame_constants.view_constants.clip_to_view, float4(uv_to_cs(uv), 0.0, 1.0)).xyz;
+    return dir / -dir.z;
+}
+
+#endif  // LIGHTING_POINT_LIGHT_TILES_HLSL
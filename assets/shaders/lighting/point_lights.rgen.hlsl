@@ -0,0 +1,102 @@
+#include "../inc/uv.hlsl"
+#include "../inc/pack_unpack.hlsl"
+#include "../inc/frame_constants.hlsl"
+#include "../inc/gbuffer.hlsl"
+#include "../inc/brdf.hlsl"
+#include "../inc/brdf_lut.hlsl"
+#include "../inc/layered_brdf.hlsl"
+#include "../inc/blue_noise.hlsl"
+#include "../inc/rt.hlsl"
+#include "../inc/math.hlsl"
+#include "../inc/lights/point.hlsl"
+#include "point_light_tiles.hlsl"
+
+[[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
+[[vk::binding(0)]] Texture2D<float> depth_tex;
+[[vk::binding(1)]] Texture2D<float3> geometric_normal_tex;
+[[vk::binding(2)]] Texture2D<float4> gbuffer_tex;
+[[vk::binding(3)]] StructuredBuffer<uint> tile_lights;
+[[vk::binding(4)]] RWTexture2D<float4> output_tex;
+[[vk::binding(5)]] cbuffer _ {
+    uint tile_count_x;
+    uint shadowed_lights_per_tile;
+};
+
+// Direct lighting from the point lights listed in the pixel's tile by `point_light_cull.hlsl`.
+// The first `shadowed_lights_per_tile` of them get a ray-traced shadow, and the rest are unshadowed.
+[shader("raygeneration")]
+void main() {
+    const uint2 px = DispatchRaysIndex().xy;
+
+    const float2 pixel_center = px + 0.5.xx;
+    const float2 uv = pixel_center / DispatchRaysDimensions().xy;
+
+    const float z_over_w = depth_tex[px];
+    if (0.0 == z_over_w) {
+        output_tex[px] = 0.0;
+        return;
+    }
+
+    const ViewRayContext view_ray_context = ViewRayContext::from_uv_and_depth(uv, z_over_w);
+    const float3 pt_ws = view_ray_context.ray_hit_ws();
+    const float3 pt_vs = view_ray_context.ray_hit_vs();
+
+    const float3 normal_vs = geometric_normal_tex[px] * 2.0 - 1.0;
+    const float3 normal_ws = direction_view_to_world(normal_vs);
+    const float bias_amount = (-pt_vs.z + length(pt_ws)) * 1e-5;
+
+    const GbufferData gbuffer = GbufferDataPacked::from_uint4(asuint(gbuffer_tex[px])).unpack();
+    const float3x3 tangent_to_world = build_orthonormal_basis(gbuffer.normal);
+    float3 wo = mul(-view_ray_context.ray_dir_ws(), tangent_to_world);
+
+    // Same hack as in `light_gbuffer.hlsl` for shading normals facing away from the viewer.
+    if (wo.z < 0.0) {
+        wo.z *= -0.25;
+        wo = normalize(wo);
+    }
+
+    const LayeredBrdf brdf = LayeredBrdf::from_gbuffer_ndotv(gbuffer, wo.z);
+    const float2 urand = blue_noise_for_pixel(px, frame_constants.frame_index).xy;
+
+    float3 total_radiance = 0.0.xxx;
+
+    const uint tile_base = point_light_tile_base(px, tile_count_x);
+    const uint tile_light_count = tile_lights[tile_base];
+
+    for (uint tile_light_idx = 0; tile_light_idx < tile_light_count; tile_light_idx += 1) {
+        const uint light_idx = tile_lights[tile_base + 1 + tile_light_idx];
+        const PointLight point_light = PointLight::from_packed(point_lights_dyn[light_idx]);
+        const PointLightSample light_sample = sample_point_light(point_light, pt_ws, urand);
+        const float3 wi = mul(light_sample.dir, tangent_to_world);
+
+        float3 brdf_value = brdf.evaluate_directional_light(wo, wi) * max(0.0, wi.z);
+        float3 bias_dir = normal_ws;
+
+        // Light arriving at the back of thin translucent surfaces; trace from that side,
+        // as `trace_sun_shadow_mask` does for the sun.
+        if (wi.z < 0.0 && gbuffer.transmission > 0.0) {
+            brdf_value = brdf.evaluate_transmission(wi) * -wi.z;
+            bias_dir = -normal_ws;
+        }
+
+        if (all(brdf_value * light_sample.irradiance == 0.0)) {
+            continue;
+        }
+
+        bool is_shadowed = false;
+        if (tile_light_idx < shadowed_lights_per_tile) {
+            is_shadowed = rt_is_shadowed(
+                acceleration_structure,
+                new_ray(
+                    pt_ws + bias_dir * bias_amount,
+                    light_sample.dir,
+                    0,
+                    light_sample.dist - bias_amount
+                ));
+        }
+
+        total_radiance += select(is_shadowed, 0.0, brdf_value * light_sample.irradiance);
+    }
+
+    output_tex[px] = float4(total_radiance, 1.0);
+}
@@ -0,0 +1,106 @@
+#include "../inc/uv.hlsl"
+#include "../inc/pack_unpack.hlsl"
+#include "../inc/frame_constants.hlsl"
+#include "../inc/gbuffer.hlsl"
+#include "../inc/brdf.hlsl"
+#include "../inc/brdf_lut.hlsl"
+#include "../inc/layered_brdf.hlsl"
+#include "../inc/blue_noise.hlsl"
+#include "../inc/rt.hlsl"
+#include "../inc/math.hlsl"
+#include "../inc/lights/rect.hlsl"
+
+[[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
+[[vk::binding(0)]] Texture2D<float> depth_tex;
+[[vk::binding(1)]] Texture2D<float3> geometric_normal_tex;
+[[vk::binding(2)]] Texture2D<float4> gbuffer_tex;
+[[vk::binding(3)]] StructuredBuffer<RectLightPacked> rect_lights;
+[[vk::binding(4)]] RWTexture2D<float> output_tex;
+[[vk::binding(5)]] cbuffer _ {
+    uint rect_light_count;
+};
+
+// One shadow ray per pixel towards a random point on one of the rect lights, picked in proportion
+// to its unshadowed contribution. The mean of the mask is then the ratio of shadowed to unshadowed
+// lighting, which `rect_lights.hlsl` applies to the analytic shading once it's been denoised.
+[shader("raygeneration")]
+void main() {
+    const uint2 px = DispatchRaysIndex().xy;
+
+    const float2 pixel_center = px + 0.5.xx;
+    const float2 uv = pixel_center / DispatchRaysDimensions().xy;
+
+    const float z_over_w = depth_tex[px];
+    if (0.0 == z_over_w) {
+        output_tex[px] = 1.0;
+        return;
+    }
+
+    const ViewRayContext view_ray_context = ViewRayContext::from_uv_and_depth(uv, z_over_w);
+    const float3 pt_ws = view_ray_context.ray_hit_ws();
+    const float3 pt_vs = view_ray_context.ray_hit_vs();
+
+    const float3 normal_vs = geometric_normal_tex[px] * 2.0 - 1.0;
+    const float3 normal_ws = direction_view_to_world(normal_vs);
+    const float bias_amount = (-pt_vs.z + length(pt_ws)) * 1e-5;
+
+    const GbufferData gbuffer = GbufferDataPacked::from_uint4(asuint(gbuffer_tex[px])).unpack();
+    const float3x3 tangent_to_world = build_orthonormal_basis(gbuffer.normal);
+    float3 wo = mul(-view_ray_context.ray_dir_ws(), tangent_to_world);
+
+    // Same hack as in `light_gbuffer.hlsl` for shading normals facing away from the viewer.
+    if (wo.z < 0.0) {
+        wo.z *= -0.25;
+        wo = normalize(wo);
+    }
+
+    const LayeredBrdf brdf = LayeredBrdf::from_gbuffer_ndotv(gbuffer, wo.z);
+
+    float total_weight = 0.0;
+    for (uint light_idx = 0; light_idx < rect_light_count; light_idx += 1) {
+        const RectLight light = RectLight::from_packed(rect_lights[light_idx]);
+        total_weight += sRGB_to_luminance(
+            shade_rect_light_unshadowed(light, pt_ws, gbuffer.normal, tangent_to_world, wo, brdf)
+        );
+    }
+
+    if (total_weight <= 0.0) {
+        output_tex[px] = 1.0;
+        return;
+    }
+
+    const float3 urand = blue_noise_for_pixel(px, frame_constants.frame_index).xyz;
+
+    // Walk the CDF again to pick the light.
+    float threshold = urand.z * total_weight;
+    uint picked_idx = rect_light_count - 1;
+    for (uint light_idx = 0; light_idx < rect_light_count; light_idx += 1) {
+        const RectLight light = RectLight::from_packed(rect_lights[light_idx]);
+        const float weight = sRGB_to_luminance(
+            shade_rect_light_unshadowed(light, pt_ws, gbuffer.normal, tangent_to_world, wo, brdf)
+        );
+
+        if (threshold < weight) {
+            picked_idx = light_idx;
+            break;
+        }
+
+        threshold -= weight;
+    }
+
+    const RectLight light = RectLight::from_packed(rect_lights[picked_idx]);
+    const float3 to_light = light.sample_point(urand.xy) - pt_ws;
+    const float dist = length(to_light);
+    const float3 dir = to_light / max(1e-8, dist);
+
+    const bool is_shadowed = rt_is_shadowed(
+        acceleration_structure,
+        new_ray(
+            pt_ws + normal_ws * bias_amount,
+            dir,
+            0,
+            dist - bias_amount
+        ));
+
+    output_tex[px] = select(is_shadowed, 0.0, 1.0);
+}
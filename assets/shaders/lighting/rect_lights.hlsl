@@ -0,0 +1,53 @@
+#include "../inc/uv.hlsl"
+#include "../inc/pack_unpack.hlsl"
+#include "../inc/frame_constants.hlsl"
+#include "../inc/gbuffer.hlsl"
+#include "../inc/brdf.hlsl"
+#include "../inc/brdf_lut.hlsl"
+#include "../inc/layered_brdf.hlsl"
+#include "../inc/math.hlsl"
+#include "../inc/lights/rect.hlsl"
+
+[[vk::binding(0)]] Texture2D<float> depth_tex;
+[[vk::binding(1)]] Texture2D<float4> gbuffer_tex;
+[[vk::binding(2)]] Texture2D<float> visibility_tex;
+[[vk::binding(3)]] StructuredBuffer<RectLightPacked> rect_lights;
+[[vk::binding(4)]] RWTexture2D<float4> output_tex;
+[[vk::binding(5)]] cbuffer _ {
+    float4 output_tex_size;
+    uint rect_light_count;
+};
+
+// Analytic rect light shading, scaled by the denoised ratio of shadowed to unshadowed lighting
+// from `rect_light_shadow_mask.rgen.hlsl`, and added to the point light output.
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float z_over_w = depth_tex[px];
+    if (0.0 == z_over_w) {
+        return;
+    }
+
+    const float2 uv = get_uv(px, output_tex_size);
+    const ViewRayContext view_ray_context = ViewRayContext::from_uv_and_depth(uv, z_over_w);
+    const float3 pt_ws = view_ray_context.ray_hit_ws();
+
+    const GbufferData gbuffer = GbufferDataPacked::from_uint4(asuint(gbuffer_tex[px])).unpack();
+    const float3x3 tangent_to_world = build_orthonormal_basis(gbuffer.normal);
+    float3 wo = mul(-view_ray_context.ray_dir_ws(), tangent_to_world);
+
+    // Same hack as in `light_gbuffer.hlsl` for shading normals facing away from the viewer.
+    if (wo.z < 0.0) {
+        wo.z *= -0.25;
+        wo = normalize(wo);
+    }
+
+    const LayeredBrdf brdf = LayeredBrdf::from_gbuffer_ndotv(gbuffer, wo.z);
+
+    float3 total_radiance = 0.0;
+    for (uint light_idx = 0; light_idx < rect_light_count; light_idx += 1) {
+        const RectLight light = RectLight::from_packed(rect_lights[light_idx]);
+        total_radiance += shade_rect_light_unshadowed(light, pt_ws, gbuffer.normal, tangent_to_world, wo, brdf);
+    }
+
+    output_tex[px] += float4(total_radiance * visibility_tex[px], 0.0);
+}
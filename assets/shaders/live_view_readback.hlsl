@@ -0,0 +1,30 @@
+// Packs the post-processed frame into sRGB RGBA8, for streaming by `live_view.rs` in kajiya-simple.
+
+#include "inc/color/srgb.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> input_tex;
+[[vk::binding(1)]] RWByteAddressBuffer output_buf;
+[[vk::binding(2)]] cbuffer _ {
+    uint2 extent;
+    uint frame_id;
+};
+
+static const uint READBACK_HEADER_SIZE = 16;
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    if (all(px == 0)) {
+        output_buf.Store4(0, uint4(frame_id, extent, 0));
+    }
+
+    if (any(px >= extent)) {
+        return;
+    }
+
+    const float3 col = sRGB_OETF(saturate(input_tex[px].rgb));
+    const uint4 rgba8 = uint4(round(col * 255.0), 255);
+    output_buf.Store(
+        READBACK_HEADER_SIZE + (px.x + px.y * extent.x) * 4,
+        rgba8.r | (rgba8.g << 8) | (rgba8.b << 16) | (rgba8.a << 24)
+    );
+}
@@ -0,0 +1,46 @@
+// Replaces the reprojection vectors of instances with shutter transforms by their
+// motion over the shutter interval, for use by motion blur only.
+
+#include "../inc/frame_constants.hlsl"
+#include "../inc/uv.hlsl"
+
+[[vk::binding(0)]] Texture2D<float> depth_tex;
+[[vk::binding(1)]] Texture2D<float4> shutter_velocity_tex;
+[[vk::binding(2)]] Texture2D<float4> reprojection_tex;
+[[vk::binding(3)]] RWTexture2D<float4> output_tex;
+[[vk::binding(4)]] cbuffer _ {
+    float4 output_tex_size;
+};
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float4 reprojection = reprojection_tex[px];
+    const float depth = depth_tex[px];
+
+    if (depth == 0.0) {
+        output_tex[px] = reprojection;
+        return;
+    }
+
+    const float4 shutter_velocity = shutter_velocity_tex[px];
+    if (shutter_velocity.w == 0.0) {
+        output_tex[px] = reprojection;
+        return;
+    }
+
+    const float2 uv = get_uv(px, output_tex_size);
+
+    // Same as in `calculate_reprojection_map.hlsl`, but with the shutter velocity.
+    float4 pos_cs = float4(uv_to_cs(uv), depth, 1.0);
+    float4 pos_vs = mul(frame_constants.view_constants.clip_to_view, pos_cs);
+
+    float4 prev_vs = pos_vs / pos_vs.w;
+    prev_vs.xyz += shutter_velocity.xyz;
+
+    float4 prev_cs = mul(frame_constants.view_constants.view_to_clip, prev_vs);
+    float4 prev_pcs = mul(frame_constants.view_constants.clip_to_prev_clip, prev_cs);
+
+    const float2 prev_uv = cs_to_uv(prev_pcs.xy / prev_pcs.w);
+
+    output_tex[px] = float4(prev_uv - uv, reprojection.zw);
+}
@@ -0,0 +1,42 @@
+#include "histogram_common.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> reprojection_tex;
+[[vk::binding(1)]] RWStructuredBuffer<uint> output_buffer;
+[[vk::binding(2)]] cbuffer _ {
+    float4 input_tex_size;
+};
+
+groupshared uint gs_bins[MOTION_VECTOR_HISTOGRAM_BIN_COUNT];
+groupshared uint gs_max_speed;
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID, uint idx_within_group: SV_GroupIndex) {
+    if (idx_within_group < MOTION_VECTOR_HISTOGRAM_BIN_COUNT) {
+        gs_bins[idx_within_group] = 0;
+    }
+    if (idx_within_group == 0) {
+        gs_max_speed = 0;
+    }
+
+    GroupMemoryBarrierWithGroupSync();
+
+    if (all(px < uint2(input_tex_size.xy))) {
+        const float speed_px = length(reprojection_tex[px].xy * input_tex_size.xy);
+        InterlockedAdd(gs_bins[motion_vector_histogram_bin(speed_px)], 1);
+
+        // Non-negative floats sort the same as their bits.
+        InterlockedMax(gs_max_speed, asuint(speed_px));
+    }
+
+    GroupMemoryBarrierWithGroupSync();
+
+    if (idx_within_group < MOTION_VECTOR_HISTOGRAM_BIN_COUNT) {
+        const uint count = gs_bins[idx_within_group];
+        if (count > 0) {
+            InterlockedAdd(output_buffer[idx_within_group], count);
+        }
+    }
+    if (idx_within_group == 0) {
+        InterlockedMax(output_buffer[MOTION_VECTOR_HISTOGRAM_BIN_COUNT], gs_max_speed);
+    }
+}
@@ -0,0 +1,11 @@
+#include "histogram_common.hlsl"
+
+[[vk::binding(0)]] RWStructuredBuffer<uint> output_buffer;
+
+[numthreads(64, 1, 1)]
+void main(uint idx: SV_DispatchThreadID) {
+    // Bins, followed by the max speed.
+    if (idx <= MOTION_VECTOR_HISTOGRAM_BIN_COUNT) {
+        output_buffer[idx] = 0;
+    }
+}
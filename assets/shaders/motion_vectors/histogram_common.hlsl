@@ -0,0 +1,15 @@
+#ifndef MOTION_VECTOR_HISTOGRAM_COMMON_HLSL
+#define MOTION_VECTOR_HISTOGRAM_COMMON_HLSL
+
+// Must match `MOTION_VECTOR_HISTOGRAM_*` in `motion_vectors.rs`
+static const uint MOTION_VECTOR_HISTOGRAM_BIN_COUNT = 16;
+static const int MOTION_VECTOR_HISTOGRAM_MIN_LOG2 = -4;
+
+// The first bin holds everything slower than 2^MIN_LOG2 pixels per frame, and the last one
+// everything faster than the bins before it.
+uint motion_vector_histogram_bin(float speed_px) {
+    const int bin = int(floor(log2(max(1e-10, speed_px)))) - MOTION_VECTOR_HISTOGRAM_MIN_LOG2 + 1;
+    return uint(clamp(bin, 0, int(MOTION_VECTOR_HISTOGRAM_BIN_COUNT) - 1));
+}
+
+#endif  // MOTION_VECTOR_HISTOGRAM_COMMON_HLSL
@@ -0,0 +1,51 @@
+#include "../inc/math_const.hlsl"
+#include "../inc/color/srgb.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> reprojection_tex;
+[[vk::binding(1)]] Texture2D<float4> input_tex;
+[[vk::binding(2)]] RWTexture2D<float4> output_tex;
+[[vk::binding(3)]] cbuffer _ {
+    float4 reprojection_tex_size;
+    float4 output_tex_size;
+    float scale_pixels;
+};
+
+static const float LEGEND_RADIUS = 48.0;
+static const float LEGEND_MARGIN = 16.0;
+
+float3 hue_to_rgb(float hue) {
+    return saturate(abs(frac(hue + float3(0.0, 2.0 / 3.0, 1.0 / 3.0)) * 6.0 - 3.0) - 1.0);
+}
+
+// Direction maps to hue, and speed to saturation, reaching full at `scale_pixels` per frame.
+float3 velocity_to_color(float2 velocity_px, float3 background) {
+    const float hue = atan2(velocity_px.y, velocity_px.x) / M_TAU + 0.5;
+    const float t = saturate(length(velocity_px) / scale_pixels);
+    return lerp(background, hue_to_rgb(hue), t);
+}
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    if (any(px >= uint2(output_tex_size.xy))) {
+        return;
+    }
+
+    // Keep a dim greyscale image in the background for context.
+    const float3 background = 0.25 * sRGB_to_luminance(input_tex[px].rgb);
+
+    // A color wheel in the bottom right corner, with the rim moving at `scale_pixels`.
+    const float2 legend_center = output_tex_size.xy - LEGEND_MARGIN - LEGEND_RADIUS;
+    const float2 legend_offset = (px + 0.5) - legend_center;
+    if (length(legend_offset) < LEGEND_RADIUS) {
+        const float2 velocity_px = legend_offset / LEGEND_RADIUS * scale_pixels;
+        output_tex[px] = float4(velocity_to_color(velocity_px, 0.0.xxx), 1);
+        return;
+    }
+
+    const uint2 reproj_px = uint2((px + 0.5) * reprojection_tex_size.xy * output_tex_size.zw);
+
+    // The map points from the current pixel to where it was the previous frame.
+    const float2 velocity_px = -reprojection_tex[reproj_px].xy * reprojection_tex_size.xy;
+
+    output_tex[px] = float4(velocity_to_color(velocity_px, background), 1);
+}
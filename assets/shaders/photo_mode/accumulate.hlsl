@@ -0,0 +1,23 @@
+#include "../inc/frame_constants.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> input_tex;
+[[vk::binding(1)]] RWTexture2D<float4> accum_tex;
+[[vk::binding(2)]] RWTexture2D<float4> output_tex;
+[[vk::binding(3)]] cbuffer _ {
+    uint sample_index;
+    uint accumulate;
+};
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    // The accumulator holds radiance without pre-exposure, which keeps adapting meanwhile.
+    float3 accum = sample_index > 0 ? accum_tex[px].rgb : 0.0.xxx;
+
+    if (accumulate) {
+        const float3 radiance = max(0.0, input_tex[px].rgb) / frame_constants.pre_exposure;
+        accum += (radiance - accum) / float(sample_index + 1);
+        accum_tex[px] = float4(accum, 1.0);
+    }
+
+    output_tex[px] = float4(accum * frame_constants.pre_exposure, 1.0);
+}
@@ -0,0 +1,29 @@
+[[vk::binding(0)]] Texture2D<float4> input_tex;
+[[vk::binding(1)]] Texture2D<float4> emissive_tex;
+[[vk::binding(2)]] RWTexture2D<float4> output_tex;
+[[vk::binding(3)]] cbuffer _ {
+    float exposure_mult;
+    float threshold;
+    float knee_width;
+    uint use_emissive;
+};
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float3 col = max(0.0, input_tex[px].rgb);
+    const float brightness = max(col.r, max(col.g, col.b)) * exposure_mult;
+
+    // Quadratic soft knee around the threshold, linear above it.
+    float soft = clamp(brightness - threshold + knee_width, 0.0, 2.0 * knee_width);
+    soft = soft * soft / (4.0 * knee_width + 1e-5);
+    const float contribution = max(soft, brightness - threshold) / max(brightness, 1e-5);
+
+    float3 result = col * contribution;
+
+    // Translucent emissives bloom regardless of the threshold; see `renderers/translucent_emissives.rs`.
+    if (use_emissive) {
+        result += max(0.0, emissive_tex[px].rgb);
+    }
+
+    output_tex[px] = float4(result, 1.0);
+}
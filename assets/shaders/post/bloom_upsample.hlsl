@@ -0,0 +1,29 @@
+#include "../inc/samplers.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> input_tail_tex;
+[[vk::binding(1)]] Texture2D<float4> input_tex;
+[[vk::binding(2)]] RWTexture2D<float4> output_tex;
+[[vk::binding(3)]] cbuffer _ {
+    uint2 output_extent;
+    float scatter;
+};
+
+// Upsamples the coarser level in `input_tex` with a small box filter, and blends it
+// over this level of the blur pyramid.
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float4 pyramid_col = input_tail_tex[px];
+    const float2 inv_size = 1.0 / float2(output_extent);
+
+    float4 self_col = 0;
+    const int k = 1;
+    for (int y = -k; y <= k; ++y) {
+        for (int x = -k; x <= k; ++x) {
+            const float2 uv = (float2(px) + 0.5 + float2(x, y)) * inv_size;
+            self_col += input_tex.SampleLevel(sampler_lnc, uv, 0);
+        }
+    }
+    self_col /= (2 * k + 1) * (2 * k + 1);
+
+    output_tex[px] = lerp(pyramid_col, self_col, scatter);
+}
@@ -0,0 +1,53 @@
+// Contrast-adaptive sharpening, after AMD FidelityFX CAS (without scaling).
+// https://gpuopen.com/fidelityfx-cas/
+
+[[vk::binding(0)]] Texture2D<float4> input_tex;
+[[vk::binding(1)]] RWTexture2D<float4> output_tex;
+[[vk::binding(2)]] cbuffer _ {
+    uint2 output_extent;
+    float sharpness;
+    // CAS expects `[0, 1]`; see `HdrOutput::peak`.
+    float output_peak;
+};
+
+float3 load(int2 px) {
+    px = clamp(px, 0, int2(output_extent) - 1);
+    return saturate(input_tex[px].rgb / output_peak);
+}
+
+[numthreads(8, 8, 1)]
+void main(int2 px: SV_DispatchThreadID) {
+    if (any(px >= int2(output_extent))) {
+        return;
+    }
+
+    // a b c
+    // d e f
+    // g h i
+    const float3 a = load(px + int2(-1, -1));
+    const float3 b = load(px + int2(0, -1));
+    const float3 c = load(px + int2(1, -1));
+    const float3 d = load(px + int2(-1, 0));
+    const float3 e = load(px);
+    const float3 f = load(px + int2(1, 0));
+    const float3 g = load(px + int2(-1, 1));
+    const float3 h = load(px + int2(0, 1));
+    const float3 i = load(px + int2(1, 1));
+
+    // Soft min and max of the cross and the full window; the sum of the two is in `[0, 2]`.
+    float3 mn = min(min(min(d, e), min(f, b)), h);
+    mn += min(mn, min(min(a, c), min(g, i)));
+    float3 mx = max(max(max(d, e), max(f, b)), h);
+    mx += max(mx, max(max(a, c), max(g, i)));
+
+    // Sharpen less where there's already high contrast, or where the signal is close to clipping.
+    const float3 amp = sqrt(saturate(min(mn, 2.0 - mx) / max(mx, 1e-5)));
+
+    // Negative lobe weight, up to -1/5 at full sharpness. Unlike in CAS, which starts at -1/8,
+    // it goes down to zero, so that the strength is continuous from the pass being off.
+    const float3 w = amp * (-0.2 * saturate(sharpness));
+
+    const float3 result = saturate((b * w + d * w + f * w + h * w + e) / (1.0 + 4.0 * w));
+
+    output_tex[px] = float4(result * output_peak, 1.0);
+}
@@ -0,0 +1,38 @@
+#include "../inc/frame_constants.hlsl"
+#include "../inc/color/srgb.hlsl"
+
+[[vk::binding(0)]] Texture2D<float3> input_tex;
+[[vk::binding(1)]] RWStructuredBuffer<uint> output_buffer;
+[[vk::binding(2)]] cbuffer _ {
+    uint2 input_extent;
+    uint2 grid_size;
+};
+
+// Layout of `output_buffer`: grid width, grid height, then one float per cell in row-major order.
+// Storing the size alongside the cells lets the CPU read back a grid that was resized in flight.
+[numthreads(8, 8, 1)]
+void main(uint2 cell: SV_DispatchThreadID) {
+    if (all(cell == 0)) {
+        output_buffer[0] = grid_size.x;
+        output_buffer[1] = grid_size.y;
+    }
+
+    if (any(cell >= grid_size)) {
+        return;
+    }
+
+    const uint2 px_begin = min(cell * input_extent / grid_size, input_extent - 1);
+    const uint2 px_end = max(px_begin + 1, (cell + 1) * input_extent / grid_size);
+
+    float lum_sum = 0.0;
+    for (uint y = px_begin.y; y < px_end.y; ++y) {
+        for (uint x = px_begin.x; x < px_end.x; ++x) {
+            lum_sum += sRGB_to_luminance(input_tex[uint2(x, y)]);
+        }
+    }
+
+    const uint2 px_count = px_end - px_begin;
+    const float lum = lum_sum / (px_count.x * px_count.y) / frame_constants.pre_exposure;
+
+    output_buffer[2 + cell.y * grid_size.x + cell.x] = asuint(lum);
+}
@@ -7,6 +7,7 @@
 [[vk::binding(1)]] RWStructuredBuffer<uint> output_buffer;
 [[vk::binding(2)]] cbuffer _ {
     uint2 input_extent;
+    uint center_weighted;
 };
 
 [numthreads(8, 8, 1)]
@@ -21,7 +22,7 @@ void main(uint2 px: SV_DispatchThreadID) {
     const uint bin = min(uint(t * 256), 255);
 
     const float2 uv = float2(px + 0.5) / input_extent;
-    const float infl = exp(-8 * pow(length(uv - 0.5), 2));
+    const float infl = center_weighted != 0 ? exp(-8 * pow(length(uv - 0.5), 2)) : 1.0;
     const uint quantized_infl = uint(infl * 256.0);
 
     InterlockedAdd(output_buffer[bin], quantized_infl);
@@ -9,17 +9,34 @@ static float2 SAMPLE_BEZOLD_BRUCKE_LUT(float coord) {
     return bindless_textures[BINDLESS_LUT_BEZOLD_BRUCKE].SampleLevel(sampler_llr, float2(coord, 0.5), 0).xy;
 }
 #include "inc/color/display_transform.hlsl"
+#include "inc/color/tonemappers.hlsl"
 
 [[vk::binding(0)]] Texture2D<float4> input_tex;
 //[[vk::binding(1)]] Texture2D<float4> debug_input_tex;
 [[vk::binding(1)]] Texture2D<float4> blur_pyramid_tex;
 [[vk::binding(2)]] Texture2D<float4> rev_blur_pyramid_tex;
 [[vk::binding(3)]] StructuredBuffer<uint> histogram_buffer;
-[[vk::binding(4)]] RWTexture2D<float4> output_tex;
-[[vk::binding(5)]] cbuffer _ {
+[[vk::binding(4)]] Texture3D<float4> lut_tex;
+[[vk::binding(5)]] Texture3D<float4> prev_lut_tex;
+[[vk::binding(6)]] RWTexture2D<float4> output_tex;
+[[vk::binding(7)]] cbuffer _ {
     float4 output_tex_size;
     float input_multiplier;
     float contrast;
+    float glare_amount;
+    uint show_histogram_overlay;
+    uint ev_false_color_enabled;
+    float sharp_weight;
+    uint lens_dirt_tex;
+    float lens_dirt_intensity;
+    // LUT size in `w` of the min
+    float4 lut_domain_min;
+    float4 lut_domain_max;
+    float4 prev_lut_domain_min;
+    float4 prev_lut_domain_max;
+    uint lut_mask;
+    float lut_blend;
+    float output_peak;
 };
 
 #define USE_GRADE 0
@@ -28,11 +45,7 @@ static float2 SAMPLE_BEZOLD_BRUCKE_LUT(float coord) {
 #define USE_SHARPEN 0
 #define USE_VIGNETTE 1
 
-#define DEBUG_HISTOGRAM 0
-
 static const float sharpen_amount = 0.1;
-static const float glare_amount = 0.05;
-//static const float glare_amount = 0.0;
 
 float sharpen_remap(float l) {
     return sqrt(l);
@@ -42,6 +55,32 @@ float sharpen_inv_remap(float l) {
     return l * l;
 }
 
+// Takes and returns sRGB-encoded color. Samples at texel centers, so that the domain
+// bounds map exactly onto the first and last entries.
+float3 apply_color_grading_lut(Texture3D<float4> lut, float3 col, float4 domain_min, float4 domain_max) {
+    const float lut_size = domain_min.w;
+    const float3 t = saturate((col - domain_min.xyz) / (domain_max.xyz - domain_min.xyz));
+    const float3 uvw = (t * (lut_size - 1.0) + 0.5) / lut_size;
+    return lut.SampleLevel(sampler_lnc, uvw, 0).rgb;
+}
+
+float3 color_grade(float3 col) {
+    const float3 encoded = sRGB_OETF(saturate(col));
+
+    // Without a previous LUT, fade in from no grading.
+    float3 prev = encoded;
+    if (lut_mask & 2) {
+        prev = apply_color_grading_lut(prev_lut_tex, encoded, prev_lut_domain_min, prev_lut_domain_max);
+    }
+
+    float3 current = encoded;
+    if (lut_mask & 1) {
+        current = apply_color_grading_lut(lut_tex, encoded, lut_domain_min, lut_domain_max);
+    }
+
+    return sRGB_EOTF(saturate(lerp(prev, current, lut_blend)));
+}
+
 float triangle_remap(float n) {
     float origin = n * 2.0 - 1.0;
     float v = origin * rsqrt(abs(origin));
@@ -105,6 +144,44 @@ void debug_histogram(int2 px, uint idx_within_group, inout float3 color) {
     }
 }
 
+// Stops relative to middle grey, two per color band.
+static const float EV_FALSE_COLOR_MIN = -8.0;
+static const float EV_FALSE_COLOR_MAX = 8.0;
+static const float EV_FALSE_COLOR_STOPS_PER_BAND = 2.0;
+static const float3 EV_FALSE_COLOR_BANDS[] = {
+    float3(0.05, 0.0, 0.1),
+    float3(0.3, 0.0, 0.6),
+    float3(0.0, 0.1, 1.0),
+    float3(0.0, 0.6, 1.0),
+    float3(0.5, 0.5, 0.5),  // Middle grey, +-1 stop
+    float3(0.3, 1.0, 0.0),
+    float3(1.0, 1.0, 0.0),
+    float3(1.0, 0.45, 0.0),
+    float3(1.0, 0.0, 0.0),
+    float3(1.0, 1.0, 1.0),
+};
+
+float3 ev_false_color(float ev) {
+    const uint band_count = 10;
+    const float band = (ev - EV_FALSE_COLOR_MIN + 1.0) / EV_FALSE_COLOR_STOPS_PER_BAND;
+    return EV_FALSE_COLOR_BANDS[uint(clamp(band, 0.0, band_count - 1))];
+}
+
+// Maps exposed scene luminance to a false-color scale, with a legend strip at the bottom.
+float3 debug_ev_false_color(int2 px, float3 col) {
+    const uint legend_height = 16;
+
+    if (px.y >= int(output_tex_size.y) - legend_height) {
+        const float t = (px.x + 0.5) * output_tex_size.z;
+        const float ev = lerp(EV_FALSE_COLOR_MIN - 2.0, EV_FALSE_COLOR_MAX + 2.0, t);
+        return ev_false_color(ev);
+    }
+
+    // The display transform maps 0.18 to middle grey.
+    const float ev = log2(max(1e-10, sRGB_to_luminance(col)) / 0.18);
+    return ev_false_color(ev);
+}
+
 [numthreads(8, 8, 1)]
 void main(uint2 px: SV_DispatchThreadID, uint idx_within_group: SV_GroupIndex) {
     float2 uv = get_uv(px, output_tex_size);
@@ -146,44 +223,84 @@ void main(uint2 px: SV_DispatchThreadID, uint idx_within_group: SV_GroupIndex) {
 	col.rgb *= max(0.0, sharpened_luma / max(1e-5, sRGB_to_luminance(col.rgb)));
 #endif
 
-    col = lerp(col, glare, glare_amount);
+    float3 bloom = glare * glare_amount;
+
+    [branch]
+    if (lens_dirt_tex != 0xffffffff) {
+        const float3 dirt = bindless_textures[NonUniformResourceIndex(lens_dirt_tex)].SampleLevel(sampler_llc, uv, 0).rgb;
+        bloom += glare * dirt * lens_dirt_intensity;
+    }
+
+    col = col * sharp_weight + bloom;
     col = max(0.0, col);
     //col = col * (1.0 - debug_input_tex[px].a) + debug_input_tex[px].rgb;
 
     col *= input_multiplier;
 
+    [branch]
+    if (ev_false_color_enabled) {
+        col = debug_ev_false_color(px, col);
+    } else {
 #if USE_VIGNETTE
-    col *= exp(-2 * pow(length(uv - 0.5), 3));
+        col *= exp(-2 * pow(length(uv - 0.5), 3));
 #endif
 
 #if USE_GRADE
-    // Lift mids
-    col = pow(col, 0.9);
+        // Lift mids
+        col = pow(col, 0.9);
 
-    // Push down lows
-    col = push_down_black_point(col, 0.2, 1.25);
+        // Push down lows
+        col = push_down_black_point(col, 0.2, 1.25);
 #endif
 
+        // HDR output: tonemap to `[0, output_peak]` instead of `[0, 1]`, with 1 at paper white.
+        col /= output_peak;
+
 #if USE_DISPLAY_TRANSFORM
-    // Apply a perceptually neutral display transform
-    col = display_transform_sRGB(col);
+        [branch]
+        switch (frame_constants.tonemapper) {
+            case TONEMAPPER_ACES:
+                col = tonemap_aces(col);
+                break;
+            case TONEMAPPER_AGX:
+                col = tonemap_agx(col);
+                break;
+            case TONEMAPPER_KHRONOS_PBR_NEUTRAL:
+                col = tonemap_khronos_pbr_neutral(col);
+                break;
+            case TONEMAPPER_REINHARD:
+                col = tonemap_reinhard(col);
+                break;
+            default:
+                // Apply a perceptually neutral display transform
+                col = display_transform_sRGB(col);
+                break;
+        }
 #endif
 
-    // Crank up the contrast
-    col = pow(col, contrast);
+        // Crank up the contrast
+        col = pow(col, contrast);
 
-    // Dither
+        [branch]
+        if (lut_mask != 0) {
+            col = color_grade(col);
+        }
+
+        col *= output_peak;
+
+        // Dither
 #if USE_DITHER
-    const uint urand_idx = frame_constants.frame_index;
-    // 256x256 blue noise
-    float dither = triangle_remap(bindless_textures[BINDLESS_LUT_BLUE_NOISE_256_LDR_RGBA_0][
-        (px + int2(urand_idx * 59, urand_idx * 37)) & 255
-    ].x);
+        const uint urand_idx = frame_constants.frame_index;
+        // 256x256 blue noise
+        float dither = triangle_remap(bindless_textures[BINDLESS_LUT_BLUE_NOISE_256_LDR_RGBA_0][
+            (px + int2(urand_idx * 59, urand_idx * 37)) & 255
+        ].x);
 
-    col += dither / 256.0;
+        col += dither / 256.0;
 #endif
+    }
 
-    if (DEBUG_HISTOGRAM) {
+    if (show_histogram_overlay) {
         debug_histogram(px, idx_within_group, col);
     }
 
@@ -5,6 +5,13 @@
 #include "inc/pack_unpack.hlsl"
 #include "inc/bindless.hlsl"
 #include "inc/gbuffer.hlsl"
+#include "inc/material_features.hlsl"
+
+// Set by `raster_simple_shutter_ps.hlsl`, used when some instance has shutter transforms;
+// see `raster_simple_vs.hlsl`.
+#ifndef RASTER_SHUTTER_VELOCITY
+    #define RASTER_SHUTTER_VELOCITY 0
+#endif
 
 struct PsIn {
     [[vk::location(0)]] float4 color: TEXCOORD0;
@@ -15,12 +22,24 @@ struct PsIn {
     [[vk::location(5)]] float3 bitangent: TEXCOORD5;
     [[vk::location(6)]] float3 vs_pos: TEXCOORD6;
     [[vk::location(7)]] float3 prev_vs_pos: TEXCOORD7;
+    [[vk::location(8)]] nointerpolation uint instance_index: TEXCOORD8;
+#if RASTER_SHUTTER_VELOCITY
+    [[vk::location(9)]] float4 shutter_velocity: TEXCOORD9;
+#endif
 };
 
 [[vk::push_constant]]
 struct {
+    // Index into `instance_dynamic_parameters_dyn`
     uint draw_index;
     uint mesh_index;
+    // Offset into `instance_batch_transforms`, or ~0 for regular instances
+    uint batch_transform_offset;
+    uint batch_moved;
+    // Index of a regular instance into the chunk of `instance_transforms_dyn` bound for the draw
+    uint transform_index;
+    // Index into the chunk of `instance_shutter_transforms_dyn`, or ~0 without shutter transforms
+    uint shutter_transform_index;
 } push_constants;
 
 struct InstanceTransform {
@@ -29,31 +48,91 @@ struct InstanceTransform {
 };
 
 [[vk::binding(0)]] StructuredBuffer<InstanceTransform> instance_transforms_dyn;
+[[vk::binding(1)]] StructuredBuffer<InstanceTransform> instance_batch_transforms;
+
+InstanceTransform get_instance_transform(uint instance_index) {
+    if (push_constants.batch_transform_offset == 0xffffffff) {
+        return instance_transforms_dyn[push_constants.transform_index];
+    }
+
+    InstanceTransform xform = instance_batch_transforms[push_constants.batch_transform_offset + instance_index];
+    if (!push_constants.batch_moved) {
+        xform.previous = xform.current;
+    }
+    return xform;
+}
+
+// Material textures are sampled through the instance's anisotropy override.
+// The override is uniform across a draw, so the branch doesn't diverge.
+//
+// Maps with a non-default sampler state (clamped decals, point-sampled pixel art)
+// use the material sampler table instead. The material is constant across a triangle,
+// so derivatives within a quad are still well-defined.
+float4 sample_material_tex(Texture2D tex, float2 uv, float lod_bias, uint max_anisotropy, uint sampler_idx) {
+    [branch]
+    if (sampler_idx != 0) {
+        return tex.SampleBias(material_samplers[NonUniformResourceIndex(sampler_idx)], uv, lod_bias);
+    } else if (max_anisotropy >= 16) {
+        return tex.SampleBias(sampler_llr, uv, lod_bias);
+    } else if (max_anisotropy >= 8) {
+        return tex.SampleBias(sampler_llr_a8, uv, lod_bias);
+    } else if (max_anisotropy >= 4) {
+        return tex.SampleBias(sampler_llr_a4, uv, lod_bias);
+    } else if (max_anisotropy >= 2) {
+        return tex.SampleBias(sampler_llr_a2, uv, lod_bias);
+    } else {
+        return tex.SampleBias(sampler_llr_a1, uv, lod_bias);
+    }
+}
 
 struct PsOut {
     float3 geometric_normal: SV_TARGET0;
     float4 gbuffer: SV_TARGET1;
     float4 velocity: SV_TARGET2;
+#if RASTER_SHUTTER_VELOCITY
+    float4 shutter_velocity: SV_TARGET3;
+#endif
 };
 
 PsOut main(PsIn ps) {
     Mesh mesh = meshes[push_constants.mesh_index];
-    MeshMaterial material = vertices.Load<MeshMaterial>(mesh.mat_data_offset + ps.material_id * sizeof(MeshMaterial));
+    MeshMaterial material = materials[mesh.material_offset + ps.material_id];
+
+    const InstanceDynamicConstants instance_params = instance_dynamic_parameters_dyn[push_constants.draw_index];
+    const float lod_bias = -0.5 + instance_params.mip_bias;
+    const uint max_anisotropy = instance_params.max_anisotropy;
 
-    const float lod_bias = -0.5;
+    // Emissive-only instances (light fixtures) skip everything but the albedo alpha test and emission.
+    const bool emissive_only = instance_params.is_emissive_only();
 
     float2 albedo_uv = transform_material_uv(material, ps.uv, 0);
     Texture2D albedo_tex = bindless_textures[NonUniformResourceIndex(material.albedo_map)];
-    float4 albedo_texel = albedo_tex.SampleBias(sampler_llr, albedo_uv, lod_bias);
+    float4 albedo_texel = sample_material_tex(albedo_tex, albedo_uv, lod_bias, max_anisotropy, material_sampler_index(material, 0));
     if (albedo_texel.a < 0.5) {
         discard;
     }
 
     float3 albedo = albedo_texel.xyz * float4(material.base_color_mult).xyz * ps.color.xyz;
 
+    // The thickness map shares the albedo map's UV transform and sampler.
+    float transmission = 0.0;
+    [branch]
+    if (material.transmission > 0.0 && !emissive_only) {
+        Texture2D thickness_tex = bindless_textures[NonUniformResourceIndex(material.transmission_map)];
+        const float thickness = sample_material_tex(thickness_tex, albedo_uv, lod_bias, max_anisotropy, material_sampler_index(material, 0)).x;
+        transmission = material.transmission * (1.0 - thickness);
+    }
+
+    const uint category = material_category(material);
+    transmission *= frame_constants.material_categories[category].transmission_scale;
+
     float2 spec_uv = transform_material_uv(material, ps.uv, 2);
     Texture2D spec_tex = bindless_textures[NonUniformResourceIndex(material.spec_map)];
-    const float4 metalness_roughness = spec_tex.SampleBias(sampler_llr, spec_uv, lod_bias);
+    float4 metalness_roughness = float4(1.0, 0.0, 0.0, 0.0);
+    [branch]
+    if (!emissive_only) {
+        metalness_roughness = sample_material_tex(spec_tex, spec_uv, lod_bias, max_anisotropy, material_sampler_index(material, 2));
+    }
     float perceptual_roughness = material.roughness_mult * metalness_roughness.x;
     float roughness = clamp(perceptual_roughness_to_roughness(perceptual_roughness), 1e-4, 1.0);
     float metalness = metalness_roughness.y * material.metalness_factor;
@@ -71,15 +150,16 @@ PsOut main(PsIn ps) {
     float3 normal_ws; {
         float3 normal_os = ps.normal;
 
+#if MATERIAL_FEATURE_NORMAL_MAPS
         [branch]
-        if (!frame_constants.render_overrides.has_flag(RenderOverrideFlags::NO_NORMAL_MAPS)) {
+        if (!emissive_only && !frame_constants.render_overrides.has_flag(RenderOverrideFlags::NO_NORMAL_MAPS)) {
             Texture2D normal_tex = bindless_textures[NonUniformResourceIndex(material.normal_map)];
 
 #if 1
-            float3 ts_normal = float3(normal_tex.SampleBias(sampler_llr, ps.uv, lod_bias).xy * 2.0 - 1.0, 0);
+            float3 ts_normal = float3(sample_material_tex(normal_tex, ps.uv, lod_bias, max_anisotropy, material_sampler_index(material, 1)).xy * 2.0 - 1.0, 0);
             ts_normal.z = sqrt(max(0.01, 1.0 - dot(ts_normal.xy, ts_normal.xy)));
 #else
-            float3 ts_normal = normal_tex.SampleBias(sampler_llr, ps.uv, lod_bias).xyz * 2.0 - 1.0;
+            float3 ts_normal = sample_material_tex(normal_tex, ps.uv, lod_bias, max_anisotropy, material_sampler_index(material, 1)).xyz * 2.0 - 1.0;
 #endif
 
             if (frame_constants.render_overrides.has_flag(RenderOverrideFlags::FLIP_NORMAL_MAP_YZ)) {
@@ -91,9 +171,10 @@ PsOut main(PsIn ps) {
                 normal_os = mul(ts_normal, tbn);
             }
         }
+#endif
 
         // Transform to world space
-        normal_ws = normalize(mul(instance_transforms_dyn[push_constants.draw_index].current, float4(normal_os, 0.0)));
+        normal_ws = normalize(transform_normal(get_instance_transform(ps.instance_index).current, normal_os));
     }
 
     // Derive normal from depth
@@ -116,13 +197,19 @@ PsOut main(PsIn ps) {
     float2 emissive_uv = transform_material_uv(material, ps.uv, 3);
     Texture2D emissive_tex = bindless_textures[NonUniformResourceIndex(material.emissive_map)];
     float3 emissive = 1.0.xxx
-        * emissive_tex.SampleBias(sampler_llr, emissive_uv, lod_bias).rgb
+        * sample_material_tex(emissive_tex, emissive_uv, lod_bias, max_anisotropy, material_sampler_index(material, 3)).rgb
         * float3(material.emissive)
-        * instance_dynamic_parameters_dyn[push_constants.draw_index].emissive_multiplier
+        * instance_params.emissive_multiplier
         * frame_constants.pre_exposure;
 
     //albedo = float3(0.966653, 0.802156, 0.323968); // Au from Mitsuba
 
+    if (emissive_only) {
+        albedo = 0.0.xxx;
+        roughness = 1.0;
+        metalness = 0.0;
+    }
+
     GbufferData gbuffer = GbufferData::create_zero();
     gbuffer.albedo = albedo;
     gbuffer.normal = normal_ws;
@@ -130,11 +217,19 @@ PsOut main(PsIn ps) {
     //gbuffer.roughness = lerp(0.05, 0.15, roughness);  // kitchen hack
     gbuffer.metalness = metalness;
     gbuffer.emissive = emissive;
+    gbuffer.transmission = transmission;
+    gbuffer.material_category = category;
+    gbuffer.gi_reception = instance_params.gi_reception;
 
     PsOut ps_out;
     ps_out.geometric_normal = geometric_normal_vs * 0.5 + 0.5;
     ps_out.gbuffer = asfloat(gbuffer.pack().data0);
-    ps_out.velocity = float4(ps.prev_vs_pos - ps.vs_pos, 0);
+    // The velocity texture's alpha channel is otherwise unused, and carries the material's
+    // AO strength to `combine_ao`.
+    ps_out.velocity = float4(ps.prev_vs_pos - ps.vs_pos, material.ao_strength);
+#if RASTER_SHUTTER_VELOCITY
+    ps_out.shutter_velocity = ps.shutter_velocity;
+#endif
 
     return ps_out;
 }
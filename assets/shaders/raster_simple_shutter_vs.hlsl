@@ -0,0 +1,2 @@
+#define RASTER_SHUTTER_VELOCITY 1
+#include "raster_simple_vs.hlsl"
@@ -1,11 +1,27 @@
 #include "inc/frame_constants.hlsl"
 #include "inc/mesh.hlsl"
 #include "inc/bindless.hlsl"
+#include "inc/material_features.hlsl"
+
+// Set by `raster_simple_shutter_vs.hlsl`, used when some instance has shutter transforms.
+// Otherwise the shutter velocity attachment, which only motion blur reads, is left out
+// of the render pass.
+#ifndef RASTER_SHUTTER_VELOCITY
+    #define RASTER_SHUTTER_VELOCITY 0
+#endif
 
 [[vk::push_constant]]
 struct {
+    // Index into `instance_dynamic_parameters_dyn`
     uint draw_index;
     uint mesh_index;
+    // Offset into `instance_batch_transforms`, or ~0 for regular instances
+    uint batch_transform_offset;
+    uint batch_moved;
+    // Index of a regular instance into the chunk of `instance_transforms_dyn` bound for the draw
+    uint transform_index;
+    // Index into the chunk of `instance_shutter_transforms_dyn`, or ~0 without shutter transforms
+    uint shutter_transform_index;
 } push_constants;
 
 struct InstanceTransform {
@@ -14,6 +30,33 @@ struct InstanceTransform {
 };
 
 [[vk::binding(0)]] StructuredBuffer<InstanceTransform> instance_transforms_dyn;
+[[vk::binding(1)]] StructuredBuffer<InstanceTransform> instance_batch_transforms;
+
+// Must match `GpuInstanceShutterTransform` in `raster_meshes.rs`
+struct InstanceShutterTransform {
+    row_major float3x4 open;
+    row_major float3x4 close;
+};
+
+// Only regular instances with shutter transforms have entries; batches don't support them.
+[[vk::binding(2)]] StructuredBuffer<InstanceShutterTransform> instance_shutter_transforms_dyn;
+
+[[vk::binding(3)]] cbuffer _ {
+    // View-space scale of viewmodel instances; see `ViewmodelSettings`
+    float4 viewmodel_view_scale;
+};
+
+InstanceTransform get_instance_transform(uint instance_index) {
+    if (push_constants.batch_transform_offset == 0xffffffff) {
+        return instance_transforms_dyn[push_constants.transform_index];
+    }
+
+    InstanceTransform xform = instance_batch_transforms[push_constants.batch_transform_offset + instance_index];
+    if (!push_constants.batch_moved) {
+        xform.previous = xform.current;
+    }
+    return xform;
+}
 
 struct VsOut {
 	float4 position: SV_Position;
@@ -25,6 +68,10 @@ struct VsOut {
     [[vk::location(5)]] float3 bitangent: TEXCOORD5;
     [[vk::location(6)]] float3 vs_pos: TEXCOORD6;
     [[vk::location(7)]] float3 prev_vs_pos: TEXCOORD7;
+    [[vk::location(8)]] nointerpolation uint instance_index: TEXCOORD8;
+#if RASTER_SHUTTER_VELOCITY
+    [[vk::location(9)]] float4 shutter_velocity: TEXCOORD9;
+#endif
 };
 
 VsOut main(uint vid: SV_VertexID, uint instance_index: SV_InstanceID) {
@@ -37,29 +84,49 @@ VsOut main(uint vid: SV_VertexID, uint instance_index: SV_InstanceID) {
     VertexPacked vp = VertexPacked(asfloat(vertices.Load4(vid * sizeof(float4) + mesh.vertex_core_offset)));
     Vertex v = unpack_vertex(vp);
 
+#if MATERIAL_FEATURE_VERTEX_COLORS
     float4 v_color =
         select(mesh.vertex_aux_offset != 0,
             asfloat(vertices.Load4(vid * sizeof(float4) + mesh.vertex_aux_offset)),
             1.0.xxxx);
+#else
+    float4 v_color = 1.0.xxxx;
+#endif
 
+#if MATERIAL_FEATURE_NORMAL_MAPS
     float4 v_tangent_packed =
         select(mesh.vertex_tangent_offset != 0,
             asfloat(vertices.Load4(vid * sizeof(float4) + mesh.vertex_tangent_offset)),
             float4(1, 0, 0, 1));
+#else
+    float4 v_tangent_packed = float4(1, 0, 0, 1);
+#endif
 
     float2 uv = asfloat(vertices.Load2(vid * sizeof(float2) + mesh.vertex_uv_offset));
     uint material_id = vertices.Load(vid * sizeof(uint) + mesh.vertex_mat_offset);
 
+    const InstanceTransform instance_transform = get_instance_transform(instance_index);
+
     //float3 ws_pos = v.position + float3(push_constants.instance_position);
-    float3 ws_pos = mul(instance_transforms_dyn[push_constants.draw_index].current, float4(v.position, 1.0));
+    float3 ws_pos = mul(instance_transform.current, float4(v.position, 1.0));
     
     float4 vs_pos = mul(frame_constants.view_constants.world_to_view, float4(ws_pos, 1.0));
     float4 cs_pos = mul(frame_constants.view_constants.view_to_sample, vs_pos);
 
-    float3 prev_ws_pos = mul(instance_transforms_dyn[push_constants.draw_index].previous, float4(v.position, 1.0));
+    float3 prev_ws_pos = mul(instance_transform.previous, float4(v.position, 1.0));
     float4 prev_vs_pos = mul(frame_constants.view_constants.world_to_view, float4(prev_ws_pos, 1.0));
     //float4 prev_cs_pos = mul(frame_constants.view_constants.view_to_sample, prev_vs_pos);
 
+    // First-person geometry gets its own field of view, and is pulled towards the camera
+    // so that it's never occluded by the walls it would clip into.
+    const bool is_viewmodel = push_constants.batch_transform_offset == 0xffffffff
+        && instance_dynamic_parameters_dyn[push_constants.draw_index].is_viewmodel();
+    if (is_viewmodel) {
+        vs_pos.xyz *= viewmodel_view_scale.xyz;
+        prev_vs_pos.xyz *= viewmodel_view_scale.xyz;
+        cs_pos = mul(frame_constants.view_constants.view_to_sample, vs_pos);
+    }
+
     vsout.position = cs_pos;
     vsout.color = v_color;
     vsout.uv = uv;
@@ -70,6 +137,21 @@ VsOut main(uint vid: SV_VertexID, uint instance_index: SV_InstanceID) {
 
     vsout.vs_pos = vs_pos.xyz / vs_pos.w;
     vsout.prev_vs_pos = prev_vs_pos.xyz / prev_vs_pos.w;
+    vsout.instance_index = instance_index;
+
+    // View-space motion from shutter close to shutter open, in the same convention as
+    // `prev_vs_pos - vs_pos`. The `w` component flags whether it's valid.
+#if RASTER_SHUTTER_VELOCITY
+    vsout.shutter_velocity = 0.0.xxxx;
+    if (push_constants.shutter_transform_index != 0xffffffff) {
+        const InstanceShutterTransform shutter = instance_shutter_transforms_dyn[push_constants.shutter_transform_index];
+        const float3 open_ws_pos = mul(shutter.open, float4(v.position, 1.0));
+        const float3 close_ws_pos = mul(shutter.close, float4(v.position, 1.0));
+        const float4 open_vs_pos = mul(frame_constants.view_constants.world_to_view, float4(open_ws_pos, 1.0));
+        const float4 close_vs_pos = mul(frame_constants.view_constants.world_to_view, float4(close_ws_pos, 1.0));
+        vsout.shutter_velocity = float4(open_vs_pos.xyz / open_vs_pos.w - close_vs_pos.xyz / close_vs_pos.w, 1.0);
+    }
+#endif
 
     return vsout;
 }
\ No newline at end of file
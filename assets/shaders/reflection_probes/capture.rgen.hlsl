@@ -0,0 +1,85 @@
+#include "../inc/math.hlsl"
+#include "../inc/samplers.hlsl"
+#include "../inc/frame_constants.hlsl"
+#include "../inc/cube_map.hlsl"
+#include "../inc/gbuffer.hlsl"
+#include "../inc/brdf.hlsl"
+#include "../inc/layered_brdf.hlsl"
+#include "../inc/rt.hlsl"
+#include "../inc/hash.hlsl"
+#include "../inc/atmosphere.hlsl"
+#include "../inc/sun.hlsl"
+#include "../ircache/bindings.hlsl"
+
+[[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
+
+[[vk::binding(0)]] TextureCube<float4> sky_cube_tex;
+DEFINE_IRCACHE_BINDINGS(1, 2, 3, 4, 5, 6, 7, 8, 9)
+[[vk::binding(10)]] RWTexture2DArray<float4> output_tex;
+[[vk::binding(11)]] cbuffer _ {
+    float4 probe_position;
+    uint face_width;
+};
+
+#define IRCACHE_LOOKUP_DONT_KEEP_ALIVE
+#include "../ircache/lookup.hlsl"
+
+// Captures scene radiance around a reflection probe, one cube map texel per ray.
+[shader("raygeneration")]
+void main() {
+    const uint3 px = DispatchRaysIndex().xyz;
+    const uint face = px.z;
+    const float2 uv = (px.xy + 0.5) / face_width;
+    const float3 dir = normalize(mul(CUBE_MAP_FACE_ROTATIONS[face], float3(uv * 2 - 1, -1.0)));
+
+    uint rng = hash3(px);
+
+    const RayDesc ray = new_ray(probe_position.xyz, dir, 0.0, FLT_MAX);
+
+    // Roughly the angle subtended by a texel
+    const float spread_angle = M_PI * 0.5 / face_width;
+
+    // Traced like primary rays, so that emissive surfaces are visible.
+    const GbufferPathVertex hit = GbufferRaytrace::with_ray(ray)
+        .with_cone(RayCone::from_spread_angle(spread_angle))
+        .with_cull_back_faces(false)
+        .with_path_length(0)
+        .trace(acceleration_structure);
+
+    float3 radiance;
+
+    if (hit.is_hit) {
+        GbufferData gbuffer = hit.gbuffer_packed.unpack();
+
+        const float3x3 tangent_to_world = build_orthonormal_basis(gbuffer.normal);
+        const float3 wo = mul(-dir, tangent_to_world);
+        const LayeredBrdf brdf = LayeredBrdf::from_gbuffer_ndotv(gbuffer, wo.z);
+
+        radiance = gbuffer.emissive;
+
+        const float3 to_light_norm = SUN_DIRECTION;
+        const float3 wi = mul(to_light_norm, tangent_to_world);
+
+        const bool is_shadowed =
+            rt_is_shadowed(
+                acceleration_structure,
+                new_ray(hit.position, to_light_norm, 1e-4, FLT_MAX));
+
+        if (!is_shadowed) {
+            const float3 brdf_value = brdf.evaluate(wo, wi) * max(0.0, wi.z)
+                + brdf.evaluate_transmission(wi) * max(0.0, -wi.z);
+            radiance += brdf_value * SUN_COLOR;
+        }
+
+        const float3 gi = IrcacheLookupParams::create(ray.Origin, hit.position, gbuffer.normal)
+            .with_query_rank(1)
+            .lookup(rng);
+
+        radiance += gi * gbuffer.albedo;
+    } else {
+        radiance = sky_cube_tex.SampleLevel(sampler_llr, dir, 0).rgb;
+    }
+
+    // Probes are captured once, and used over many frames, so store them without pre-exposure.
+    output_tex[px] = float4(radiance / frame_constants.pre_exposure, 1);
+}
@@ -0,0 +1,78 @@
+#include "../inc/math.hlsl"
+#include "../inc/math_const.hlsl"
+#include "../inc/quasi_random.hlsl"
+#include "../inc/samplers.hlsl"
+#include "../inc/cube_map.hlsl"
+
+[[vk::binding(0)]] TextureCube<float4> input_tex;
+[[vk::binding(1)]] RWTexture2DArray<float4> output_tex;
+[[vk::binding(2)]] cbuffer _ {
+    uint face_width;
+    uint probe_slot;
+    float perceptual_roughness;
+    uint input_face_width;
+};
+
+static const uint SAMPLE_COUNT = 128;
+
+float ggx_ndf(float a2, float cos_theta) {
+    const float denom = cos_theta * cos_theta * (a2 - 1.0) + 1.0;
+    return a2 / (M_PI * denom * denom);
+}
+
+// Prefilters a captured probe with the GGX lobe for the roughness of the target mip,
+// assuming the view and normal directions coincide with the reflection vector.
+//
+// Filtered importance sampling: each sample reads the input at a mip matching the solid angle
+// it represents, so that a few samples suffice without aliasing.
+// https://developer.nvidia.com/gpugems/gpugems3/part-iii-rendering/chapter-20-gpu-based-importance-sampling
+[numthreads(8, 8, 1)]
+void main(uint3 px: SV_DispatchThreadID) {
+    if (any(px.xy >= face_width)) {
+        return;
+    }
+
+    const uint face = px.z;
+    const float2 uv = (px.xy + 0.5) / face_width;
+    const float3 normal = normalize(mul(CUBE_MAP_FACE_ROTATIONS[face], float3(uv * 2 - 1, -1.0)));
+
+    const uint3 output_px = uint3(px.xy, probe_slot * 6 + face);
+
+    if (perceptual_roughness == 0.0) {
+        output_tex[output_px] = float4(input_tex.SampleLevel(sampler_llr, normal, 0).rgb, 1);
+        return;
+    }
+
+    const float a = perceptual_roughness * perceptual_roughness;
+    const float a2 = a * a;
+    const float3x3 basis = build_orthonormal_basis(normal);
+
+    const float texel_solid_angle = 4.0 * M_PI / (6.0 * input_face_width * input_face_width);
+
+    float3 radiance_sum = 0.0.xxx;
+    float weight_sum = 0.0;
+
+    for (uint i = 0; i < SAMPLE_COUNT; ++i) {
+        const float2 urand = hammersley(i, SAMPLE_COUNT);
+
+        const float cos_theta = sqrt((1.0 - urand.x) / (1.0 + (a2 - 1.0) * urand.x));
+        const float sin_theta = sqrt(max(0.0, 1.0 - cos_theta * cos_theta));
+        const float phi = 2.0 * M_PI * urand.y;
+        const float3 h = mul(basis, float3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta));
+
+        const float3 l = reflect(-normal, h);
+        const float n_dot_l = dot(normal, l);
+
+        if (n_dot_l > 0.0) {
+            // With the view along the normal, the pdf of `l` is D / 4.
+            const float pdf = ggx_ndf(a2, cos_theta) * 0.25;
+            const float sample_solid_angle = 1.0 / (SAMPLE_COUNT * pdf + 1e-5);
+            const float lod = max(0.0, 0.5 * log2(sample_solid_angle / texel_solid_angle) + 1.0);
+
+            radiance_sum += input_tex.SampleLevel(sampler_llr, l, lod).rgb * n_dot_l;
+            weight_sum += n_dot_l;
+        }
+    }
+
+    output_tex[output_px] = float4(radiance_sum / max(1e-5, weight_sum), 1);
+}
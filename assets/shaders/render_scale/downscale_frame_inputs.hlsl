@@ -0,0 +1,28 @@
+[[vk::binding(0)]] Texture2D<float4> gbuffer_tex;
+[[vk::binding(1)]] Texture2D<float> depth_tex;
+[[vk::binding(2)]] Texture2D<float4> geometric_normal_tex;
+[[vk::binding(3)]] Texture2D<float4> reprojection_tex;
+[[vk::binding(4)]] Texture2D<float> ao_tex;
+[[vk::binding(5)]] RWTexture2D<float4> gbuffer_output_tex;
+[[vk::binding(6)]] RWTexture2D<float> depth_output_tex;
+[[vk::binding(7)]] RWTexture2D<float4> geometric_normal_output_tex;
+[[vk::binding(8)]] RWTexture2D<float4> reprojection_output_tex;
+[[vk::binding(9)]] RWTexture2D<float> ao_output_tex;
+[[vk::binding(10)]] cbuffer _ {
+    float4 input_tex_size;
+    float4 output_tex_size;
+};
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    // Point sampling keeps all the outputs describing the same surface.
+    // The gbuffer is bit-packed, so it must not be filtered anyway.
+    const float2 uv = (px + 0.5) * output_tex_size.zw;
+    const uint2 src_px = min(uint2(uv * input_tex_size.xy), uint2(input_tex_size.xy) - 1);
+
+    gbuffer_output_tex[px] = gbuffer_tex[src_px];
+    depth_output_tex[px] = depth_tex[src_px];
+    geometric_normal_output_tex[px] = geometric_normal_tex[src_px];
+    reprojection_output_tex[px] = reprojection_tex[src_px];
+    ao_output_tex[px] = ao_tex[src_px];
+}
@@ -0,0 +1,50 @@
+[[vk::binding(0)]] Texture2D<float4> input_tex;
+[[vk::binding(1)]] Texture2D<float> input_depth_tex;
+[[vk::binding(2)]] Texture2D<float> output_depth_tex;
+[[vk::binding(3)]] RWTexture2D<float4> output_tex;
+[[vk::binding(4)]] cbuffer _ {
+    float4 input_tex_size;
+    float4 output_tex_size;
+};
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float center_depth = output_depth_tex[px];
+
+    // Bilinear footprint in the input
+    const float2 src_pos = (px + 0.5) * output_tex_size.zw * input_tex_size.xy - 0.5;
+    const int2 src_px0 = int2(floor(src_pos));
+    const float2 f = src_pos - src_px0;
+    const int2 max_px = int2(input_tex_size.xy) - 1;
+
+    float4 result = 0.0;
+    float w_sum = 0.0;
+    float4 fallback = 0.0;
+    float fallback_depth_diff = 1e10;
+
+    for (uint i = 0; i < 4; ++i) {
+        const int2 offset = int2(i & 1, i >> 1);
+        const int2 src_px = clamp(src_px0 + offset, 0, max_px);
+        const float bilinear_w = (offset.x ? f.x : 1.0 - f.x) * (offset.y ? f.y : 1.0 - f.y);
+
+        const float sample_depth = input_depth_tex[src_px];
+        const float4 value = input_tex[src_px];
+
+        // Reverse-Z; sky pixels (0) only match other sky pixels.
+        const float depth_diff = center_depth == 0.0
+            ? (sample_depth == 0.0 ? 0.0 : 1.0)
+            : abs(1.0 - sample_depth / center_depth);
+        const float w = bilinear_w * exp2(-200.0 * depth_diff);
+
+        result += value * w;
+        w_sum += w;
+
+        if (depth_diff < fallback_depth_diff) {
+            fallback_depth_diff = depth_diff;
+            fallback = value;
+        }
+    }
+
+    // If no sample is on the same surface, take the closest in depth instead of smearing.
+    output_tex[px] = w_sum > 1e-5 ? result / w_sum : fallback;
+}
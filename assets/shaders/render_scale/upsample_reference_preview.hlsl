@@ -0,0 +1,20 @@
+#include "../inc/samplers.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> input_tex;
+[[vk::binding(1)]] RWTexture2D<float4> output_tex;
+[[vk::binding(2)]] cbuffer _ {
+    float4 output_tex_size;
+    uint use_nearest;
+};
+
+// Stretches the reduced-resolution reference accumulation over the full render extent.
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float2 uv = (px + 0.5) * output_tex_size.zw;
+
+    if (use_nearest) {
+        output_tex[px] = input_tex.SampleLevel(sampler_nnc, uv, 0);
+    } else {
+        output_tex[px] = input_tex.SampleLevel(sampler_lnc, uv, 0);
+    }
+}
@@ -0,0 +1,16 @@
+// Default any-hit shader of the hit groups; see `DEFAULT_ANY_HIT` in `custom_hit_groups.rs`.
+// Only runs on triangles outside of the opaque BLAS geometries. The payload is declared
+// for the G-buffer rays, but shadow rays run this too, so it must not be accessed.
+
+#include "../inc/rt.hlsl"
+
+struct RayHitAttrib {
+    float2 bary;
+};
+
+[shader("anyhit")]
+void main(inout GbufferRayPayload payload: SV_RayPayload, in RayHitAttrib attrib: SV_IntersectionAttributes) {
+    if (rt_is_alpha_cut_out(RtHitInstance::from_intrinsics(), PrimitiveIndex(), attrib.bary)) {
+        IgnoreHit();
+    }
+}
@@ -0,0 +1,58 @@
+// Example custom hit group shader; see `CustomHitGroup` in `custom_hit_groups.rs`.
+// Shades meshes with a world-space checkerboard of one meter cells,
+// ignoring their materials.
+
+#include "../inc/math.hlsl"
+#include "../inc/mesh.hlsl"
+#include "../inc/frame_constants.hlsl"
+#include "../inc/bindless.hlsl"
+#include "../inc/rt.hlsl"
+#include "../inc/rt_hit.hlsl"
+
+[[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
+
+struct RayHitAttrib {
+    float2 bary;
+};
+
+[shader("closesthit")]
+void main(inout GbufferRayPayload payload: SV_RayPayload, in RayHitAttrib attrib: SV_IntersectionAttributes) {
+    const float3 hit_point = WorldRayOrigin() + WorldRayDirection() * RayTCurrent();
+
+    const RtHitInstance hit = RtHitInstance::from_intrinsics();
+    const Mesh mesh = meshes[hit.mesh_index];
+    const uint primitive_index = PrimitiveIndex() + hit.primitive_offset;
+
+    const uint3 ind = uint3(
+        vertices.Load((primitive_index * 3 + 0) * sizeof(uint) + mesh.index_offset),
+        vertices.Load((primitive_index * 3 + 1) * sizeof(uint) + mesh.index_offset),
+        vertices.Load((primitive_index * 3 + 2) * sizeof(uint) + mesh.index_offset)
+    );
+
+    const Vertex v0 = unpack_vertex(VertexPacked(asfloat(vertices.Load4(ind.x * sizeof(float4) + mesh.vertex_core_offset))));
+    const Vertex v1 = unpack_vertex(VertexPacked(asfloat(vertices.Load4(ind.y * sizeof(float4) + mesh.vertex_core_offset))));
+    const Vertex v2 = unpack_vertex(VertexPacked(asfloat(vertices.Load4(ind.z * sizeof(float4) + mesh.vertex_core_offset))));
+
+    const float3 surf_normal_os = normalize(cross(v1.position - v0.position, v2.position - v0.position));
+    float3 surf_normal_ws = normalize(transform_normal(hit.object_to_world, surf_normal_os));
+
+    // Force double-sided
+    if (dot(WorldRayDirection(), surf_normal_ws) > 0) {
+        surf_normal_ws *= -1;
+    }
+
+    // Nudged off the surface, so that cells don't flicker on axis-aligned faces.
+    const int3 cell = int3(floor((hit_point + surf_normal_ws * 1e-3) / world_units(1.0)));
+    const bool is_odd = ((cell.x + cell.y + cell.z) & 1) != 0;
+
+    GbufferData gbuffer = GbufferData::create_zero();
+    gbuffer.albedo = is_odd ? 0.8 : 0.1;
+    gbuffer.normal = surf_normal_ws;
+    gbuffer.roughness = 0.5;
+
+    const float ray_bias_ws = mesh.ray_bias * length(mul(hit.object_to_world, float4(surf_normal_os, 0.0)));
+    payload.hit_offset = surf_normal_ws * ray_bias_ws;
+
+    payload.gbuffer_packed = gbuffer.pack();
+    payload.t = RayTCurrent();
+}
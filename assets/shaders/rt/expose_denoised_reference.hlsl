@@ -0,0 +1,13 @@
+// Scales the denoised reference image uploaded by `renderers/oidn_denoise.rs`,
+// which is scene-referred, to the pre-exposed units of the accumulation.
+
+[[vk::binding(0)]] Texture2D<float4> input_tex;
+[[vk::binding(1)]] RWTexture2D<float4> output_tex;
+[[vk::binding(2)]] cbuffer _ {
+    float pre_exposure;
+};
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    output_tex[px] = float4(input_tex[px].rgb * pre_exposure, 1.0);
+}
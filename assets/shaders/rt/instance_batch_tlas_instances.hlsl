@@ -0,0 +1,40 @@
+// Expands an instance batch into `VkAccelerationStructureInstanceKHR` records.
+
+struct InstanceTransform {
+    row_major float3x4 current;
+    row_major float3x4 previous;
+};
+
+[[vk::binding(0)]] StructuredBuffer<InstanceTransform> instance_batch_transforms;
+[[vk::binding(1)]] RWByteAddressBuffer tlas_instances;
+[[vk::binding(2)]] cbuffer _ {
+    uint transform_offset;
+    uint instance_count;
+    uint dst_instance_offset;
+    uint instance_id_and_mask;
+    uint instance_sbt_offset_and_flags;
+    uint blas_address_lo;
+    uint blas_address_hi;
+};
+
+static const uint TLAS_INSTANCE_SIZE = 64;
+
+[numthreads(64, 1, 1)]
+void main(uint idx: SV_DispatchThreadID) {
+    if (idx >= instance_count) {
+        return;
+    }
+
+    const float3x4 xform = instance_batch_transforms[transform_offset + idx].current;
+    const uint dst = (dst_instance_offset + idx) * TLAS_INSTANCE_SIZE;
+
+    tlas_instances.Store4(dst + 0, asuint(xform[0]));
+    tlas_instances.Store4(dst + 16, asuint(xform[1]));
+    tlas_instances.Store4(dst + 32, asuint(xform[2]));
+    tlas_instances.Store4(dst + 48, uint4(
+        instance_id_and_mask,
+        instance_sbt_offset_and_flags,
+        blas_address_lo,
+        blas_address_hi
+    ));
+}
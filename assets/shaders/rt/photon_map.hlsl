@@ -0,0 +1,63 @@
+#ifndef PHOTON_MAP_HLSL
+#define PHOTON_MAP_HLSL
+
+#include "../inc/hash.hlsl"
+#include "../inc/pack_unpack.hlsl"
+
+// Caustic photon map of `ReferenceIntegrator::PhotonMapping`, rebuilt every frame by
+// `photon_trace.rgen.hlsl`. Photons are kept in linked lists per cell of a hashed grid,
+// with cells twice the gather radius across, so gathering visits the eight cells nearest
+// to the shaded point.
+
+// Must match `PHOTON_MAP_CELL_COUNT` in `reference.rs`
+static const uint PHOTON_MAP_CELL_COUNT = 1 << 20;
+
+// Lists link photon indices plus one, so that cleared memory is empty.
+static const uint PHOTON_LIST_END = 0;
+
+struct Photon {
+    float3 position;
+
+    // Direction the photon arrived from
+    float3 incident_dir;
+
+    // Pre-exposed flux, divided by the number of photons emitted in the frame
+    float3 flux;
+};
+
+// Must match `PHOTON_SIZE` in `reference.rs`
+struct PhotonPacked {
+    float4 data0;
+    float4 data1;
+};
+
+PhotonPacked pack_photon(Photon photon) {
+    PhotonPacked res;
+    res.data0 = float4(photon.position, pack_normal_11_10_11(photon.incident_dir));
+    res.data1 = float4(photon.flux, 0.0);
+    return res;
+}
+
+Photon unpack_photon(PhotonPacked packed) {
+    Photon res;
+    res.position = packed.data0.xyz;
+    res.incident_dir = unpack_normal_11_10_11(packed.data0.w);
+    res.flux = packed.data1.xyz;
+    return res;
+}
+
+int3 photon_map_cell_coord(float3 position, float gather_radius) {
+    return int3(floor(position / (2.0 * gather_radius)));
+}
+
+uint photon_map_cell_index(int3 coord) {
+    return hash3(asuint(coord)) % PHOTON_MAP_CELL_COUNT;
+}
+
+// The cells which photons within `gather_radius` of `position` can be in
+int3 photon_map_gather_cell_coord(float3 position, float gather_radius, uint corner) {
+    const int3 base = int3(floor(position / (2.0 * gather_radius) - 0.5));
+    return base + int3(corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+}
+
+#endif  // PHOTON_MAP_HLSL
@@ -0,0 +1,15 @@
+#include "photon_map.hlsl"
+
+[[vk::binding(0)]] RWStructuredBuffer<uint> cell_heads;
+[[vk::binding(1)]] RWStructuredBuffer<uint> photon_counter;
+
+[numthreads(64, 1, 1)]
+void main(uint idx: SV_DispatchThreadID) {
+    if (idx < PHOTON_MAP_CELL_COUNT) {
+        cell_heads[idx] = PHOTON_LIST_END;
+    }
+
+    if (idx == 0) {
+        photon_counter[0] = 0;
+    }
+}
@@ -0,0 +1,195 @@
+#include "../inc/uv.hlsl"
+#include "../inc/pack_unpack.hlsl"
+#include "../inc/frame_constants.hlsl"
+#include "../inc/gbuffer.hlsl"
+#include "../inc/brdf.hlsl"
+#include "../inc/layered_brdf.hlsl"
+#include "../inc/rt.hlsl"
+#include "../inc/hash.hlsl"
+#include "../inc/atmosphere.hlsl"
+#include "../inc/sun.hlsl"
+#include "../inc/lights/triangle.hlsl"
+#include "../inc/lights/point.hlsl"
+#include "photon_map.hlsl"
+
+[[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
+
+[[vk::binding(0)]] RWStructuredBuffer<PhotonPacked> photons;
+[[vk::binding(1)]] RWStructuredBuffer<uint> photon_next;
+[[vk::binding(2)]] RWStructuredBuffer<uint> cell_heads;
+[[vk::binding(3)]] RWStructuredBuffer<uint> photon_counter;
+[[vk::binding(4)]] cbuffer _ {
+    uint photon_count;
+    uint photon_capacity;
+    // In meters, as are the other lengths here
+    float gather_radius_m;
+    float sun_photon_radius_m;
+    uint max_path_length;
+    float max_specular_roughness;
+};
+
+// Starting points of sun photons, towards the sun from the disk they cover
+static const float SUN_PHOTON_DISTANCE_M = 10000.0;
+
+void store_photon(Photon photon) {
+    uint idx;
+    InterlockedAdd(photon_counter[0], 1, idx);
+
+    // Out of space; the remaining photons of the frame are lost.
+    if (idx >= photon_capacity) {
+        return;
+    }
+
+    photons[idx] = pack_photon(photon);
+
+    const uint cell = photon_map_cell_index(photon_map_cell_coord(photon.position, world_units(gather_radius_m)));
+    uint next;
+    InterlockedExchange(cell_heads[cell], idx + 1, next);
+    photon_next[idx] = next;
+}
+
+float3 cosine_sample_hemisphere(float2 urand) {
+    const float phi = urand.x * M_TAU;
+    const float cos_theta = sqrt(max(0.0, 1.0 - urand.y));
+    const float sin_theta = sqrt(max(0.0, 1.0 - cos_theta * cos_theta));
+    return float3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+float2 urand2_mut(inout uint rng) {
+    return float2(uint_to_u01_float(hash1_mut(rng)), uint_to_u01_float(hash1_mut(rng)));
+}
+
+// Only caustic photons get stored: those which bounced off smooth specular lobes, and nothing
+// else, since leaving the light. The path tracer accounts for all the other light transport,
+// and skips those paths in turn.
+[shader("raygeneration")]
+void main() {
+    const uint photon_idx = DispatchRaysIndex().x;
+    uint rng = hash_combine2(hash1(photon_idx), hash1(frame_constants.frame_index + 0x9e3779b9));
+
+    // Every kind of light present is picked equally often.
+    const bool has_triangle_lights = frame_constants.triangle_light_count > 0;
+    const bool has_point_lights = frame_constants.point_light_count > 0;
+    const uint light_kind_count = 1 + uint(has_triangle_lights) + uint(has_point_lights);
+    uint light_kind = min(uint(uint_to_u01_float(hash1_mut(rng)) * light_kind_count), light_kind_count - 1);
+
+    if (light_kind >= 1 && !has_triangle_lights) {
+        ++light_kind;
+    }
+
+    RayDesc ray;
+    float3 flux;
+
+    if (0 == light_kind) {
+        // The sun, through a disk around the camera
+        const float3 to_sun = sample_sun_direction(urand2_mut(rng), true);
+        const float3x3 basis = build_orthonormal_basis(to_sun);
+
+        const float2 urand = urand2_mut(rng);
+        const float disk_radius = world_units(sun_photon_radius_m);
+        const float r = disk_radius * sqrt(urand.x);
+        const float phi = urand.y * M_TAU;
+        const float3 disk_offset = mul(basis, float3(r * cos(phi), r * sin(phi), 0.0));
+
+        ray = new_ray(
+            get_eye_position() + disk_offset + to_sun * world_units(SUN_PHOTON_DISTANCE_M),
+            -to_sun,
+            0.0,
+            FLT_MAX
+        );
+        flux = SUN_COLOR * M_PI * disk_radius * disk_radius;
+    } else if (1 == light_kind) {
+        // Emissive triangles, emitting from their front side
+        const TriangleLightSelection light_selection = select_triangle_light(urand2_mut(rng));
+        TriangleLight triangle_light = TriangleLight::from_packed(triangle_lights_dyn[light_selection.light_idx]);
+        const LightSampleResultArea light_sample = sample_triangle_light(triangle_light.as_triangle(), urand2_mut(rng));
+
+        const float3 dir = mul(build_orthonormal_basis(light_sample.normal), cosine_sample_hemisphere(urand2_mut(rng)));
+
+        ray = new_ray(light_sample.pos, dir, world_units(1e-3), FLT_MAX);
+        flux = triangle_light.radiance() * M_PI / (light_sample.pdf.value * light_selection.pmf);
+    } else {
+        const float light_selection_pmf = 1.0 / frame_constants.point_light_count;
+        const uint light_idx = hash1_mut(rng) % frame_constants.point_light_count;
+        PointLight point_light = PointLight::from_packed(point_lights_dyn[light_idx]);
+
+        float3 dir;
+        if (point_light.spot_cos_outer < -1.0) {
+            dir = uniform_sample_sphere(urand2_mut(rng));
+            flux = point_light.intensity * 4.0 * M_PI;
+        } else {
+            // Only within the outer cone of spot lights
+            dir = mul(
+                build_orthonormal_basis(point_light.spot_direction),
+                uniform_sample_cone(urand2_mut(rng), point_light.spot_cos_outer)
+            );
+            flux = point_light.intensity * M_TAU * (1.0 - point_light.spot_cos_outer)
+                * point_light.spot_attenuation(dir);
+        }
+
+        ray = new_ray(point_light.position, dir, 0.0, FLT_MAX);
+        flux /= light_selection_pmf;
+    }
+
+    flux *= float(light_kind_count) / photon_count;
+
+    bool is_caustic = false;
+
+    [loop]
+    for (uint path_length = 0; path_length < max_path_length && any(flux > 0.0); ++path_length) {
+        GbufferPathVertex hit = GbufferRaytrace::with_ray(ray)
+            .with_cone(RayCone::from_spread_angle(0.0))
+            .with_cull_back_faces(false)
+            .with_path_length(path_length + 1)
+            .trace(acceleration_structure);
+
+        if (!hit.is_hit) {
+            break;
+        }
+
+        const GbufferData gbuffer = hit.gbuffer_packed.unpack();
+
+        // Back faces end paths of the path tracer too.
+        if (dot(gbuffer.normal, ray.Direction) >= 0.0) {
+            break;
+        }
+
+        if (is_caustic) {
+            Photon photon;
+            photon.position = hit.position;
+            photon.incident_dir = -ray.Direction;
+            photon.flux = flux;
+            store_photon(photon);
+        }
+
+        if (gbuffer.roughness > max_specular_roughness) {
+            break;
+        }
+
+        const float3x3 tangent_to_world = build_orthonormal_basis(gbuffer.normal);
+        const float3 wo = mul(-ray.Direction, tangent_to_world);
+        LayeredBrdf brdf = LayeredBrdf::from_gbuffer_ndotv(gbuffer, wo.z);
+
+        // Photons picking the diffuse lobe, like `LayeredBrdf::sample` would, end here.
+        const float diffuse_lobe_p = brdf.diffuse_lobe_probability();
+        if (uint_to_u01_float(hash1_mut(rng)) < diffuse_lobe_p) {
+            break;
+        }
+
+        BrdfSample brdf_sample = brdf.specular_brdf.sample(wo, urand2_mut(rng));
+        if (!brdf_sample.is_valid()) {
+            break;
+        }
+
+        flux *= brdf_sample.value_over_pdf
+            * brdf.energy_preservation.preintegrated_reflection_mult
+            / (1.0 - diffuse_lobe_p);
+
+        ray.Origin = hit.position;
+        ray.Direction = mul(tangent_to_world, brdf_sample.wi);
+        ray.TMin = world_units(1e-4);
+        ray.TMax = FLT_MAX;
+
+        is_caustic = true;
+    }
+}
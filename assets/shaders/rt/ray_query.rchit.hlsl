@@ -0,0 +1,32 @@
+#include "../inc/math.hlsl"
+#include "../inc/mesh.hlsl"
+#include "../inc/bindless.hlsl"
+#include "../inc/rt_hit.hlsl"
+#include "ray_query_payload.hlsl"
+
+[shader("closesthit")]
+void main(inout RayQueryPayload payload: SV_RayPayload, in BuiltInTriangleIntersectionAttributes attrib: SV_IntersectionAttributes) {
+    const RtHitInstance hit = RtHitInstance::from_intrinsics();
+    Mesh mesh = meshes[hit.mesh_index];
+    const uint primitive_index = PrimitiveIndex() + hit.primitive_offset;
+
+    // Indices of the triangle
+    uint3 ind = uint3(
+        vertices.Load((primitive_index * 3 + 0) * sizeof(uint) + mesh.index_offset),
+        vertices.Load((primitive_index * 3 + 1) * sizeof(uint) + mesh.index_offset),
+        vertices.Load((primitive_index * 3 + 2) * sizeof(uint) + mesh.index_offset)
+    );
+
+    Vertex v0 = unpack_vertex(VertexPacked(asfloat(vertices.Load4(ind.x * sizeof(float4) + mesh.vertex_core_offset))));
+    Vertex v1 = unpack_vertex(VertexPacked(asfloat(vertices.Load4(ind.y * sizeof(float4) + mesh.vertex_core_offset))));
+    Vertex v2 = unpack_vertex(VertexPacked(asfloat(vertices.Load4(ind.z * sizeof(float4) + mesh.vertex_core_offset))));
+
+    const float3 surf_normal_os = cross(v1.position - v0.position, v2.position - v0.position);
+
+    payload.t = RayTCurrent();
+    payload.instance_index = hit.instance_index;
+    payload.mesh_index = hit.mesh_index;
+    payload.material_index = vertices.Load(ind.x * sizeof(uint) + mesh.vertex_mat_offset);
+    payload.normal = normalize(transform_normal(hit.object_to_world, surf_normal_os));
+    payload.primitive_index = primitive_index;
+}
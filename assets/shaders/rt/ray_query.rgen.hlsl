@@ -0,0 +1,47 @@
+// Traces application-provided rays, and writes out their closest hits.
+// See `renderers/ray_queries.rs`.
+
+#include "ray_query_payload.hlsl"
+
+struct RayQuery {
+    float4 origin_t_min;
+    float4 direction_t_max;
+};
+
+[[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
+[[vk::binding(0)]] StructuredBuffer<RayQuery> ray_queries;
+[[vk::binding(1)]] RWByteAddressBuffer results;
+[[vk::binding(2)]] cbuffer _ {
+    uint ray_count;
+    uint batch_id;
+};
+
+static const uint RESULTS_HEADER_SIZE = 16;
+static const uint RAY_QUERY_HIT_SIZE = 32;
+
+[shader("raygeneration")]
+void main() {
+    const uint idx = DispatchRaysIndex().x;
+    if (idx >= ray_count) {
+        return;
+    }
+
+    if (0 == idx) {
+        results.Store4(0, uint4(batch_id, ray_count, 0, 0));
+    }
+
+    const RayQuery query = ray_queries[idx];
+
+    RayDesc ray;
+    ray.Origin = query.origin_t_min.xyz;
+    ray.Direction = normalize(query.direction_t_max.xyz);
+    ray.TMin = query.origin_t_min.w;
+    ray.TMax = query.direction_t_max.w;
+
+    RayQueryPayload payload = RayQueryPayload::new_miss();
+    TraceRay(acceleration_structure, RAY_FLAG_NONE, 0xff, 0, 0, 0, ray, payload);
+
+    const uint dst = RESULTS_HEADER_SIZE + idx * RAY_QUERY_HIT_SIZE;
+    results.Store4(dst + 0, uint4(asuint(payload.t), payload.instance_index, payload.mesh_index, payload.material_index));
+    results.Store4(dst + 16, uint4(asuint(payload.normal), payload.primitive_index));
+}
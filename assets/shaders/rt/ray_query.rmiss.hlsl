@@ -0,0 +1,4 @@
+#include "ray_query_payload.hlsl"
+
+[shader("miss")]
+void main(inout RayQueryPayload payload: SV_RayPayload) {}
@@ -0,0 +1,24 @@
+#ifndef RAY_QUERY_PAYLOAD_HLSL
+#define RAY_QUERY_PAYLOAD_HLSL
+
+struct RayQueryPayload {
+    float t;
+    uint instance_index;
+    uint mesh_index;
+    uint material_index;
+    float3 normal;
+    uint primitive_index;
+
+    static RayQueryPayload new_miss() {
+        RayQueryPayload res;
+        res.t = 0;
+        res.instance_index = 0xffffffff;
+        res.mesh_index = 0xffffffff;
+        res.material_index = 0xffffffff;
+        res.normal = 0.0.xxx;
+        res.primitive_index = 0xffffffff;
+        return res;
+    }
+};
+
+#endif  // RAY_QUERY_PAYLOAD_HLSL
@@ -11,15 +11,16 @@
 #include "../inc/atmosphere.hlsl"
 #include "../inc/sun.hlsl"
 #include "../inc/lights/triangle.hlsl"
+#include "../inc/lights/point.hlsl"
+#include "../inc/samplers.hlsl"
+#include "../inc/environment_map.hlsl"
+#include "../inc/spectral.hlsl"
+#include "photon_map.hlsl"
 
 [[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
 
 [[vk::binding(0)]] RWTexture2D<float4> output_tex;
 
-// Does not include the segment used to connect to the sun
-static const uint MAX_EYE_PATH_LENGTH = 16;
-
-static const uint RUSSIAN_ROULETTE_START_PATH_LENGTH = 3;
 static const float MAX_RAY_LENGTH = FLT_MAX;
 //static const float MAX_RAY_LENGTH = 5.0;
 
@@ -37,11 +38,134 @@ static const bool ONLY_SPECULAR_FIRST_BOUNCE = !true;
 static const bool USE_SOFT_SHADOWS = true;
 static const bool SHOW_ALBEDO = !true;
 
-static const bool USE_LIGHTS = true;
+#ifndef PATH_TRACE_USE_LIGHTS
+    #define PATH_TRACE_USE_LIGHTS true
+#endif
+
+#ifndef PATH_TRACE_SAMPLES_PER_PIXEL
+    #define PATH_TRACE_SAMPLES_PER_PIXEL 1
+#endif
+
+// The environment map and `ReferencePathTraceConfig` occupy bindings 1 to 3, so shaders reusing
+// this path tracer with their own bindings need to disable them, and get the default settings.
+#ifndef PATH_TRACE_USE_ENVIRONMENT_MAP
+    #define PATH_TRACE_USE_ENVIRONMENT_MAP 1
+#endif
+
+struct PathTraceSettings {
+    // Does not include the segment used to connect to the sun
+    uint max_path_length;
+    uint russian_roulette_start_path_length;
+    // Upper bound on the luminance of a single path sample; zero disables it.
+    float firefly_clamp;
+    // Pixels stop accumulating once they reach this many samples.
+    uint max_sample_count;
+    // Trace hero wavelengths instead of RGB; see `inc/spectral.hlsl`.
+    bool spectral;
+    // Dispersion of dielectrics in spectral mode; zero disables it.
+    float abbe_number;
+    // Photon mapping gathers caustics within this radius (in meters) from the photon map.
+    float photon_gather_radius_m;
+    // Photon mapping: vertices this smooth or smoother are specular for the caustic paths
+    // it leaves to the photon map. Negative with path tracing alone.
+    float max_specular_roughness;
+};
+
+// Bindings 6 to 8 hold the caustic photon map of `ReferenceIntegrator::PhotonMapping`.
+#ifndef PATH_TRACE_PHOTON_MAPPING
+    #define PATH_TRACE_PHOTON_MAPPING 0
+#endif
+
+#if PATH_TRACE_USE_ENVIRONMENT_MAP
+    [[vk::binding(1)]] TextureCube<float4> environment_cube;
+    [[vk::binding(2)]] StructuredBuffer<float> environment_cdf;
+    [[vk::binding(3)]] cbuffer _ {
+        uint environment_map_enabled;
+        uint max_path_length;
+        uint russian_roulette_start_path_length;
+        float firefly_clamp;
+        uint max_sample_count;
+        uint spectral;
+        float abbe_number;
+        float photon_gather_radius_m;
+        float max_specular_roughness;
+    };
+
+    PathTraceSettings path_trace_settings() {
+        PathTraceSettings res;
+        res.max_path_length = max_path_length;
+        res.russian_roulette_start_path_length = russian_roulette_start_path_length;
+        res.firefly_clamp = firefly_clamp;
+        res.max_sample_count = max_sample_count;
+        res.spectral = spectral != 0;
+        res.abbe_number = abbe_number;
+        res.photon_gather_radius_m = photon_gather_radius_m;
+        res.max_specular_roughness = max_specular_roughness;
+        return res;
+    }
+#else
+    // Defaults of `ReferencePathTraceConfig`
+    PathTraceSettings path_trace_settings() {
+        PathTraceSettings res;
+        res.max_path_length = 16;
+        res.russian_roulette_start_path_length = 3;
+        res.firefly_clamp = 0.0;
+        res.max_sample_count = 1000;
+        res.spectral = false;
+        res.abbe_number = 0.0;
+        res.photon_gather_radius_m = 0.0;
+        res.max_specular_roughness = -1.0;
+        return res;
+    }
+#endif
+
+// First-hit albedo and normal, accumulated alongside the radiance for external denoisers.
+// Follow the bindings of the environment map and settings.
+#ifndef PATH_TRACE_WRITE_AOVS
+    #define PATH_TRACE_WRITE_AOVS PATH_TRACE_USE_ENVIRONMENT_MAP
+#endif
+
+#if PATH_TRACE_WRITE_AOVS
+    [[vk::binding(4)]] RWTexture2D<float4> albedo_aov_tex;
+    [[vk::binding(5)]] RWTexture2D<float4> normal_aov_tex;
+#endif
+
+#if PATH_TRACE_PHOTON_MAPPING
+    [[vk::binding(6)]] StructuredBuffer<PhotonPacked> photons;
+    [[vk::binding(7)]] StructuredBuffer<uint> photon_next;
+    [[vk::binding(8)]] StructuredBuffer<uint> photon_cell_heads;
+#endif
+
+static const bool USE_LIGHTS = PATH_TRACE_USE_LIGHTS;
 static const bool USE_EMISSIVE = true;
 static const bool RESET_ACCUMULATION = !true;
 static const bool ROLLING_ACCUMULATION = !true;
 
+// Shaders reusing this path tracer with their own view (e.g. `thumbnail/path_trace.rgen.hlsl`)
+// define `PATH_TRACE_CUSTOM_VIEW`, and provide these functions themselves.
+#ifndef PATH_TRACE_CUSTOM_VIEW
+RayDesc path_trace_camera_ray(float2 uv) {
+    const ViewRayContext view_ray_context = ViewRayContext::from_uv(uv);
+    const float3 ray_dir_ws = view_ray_context.ray_dir_ws();
+
+    return new_ray(
+        view_ray_context.ray_origin_ws(), 
+        normalize(ray_dir_ws.xyz),
+        0.0,
+        FLT_MAX
+    );
+}
+
+uint path_trace_seed() {
+    return frame_constants.frame_index;
+}
+
+// Applied to the accumulated radiance, which is otherwise pre-exposed.
+float path_trace_radiance_scale() {
+    return 1.0;
+}
+#endif
+
 float3 sample_environment_light(float3 dir) {
     //return 0.5.xxx;
 
@@ -49,6 +173,13 @@ float3 sample_environment_light(float3 dir) {
         return 0.5.xxx;
     }
 
+    #if PATH_TRACE_USE_ENVIRONMENT_MAP
+        if (environment_map_enabled) {
+            // Already rotated, scaled by the intensity, and pre-exposed.
+            return environment_cube.SampleLevel(sampler_llr, dir, 0).rgb;
+        }
+    #endif
+
     return atmosphere_default(dir, SUN_DIRECTION);
 
     float3 col = (dir.zyx * float3(1, 1, -1) * 0.5 + float3(0.6, 0.5, 0.5)) * 0.75;
@@ -56,6 +187,79 @@ float3 sample_environment_light(float3 dir) {
     return col;
 }
 
+// Radiance of lights and the sky, given in linear sRGB; a spectrum at `wavelengths` in spectral mode.
+float3 path_trace_emission(PathTraceSettings settings, float3 rgb, float3 wavelengths) {
+    if (settings.spectral) {
+        return rgb_to_spectral_radiance(rgb, wavelengths);
+    } else {
+        return rgb;
+    }
+}
+
+// Caustics reaching `position`: radiance towards `wo` from the photons within the gather radius.
+// Where the path tracer treats the vertex as specular, only its diffuse lobe gathers,
+// since the specular one continues caustic paths to the photons' previous vertex.
+float3 gather_caustic_photons(
+    PathTraceSettings settings,
+    float3 position,
+    float3x3 tangent_to_world,
+    float3 wo,
+    LayeredBrdf brdf,
+    bool is_specular_vertex,
+    float3 wavelengths
+) {
+    float3 radiance = 0.0;
+
+    #if PATH_TRACE_PHOTON_MAPPING
+        const float gather_radius = world_units(settings.photon_gather_radius_m);
+
+        for (uint corner = 0; corner < 8; ++corner) {
+            const int3 cell_coord = photon_map_gather_cell_coord(position, gather_radius, corner);
+            uint photon_link = photon_cell_heads[photon_map_cell_index(cell_coord)];
+
+            [loop]
+            while (photon_link != PHOTON_LIST_END) {
+                const uint photon_idx = photon_link - 1;
+                photon_link = photon_next[photon_idx];
+
+                const Photon photon = unpack_photon(photons[photon_idx]);
+
+                // Cells sharing a list through a hash collision would otherwise count twice.
+                if (any(photon_map_cell_coord(photon.position, gather_radius) != cell_coord)) {
+                    continue;
+                }
+
+                const float3 to_photon = photon.position - position;
+                if (dot(to_photon, to_photon) > gather_radius * gather_radius) {
+                    continue;
+                }
+
+                const float3 wi = mul(photon.incident_dir, tangent_to_world);
+                float3 brdf_value;
+                if (wi.z < 0.0) {
+                    brdf_value = brdf.evaluate_transmission(wi);
+                } else if (is_specular_vertex) {
+                    brdf_value = brdf.evaluate_diffuse_lobe(wo, wi);
+                } else {
+                    brdf_value = brdf.evaluate(wo, wi);
+                }
+
+                radiance += brdf_value * path_trace_emission(settings, photon.flux, wavelengths);
+            }
+        }
+
+        radiance /= M_PI * gather_radius * gather_radius;
+    #endif
+
+    return radiance;
+}
+
+float mis_power_heuristic(float pdf, float other_pdf) {
+    const float pdf2 = pdf * pdf;
+    const float other_pdf2 = other_pdf * other_pdf;
+    return pdf2 > 0.0 ? pdf2 / (pdf2 + other_pdf2) : 0.0;
+}
+
 // Approximate Gaussian remap
 // https://www.shadertoy.com/view/MlVSzw
 float inv_error_function(float x, float truncation) {
@@ -75,6 +279,7 @@ float remap_unorm_to_gaussian(float x, float truncation) {
 [shader("raygeneration")]
 void main() {
     const uint2 px = DispatchRaysIndex().xy;
+    const PathTraceSettings settings = path_trace_settings();
 
     float4 prev;
     if (ROLLING_ACCUMULATION) {
@@ -83,12 +288,14 @@ void main() {
         prev = select(RESET_ACCUMULATION, 0, output_tex[px]);
     }
 
-    if (prev.w < 1000)
+    if (prev.w < settings.max_sample_count)
     {
         float4 radiance_sample_count_packed = 0.0;
-        uint rng = hash_combine2(hash_combine2(px.x, hash1(px.y)), frame_constants.frame_index);
+        float3 albedo_sum = 0.0;
+        float3 normal_sum = 0.0;
+        uint rng = hash_combine2(hash_combine2(px.x, hash1(px.y)), path_trace_seed());
 
-        static const uint sample_count = 1;
+        static const uint sample_count = PATH_TRACE_SAMPLES_PER_PIXEL;
         for (uint sample_idx = 0; sample_idx < sample_count; ++sample_idx) {
             float px_off0 = 0.5;
             float px_off1 = 0.5;
@@ -102,17 +309,12 @@ void main() {
             const float2 pixel_center = px + float2(px_off0, px_off1);
             const float2 uv = pixel_center / DispatchRaysDimensions().xy;
 
-            RayDesc outgoing_ray;
-            {
-                const ViewRayContext view_ray_context = ViewRayContext::from_uv(uv);
-                const float3 ray_dir_ws = view_ray_context.ray_dir_ws();
-
-                outgoing_ray = new_ray(
-                    view_ray_context.ray_origin_ws(), 
-                    normalize(ray_dir_ws.xyz),
-                    0.0,
-                    FLT_MAX
-                );
+            RayDesc outgoing_ray = path_trace_camera_ray(uv);
+
+            // Hero wavelengths of the path in spectral mode, in nanometers
+            float3 wavelengths = 0.0;
+            if (settings.spectral) {
+                wavelengths = spectral_sample_wavelengths(uint_to_u01_float(hash1_mut(rng)));
             }
 
             float3 throughput = 1.0.xxx;
@@ -120,6 +322,21 @@ void main() {
 
             float roughness_bias = 0.0;
 
+            // Whether the path took a non-specular event before; see `max_specular_roughness`.
+            // Photon mapping leaves the rest of caustic paths from there to the photon map.
+            bool after_diffuse_event = false;
+
+            // Whether the path got here off the specular lobe of a vertex after a diffuse event.
+            // Emission found this way completes a caustic path, which the photons of
+            // triangle lights already account for.
+            bool arrived_via_caustic_path = false;
+
+            // Whether the environment map was sampled for next event estimation at the last
+            // path vertex, and the solid angle pdf of the BRDF sample which continued the path.
+            // If both techniques could have produced a ray escaping to the sky, it's weighted via MIS.
+            bool environment_sampled_at_last_vertex = false;
+            float last_brdf_pdf = 0.0;
+
             RayCone ray_cone = pixel_ray_cone_from_image_height(
                 DispatchRaysDimensions().y
             );
@@ -128,7 +345,7 @@ void main() {
             ray_cone.spread_angle *= 0.3;
 
             [loop]
-            for (uint path_length = 0; path_length < MAX_EYE_PATH_LENGTH; ++path_length) {
+            for (uint path_length = 0; path_length < settings.max_path_length; ++path_length) {
                 /*if (path_length == 1 && outgoing_ray.Direction.x > -0.8) {
                     throughput = 0;
                 } else {
@@ -147,6 +364,8 @@ void main() {
                     .trace(acceleration_structure);
 
                 if (primary_hit.is_hit) {
+                    environment_sampled_at_last_vertex = false;
+
                     // TODO
                     const float surface_spread_angle = 0.0;
                     ray_cone = ray_cone.propagate(surface_spread_angle, primary_hit.ray_t);
@@ -163,7 +382,7 @@ void main() {
                             new_ray(
                                 primary_hit.position,
                                 to_light_norm,
-                                1e-4,
+                                world_units(1e-4),
                                 FLT_MAX
                         ));
 
@@ -184,6 +403,16 @@ void main() {
                         }
                     }
 
+                    if (0 == path_length) {
+                        albedo_sum += gbuffer.albedo;
+                        normal_sum += gbuffer.normal;
+                    }
+
+                    if (settings.spectral) {
+                        gbuffer.albedo = rgb_to_spectral_reflectance(gbuffer.albedo, wavelengths);
+                        gbuffer.emissive = rgb_to_spectral_radiance(gbuffer.emissive, wavelengths);
+                    }
+
                     if (FURNACE_TEST && !FURNACE_TEST_EXCLUDE_DIFFUSE) {
                         gbuffer.albedo = 1;
                     }
@@ -236,7 +465,12 @@ void main() {
                         wo = normalize(wo);
                     }
 
-                    LayeredBrdf brdf = LayeredBrdf::from_gbuffer_ndotv(gbuffer, wo.z);
+                    float3 dielectric_f0_value = 0.04;
+                    if (settings.spectral) {
+                        dielectric_f0_value = dielectric_f0(dispersive_ior(settings.abbe_number, wavelengths));
+                    }
+
+                    LayeredBrdf brdf = LayeredBrdf::from_gbuffer_ndotv_dielectric_f0(gbuffer, wo.z, dielectric_f0_value);
 
                     if (FIREFLY_SUPPRESSION) {
                         brdf.specular_brdf.roughness = lerp(brdf.specular_brdf.roughness, 1.0, roughness_bias);
@@ -246,20 +480,45 @@ void main() {
                         brdf.diffuse_brdf.albedo = 0.0.xxx;
                     }
 
+                    const bool is_specular_vertex = gbuffer.roughness <= settings.max_specular_roughness;
+
+                    // Light reflected off the specular lobe here completes a caustic path
+                    // which the photon map already accounts for at the last diffuse event.
+                    const bool exclude_specular_lobe = is_specular_vertex && after_diffuse_event;
+
                     if (!FURNACE_TEST && !(ONLY_SPECULAR_FIRST_BOUNCE && path_length == 0)) {
-                        const float3 brdf_value = brdf.evaluate_directional_light(wo, wi);
-                        const float3 light_radiance = select(is_shadowed, 0.0, SUN_COLOR);
+                        const float3 brdf_value = select(
+                            exclude_specular_lobe,
+                            brdf.evaluate_diffuse_lobe(wo, wi),
+                            brdf.evaluate_directional_light(wo, wi)
+                        );
+                        const float3 light_radiance = select(is_shadowed, 0.0, path_trace_emission(settings, SUN_COLOR, wavelengths));
                         total_radiance += throughput * brdf_value * light_radiance * max(0.0, wi.z);
+                        total_radiance += throughput * brdf.evaluate_transmission(wi) * light_radiance * max(0.0, -wi.z);
 
-                        if (USE_EMISSIVE) {
+                        if (USE_EMISSIVE && !arrived_via_caustic_path) {
                             total_radiance += gbuffer.emissive * throughput;
                         }
+
+                        if (PATH_TRACE_PHOTON_MAPPING) {
+                            total_radiance += throughput * gather_caustic_photons(
+                                settings,
+                                primary_hit.position,
+                                tangent_to_world,
+                                wo,
+                                brdf,
+                                is_specular_vertex,
+                                wavelengths
+                            );
+                        }
                         
                         if (USE_LIGHTS && frame_constants.triangle_light_count > 0/* && path_length > 0*/) {   // rtr comp
-                            const float light_selection_pmf = 1.0 / frame_constants.triangle_light_count;
-                            const uint light_idx = hash1_mut(rng) % frame_constants.triangle_light_count;
-                            //const float light_selection_pmf = 1;
-                            //for (uint light_idx = 0; light_idx < frame_constants.triangle_light_count; light_idx += 1)
+                            const TriangleLightSelection light_selection = select_triangle_light(float2(
+                                uint_to_u01_float(hash1_mut(rng)),
+                                uint_to_u01_float(hash1_mut(rng))
+                            ));
+                            const float light_selection_pmf = light_selection.pmf;
+                            const uint light_idx = light_selection.light_idx;
                             {
                                 const float2 urand = float2(
                                     uint_to_u01_float(hash1_mut(rng)),
@@ -287,16 +546,95 @@ void main() {
                                             new_ray(
                                                 shadow_ray_origin,
                                                 to_light_norm_ws,
-                                                1e-3,
-                                                sqrt(dist_to_light2) - 2e-3
+                                                world_units(1e-3),
+                                                sqrt(dist_to_light2) - world_units(2e-3)
                                         ));
 
+                                    const float3 brdf_value = select(
+                                        exclude_specular_lobe,
+                                        brdf.evaluate_diffuse_lobe(wo, wi),
+                                        brdf.evaluate(wo, wi)
+                                    );
+
                                     total_radiance +=
                                         select(is_shadowed, 0,
-                                            throughput * triangle_light.radiance() * brdf.evaluate(wo, wi) / light_sample.pdf.value * to_psa_metric / light_selection_pmf);
+                                            throughput * path_trace_emission(settings, triangle_light.radiance(), wavelengths) * brdf_value / light_sample.pdf.value * to_psa_metric / light_selection_pmf);
                                 }
                             }
                         }
+
+                        #if PATH_TRACE_USE_ENVIRONMENT_MAP
+                        if (environment_map_enabled) {
+                            environment_sampled_at_last_vertex = true;
+
+                            const EnvironmentMapSample env_sample = sample_environment_map(
+                                environment_cdf,
+                                float2(uint_to_u01_float(hash1_mut(rng)), uint_to_u01_float(hash1_mut(rng)))
+                            );
+                            const float3 wi = mul(env_sample.dir, tangent_to_world);
+
+                            const float3 brdf_value = brdf.evaluate(wo, wi) * max(0.0, wi.z)
+                                + brdf.evaluate_transmission(wi) * max(0.0, -wi.z);
+
+                            if (env_sample.pdf > 0.0 && any(brdf_value > 0.0)) {
+                                const bool is_shadowed =
+                                    rt_is_shadowed(
+                                        acceleration_structure,
+                                        new_ray(
+                                            primary_hit.position,
+                                            env_sample.dir,
+                                            world_units(1e-4),
+                                            FLT_MAX
+                                    ));
+
+                                const float brdf_pdf = brdf.pdf(wo, wi) * max(0.0, wi.z);
+                                const float mis_weight = mis_power_heuristic(env_sample.pdf, brdf_pdf);
+
+                                total_radiance +=
+                                    select(is_shadowed, 0,
+                                        throughput * brdf_value * path_trace_emission(settings, sample_environment_light(env_sample.dir), wavelengths)
+                                        * mis_weight / env_sample.pdf);
+                            }
+                        }
+                        #endif
+
+                        if (USE_LIGHTS && frame_constants.point_light_count > 0) {
+                            const float light_selection_pmf = 1.0 / frame_constants.point_light_count;
+                            const uint light_idx = hash1_mut(rng) % frame_constants.point_light_count;
+
+                            const float2 urand = float2(
+                                uint_to_u01_float(hash1_mut(rng)),
+                                uint_to_u01_float(hash1_mut(rng))
+                            );
+
+                            const PointLight point_light = PointLight::from_packed(point_lights_dyn[light_idx]);
+                            const PointLightSample light_sample = sample_point_light(point_light, primary_hit.position, urand);
+                            const float3 wi = mul(light_sample.dir, tangent_to_world);
+
+                            if (wi.z != 0.0 && any(light_sample.irradiance > 0.0)) {
+                                const bool is_shadowed =
+                                    rt_is_shadowed(
+                                        acceleration_structure,
+                                        new_ray(
+                                            primary_hit.position,
+                                            light_sample.dir,
+                                            world_units(1e-3),
+                                            light_sample.dist - world_units(2e-3)
+                                    ));
+
+                                const float3 reflection_value = select(
+                                    exclude_specular_lobe,
+                                    brdf.evaluate_diffuse_lobe(wo, wi),
+                                    brdf.evaluate_directional_light(wo, wi)
+                                );
+                                const float3 brdf_value = reflection_value * max(0.0, wi.z)
+                                    + brdf.evaluate_transmission(wi) * max(0.0, -wi.z);
+
+                                total_radiance +=
+                                    select(is_shadowed, 0,
+                                        throughput * brdf_value * path_trace_emission(settings, light_sample.irradiance, wavelengths) / light_selection_pmf);
+                            }
+                        }
                     }
 
                     float3 urand;
@@ -324,6 +662,13 @@ void main() {
                             uint_to_u01_float(hash1_mut(rng)));
                     }
 
+                    // `sample` picks the lobe the same way.
+                    const bool diffuse_lobe_sampled = urand.z < brdf.diffuse_lobe_probability();
+                    arrived_via_caustic_path = exclude_specular_lobe && !diffuse_lobe_sampled;
+                    after_diffuse_event = after_diffuse_event
+                        || !is_specular_vertex
+                        || diffuse_lobe_sampled;
+
                     brdf_sample = brdf.sample(wo, urand);
 
                     if (brdf_sample.is_valid()) {
@@ -335,18 +680,19 @@ void main() {
                         outgoing_ray.Direction = mul(tangent_to_world, brdf_sample.wi);
                         outgoing_ray.TMin = 1e-4;
                         throughput *= brdf_sample.value_over_pdf;
+                        last_brdf_pdf = brdf.pdf(wo, brdf_sample.wi) * brdf_sample.wi.z;
                     } else {
                          break;
                     }
 
                     if (FURNACE_TEST) {
                         // Short-circuit the path tracing
-                        total_radiance += throughput * sample_environment_light(outgoing_ray.Direction);
+                        total_radiance += throughput * path_trace_emission(settings, sample_environment_light(outgoing_ray.Direction), wavelengths);
                         break;
                     }
 
                     // Russian roulette
-                    if (path_length >= RUSSIAN_ROULETTE_START_PATH_LENGTH) {
+                    if (path_length >= settings.russian_roulette_start_path_length) {
                         const float rr_coin = uint_to_u01_float(hash1_mut(rng));
                         const float continue_p = max(gbuffer.albedo.r, max(gbuffer.albedo.g, gbuffer.albedo.b));
                         if (rr_coin > continue_p) {
@@ -356,13 +702,48 @@ void main() {
                         }
                     }
                 } else {
-                    total_radiance += throughput * sample_environment_light(outgoing_ray.Direction);
+                    if (0 == path_length) {
+                        // Denoisers expect the background color as the albedo of pixels which see it.
+                        albedo_sum += saturate(sample_environment_light(outgoing_ray.Direction));
+                    }
+
+                    float mis_weight = 1.0;
+
+                    #if PATH_TRACE_USE_ENVIRONMENT_MAP
+                        if (environment_sampled_at_last_vertex) {
+                            mis_weight = mis_power_heuristic(
+                                last_brdf_pdf,
+                                environment_map_pdf(environment_cdf, outgoing_ray.Direction)
+                            );
+                        }
+                    #endif
+
+                    total_radiance += throughput * path_trace_emission(settings, sample_environment_light(outgoing_ray.Direction), wavelengths) * mis_weight;
                     break;
                 }
             }
 
-            if (all(total_radiance >= 0.0)) {
-                radiance_sample_count_packed += float4(total_radiance, 1.0);
+            if (settings.spectral) {
+                total_radiance = spectral_radiance_to_rgb(total_radiance, wavelengths);
+            }
+
+            if (settings.firefly_clamp > 0.0) {
+                const float luminance = sRGB_to_luminance(total_radiance);
+                if (luminance > settings.firefly_clamp) {
+                    total_radiance *= settings.firefly_clamp / luminance;
+                }
+            }
+
+            // Individual spectral samples often have negative channels, which average out
+            // (the accumulation is clamped, but only its running mean), so keep those.
+            const bool is_valid_sample = select(
+                settings.spectral,
+                all(isfinite(total_radiance)),
+                all(total_radiance >= 0.0)
+            );
+
+            if (is_valid_sample) {
+                radiance_sample_count_packed += float4(total_radiance * path_trace_radiance_scale(), 1.0);
             }
         }
 
@@ -373,5 +754,11 @@ void main() {
         cur.rgb /= max(1.0, cur.w);
 
         output_tex[px] = float4(max(0.0.xxx, lerp(prev.rgb, cur.rgb, lrp)), max(1, tsc));
+
+        #if PATH_TRACE_WRITE_AOVS
+            const float aov_lrp = sample_count / max(1.0, sample_count + prev.w);
+            albedo_aov_tex[px] = float4(lerp(albedo_aov_tex[px].rgb, albedo_sum / sample_count, aov_lrp), 1);
+            normal_aov_tex[px] = float4(lerp(normal_aov_tex[px].xyz, normal_sum / sample_count, aov_lrp), 1);
+        #endif
     }
 }
@@ -0,0 +1,25 @@
+// Packs the reference accumulation and its AOVs into linear RGB float planes,
+// for read-back by `renderers/reference.rs`: beauty, then albedo, then normal.
+
+[[vk::binding(0)]] Texture2D<float4> beauty_tex;
+[[vk::binding(1)]] Texture2D<float4> albedo_aov_tex;
+[[vk::binding(2)]] Texture2D<float4> normal_aov_tex;
+[[vk::binding(3)]] RWByteAddressBuffer output_buf;
+[[vk::binding(4)]] cbuffer _ {
+    uint2 extent;
+    float radiance_scale;
+};
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    if (any(px >= extent)) {
+        return;
+    }
+
+    const uint plane_size = extent.x * extent.y * 12;
+    const uint offset = (px.y * extent.x + px.x) * 12;
+
+    output_buf.Store3(offset, asuint(beauty_tex[px].rgb * radiance_scale));
+    output_buf.Store3(plane_size + offset, asuint(albedo_aov_tex[px].rgb));
+    output_buf.Store3(plane_size * 2 + offset, asuint(normal_aov_tex[px].xyz));
+}
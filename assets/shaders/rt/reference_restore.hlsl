@@ -0,0 +1,21 @@
+// Writes a saved reference state back into the accumulation and its AOVs;
+// see `restore_reference` in `renderers/reference.rs`.
+
+[[vk::binding(0)]] Texture2D<float4> beauty_tex;
+[[vk::binding(1)]] Texture2D<float4> albedo_tex;
+[[vk::binding(2)]] Texture2D<float4> normal_tex;
+[[vk::binding(3)]] RWTexture2D<float4> accum_output_tex;
+[[vk::binding(4)]] RWTexture2D<float4> albedo_aov_output_tex;
+[[vk::binding(5)]] RWTexture2D<float4> normal_aov_output_tex;
+[[vk::binding(6)]] cbuffer _ {
+    float pre_exposure;
+    float sample_count;
+};
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    // Alpha is the sample count the path tracer weighs new samples against.
+    accum_output_tex[px] = float4(beauty_tex[px].rgb * pre_exposure, sample_count);
+    albedo_aov_output_tex[px] = float4(albedo_tex[px].rgb, 1);
+    normal_aov_output_tex[px] = float4(normal_tex[px].xyz, 1);
+}
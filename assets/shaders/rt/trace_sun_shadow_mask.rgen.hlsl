@@ -8,16 +8,27 @@
 
 #include "../inc/blue_noise.hlsl"
 #include "../inc/math.hlsl"
+#include "../inc/gbuffer.hlsl"
 
-#define USE_SOFT_SHADOWS 1
+// Set by `trace_sun_shadow_mask` from `QualityConfig::shadows`: 0 = low, 1 = medium, 2 = high
+#ifndef SHADOW_QUALITY
+    #define SHADOW_QUALITY 1
+#endif
+
+#define USE_SOFT_SHADOWS (SHADOW_QUALITY >= 1)
+#if SHADOW_QUALITY >= 2
+    #define SHADOW_RAY_COUNT 2
+#else
+    #define SHADOW_RAY_COUNT 1
+#endif
 
 [[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
 [[vk::binding(0)]] Texture2D<float> depth_tex;
 [[vk::binding(1)]] Texture2D<float3> geometric_normal_tex;
-[[vk::binding(2)]] RWTexture2D<float4> output_tex;
+[[vk::binding(2)]] Texture2D<float4> gbuffer_tex;
+[[vk::binding(3)]] RWTexture2D<float4> output_tex;
 
-[shader("raygeneration")]
-void main() {
+RAYGEN_MAIN {
     const uint2 px = DispatchRaysIndex().xy;
 
     const float2 pixel_center = px + 0.5.xx;
@@ -40,21 +51,41 @@ void main() {
     const float3 normal_vs = geometric_normal_tex[px] * 2.0 - 1.0;
     const float3 normal_ws = mul(frame_constants.view_constants.view_to_world, float4(normal_vs, 0.0)).xyz;
 
-    const float3 bias_dir = normal_ws;
     const float bias_amount = (-pt_vs.z + length(pt_ws.xyz)) * 1e-5;
-    const float3 ray_origin = pt_ws.xyz + bias_dir * bias_amount;
-
-    const bool is_shadowed = rt_is_shadowed(
-        acceleration_structure,
-        new_ray(
-            ray_origin,
-            sample_sun_direction(
-                blue_noise_for_pixel(px, frame_constants.frame_index).xy,
-                USE_SOFT_SHADOWS
-            ),
-            0,
-            FLT_MAX
-        ));
-
-    output_tex[px] = select(is_shadowed, 0.0, 1.0);
+
+    float visibility = 0.0;
+
+    for (uint ray_i = 0; ray_i < SHADOW_RAY_COUNT; ++ray_i) {
+        // Further rays use the blue noise of other frames, which is decorrelated from this one.
+        const float3 to_light_norm = sample_sun_direction(
+            blue_noise_for_pixel(px, frame_constants.frame_index + ray_i * 17).xy,
+            USE_SOFT_SHADOWS
+        );
+
+        float3 bias_dir = normal_ws;
+
+        // Thin translucent surfaces lit from behind receive light through their back side,
+        // so trace from there. Otherwise the ray would be blocked by the surface itself.
+        if (dot(normal_ws, to_light_norm) < 0.0) {
+            const float transmission = GbufferDataPacked::from_uint4(asuint(gbuffer_tex[px])).unpack().transmission;
+            if (transmission > 0.0) {
+                bias_dir = -normal_ws;
+            }
+        }
+
+        const float3 ray_origin = pt_ws.xyz + bias_dir * bias_amount;
+
+        const bool is_shadowed = rt_is_shadowed(
+            acceleration_structure,
+            new_ray(
+                ray_origin,
+                to_light_norm,
+                0,
+                FLT_MAX
+            ));
+
+        visibility += select(is_shadowed, 0.0, 1.0);
+    }
+
+    output_tex[px] = visibility / SHADOW_RAY_COUNT;
 }
@@ -0,0 +1,59 @@
+#include "../inc/uv.hlsl"
+#include "../inc/math.hlsl"
+#include "../inc/frame_constants.hlsl"
+#include "../inc/rt.hlsl"
+#include "../inc/blue_noise.hlsl"
+
+[[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
+
+[[vk::binding(0)]] Texture2D<float3> half_view_normal_tex;
+[[vk::binding(1)]] Texture2D<float> depth_tex;
+[[vk::binding(2)]] RWTexture2D<float> output_tex;
+[[vk::binding(3)]] cbuffer _ {
+    float4 gbuffer_tex_size;
+    float ray_length_meters;
+    uint rays_per_pixel;
+};
+
+// Cosine-weighted, so that the fraction of unoccluded rays is the AO term.
+float3 cosine_sample_hemisphere(float2 urand) {
+    const float phi = urand.y * M_TAU;
+    const float sin_theta = sqrt(urand.x);
+    const float cos_theta = sqrt(1.0 - urand.x);
+    return float3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+[shader("raygeneration")]
+void main() {
+    const uint2 px = DispatchRaysIndex().xy;
+    const uint2 hi_px = px * 2 + HALFRES_SUBSAMPLE_OFFSET;
+
+    const float depth = depth_tex[hi_px];
+    if (0.0 == depth) {
+        output_tex[px] = 1.0;
+        return;
+    }
+
+    const float2 uv = get_uv(hi_px, gbuffer_tex_size);
+    const ViewRayContext view_ray_context = ViewRayContext::from_uv_and_biased_depth(uv, depth);
+
+    const float3 normal_ws = direction_view_to_world(half_view_normal_tex[px]);
+    const float3x3 tangent_to_world = build_orthonormal_basis(normal_ws);
+    const float3 ray_origin = view_ray_context.biased_secondary_ray_origin_ws_with_normal(normal_ws);
+    const float ray_length = world_units(ray_length_meters);
+
+    uint unoccluded_count = 0;
+    for (uint ray_idx = 0; ray_idx < rays_per_pixel; ++ray_idx) {
+        const float2 urand = blue_noise_for_pixel(px, frame_constants.frame_index * rays_per_pixel + ray_idx).xy;
+        const float3 ray_dir = mul(tangent_to_world, cosine_sample_hemisphere(urand));
+
+        const bool is_occluded = rt_is_shadowed(
+            acceleration_structure,
+            new_ray(ray_origin, ray_dir, 0.0, ray_length)
+        );
+
+        unoccluded_count += select(is_occluded, 0, 1);
+    }
+
+    output_tex[px] = float(unoccluded_count) / float(max(1, rays_per_pixel));
+}
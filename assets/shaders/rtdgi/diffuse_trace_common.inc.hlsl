@@ -1,5 +1,10 @@
+// Set by `RtdgiRenderer` from `QualityConfig::rtdgi`: 0 = low, 1 = medium, 2 = high
+#ifndef RTDGI_QUALITY
+    #define RTDGI_QUALITY 1
+#endif
+
 // Should be 1, but rarely matters for the diffuse bounce, so might as well save a few cycles.
-#define USE_SOFT_SHADOWS 0
+#define USE_SOFT_SHADOWS (RTDGI_QUALITY >= 2)
 
 #define USE_IRCACHE 1
 #define USE_WORLD_RADIANCE_CACHE 0
@@ -11,6 +16,9 @@
 #define USE_EMISSIVE 1
 #define USE_LIGHTS 1
 
+// Without it, hits outside of the screen only get triangle lights through the irradiance cache.
+#define USE_OFF_SCREEN_TRIANGLE_LIGHTS (RTDGI_QUALITY >= 1)
+
 #define USE_SKY_CUBE_TEX 1
 
 static const float SKY_DIST = 1e4;
@@ -125,16 +133,47 @@ TraceResult do_the_thing(uint2 px, float3 normal_ws, inout uint rng, RayDesc out
                     new_ray(
                         primary_hit.position,
                         to_light_norm,
-                        1e-4,
+                        world_units(1e-4),
                         SKY_DIST
                 ));
 
             const float3 wi = mul(to_light_norm, tangent_to_world);
-            const float3 brdf_value = brdf.evaluate(wo, wi) * max(0.0, wi.z);
+            const float3 brdf_value = brdf.evaluate(wo, wi) * max(0.0, wi.z)
+                + brdf.evaluate_transmission(wi) * max(0.0, -wi.z);
             const float3 light_radiance = select(is_shadowed, 0.0, sun_radiance);
             total_radiance += brdf_value * light_radiance;
         }
 
+        // Point lights aren't visible in the reprojected irradiance, so always shade them here.
+        if (USE_LIGHTS) {
+            const float2 urand = float2(
+                uint_to_u01_float(hash1_mut(rng)),
+                uint_to_u01_float(hash1_mut(rng))
+            );
+
+            for (uint light_idx = 0; light_idx < frame_constants.point_light_count; light_idx += 1) {
+                const PointLight point_light = PointLight::from_packed(point_lights_dyn[light_idx]);
+                const PointLightSample light_sample = sample_point_light(point_light, primary_hit.position, urand);
+                const float3 wi = mul(light_sample.dir, tangent_to_world);
+
+                if (wi.z != 0.0 && any(light_sample.irradiance > 0.0)) {
+                    const bool is_shadowed =
+                        rt_is_shadowed(
+                            acceleration_structure,
+                            new_ray(
+                                primary_hit.position,
+                                light_sample.dir,
+                                world_units(1e-4),
+                                light_sample.dist - world_units(2e-3)
+                        ));
+
+                    const float3 brdf_value = brdf.evaluate(wo, wi) * max(0.0, wi.z)
+                        + brdf.evaluate_transmission(wi) * max(0.0, -wi.z);
+                    total_radiance += select(is_shadowed, 0.0, brdf_value * light_sample.irradiance);
+                }
+            }
+        }
+
         if (USE_EMISSIVE) {
             total_radiance += gbuffer.emissive;
         }
@@ -142,13 +181,21 @@ TraceResult do_the_thing(uint2 px, float3 normal_ws, inout uint rng, RayDesc out
         if (USE_SCREEN_GI_REPROJECTION && is_on_screen) {
             total_radiance += reprojected_radiance.rgb * gbuffer.albedo;
         } else {
-            if (USE_LIGHTS) {
+            if (USE_LIGHTS && USE_OFF_SCREEN_TRIANGLE_LIGHTS && frame_constants.triangle_light_count > 0) {
+                // One shadow ray per hit, towards a light picked proportionally to its power,
+                // so that scenes with many emissive triangles stay affordable.
+                const TriangleLightSelection light_selection = select_triangle_light(float2(
+                    uint_to_u01_float(hash1_mut(rng)),
+                    uint_to_u01_float(hash1_mut(rng))
+                ));
+
                 float2 urand = float2(
                     uint_to_u01_float(hash1_mut(rng)),
                     uint_to_u01_float(hash1_mut(rng))
                 );
 
-                for (uint light_idx = 0; light_idx < frame_constants.triangle_light_count; light_idx += 1) {
+                {
+                    const uint light_idx = light_selection.light_idx;
                     TriangleLight triangle_light = TriangleLight::from_packed(triangle_lights_dyn[light_idx]);
                     LightSampleResultArea light_sample = sample_triangle_light(triangle_light.as_triangle(), urand);
                     const float3 shadow_ray_origin = primary_hit.position;
@@ -168,8 +215,8 @@ TraceResult do_the_thing(uint2 px, float3 normal_ws, inout uint rng, RayDesc out
                                 new_ray(
                                     shadow_ray_origin,
                                     to_light_norm_ws,
-                                    1e-3,
-                                    sqrt(dist_to_light2) - 2e-3
+                                    world_units(1e-3),
+                                    sqrt(dist_to_light2) - world_units(2e-3)
                             ));
 
                         #if 1
@@ -181,7 +228,7 @@ TraceResult do_the_thing(uint2 px, float3 normal_ws, inout uint rng, RayDesc out
                         #endif
 
                         total_radiance +=
-                            select(!is_shadowed, (triangle_light.radiance() * brdf_value / light_sample.pdf.value), 0);
+                            select(!is_shadowed, (triangle_light.radiance() * brdf_value / light_sample.pdf.value / light_selection.pmf), 0);
                     }
                 }
             }
@@ -194,7 +241,7 @@ TraceResult do_the_thing(uint2 px, float3 normal_ws, inout uint rng, RayDesc out
                     .with_query_rank(1)
                     .lookup(rng);
 
-                total_radiance += gi * gbuffer.albedo;
+                total_radiance += gi * gbuffer.albedo * gbuffer.gi_reception;
             }
         }
     } else {
@@ -1,3 +1,6 @@
+// Diffuse GI rays skip instances flagged with `InstanceFlags::NO_GI_CONTRIBUTION`.
+#define RT_INSTANCE_INCLUSION_MASK 0x01
+
 #include "../inc/uv.hlsl"
 #include "../inc/pack_unpack.hlsl"
 #include "../inc/frame_constants.hlsl"
@@ -10,6 +13,7 @@
 #include "../inc/atmosphere.hlsl"
 #include "../inc/sun.hlsl"
 #include "../inc/lights/triangle.hlsl"
+#include "../inc/lights/point.hlsl"
 #include "../inc/reservoir.hlsl"
 #include "../ircache/bindings.hlsl"
 #include "../wrc/bindings.hlsl"
@@ -43,8 +47,7 @@ DEFINE_WRC_BINDINGS(15)
 #include "diffuse_trace_common.inc.hlsl"
 
 
-[shader("raygeneration")]
-void main() {
+RAYGEN_MAIN {
     const uint2 px = DispatchRaysIndex().xy;
     const int2 hi_px_offset = HALFRES_SUBSAMPLE_OFFSET;
     const uint2 hi_px = px * 2 + hi_px_offset;
@@ -1,3 +1,6 @@
+// Diffuse GI rays skip instances flagged with `InstanceFlags::NO_GI_CONTRIBUTION`.
+#define RT_INSTANCE_INCLUSION_MASK 0x01
+
 #include "../inc/uv.hlsl"
 #include "../inc/frame_constants.hlsl"
 #include "../inc/rt.hlsl"
@@ -18,8 +21,7 @@ uint2 reservoir_payload_to_px(uint payload) {
     return uint2(payload & 0xffff, payload >> 16);
 }
 
-[shader("raygeneration")]
-void main() {
+RAYGEN_MAIN {
     const uint2 px = DispatchRaysIndex().xy;
     const int2 hi_px_offset = HALFRES_SUBSAMPLE_OFFSET;
     const uint2 hi_px = px * 2 + hi_px_offset;
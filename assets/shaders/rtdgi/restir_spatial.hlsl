@@ -29,6 +29,11 @@
     // Only done in the last spatial resampling pass
     uint perform_occlusion_raymarch;
     uint occlusion_raymarch_importance_only;
+    // `RtdgiRenderer::spatial_reuse_sample_count`
+    uint sample_count_pass0;
+    uint sample_count_pass1;
+    // `RtdgiRenderer::temporal_reservoir_m_clamp`
+    float temporal_m_clamp;
 };
 
 #define USE_SSAO_WEIGHING 1
@@ -72,10 +77,10 @@ void main(uint2 px : SV_DispatchThreadID) {
 
     float kernel_tightness = 1.0 - center_ssao;
 
-    const uint SAMPLE_COUNT_PASS0 = 8;
-    const uint SAMPLE_COUNT_PASS1 = 5;
+    const uint SAMPLE_COUNT_PASS0 = sample_count_pass0;
+    const uint SAMPLE_COUNT_PASS1 = sample_count_pass1;
 
-    const float MAX_INPUT_M_IN_PASS0 = RESTIR_TEMPORAL_M_CLAMP;
+    const float MAX_INPUT_M_IN_PASS0 = temporal_m_clamp;
     const float MAX_INPUT_M_IN_PASS1 = MAX_INPUT_M_IN_PASS0 * SAMPLE_COUNT_PASS0;
     const float MAX_INPUT_M_IN_PASS = select(spatial_reuse_pass_idx == 0, MAX_INPUT_M_IN_PASS0, MAX_INPUT_M_IN_PASS1);
 
@@ -37,6 +37,8 @@
 [[vk::binding(19)]] RWTexture2D<uint4> temporal_reservoir_packed_tex;
 [[vk::binding(20)]] cbuffer _ {
     float4 gbuffer_tex_size;
+    // `RtdgiRenderer::temporal_reservoir_m_clamp`
+    float temporal_m_clamp;
 };
 
 static const float SKY_DIST = 1e4;
@@ -177,7 +179,7 @@ void main(uint2 px : SV_DispatchThreadID) {
             sample_i < MAX_RESOLVE_SAMPLE_COUNT
             // Use permutation sampling, but only up to a certain M; those are lower quality,
             // so we want to be rather conservative.
-            && stream_state.M_sum < 1.25 * RESTIR_TEMPORAL_M_CLAMP;
+            && stream_state.M_sum < 1.25 * temporal_m_clamp;
             ++sample_i) {
             const int2 rpx_offset = get_rpx_offset(sample_i, frame_constants.frame_index);
             if (sample_i > 0 && all(rpx_offset == 0)) {
@@ -325,8 +327,9 @@ void main(uint2 px : SV_DispatchThreadID) {
             // resampling. To fix this, we simply clamp the previous frame’s M
             // to at most 20× of the current frame’s reservoir’s M
 
-            r.M = max(0, min(r.M, exp2(log2(RESTIR_TEMPORAL_M_CLAMP) * (1.0 - rt_invalidity))));
-            //r.M = min(r.M, RESTIR_TEMPORAL_M_CLAMP);
+            const float m_clamp = max(1.0, temporal_m_clamp * frame_constants.gi_history_scale);
+            r.M = max(0, min(r.M, exp2(log2(m_clamp) * (1.0 - rt_invalidity))));
+            //r.M = min(r.M, temporal_m_clamp);
             //r.M = min(r.M, 0.1);
 
             const float p_q = 1
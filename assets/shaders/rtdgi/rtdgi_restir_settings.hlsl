@@ -1,5 +1,7 @@
 #define DIFFUSE_GI_USE_RESTIR 1
-#define RESTIR_TEMPORAL_M_CLAMP 20.0
+
+// The temporal M clamp, spatial sample counts, and visibility checks are set
+// on the CPU; see `RtdgiRenderer`.
 
 // Reduces fireflies, but causes darkening in corners
 #define RESTIR_RESERVOIR_W_CLAMP 10.0
@@ -195,6 +195,7 @@ void main(uint2 px: SV_DispatchThreadID) {
     max_sample_count = lerp(max_sample_count, 4, variance_adjusted_temporal_change);
     //max_sample_count = lerp(max_sample_count, 1, smoothstep(0.01, 0.6, 10 * temporal_change * (center_dev / max(1e-5, center_luma))));
     max_sample_count *= lerp(1.0, 0.5, rt_invalid);
+    max_sample_count = max(1.0, max_sample_count * frame_constants.gi_history_scale);
 
 // hax
 //max_sample_count = 32;
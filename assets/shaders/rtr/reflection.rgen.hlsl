@@ -10,6 +10,8 @@
 #include "../inc/atmosphere.hlsl"
 #include "../inc/sun.hlsl"
 #include "../inc/lights/triangle.hlsl"
+#include "../inc/lights/point.hlsl"
+#include "../inc/reflection_probes.hlsl"
 #include "../ircache/bindings.hlsl"
 #include "../wrc/bindings.hlsl"
 #include "rtr_settings.hlsl"
@@ -27,9 +29,12 @@ DEFINE_WRC_BINDINGS(16)
 [[vk::binding(18)]] RWTexture2D<float4> out1_tex;
 [[vk::binding(19)]] RWTexture2D<float4> out2_tex;
 [[vk::binding(20)]] RWTexture2D<uint> rng_out_tex;
-[[vk::binding(21)]] cbuffer _ {
+[[vk::binding(21)]] TextureCubeArray<float4> reflection_probe_cubes_tex;
+[[vk::binding(22)]] cbuffer _ {
     float4 gbuffer_tex_size;
     uint reuse_rtdgi_rays;
+    uint3 _pad;
+    ReflectionProbeConstants reflection_probes;
 };
 
 //#define IRCACHE_LOOKUP_KEEP_ALIVE_PROB 0.125
@@ -38,8 +43,7 @@ DEFINE_WRC_BINDINGS(16)
 
 #include "reflection_trace_common.inc.hlsl"
 
-[shader("raygeneration")]
-void main() {
+RAYGEN_MAIN {
     const uint2 px = DispatchRaysIndex().xy;
     const uint2 hi_px = px * 2 + HALFRES_SUBSAMPLE_OFFSET;
     float depth = depth_tex[hi_px];
@@ -2,14 +2,19 @@
 // the hit points/vectors fit within fp16.
 static const float SKY_DIST = 1e4;
 
-#define USE_SOFT_SHADOWS 1
+// Set by `RtrRenderer` from `QualityConfig::rtr`: 0 = low, 1 = medium, 2 = high
+#ifndef RTR_QUALITY
+    #define RTR_QUALITY 1
+#endif
+
+#define USE_SOFT_SHADOWS (RTR_QUALITY >= 1)
 #define USE_SOFT_SHADOWS_TEMPORAL_JITTER 0
 
 #define USE_TEMPORAL_JITTER 1
 
 // Should be off then iterating on reflections,
 // but might be a good idea to enable for shipping anything.
-#define USE_HEAVY_BIAS 1
+#define USE_HEAVY_BIAS (RTR_QUALITY <= 1)
 
 #define USE_WORLD_RADIANCE_CACHE 0
 #define LOWEST_ROUGHNESS_FOR_RADIANCE_CACHE 0.5
@@ -20,6 +25,9 @@ static const float SKY_DIST = 1e4;
 #define USE_EMISSIVE 1
 #define USE_LIGHTS 1
 
+// Without it, hits outside of the screen only get triangle lights through the irradiance cache.
+#define USE_OFF_SCREEN_TRIANGLE_LIGHTS (RTR_QUALITY >= 1)
+
 // Debug bias in sample reuse with position-based hit storage
 #define COLOR_CODE_GROUND_SKY_BLACK_WHITE 0
 
@@ -133,17 +141,48 @@ RtrTraceResult do_the_thing(uint2 px, float3 normal_ws, float roughness, inout u
                             new_ray(
                                 primary_hit.position,
                                 to_light_norm,
-                                1e-4,
+                                world_units(1e-4),
                                 SKY_DIST
                         ));
 
                     const float3 wi = mul(to_light_norm, tangent_to_world);
 
-                    const float3 brdf_value = brdf.evaluate(wo, wi) * max(0.0, wi.z);
+                    const float3 brdf_value = brdf.evaluate(wo, wi) * max(0.0, wi.z)
+                        + brdf.evaluate_transmission(wi) * max(0.0, -wi.z);
                     const float3 light_radiance = select(is_shadowed, 0.0, SUN_COLOR);
                     total_radiance += brdf_value * light_radiance;
                 }
 
+                // Point lights aren't visible in the reprojected irradiance, so always shade them here.
+                if (USE_LIGHTS) {
+                    const float2 urand = float2(
+                        uint_to_u01_float(hash1_mut(rng)),
+                        uint_to_u01_float(hash1_mut(rng))
+                    );
+
+                    for (uint light_idx = 0; light_idx < frame_constants.point_light_count; light_idx += 1) {
+                        const PointLight point_light = PointLight::from_packed(point_lights_dyn[light_idx]);
+                        const PointLightSample light_sample = sample_point_light(point_light, primary_hit.position, urand);
+                        const float3 wi = mul(light_sample.dir, tangent_to_world);
+
+                        if (wi.z != 0.0 && any(light_sample.irradiance > 0.0)) {
+                            const bool is_shadowed =
+                                rt_is_shadowed(
+                                    acceleration_structure,
+                                    new_ray(
+                                        primary_hit.position,
+                                        light_sample.dir,
+                                        world_units(1e-4),
+                                        light_sample.dist - 2e-4
+                                ));
+
+                            const float3 brdf_value = brdf.evaluate(wo, wi) * max(0.0, wi.z)
+                                + brdf.evaluate_transmission(wi) * max(0.0, -wi.z);
+                            total_radiance += select(is_shadowed, 0.0, brdf_value * light_sample.irradiance);
+                        }
+                    }
+                }
+
                 reflected_normal_vs = direction_world_to_view(gbuffer.normal);
 
                 if (USE_EMISSIVE) {
@@ -157,7 +196,7 @@ RtrTraceResult do_the_thing(uint2 px, float3 normal_ws, float roughness, inout u
 
                     total_radiance += reprojected_radiance.rgb * gbuffer.albedo;
                 } else {
-                    if (USE_LIGHTS) {
+                    if (USE_LIGHTS && USE_OFF_SCREEN_TRIANGLE_LIGHTS) {
                         float2 urand = float2(
                             uint_to_u01_float(hash1_mut(rng)),
                             uint_to_u01_float(hash1_mut(rng))
@@ -183,7 +222,7 @@ RtrTraceResult do_the_thing(uint2 px, float3 normal_ws, float roughness, inout u
                                         new_ray(
                                             shadow_ray_origin,
                                             to_light_norm_ws,
-                                            1e-4,
+                                            world_units(1e-4),
                                             sqrt(dist_to_light2) - 2e-4
                                     ));
 
@@ -242,6 +281,18 @@ RtrTraceResult do_the_thing(uint2 px, float3 normal_ws, float roughness, inout u
         hit_t = far_field.approx_surface_t;
     } else {
         far_gi = sky_cube_tex.SampleLevel(sampler_llr, outgoing_ray.Direction, 0).rgb;
+
+        // Local probes capture what the rays can't reach.
+        // The ray direction is already spread over the BRDF lobe, so only use
+        // part of its roughness for the probe's prefiltering, to reduce noise.
+        const float4 probe_radiance = sample_reflection_probes(
+            reflection_probe_cubes_tex,
+            reflection_probes,
+            outgoing_ray.Origin,
+            outgoing_ray.Direction,
+            0.5 * sqrt(roughness));
+
+        far_gi = lerp(far_gi, probe_radiance.rgb * frame_constants.pre_exposure, probe_radiance.a);
     }
 
     #if COLOR_CODE_GROUND_SKY_BLACK_WHITE
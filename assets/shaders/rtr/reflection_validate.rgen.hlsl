@@ -10,7 +10,9 @@
 #include "../inc/atmosphere.hlsl"
 #include "../inc/sun.hlsl"
 #include "../inc/lights/triangle.hlsl"
+#include "../inc/lights/point.hlsl"
 #include "../inc/reservoir.hlsl"
+#include "../inc/reflection_probes.hlsl"
 #include "../ircache/bindings.hlsl"
 #include "../wrc/bindings.hlsl"
 #include "rtr_settings.hlsl"
@@ -29,8 +31,10 @@ DEFINE_WRC_BINDINGS(14)
 [[vk::binding(17)]] Texture2D<uint> rng_history_tex;
 [[vk::binding(18)]] RWTexture2D<float4> irradiance_history_tex;
 [[vk::binding(19)]] RWTexture2D<uint2> reservoir_history_tex;
-[[vk::binding(20)]] cbuffer _ {
+[[vk::binding(20)]] TextureCubeArray<float4> reflection_probe_cubes_tex;
+[[vk::binding(21)]] cbuffer _ {
     float4 gbuffer_tex_size;
+    ReflectionProbeConstants reflection_probes;
 };
 
 //#define IRCACHE_LOOKUP_KEEP_ALIVE_PROB 0.125
@@ -39,8 +43,7 @@ DEFINE_WRC_BINDINGS(14)
 
 #include "reflection_trace_common.inc.hlsl"
 
-[shader("raygeneration")]
-void main() {
+RAYGEN_MAIN {
     if (!RTR_RESTIR_USE_PATH_VALIDATION) {
         return;
     }
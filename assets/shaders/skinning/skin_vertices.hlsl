@@ -0,0 +1,55 @@
+// Deforms the vertices of a skinned mesh instance into its own region of the vertex buffer.
+
+#include "../inc/math.hlsl"
+#include "../inc/mesh.hlsl"
+
+struct JointTransform {
+    row_major float3x4 xform;
+};
+
+[[vk::binding(0)]] RWByteAddressBuffer vertices;
+[[vk::binding(1)]] StructuredBuffer<JointTransform> joint_transforms_dyn;
+[[vk::binding(2)]] cbuffer _ {
+    uint vertex_count;
+    uint src_core_offset;
+    uint src_tangent_offset;
+    uint dst_core_offset;
+    // Zero if the mesh has no tangents
+    uint dst_tangent_offset;
+    uint joint_offset;
+    uint weight_offset;
+};
+
+[numthreads(64, 1, 1)]
+void main(uint vid: SV_DispatchThreadID) {
+    if (vid >= vertex_count) {
+        return;
+    }
+
+    // Four 16-bit joint indices per vertex
+    const uint2 joints_packed = vertices.Load2(joint_offset + vid * sizeof(uint2));
+    const uint4 joints = uint4(
+        joints_packed.x & 0xffff,
+        joints_packed.x >> 16,
+        joints_packed.y & 0xffff,
+        joints_packed.y >> 16
+    );
+    const float4 weights = asfloat(vertices.Load4(weight_offset + vid * sizeof(float4)));
+
+    const float3x4 xform =
+        weights.x * joint_transforms_dyn[joints.x].xform
+        + weights.y * joint_transforms_dyn[joints.y].xform
+        + weights.z * joint_transforms_dyn[joints.z].xform
+        + weights.w * joint_transforms_dyn[joints.w].xform;
+
+    Vertex v = unpack_vertex(VertexPacked(asfloat(vertices.Load4(src_core_offset + vid * sizeof(float4)))));
+    v.position = mul(xform, float4(v.position, 1.0));
+    v.normal = normalize(transform_normal(xform, v.normal));
+    vertices.Store4(dst_core_offset + vid * sizeof(float4), asuint(pack_vertex(v).data0));
+
+    if (dst_tangent_offset != 0) {
+        float4 tangent = asfloat(vertices.Load4(src_tangent_offset + vid * sizeof(float4)));
+        tangent.xyz = normalize(mul(xform, float4(tangent.xyz, 0.0)));
+        vertices.Store4(dst_tangent_offset + vid * sizeof(float4), asuint(tangent));
+    }
+}
@@ -0,0 +1,48 @@
+#include "../inc/math.hlsl"
+#include "../inc/frame_constants.hlsl"
+#include "../inc/gbuffer.hlsl"
+#include "../inc/rt.hlsl"
+#include "../inc/sky_visibility.hlsl"
+
+[[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
+
+[[vk::binding(0)]] RWTexture2D<float> heightmap_tex;
+[[vk::binding(1)]] cbuffer _ {
+    int2 origin_cell;
+    int2 prev_origin_cell;
+    float cell_size;
+    float ray_start_height;
+    uint frame_index;
+    uint history_valid;
+};
+
+// Finds the height of the top-most surface in a cell of the sky visibility field.
+[shader("raygeneration")]
+void main() {
+    const uint2 px = DispatchRaysIndex().xy;
+
+    const int2 cell = sky_visibility_texel_to_cell(px, origin_cell);
+    const int2 prev_cell = sky_visibility_texel_to_cell(px, prev_origin_cell);
+
+    // Cells already in the field are refreshed a quarter at a time; ones just entering it right away.
+    const bool is_refresh_frame = ((px.x & 1) | ((px.y & 1) << 1)) == (frame_index & 3);
+    if (history_valid != 0 && all(cell == prev_cell) && !is_refresh_frame) {
+        return;
+    }
+
+    const float2 cell_center = (float2(cell) + 0.5) * cell_size;
+    const RayDesc ray = new_ray(
+        float3(cell_center.x, ray_start_height, cell_center.y),
+        float3(0.0, -1.0, 0.0),
+        0.0,
+        FLT_MAX
+    );
+
+    const GbufferPathVertex hit = GbufferRaytrace::with_ray(ray)
+        .with_cone(RayCone::from_spread_angle(0.0))
+        .with_cull_back_faces(false)
+        .with_path_length(1)
+        .trace(acceleration_structure);
+
+    heightmap_tex[px] = hit.is_hit ? hit.position.y : SKY_VISIBILITY_NO_HIT;
+}
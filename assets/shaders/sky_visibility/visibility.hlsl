@@ -0,0 +1,59 @@
+#include "../inc/math.hlsl"
+#include "../inc/sky_visibility.hlsl"
+
+[[vk::binding(0)]] Texture2D<float> heightmap_tex;
+[[vk::binding(1)]] RWTexture2D<float> output_tex;
+[[vk::binding(2)]] cbuffer _ {
+    int2 origin_cell;
+    float cell_size;
+};
+
+#define DIRECTION_COUNT 8
+#define STEPS_PER_DIRECTION 8
+
+// Estimates the fraction of the sky visible from the top of each cell by finding
+// the horizon in a number of directions across the heightmap.
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float height = heightmap_tex[px];
+
+    if (height == SKY_VISIBILITY_NO_HIT) {
+        output_tex[px] = 1.0;
+        return;
+    }
+
+    const int2 cell = sky_visibility_texel_to_cell(px, origin_cell);
+    const int2 field_end = origin_cell + SKY_VISIBILITY_RESOLUTION;
+
+    float visibility_sum = 0.0;
+
+    for (uint dir_idx = 0; dir_idx < DIRECTION_COUNT; ++dir_idx) {
+        const float angle = (dir_idx + 0.5) * M_TAU / DIRECTION_COUNT;
+        const float2 dir = float2(cos(angle), sin(angle));
+
+        float max_sin_horizon = 0.0;
+
+        // Exponentially spaced steps: fine detail up close, and distant ridges further out.
+        for (uint step_idx = 0; step_idx < STEPS_PER_DIRECTION; ++step_idx) {
+            const float step_cells = float(1u << step_idx);
+            const int2 sample_cell = cell + int2(round(dir * step_cells));
+
+            if (any(sample_cell < origin_cell) || any(sample_cell >= field_end)) {
+                break;
+            }
+
+            const float sample_height = heightmap_tex[sky_visibility_cell_to_texel(sample_cell)];
+            const float dh = sample_height - height;
+
+            if (dh > 0.0) {
+                const float dist = step_cells * cell_size;
+                max_sin_horizon = max(max_sin_horizon, dh * rsqrt(dh * dh + dist * dist));
+            }
+        }
+
+        // Cosine-weighted visibility of the sky above a horizon, for an upward-facing surface.
+        visibility_sum += 1.0 - max_sin_horizon * max_sin_horizon;
+    }
+
+    output_tex[px] = visibility_sum / DIRECTION_COUNT;
+}
@@ -0,0 +1,26 @@
+[[vk::binding(0)]] Texture2D<float> depth_tex;
+[[vk::binding(1)]] Texture2D<float> ssao_tex;
+[[vk::binding(2)]] Texture2D<float> rtao_tex;
+[[vk::binding(3)]] Texture2D<float4> velocity_tex;
+[[vk::binding(4)]] RWTexture2D<float> output_tex;
+[[vk::binding(5)]] cbuffer _ {
+    uint use_rtao;
+};
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    if (depth_tex[px] == 0.0) {
+        output_tex[px] = 1.0;
+        return;
+    }
+
+    // Written by the raster pass in lieu of motion vector alpha
+    const float ao_strength = max(0.0, velocity_tex[px].w);
+    float ssao = ssao_tex[px];
+    if (use_rtao) {
+        ssao *= rtao_tex[px];
+    }
+
+    // Same as glTF's `occlusionTexture.strength`
+    output_tex[px] = saturate(1.0 + ao_strength * (ssao - 1.0));
+}
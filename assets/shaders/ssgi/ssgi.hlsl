@@ -21,6 +21,11 @@
     float4 output_tex_size;
 };
 
+// Set by `SsgiRenderer` from `QualityConfig::ssgi`: 0 = low, 1 = medium, 2 = high
+#ifndef SSGI_QUALITY
+    #define SSGI_QUALITY 1
+#endif
+
 #ifndef SSGI_FULLRES
     #define USE_AO_ONLY 1
     #define USE_SSGI_FACING_CORRECTION 1
@@ -28,7 +33,13 @@
     #if 1
         // Micro-occlusion settings used for denoising
 
-        static const uint SSGI_HALF_SAMPLE_COUNT = 6;
+        #if SSGI_QUALITY == 0
+            static const uint SSGI_HALF_SAMPLE_COUNT = 3;
+        #elif SSGI_QUALITY == 1
+            static const uint SSGI_HALF_SAMPLE_COUNT = 6;
+        #else
+            static const uint SSGI_HALF_SAMPLE_COUNT = 10;
+        #endif
         #define SSGI_KERNEL_RADIUS (60.0 * output_tex_size.w)
         #define MAX_KERNEL_RADIUS_CS 0.4
         #define USE_KERNEL_DISTANCE_SCALING 0
@@ -0,0 +1,18 @@
+#include "../inc/color.hlsl"
+#include "../inc/gbuffer.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> gbuffer_tex;
+[[vk::binding(1)]] Texture2D<float4> lit_tex;
+[[vk::binding(2)]] RWTexture2D<float> output_tex;
+
+// Fraction of the radiance of each pixel which is emitted rather than reflected.
+// TAA resolves such pixels in a luminance-preserving way, and clamps their history less,
+// so that small bright emitters don't flicker or fade at high upscaling ratios.
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float3 emissive = GbufferDataPacked::from_uint4(asuint(gbuffer_tex[px])).unpack_emissive();
+    const float emissive_lum = sRGB_to_luminance(emissive);
+    const float total_lum = sRGB_to_luminance(lit_tex[px].rgb);
+
+    output_tex[px] = saturate(emissive_lum / max(1e-5, total_lum));
+}
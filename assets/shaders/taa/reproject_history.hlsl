@@ -13,10 +13,12 @@
 [[vk::binding(5)]] cbuffer _ {
     float4 input_tex_size;
     float4 output_tex_size;
+    uint dilation_radius;
+    uint always_dilate;
 };
 
 // Optimization: Try to skip velocity dilation if velocity diff is small
-// around the pixel.
+// around the pixel. Can be overridden at runtime via `always_dilate`.
 #define APPROX_SKIP_DILATION true
 
 float4 fetch_history(float2 uv) {
@@ -80,6 +82,7 @@ void main(
     should_dilate |= WaveReadLaneAt(should_dilate, WaveGetLaneIndex() ^ 2);
     //should_dilate |= WaveReadLaneAt(should_dilate, WaveGetLaneIndex() ^ 8);
     should_dilate |= WaveReadLaneAt(should_dilate, WaveGetLaneIndex() ^ 16);
+    should_dilate |= always_dilate != 0;
 
     // We want to find the velocity of the pixel which is closest to the camera,
     // which is critical to anti-aliased moving edges.
@@ -92,7 +95,7 @@ void main(
 #endif
     {
         float reproj_depth = depth_tex[reproj_px];
-        int k = 1;
+        const int k = dilation_radius;
         for (int y = -k; y <= k; ++y) {
             for (int x = -k; x <= k; ++x) {
                 float d = depth_tex[reproj_px + int2(x, y)];
@@ -15,13 +15,22 @@
 [[vk::binding(5)]] Texture2D<float> depth_tex;
 [[vk::binding(6)]] Texture2D<float3> smooth_var_history_tex;
 [[vk::binding(7)]] Texture2D<float> input_prob_tex;
-[[vk::binding(8)]] RWTexture2D<float4> temporal_output_tex;
-[[vk::binding(9)]] RWTexture2D<float4> output_tex;
-[[vk::binding(10)]] RWTexture2D<float3> smooth_var_output_tex;
-[[vk::binding(11)]] RWTexture2D<float2> velocity_output_tex;
-[[vk::binding(12)]] cbuffer _ {
+[[vk::binding(8)]] Texture2D<float> reactive_mask_tex;
+[[vk::binding(9)]] RWTexture2D<float4> temporal_output_tex;
+[[vk::binding(10)]] RWTexture2D<float4> output_tex;
+[[vk::binding(11)]] RWTexture2D<float3> smooth_var_output_tex;
+[[vk::binding(12)]] RWTexture2D<float2> velocity_output_tex;
+struct ResponsiveRegion {
+    float4 uv_min_max;
+    float history_weight;
+    float max_velocity_pixels;
+    float2 pad;
+};
+[[vk::binding(13)]] StructuredBuffer<ResponsiveRegion> responsive_regions_dyn;
+[[vk::binding(14)]] cbuffer _ {
     float4 input_tex_size;
     float4 output_tex_size;
+    uint responsive_region_count;
 };
 
 // Apply at spatial kernel to the current frame, "un-jittering" it.
@@ -80,6 +89,23 @@ float4 fetch_blurred_history(int2 px, int k, float sigma) {
     return csum / wsum;
 }
 
+// Scale of history coverage for camera-locked pixels in the responsive regions
+// set by the application (first-person weapons, cockpits).
+float responsive_history_weight(float2 uv, float velocity_pixels) {
+    float weight = 1;
+
+    for (uint i = 0; i < responsive_region_count; ++i) {
+        const ResponsiveRegion region = responsive_regions_dyn[i];
+        const bool in_region = all(uv >= region.uv_min_max.xy) && all(uv <= region.uv_min_max.zw);
+
+        if (in_region && velocity_pixels <= region.max_velocity_pixels) {
+            weight = min(weight, region.history_weight);
+        }
+    }
+
+    return weight;
+}
+
 struct HistoryRemap {
     static HistoryRemap create() {
         HistoryRemap res;
@@ -164,6 +190,17 @@ void main(uint2 px: SV_DispatchThreadID) {
 
     const float input_prob = input_prob_tex[reproj_px];
 
+    // Emissive content, dilated to cover emitters moving between pixels with the jitter.
+    float reactive = 0;
+    {
+        const int k = 1;
+        for (int y = -k; y <= k; ++y) {
+            for (int x = -k; x <= k; ++x) {
+                reactive = max(reactive, reactive_mask_tex[int2(reproj_px) + int2(x, y)]);
+            }
+        }
+    }
+
     float3 ex = center_sample.ex;
     float3 ex2 = center_sample.ex2;
     const float3 var = max(0.0.xxx, ex2 - ex * ex);
@@ -198,6 +235,10 @@ void main(uint2 px: SV_DispatchThreadID) {
             box_n_deviations = lerp(box_n_deviations, 3, input_prob);
         }
 
+        // Small emitters can be missing from the neighborhood in some frames;
+        // don't let that clamp them out of history.
+        box_n_deviations = lerp(box_n_deviations, 4, reactive);
+
     	float3 nmin = ex - input_dev * box_n_deviations;
     	float3 nmax = ex + input_dev * box_n_deviations;
 
@@ -303,6 +344,8 @@ void main(uint2 px: SV_DispatchThreadID) {
     }
 
 
+        history_coverage *= responsive_history_weight(uv, length(reproj_xy * output_tex_size.xy));
+
     #if RESET_ACCUMULATION
         history_coverage = 0;
     #endif
@@ -310,6 +353,16 @@ void main(uint2 px: SV_DispatchThreadID) {
         float total_coverage = max(1e-5, history_coverage + coverage);
         float3 temporal_result = (clamped_history * history_coverage + center) / total_coverage;
 
+        // Averaging in the perceptual space loses energy of emitters which flicker between
+        // pixels; blend emissive content in linear space instead.
+        if (reactive > 0) {
+            const float3 linear_history = encode_rgb(YCbCr_to_sRGB(clamped_history));
+            const float3 linear_center = encode_rgb(YCbCr_to_sRGB(center / max(1e-5, coverage)));
+            const float3 linear_result = (linear_history * history_coverage + linear_center * coverage) / total_coverage;
+
+            temporal_result = lerp(temporal_result, sRGB_to_YCbCr(decode_rgb(linear_result)), reactive);
+        }
+
         const float max_coverage = max(2, TARGET_SAMPLE_COUNT / (input_resolution_fraction.x * input_resolution_fraction.y));
 
         total_coverage = min(max_coverage, total_coverage);
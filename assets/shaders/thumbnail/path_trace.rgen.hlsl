@@ -0,0 +1,46 @@
+// Path traces the instances being captured by `renderers/thumbnail.rs` from the thumbnail's
+// own camera, reusing the reference path tracer. Other instances are masked out of the TLAS,
+// and triangle lights are skipped since they may belong to the rest of the scene.
+//
+// Accumulates radiance without the frame's pre-exposure, so that the result is independent
+// of the exposure of the main view.
+
+#define RT_INSTANCE_INCLUSION_MASK 0x80
+#define PATH_TRACE_USE_LIGHTS false
+#define PATH_TRACE_USE_ENVIRONMENT_MAP 0
+#define PATH_TRACE_SAMPLES_PER_PIXEL 16
+#define PATH_TRACE_CUSTOM_VIEW
+
+#include "../inc/frame_constants.hlsl"
+#include "../inc/hash.hlsl"
+#include "../inc/rt.hlsl"
+
+[[vk::binding(1)]] cbuffer _ {
+    float4 eye_position;
+    // Scaled by the tangent of the half field of view, and the aspect ratio
+    float4 eye_right;
+    float4 eye_up;
+    float4 eye_forward;
+    uint capture_id;
+    uint frame_index;
+};
+
+RayDesc path_trace_camera_ray(float2 uv) {
+    const float2 ndc = uv_to_cs(uv);
+    return new_ray(
+        eye_position.xyz,
+        normalize(eye_forward.xyz + eye_right.xyz * ndc.x + eye_up.xyz * ndc.y),
+        0.0,
+        FLT_MAX
+    );
+}
+
+uint path_trace_seed() {
+    return hash_combine2(capture_id, frame_index);
+}
+
+float path_trace_radiance_scale() {
+    return 1.0 / frame_constants.pre_exposure;
+}
+
+#include "../rt/reference_path_trace.rgen.hlsl"
@@ -0,0 +1,36 @@
+// Tonemaps the accumulated thumbnail at a fixed exposure, and packs it into
+// sRGB RGBA8 for read-back by `renderers/thumbnail.rs`.
+
+#include "../inc/samplers.hlsl"
+#include "../inc/bindless_textures.hlsl"
+#include "../inc/color/srgb.hlsl"
+
+#define DECLARE_BEZOLD_BRUCKE_LUT
+static float2 SAMPLE_BEZOLD_BRUCKE_LUT(float coord) {
+    return bindless_textures[BINDLESS_LUT_BEZOLD_BRUCKE].SampleLevel(sampler_llr, float2(coord, 0.5), 0).xy;
+}
+#include "../inc/color/display_transform.hlsl"
+
+[[vk::binding(0)]] Texture2D<float4> accum_tex;
+[[vk::binding(1)]] RWByteAddressBuffer output_buf;
+[[vk::binding(2)]] cbuffer _ {
+    uint2 extent;
+    float exposure_mult;
+};
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    if (any(px >= extent)) {
+        return;
+    }
+
+    float3 col = accum_tex[px].rgb * exposure_mult;
+    col = display_transform_sRGB(col);
+    col = sRGB_OETF(saturate(col));
+
+    const uint4 rgba8 = uint4(round(col * 255.0), 255);
+    output_buf.Store(
+        (px.x + px.y * extent.x) * 4,
+        rgba8.r | (rgba8.g << 8) | (rgba8.b << 16) | (rgba8.a << 24)
+    );
+}
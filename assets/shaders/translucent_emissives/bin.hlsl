@@ -0,0 +1,86 @@
+// Lists the emissives overlapping each cluster. Each emissive is only listed in the nearest
+// slice it overlaps within a tile, so that pixels can walk the slices in front of the opaque
+// geometry without visiting any of them twice.
+
+#include "clusters.hlsl"
+
+[[vk::binding(0)]] RWStructuredBuffer<uint> clusters;
+[[vk::binding(1)]] StructuredBuffer<TranslucentEmissive> emissives_dyn;
+[[vk::binding(2)]] cbuffer _ {
+    uint2 output_extent;
+    uint2 tile_count;
+    uint emissive_count;
+};
+
+struct ClusterBounds {
+    float3 aabb_min;
+    float3 aabb_max;
+
+    // View-space bounding box of the part of the tile's frustum between two view depths
+    static ClusterBounds create(float3 corner_rays[4], float start_depth, float end_depth) {
+        ClusterBounds res;
+        res.aabb_min = 1e30;
+        res.aabb_max = -1e30;
+
+        for (uint i = 0; i < 4; ++i) {
+            res.aabb_min = min(res.aabb_min, min(corner_rays[i] * start_depth, corner_rays[i] * end_depth));
+            res.aabb_max = max(res.aabb_max, max(corner_rays[i] * start_depth, corner_rays[i] * end_depth));
+        }
+
+        return res;
+    }
+
+    bool overlaps_sphere(float3 center, float radius) {
+        const float3 offset = max(0.0, max(aabb_min - center, center - aabb_max));
+        return dot(offset, offset) <= radius * radius;
+    }
+};
+
+[numthreads(4, 4, 4)]
+void main(uint3 idx: SV_DispatchThreadID) {
+    const uint2 tile = idx.xy;
+    const uint slice = idx.z;
+
+    if (any(tile >= tile_count) || slice >= CLUSTER_SLICE_COUNT) {
+        return;
+    }
+
+    const float2 uv_min = float2(tile * CLUSTER_TILE_SIZE) / output_extent;
+    const float2 uv_max = min(1.0, float2((tile + 1) * CLUSTER_TILE_SIZE) / output_extent);
+
+    float3 corner_rays[4] = {
+        cluster_view_ray(uv_min),
+        cluster_view_ray(float2(uv_max.x, uv_min.y)),
+        cluster_view_ray(float2(uv_min.x, uv_max.y)),
+        cluster_view_ray(uv_max),
+    };
+
+    const float start_depth = cluster_slice_start_depth(slice);
+    const float end_depth = slice + 1 < CLUSTER_SLICE_COUNT ? cluster_slice_start_depth(slice + 1) : 1e20;
+    const float prev_start_depth = slice > 0 ? cluster_slice_start_depth(slice - 1) : 0.0;
+
+    ClusterBounds bounds = ClusterBounds::create(corner_rays, start_depth, end_depth);
+    ClusterBounds prev_bounds = ClusterBounds::create(corner_rays, prev_start_depth, start_depth);
+
+    const uint base = cluster_index(tile, slice, tile_count.x) * CLUSTER_STRIDE;
+    uint count = 0;
+
+    for (uint i = 0; i < emissive_count && count < MAX_EMISSIVES_PER_CLUSTER; ++i) {
+        const float4 position_radius = emissives_dyn[i].position_radius;
+        const float3 center_vs = position_world_to_view(position_radius.xyz);
+        const float radius = position_radius.w;
+
+        if (!bounds.overlaps_sphere(center_vs, radius)) {
+            continue;
+        }
+
+        if (slice > 0 && prev_bounds.overlaps_sphere(center_vs, radius)) {
+            continue;
+        }
+
+        clusters[base + 1 + count] = i;
+        count += 1;
+    }
+
+    clusters[base] = count;
+}
@@ -0,0 +1,61 @@
+#ifndef TRANSLUCENT_EMISSIVES_CLUSTERS_HLSL
+#define TRANSLUCENT_EMISSIVES_CLUSTERS_HLSL
+
+#include "../inc/frame_constants.hlsl"
+#include "../inc/uv.hlsl"
+
+// Must match `renderers/translucent_emissives.rs`
+#define CLUSTER_TILE_SIZE 32
+#define CLUSTER_SLICE_COUNT 16
+
+// A count followed by emissive indices
+#define CLUSTER_STRIDE 32
+#define MAX_EMISSIVES_PER_CLUSTER (CLUSTER_STRIDE - 1)
+
+// The first slice spans up to `CLUSTER_NEAR`, and the last one everything past `CLUSTER_FAR`.
+// The slices in between are spaced exponentially.
+#define CLUSTER_NEAR_METERS 0.25
+#define CLUSTER_FAR_METERS 250.0
+
+// Uploaded by `renderers/translucent_emissives.rs`
+struct TranslucentEmissive {
+    float4 position_radius;
+    // Radiance through the center, in `rgb`
+    float4 radiance;
+};
+
+uint cluster_index(uint2 tile, uint slice, uint tile_count_x) {
+    return (tile.x + tile.y * tile_count_x) * CLUSTER_SLICE_COUNT + slice;
+}
+
+// View depth at which `slice` starts
+float cluster_slice_start_depth(uint slice) {
+    if (slice == 0) {
+        return 0.0;
+    }
+
+    const float near_depth = world_units(CLUSTER_NEAR_METERS);
+    const float far_depth = world_units(CLUSTER_FAR_METERS);
+    return near_depth * pow(far_depth / near_depth, float(slice - 1) / (CLUSTER_SLICE_COUNT - 2));
+}
+
+uint cluster_slice_of_depth(float view_depth) {
+    const float near_depth = world_units(CLUSTER_NEAR_METERS);
+    const float far_depth = world_units(CLUSTER_FAR_METERS);
+
+    if (view_depth < near_depth) {
+        return 0;
+    }
+
+    const float t = log2(view_depth / near_depth) / log2(far_depth / near_depth);
+    return min(CLUSTER_SLICE_COUNT - 1, 1 + uint(t * (CLUSTER_SLICE_COUNT - 2)));
+}
+
+// Direction of the un-jittered view ray through `uv`, in view space, scaled to unit view depth.
+float3 cluster_view_ray(float2 uv) {
+    // The far plane is at infinity with reverse-Z, so this is already a direction.
+    const float3 dir = mul(frame_constants.view_constants.clip_to_view, float4(uv_to_cs(uv), 0.0, 1.0)).xyz;
+    return dir / -dir.z;
+}
+
+#endif  // TRANSLUCENT_EMISSIVES_CLUSTERS_HLSL
@@ -0,0 +1,89 @@
+// Integrates the glows of the emissives along each view ray, up to the opaque geometry.
+//
+// The density of a glow falls off as `1 - r^2 / radius^2` from its center, which integrates
+// in closed form along the ray. It's normalized so that the ray through the center sees
+// the emissive's radiance.
+
+#include "clusters.hlsl"
+
+[[vk::binding(0)]] StructuredBuffer<uint> clusters;
+[[vk::binding(1)]] Texture2D<float> depth_tex;
+[[vk::binding(2)]] RWTexture2D<float4> output_tex;
+[[vk::binding(3)]] RWTexture2D<float4> emissive_tex;
+[[vk::binding(4)]] StructuredBuffer<TranslucentEmissive> emissives_dyn;
+[[vk::binding(5)]] cbuffer _ {
+    uint2 output_extent;
+    uint2 tile_count;
+};
+
+// Fraction of the center line integral of a glow covered by the segment `[0, t_max]` of a ray
+float glow_along_ray(float3 ray_dir, float t_max, float3 center, float radius) {
+    const float t_center = dot(center, ray_dir);
+    const float radius2 = radius * radius;
+    const float dist2 = max(0.0, dot(center, center) - t_center * t_center);
+
+    if (dist2 >= radius2) {
+        return 0.0;
+    }
+
+    const float half_chord = sqrt(radius2 - dist2);
+    const float t0 = max(0.0, t_center - half_chord);
+    const float t1 = min(t_max, t_center + half_chord);
+
+    if (t1 <= t0) {
+        return 0.0;
+    }
+
+    const float a = 1.0 - dist2 / radius2;
+    const float x0 = t0 - t_center;
+    const float x1 = t1 - t_center;
+    const float integral = a * (t1 - t0) - (x1 * x1 * x1 - x0 * x0 * x0) / (3.0 * radius2);
+
+    return integral / (4.0 / 3.0 * radius);
+}
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    if (any(px >= output_extent)) {
+        return;
+    }
+
+    const float2 uv = (px + 0.5) / output_extent;
+    const float depth = depth_tex[px];
+
+    const float3 ray_dir_vs = cluster_view_ray(uv);
+    const float3 ray_dir_norm = normalize(ray_dir_vs);
+
+    // Reverse-Z; zero is the sky, at infinity.
+    float t_max = 1e30;
+    uint last_slice = CLUSTER_SLICE_COUNT - 1;
+
+    if (depth > 0.0) {
+        const float view_depth = -depth_to_view_z(depth);
+        t_max = length(ray_dir_vs * view_depth);
+        last_slice = cluster_slice_of_depth(view_depth);
+    }
+
+    const uint2 tile = px / CLUSTER_TILE_SIZE;
+    float3 radiance = 0.0;
+
+    for (uint slice = 0; slice <= last_slice; ++slice) {
+        const uint base = cluster_index(tile, slice, tile_count.x) * CLUSTER_STRIDE;
+        const uint count = clusters[base];
+
+        for (uint i = 0; i < count; ++i) {
+            const TranslucentEmissive emissive = emissives_dyn[clusters[base + 1 + i]];
+            const float3 center_vs = position_world_to_view(emissive.position_radius.xyz);
+
+            radiance += emissive.radiance.rgb
+                * glow_along_ray(ray_dir_norm, t_max, center_vs, emissive.position_radius.w);
+        }
+    }
+
+    radiance *= frame_constants.pre_exposure;
+
+    emissive_tex[px] = float4(radiance, 0.0);
+
+    const float4 scene = output_tex[px];
+    output_tex[px] = float4(scene.rgb + radiance, scene.a);
+}
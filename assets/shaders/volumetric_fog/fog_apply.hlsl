@@ -0,0 +1,53 @@
+#include "../inc/color.hlsl"
+#include "../inc/samplers.hlsl"
+#include "../inc/frame_constants.hlsl"
+#include "../inc/uv.hlsl"
+#include "froxel.hlsl"
+
+[[vk::binding(0)]] Texture2D<float> depth_tex;
+[[vk::binding(1)]] Texture3D<float4> integrated_tex;
+[[vk::binding(2)]] RWTexture2D<float4> output_tex;
+[[vk::binding(3)]] cbuffer _ {
+    float4 output_tex_size;
+    float4 volume_size;
+    float max_distance;
+    uint tile_size;
+};
+
+#if FOG_APPLY_REACTIVE_MASK
+    // See `renderers::taa::reactive_mask`
+    [[vk::binding(4)]] RWTexture2D<float> reactive_mask_tex;
+#endif
+
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float2 uv = get_uv(px, output_tex_size);
+    const float z_over_w = depth_tex[px];
+
+    float slice_t = 1.0;
+    if (z_over_w != 0.0) {
+        const ViewRayContext view_ray_context = ViewRayContext::from_uv_and_depth(uv, z_over_w);
+        slice_t = froxel_view_depth_to_slice(-view_ray_context.ray_hit_vs().z, world_units(max_distance));
+    }
+
+    // Slices hold totals up to their far end, so shift by half a slice.
+    uint3 volume_extent;
+    integrated_tex.GetDimensions(volume_extent.x, volume_extent.y, volume_extent.z);
+
+    const float3 volume_uv = float3(
+        froxel_screen_uv_to_volume_uv(uv, output_tex_size.xy, volume_size.xy, tile_size),
+        saturate(slice_t) - 0.5 / volume_extent.z
+    );
+
+    const float4 fog = integrated_tex.SampleLevel(sampler_llc, volume_uv, 0);
+
+    const float4 color = output_tex[px];
+    const float3 fogged = color.rgb * fog.a + fog.rgb;
+    output_tex[px] = float4(fogged, color.a);
+
+#if FOG_APPLY_REACTIVE_MASK
+    // Emitted radiance is attenuated along with the rest, and in-scattering isn't emitted.
+    const float emitted_lum = reactive_mask_tex[px] * sRGB_to_luminance(color.rgb) * fog.a;
+    reactive_mask_tex[px] = saturate(emitted_lum / max(1e-5, sRGB_to_luminance(fogged)));
+#endif
+}
@@ -0,0 +1,202 @@
+#include "../inc/uv.hlsl"
+#include "../inc/samplers.hlsl"
+#include "../inc/frame_constants.hlsl"
+#include "../inc/rt.hlsl"
+#include "../inc/atmosphere.hlsl"
+#include "../inc/sun.hlsl"
+#include "../inc/hash.hlsl"
+#include "../inc/math.hlsl"
+#include "../inc/math_const.hlsl"
+#include "../inc/lights/point.hlsl"
+#include "../ircache/bindings.hlsl"
+#include "froxel.hlsl"
+
+[[vk::binding(0, 3)]] RaytracingAccelerationStructure acceleration_structure;
+[[vk::binding(0)]] Texture3D<float4> history_tex;
+[[vk::binding(1)]] RWTexture3D<float4> output_tex;
+DEFINE_IRCACHE_BINDINGS(2, 3, 4, 5, 6, 7, 8, 9, 10)
+[[vk::binding(11)]] TextureCube<float4> sky_cube_tex;
+[[vk::binding(12)]] cbuffer _ {
+    float4 screen_size;
+    float density;
+    float anisotropy;
+    float height_fog_base;
+    float height_fog_falloff;
+    float4 albedo_max_distance;
+    float sun_scattering;
+    uint shadow_ray_count;
+    uint tile_size;
+    uint history_valid;
+    float point_light_scattering;
+};
+
+#define IRCACHE_LOOKUP_DONT_KEEP_ALIVE
+#include "../ircache/lookup.hlsl"
+
+// Fraction of the new sample blended into the history.
+static const float TEMPORAL_BLEND = 0.1;
+
+float henyey_greenstein_phase(float cos_theta, float g) {
+    const float g2 = g * g;
+    return (1.0 - g2) / (4.0 * M_PI * pow(max(1e-5, 1.0 + g2 - 2.0 * g * cos_theta), 1.5));
+}
+
+// Average radiance arriving at the point from all directions, for isotropic in-scattering.
+float3 ambient_radiance(float3 pt_ws, inout uint rng) {
+    if (!frame_constants.render_overrides.has_flag(RenderOverrideFlags::NO_IRCACHE)) {
+        float3 irradiance_sum = 0.0;
+        uint found = 0;
+
+        const float3 normals[2] = { float3(0, 1, 0), float3(0, -1, 0) };
+
+        [unroll]
+        for (uint i = 0; i < 2; ++i) {
+            const float3 jitter = float3(
+                uint_to_u01_float(hash1_mut(rng)),
+                uint_to_u01_float(hash1_mut(rng)),
+                uint_to_u01_float(hash1_mut(rng))
+            ) - 0.5;
+
+            const IrcacheLookup lookup = ircache_lookup(pt_ws, normals[i], jitter);
+            if (lookup.count > 0) {
+                const uint entry_idx = lookup.entry_idx[0];
+
+                float3 irradiance = 0;
+                for (uint basis_i = 0; basis_i < 3; ++basis_i) {
+                    irradiance[basis_i] += eval_sh(ircache_irradiance_buf[entry_idx * IRCACHE_IRRADIANCE_STRIDE + basis_i], normals[i]);
+                }
+
+                irradiance_sum += max(0.0, irradiance);
+                found += 1;
+            }
+        }
+
+        if (found > 0) {
+            return irradiance_sum / (found * M_PI);
+        }
+    }
+
+    // Away from geometry the cache has no entries; the sky is a decent stand-in there.
+    return 0.5 * (
+        sky_cube_tex.SampleLevel(sampler_llr, float3(0, 1, 0), 0).rgb
+        + sky_cube_tex.SampleLevel(sampler_llr, float3(0, -1, 0), 0).rgb
+    );
+}
+
+[shader("raygeneration")]
+void main() {
+    const uint3 froxel = DispatchRaysIndex().xyz;
+    const uint3 volume_extent = DispatchRaysDimensions().xyz;
+
+    const float3 albedo = albedo_max_distance.xyz;
+    const float far = world_units(albedo_max_distance.w);
+
+    uint rng = hash4(uint4(froxel, frame_constants.frame_index));
+
+    // Jitter within the froxel; the temporal filter integrates over it.
+    const float3 froxel_jitter = float3(
+        uint_to_u01_float(hash1_mut(rng)),
+        uint_to_u01_float(hash1_mut(rng)),
+        uint_to_u01_float(hash1_mut(rng))
+    );
+
+    const float2 uv = (froxel.xy + froxel_jitter.xy) * tile_size * screen_size.zw;
+    const float slice_t = (froxel.z + froxel_jitter.z) / volume_extent.z;
+    const float view_depth = froxel_slice_to_view_depth(slice_t, far);
+
+    const float4 ray_dir_vs_h = mul(frame_constants.view_constants.sample_to_view, float4(uv_to_cs(uv), 0.0, 1.0));
+    const float3 ray_dir_vs = ray_dir_vs_h.xyz / ray_dir_vs_h.w;
+    const float3 pt_vs = ray_dir_vs * (view_depth / -ray_dir_vs.z);
+    const float3 pt_ws = mul(frame_constants.view_constants.view_to_world, float4(pt_vs, 1.0)).xyz;
+    const float3 eye_ws = get_eye_position();
+    const float3 view_dir_ws = normalize(pt_ws - eye_ws);
+
+    // Exponential height fog; parameters are in meters.
+    const float height_above_base = pt_ws.y / frame_constants.world_units_per_meter - height_fog_base;
+    const float density_meters = density * exp(-max(0.0, height_above_base) * height_fog_falloff);
+    const float extinction = density_meters / frame_constants.world_units_per_meter;
+
+    float3 radiance = 0.0;
+
+    if (extinction > 0.0) {
+        float sun_phase_sum = 0.0;
+
+        for (uint ray_i = 0; ray_i < shadow_ray_count; ++ray_i) {
+            const float3 to_light = sample_sun_direction(
+                float2(uint_to_u01_float(hash1_mut(rng)), uint_to_u01_float(hash1_mut(rng))),
+                true
+            );
+
+            const bool is_shadowed = rt_is_shadowed(
+                acceleration_structure,
+                new_ray(
+                    pt_ws,
+                    to_light,
+                    0,
+                    FLT_MAX
+                ));
+
+            if (!is_shadowed) {
+                sun_phase_sum += henyey_greenstein_phase(dot(to_light, view_dir_ws), anisotropy);
+            }
+        }
+
+        radiance += SUN_COLOR * sun_scattering * sun_phase_sum / shadow_ray_count;
+
+        // Point and spot lights get one shadow ray each, towards a random point
+        // on the light's sphere, softened by the temporal filter.
+        if (point_light_scattering > 0.0) {
+            for (uint light_idx = 0; light_idx < frame_constants.point_light_count; ++light_idx) {
+                const PointLight light = PointLight::from_packed(point_lights_dyn[light_idx]);
+                if (light.volumetric_intensity <= 0.0) {
+                    continue;
+                }
+
+                const float2 urand = float2(uint_to_u01_float(hash1_mut(rng)), uint_to_u01_float(hash1_mut(rng)));
+                const PointLightSample light_sample = sample_point_light(light, pt_ws, urand);
+                if (all(light_sample.irradiance == 0.0)) {
+                    continue;
+                }
+
+                const bool is_shadowed = rt_is_shadowed(
+                    acceleration_structure,
+                    new_ray(
+                        pt_ws,
+                        light_sample.dir,
+                        0,
+                        light_sample.dist
+                    ));
+
+                if (!is_shadowed) {
+                    radiance += light_sample.irradiance
+                        * (light.volumetric_intensity * point_light_scattering)
+                        * henyey_greenstein_phase(dot(light_sample.dir, view_dir_ws), anisotropy);
+                }
+            }
+        }
+
+        radiance += ambient_radiance(pt_ws, rng);
+    }
+
+    float4 result = float4(albedo * extinction * radiance, extinction);
+
+    if (history_valid) {
+        const float4 prev_vs = mul(frame_constants.view_constants.prev_world_to_prev_view, float4(pt_ws, 1.0));
+        const float4 prev_cs = mul(frame_constants.view_constants.prev_view_to_prev_clip, prev_vs);
+        const float2 prev_uv = cs_to_uv(prev_cs.xy / prev_cs.w);
+        const float prev_slice_t = froxel_view_depth_to_slice(-prev_vs.z / prev_vs.w, far);
+
+        const float3 prev_volume_uv = float3(
+            froxel_screen_uv_to_volume_uv(prev_uv, screen_size.xy, volume_extent.xy, tile_size),
+            prev_slice_t
+        );
+
+        if (prev_vs.z < 0.0 && all(prev_volume_uv == saturate(prev_volume_uv))) {
+            float4 history = history_tex.SampleLevel(sampler_lnc, prev_volume_uv, 0);
+            history.rgb *= frame_constants.pre_exposure_delta;
+            result = lerp(history, result, TEMPORAL_BLEND);
+        }
+    }
+
+    output_tex[froxel] = result;
+}
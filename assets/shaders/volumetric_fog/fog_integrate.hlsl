@@ -0,0 +1,41 @@
+#include "../inc/frame_constants.hlsl"
+#include "froxel.hlsl"
+
+[[vk::binding(0)]] Texture3D<float4> input_tex;
+[[vk::binding(1)]] RWTexture3D<float4> output_tex;
+[[vk::binding(2)]] cbuffer _ {
+    float max_distance;
+};
+
+// Accumulates in-scattered light and transmittance front-to-back through the slices.
+// Each output slice holds the totals from the eye to its far end.
+[numthreads(8, 8, 1)]
+void main(uint2 px: SV_DispatchThreadID) {
+    const float far = world_units(max_distance);
+
+    uint3 volume_extent;
+    input_tex.GetDimensions(volume_extent.x, volume_extent.y, volume_extent.z);
+    const uint slice_count = volume_extent.z;
+
+    float3 scattering = 0.0;
+    float transmittance = 1.0;
+
+    float slice_start = froxel_slice_to_view_depth(0.0, far);
+
+    for (uint slice = 0; slice < slice_count; ++slice) {
+        const float slice_end = froxel_slice_to_view_depth(float(slice + 1) / slice_count, far);
+        const float step_length = slice_end - slice_start;
+        slice_start = slice_end;
+
+        const float4 froxel = input_tex[uint3(px, slice)];
+        const float extinction = max(1e-7, froxel.a);
+        const float step_transmittance = exp(-extinction * step_length);
+
+        // Energy-conserving integration over the step:
+        // "Physically Based and Unified Volumetric Rendering in Frostbite", Hillaire 2015.
+        scattering += transmittance * (froxel.rgb - froxel.rgb * step_transmittance) / extinction;
+        transmittance *= step_transmittance;
+
+        output_tex[uint3(px, slice)] = float4(scattering, transmittance);
+    }
+}
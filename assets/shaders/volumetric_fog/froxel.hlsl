@@ -0,0 +1,26 @@
+#ifndef VOLUMETRIC_FOG_FROXEL_HLSL
+#define VOLUMETRIC_FOG_FROXEL_HLSL
+
+// Distance from the eye at which the first slice starts.
+static const float FROXEL_NEAR_METERS = 0.25;
+
+// Slices are distributed exponentially in view depth, so that each covers
+// a roughly constant depth range relative to its distance.
+float froxel_slice_to_view_depth(float slice_t, float far) {
+    const float near = world_units(FROXEL_NEAR_METERS);
+    return near * pow(far / near, slice_t);
+}
+
+// Inverse of `froxel_slice_to_view_depth`; the result is in [0, 1] for depths in [near, far].
+float froxel_view_depth_to_slice(float view_depth, float far) {
+    const float near = world_units(FROXEL_NEAR_METERS);
+    return log(max(view_depth, near) / near) / log(far / near);
+}
+
+// Screen UV to the XY coordinate of the froxel volume. The volume can be slightly larger
+// than the screen, as it's rounded up to whole tiles.
+float2 froxel_screen_uv_to_volume_uv(float2 uv, float2 screen_extent, float2 volume_extent, uint tile_size) {
+    return uv * screen_extent / (volume_extent * tile_size);
+}
+
+#endif  // VOLUMETRIC_FOG_FROXEL_HLSL
@@ -12,6 +12,7 @@
 #include "../inc/atmosphere.hlsl"
 #include "../inc/sun.hlsl"
 #include "../inc/lights/triangle.hlsl"
+#include "../inc/lights/point.hlsl"
 
 #include "bindings.hlsl"
 #include "../ircache/bindings.hlsl"
@@ -110,16 +111,46 @@ void main() {
                     new_ray(
                         primary_hit.position,
                         to_light_norm,
-                        1e-4,
+                        world_units(1e-4),
                         SKY_DIST
                 ));
 
             const float3 wi = mul(to_light_norm, tangent_to_world);
-            const float3 brdf_value = brdf.evaluate(wo, wi) * max(0.0, wi.z);
+            const float3 brdf_value = brdf.evaluate(wo, wi) * max(0.0, wi.z)
+                + brdf.evaluate_transmission(wi) * max(0.0, -wi.z);
             const float3 light_radiance = select(is_shadowed, 0.0, sun_radiance);
             total_radiance += brdf_value * light_radiance;
         }
 
+        if (USE_LIGHTS) {
+            const float2 urand = float2(
+                uint_to_u01_float(hash1_mut(rng)),
+                uint_to_u01_float(hash1_mut(rng))
+            );
+
+            for (uint light_idx = 0; light_idx < frame_constants.point_light_count; light_idx += 1) {
+                const PointLight point_light = PointLight::from_packed(point_lights_dyn[light_idx]);
+                const PointLightSample light_sample = sample_point_light(point_light, primary_hit.position, urand);
+                const float3 wi = mul(light_sample.dir, tangent_to_world);
+
+                if (wi.z != 0.0 && any(light_sample.irradiance > 0.0)) {
+                    const bool is_shadowed =
+                        rt_is_shadowed(
+                            acceleration_structure,
+                            new_ray(
+                                primary_hit.position,
+                                light_sample.dir,
+                                world_units(1e-3),
+                                light_sample.dist - world_units(2e-3)
+                        ));
+
+                    const float3 brdf_value = brdf.evaluate(wo, wi) * max(0.0, wi.z)
+                        + brdf.evaluate_transmission(wi) * max(0.0, -wi.z);
+                    total_radiance += select(is_shadowed, 0.0, brdf_value * light_sample.irradiance);
+                }
+            }
+        }
+
         if (USE_EMISSIVE) {
             total_radiance += gbuffer.emissive;
         }
@@ -150,8 +181,8 @@ void main() {
                             new_ray(
                                 shadow_ray_origin,
                                 to_light_norm_ws,
-                                1e-3,
-                                sqrt(dist_to_light2) - 2e-3
+                                world_units(1e-3),
+                                sqrt(dist_to_light2) - world_units(2e-3)
                         ));
 
                     #if 1
@@ -38,8 +38,11 @@ fn main() -> anyhow::Result<()> {
 
         WorldFrameDesc {
             camera_matrices: camera.through(&lens),
+            physical_camera: lens.physical_camera(),
             render_extent: ctx.render_extent,
             sun_direction: Vec3::new(4.0, 1.0, 1.0).normalize(),
+            sun_size_multiplier: 1.0,
+            disabled_features: 0,
         }
     })
 }
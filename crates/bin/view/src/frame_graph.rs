@@ -0,0 +1,60 @@
+use anyhow::Context;
+use kajiya::frame_graph_config::FrameGraphConfig;
+use std::{fs::File, path::PathBuf, time::SystemTime};
+
+/// Keeps track of a RON or TOML file describing a `FrameGraphConfig`,
+/// and reloads it whenever it's modified on disk.
+pub struct FrameGraphConfigWatcher {
+    path: PathBuf,
+    last_modified: Option<SystemTime>,
+}
+
+impl FrameGraphConfigWatcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            last_modified: None,
+        }
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    /// Returns the freshly loaded config if the file appeared or changed since the last call.
+    /// A missing file is not an error; the renderer keeps its current config.
+    pub fn poll(&mut self) -> Option<anyhow::Result<FrameGraphConfig>> {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|meta| meta.modified())
+            .ok()?;
+
+        if self.last_modified == Some(modified) {
+            return None;
+        }
+
+        self.last_modified = Some(modified);
+        Some(self.load())
+    }
+
+    fn load(&self) -> anyhow::Result<FrameGraphConfig> {
+        let is_toml = self
+            .path
+            .extension()
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("toml"));
+
+        if is_toml {
+            let text = std::fs::read_to_string(&self.path)
+                .with_context(|| format!("Reading {:?}", self.path))?;
+
+            // Don't use anyhow context here because it doesn't show the parsing error.
+            toml::from_str(&text)
+                .map_err(|e| anyhow::anyhow!("Failed to parse {:?}: {}", self.path, e))
+        } else {
+            let file =
+                File::open(&self.path).with_context(|| format!("Opening {:?}", self.path))?;
+
+            ron::de::from_reader(file)
+                .map_err(|e| anyhow::anyhow!("Failed to parse {:?}: {}", self.path, e))
+        }
+    }
+}
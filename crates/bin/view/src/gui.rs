@@ -1,5 +1,18 @@
 use imgui::im_str;
-use kajiya::RenderOverrideFlags;
+use kajiya::{
+    asset::mesh::MaterialCategory,
+    frame_desc::RenderFeatureFlags,
+    frame_graph_config::QualityTier,
+    renderers::{
+        ao::AmbientOcclusionMode,
+        denoiser::DenoiserSignal,
+        reference::{ReferenceIntegrator, ReferencePreviewFilter},
+        rtdgi::RtdgiBiasCorrection,
+        taa::MAX_VELOCITY_DILATION_RADIUS,
+    },
+    world_renderer::{ExposureMeteringMode, TemporalUpscaler, Tonemapper},
+    RenderOverrideFlags,
+};
 use kajiya_simple::*;
 
 use crate::{
@@ -7,6 +20,9 @@ use crate::{
     PersistedState,
 };
 
+// Where the reference path tracer's state is saved to, and resumed from
+const REFERENCE_STATE_FILE: &str = "reference_state.exr";
+
 impl RuntimeState {
     pub fn do_gui(&mut self, persisted: &mut PersistedState, ctx: &mut FrameContext) {
         if self.keyboard.was_just_pressed(self.keymap_config.ui.toggle) {
@@ -31,11 +47,51 @@ impl RuntimeState {
                         &mut persisted.exposure.use_dynamic_adaptation,
                     );
 
-                    imgui::Drag::<f32>::new(im_str!("Adaptation speed"))
+                    ui.checkbox(
+                        im_str!("Lock dynamic exposure"),
+                        &mut persisted.exposure.lock_dynamic_adaptation,
+                    );
+
+                    {
+                        let mut metering_idx = ExposureMeteringMode::ALL
+                            .iter()
+                            .position(|&m| m == persisted.exposure.dynamic_adaptation_metering)
+                            .unwrap_or(0);
+
+                        if imgui::ComboBox::new(im_str!("Metering")).build_simple_string(
+                            ui,
+                            &mut metering_idx,
+                            &[
+                                im_str!("Average"),
+                                im_str!("Center-weighted"),
+                                im_str!("Histogram"),
+                            ],
+                        ) {
+                            persisted.exposure.dynamic_adaptation_metering =
+                                ExposureMeteringMode::ALL[metering_idx];
+                        }
+                    }
+
+                    imgui::Drag::<f32>::new(im_str!("Adaptation speed up"))
                         .range(-4.0..=4.0)
                         .speed(0.01)
                         .build(ui, &mut persisted.exposure.dynamic_adaptation_speed);
 
+                    imgui::Drag::<f32>::new(im_str!("Adaptation speed down"))
+                        .range(-4.0..=4.0)
+                        .speed(0.01)
+                        .build(ui, &mut persisted.exposure.dynamic_adaptation_speed_down);
+
+                    imgui::Drag::<f32>::new(im_str!("Dynamic exposure min EV"))
+                        .range(-16.0..=16.0)
+                        .speed(0.01)
+                        .build(ui, &mut persisted.exposure.dynamic_adaptation_min_ev);
+
+                    imgui::Drag::<f32>::new(im_str!("Dynamic exposure max EV"))
+                        .range(-16.0..=16.0)
+                        .speed(0.01)
+                        .build(ui, &mut persisted.exposure.dynamic_adaptation_max_ev);
+
                     imgui::Drag::<f32>::new(im_str!("Luminance histogram low clip"))
                         .range(0.0..=1.0)
                         .speed(0.001)
@@ -59,6 +115,84 @@ impl RuntimeState {
                         .speed(0.001)
                         .build(ui, &mut persisted.exposure.contrast);
 
+                    imgui::Drag::<f32>::new(im_str!("Sharpness"))
+                        .range(0.0..=1.0)
+                        .speed(0.01)
+                        .build(ui, &mut ctx.world_renderer.sharpness);
+
+                    {
+                        let mut tonemapper_idx = Tonemapper::ALL
+                            .iter()
+                            .position(|&t| t == persisted.exposure.tonemapper)
+                            .unwrap_or(0);
+
+                        if imgui::ComboBox::new(im_str!("Tonemapper")).build_simple_string(
+                            ui,
+                            &mut tonemapper_idx,
+                            &[
+                                im_str!("Filmic"),
+                                im_str!("ACES"),
+                                im_str!("AgX"),
+                                im_str!("Khronos PBR Neutral"),
+                                im_str!("Reinhard"),
+                            ],
+                        ) {
+                            persisted.exposure.tonemapper = Tonemapper::ALL[tonemapper_idx];
+                        }
+                    }
+
+                    if ctx.world_renderer.hdr_output.color_space.is_hdr() {
+                        let hdr_output = &mut ctx.world_renderer.hdr_output;
+
+                        imgui::Drag::<f32>::new(im_str!("Paper white (nits)"))
+                            .range(80.0..=500.0)
+                            .speed(1.0)
+                            .build(ui, &mut hdr_output.paper_white_nits);
+
+                        imgui::Drag::<f32>::new(im_str!("Display peak (nits)"))
+                            .range(400.0..=10000.0)
+                            .speed(10.0)
+                            .build(ui, &mut hdr_output.max_nits);
+                    }
+
+                    if ctx.world_renderer.color_grading.has_lut() {
+                        imgui::Drag::<f32>::new(im_str!("Color grading blend"))
+                            .range(0.0..=1.0)
+                            .speed(0.01)
+                            .build(ui, &mut ctx.world_renderer.color_grading.blend);
+
+                        if ui.button(im_str!("Clear color grading"), [0.0, 0.0]) {
+                            // Can't fail without a LUT to create.
+                            let _ = ctx.world_renderer.set_color_grading_lut(None);
+                        }
+                    } else {
+                        ui.text(im_str!("Drag a .cube LUT to color grade"));
+                    }
+
+                    {
+                        let bloom = &mut ctx.world_renderer.frame_graph_config.bloom;
+                        ui.checkbox(im_str!("Bloom"), &mut bloom.enabled);
+                        imgui::Drag::<f32>::new(im_str!("Bloom amount"))
+                            .range(0.0..=1.0)
+                            .speed(0.001)
+                            .build(ui, &mut bloom.amount);
+                        imgui::Drag::<f32>::new(im_str!("Bloom threshold"))
+                            .range(0.0..=16.0)
+                            .speed(0.01)
+                            .build(ui, &mut bloom.threshold);
+                        imgui::Drag::<f32>::new(im_str!("Bloom knee"))
+                            .range(0.0..=1.0)
+                            .speed(0.01)
+                            .build(ui, &mut bloom.knee);
+                        imgui::Drag::<f32>::new(im_str!("Bloom scatter"))
+                            .range(0.0..=1.0)
+                            .speed(0.01)
+                            .build(ui, &mut bloom.scatter);
+                        imgui::Drag::<u32>::new(im_str!("Bloom mips"))
+                            .range(0..=12)
+                            .build(ui, &mut bloom.mip_count);
+                    }
+
                     imgui::Drag::<f32>::new(im_str!("Emissive multiplier"))
                         .range(0.0..=10.0)
                         .speed(0.1)
@@ -99,6 +233,40 @@ impl RuntimeState {
                         .speed(0.02)
                         .build(ui, &mut persisted.light.sun.size_multiplier);
 
+                    ui.checkbox(
+                        im_str!("Time of day"),
+                        &mut persisted.light.sun.use_time_of_day,
+                    );
+
+                    if persisted.light.sun.use_time_of_day {
+                        let time_of_day = &mut persisted.light.sun.time_of_day;
+
+                        imgui::Drag::<f32>::new(im_str!("Hour"))
+                            .range(0.0..=24.0)
+                            .speed(0.01)
+                            .build(ui, &mut time_of_day.hours);
+
+                        imgui::Drag::<f32>::new(im_str!("Day of year"))
+                            .range(1.0..=365.0)
+                            .speed(0.5)
+                            .build(ui, &mut time_of_day.day_of_year);
+
+                        imgui::Drag::<f32>::new(im_str!("Latitude"))
+                            .range(-90.0..=90.0)
+                            .speed(0.1)
+                            .build(ui, &mut time_of_day.latitude_degrees);
+
+                        imgui::Drag::<f32>::new(im_str!("North offset"))
+                            .range(-180.0..=180.0)
+                            .speed(0.5)
+                            .build(ui, &mut time_of_day.north_offset_degrees);
+
+                        imgui::Drag::<f32>::new(im_str!("Hours per second"))
+                            .range(0.0..=1.0)
+                            .speed(0.001)
+                            .build(ui, &mut time_of_day.hours_per_second);
+                    }
+
                     /*ui.checkbox(
                         im_str!("Show world radiance cache"),
                         &mut ctx.world_renderer.debug_show_wrc,
@@ -122,6 +290,18 @@ impl RuntimeState {
                         .range(0..=10)
                         .build(ui, &mut state.lights.count);*/
 
+                    imgui::Drag::<u32>::new(im_str!("Shadowed point lights per tile"))
+                        .range(0..=63)
+                        .build(
+                            ui,
+                            &mut ctx.world_renderer.lighting.shadowed_point_lights_per_tile,
+                        );
+
+                    imgui::Drag::<f32>::new(im_str!("Point light cutoff"))
+                        .range(0.0..=1.0)
+                        .speed(0.0001)
+                        .build(ui, &mut ctx.world_renderer.lighting.point_light_cutoff);
+
                     ui.checkbox(
                         im_str!("Scroll irradiance cache"),
                         &mut ctx.world_renderer.ircache.enable_scroll,
@@ -137,19 +317,351 @@ impl RuntimeState {
                         .spatial_reuse_pass_count
                         .clamp(1, 3);
 
-                    ui.checkbox(
-                        im_str!("Ray-traced reservoir visibility"),
-                        &mut ctx.world_renderer.rtdgi.use_raytraced_reservoir_visibility,
-                    );
+                    {
+                        let rtdgi = &mut ctx.world_renderer.rtdgi;
+
+                        imgui::Drag::<u32>::new(im_str!("GI spatial reuse taps"))
+                            .range(1..=16)
+                            .build_array(ui, &mut rtdgi.spatial_reuse_sample_count);
+
+                        for count in &mut rtdgi.spatial_reuse_sample_count {
+                            *count = (*count).clamp(1, 16);
+                        }
+
+                        imgui::Drag::<f32>::new(im_str!("GI reservoir M clamp"))
+                            .range(1.0..=64.0)
+                            .speed(0.1)
+                            .build(ui, &mut rtdgi.temporal_reservoir_m_clamp);
+
+                        let mut bias_correction_idx = RtdgiBiasCorrection::ALL
+                            .iter()
+                            .position(|&b| b == rtdgi.bias_correction)
+                            .unwrap_or(0);
+
+                        let names = RtdgiBiasCorrection::ALL
+                            .iter()
+                            .map(|b| imgui::ImString::new(b.name()))
+                            .collect::<Vec<_>>();
+                        let names = names
+                            .iter()
+                            .map(|n| n.as_ref())
+                            .collect::<Vec<&imgui::ImStr>>();
+
+                        if imgui::ComboBox::new(im_str!("GI bias correction")).build_simple_string(
+                            ui,
+                            &mut bias_correction_idx,
+                            &names,
+                        ) {
+                            rtdgi.bias_correction = RtdgiBiasCorrection::ALL[bias_correction_idx];
+                        }
+                    }
 
                     ui.checkbox(
                         im_str!("Allow diffuse ray reuse for reflections"),
                         &mut ctx.world_renderer.rtr.reuse_rtdgi_rays,
                     );
 
-                    #[cfg(feature = "dlss")]
+                    ui.checkbox(
+                        im_str!("Aggregate static instances"),
+                        &mut ctx.world_renderer.instance_aggregation.enabled,
+                    );
+
+                    let fog = &mut ctx.world_renderer.volumetric_fog;
+                    ui.checkbox(im_str!("Volumetric fog"), &mut fog.enabled);
+
+                    if fog.enabled {
+                        imgui::Drag::<f32>::new(im_str!("Fog density"))
+                            .range(0.0..=1.0)
+                            .speed(0.001)
+                            .build(ui, &mut fog.density);
+
+                        imgui::Drag::<f32>::new(im_str!("Fog anisotropy"))
+                            .range(-0.99..=0.99)
+                            .speed(0.01)
+                            .build(ui, &mut fog.anisotropy);
+
+                        imgui::Drag::<f32>::new(im_str!("Fog base height"))
+                            .range(-1000.0..=1000.0)
+                            .speed(0.1)
+                            .build(ui, &mut fog.height_fog_base);
+
+                        imgui::Drag::<f32>::new(im_str!("Fog height falloff"))
+                            .range(0.0..=10.0)
+                            .speed(0.001)
+                            .build(ui, &mut fog.height_fog_falloff);
+
+                        imgui::Drag::<f32>::new(im_str!("Fog distance"))
+                            .range(1.0..=10000.0)
+                            .speed(1.0)
+                            .build(ui, &mut fog.max_distance);
+
+                        imgui::Drag::<f32>::new(im_str!("Light shaft intensity"))
+                            .range(0.0..=10.0)
+                            .speed(0.01)
+                            .build(ui, &mut fog.sun_scattering);
+
+                        imgui::Drag::<f32>::new(im_str!("Light cone intensity"))
+                            .range(0.0..=10.0)
+                            .speed(0.01)
+                            .build(ui, &mut fog.point_light_scattering);
+                    }
+
+                    let probes = &mut ctx.world_renderer.reflection_probes;
+                    ui.checkbox(
+                        &im_str!("Reflection probes ({})", probes.probe_count()),
+                        &mut probes.enabled,
+                    );
+
+                    if probes.enabled {
+                        if ui.button(im_str!("Add probe at camera"), [0.0, 0.0]) {
+                            probes.add(persisted.camera.position, 10.0);
+                        }
+
+                        ui.same_line(0.0);
+                        if ui.button(im_str!("Recapture probes"), [0.0, 0.0]) {
+                            probes.recapture_all();
+                        }
+                    }
+
+                    let environment_capture = &mut ctx.world_renderer.environment_capture;
+                    ui.checkbox(
+                        im_str!("Environment capture for translucents"),
+                        &mut environment_capture.enabled,
+                    );
+
+                    if environment_capture.enabled {
+                        imgui::Drag::<u32>::new(im_str!("Capture interval (frames)"))
+                            .range(1..=120)
+                            .build(ui, &mut environment_capture.capture_interval);
+                    }
+
+                    let sky_visibility = &mut ctx.world_renderer.sky_visibility;
+                    ui.checkbox(im_str!("Sky visibility field"), &mut sky_visibility.enabled);
+
+                    if sky_visibility.enabled {
+                        imgui::Drag::<f32>::new(im_str!("Sky visibility cell size"))
+                            .range(0.25..=64.0)
+                            .speed(0.05)
+                            .build(ui, &mut sky_visibility.cell_size);
+
+                        imgui::Drag::<f32>::new(im_str!("Sky visibility fade start"))
+                            .range(0.0..=10000.0)
+                            .speed(1.0)
+                            .build(ui, &mut sky_visibility.fade_start);
+
+                        imgui::Drag::<f32>::new(im_str!("Sky visibility strength"))
+                            .range(0.0..=1.0)
+                            .speed(0.01)
+                            .build(ui, &mut sky_visibility.strength);
+                    }
+
+                    let photo_mode = &mut ctx.world_renderer.photo_mode;
+                    if photo_mode.is_active() {
+                        let (accumulated, sample_count) = photo_mode.progress();
+                        if ui.button(im_str!("Stop photo"), [0.0, 0.0]) {
+                            photo_mode.stop();
+                        }
+                        ui.same_line(0.0);
+                        ui.text(format!("{}/{} frames", accumulated, sample_count));
+                    } else {
+                        imgui::Drag::<u32>::new(im_str!("Photo frames"))
+                            .range(1..=4096)
+                            .build(ui, &mut self.photo_sample_count);
+                        if ui.button(im_str!("Take photo"), [0.0, 0.0]) {
+                            photo_mode.start(self.photo_sample_count);
+                        }
+                    }
+
                     {
-                        ui.checkbox(im_str!("Use DLSS"), &mut ctx.world_renderer.use_dlss);
+                        let upscalers = TemporalUpscaler::all();
+                        if upscalers.len() > 1 {
+                            let mut upscaler_idx = upscalers
+                                .iter()
+                                .position(|&u| u == ctx.world_renderer.temporal_upscaler)
+                                .unwrap_or(0);
+
+                            let names = upscalers
+                                .iter()
+                                .map(|u| imgui::ImString::new(u.name()))
+                                .collect::<Vec<_>>();
+                            let names = names
+                                .iter()
+                                .map(|n| n.as_ref())
+                                .collect::<Vec<&imgui::ImStr>>();
+
+                            if imgui::ComboBox::new(im_str!("Upscaler")).build_simple_string(
+                                ui,
+                                &mut upscaler_idx,
+                                &names,
+                            ) {
+                                ctx.world_renderer.temporal_upscaler = upscalers[upscaler_idx];
+                            }
+                        }
+                    }
+
+                    let mut reference_preview = ctx.world_renderer.reference_preview_scale < 1.0;
+                    if ui.checkbox(
+                        im_str!("Half-res reference preview"),
+                        &mut reference_preview,
+                    ) {
+                        ctx.world_renderer.reference_preview_scale =
+                            if reference_preview { 0.5 } else { 1.0 };
+                    }
+
+                    if reference_preview {
+                        let mut nearest = ctx.world_renderer.reference_preview_filter
+                            == ReferencePreviewFilter::Nearest;
+                        if ui.checkbox(im_str!("Nearest-neighbor preview"), &mut nearest) {
+                            ctx.world_renderer.reference_preview_filter = if nearest {
+                                ReferencePreviewFilter::Nearest
+                            } else {
+                                ReferencePreviewFilter::Bilinear
+                            };
+                        }
+
+                        if ui.button(im_str!("Restart at full resolution"), [0.0, 0.0]) {
+                            ctx.world_renderer.restart_reference_at_full_resolution();
+                        }
+                    }
+
+                    ui.text(format!(
+                        "Reference samples: {}{}",
+                        ctx.world_renderer.reference_sample_count(),
+                        if ctx.world_renderer.is_reference_complete() {
+                            " (done)"
+                        } else {
+                            ""
+                        }
+                    ));
+
+                    if ui.button(im_str!("Save reference state"), [0.0, 0.0]) {
+                        ctx.world_renderer
+                            .save_reference_state(REFERENCE_STATE_FILE);
+                    }
+                    ui.same_line(0.0);
+                    if ui.button(im_str!("Load reference state"), [0.0, 0.0]) {
+                        if let Err(err) = ctx
+                            .world_renderer
+                            .load_reference_state(REFERENCE_STATE_FILE)
+                        {
+                            log::error!(
+                                "Failed to load the reference state from {:?}: {:#}",
+                                REFERENCE_STATE_FILE,
+                                err
+                            );
+                        }
+                    }
+
+                    let reference = &mut ctx.world_renderer.reference_config;
+                    imgui::Drag::<u32>::new(im_str!("Max path length"))
+                        .range(1..=64)
+                        .build(ui, &mut reference.max_path_length);
+                    imgui::Drag::<u32>::new(im_str!("Russian roulette start"))
+                        .range(1..=64)
+                        .build(ui, &mut reference.russian_roulette_start_path_length);
+
+                    let mut clamp_fireflies = reference.firefly_clamp.is_some();
+                    if ui.checkbox(im_str!("Clamp fireflies"), &mut clamp_fireflies) {
+                        reference.firefly_clamp = clamp_fireflies.then(|| 10.0);
+                    }
+                    if let Some(clamp) = reference.firefly_clamp.as_mut() {
+                        imgui::Drag::<f32>::new(im_str!("Firefly clamp"))
+                            .range(0.1..=1000.0)
+                            .speed(0.1)
+                            .build(ui, clamp);
+                    }
+
+                    let mut limit_samples = reference.target_sample_count.is_some();
+                    if ui.checkbox(im_str!("Limit reference samples"), &mut limit_samples) {
+                        reference.target_sample_count = limit_samples.then(|| 1000);
+                    }
+                    if let Some(target) = reference.target_sample_count.as_mut() {
+                        imgui::Drag::<u32>::new(im_str!("Target samples"))
+                            .range(1..=100000)
+                            .build(ui, target);
+                    }
+
+                    let mut spectral = reference.spectral.is_some();
+                    if ui.checkbox(im_str!("Spectral"), &mut spectral) {
+                        reference.spectral = spectral.then(Default::default);
+                    }
+                    if let Some(spectral) = reference.spectral.as_mut() {
+                        let mut dispersion = spectral.abbe_number.is_some();
+                        if ui.checkbox(im_str!("Dispersion"), &mut dispersion) {
+                            spectral.abbe_number = dispersion.then(|| 35.0);
+                        }
+                        if let Some(abbe_number) = spectral.abbe_number.as_mut() {
+                            imgui::Drag::<f32>::new(im_str!("Abbe number"))
+                                .range(5.0..=100.0)
+                                .speed(0.1)
+                                .build(ui, abbe_number);
+                        }
+                    }
+
+                    {
+                        let mut integrator_idx = ReferenceIntegrator::ALL
+                            .iter()
+                            .position(|&i| i == reference.integrator)
+                            .unwrap_or(0);
+
+                        let names = ReferenceIntegrator::ALL
+                            .iter()
+                            .map(|i| imgui::ImString::new(i.name()))
+                            .collect::<Vec<_>>();
+                        let names = names
+                            .iter()
+                            .map(|n| n.as_ref())
+                            .collect::<Vec<&imgui::ImStr>>();
+
+                        if imgui::ComboBox::new(im_str!("Integrator")).build_simple_string(
+                            ui,
+                            &mut integrator_idx,
+                            &names,
+                        ) {
+                            reference.integrator = ReferenceIntegrator::ALL[integrator_idx];
+                        }
+
+                        if reference.integrator == ReferenceIntegrator::PhotonMapping {
+                            let photons = &mut reference.photon_mapping;
+                            imgui::Drag::<u32>::new(im_str!("Photons per frame"))
+                                .range(1024..=(1 << 22))
+                                .speed(1024.0)
+                                .build(ui, &mut photons.photon_count);
+                            imgui::Drag::<f32>::new(im_str!("Initial gather radius"))
+                                .range(0.001..=1.0)
+                                .speed(0.001)
+                                .build(ui, &mut photons.initial_radius);
+                            imgui::Drag::<f32>::new(im_str!("Max specular roughness"))
+                                .range(0.0..=1.0)
+                                .speed(0.01)
+                                .build(ui, &mut photons.max_specular_roughness);
+                            imgui::Drag::<f32>::new(im_str!("Sun photon radius"))
+                                .range(1.0..=1000.0)
+                                .speed(0.1)
+                                .build(ui, &mut photons.sun_photon_radius);
+                        }
+                    }
+
+                    #[cfg(feature = "oidn")]
+                    {
+                        let oidn = &mut ctx.world_renderer.oidn;
+                        ui.checkbox(im_str!("Denoise (OIDN)"), &mut oidn.enabled);
+                        if oidn.enabled {
+                            imgui::Drag::<u32>::new(im_str!("Samples between denoises"))
+                                .range(1..=4096)
+                                .build(ui, &mut oidn.sample_interval);
+
+                            ui.text(format!(
+                                "Denoised samples: {}{}",
+                                oidn.denoised_sample_count()
+                                    .map_or_else(|| "-".to_owned(), |count| count.to_string()),
+                                if oidn.is_denoising() {
+                                    " (denoising)"
+                                } else {
+                                    ""
+                                }
+                            ));
+                        }
                     }
                 }
 
@@ -163,6 +675,23 @@ impl RuntimeState {
                             ctx.world_renderer.ibl.unload_image();
                             persisted.scene.ibl = None;
                         }
+
+                        let ibl = &mut ctx.world_renderer.ibl;
+                        let (prev_rotation, prev_intensity) = (ibl.rotation, ibl.intensity);
+
+                        imgui::Drag::<f32>::new(im_str!("IBL rotation"))
+                            .range(-std::f32::consts::PI..=std::f32::consts::PI)
+                            .speed(0.01)
+                            .build(ui, &mut ibl.rotation);
+
+                        imgui::Drag::<f32>::new(im_str!("IBL intensity"))
+                            .range(0.0..=100.0)
+                            .speed(0.01)
+                            .build(ui, &mut ibl.intensity);
+
+                        if ibl.rotation != prev_rotation || ibl.intensity != prev_intensity {
+                            ctx.world_renderer.reset_reference_accumulation = true;
+                        }
                     } else {
                         ui.text(im_str!("Drag a sphere-mapped .hdr/.exr to load as IBL"));
                     }
@@ -259,6 +788,19 @@ impl RuntimeState {
                         };
                     }
 
+                    macro_rules! do_feature {
+                        ($feature:path, $name:literal) => {
+                            let mut is_disabled: bool =
+                                (ctx.world_renderer.disabled_features & $feature) != 0;
+                            ui.checkbox(im_str!($name), &mut is_disabled);
+                            if is_disabled {
+                                ctx.world_renderer.disabled_features |= $feature;
+                            } else {
+                                ctx.world_renderer.disabled_features &= !$feature;
+                            }
+                        };
+                    }
+
                     do_flag!(
                         RenderOverrideFlags::FORCE_FACE_NORMALS,
                         "Force face normals"
@@ -270,6 +812,16 @@ impl RuntimeState {
                     );
                     do_flag!(RenderOverrideFlags::NO_METAL, "No metal");
 
+                    do_feature!(RenderFeatureFlags::SSGI, "No SSGI");
+                    do_feature!(RenderFeatureFlags::RTDGI, "No RTDGI");
+                    do_feature!(RenderFeatureFlags::RTR, "No reflections");
+                    do_flag!(RenderOverrideFlags::NO_IRCACHE, "No irradiance cache");
+                    do_feature!(RenderFeatureFlags::SUN_SHADOWS, "No sun shadows");
+                    do_feature!(RenderFeatureFlags::TAA, "No TAA");
+                    do_feature!(RenderFeatureFlags::VOLUMETRICS, "No volumetrics");
+                    do_feature!(RenderFeatureFlags::BLOOM, "No bloom");
+                    do_feature!(RenderFeatureFlags::MOTION_BLUR, "No motion blur");
+
                     imgui::Drag::<f32>::new(im_str!("Roughness scale"))
                         .range(0.0..=4.0)
                         .speed(0.001)
@@ -277,6 +829,28 @@ impl RuntimeState {
                             ui,
                             &mut ctx.world_renderer.render_overrides.material_roughness_scale,
                         );
+
+                    for category in MaterialCategory::ALL {
+                        let id_token = ui.push_id(category as i32);
+                        let settings =
+                            &mut ctx.world_renderer.material_categories[category as usize];
+
+                        ui.text(format!("{} materials", category.name()));
+                        imgui::Drag::<f32>::new(im_str!("GI strength"))
+                            .range(0.0..=4.0)
+                            .speed(0.01)
+                            .build(ui, &mut settings.gi_strength);
+                        imgui::Drag::<f32>::new(im_str!("Transmission scale"))
+                            .range(0.0..=4.0)
+                            .speed(0.01)
+                            .build(ui, &mut settings.transmission_scale);
+                        imgui::Drag::<f32>::new(im_str!("Sheen"))
+                            .range(0.0..=1.0)
+                            .speed(0.01)
+                            .build(ui, &mut settings.sheen);
+
+                        id_token.pop(ui);
+                    }
                 }
 
                 if imgui::CollapsingHeader::new(im_str!("Sequence"))
@@ -398,9 +972,216 @@ impl RuntimeState {
                             im_str!("Reflections"),
                             im_str!("RTX OFF"),
                             im_str!("Irradiance cache"),
+                            im_str!("Ambient occlusion"),
+                            im_str!("Material categories"),
                         ],
                     );
 
+                    ui.checkbox(
+                        im_str!("EV false color"),
+                        &mut ctx
+                            .world_renderer
+                            .frame_graph_config
+                            .debug_overlays
+                            .ev_false_color,
+                    );
+
+                    let debug_overlays = &mut ctx.world_renderer.frame_graph_config.debug_overlays;
+                    ui.checkbox(
+                        im_str!("Motion vectors"),
+                        &mut debug_overlays.motion_vectors,
+                    );
+                    ui.checkbox(
+                        im_str!("Motion vector histogram"),
+                        &mut debug_overlays.motion_vector_histogram,
+                    );
+
+                    if debug_overlays.motion_vectors {
+                        imgui::Drag::<f32>::new(im_str!("Motion vector scale (px)"))
+                            .range(0.1..=256.0)
+                            .speed(0.1)
+                            .build(
+                                ui,
+                                &mut ctx.world_renderer.motion_vectors.visualization_scale_pixels,
+                            );
+                    }
+
+                    if let Some(histogram) = ctx.world_renderer.motion_vectors.histogram() {
+                        let pixel_count = histogram.pixel_count().max(1) as f32;
+                        let bins: Vec<f32> = histogram
+                            .bins
+                            .iter()
+                            .map(|&count| count as f32 / pixel_count)
+                            .collect();
+                        ui.plot_histogram(im_str!("Speed (log2 px)"), &bins)
+                            .scale_min(0.0)
+                            .scale_max(1.0)
+                            .graph_size([0.0, 60.0])
+                            .build();
+                        ui.text(format!(
+                            "Max speed: {:.2} px/frame",
+                            histogram.max_velocity_pixels
+                        ));
+                    }
+
+                    let taa = &mut ctx.world_renderer.taa;
+                    imgui::Drag::<u32>::new(im_str!("TAA velocity dilation"))
+                        .range(0..=MAX_VELOCITY_DILATION_RADIUS)
+                        .build(ui, &mut taa.velocity_dilation_radius);
+                    ui.checkbox(
+                        im_str!("TAA always dilate velocity"),
+                        &mut taa.always_dilate_velocity,
+                    );
+
+                    {
+                        let mut ao_mode_idx = AmbientOcclusionMode::ALL
+                            .iter()
+                            .position(|&m| m == ctx.world_renderer.ambient_occlusion)
+                            .unwrap_or(0);
+
+                        let names = AmbientOcclusionMode::ALL
+                            .iter()
+                            .map(|m| imgui::ImString::new(m.name()))
+                            .collect::<Vec<_>>();
+                        let names = names
+                            .iter()
+                            .map(|n| n.as_ref())
+                            .collect::<Vec<&imgui::ImStr>>();
+
+                        if imgui::ComboBox::new(im_str!("Ambient occlusion")).build_simple_string(
+                            ui,
+                            &mut ao_mode_idx,
+                            &names,
+                        ) {
+                            ctx.world_renderer.ambient_occlusion =
+                                AmbientOcclusionMode::ALL[ao_mode_idx];
+                        }
+
+                        if ctx.world_renderer.ambient_occlusion.uses_rtao() {
+                            let rtao = &mut ctx.world_renderer.rtao;
+                            imgui::Drag::<f32>::new(im_str!("RTAO ray length"))
+                                .range(0.01..=10.0)
+                                .speed(0.01)
+                                .build(ui, &mut rtao.ray_length);
+                            imgui::Drag::<u32>::new(im_str!("RTAO rays per pixel"))
+                                .range(1..=16)
+                                .build(ui, &mut rtao.rays_per_pixel);
+                        }
+                    }
+
+                    let active_quality = ctx.world_renderer.active_quality();
+
+                    let frame_graph_config = &mut ctx.world_renderer.frame_graph_config;
+                    ui.checkbox(im_str!("SSGI"), &mut frame_graph_config.ssgi);
+                    ui.checkbox(im_str!("RTDGI"), &mut frame_graph_config.rtdgi);
+                    ui.checkbox(im_str!("Reflections"), &mut frame_graph_config.rtr);
+                    ui.checkbox(im_str!("Irradiance cache"), &mut frame_graph_config.ircache);
+                    ui.checkbox(
+                        im_str!("Ray-traced shadows"),
+                        &mut frame_graph_config.ray_traced_shadows,
+                    );
+
+                    let quality = &mut frame_graph_config.quality;
+                    let mut auto_quality = quality.auto.is_some();
+                    if ui.checkbox(im_str!("Auto quality"), &mut auto_quality) {
+                        quality.auto = auto_quality.then(Default::default);
+                    }
+
+                    if let Some(auto) = &mut quality.auto {
+                        imgui::Drag::<f32>::new(im_str!("GPU frame budget (ms)"))
+                            .range(1.0..=100.0)
+                            .speed(0.1)
+                            .build(ui, &mut auto.target_gpu_ms);
+
+                        ui.text(format!("Quality tier: {}", active_quality.rtr.name()));
+                    } else {
+                        let names = QualityTier::ALL
+                            .iter()
+                            .map(|tier| imgui::ImString::new(tier.name()))
+                            .collect::<Vec<_>>();
+                        let names = names
+                            .iter()
+                            .map(|n| n.as_ref())
+                            .collect::<Vec<&imgui::ImStr>>();
+
+                        for (label, tier) in [
+                            (im_str!("Reflection quality"), &mut quality.rtr),
+                            (im_str!("Diffuse GI quality"), &mut quality.rtdgi),
+                            (im_str!("SSGI quality"), &mut quality.ssgi),
+                            (im_str!("Volumetrics quality"), &mut quality.volumetrics),
+                            (im_str!("Shadow quality"), &mut quality.shadows),
+                        ] {
+                            let mut tier_idx = *tier as usize;
+                            if imgui::ComboBox::new(label).build_simple_string(
+                                ui,
+                                &mut tier_idx,
+                                &names,
+                            ) {
+                                *tier = QualityTier::ALL[tier_idx];
+                            }
+                        }
+                    }
+
+                    // Only shown for signals with denoisers registered by the app.
+                    let denoisers = &mut ctx.world_renderer.denoisers;
+                    for signal in DenoiserSignal::ALL {
+                        let names = std::iter::once("Built-in")
+                            .chain(denoisers.names(signal))
+                            .map(imgui::ImString::new)
+                            .collect::<Vec<_>>();
+                        if names.len() < 2 {
+                            continue;
+                        }
+
+                        let names = names
+                            .iter()
+                            .map(|n| n.as_ref())
+                            .collect::<Vec<&imgui::ImStr>>();
+                        let label = imgui::ImString::new(format!("{} denoiser", signal.name()));
+
+                        let mut denoiser_idx = denoisers.active(signal).map_or(0, |idx| idx + 1);
+                        if imgui::ComboBox::new(&label).build_simple_string(
+                            ui,
+                            &mut denoiser_idx,
+                            &names,
+                        ) {
+                            denoisers.set_active(signal, denoiser_idx.checked_sub(1));
+                        }
+                    }
+
+                    let dof = &mut frame_graph_config.dof;
+                    ui.checkbox(im_str!("Depth of field"), &mut dof.enabled);
+
+                    if dof.enabled {
+                        imgui::Drag::<f32>::new(im_str!("f-number"))
+                            .range(0.7..=32.0)
+                            .speed(0.05)
+                            .build(ui, &mut persisted.camera.f_number);
+
+                        ui.checkbox(im_str!("Autofocus"), &mut dof.autofocus);
+
+                        if !dof.autofocus {
+                            imgui::Drag::<f32>::new(im_str!("Focus distance"))
+                                .range(0.1..=1000.0)
+                                .speed(0.05)
+                                .build(ui, &mut persisted.camera.focus_distance);
+                        }
+
+                        imgui::Drag::<f32>::new(im_str!("Near field bleed"))
+                            .range(0.0..=1.0)
+                            .speed(0.01)
+                            .build(ui, &mut dof.near_field_bleed);
+
+                        imgui::Drag::<u32>::new(im_str!("Aperture blades"))
+                            .range(0..=12)
+                            .build(ui, &mut dof.bokeh_blade_count);
+
+                        imgui::Drag::<f32>::new(im_str!("Bokeh aspect ratio"))
+                            .range(1.0..=2.0)
+                            .speed(0.01)
+                            .build(ui, &mut dof.bokeh_aspect_ratio);
+                    }
+
                     imgui::Drag::<u32>::new(im_str!("Max FPS"))
                         .range(1..=MAX_FPS_LIMIT)
                         .build(ui, &mut self.max_fps);
@@ -408,6 +1189,40 @@ impl RuntimeState {
                     ui.checkbox(im_str!("Allow pass overlap"), unsafe {
                         &mut kajiya::rg::RG_ALLOW_PASS_OVERLAP
                     });
+
+                    ui.checkbox(im_str!("Allow async compute"), unsafe {
+                        &mut kajiya::rg::RG_ALLOW_ASYNC_COMPUTE
+                    });
+
+                    if ui.button(im_str!("Dump frame"), [0.0, 0.0]) {
+                        ctx.world_renderer
+                            .dump_frame(kajiya::frame_dump::default_frame_dump_dir());
+                    }
+
+                    if ctx.world_renderer.image_metrics.has_reference_image() {
+                        let image_metrics = &mut ctx.world_renderer.image_metrics;
+
+                        if let Some(metrics) = image_metrics.latest() {
+                            ui.text(format!(
+                                "Reference RMSE: {:.5}, FLIP: {:.5}",
+                                metrics.rmse, metrics.mean_flip
+                            ));
+                        }
+
+                        let history = image_metrics.history();
+                        let flip: Vec<f32> = history
+                            .iter()
+                            .skip(history.len().saturating_sub(256))
+                            .map(|metrics| metrics.mean_flip)
+                            .collect();
+                        ui.plot_lines(im_str!("FLIP"), &flip)
+                            .graph_size([0.0, 60.0])
+                            .build();
+
+                        if ui.button(im_str!("Clear metrics history"), [0.0, 0.0]) {
+                            image_metrics.clear_history();
+                        }
+                    }
                 }
 
                 if imgui::CollapsingHeader::new(im_str!("GPU passes"))
@@ -416,20 +1231,22 @@ impl RuntimeState {
                 {
                     ui.text(format!("CPU frame time: {:.3}ms", ctx.dt_filtered * 1000.0));
 
-                    if let Some(report) = gpu_profiler::profiler().last_report() {
-                        let ordered_scopes = report.scopes.as_slice();
-                        let gpu_time_ms: f64 =
-                            ordered_scopes.iter().map(|scope| scope.duration.ms()).sum();
+                    let pass_timings = ctx.world_renderer.last_frame_gpu_timings();
+                    // Applied after the loop, which borrows the timings from the renderer
+                    let mut hovered_rg_debug_hook = None;
+
+                    if !pass_timings.is_empty() {
+                        let gpu_time_ms: f32 = pass_timings.iter().map(|(_, ms)| ms).sum();
 
                         ui.text(format!("GPU frame time: {:.3}ms", gpu_time_ms));
 
-                        for (scope_index, scope) in ordered_scopes.iter().enumerate() {
-                            if scope.name == "debug" || scope.name.starts_with('_') {
+                        for (scope_index, (name, ms)) in pass_timings.iter().enumerate() {
+                            if name == "debug" || name.starts_with('_') {
                                 continue;
                             }
 
                             let render_debug_hook = kajiya::rg::RenderDebugHook {
-                                name: scope.name.clone(),
+                                name: name.clone(),
                                 id: scope_index as u64,
                             };
 
@@ -444,28 +1261,31 @@ impl RuntimeState {
                                 }
                             });
 
-                            ui.text(format!("{}: {:.3}ms", scope.name, scope.duration.ms()));
+                            ui.text(format!("{}: {:.3}ms", name, ms));
 
                             if let Some(style) = style {
                                 style.pop(ui);
                             }
 
                             if ui.is_item_hovered() {
-                                ctx.world_renderer.rg_debug_hook =
-                                    Some(kajiya::rg::GraphDebugHook { render_debug_hook });
+                                let hook = Some(kajiya::rg::GraphDebugHook { render_debug_hook });
 
                                 if ui.is_item_clicked(imgui::MouseButton::Left) {
-                                    if self.locked_rg_debug_hook == ctx.world_renderer.rg_debug_hook
-                                    {
+                                    if self.locked_rg_debug_hook == hook {
                                         self.locked_rg_debug_hook = None;
                                     } else {
-                                        self.locked_rg_debug_hook =
-                                            ctx.world_renderer.rg_debug_hook.clone();
+                                        self.locked_rg_debug_hook = hook.clone();
                                     }
                                 }
+
+                                hovered_rg_debug_hook = hook;
                             }
                         }
                     }
+
+                    if hovered_rg_debug_hook.is_some() {
+                        ctx.world_renderer.rg_debug_hook = hovered_rg_debug_hook;
+                    }
                 }
             });
         }
@@ -1,3 +1,4 @@
+mod frame_graph;
 mod gui;
 mod keymap;
 mod misc;
@@ -27,20 +28,38 @@ struct AppState {
 
 impl AppState {
     fn new(mut persisted: PersistedState, opt: &Opt) -> anyhow::Result<Self> {
-        let mut kajiya = SimpleMainLoop::builder()
+        let builder = SimpleMainLoop::builder()
             .resolution([opt.width, opt.height])
             .vsync(!opt.no_vsync)
+            .color_space(opt.hdr.unwrap_or_default())
             .graphics_debugging(opt.graphics_debugging)
             .physical_device_index(opt.physical_device_index)
+            .ray_tracing_mode(if opt.ray_query {
+                RayTracingMode::RayQuery
+            } else {
+                RayTracingMode::Pipeline
+            })
             .temporal_upsampling(opt.temporal_upsampling)
+            .render_graph_leak_detection(opt.rg_leak_detection_frames)
             .default_log_level(log::LevelFilter::Info)
-            .fullscreen(opt.fullscreen.then_some(FullscreenMode::Exclusive))
-            .build(
-                WindowBuilder::new()
-                    .with_title("kajiya")
-                    .with_resizable(false)
-                    .with_decorations(!opt.no_window_decorations),
-            )?;
+            .fullscreen(opt.fullscreen.then_some(FullscreenMode::Exclusive));
+
+        #[cfg(feature = "live-view")]
+        let builder = if let Some(address) = opt.live_view.clone() {
+            builder.live_view(LiveViewConfig {
+                address,
+                ..Default::default()
+            })
+        } else {
+            builder
+        };
+
+        let mut kajiya = builder.build(
+            WindowBuilder::new()
+                .with_title("kajiya")
+                .with_resizable(false)
+                .with_decorations(!opt.no_window_decorations),
+        )?;
 
         let runtime = RuntimeState::new(&mut persisted, &mut kajiya.world_renderer, opt);
 
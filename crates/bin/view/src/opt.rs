@@ -1,5 +1,6 @@
 use std::path::PathBuf;
 
+use kajiya_simple::SwapchainColorSpace;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -26,6 +27,10 @@ pub struct Opt {
     #[structopt(long)]
     pub no_vsync: bool,
 
+    /// Present in HDR, as `hdr10` or `scrgb`. Falls back to SDR if the display can't.
+    #[structopt(long, parse(try_from_str = parse_hdr_color_space))]
+    pub hdr: Option<SwapchainColorSpace>,
+
     #[structopt(long)]
     pub no_window_decorations: bool,
 
@@ -38,6 +43,43 @@ pub struct Opt {
     #[structopt(long)]
     pub physical_device_index: Option<usize>,
 
+    /// Trace shadows, reflections and diffuse GI with inline ray queries from compute shaders,
+    /// instead of ray tracing pipelines.
+    #[structopt(long)]
+    pub ray_query: bool,
+
+    /// Warn about render graph resources unused for this many frames, and print
+    /// per-pass GPU memory watermarks on exit. Zero disables.
+    #[structopt(long, default_value = "0")]
+    pub rg_leak_detection_frames: u64,
+
     #[structopt(long)]
     pub keymap: Option<PathBuf>,
+
+    /// Image to compare every frame against, for RMSE and FLIP error metrics.
+    /// EXR and HDR files are taken as linear; anything else as sRGB-encoded.
+    #[structopt(long)]
+    pub reference_image: Option<PathBuf>,
+
+    /// Appends the per-frame metrics from `--reference-image` to this CSV file.
+    #[structopt(long)]
+    pub metrics_csv: Option<PathBuf>,
+
+    /// Address to stream the frames to browsers on, e.g. `127.0.0.1:8787`.
+    /// Only bind to other interfaces, e.g. `0.0.0.0:8787`, on trusted networks.
+    #[cfg(feature = "live-view")]
+    #[structopt(long)]
+    pub live_view: Option<String>,
+
+    /// RON or TOML file with the frame graph config; reloaded when modified.
+    #[structopt(long, default_value = "frame_graph.ron")]
+    pub frame_graph: PathBuf,
+}
+
+fn parse_hdr_color_space(s: &str) -> anyhow::Result<SwapchainColorSpace> {
+    match s {
+        "hdr10" => Ok(SwapchainColorSpace::Hdr10),
+        "scrgb" => Ok(SwapchainColorSpace::ScRgb),
+        _ => Err(anyhow::anyhow!("Expected `hdr10` or `scrgb`, got {:?}", s)),
+    }
 }
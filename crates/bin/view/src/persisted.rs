@@ -1,14 +1,23 @@
 use std::path::PathBuf;
 
-use kajiya::world_renderer::InstanceHandle;
+use kajiya::{
+    time_of_day::TimeOfDay,
+    world_renderer::{ExposureMeteringMode, InstanceHandle, LightHandle, Tonemapper},
+};
 use kajiya_simple::{Affine3A, EulerRot, Mat2, Quat, Vec2, Vec3, Vec3Swizzles};
 
-use crate::{misc::smoothstep, sequence::Sequence};
+use crate::{misc::smoothstep, scene::SceneLightDesc, sequence::Sequence};
 
 #[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub struct SunState {
     pub controller: SunController,
     pub size_multiplier: f32,
+
+    /// Drive the sun from `time_of_day` instead of the mouse
+    #[serde(default)]
+    pub use_time_of_day: bool,
+    #[serde(default)]
+    pub time_of_day: TimeOfDay,
 }
 
 impl Default for SunState {
@@ -16,6 +25,8 @@ impl Default for SunState {
         Self {
             controller: SunController::default(),
             size_multiplier: 1.0,
+            use_time_of_day: false,
+            time_of_day: TimeOfDay::default(),
         }
     }
 }
@@ -147,6 +158,20 @@ pub struct CameraState {
     pub position: Vec3,
     pub rotation: Quat,
     pub vertical_fov: f32,
+
+    // Depth of field; see `CameraLens`
+    #[serde(default = "default_f_number")]
+    pub f_number: f32,
+    #[serde(default = "default_focus_distance")]
+    pub focus_distance: f32,
+}
+
+fn default_f_number() -> f32 {
+    2.8
+}
+
+fn default_focus_distance() -> f32 {
+    10.0
 }
 
 impl Default for CameraState {
@@ -155,6 +180,8 @@ impl Default for CameraState {
             position: Vec3::ONE,
             rotation: Quat::IDENTITY,
             vertical_fov: 62.0,
+            f_number: default_f_number(),
+            focus_distance: default_focus_distance(),
         }
     }
 }
@@ -224,19 +251,39 @@ fn default_contrast() -> f32 {
     1.0
 }
 
+fn default_dynamic_adaptation_min_ev() -> f32 {
+    -16.0
+}
+
+fn default_dynamic_adaptation_max_ev() -> f32 {
+    16.0
+}
+
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct ExposureState {
     pub ev_shift: f32,
     #[serde(default)]
     pub use_dynamic_adaptation: bool,
     #[serde(default)]
+    pub dynamic_adaptation_metering: ExposureMeteringMode,
+    #[serde(default)]
     pub dynamic_adaptation_speed: f32,
     #[serde(default)]
+    pub dynamic_adaptation_speed_down: f32,
+    #[serde(default = "default_dynamic_adaptation_min_ev")]
+    pub dynamic_adaptation_min_ev: f32,
+    #[serde(default = "default_dynamic_adaptation_max_ev")]
+    pub dynamic_adaptation_max_ev: f32,
+    #[serde(default)]
+    pub lock_dynamic_adaptation: bool,
+    #[serde(default)]
     pub dynamic_adaptation_low_clip: f32,
     #[serde(default)]
     pub dynamic_adaptation_high_clip: f32,
     #[serde(default = "default_contrast")]
     pub contrast: f32,
+    #[serde(default)]
+    pub tonemapper: Tonemapper,
 }
 
 impl Default for ExposureState {
@@ -244,10 +291,16 @@ impl Default for ExposureState {
         Self {
             ev_shift: 0.0,
             use_dynamic_adaptation: false,
+            dynamic_adaptation_metering: ExposureMeteringMode::default(),
             dynamic_adaptation_speed: 0.0,
+            dynamic_adaptation_speed_down: 0.0,
+            dynamic_adaptation_min_ev: default_dynamic_adaptation_min_ev(),
+            dynamic_adaptation_max_ev: default_dynamic_adaptation_max_ev(),
+            lock_dynamic_adaptation: false,
             dynamic_adaptation_low_clip: 0.0,
             dynamic_adaptation_high_clip: 0.0,
             contrast: default_contrast(),
+            tonemapper: Tonemapper::default(),
         }
     }
 }
@@ -297,17 +350,48 @@ pub struct SceneElement {
     pub transform: SceneElementTransform,
 }
 
-#[derive(Clone, Default, serde::Serialize, serde::Deserialize)]
+#[derive(Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct SceneLight {
+    #[serde(skip)]
+    pub handle: Option<LightHandle>,
+
+    pub desc: SceneLightDesc,
+}
+
+fn default_world_units_per_meter() -> f32 {
+    1.0
+}
+
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
 pub struct SceneState {
     pub elements: Vec<SceneElement>,
 
+    #[serde(default)]
+    pub lights: Vec<SceneLight>,
+
     #[serde(default)]
     pub ibl: Option<PathBuf>,
+
+    #[serde(default = "default_world_units_per_meter")]
+    pub world_units_per_meter: f32,
+}
+
+impl Default for SceneState {
+    fn default() -> Self {
+        Self {
+            elements: Default::default(),
+            lights: Default::default(),
+            ibl: None,
+            world_units_per_meter: default_world_units_per_meter(),
+        }
+    }
 }
 
 impl ShouldResetPathTracer for SceneState {
     fn should_reset_path_tracer(&self, other: &Self) -> bool {
         self.elements != other.elements
+            || self.lights != other.lights
+            || self.world_units_per_meter != other.world_units_per_meter
     }
 }
 
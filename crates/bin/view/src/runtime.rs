@@ -4,14 +4,18 @@ use anyhow::Context;
 
 use dolly::prelude::*;
 use kajiya::{
+    renderers::color_grading::CubeLut,
     rg::GraphDebugHook,
-    world_renderer::{AddMeshOptions, MeshHandle, WorldRenderer},
+    world_renderer::{AddMeshOptions, AutoExposure, HistogramClipping, MeshHandle, WorldRenderer},
 };
 use kajiya_simple::*;
 
 use crate::{
+    frame_graph::FrameGraphConfigWatcher,
     opt::Opt,
-    persisted::{MeshSource, SceneElement, SceneElementTransform, ShouldResetPathTracer as _},
+    persisted::{
+        MeshSource, SceneElement, SceneElementTransform, SceneLight, ShouldResetPathTracer as _,
+    },
     scene::SceneDesc,
     sequence::{CameraPlaybackSequence, MemOption, SequenceValue},
     PersistedState,
@@ -23,6 +27,7 @@ use std::{
     collections::{hash_map::DefaultHasher, HashMap},
     fs::File,
     hash::{Hash, Hasher},
+    io::Write as _,
     path::PathBuf,
 };
 
@@ -34,9 +39,15 @@ pub struct RuntimeState {
     pub keyboard: KeyboardState,
     pub keymap_config: KeymapConfig,
     pub movement_map: KeyboardMap,
+    pub frame_graph_config: FrameGraphConfigWatcher,
 
     pub show_gui: bool,
     pub sun_direction_interp: Vec3,
+
+    // So that the sky parameters are only reset when time of day gets switched off,
+    // and can be tweaked otherwise.
+    time_of_day_was_used: bool,
+
     pub left_click_edit_mode: LeftClickEditMode,
 
     pub max_fps: u32,
@@ -49,7 +60,14 @@ pub struct RuntimeState {
     sequence_playback_state: SequencePlaybackState,
     pub sequence_playback_speed: f32,
 
+    // Frames accumulated by the photo mode
+    pub photo_sample_count: u32,
+
     known_meshes: HashMap<PathBuf, MeshHandle>,
+
+    // See `Opt::metrics_csv`
+    metrics_csv: Option<File>,
+    metrics_csv_rows_written: usize,
 }
 
 enum SequencePlaybackState {
@@ -94,9 +112,11 @@ impl RuntimeState {
             keyboard,
             keymap_config: keymap_config.clone(),
             movement_map: keymap_config.movement.into(),
+            frame_graph_config: FrameGraphConfigWatcher::new(&opt.frame_graph),
 
             show_gui: false,
             sun_direction_interp,
+            time_of_day_was_used: false,
             left_click_edit_mode: LeftClickEditMode::MoveSun,
 
             max_fps: MAX_FPS_LIMIT,
@@ -109,7 +129,12 @@ impl RuntimeState {
             sequence_playback_state: SequencePlaybackState::NotPlaying,
             sequence_playback_speed: 1.0,
 
+            photo_sample_count: 64,
+
             known_meshes: Default::default(),
+
+            metrics_csv: None,
+            metrics_csv_rows_written: 0,
         };
 
         // Load meshes that the persisted scene was referring to
@@ -118,6 +143,7 @@ impl RuntimeState {
                 Ok(mesh) => {
                     elem.instance =
                         world_renderer.add_instance(mesh, elem.transform.affine_transform());
+                    world_renderer.set_instance_static(elem.instance, true);
                     true
                 }
                 Err(err) => {
@@ -127,6 +153,12 @@ impl RuntimeState {
             }
         });
 
+        for light in &mut persisted.scene.lights {
+            light.handle = Some(world_renderer.add_light(light.desc.to_light()));
+        }
+
+        world_renderer.world_units_per_meter = persisted.scene.world_units_per_meter;
+
         // Load the IBL too
         if let Some(ibl) = persisted.scene.ibl.as_ref() {
             if world_renderer.ibl.load_image(ibl).is_err() {
@@ -134,9 +166,60 @@ impl RuntimeState {
             }
         }
 
+        if let Some(path) = opt.reference_image.as_ref() {
+            if let Err(err) = world_renderer.image_metrics.load_reference_image(path) {
+                log::error!("Failed to load the reference image {:?}: {:#}", path, err);
+            }
+        }
+
+        if let Some(path) = opt.metrics_csv.as_ref() {
+            match File::create(path).and_then(|mut file| {
+                writeln!(file, "frame,rmse,mean_flip")?;
+                Ok(file)
+            }) {
+                Ok(file) => res.metrics_csv = Some(file),
+                Err(err) => log::error!("Failed to create {:?}: {}", path, err),
+            }
+        }
+
         res
     }
 
+    fn write_metrics_csv(&mut self, world_renderer: &WorldRenderer) {
+        let file = if let Some(file) = self.metrics_csv.as_mut() {
+            file
+        } else {
+            return;
+        };
+
+        let image_metrics = &world_renderer.image_metrics;
+        let history = image_metrics.history();
+        let history_len = image_metrics.history_dropped_len() + history.len();
+
+        // The history restarts when the reference image changes.
+        if history_len < self.metrics_csv_rows_written {
+            self.metrics_csv_rows_written = 0;
+        }
+
+        let rows_to_skip = self
+            .metrics_csv_rows_written
+            .saturating_sub(image_metrics.history_dropped_len());
+
+        for metrics in history.iter().skip(rows_to_skip) {
+            if let Err(err) = writeln!(
+                file,
+                "{},{},{}",
+                metrics.frame_index, metrics.rmse, metrics.mean_flip
+            ) {
+                log::error!("Failed to write image metrics: {}", err);
+                self.metrics_csv = None;
+                return;
+            }
+        }
+
+        self.metrics_csv_rows_written = history_len;
+    }
+
     pub fn clear_scene(
         &mut self,
         persisted: &mut PersistedState,
@@ -145,6 +228,12 @@ impl RuntimeState {
         for elem in persisted.scene.elements.drain(..) {
             world_renderer.remove_instance(elem.instance);
         }
+
+        for light in persisted.scene.lights.drain(..) {
+            if let Some(handle) = light.handle {
+                world_renderer.remove_light(handle);
+            }
+        }
     }
 
     pub fn load_scene(
@@ -161,6 +250,9 @@ impl RuntimeState {
 
         self.clear_scene(persisted, world_renderer);
 
+        persisted.scene.world_units_per_meter = scene_desc.world_units_per_meter;
+        world_renderer.world_units_per_meter = scene_desc.world_units_per_meter;
+
         for instance in scene_desc.instances {
             let mesh_path = canonical_path_from_vfs(&instance.mesh)
                 .with_context(|| format!("Mesh path: {:?}", instance.mesh))
@@ -186,6 +278,13 @@ impl RuntimeState {
             });
         }
 
+        for desc in scene_desc.lights {
+            persisted.scene.lights.push(SceneLight {
+                handle: Some(world_renderer.add_light(desc.to_light())),
+                desc,
+            });
+        }
+
         Ok(())
     }
 
@@ -235,9 +334,23 @@ impl RuntimeState {
             );
         }
 
-        self.camera
-            .driver_mut::<Position>()
-            .translate(move_vec * ctx.dt_filtered * persisted.movement.camera_speed);
+        // Dragging in the browser looks around like holding the right mouse button here.
+        #[cfg(feature = "live-view")]
+        for event in ctx.live_view_events {
+            if let LiveViewEvent::Look { delta } = event {
+                let sensitivity = 0.1;
+                self.camera
+                    .driver_mut::<YawPitch>()
+                    .rotate_yaw_pitch(-sensitivity * delta.x, -sensitivity * delta.y);
+            }
+        }
+
+        self.camera.driver_mut::<Position>().translate(
+            move_vec
+                * ctx.dt_filtered
+                * persisted.movement.camera_speed
+                * persisted.scene.world_units_per_meter,
+        );
 
         if let SequencePlaybackState::Playing { t, sequence } = &mut self.sequence_playback_state {
             let smooth = self.camera.driver_mut::<Smooth>();
@@ -316,6 +429,19 @@ impl RuntimeState {
         //state.sun.phi += dt;
         //state.sun.phi %= std::f32::consts::TAU;
 
+        if persisted.light.sun.use_time_of_day {
+            let time_of_day = &mut persisted.light.sun.time_of_day;
+            time_of_day.advance(ctx.dt_filtered);
+            time_of_day.apply_sky_params(ctx.world_renderer);
+
+            let towards_sun = time_of_day.sun_direction();
+            persisted.light.sun.controller.set_towards_sun(towards_sun);
+        } else if self.time_of_day_was_used {
+            ctx.world_renderer.sun_color_multiplier = Vec3::ONE;
+            ctx.world_renderer.sky_ambient = Vec3::ZERO;
+        }
+        self.time_of_day_was_used = persisted.light.sun.use_time_of_day;
+
         let sun_direction = persisted.light.sun.controller.towards_sun();
         if (sun_direction.dot(self.sun_direction_interp) - 1.0).abs() > 1e-5 {
             self.reset_path_tracer = true;
@@ -329,8 +455,6 @@ impl RuntimeState {
 
         self.sun_direction_interp =
             Vec3::lerp(self.sun_direction_interp, sun_direction, sun_interp_t).normalize();
-
-        ctx.world_renderer.sun_size_multiplier = persisted.light.sun.size_multiplier;
     }
 
     fn update_lights(&mut self, persisted: &mut PersistedState, ctx: &mut FrameContext) {
@@ -374,7 +498,7 @@ impl RuntimeState {
             light_instances.extend(
                 (0..(state.lights.count as usize - light_instances.len())).map(|_| {
                     ctx.world_renderer
-                        .add_instance(light_mesh, Vec3::ZERO, Quat::IDENTITY)
+                        .add_instance(light_mesh, Affine3A::IDENTITY)
                 }),
             );
         }
@@ -389,8 +513,10 @@ impl RuntimeState {
                     * ring_rot;
             ctx.world_renderer.set_instance_transform(
                 *inst,
-                rot * (Vec3::Z * state.lights.distance) + Vec3::new(0.1, 1.2, 0.0),
-                rot,
+                Affine3A::from_rotation_translation(
+                    rot,
+                    rot * (Vec3::Z * state.lights.distance) + Vec3::new(0.1, 1.2, 0.0),
+                ),
             );
 
             ctx.world_renderer
@@ -427,19 +553,27 @@ impl RuntimeState {
             ));
         }
 
+        #[cfg(feature = "live-view")]
+        self.keyboard.apply_live_view_events(ctx.live_view_events);
         self.keyboard.update(ctx.events);
         self.mouse.update(ctx.events);
         self.handle_file_drop_events(persisted, ctx.world_renderer, ctx.events);
+        self.update_frame_graph_config(&mut ctx);
+        self.write_metrics_csv(ctx.world_renderer);
 
         let orig_persisted_state = persisted.clone();
         let orig_render_overrides = ctx.world_renderer.render_overrides;
 
         self.do_gui(persisted, &mut ctx);
-        self.update_lights(persisted, &mut ctx);
-        self.update_objects(persisted, &mut ctx);
-        self.update_sun(persisted, &mut ctx);
 
-        self.update_camera(persisted, &ctx);
+        // Time stands still, and the camera is locked while the photo mode accumulates a still.
+        if !ctx.world_renderer.photo_mode.is_active() {
+            self.update_lights(persisted, &mut ctx);
+            self.update_objects(persisted, &mut ctx);
+            self.update_sun(persisted, &mut ctx);
+
+            self.update_camera(persisted, &ctx);
+        }
 
         if self
             .keyboard
@@ -465,18 +599,26 @@ impl RuntimeState {
 
         ctx.world_renderer.ev_shift = persisted.exposure.ev_shift;
         ctx.world_renderer.contrast = persisted.exposure.contrast;
-        ctx.world_renderer.dynamic_exposure.enabled = persisted.exposure.use_dynamic_adaptation;
-        ctx.world_renderer.dynamic_exposure.speed_log2 =
-            persisted.exposure.dynamic_adaptation_speed;
-        ctx.world_renderer.dynamic_exposure.histogram_clipping.low =
-            persisted.exposure.dynamic_adaptation_low_clip;
-        ctx.world_renderer.dynamic_exposure.histogram_clipping.high =
-            persisted.exposure.dynamic_adaptation_high_clip;
+        ctx.world_renderer.tonemapper = persisted.exposure.tonemapper;
+        ctx.world_renderer.auto_exposure = AutoExposure {
+            enabled: persisted.exposure.use_dynamic_adaptation,
+            metering_mode: persisted.exposure.dynamic_adaptation_metering,
+            histogram_clipping: HistogramClipping {
+                low: persisted.exposure.dynamic_adaptation_low_clip,
+                high: persisted.exposure.dynamic_adaptation_high_clip,
+            },
+            speed_up_log2: persisted.exposure.dynamic_adaptation_speed,
+            speed_down_log2: persisted.exposure.dynamic_adaptation_speed_down,
+            min_ev: persisted.exposure.dynamic_adaptation_min_ev,
+            max_ev: persisted.exposure.dynamic_adaptation_max_ev,
+            locked: persisted.exposure.lock_dynamic_adaptation,
+        };
 
         if persisted.should_reset_path_tracer(&orig_persisted_state)
             || ctx.world_renderer.render_overrides != orig_render_overrides
         {
             self.reset_path_tracer = true;
+            ctx.world_renderer.photo_mode.restart();
         }
 
         // Reset accumulation of the path tracer whenever the camera moves
@@ -493,8 +635,11 @@ impl RuntimeState {
         let lens = CameraLens {
             aspect_ratio: ctx.aspect_ratio(),
             vertical_fov: persisted.camera.vertical_fov,
+            f_number: persisted.camera.f_number,
+            focus_distance: persisted.camera.focus_distance,
             ..Default::default()
-        };
+        }
+        .with_world_units_per_meter(persisted.scene.world_units_per_meter);
 
         WorldFrameDesc {
             camera_matrices: self
@@ -502,8 +647,27 @@ impl RuntimeState {
                 .final_transform
                 .into_position_rotation()
                 .through(&lens),
+            physical_camera: lens.physical_camera(),
             render_extent: ctx.render_extent,
             sun_direction: self.sun_direction_interp,
+            sun_size_multiplier: persisted.light.sun.size_multiplier,
+            disabled_features: 0,
+        }
+    }
+
+    fn update_frame_graph_config(&mut self, ctx: &mut FrameContext) {
+        match self.frame_graph_config.poll() {
+            Some(Ok(config)) => {
+                info!(
+                    "Loaded frame graph config from {:?}",
+                    self.frame_graph_config.path()
+                );
+                ctx.world_renderer.frame_graph_config = config;
+            }
+            Some(Err(err)) => {
+                log::error!("{:#}", err);
+            }
+            None => {}
         }
     }
 
@@ -692,6 +856,14 @@ impl RuntimeState {
                                 }
                             }
                         }
+                        "cube" => {
+                            // Color grading LUT
+                            if let Err(err) = CubeLut::load(path).and_then(|lut| {
+                                world_renderer.set_color_grading_lut(Some(lut.into()))
+                            }) {
+                                log::error!("Failed to load color grading LUT: {:#}", err);
+                            }
+                        }
                         "ron" => {
                             // Scene
                             if let Err(err) = self.load_scene(persisted, world_renderer, path) {
@@ -1,6 +1,20 @@
+use kajiya::world_renderer::{PointLight, PointLightUnit, SpotCone};
+use kajiya_simple::Vec3;
+
 #[derive(serde::Deserialize)]
 pub struct SceneDesc {
     pub instances: Vec<SceneInstanceDesc>,
+    #[serde(default)]
+    pub lights: Vec<SceneLightDesc>,
+
+    /// Length of a meter in the units of the scene, e.g. 100 for centimeters;
+    /// see `WorldRenderer::world_units_per_meter`.
+    #[serde(default = "default_world_units_per_meter")]
+    pub world_units_per_meter: f32,
+}
+
+fn default_world_units_per_meter() -> f32 {
+    1.0
 }
 
 fn default_instance_scale() -> [f32; 3] {
@@ -16,3 +30,59 @@ pub struct SceneInstanceDesc {
     pub rotation: [f32; 3],
     pub mesh: String,
 }
+
+fn default_light_color() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn default_light_radius() -> f32 {
+    PointLight::default().radius
+}
+
+fn default_light_volumetric_intensity() -> f32 {
+    PointLight::default().volumetric_intensity
+}
+
+/// A point light, or a spot light if `spot` is set; see `PointLight`.
+#[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SceneLightDesc {
+    pub position: [f32; 3],
+    #[serde(default = "default_light_color")]
+    pub color: [f32; 3],
+    pub intensity: f32,
+    #[serde(default)]
+    pub unit: PointLightUnit,
+    #[serde(default = "default_light_radius")]
+    pub radius: f32,
+    #[serde(default)]
+    pub spot: Option<SceneSpotDesc>,
+    #[serde(default = "default_light_volumetric_intensity")]
+    pub volumetric_intensity: f32,
+}
+
+#[derive(Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SceneSpotDesc {
+    pub direction: [f32; 3],
+    /// Half-angle in degrees
+    pub inner_angle: f32,
+    /// Half-angle in degrees
+    pub outer_angle: f32,
+}
+
+impl SceneLightDesc {
+    pub fn to_light(&self) -> PointLight {
+        PointLight {
+            position: Vec3::from(self.position),
+            color: Vec3::from(self.color),
+            intensity: self.intensity,
+            unit: self.unit,
+            radius: self.radius,
+            spot: self.spot.as_ref().map(|spot| SpotCone {
+                direction: Vec3::from(spot.direction),
+                inner_angle: spot.inner_angle.to_radians(),
+                outer_angle: spot.outer_angle.to_radians(),
+            }),
+            volumetric_intensity: self.volumetric_intensity,
+        }
+    }
+}
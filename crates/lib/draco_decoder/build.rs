@@ -0,0 +1,43 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+fn main() {
+    // Find the Draco headers and lib in this crate rather than in the including project
+    let dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    let draco_dir = Path::new(&dir).join("draco");
+
+    // Tell cargo to invalidate the built crate whenever the wrapper changes
+    println!("cargo:rerun-if-changed=wrapper.h");
+    println!("cargo:rerun-if-changed=wrapper.cpp");
+
+    cc::Build::new()
+        .cpp(true)
+        .flag_if_supported("-std=c++14")
+        .include(draco_dir.join("include"))
+        .file("wrapper.cpp")
+        .compile("draco_wrapper");
+
+    // After the wrapper, which depends on it
+    println!(
+        "cargo:rustc-link-search=native={}",
+        draco_dir.join("lib").display()
+    );
+    println!("cargo:rustc-link-lib=static=draco");
+
+    let bindings = bindgen::Builder::default()
+        .header("wrapper.h")
+        .allowlist_function("draco_.*")
+        .allowlist_type("Draco.*")
+        .size_t_is_usize(true)
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks))
+        .generate()
+        .expect("Unable to generate bindings");
+
+    // Write the bindings to the $OUT_DIR/bindings.rs file.
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+    bindings
+        .write_to_file(out_path.join("bindings.rs"))
+        .expect("Couldn't write bindings!");
+}
@@ -0,0 +1,88 @@
+#include "wrapper.h"
+
+#include <memory>
+
+#include "draco/compression/decode.h"
+
+struct DracoMesh {
+    std::unique_ptr<draco::Mesh> mesh;
+};
+
+DracoMesh* draco_decode_mesh(const uint8_t* data, size_t size) {
+    draco::DecoderBuffer buffer;
+    buffer.Init(reinterpret_cast<const char*>(data), size);
+
+    draco::Decoder decoder;
+    auto mesh = decoder.DecodeMeshFromBuffer(&buffer);
+    if (!mesh.ok()) {
+        return nullptr;
+    }
+
+    return new DracoMesh{std::move(mesh).value()};
+}
+
+void draco_mesh_free(DracoMesh* mesh) {
+    delete mesh;
+}
+
+uint32_t draco_mesh_point_count(const DracoMesh* mesh) {
+    return mesh->mesh->num_points();
+}
+
+uint32_t draco_mesh_face_count(const DracoMesh* mesh) {
+    return mesh->mesh->num_faces();
+}
+
+void draco_mesh_read_indices(const DracoMesh* mesh, uint32_t* out) {
+    for (draco::FaceIndex i(0); i < mesh->mesh->num_faces(); ++i) {
+        const draco::Mesh::Face& face = mesh->mesh->face(i);
+        for (int j = 0; j < 3; ++j) {
+            *out++ = face[j].value();
+        }
+    }
+}
+
+template <typename T>
+static bool read_attribute(
+    const draco::Mesh& mesh,
+    const draco::PointAttribute& attribute,
+    uint32_t component_count,
+    T* out) {
+    for (draco::PointIndex i(0); i < mesh.num_points(); ++i) {
+        const draco::AttributeValueIndex value = attribute.mapped_index(i);
+        if (!attribute.ConvertValue<T>(value, static_cast<int8_t>(component_count), out)) {
+            return false;
+        }
+        out += component_count;
+    }
+    return true;
+}
+
+bool draco_mesh_read_attribute(
+    const DracoMesh* mesh,
+    uint32_t unique_id,
+    uint32_t component_type,
+    uint32_t component_count,
+    void* out) {
+    const draco::PointAttribute* attribute = mesh->mesh->GetAttributeByUniqueId(unique_id);
+    if (!attribute) {
+        return false;
+    }
+
+    switch (component_type) {
+        case 5120:
+            return read_attribute(*mesh->mesh, *attribute, component_count, static_cast<int8_t*>(out));
+        case 5121:
+            return read_attribute(*mesh->mesh, *attribute, component_count, static_cast<uint8_t*>(out));
+        case 5122:
+            return read_attribute(*mesh->mesh, *attribute, component_count, static_cast<int16_t*>(out));
+        case 5123:
+            return read_attribute(*mesh->mesh, *attribute, component_count, static_cast<uint16_t*>(out));
+        case 5125:
+            return read_attribute(*mesh->mesh, *attribute, component_count, static_cast<uint32_t*>(out));
+        case 5126:
+            return read_attribute(*mesh->mesh, *attribute, component_count, static_cast<float*>(out));
+        default:
+            return false;
+    }
+}
@@ -0,0 +1,38 @@
+// A C interface to the parts of the Draco decoder needed for `KHR_draco_mesh_compression`,
+// implemented in `wrapper.cpp`.
+
+#pragma once
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct DracoMesh DracoMesh;
+
+// Returns null if the data isn't a valid Draco triangle mesh.
+DracoMesh* draco_decode_mesh(const uint8_t* data, size_t size);
+void draco_mesh_free(DracoMesh* mesh);
+
+uint32_t draco_mesh_point_count(const DracoMesh* mesh);
+uint32_t draco_mesh_face_count(const DracoMesh* mesh);
+
+// Writes `draco_mesh_face_count * 3` indices.
+void draco_mesh_read_indices(const DracoMesh* mesh, uint32_t* out);
+
+// Writes `draco_mesh_point_count * component_count` values of the attribute with `unique_id`,
+// converted to `component_type`, a glTF accessor component type (5120 to 5126).
+// Returns false if there's no such attribute, or it can't be converted.
+bool draco_mesh_read_attribute(
+    const DracoMesh* mesh,
+    uint32_t unique_id,
+    uint32_t component_type,
+    uint32_t component_count,
+    void* out);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,3 @@
+#include "vulkan.h"
+#include "FSR2/include/ffx_fsr2.h"
+#include "FSR2/include/vk/ffx_fsr2_vk.h"
@@ -0,0 +1,140 @@
+use anyhow::{bail, Context as _};
+use std::path::Path;
+
+use crate::{
+    ply::{ply_property_index, read_ply_vertices},
+    point_cloud::srgb_to_linear,
+};
+
+// Zeroth-order spherical harmonics basis constant
+const SH_C0: f32 = 0.282_094_8;
+
+/// 3D Gaussians, as trained by "3D Gaussian Splatting for Real-Time Radiance Field Rendering"
+/// by Kerbl et al., and its descendants.
+///
+/// Only the view-independent (DC) part of the color is kept.
+#[derive(Clone, Default)]
+pub struct GaussianSplats {
+    pub positions: Vec<[f32; 3]>,
+
+    /// Standard deviations along the local axes
+    pub scales: Vec<[f32; 3]>,
+
+    /// Normalized quaternions in `xyzw` order, rotating the local axes
+    pub rotations: Vec<[f32; 4]>,
+
+    /// In [0, 1]
+    pub opacities: Vec<f32>,
+
+    /// Linear RGB
+    pub colors: Vec<[f32; 3]>,
+}
+
+impl GaussianSplats {
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Column-major 3x3 covariance of each splat
+    pub fn covariance(&self, i: usize) -> [[f32; 3]; 3] {
+        let [x, y, z, w] = self.rotations[i];
+        let rot = [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y + w * z),
+                2.0 * (x * z - w * y),
+            ],
+            [
+                2.0 * (x * y - w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z + w * x),
+            ],
+            [
+                2.0 * (x * z + w * y),
+                2.0 * (y * z - w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ];
+        let scale = self.scales[i];
+
+        // R * S * S^T * R^T
+        let mut cov = [[0.0f32; 3]; 3];
+        for (col, cov_col) in cov.iter_mut().enumerate() {
+            for (row, value) in cov_col.iter_mut().enumerate() {
+                *value = (0..3)
+                    .map(|k| rot[k][row] * rot[k][col] * scale[k] * scale[k])
+                    .sum();
+            }
+        }
+        cov
+    }
+}
+
+/// Loads the `.ply` files written by the reference 3D Gaussian Splatting implementation
+/// and most tools following it. Higher-order spherical harmonics are ignored.
+pub fn load_gaussian_splats(path: impl AsRef<Path>) -> anyhow::Result<GaussianSplats> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).with_context(|| format!("Reading {:?}", path))?;
+
+    parse_gaussian_splat_ply(&data).with_context(|| format!("Loading Gaussian splats {:?}", path))
+}
+
+fn parse_gaussian_splat_ply(data: &[u8]) -> anyhow::Result<GaussianSplats> {
+    let mut splats = GaussianSplats::default();
+    let out = &mut splats;
+
+    read_ply_vertices(data, move |properties| {
+        let index = |name: &str| {
+            ply_property_index(properties, &[name])
+                .with_context(|| format!("PLY vertices have no {:?} property", name))
+        };
+
+        let pos_idx = [index("x")?, index("y")?, index("z")?];
+        let color_idx = [index("f_dc_0")?, index("f_dc_1")?, index("f_dc_2")?];
+        let opacity_idx = index("opacity")?;
+        let scale_idx = [index("scale_0")?, index("scale_1")?, index("scale_2")?];
+        let rot_idx = [
+            index("rot_0")?,
+            index("rot_1")?,
+            index("rot_2")?,
+            index("rot_3")?,
+        ];
+
+        Ok(move |values: &[f64]| {
+            let value = |idx: usize| values[idx] as f32;
+
+            out.positions.push(pos_idx.map(value));
+
+            // Stored as logarithms
+            out.scales.push(scale_idx.map(|idx| value(idx).exp()));
+
+            // Stored `wxyz`, and not necessarily normalized
+            let [w, x, y, z] = rot_idx.map(value);
+            let len = (w * w + x * x + y * y + z * z).sqrt();
+            out.rotations.push(if len > 0.0 {
+                [x / len, y / len, z / len, w / len]
+            } else {
+                [0.0, 0.0, 0.0, 1.0]
+            });
+
+            // Stored as logits
+            out.opacities
+                .push(1.0 / (1.0 + (-value(opacity_idx)).exp()));
+
+            // Training happens on sRGB-encoded images, so that's what the SH evaluate to.
+            out.colors.push(
+                color_idx.map(|idx| srgb_to_linear((0.5 + SH_C0 * value(idx)).clamp(0.0, 1.0))),
+            );
+        })
+    })?;
+
+    if splats.is_empty() {
+        bail!("PLY file has no splats");
+    }
+
+    Ok(splats)
+}
@@ -0,0 +1,1506 @@
+// Decompression of `EXT_meshopt_compression` buffer views, and with the `draco` feature,
+// of `KHR_draco_mesh_compression` primitives, done at import time, so that the rest
+// of the pipeline only ever sees plain glTF buffers.
+//
+// The meshopt decoders follow the bitstream described in the extension's spec, and produce
+// the same output as `meshopt_decodeVertexBuffer`, `meshopt_decodeIndexBuffer`,
+// `meshopt_decodeIndexSequence`, and the filters of `meshoptimizer`. Draco is decoded
+// by Google's library, through the `draco_decoder` crate.
+
+use anyhow::{bail, ensure, Context as _};
+use gltf::Document;
+use std::collections::{HashMap, HashSet};
+
+const MESHOPT_EXTENSION: &str = "EXT_meshopt_compression";
+const DRACO_EXTENSION: &str = "KHR_draco_mesh_compression";
+
+#[derive(Clone, Copy, Debug)]
+enum MeshoptMode {
+    Attributes,
+    Triangles,
+    Indices,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum MeshoptFilter {
+    None,
+    Octahedral,
+    Quaternion,
+    Exponential,
+}
+
+struct MeshoptView {
+    view: usize,
+
+    // Compressed data
+    source_buffer: usize,
+    source_offset: usize,
+    source_length: usize,
+
+    stride: usize,
+    count: usize,
+    mode: MeshoptMode,
+    filter: MeshoptFilter,
+}
+
+// An accessor of a Draco-compressed primitive, pointed at a view of the decompressed buffer
+#[cfg_attr(not(feature = "draco"), allow(dead_code))]
+struct DracoAccessor {
+    accessor: usize,
+    // Into the decompressed buffer
+    offset: usize,
+    count: usize,
+    component_type: u32,
+    component_count: usize,
+}
+
+#[cfg_attr(not(feature = "draco"), allow(dead_code))]
+struct DracoPrimitive {
+    mesh: usize,
+    primitive: usize,
+
+    // Compressed data
+    view: usize,
+
+    indices: DracoAccessor,
+    // Draco attribute ids, and the accessors they decompress into
+    attributes: Vec<(u32, DracoAccessor)>,
+}
+
+/// Compression extensions used by a glTF file. The `gltf` crate drops extensions it
+/// doesn't know about, so they're read from the JSON directly.
+#[derive(Default)]
+pub(crate) struct GltfCompression {
+    // Buffers with no data of their own, only backing decompressed views
+    fallback_buffers: HashSet<usize>,
+    meshopt_views: Vec<MeshoptView>,
+
+    draco_primitives: Vec<DracoPrimitive>,
+    // Appended to the file's buffers, and receiving the decompressed Draco accessors
+    draco_buffer: usize,
+    // The document's JSON, with Draco accessors pointed at views of `draco_buffer`
+    draco_json: Option<serde_json::Value>,
+}
+
+impl GltfCompression {
+    /// Parses the extensions from the contents of a `.gltf` or `.glb` file.
+    pub fn from_file_contents(data: &[u8]) -> anyhow::Result<Self> {
+        let mut json: serde_json::Value = serde_json::from_slice(json_chunk(data)?)?;
+
+        let is_required = |ext: &str| {
+            json["extensionsRequired"]
+                .as_array()
+                .map_or(false, |exts| exts.iter().any(|e| e == ext))
+        };
+
+        let draco_required = is_required(DRACO_EXTENSION);
+        let mut draco_primitives = parse_draco_primitives(&json)?;
+
+        if !cfg!(feature = "draco") && !draco_primitives.is_empty() {
+            // Without a fallback, Draco-compressed primitives have no geometry we could read.
+            if draco_required {
+                bail!(
+                    "{} needs the `draco` feature of kajiya-asset; please enable it, \
+                    or re-export the asset with EXT_meshopt_compression instead",
+                    DRACO_EXTENSION
+                );
+            }
+
+            // Use the uncompressed fallback instead.
+            draco_primitives.clear();
+        }
+
+        let draco_buffer = json["buffers"]
+            .as_array()
+            .map_or(0, |buffers| buffers.len());
+        let draco_json = if draco_primitives.is_empty() {
+            None
+        } else {
+            point_accessors_at_draco_buffer(&mut json, &mut draco_primitives, draco_buffer)?;
+            Some(json.clone())
+        };
+
+        let mut fallback_buffers: HashSet<usize> = json["buffers"] = json["buffers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .filter(|(_, buffer)| buffer["extensions"][MESHOPT_EXTENSION]["fallback"] == true)
+            .map(|(idx, _)| idx)
+            .collect();
+
+        if draco_json.is_some() {
+            fallback_buffers.insert(draco_buffer);
+        }
+
+        let mut meshopt_views = Vec::new();
+        for (view_idx, view) in json["bufferViews"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            let ext = &view["extensions"][MESHOPT_EXTENSION];
+            if ext.is_null() {
+                continue;
+            }
+
+            let field = |name: &str| {
+                ext[name].as_u64().map(|v| v as usize).with_context(|| {
+                    format!(
+                        "bufferView {}: {} is missing {}",
+                        view_idx, MESHOPT_EXTENSION, name
+                    )
+                })
+            };
+
+            let mode = match ext["mode"].as_str() {
+                Some("ATTRIBUTES") => MeshoptMode::Attributes,
+                Some("TRIANGLES") => MeshoptMode::Triangles,
+                Some("INDICES") => MeshoptMode::Indices,
+                other => bail!("bufferView {}: unknown meshopt mode {:?}", view_idx, other),
+            };
+
+            let filter = match ext["filter"].as_str().unwrap_or("NONE") {
+                "NONE" => MeshoptFilter::None,
+                "OCTAHEDRAL" => MeshoptFilter::Octahedral,
+                "QUATERNION" => MeshoptFilter::Quaternion,
+                "EXPONENTIAL" => MeshoptFilter::Exponential,
+                other => bail!(
+                    "bufferView {}: unknown meshopt filter {:?}",
+                    view_idx,
+                    other
+                ),
+            };
+
+            meshopt_views.push(MeshoptView {
+                view: view_idx,
+                source_buffer: field("buffer")?,
+                source_offset: ext["byteOffset"].as_u64().unwrap_or(0) as usize,
+                source_length: field("byteLength")?,
+                stride: field("byteStride")?,
+                count: field("count")?,
+                mode,
+                filter,
+            });
+        }
+
+        Ok(Self {
+            fallback_buffers,
+            meshopt_views,
+            draco_primitives,
+            draco_buffer,
+            draco_json,
+        })
+    }
+
+    /// The document to import instead of the one in the file, if Draco-compressed accessors
+    /// had to be pointed at the buffer they're decompressed into.
+    pub fn patched_document(&self) -> anyhow::Result<Option<Document>> {
+        self.draco_json
+            .as_ref()
+            .map(|json| {
+                Ok(Document::from_json_without_validation(
+                    serde_json::from_value(json.clone())?,
+                ))
+            })
+            .transpose()
+    }
+
+    pub fn is_fallback_buffer(&self, buffer: usize) -> bool {
+        self.fallback_buffers.contains(&buffer)
+    }
+
+    /// Decompresses views in place, into the buffers they reference.
+    pub fn decompress(&self, document: &Document, buffers: &mut [Vec<u8>]) -> anyhow::Result<()> {
+        for mv in &self.meshopt_views {
+            let view = document
+                .views()
+                .nth(mv.view)
+                .with_context(|| format!("bufferView {} not found", mv.view))?;
+
+            let source = buffers
+                .get(mv.source_buffer)
+                .and_then(|buffer| {
+                    buffer.get(mv.source_offset..mv.source_offset + mv.source_length)
+                })
+                .with_context(|| {
+                    format!("bufferView {}: compressed data out of bounds", mv.view)
+                })?;
+
+            let mut decoded = vec![0u8; mv.count * mv.stride];
+            match mv.mode {
+                MeshoptMode::Attributes => decode_vertex_buffer(&mut decoded, mv.stride, source),
+                MeshoptMode::Triangles => decode_index_buffer(&mut decoded, mv.stride, source),
+                MeshoptMode::Indices => decode_index_sequence(&mut decoded, mv.stride, source),
+            }
+            .and_then(|_| apply_filter(&mut decoded, mv.stride, mv.filter))
+            .with_context(|| format!("Decompressing bufferView {}", mv.view))?;
+
+            ensure!(
+                decoded.len() <= view.length(),
+                "bufferView {}: decompressed data doesn't fit the view",
+                mv.view
+            );
+
+            let dst = buffers
+                .get_mut(view.buffer().index())
+                .and_then(|buffer| buffer.get_mut(view.offset()..view.offset() + decoded.len()))
+                .with_context(|| format!("bufferView {} out of bounds", mv.view))?;
+            dst.copy_from_slice(&decoded);
+        }
+
+        #[cfg(feature = "draco")]
+        self.decompress_draco(document, buffers)?;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "draco")]
+    fn decompress_draco(&self, document: &Document, buffers: &mut [Vec<u8>]) -> anyhow::Result<()> {
+        for prim in &self.draco_primitives {
+            let context = || {
+                format!(
+                    "Decompressing mesh {} primitive {}",
+                    prim.mesh, prim.primitive
+                )
+            };
+
+            let view = document
+                .views()
+                .nth(prim.view)
+                .with_context(|| format!("bufferView {} not found", prim.view))?;
+            let source = buffers
+                .get(view.buffer().index())
+                .and_then(|buffer| buffer.get(view.offset()..view.offset() + view.length()))
+                .with_context(|| format!("bufferView {} out of bounds", prim.view))
+                .with_context(context)?;
+
+            let mesh = DracoMesh::decode(source).with_context(context)?;
+
+            let dst = &mut buffers[self.draco_buffer];
+            mesh.read_indices(dst, &prim.indices)
+                .with_context(context)?;
+            for (id, accessor) in &prim.attributes {
+                mesh.read_attribute(dst, *id, accessor)
+                    .with_context(context)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Owns a mesh decoded by `draco_decoder`
+#[cfg(feature = "draco")]
+struct DracoMesh(*mut draco_decoder::DracoMesh);
+
+#[cfg(feature = "draco")]
+impl DracoMesh {
+    fn decode(data: &[u8]) -> anyhow::Result<Self> {
+        let mesh = unsafe { draco_decoder::draco_decode_mesh(data.as_ptr(), data.len()) };
+        ensure!(!mesh.is_null(), "Invalid Draco mesh");
+        Ok(Self(mesh))
+    }
+
+    fn point_count(&self) -> usize {
+        unsafe { draco_decoder::draco_mesh_point_count(self.0) as usize }
+    }
+
+    fn read_indices(&self, dst: &mut [u8], accessor: &DracoAccessor) -> anyhow::Result<()> {
+        let face_count = unsafe { draco_decoder::draco_mesh_face_count(self.0) } as usize;
+        ensure!(
+            face_count * 3 == accessor.count,
+            "Draco mesh has {} indices, but accessor {} has {}",
+            face_count * 3,
+            accessor.accessor,
+            accessor.count
+        );
+
+        let mut indices = vec![0u32; face_count * 3];
+        unsafe { draco_decoder::draco_mesh_read_indices(self.0, indices.as_mut_ptr()) };
+
+        let size = component_size(accessor.component_type)?;
+        let dst = &mut dst[accessor.offset..accessor.offset + indices.len() * size];
+        for (dst, index) in dst.chunks_exact_mut(size).zip(indices) {
+            match accessor.component_type {
+                5121 => dst[0] = index as u8,
+                5123 => dst.copy_from_slice(&(index as u16).to_le_bytes()),
+                5125 => dst.copy_from_slice(&index.to_le_bytes()),
+                other => bail!("Invalid index component type {}", other),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_attribute(
+        &self,
+        dst: &mut [u8],
+        id: u32,
+        accessor: &DracoAccessor,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            self.point_count() == accessor.count,
+            "Draco mesh has {} points, but accessor {} has {}",
+            self.point_count(),
+            accessor.accessor,
+            accessor.count
+        );
+
+        let size =
+            accessor.count * accessor.component_count * component_size(accessor.component_type)?;
+        let dst = &mut dst[accessor.offset..accessor.offset + size];
+
+        // Draco writes native-endian values, which are little-endian on every supported platform.
+        let read = unsafe {
+            draco_decoder::draco_mesh_read_attribute(
+                self.0,
+                id,
+                accessor.component_type,
+                accessor.component_count as u32,
+                dst.as_mut_ptr() as *mut _,
+            )
+        };
+        ensure!(
+            read,
+            "Can't read Draco attribute {} into accessor {}",
+            id,
+            accessor.accessor
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "draco")]
+impl Drop for DracoMesh {
+    fn drop(&mut self) {
+        unsafe { draco_decoder::draco_mesh_free(self.0) }
+    }
+}
+
+// Primitives with `KHR_draco_mesh_compression`. Their indices and attributes are decompressed
+// into the accessors which the primitive references, whatever the layout of those, as they
+// only specify the count and format.
+fn parse_draco_primitives(json: &serde_json::Value) -> anyhow::Result<Vec<DracoPrimitive>> {
+    let accessor = |accessor: usize| -> anyhow::Result<DracoAccessor> {
+        let desc = &json["accessors"][accessor];
+        let component_type = desc["componentType"]
+            .as_u64()
+            .with_context(|| format!("accessor {} has no componentType", accessor))?
+            as u32;
+        let component_count = match desc["type"].as_str() {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            other => bail!("accessor {}: unsupported type {:?}", accessor, other),
+        };
+
+        Ok(DracoAccessor {
+            accessor,
+            offset: 0,
+            count: desc["count"]
+                .as_u64()
+                .with_context(|| format!("accessor {} has no count", accessor))?
+                as usize,
+            component_type,
+            component_count,
+        })
+    };
+
+    let mut primitives = Vec::new();
+    for (mesh_idx, mesh) in json["meshes"].as_array().into_iter().flatten().enumerate() {
+        for (prim_idx, prim) in mesh["primitives"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            let ext = &prim["extensions"][DRACO_EXTENSION];
+            if ext.is_null() {
+                continue;
+            }
+
+            let context = || format!("mesh {} primitive {}", mesh_idx, prim_idx);
+            let index = |value: &serde_json::Value, what: &str| {
+                value.as_u64().map(|v| v as usize).with_context(|| {
+                    format!("{}: {} is missing {}", context(), DRACO_EXTENSION, what)
+                })
+            };
+
+            let mut attributes = Vec::new();
+            for (name, id) in ext["attributes"].as_object().into_iter().flatten() {
+                let accessor_idx = index(&prim["attributes"][name], name)?;
+                attributes.push((index(id, name)? as u32, accessor(accessor_idx)?));
+            }
+
+            primitives.push(DracoPrimitive {
+                mesh: mesh_idx,
+                primitive: prim_idx,
+                view: index(&ext["bufferView"], "bufferView")?,
+                indices: accessor(index(&prim["indices"], "indices")?)?,
+                attributes,
+            });
+        }
+    }
+
+    Ok(primitives)
+}
+
+fn component_size(component_type: u32) -> anyhow::Result<usize> {
+    Ok(match component_type {
+        5120 | 5121 => 1,
+        5122 | 5123 => 2,
+        5125 | 5126 => 4,
+        _ => bail!("Unsupported component type {}", component_type),
+    })
+}
+
+// Lays the Draco accessors out in a new buffer, and points the accessors at views of it.
+// Accessors shared between primitives are decompressed once per primitive, into the same place.
+fn point_accessors_at_draco_buffer(
+    json: &mut serde_json::Value,
+    primitives: &mut [DracoPrimitive],
+    draco_buffer: usize,
+) -> anyhow::Result<()> {
+    let mut offsets: HashMap<usize, usize> = HashMap::new();
+    let mut views = Vec::new();
+    let mut buffer_length = 0;
+
+    for prim in primitives {
+        for accessor in std::iter::once(&mut prim.indices)
+            .chain(prim.attributes.iter_mut().map(|(_, accessor)| accessor))
+        {
+            if let Some(&offset) = offsets.get(&accessor.accessor) {
+                accessor.offset = offset;
+                continue;
+            }
+
+            let length = accessor.count
+                * accessor.component_count
+                * component_size(accessor.component_type)?;
+            accessor.offset = buffer_length;
+            offsets.insert(accessor.accessor, buffer_length);
+
+            json["accessors"][accessor.accessor]["bufferView"] =
+                (json["bufferViews"].as_array().map_or(0, |v| v.len()) + views.len()).into();
+            json["accessors"][accessor.accessor]["byteOffset"] = 0.into();
+            views.push(serde_json::json!({
+                "buffer": draco_buffer,
+                "byteOffset": buffer_length,
+                "byteLength": length,
+            }));
+
+            // Keeps every accessor aligned to its components.
+            buffer_length += (length + 3) & !3;
+        }
+    }
+
+    if json["bufferViews"].is_null() {
+        json["bufferViews"] = serde_json::json!([]);
+    }
+    if json["buffers"].is_null() {
+        json["buffers"] = serde_json::json!([]);
+    }
+    json["bufferViews"].as_array_mut().unwrap().extend(views);
+    json["buffers"]
+        .as_array_mut()
+        .unwrap()
+        .push(serde_json::json!({ "byteLength": buffer_length }));
+
+    Ok(())
+}
+
+// The JSON part of a `.gltf` or `.glb` file
+fn json_chunk(data: &[u8]) -> anyhow::Result<&[u8]> {
+    if !data.starts_with(b"glTF") {
+        return Ok(data);
+    }
+
+    let length = data
+        .get(12..16)
+        .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize)
+        .context("Truncated GLB header")?;
+
+    data.get(20..20 + length)
+        .context("Truncated GLB JSON chunk")
+}
+
+const VERTEX_HEADER: u8 = 0xa0;
+const INDEX_HEADER: u8 = 0xe0;
+const INDEX_SEQUENCE_HEADER: u8 = 0xd0;
+const BYTE_GROUP_SIZE: usize = 16;
+
+fn read_byte(src: &[u8], pos: &mut usize) -> anyhow::Result<u8> {
+    let byte = *src.get(*pos).context("Truncated data")?;
+    *pos += 1;
+    Ok(byte)
+}
+
+fn decode_vbyte(src: &[u8], pos: &mut usize) -> anyhow::Result<u32> {
+    let mut result = 0u32;
+    for shift in (0..35).step_by(7) {
+        let byte = read_byte(src, pos)?;
+        result |= ((byte & 127) as u32) << shift;
+        if byte < 128 {
+            break;
+        }
+    }
+    Ok(result)
+}
+
+fn unzigzag8(v: u8) -> u8 {
+    (v >> 1) ^ (v & 1).wrapping_neg()
+}
+
+fn unzigzag32(v: u32) -> u32 {
+    (v >> 1) ^ (v & 1).wrapping_neg()
+}
+
+// Vertex attributes are stored in blocks, with each byte of the vertex delta-encoded from
+// the previous vertex, and bit-packed in groups of 16.
+fn decode_vertex_buffer(dst: &mut [u8], stride: usize, src: &[u8]) -> anyhow::Result<()> {
+    ensure!(
+        stride > 0 && stride <= 256 && stride % 4 == 0,
+        "Invalid vertex stride {}",
+        stride
+    );
+    ensure!(
+        src.first() == Some(&VERTEX_HEADER),
+        "Unsupported vertex codec version"
+    );
+
+    // The first vertex is stored at the end, and seeds the deltas.
+    let tail_size = stride.max(32);
+    ensure!(src.len() > tail_size, "Truncated vertex data");
+    let mut last_vertex = src[src.len() - stride..].to_vec();
+
+    let count = dst.len() / stride;
+    let block_size = ((8192 / stride) & !(BYTE_GROUP_SIZE - 1)).min(256);
+    let mut bytes = [0u8; 256];
+    let mut pos = 1;
+
+    for block_start in (0..count).step_by(block_size) {
+        let block_count = block_size.min(count - block_start);
+        let aligned_count = (block_count + BYTE_GROUP_SIZE - 1) & !(BYTE_GROUP_SIZE - 1);
+
+        for k in 0..stride {
+            pos = decode_bytes(src, pos, &mut bytes[..aligned_count])?;
+
+            let mut p = last_vertex[k];
+            for (i, &delta) in bytes[..block_count].iter().enumerate() {
+                p = unzigzag8(delta).wrapping_add(p);
+                dst[(block_start + i) * stride + k] = p;
+            }
+            last_vertex[k] = p;
+        }
+    }
+
+    ensure!(src.len() - pos == tail_size, "Malformed vertex data");
+    Ok(())
+}
+
+fn decode_bytes(src: &[u8], mut pos: usize, out: &mut [u8]) -> anyhow::Result<usize> {
+    // Two bits per group, selecting its bit width
+    let header_size = (out.len() / BYTE_GROUP_SIZE + 3) / 4;
+    let header = src
+        .get(pos..pos + header_size)
+        .context("Truncated vertex data")?;
+    pos += header_size;
+
+    for (group_idx, group) in out.chunks_exact_mut(BYTE_GROUP_SIZE).enumerate() {
+        let bits_log2 = (header[group_idx / 4] >> ((group_idx % 4) * 2)) & 3;
+        pos = decode_bytes_group(src, pos, group, bits_log2)?;
+    }
+
+    Ok(pos)
+}
+
+fn decode_bytes_group(
+    src: &[u8],
+    pos: usize,
+    group: &mut [u8],
+    bits_log2: u8,
+) -> anyhow::Result<usize> {
+    match bits_log2 {
+        0 => {
+            group.fill(0);
+            Ok(pos)
+        }
+        3 => {
+            let literal = src
+                .get(pos..pos + BYTE_GROUP_SIZE)
+                .context("Truncated vertex data")?;
+            group.copy_from_slice(literal);
+            Ok(pos + BYTE_GROUP_SIZE)
+        }
+        _ => {
+            // 2 or 4 bits per value, most significant first. The maximum value is an escape,
+            // with the actual byte following the packed values.
+            let bits = 1usize << bits_log2;
+            let packed = src
+                .get(pos..pos + bits * 2)
+                .context("Truncated vertex data")?;
+            let escape_value = (1u8 << bits) - 1;
+            let mut escape_pos = pos + bits * 2;
+
+            for (i, out) in group.iter_mut().enumerate() {
+                let bit_offset = i * bits;
+                let v = (packed[bit_offset / 8] >> (8 - bits - bit_offset % 8)) & escape_value;
+                *out = if v == escape_value {
+                    read_byte(src, &mut escape_pos)?
+                } else {
+                    v
+                };
+            }
+
+            Ok(escape_pos)
+        }
+    }
+}
+
+fn write_index(dst: &mut [u8], stride: usize, idx: usize, value: u32) {
+    if stride == 2 {
+        dst[idx * 2..idx * 2 + 2].copy_from_slice(&(value as u16).to_le_bytes());
+    } else {
+        dst[idx * 4..idx * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    }
+}
+
+// Recently seen edges and vertices, referenced by triangle codes
+struct IndexFifos {
+    edges: [[u32; 2]; 16],
+    edge_offset: usize,
+    vertices: [u32; 16],
+    vertex_offset: usize,
+}
+
+impl IndexFifos {
+    fn edge(&self, fe: usize) -> [u32; 2] {
+        self.edges[self.edge_offset.wrapping_sub(1 + fe) & 15]
+    }
+
+    // `back` is one for the most recently pushed vertex.
+    fn vertex(&self, back: usize) -> u32 {
+        self.vertices[self.vertex_offset.wrapping_sub(back) & 15]
+    }
+
+    fn push_edge(&mut self, a: u32, b: u32) {
+        self.edges[self.edge_offset] = [a, b];
+        self.edge_offset = (self.edge_offset + 1) & 15;
+    }
+
+    fn push_vertex(&mut self, v: u32, advance: bool) {
+        self.vertices[self.vertex_offset] = v;
+        self.vertex_offset = (self.vertex_offset + advance as usize) & 15;
+    }
+}
+
+// Triangle lists, encoded as references to recent edges and vertices, with new vertices
+// mostly appearing in order.
+fn decode_index_buffer(dst: &mut [u8], stride: usize, src: &[u8]) -> anyhow::Result<()> {
+    ensure!(
+        stride == 2 || stride == 4,
+        "Invalid index stride {}",
+        stride
+    );
+
+    let count = dst.len() / stride;
+    ensure!(count % 3 == 0, "Index count must be a multiple of three");
+    ensure!(src.len() >= 1 + count / 3 + 16, "Truncated index data");
+    ensure!(src[0] & 0xf0 == INDEX_HEADER, "Invalid index data");
+
+    let version = src[0] & 0x0f;
+    ensure!(version <= 1, "Unsupported index codec version {}", version);
+    let fecmax = if version >= 1 { 13 } else { 15 };
+
+    let codes = &src[1..1 + count / 3];
+    let data_end = src.len() - 16;
+    let codeaux_table = &src[data_end..];
+    let data = &src[..data_end];
+    let mut pos = 1 + count / 3;
+
+    let mut fifos = IndexFifos {
+        edges: [[u32::MAX; 2]; 16],
+        edge_offset: 0,
+        vertices: [u32::MAX; 16],
+        vertex_offset: 0,
+    };
+    let mut next = 0u32;
+    let mut last = 0u32;
+
+    for (tri, &code) in codes.iter().enumerate() {
+        let [a, b, c] = if code < 0xf0 {
+            // Reuses an edge
+            let [a, b] = fifos.edge((code >> 4) as usize);
+            let fec = (code & 15) as usize;
+
+            let c = if fec < fecmax {
+                let c = if fec == 0 {
+                    next
+                } else {
+                    fifos.vertex(fec + 1)
+                };
+                next += (fec == 0) as u32;
+                fifos.push_vertex(c, fec == 0);
+                c
+            } else {
+                last = if fec != 15 {
+                    // 13 and 14 are -1 and +1 from the last explicit index
+                    last.wrapping_add(if fec == 13 { u32::MAX } else { 1 })
+                } else {
+                    last.wrapping_add(unzigzag32(decode_vbyte(data, &mut pos)?))
+                };
+                fifos.push_vertex(last, true);
+                last
+            };
+
+            fifos.push_edge(c, b);
+            fifos.push_edge(a, c);
+            [a, b, c]
+        } else {
+            // A new triangle, not sharing an edge
+            let (fea, feb, fec) = if code < 0xfe {
+                let codeaux = codeaux_table[(code & 15) as usize];
+                (0, (codeaux >> 4) as usize, (codeaux & 15) as usize)
+            } else {
+                let codeaux = read_byte(data, &mut pos)?;
+                if codeaux == 0 {
+                    next = 0;
+                }
+                (
+                    if code == 0xfe { 0 } else { 15 },
+                    (codeaux >> 4) as usize,
+                    (codeaux & 15) as usize,
+                )
+            };
+
+            // New vertices are numbered before explicit indices are read, as in the encoder.
+            let mut take_next = || {
+                next += 1;
+                next - 1
+            };
+            let mut a = if fea == 0 { take_next() } else { 0 };
+            let mut b = match feb {
+                0 => take_next(),
+                15 => 0,
+                _ => fifos.vertex(feb),
+            };
+            let mut c = match fec {
+                0 => take_next(),
+                15 => 0,
+                _ => fifos.vertex(fec),
+            };
+
+            for (fe, v) in [(fea, &mut a), (feb, &mut b), (fec, &mut c)] {
+                if fe == 15 {
+                    last = last.wrapping_add(unzigzag32(decode_vbyte(data, &mut pos)?));
+                    *v = last;
+                }
+            }
+
+            fifos.push_vertex(a, true);
+            fifos.push_vertex(b, feb == 0 || feb == 15);
+            fifos.push_vertex(c, fec == 0 || fec == 15);
+            fifos.push_edge(b, a);
+            fifos.push_edge(c, b);
+            fifos.push_edge(a, c);
+            [a, b, c]
+        };
+
+        write_index(dst, stride, tri * 3, a);
+        write_index(dst, stride, tri * 3 + 1, b);
+        write_index(dst, stride, tri * 3 + 2, c);
+    }
+
+    ensure!(pos == data_end, "Malformed index data");
+    Ok(())
+}
+
+// Arbitrary index lists, as deltas from one of two previous indices
+fn decode_index_sequence(dst: &mut [u8], stride: usize, src: &[u8]) -> anyhow::Result<()> {
+    ensure!(
+        stride == 2 || stride == 4,
+        "Invalid index stride {}",
+        stride
+    );
+
+    let count = dst.len() / stride;
+    ensure!(src.len() >= 1 + count + 4, "Truncated index data");
+    ensure!(
+        src[0] & 0xf0 == INDEX_SEQUENCE_HEADER,
+        "Invalid index sequence data"
+    );
+
+    let version = src[0] & 0x0f;
+    ensure!(
+        version <= 1,
+        "Unsupported index sequence codec version {}",
+        version
+    );
+
+    let data_end = src.len() - 4;
+    let data = &src[..data_end];
+    let mut pos = 1;
+    let mut last = [0u32; 2];
+
+    for i in 0..count {
+        let v = decode_vbyte(data, &mut pos)?;
+        let baseline = (v & 1) as usize;
+        let index = last[baseline].wrapping_add(unzigzag32(v >> 1));
+        last[baseline] = index;
+        write_index(dst, stride, i, index);
+    }
+
+    ensure!(pos == data_end, "Malformed index data");
+    Ok(())
+}
+
+fn apply_filter(data: &mut [u8], stride: usize, filter: MeshoptFilter) -> anyhow::Result<()> {
+    match filter {
+        MeshoptFilter::None => {}
+        MeshoptFilter::Octahedral => match stride {
+            4 => {
+                for v in data.chunks_exact_mut(4) {
+                    let n = decode_octahedral([v[0], v[1], v[2]].map(|c| c as i8 as f32), 127.0);
+                    for (dst, c) in v.iter_mut().zip(n) {
+                        *dst = c as i8 as u8;
+                    }
+                }
+            }
+            8 => {
+                for v in data.chunks_exact_mut(8) {
+                    let n = decode_octahedral([0, 1, 2].map(|i| read_i16(v, i) as f32), 32767.0);
+                    for (i, c) in n.into_iter().enumerate() {
+                        write_i16(v, i, c as i16);
+                    }
+                }
+            }
+            _ => bail!("Invalid stride {} for the octahedral filter", stride),
+        },
+        MeshoptFilter::Quaternion => {
+            ensure!(
+                stride == 8,
+                "Invalid stride {} for the quaternion filter",
+                stride
+            );
+
+            for v in data.chunks_exact_mut(8) {
+                let q = [0, 1, 2, 3].map(|i| read_i16(v, i));
+
+                // The low bits of `w` hold the index of the omitted largest component,
+                // and the rest, the scale of the other three.
+                let scale = std::f32::consts::FRAC_1_SQRT_2 / (q[3] | 3) as f32;
+                let [x, y, z] = [q[0], q[1], q[2]].map(|c| c as f32 * scale);
+                let w = (1.0 - x * x - y * y - z * z).max(0.0).sqrt();
+
+                let max_component = (q[3] & 3) as usize;
+                for (i, c) in [w, x, y, z].into_iter().enumerate() {
+                    write_i16(v, (max_component + i) & 3, (c * 32767.0).round() as i16);
+                }
+            }
+        }
+        MeshoptFilter::Exponential => {
+            ensure!(
+                stride % 4 == 0,
+                "Invalid stride {} for the exponential filter",
+                stride
+            );
+
+            // 24-bit signed mantissa, and 8-bit signed exponent
+            for v in data.chunks_exact_mut(4) {
+                let bits = u32::from_le_bytes(v.try_into().unwrap());
+                let mantissa = ((bits << 8) as i32) >> 8;
+                let exponent = (bits as i32) >> 24;
+                let value = mantissa as f32 * 2f32.powi(exponent);
+                v.copy_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Rounded to integers in `[-max, max]`
+fn decode_octahedral([x, y, z]: [f32; 3], max: f32) -> [f32; 3] {
+    let z = z - x.abs() - y.abs();
+
+    // Unfold the lower hemisphere
+    let t = z.min(0.0);
+    let x = x + if x >= 0.0 { t } else { -t };
+    let y = y + if y >= 0.0 { t } else { -t };
+
+    let scale = max / (x * x + y * y + z * z).sqrt();
+    [x, y, z].map(|c| (c * scale).round())
+}
+
+fn read_i16(v: &[u8], idx: usize) -> i16 {
+    i16::from_le_bytes([v[idx * 2], v[idx * 2 + 1]])
+}
+
+fn write_i16(v: &mut [u8], idx: usize, value: i16) {
+    v[idx * 2..idx * 2 + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Ports of the `meshoptimizer` encoders, for round trips through the decoders above
+
+    fn zigzag8(v: u8) -> u8 {
+        ((v as i8) >> 7) as u8 ^ (v << 1)
+    }
+
+    fn zigzag32(v: u32) -> u32 {
+        ((v as i32) >> 31) as u32 ^ (v << 1)
+    }
+
+    fn encode_vbyte(out: &mut Vec<u8>, mut v: u32) {
+        loop {
+            out.push((v & 127) as u8 | if v > 127 { 128 } else { 0 });
+            v >>= 7;
+            if v == 0 {
+                break;
+            }
+        }
+    }
+
+    fn encode_bytes_group_size(group: &[u8], bits: usize) -> usize {
+        match bits {
+            1 if group.iter().all(|&v| v == 0) => 0,
+            1 => usize::MAX,
+            8 => BYTE_GROUP_SIZE,
+            _ => {
+                let escape_value = (1u8 << bits) - 1;
+                BYTE_GROUP_SIZE * bits / 8 + group.iter().filter(|&&v| v >= escape_value).count()
+            }
+        }
+    }
+
+    fn encode_bytes_group(out: &mut Vec<u8>, group: &[u8], bits: usize) {
+        match bits {
+            1 => {}
+            8 => out.extend_from_slice(group),
+            _ => {
+                let escape_value = (1u8 << bits) - 1;
+                for values in group.chunks(8 / bits) {
+                    out.push(
+                        values
+                            .iter()
+                            .fold(0, |byte, &v| (byte << bits) | v.min(escape_value)),
+                    );
+                }
+                out.extend(group.iter().filter(|&&v| v >= escape_value));
+            }
+        }
+    }
+
+    fn encode_bytes(out: &mut Vec<u8>, buffer: &[u8]) {
+        let header_start = out.len();
+        out.resize(header_start + (buffer.len() / BYTE_GROUP_SIZE + 3) / 4, 0);
+
+        for (group_idx, group) in buffer.chunks_exact(BYTE_GROUP_SIZE).enumerate() {
+            // Ties go to the earlier candidate, as in `meshoptimizer`.
+            let bits = [8, 1, 2, 4]
+                .into_iter()
+                .min_by_key(|&bits| encode_bytes_group_size(group, bits))
+                .unwrap();
+
+            out[header_start + group_idx / 4] |=
+                (bits.trailing_zeros() as u8) << ((group_idx % 4) * 2);
+            encode_bytes_group(out, group, bits);
+        }
+    }
+
+    fn encode_vertex_buffer(vertices: &[u8], stride: usize) -> Vec<u8> {
+        let mut out = vec![VERTEX_HEADER];
+        let first_vertex = &vertices[..stride];
+        let mut last_vertex = first_vertex.to_vec();
+        let block_size = ((8192 / stride) & !(BYTE_GROUP_SIZE - 1)).min(256);
+
+        for block in vertices.chunks(block_size * stride) {
+            let block_count = block.len() / stride;
+            let aligned_count = (block_count + BYTE_GROUP_SIZE - 1) & !(BYTE_GROUP_SIZE - 1);
+            let mut deltas = vec![0u8; aligned_count];
+
+            for (k, &last) in last_vertex.iter().enumerate() {
+                let mut p = last;
+                for (i, delta) in deltas[..block_count].iter_mut().enumerate() {
+                    let v = block[i * stride + k];
+                    *delta = zigzag8(v.wrapping_sub(p));
+                    p = v;
+                }
+
+                let last_delta = deltas[block_count - 1];
+                deltas[block_count..].fill(last_delta);
+                encode_bytes(&mut out, &deltas);
+            }
+
+            last_vertex.copy_from_slice(&block[(block_count - 1) * stride..]);
+        }
+
+        // The first vertex, padded to 32 bytes
+        out.resize(out.len() + 32usize.saturating_sub(stride), 0);
+        out.extend_from_slice(first_vertex);
+        out
+    }
+
+    const CODEAUX_TABLE: [u8; 16] = [
+        0x00, 0x76, 0x87, 0x56, 0x67, 0x78, 0xa9, 0x86, 0x65, 0x89, 0x68, 0x98, 0x01, 0x69, 0, 0,
+    ];
+
+    fn encode_index(out: &mut Vec<u8>, index: u32, last: u32) {
+        encode_vbyte(out, zigzag32(index.wrapping_sub(last)));
+    }
+
+    // Position in the vertex FIFO, the most recent first
+    fn find_vertex(fifos: &IndexFifos, v: u32) -> Option<usize> {
+        (0..16).find(|&i| fifos.vertex(i + 1) == v)
+    }
+
+    // Position in the edge FIFO, and the rotation of the triangle which starts with the edge
+    fn find_edge(fifos: &IndexFifos, [a, b, c]: [u32; 3]) -> Option<(usize, usize)> {
+        (0..16).find_map(|i| {
+            [[a, b], [b, c], [c, a]]
+                .iter()
+                .position(|&edge| fifos.edge(i) == edge)
+                .map(|rotation| (i, rotation))
+        })
+    }
+
+    // Zero for the next new vertex, one past the position in the FIFO for a recent one,
+    // and 15 for explicitly encoded indices
+    fn vertex_code(v: u32, fifo_pos: Option<usize>, next: &mut u32) -> usize {
+        match fifo_pos {
+            Some(pos) if pos < 14 => pos + 1,
+            _ if v == *next => {
+                *next += 1;
+                0
+            }
+            _ => 15,
+        }
+    }
+
+    // Version 1 of the codec
+    fn encode_index_buffer(indices: &[u32]) -> Vec<u8> {
+        const TRIANGLE_INDEX_ORDER: [[usize; 3]; 3] = [[0, 1, 2], [1, 2, 0], [2, 0, 1]];
+        const FECMAX: usize = 13;
+
+        let mut fifos = IndexFifos {
+            edges: [[u32::MAX; 2]; 16],
+            edge_offset: 0,
+            vertices: [u32::MAX; 16],
+            vertex_offset: 0,
+        };
+        let mut codes = Vec::new();
+        let mut data = Vec::new();
+        let mut next = 0u32;
+        let mut last = 0u32;
+
+        for tri in indices.chunks_exact(3) {
+            let tri = [tri[0], tri[1], tri[2]];
+
+            match find_edge(&fifos, tri) {
+                Some((fe, rotation)) if fe < 15 => {
+                    let [a, b, c] = TRIANGLE_INDEX_ORDER[rotation].map(|i| tri[i]);
+
+                    let mut fec = match find_vertex(&fifos, c) {
+                        Some(pos) if (1..FECMAX).contains(&pos) => pos,
+                        _ if c == next => {
+                            next += 1;
+                            0
+                        }
+                        _ => 15,
+                    };
+
+                    if fec == 15 {
+                        if c.wrapping_add(1) == last {
+                            fec = 13;
+                            last = c;
+                        }
+                        if c == last.wrapping_add(1) {
+                            fec = 14;
+                            last = c;
+                        }
+                    }
+
+                    codes.push(((fe << 4) | fec) as u8);
+
+                    if fec == 15 {
+                        encode_index(&mut data, c, last);
+                        last = c;
+                    }
+
+                    if fec == 0 || fec >= FECMAX {
+                        fifos.push_vertex(c, true);
+                    }
+
+                    fifos.push_edge(c, b);
+                    fifos.push_edge(a, c);
+                }
+                _ => {
+                    let rotation = if tri[1] == next {
+                        1
+                    } else if tri[2] == next {
+                        2
+                    } else {
+                        0
+                    };
+                    let [a, b, c] = TRIANGLE_INDEX_ORDER[rotation].map(|i| tri[i]);
+
+                    let reset = [a, b, c] == [0, 1, 2] && next > 0;
+                    if reset {
+                        next = 0;
+                        fifos.vertices = [u32::MAX; 16];
+                    }
+
+                    let fb = find_vertex(&fifos, b);
+                    let fc = find_vertex(&fifos, c);
+
+                    let fea = vertex_code(a, None, &mut next);
+                    let feb = vertex_code(b, fb, &mut next);
+                    let fec = vertex_code(c, fc, &mut next);
+
+                    let codeaux = ((feb << 4) | fec) as u8;
+                    match CODEAUX_TABLE[..14].iter().position(|&e| e == codeaux) {
+                        Some(codeaux_idx) if fea == 0 && !reset => {
+                            codes.push(0xf0 | codeaux_idx as u8)
+                        }
+                        _ => {
+                            codes.push(if fea == 0 { 0xfe } else { 0xff });
+                            data.push(codeaux);
+                        }
+                    }
+
+                    for (fe, v) in [(fea, a), (feb, b), (fec, c)] {
+                        if fe == 15 {
+                            encode_index(&mut data, v, last);
+                            last = v;
+                        }
+                    }
+
+                    for (fe, v) in [(fea, a), (feb, b), (fec, c)] {
+                        if fe == 0 || fe == 15 {
+                            fifos.push_vertex(v, true);
+                        }
+                    }
+
+                    fifos.push_edge(b, a);
+                    fifos.push_edge(c, b);
+                    fifos.push_edge(a, c);
+                }
+            }
+        }
+
+        let mut out = vec![INDEX_HEADER | 1];
+        out.extend(codes);
+        out.extend(data);
+        out.extend(CODEAUX_TABLE);
+        out
+    }
+
+    // Version 1 of the codec
+    fn encode_index_sequence(indices: &[u32]) -> Vec<u8> {
+        let mut out = vec![INDEX_SEQUENCE_HEADER | 1];
+        let mut last = [0u32; 2];
+        let mut current = 0;
+
+        for &index in indices {
+            // Switches baselines when the delta grows too large
+            let delta = index.wrapping_sub(last[current]) as i32;
+            current ^= (delta.unsigned_abs() >= 30) as usize;
+
+            let v = zigzag32(index.wrapping_sub(last[current]));
+            encode_vbyte(&mut out, (v << 1) | current as u32);
+            last[current] = index;
+        }
+
+        out.extend([0; 4]);
+        out
+    }
+
+    fn quantize_snorm(v: f32, bits: u32) -> i32 {
+        let scale = ((1 << (bits - 1)) - 1) as f32;
+        let round = if v >= 0.0 { 0.5 } else { -0.5 };
+        (v.clamp(-1.0, 1.0) * scale + round) as i32
+    }
+
+    // `meshopt_encodeFilterOct`, with `w` left at zero
+    fn encode_octahedral([x, y, z]: [f32; 3], bits: u32) -> [i32; 4] {
+        let sign = |c: f32| if c >= 0.0 { 1.0 } else { -1.0 };
+
+        let l1 = x.abs() + y.abs() + z.abs();
+        let (x, y) = (x / l1, y / l1);
+        let u = if z >= 0.0 {
+            x
+        } else {
+            (1.0 - y.abs()) * sign(x)
+        };
+        let v = if z >= 0.0 {
+            y
+        } else {
+            (1.0 - x.abs()) * sign(y)
+        };
+
+        [
+            quantize_snorm(u, bits),
+            quantize_snorm(v, bits),
+            quantize_snorm(1.0, bits),
+            0,
+        ]
+    }
+
+    // `meshopt_encodeFilterQuat`
+    fn encode_quaternion(q: [f32; 4], bits: u32) -> [i16; 4] {
+        let qc = (1..4).fold(0, |qc, i| if q[i].abs() > q[qc].abs() { i } else { qc });
+
+        // Double cover: `q` and `-q` are the same rotation.
+        let sign = if q[qc] < 0.0 { -1.0 } else { 1.0 };
+        let component = |i: usize| {
+            quantize_snorm(q[(qc + i) & 3] * std::f32::consts::SQRT_2 * sign, bits) as i16
+        };
+
+        [
+            component(1),
+            component(2),
+            component(3),
+            ((quantize_snorm(1.0, bits) & !3) | qc as i32) as i16,
+        ]
+    }
+
+    // `meshopt_encodeFilterExp`, for a single component
+    fn encode_exponential(v: f32, bits: i32) -> u32 {
+        // The exponent of `frexp`
+        let exponent = if v == 0.0 {
+            0
+        } else {
+            ((v.to_bits() >> 23) & 0xff) as i32 - 126
+        } - (bits - 1);
+
+        let round = if v >= 0.0 { 0.5 } else { -0.5 };
+        let mantissa = (v * 2f32.powi(-exponent) + round) as i32;
+        (mantissa as u32 & 0xff_ffff) | ((exponent as u32) << 24)
+    }
+
+    // Deterministic data, without pulling in a random number crate
+    fn pseudo_random(count: usize) -> impl Iterator<Item = u32> {
+        let mut state = 0x2545_f491u32;
+        (0..count).map(move |_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        })
+    }
+
+    fn u32s_from_indices(data: &[u8], stride: usize) -> Vec<u32> {
+        data.chunks_exact(stride)
+            .map(|v| match stride {
+                2 => u16::from_le_bytes([v[0], v[1]]) as u32,
+                _ => u32::from_le_bytes(v.try_into().unwrap()),
+            })
+            .collect()
+    }
+
+    // Two triangles per quad, sharing edges with their neighbors
+    fn grid_indices(width: u32, height: u32) -> Vec<u32> {
+        let mut indices = Vec::new();
+        for y in 0..height - 1 {
+            for x in 0..width - 1 {
+                let i = y * width + x;
+                indices.extend([i, i + 1, i + width, i + 1, i + width + 1, i + width]);
+            }
+        }
+        indices
+    }
+
+    #[test]
+    fn vertex_buffer_round_trip() {
+        // Smooth, constant and noisy attributes, across several blocks and a partial one
+        let stride = 16;
+        let vertices: Vec<u8> = pseudo_random(1000)
+            .enumerate()
+            .flat_map(|(i, noise)| {
+                let smooth = (i as f32 * 0.01).sin();
+                [
+                    smooth.to_le_bytes(),
+                    (i as u32).to_le_bytes(),
+                    7u32.to_le_bytes(),
+                    noise.to_le_bytes(),
+                ]
+                .concat()
+            })
+            .collect();
+
+        let encoded = encode_vertex_buffer(&vertices, stride);
+        let mut decoded = vec![0u8; vertices.len()];
+        decode_vertex_buffer(&mut decoded, stride, &encoded).unwrap();
+        assert_eq!(decoded, vertices);
+
+        // A single vertex, smaller than the padded tail
+        let encoded = encode_vertex_buffer(&vertices[..4], 4);
+        let mut decoded = vec![0u8; 4];
+        decode_vertex_buffer(&mut decoded, 4, &encoded).unwrap();
+        assert_eq!(decoded, &vertices[..4]);
+    }
+
+    // The index codec keeps the winding of triangles, but not which vertex comes first.
+    fn canonical_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        indices
+            .chunks_exact(3)
+            .map(|tri| {
+                let first = (0..3).min_by_key(|&i| tri[i]).unwrap();
+                [0, 1, 2].map(|i| tri[(first + i) % 3])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn index_buffer_round_trip() {
+        let mut indices = grid_indices(40, 30);
+
+        // Triangles reusing no edges, then a restart from zero
+        let vertex_count = 40 * 30;
+        let random: Vec<u32> = pseudo_random(300).map(|v| v % vertex_count).collect();
+        indices.extend(random);
+        indices.extend([0, 1, 2]);
+        indices.extend(grid_indices(8, 8));
+
+        let encoded = encode_index_buffer(&indices);
+
+        for stride in [2, 4] {
+            let mut decoded = vec![0u8; indices.len() * stride];
+            decode_index_buffer(&mut decoded, stride, &encoded).unwrap();
+            assert_eq!(
+                canonical_triangles(&u32s_from_indices(&decoded, stride)),
+                canonical_triangles(&indices)
+            );
+        }
+    }
+
+    #[test]
+    fn index_sequence_round_trip() {
+        // Runs of nearby indices, with large jumps in between
+        let indices: Vec<u32> = pseudo_random(2000)
+            .enumerate()
+            .map(|(i, noise)| {
+                if i % 50 == 0 {
+                    noise % 60000
+                } else {
+                    (i as u32 * 3 + noise % 7) % 60000
+                }
+            })
+            .collect();
+
+        let encoded = encode_index_sequence(&indices);
+
+        for stride in [2, 4] {
+            let mut decoded = vec![0u8; indices.len() * stride];
+            decode_index_sequence(&mut decoded, stride, &encoded).unwrap();
+            assert_eq!(u32s_from_indices(&decoded, stride), indices);
+        }
+    }
+
+    #[test]
+    fn octahedral_filter_round_trip() {
+        let normals: Vec<[f32; 3]> = [
+            [0.0, 0.0, 1.0],
+            [0.0, 0.0, -1.0],
+            [1.0, 0.0, 0.0],
+            [0.0, -1.0, 0.0],
+            [0.3, -0.5, 0.8],
+            [-0.6, 0.2, -0.7],
+            [0.5, 0.5, -0.5],
+        ]
+        .iter()
+        .map(|&n: &[f32; 3]| {
+            let l = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+            n.map(|c| c / l)
+        })
+        .collect();
+
+        let check = |decoded: [f32; 3], n: [f32; 3], tolerance: f32| {
+            for (d, n) in decoded.into_iter().zip(n) {
+                assert!((d - n).abs() < tolerance, "{:?} != {:?}", decoded, n);
+            }
+        };
+
+        let mut data: Vec<u8> = normals
+            .iter()
+            .flat_map(|&n| encode_octahedral(n, 8).map(|c| c as i8 as u8))
+            .collect();
+        apply_filter(&mut data, 4, MeshoptFilter::Octahedral).unwrap();
+        for (v, &n) in data.chunks_exact(4).zip(&normals) {
+            check([v[0], v[1], v[2]].map(|c| c as i8 as f32 / 127.0), n, 0.02);
+        }
+
+        let mut data: Vec<u8> = normals
+            .iter()
+            .flat_map(|&n| encode_octahedral(n, 16))
+            .flat_map(|c| (c as i16).to_le_bytes())
+            .collect();
+        apply_filter(&mut data, 8, MeshoptFilter::Octahedral).unwrap();
+        for (v, &n) in data.chunks_exact(8).zip(&normals) {
+            check([0, 1, 2].map(|i| read_i16(v, i) as f32 / 32767.0), n, 0.001);
+        }
+    }
+
+    #[test]
+    fn quaternion_filter_round_trip() {
+        let quaternions: Vec<[f32; 4]> = [
+            [0.0, 0.0, 0.0, 1.0],
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, -1.0, 0.0, 0.0],
+            [0.1, 0.2, -0.9, 0.3],
+            [-0.5, 0.5, 0.5, -0.5],
+            [0.7, -0.1, 0.2, -0.6],
+        ]
+        .iter()
+        .map(|&q: &[f32; 4]| {
+            let l = q.iter().map(|c| c * c).sum::<f32>().sqrt();
+            q.map(|c| c / l)
+        })
+        .collect();
+
+        let mut data: Vec<u8> = quaternions
+            .iter()
+            .flat_map(|&q| encode_quaternion(q, 16))
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        apply_filter(&mut data, 8, MeshoptFilter::Quaternion).unwrap();
+
+        for (v, q) in data.chunks_exact(8).zip(&quaternions) {
+            let decoded = [0, 1, 2, 3].map(|i| read_i16(v, i) as f32 / 32767.0);
+
+            // The same rotation, possibly negated
+            let dot: f32 = decoded.iter().zip(q).map(|(a, b)| a * b).sum();
+            assert!(dot.abs() > 0.9999, "{:?} != {:?}", decoded, q);
+        }
+    }
+
+    #[test]
+    fn exponential_filter_round_trip() {
+        // Exactly representable with 24-bit mantissas
+        let exact = [0.0f32, 1.0, -2.5, 0.125, 1000.0, -65536.0, 3.0 / 65536.0];
+
+        let mut data: Vec<u8> = exact
+            .iter()
+            .flat_map(|&v| encode_exponential(v, 24).to_le_bytes())
+            .collect();
+        apply_filter(&mut data, 4, MeshoptFilter::Exponential).unwrap();
+
+        let decoded: Vec<f32> = data
+            .chunks_exact(4)
+            .map(|v| f32::from_le_bytes(v.try_into().unwrap()))
+            .collect();
+        assert_eq!(decoded, exact);
+
+        // Lossy with fewer bits
+        let mut data = encode_exponential(std::f32::consts::PI, 12).to_le_bytes();
+        apply_filter(&mut data, 4, MeshoptFilter::Exponential).unwrap();
+        assert!((f32::from_le_bytes(data) - std::f32::consts::PI).abs() < 1e-3);
+    }
+}
@@ -4,7 +4,7 @@ use bytes::Bytes;
 use gltf::{buffer, image, Document, Error, Gltf, Result};
 use std::{fs, io, path::Path};
 
-use crate::image::ImageSource;
+use crate::{gltf_compression::GltfCompression, image::ImageSource};
 
 type BufferBytes = Bytes;
 
@@ -85,31 +85,41 @@ where
     Ok(data)
 }
 
-/// Import the buffer data referenced by a glTF document.
+/// Import the buffer data referenced by a glTF document, decompressing it if needed.
 pub fn import_buffer_data(
     document: &Document,
     base: Option<&Path>,
     mut blob: Option<Vec<u8>>,
-) -> Result<Vec<Bytes>> {
+    compression: &GltfCompression,
+) -> anyhow::Result<Vec<Bytes>> {
     let mut buffers = Vec::new();
     for buffer in document.buffers() {
-        let mut data = match buffer.source() {
-            buffer::Source::Uri(uri) => Scheme::read(base, uri),
-            buffer::Source::Bin => blob.take().ok_or(Error::MissingBlob),
-        }?;
+        let mut data = if compression.is_fallback_buffer(buffer.index()) {
+            // Only holds decompressed views, filled in below
+            vec![0; buffer.length()]
+        } else {
+            match buffer.source() {
+                buffer::Source::Uri(uri) => Scheme::read(base, uri),
+                buffer::Source::Bin => blob.take().ok_or(Error::MissingBlob),
+            }?
+        };
         if data.len() < buffer.length() {
             return Err(Error::BufferLength {
                 buffer: buffer.index(),
                 expected: buffer.length(),
                 actual: data.len(),
-            });
+            }
+            .into());
         }
         while data.len() % 4 != 0 {
             data.push(0);
         }
-        buffers.push(Bytes::from(data));
+        buffers.push(data);
     }
-    Ok(buffers)
+
+    compression.decompress(document, &mut buffers)?;
+
+    Ok(buffers.into_iter().map(Bytes::from).collect())
 }
 
 /// Import the image data referenced by a glTF document.
@@ -154,22 +164,34 @@ pub fn import_image_data(
     Ok(images)
 }
 
-fn import_impl(Gltf { document, blob }: Gltf, base: Option<&Path>) -> Result<Import> {
-    let buffer_data = import_buffer_data(&document, base, blob)?;
+fn import_impl(
+    Gltf { document, blob }: Gltf,
+    base: Option<&Path>,
+    compression: &GltfCompression,
+) -> anyhow::Result<Import> {
+    let buffer_data = import_buffer_data(&document, base, blob, compression)?;
     let image_data = import_image_data(&document, base, &buffer_data)?;
     let import = (document, buffer_data, image_data);
     Ok(import)
 }
 
-fn import_path(path: &Path) -> Result<Import> {
+fn import_path(path: &Path) -> anyhow::Result<Import> {
     let base = path.parent().unwrap_or_else(|| Path::new("./"));
-    let file = fs::File::open(path).map_err(Error::Io)?;
-    let reader = io::BufReader::new(file);
-    import_impl(Gltf::from_reader_without_validation(reader)?, Some(base))
+    let data = read_to_end(path)?;
+    let compression = GltfCompression::from_file_contents(&data)?;
+
+    let mut gltf = Gltf::from_slice_without_validation(&data)?;
+    if let Some(document) = compression.patched_document()? {
+        gltf.document = document;
+    }
+
+    import_impl(gltf, Some(base), &compression)
 }
 
-/// Import some glTF 2.0 from the file system.
-pub fn import<P>(path: P) -> Result<Import>
+/// Import some glTF 2.0 from the file system. Meshes compressed with `EXT_meshopt_compression`,
+/// or with `KHR_draco_mesh_compression` if the `draco` feature is enabled, are decompressed
+/// on the way.
+pub fn import<P>(path: P) -> anyhow::Result<Import>
 where
     P: AsRef<Path>,
 {
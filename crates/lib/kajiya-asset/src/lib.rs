@@ -1,4 +1,8 @@
+pub mod gaussian_splats;
 pub mod image;
 pub mod mesh;
+pub mod point_cloud;
 
+mod gltf_compression;
 mod import_gltf;
+mod ply;
@@ -17,6 +17,7 @@ use kajiya_backend::bytes::into_byte_vec;
 };*/
 use anyhow::Context as _;
 use std::{
+    collections::HashMap,
     hash::Hash,
     mem::size_of,
     path::{Path, PathBuf},
@@ -56,6 +57,52 @@ pub struct TexParams {
     pub channel_swizzle: Option<[usize; 4]>,
 }
 
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum TexAddressMode {
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum TexFilter {
+    Linear,
+    Nearest,
+}
+
+/// Sampler state of a material map; selects an entry of `MATERIAL_SAMPLER_DESCS`.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct TexSampler {
+    pub address_mode: TexAddressMode,
+    pub filter: TexFilter,
+}
+
+impl Default for TexSampler {
+    fn default() -> Self {
+        Self {
+            address_mode: TexAddressMode::Repeat,
+            filter: TexFilter::Linear,
+        }
+    }
+}
+
+impl TexSampler {
+    /// Index into `kajiya_backend::vulkan::device::MATERIAL_SAMPLER_DESCS`
+    pub fn index(&self) -> u8 {
+        let address_mode = match self.address_mode {
+            TexAddressMode::Repeat => 0,
+            TexAddressMode::Clamp => 1,
+            TexAddressMode::Mirror => 2,
+        };
+        let filter = match self.filter {
+            TexFilter::Linear => 0,
+            TexFilter::Nearest => 1,
+        };
+
+        address_mode * 2 + filter
+    }
+}
+
 #[derive(Clone, Hash, PartialEq, Eq)]
 pub enum MeshMaterialMap {
     Image {
@@ -68,18 +115,162 @@ pub enum MeshMaterialMap {
 pub struct MeshMaterialFlags;
 impl MeshMaterialFlags {
     pub const MESH_MATERIAL_FLAG_EMISSIVE_USED_AS_LIGHT: u32 = 1;
+
+    /// Two bits holding the `MaterialOpacity`
+    pub const MESH_MATERIAL_OPACITY_SHIFT: u32 = 1;
+    pub const MESH_MATERIAL_OPACITY_MASK: u32 = 0b11 << Self::MESH_MATERIAL_OPACITY_SHIFT;
+
+    /// Two bits holding the `MaterialCategory`
+    pub const MESH_MATERIAL_CATEGORY_SHIFT: u32 = 3;
+    pub const MESH_MATERIAL_CATEGORY_MASK: u32 = 0b11 << Self::MESH_MATERIAL_CATEGORY_SHIFT;
+}
+
+/// How much of a material the albedo alpha test (texels below 0.5) cuts out, classified
+/// when baking the mesh. Triangles of the other materials are further classified against the
+/// texels they cover; see `sort_triangles_by_opacity`.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum MaterialOpacity {
+    /// Not classified, e.g. built at runtime, or with a DDS albedo map. Treated as `Masked`.
+    Unclassified = 0,
+
+    /// No albedo texel is cut out.
+    Opaque = 1,
+
+    /// Some albedo texels are cut out.
+    Masked = 2,
+
+    /// A glTF `BLEND` material with some texels cut out. It's alpha-tested like `Masked` ones.
+    Blend = 3,
+}
+
+impl Default for MaterialOpacity {
+    fn default() -> Self {
+        MaterialOpacity::Unclassified
+    }
+}
+
+impl MaterialOpacity {
+    pub const ALL: [MaterialOpacity; 4] = [
+        MaterialOpacity::Unclassified,
+        MaterialOpacity::Opaque,
+        MaterialOpacity::Masked,
+        MaterialOpacity::Blend,
+    ];
+}
+
+/// Class of assets a material belongs to, so that their look can be tuned together at runtime.
+/// Must match `MATERIAL_CATEGORY_*` in `inc/mesh.hlsl`.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum MaterialCategory {
+    Default = 0,
+    Skin = 1,
+    Foliage = 2,
+    Cloth = 3,
+}
+
+impl Default for MaterialCategory {
+    fn default() -> Self {
+        MaterialCategory::Default
+    }
+}
+
+impl MaterialCategory {
+    pub const COUNT: usize = 4;
+
+    pub const ALL: [MaterialCategory; Self::COUNT] = [
+        MaterialCategory::Default,
+        MaterialCategory::Skin,
+        MaterialCategory::Foliage,
+        MaterialCategory::Cloth,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MaterialCategory::Default => "Default",
+            MaterialCategory::Skin => "Skin",
+            MaterialCategory::Foliage => "Foliage",
+            MaterialCategory::Cloth => "Cloth",
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
 #[repr(C)]
 pub struct MeshMaterial {
     pub base_color_mult: [f32; 4],
-    pub maps: [u32; 4],
+
+    /// Normal, specular, albedo, emissive, and transmission thickness maps.
+    pub maps: [u32; 5],
     pub roughness_mult: f32,
     pub metalness_factor: f32,
     pub emissive: [f32; 3],
     pub flags: u32,
     pub map_transforms: [[f32; 6]; 4],
+
+    /// Scales the ambient occlusion applied to surfaces using this material.
+    /// Zero disables occlusion, one is physically-based.
+    pub ao_strength: f32,
+
+    /// `TexSampler::index` of each map, in the same order as `map_transforms`.
+    pub map_samplers: [u8; 4],
+
+    /// Fraction of diffuse light arriving at the back of a thin surface (leaves, paper)
+    /// which passes through to the front. Scaled by one minus the thickness map,
+    /// which uses the UV transform and sampler of the albedo map.
+    pub transmission: f32,
+}
+
+impl MeshMaterial {
+    pub fn set_map_sampler(&mut self, map_idx: usize, sampler: TexSampler) {
+        self.map_samplers[map_idx] = sampler.index();
+    }
+
+    pub fn opacity(&self) -> MaterialOpacity {
+        let bits = (self.flags & MeshMaterialFlags::MESH_MATERIAL_OPACITY_MASK)
+            >> MeshMaterialFlags::MESH_MATERIAL_OPACITY_SHIFT;
+        MaterialOpacity::ALL[bits as usize]
+    }
+
+    pub fn set_opacity(&mut self, opacity: MaterialOpacity) {
+        self.flags = (self.flags & !MeshMaterialFlags::MESH_MATERIAL_OPACITY_MASK)
+            | ((opacity as u32) << MeshMaterialFlags::MESH_MATERIAL_OPACITY_SHIFT);
+    }
+
+    pub fn category(&self) -> MaterialCategory {
+        let bits = (self.flags & MeshMaterialFlags::MESH_MATERIAL_CATEGORY_MASK)
+            >> MeshMaterialFlags::MESH_MATERIAL_CATEGORY_SHIFT;
+        MaterialCategory::ALL[bits as usize]
+    }
+
+    pub fn set_category(&mut self, category: MaterialCategory) {
+        self.flags = (self.flags & !MeshMaterialFlags::MESH_MATERIAL_CATEGORY_MASK)
+            | ((category as u32) << MeshMaterialFlags::MESH_MATERIAL_CATEGORY_SHIFT);
+    }
+}
+
+/// Separates the name of a base material from that of its variant in glTF material names,
+/// e.g. `Window@night` is the `night` variant of `Window`.
+pub const MATERIAL_VARIANT_SEPARATOR: char = '@';
+
+/// Replaces `material` with `replacement` (both indices into the mesh's materials)
+/// while the variant is active.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct MeshMaterialVariant {
+    /// See `material_variant_id`
+    pub variant_id: u64,
+    pub material: u32,
+    pub replacement: u32,
+}
+
+/// Stable identifier of a material variant name, as stored in baked meshes.
+pub fn material_variant_id(name: &str) -> u64 {
+    // FNV-1a
+    name.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
 }
 
 #[derive(Clone, Default)]
@@ -93,6 +284,7 @@ pub struct TriangleMesh {
     pub indices: Vec<u32>,
     pub materials: Vec<MeshMaterial>, // global
     pub maps: Vec<MeshMaterialMap>,   // global
+    pub material_variants: Vec<MeshMaterialVariant>,
     pub images: Vec<ImageSource>,
 }
 
@@ -117,12 +309,48 @@ fn get_gltf_texture_source(tex: gltf::texture::Texture) -> Option<String> {
     }
 }
 
+fn get_gltf_texture_sampler(tex: gltf::texture::Texture) -> TexSampler {
+    use gltf::texture::{MagFilter, WrappingMode};
+
+    let sampler = tex.sampler();
+    let address_mode = |mode| match mode {
+        WrappingMode::Repeat => TexAddressMode::Repeat,
+        WrappingMode::ClampToEdge => TexAddressMode::Clamp,
+        WrappingMode::MirroredRepeat => TexAddressMode::Mirror,
+    };
+
+    // Material samplers use the same address mode on both axes.
+    // Clamping wins, so that decals don't bleed over their edges.
+    let address_modes = [
+        address_mode(sampler.wrap_s()),
+        address_mode(sampler.wrap_t()),
+    ];
+    let address_mode = if address_modes.contains(&TexAddressMode::Clamp) {
+        TexAddressMode::Clamp
+    } else if address_modes.contains(&TexAddressMode::Mirror) {
+        TexAddressMode::Mirror
+    } else {
+        TexAddressMode::Repeat
+    };
+
+    let filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => TexFilter::Nearest,
+        _ => TexFilter::Linear,
+    };
+
+    TexSampler {
+        address_mode,
+        filter,
+    }
+}
+
 fn load_gltf_material(
     mat: &gltf::material::Material,
     document_images: &[ImageSource],
 ) -> (Vec<MeshMaterialMap>, MeshMaterial) {
     const DEFAULT_MAP_TRANSFORM: [f32; 6] = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
     let mut map_transforms: [[f32; 6]; 4] = [DEFAULT_MAP_TRANSFORM; 4];
+    let mut map_samplers: [u8; 4] = [TexSampler::default().index(); 4];
 
     fn texture_transform_to_matrix(xform: Option<TextureTransform>) -> [f32; 6] {
         if let Some(xform) = xform {
@@ -154,6 +382,7 @@ fn load_gltf_material(
             ),
             |tex| {
                 let transform = texture_transform_to_matrix(tex.texture_transform());
+                map_samplers[0] = get_gltf_texture_sampler(tex.texture()).index();
 
                 (
                     MeshMaterialMap::Image {
@@ -176,6 +405,7 @@ fn load_gltf_material(
     let normal_map =
         mat.normal_texture()
             .map_or(MeshMaterialMap::Placeholder([127, 127, 255, 255]), |tex| {
+                map_samplers[1] = get_gltf_texture_sampler(tex.texture()).index();
                 MeshMaterialMap::Image {
                     source: document_images[tex.texture().source().index()].clone(),
                     params: TexParams {
@@ -200,6 +430,7 @@ fn load_gltf_material(
                 )
             },
             |tex| {
+                map_samplers[2] = get_gltf_texture_sampler(tex.texture()).index();
                 (
                     MeshMaterialMap::Image {
                         source: document_images[tex.texture().source().index()].clone(),
@@ -220,6 +451,7 @@ fn load_gltf_material(
     let mut emissive_map = MeshMaterialMap::Placeholder([255, 255, 255, 255]);
     if let Some(tex) = mat.emissive_texture() {
         map_transforms[3] = texture_transform_to_matrix(tex.texture_transform());
+        map_samplers[3] = get_gltf_texture_sampler(tex.texture()).index();
         emissive_map = MeshMaterialMap::Image {
             source: document_images[tex.texture().source().index()].clone(),
             params: TexParams {
@@ -236,20 +468,45 @@ fn load_gltf_material(
     let base_color_mult = mat.pbr_metallic_roughness().base_color_factor();
     let roughness_mult = mat.pbr_metallic_roughness().roughness_factor();
     let metalness_factor = mat.pbr_metallic_roughness().metallic_factor();
+    let ao_strength = mat
+        .occlusion_texture()
+        .map_or(1.0, |occlusion| occlusion.strength());
+
+    // Thin-surface transmission is not read from glTF; see `WorldRenderer::set_material_transmission`.
+    let transmission_map = MeshMaterialMap::Placeholder([0, 0, 0, 255]);
 
     //mata.normal_texture().and_then(|tex| tex.transform())
 
+    // Refined from the albedo map when baking; see `classify_material_opacity`.
+    let opacity = if mat.alpha_mode() == gltf::material::AlphaMode::Blend {
+        MaterialOpacity::Blend
+    } else {
+        MaterialOpacity::Unclassified
+    };
+
+    let mut material = MeshMaterial {
+        base_color_mult,
+        maps: [0, 1, 2, 3, 4],
+        roughness_mult,
+        metalness_factor,
+        emissive,
+        flags: 0,
+        map_transforms,
+        ao_strength,
+        map_samplers,
+        transmission: 0.0,
+    };
+    material.set_opacity(opacity);
+
     (
-        vec![normal_map, spec_map, albedo_map, emissive_map],
-        MeshMaterial {
-            base_color_mult,
-            maps: [0, 1, 2, 3],
-            roughness_mult,
-            metalness_factor,
-            emissive,
-            flags: 0,
-            map_transforms,
-        },
+        vec![
+            normal_map,
+            spec_map,
+            albedo_map,
+            emissive_map,
+            transmission_map,
+        ],
+        material,
     )
 }
 
@@ -282,6 +539,19 @@ impl LazyWorker for LoadGltfScene {
         if let Some(scene) = gltf.default_scene().or_else(|| gltf.scenes().next()) {
             let mut res: TriangleMesh = TriangleMesh::default();
 
+            // Base material name -> (variant name, variant material)
+            let mut material_variants: HashMap<String, Vec<(String, gltf::Material)>> =
+                HashMap::new();
+            for mat in gltf.materials() {
+                let name = mat.name().unwrap_or_default().to_owned();
+                if let Some((base, variant)) = name.split_once(MATERIAL_VARIANT_SEPARATOR) {
+                    material_variants
+                        .entry(base.to_owned())
+                        .or_default()
+                        .push((variant.to_owned(), mat));
+                }
+            }
+
             let mut process_node = |node: &gltf::scene::Node, xform: Mat4| {
                 if let Some(mesh) = node.mesh() {
                     let flip_winding_order = xform.determinant() < 0.0;
@@ -291,9 +561,8 @@ impl LazyWorker for LoadGltfScene {
 
                         let res_material_index = res.materials.len() as u32;
 
-                        {
-                            let (mut maps, mut material) =
-                                load_gltf_material(&prim.material(), imgs.as_slice());
+                        let mut push_material = |mat: &gltf::Material| {
+                            let (mut maps, mut material) = load_gltf_material(mat, imgs.as_slice());
 
                             let map_base = res.maps.len() as u32;
                             for id in material.maps.iter_mut() {
@@ -302,6 +571,23 @@ impl LazyWorker for LoadGltfScene {
 
                             res.materials.push(material);
                             res.maps.append(&mut maps);
+                            res.materials.len() as u32 - 1
+                        };
+
+                        let prim_material = prim.material();
+                        push_material(&prim_material);
+
+                        // Variants aren't referenced by primitives; load them alongside the base.
+                        let variants = prim_material
+                            .name()
+                            .and_then(|name| material_variants.get(name));
+                        for (variant, mat) in variants.into_iter().flatten() {
+                            let replacement = push_material(mat);
+                            res.material_variants.push(MeshMaterialVariant {
+                                variant_id: material_variant_id(variant),
+                                material: res_material_index,
+                                replacement,
+                            });
                         }
 
                         // Collect positions (required)
@@ -449,6 +735,79 @@ pub struct PackedVertex {
     normal: u32,
 }
 
+impl PackedVertex {
+    /// `normal` is expected to be normalized; it's packed to 11/10/11 bits.
+    pub fn new(pos: [f32; 3], normal: [f32; 3]) -> Self {
+        Self {
+            pos,
+            normal: pack_unit_direction_11_10_11(normal[0], normal[1], normal[2]),
+        }
+    }
+}
+
+/// Size and curvature of the triangles of a mesh, in its own units. Used to offset rays
+/// leaving its surface by just enough to avoid self-intersection, whatever the scale
+/// of the geometry.
+#[derive(Clone, Copy, Default, Debug)]
+#[repr(C)]
+pub struct MeshGeometryScale {
+    /// Median length of the triangle edges
+    pub edge_length: f32,
+
+    /// Median angle between the normals at either end of an edge, in radians.
+    /// Large for coarse tessellations of curved surfaces, where the interpolated
+    /// shading normals stray far from the flat triangles.
+    pub normal_angle: f32,
+}
+
+impl MeshGeometryScale {
+    pub fn compute(positions: &[[f32; 3]], normals: &[[f32; 3]], indices: &[u32]) -> Self {
+        let mut edge_lengths = Vec::with_capacity(indices.len());
+        let mut normal_angles = Vec::with_capacity(indices.len());
+
+        for tri in indices.chunks_exact(3) {
+            for (a, b) in [(tri[0], tri[1]), (tri[1], tri[2]), (tri[2], tri[0])] {
+                let (a, b) = (a as usize, b as usize);
+
+                let edge_length = Vec3::from(positions[a]).distance(Vec3::from(positions[b]));
+                if edge_length > 0.0 {
+                    edge_lengths.push(edge_length);
+                }
+
+                if let (Some(na), Some(nb)) = (normals.get(a), normals.get(b)) {
+                    let cos_angle = Vec3::from(*na)
+                        .normalize_or_zero()
+                        .dot(Vec3::from(*nb).normalize_or_zero());
+                    normal_angles.push(cos_angle.clamp(-1.0, 1.0).acos());
+                }
+            }
+        }
+
+        fn median(values: &mut [f32]) -> f32 {
+            if values.is_empty() {
+                return 0.0;
+            }
+
+            let mid = values.len() / 2;
+            *values
+                .select_nth_unstable_by(mid, |a, b| a.partial_cmp(b).unwrap())
+                .1
+        }
+
+        Self {
+            edge_length: median(&mut edge_lengths),
+            normal_angle: median(&mut normal_angles),
+        }
+    }
+
+    /// Distance to offset rays from the surface by, in mesh units. Covers the gap between
+    /// a flat triangle and the smooth surface implied by its normals (the sagitta of an arc
+    /// spanning the edge), along with a small fraction of the edge for numerical precision.
+    pub fn ray_bias(&self) -> f32 {
+        self.edge_length * (1e-3 + 0.125 * self.normal_angle)
+    }
+}
+
 fn pack_unit_direction_11_10_11(x: f32, y: f32, z: f32) -> u32 {
     let x = ((x.max(-1.0).min(1.0) * 0.5 + 0.5) * ((1u32 << 11u32) - 1u32) as f32) as u32;
     let y = ((y.max(-1.0).min(1.0) * 0.5 + 0.5) * ((1u32 << 10u32) - 1u32) as f32) as u32;
@@ -801,9 +1160,12 @@ def_asset! {
         tangents { Vec([f32; 4]) }
         colors { Vec([f32; 4]) }
         indices { Vec(u32) }
+        opaque_index_count { u32 }
         material_ids { Vec(u32) }
         materials { Vec(MeshMaterial) }
         maps { Vec(Asset(GpuImage)) }
+        material_variants { Vec(MeshMaterialVariant) }
+        geometry_scale { MeshGeometryScale }
     }
 }
 
@@ -827,10 +1189,7 @@ pub fn pack_triangle_mesh(mesh: &TriangleMesh) -> PackedTriangleMesh {
     for (i, pos) in mesh.positions.iter().enumerate() {
         let n = mesh.normals[i];
 
-        verts.push(PackedVertex {
-            pos: *pos,
-            normal: pack_unit_direction_11_10_11(n[0], n[1], n[2]),
-        });
+        verts.push(PackedVertex::new(*pos, n));
     }
 
     let maps = mesh
@@ -857,18 +1216,309 @@ pub fn pack_triangle_mesh(mesh: &TriangleMesh) -> PackedTriangleMesh {
         })
         .collect();
 
+    // Albedo maps are often shared between materials, so each is decoded once.
+    let mut alpha_maps: HashMap<u32, Option<AlphaMap>> = HashMap::new();
+    for material in &mesh.materials {
+        // The albedo map is the third one; see `MeshMaterial::maps`.
+        let albedo_map = material.maps[2];
+        alpha_maps
+            .entry(albedo_map)
+            .or_insert_with(|| match &mesh.maps[albedo_map as usize] {
+                MeshMaterialMap::Placeholder(values) => Some(AlphaMap::placeholder(values[3])),
+                MeshMaterialMap::Image { source, .. } => AlphaMap::decode(source),
+            });
+    }
+
+    let materials: Vec<MeshMaterial> = mesh
+        .materials
+        .iter()
+        .map(|material| {
+            let has_cut_out_texels = alpha_maps[&material.maps[2]]
+                .as_ref()
+                .map(AlphaMap::has_cut_out_texels);
+
+            let mut material = *material;
+            material.set_opacity(classify_material_opacity(
+                material.opacity(),
+                has_cut_out_texels,
+            ));
+            material
+        })
+        .collect();
+
+    let (indices, opaque_index_count) = sort_triangles_by_opacity(mesh, &materials, &alpha_maps);
+
     PackedTriangleMesh {
         verts,
         uvs: mesh.uvs.clone(),
         tangents: mesh.tangents.clone(),
         colors: mesh.colors.clone(),
-        indices: mesh.indices.clone(),
+        indices,
+        opaque_index_count: opaque_index_count as u32,
         material_ids: mesh.material_ids.clone(),
-        materials: mesh.materials.clone(),
+        materials,
         maps,
+        material_variants: mesh.material_variants.clone(),
+        geometry_scale: MeshGeometryScale::compute(&mesh.positions, &mesh.normals, &mesh.indices),
     }
 }
 
+/// Classifies a material by whether the alpha test cuts out texels of its albedo map.
+/// Materials whose map couldn't be decoded keep their opacity.
+fn classify_material_opacity(
+    opacity: MaterialOpacity,
+    has_cut_out_texels: Option<bool>,
+) -> MaterialOpacity {
+    match has_cut_out_texels {
+        None => opacity,
+        Some(false) => MaterialOpacity::Opaque,
+        Some(true) if opacity == MaterialOpacity::Blend => MaterialOpacity::Blend,
+        Some(true) => MaterialOpacity::Masked,
+    }
+}
+
+/// How much of a triangle the albedo alpha test cuts out.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TriangleOpacity {
+    Opaque,
+    Mixed,
+    Transparent,
+}
+
+impl TriangleOpacity {
+    // Opacity of a triangle which switches between materials with these opacities
+    fn union(self, other: Self) -> Self {
+        if self == other {
+            self
+        } else {
+            TriangleOpacity::Mixed
+        }
+    }
+}
+
+// Triangles whose UVs span more texels than this aren't rasterized, and count as `Mixed`.
+const MAX_CLASSIFIED_TRIANGLE_TEXELS: f32 = (1 << 22) as f32;
+
+// Texels within this distance of a triangle count as covered by it, as bilinear filtering
+// and mip-mapping blend them into its alpha.
+const TRIANGLE_COVERAGE_MARGIN_TEXELS: f32 = 1.0;
+
+/// Alpha channel of an albedo map, for classifying triangles against the alpha test
+/// (texels below 0.5 are cut out).
+struct AlphaMap {
+    width: u32,
+    height: u32,
+    alpha: Vec<u8>,
+}
+
+impl AlphaMap {
+    fn placeholder(alpha: u8) -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            alpha: vec![alpha],
+        }
+    }
+
+    // `None` for images `image` can't decode, like DDS.
+    fn decode(source: &ImageSource) -> Option<Self> {
+        let bytes = match source {
+            ImageSource::File(path) => {
+                std::fs::read(kajiya_backend::file::canonical_path_from_vfs(path).ok()?).ok()?
+            }
+            ImageSource::Memory(bytes) => bytes.to_vec(),
+        };
+
+        let image = image::load_from_memory(&bytes).ok()?;
+        if !image.color().has_alpha() {
+            return Some(Self::placeholder(255));
+        }
+
+        let image = image.to_rgba8();
+        Some(Self {
+            width: image.width(),
+            height: image.height(),
+            alpha: image.pixels().map(|texel| texel.0[3]).collect(),
+        })
+    }
+
+    fn has_cut_out_texels(&self) -> bool {
+        self.alpha.iter().any(|&alpha| alpha < 128)
+    }
+
+    fn texel(&self, x: i64, y: i64, address_mode: TexAddressMode) -> u8 {
+        let wrap = |coord: i64, size: u32| -> usize {
+            let size = size as i64;
+            let coord = match address_mode {
+                TexAddressMode::Repeat => coord.rem_euclid(size),
+                TexAddressMode::Clamp => coord.clamp(0, size - 1),
+                TexAddressMode::Mirror => {
+                    let coord = coord.rem_euclid(2 * size);
+                    if coord < size {
+                        coord
+                    } else {
+                        2 * size - 1 - coord
+                    }
+                }
+            };
+            coord as usize
+        };
+
+        self.alpha[wrap(y, self.height) * self.width as usize + wrap(x, self.width)]
+    }
+
+    /// Classifies the texels covered by a triangle with the given (transformed) UVs.
+    fn triangle_opacity(
+        &self,
+        uvs: [[f32; 2]; 3],
+        address_mode: TexAddressMode,
+    ) -> TriangleOpacity {
+        let size = glam::Vec2::new(self.width as f32, self.height as f32);
+        let p = uvs.map(|uv| glam::Vec2::from(uv) * size);
+
+        let margin = glam::Vec2::splat(TRIANGLE_COVERAGE_MARGIN_TEXELS);
+        let bounds_min = (p[0].min(p[1]).min(p[2]) - margin).floor();
+        let bounds_max = (p[0].max(p[1]).max(p[2]) + margin).ceil();
+        let bounds_size = bounds_max - bounds_min;
+        let texel_count = bounds_size.x * bounds_size.y;
+        if !texel_count.is_finite() || texel_count > MAX_CLASSIFIED_TRIANGLE_TEXELS {
+            return TriangleOpacity::Mixed;
+        }
+
+        // Signed distance of a point to each edge, positive inside the triangle.
+        let winding = (p[1] - p[0]).perp_dot(p[2] - p[0]).signum();
+        let edges: Vec<(glam::Vec2, glam::Vec2)> = (0..3)
+            .filter_map(|i| {
+                let (a, b) = (p[i], p[(i + 1) % 3]);
+                let len = (b - a).length();
+                (len > 0.0).then(|| (a, (b - a) * (winding / len)))
+            })
+            .collect();
+        let is_covered = |point: glam::Vec2| {
+            // Degenerate triangles are only classified by their vertices.
+            winding != 0.0
+                && edges.iter().all(|&(origin, dir)| {
+                    dir.perp_dot(point - origin) >= -TRIANGLE_COVERAGE_MARGIN_TEXELS
+                })
+        };
+
+        let mut any_opaque = false;
+        let mut any_cut_out = false;
+        let mut visit = |x: i64, y: i64| {
+            if self.texel(x, y, address_mode) < 128 {
+                any_cut_out = true;
+            } else {
+                any_opaque = true;
+            }
+            any_opaque && any_cut_out
+        };
+
+        for vertex in p {
+            if visit(vertex.x.floor() as i64, vertex.y.floor() as i64) {
+                return TriangleOpacity::Mixed;
+            }
+        }
+
+        for y in bounds_min.y as i64..bounds_max.y as i64 {
+            for x in bounds_min.x as i64..bounds_max.x as i64 {
+                let center = glam::Vec2::new(x as f32 + 0.5, y as f32 + 0.5);
+                if is_covered(center) && visit(x, y) {
+                    return TriangleOpacity::Mixed;
+                }
+            }
+        }
+
+        if any_cut_out {
+            TriangleOpacity::Transparent
+        } else {
+            TriangleOpacity::Opaque
+        }
+    }
+}
+
+fn tex_address_mode(sampler_index: u8) -> TexAddressMode {
+    // Inverse of `TexSampler::index`
+    match sampler_index / 2 {
+        1 => TexAddressMode::Clamp,
+        2 => TexAddressMode::Mirror,
+        _ => TexAddressMode::Repeat,
+    }
+}
+
+fn triangle_opacity(
+    mesh: &TriangleMesh,
+    material: &MeshMaterial,
+    alpha_maps: &HashMap<u32, Option<AlphaMap>>,
+    triangle: &[u32],
+) -> TriangleOpacity {
+    let alpha_map = match material.opacity() {
+        MaterialOpacity::Opaque => return TriangleOpacity::Opaque,
+        // Blending also shows texels which the alpha test would cut out.
+        MaterialOpacity::Blend => return TriangleOpacity::Mixed,
+        MaterialOpacity::Unclassified | MaterialOpacity::Masked => {
+            match &alpha_maps[&material.maps[2]] {
+                Some(alpha_map) => alpha_map,
+                None => return TriangleOpacity::Mixed,
+            }
+        }
+    };
+
+    // Matches `transform_material_uv` for the albedo map.
+    let xform = material.map_transforms[0];
+    let uvs = [0, 1, 2].map(|i| {
+        let [u, v] = mesh
+            .uvs
+            .get(triangle[i] as usize)
+            .copied()
+            .unwrap_or_default();
+        [
+            xform[0] * u + xform[1] * v + xform[4],
+            xform[2] * u + xform[3] * v + xform[5],
+        ]
+    });
+
+    alpha_map.triangle_opacity(uvs, tex_address_mode(material.map_samplers[0]))
+}
+
+/// Moves triangles which the alpha test never cuts into to the front of the index buffer,
+/// followed by those it sometimes does, and drops ones it always cuts out. Ray tracing
+/// builds the former into an opaque BLAS geometry, and only runs any-hit shaders on the latter.
+///
+/// Returns the indices, and the number of them belonging to opaque triangles.
+fn sort_triangles_by_opacity(
+    mesh: &TriangleMesh,
+    materials: &[MeshMaterial],
+    alpha_maps: &HashMap<u32, Option<AlphaMap>>,
+) -> (Vec<u32>, usize) {
+    let mut opaque = Vec::with_capacity(mesh.indices.len());
+    let mut mixed = Vec::new();
+
+    for triangle in mesh.indices.chunks_exact(3) {
+        // Material variants may switch the triangle to another material at runtime.
+        let material_idx = mesh.material_ids[triangle[0] as usize];
+        let opacity = std::iter::once(material_idx)
+            .chain(
+                mesh.material_variants
+                    .iter()
+                    .filter(|variant| variant.material == material_idx)
+                    .map(|variant| variant.replacement),
+            )
+            .map(|idx| triangle_opacity(mesh, &materials[idx as usize], alpha_maps, triangle))
+            .reduce(TriangleOpacity::union)
+            .unwrap();
+
+        match opacity {
+            TriangleOpacity::Opaque => opaque.extend_from_slice(triangle),
+            TriangleOpacity::Mixed => mixed.extend_from_slice(triangle),
+            TriangleOpacity::Transparent => {}
+        }
+    }
+
+    let opaque_index_count = opaque.len();
+    opaque.append(&mut mixed);
+    (opaque, opaque_index_count)
+}
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 struct GpuMaterial {
@@ -885,6 +1535,24 @@ impl Default for GpuMaterial {
     }
 }
 
+/// MikkTSpace tangents of an indexed triangle mesh, with the bitangent sign in `w`.
+pub fn calculate_tangents(
+    indices: &[u32],
+    positions: &[[f32; 3]],
+    normals: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+) -> Vec<[f32; 4]> {
+    let mut tangents = vec![[1.0, 0.0, 0.0, 1.0]; positions.len()];
+    mikktspace::generate_tangents(&mut TangentCalcContext {
+        indices,
+        positions,
+        normals,
+        uvs,
+        tangents: tangents.as_mut_slice(),
+    });
+    tangents
+}
+
 struct TangentCalcContext<'a> {
     indices: &'a [u32],
     positions: &'a [[f32; 3]],
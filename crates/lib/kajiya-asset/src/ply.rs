@@ -0,0 +1,200 @@
+use anyhow::{bail, Context as _};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum PlyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyType {
+    fn parse(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => bail!("Unknown PLY type {:?}", s),
+        })
+    }
+
+    // Maps color values of this type to [0, 1]
+    pub(crate) fn color_scale(self) -> f32 {
+        match self {
+            Self::I8 | Self::U8 | Self::I32 | Self::U32 => 1.0 / 255.0,
+            Self::I16 | Self::U16 => 1.0 / 65535.0,
+            Self::F32 | Self::F64 => 1.0,
+        }
+    }
+}
+
+pub(crate) struct PlyProperty {
+    pub(crate) name: String,
+    pub(crate) ty: PlyType,
+
+    // Type of the item count, for list properties
+    list_count_ty: Option<PlyType>,
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+enum PlyBody<'a> {
+    Ascii(std::str::SplitAsciiWhitespace<'a>),
+    BinaryLittleEndian(&'a [u8]),
+    BinaryBigEndian(&'a [u8]),
+}
+
+impl<'a> PlyBody<'a> {
+    fn read(&mut self, ty: PlyType) -> anyhow::Result<f64> {
+        match self {
+            Self::Ascii(tokens) => Ok(tokens
+                .next()
+                .context("PLY data is truncated")?
+                .parse::<f64>()?),
+            Self::BinaryLittleEndian(data) => read_ply_binary::<LittleEndian>(data, ty),
+            Self::BinaryBigEndian(data) => read_ply_binary::<BigEndian>(data, ty),
+        }
+        .context("Reading PLY data")
+    }
+}
+
+fn read_ply_binary<B: ByteOrder>(data: &mut &[u8], ty: PlyType) -> anyhow::Result<f64> {
+    Ok(match ty {
+        PlyType::I8 => data.read_i8()? as f64,
+        PlyType::U8 => data.read_u8()? as f64,
+        PlyType::I16 => data.read_i16::<B>()? as f64,
+        PlyType::U16 => data.read_u16::<B>()? as f64,
+        PlyType::I32 => data.read_i32::<B>()? as f64,
+        PlyType::U32 => data.read_u32::<B>()? as f64,
+        PlyType::F32 => data.read_f32::<B>()? as f64,
+        PlyType::F64 => data.read_f64::<B>()?,
+    })
+}
+
+/// Reads the `vertex` element of a PLY file. `make_visitor` gets the vertex properties,
+/// and returns a function called with their values for every vertex, in the same order.
+/// List properties are skipped, and read as zero.
+pub(crate) fn read_ply_vertices<F, V>(data: &[u8], make_visitor: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&[PlyProperty]) -> anyhow::Result<V>,
+    V: FnMut(&[f64]),
+{
+    const END_HEADER: &[u8] = b"end_header";
+
+    let header_end = data
+        .windows(END_HEADER.len())
+        .position(|w| w == END_HEADER)
+        .context("PLY header is not terminated")?;
+    let body_start = data[header_end..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map(|pos| header_end + pos + 1)
+        .context("PLY header is not terminated")?;
+
+    let header = std::str::from_utf8(&data[..header_end]).context("PLY header is not UTF-8")?;
+    let mut lines = header.lines().map(str::trim);
+
+    if lines.next() != Some("ply") {
+        bail!("Not a PLY file");
+    }
+
+    let mut format = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+
+    for line in lines {
+        let words: Vec<&str> = line.split_ascii_whitespace().collect();
+        match words.as_slice() {
+            ["format", format_name, _version] => format = Some(format_name.to_string()),
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count.parse()?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count_ty, ty, name] => elements
+                .last_mut()
+                .context("PLY property outside of an element")?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    ty: PlyType::parse(ty)?,
+                    list_count_ty: Some(PlyType::parse(count_ty)?),
+                }),
+            ["property", ty, name] => elements
+                .last_mut()
+                .context("PLY property outside of an element")?
+                .properties
+                .push(PlyProperty {
+                    name: name.to_string(),
+                    ty: PlyType::parse(ty)?,
+                    list_count_ty: None,
+                }),
+            _ => {}
+        }
+    }
+
+    let body_bytes = &data[body_start..];
+    let mut body = match format.as_deref() {
+        Some("ascii") => PlyBody::Ascii(
+            std::str::from_utf8(body_bytes)
+                .context("PLY data is not UTF-8")?
+                .split_ascii_whitespace(),
+        ),
+        Some("binary_little_endian") => PlyBody::BinaryLittleEndian(body_bytes),
+        Some("binary_big_endian") => PlyBody::BinaryBigEndian(body_bytes),
+        _ => bail!("Unknown PLY format {:?}", format),
+    };
+
+    let mut visitor = None;
+
+    for element in &elements {
+        let is_vertex = element.name == "vertex";
+        if is_vertex {
+            visitor = Some(make_visitor(&element.properties)?);
+        }
+
+        let mut values = vec![0.0f64; element.properties.len()];
+
+        for _ in 0..element.count {
+            for (value, property) in values.iter_mut().zip(&element.properties) {
+                if let Some(count_ty) = property.list_count_ty {
+                    let count = body.read(count_ty)? as usize;
+                    for _ in 0..count {
+                        body.read(property.ty)?;
+                    }
+                } else {
+                    *value = body.read(property.ty)?;
+                }
+            }
+
+            if let Some(visitor) = visitor.as_mut() {
+                visitor(&values);
+            }
+        }
+
+        // Nothing of interest follows the vertices.
+        if is_vertex {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+/// Index of the first property named any of `names`
+pub(crate) fn ply_property_index(properties: &[PlyProperty], names: &[&str]) -> Option<usize> {
+    properties.iter().position(|p| names.contains(&&*p.name))
+}
@@ -0,0 +1,441 @@
+use anyhow::{bail, Context as _};
+use byteorder::{ByteOrder, LittleEndian};
+use glam::Vec3;
+use std::{collections::HashMap, path::Path};
+
+use crate::ply::{ply_property_index, read_ply_vertices};
+
+/// Points loaded from a LAS or PLY scan.
+///
+/// Positions are re-centered on the bounding box, and `origin` holds the offset.
+/// Georeferenced scans use coordinates far too large for `f32` to resolve millimeters.
+#[derive(Clone, Default)]
+pub struct PointCloud {
+    /// Added to `positions` to get back the original coordinates
+    pub origin: [f64; 3],
+    pub positions: Vec<[f32; 3]>,
+
+    /// Linear RGB; empty if the file has no colors.
+    pub colors: Vec<[f32; 3]>,
+
+    /// Empty if the file has no normals; see `estimate_normals`.
+    pub normals: Vec<[f32; 3]>,
+}
+
+/// Loads a `.las` or `.ply` file, picking the format by the extension.
+/// Compressed LAZ files aren't supported; decompress them with `laszip` first.
+pub fn load_point_cloud(path: impl AsRef<Path>) -> anyhow::Result<PointCloud> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).with_context(|| format!("Reading {:?}", path))?;
+
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("las") => parse_las(&data),
+        Some("ply") => parse_ply(&data),
+        Some("laz") => bail!("LAZ point clouds are not supported; decompress to LAS first"),
+        _ => bail!("Unrecognized point cloud format: {:?}", path),
+    }
+    .with_context(|| format!("Loading point cloud {:?}", path))
+}
+
+impl PointCloud {
+    fn from_f64_positions(
+        positions: Vec<[f64; 3]>,
+        colors: Vec<[f32; 3]>,
+        normals: Vec<[f32; 3]>,
+    ) -> Self {
+        let mut min = [f64::MAX; 3];
+        let mut max = [f64::MIN; 3];
+        for pos in &positions {
+            for i in 0..3 {
+                min[i] = min[i].min(pos[i]);
+                max[i] = max[i].max(pos[i]);
+            }
+        }
+
+        let origin = if positions.is_empty() {
+            [0.0; 3]
+        } else {
+            [
+                (min[0] + max[0]) * 0.5,
+                (min[1] + max[1]) * 0.5,
+                (min[2] + max[2]) * 0.5,
+            ]
+        };
+
+        let positions = positions
+            .iter()
+            .map(|pos| {
+                [
+                    (pos[0] - origin[0]) as f32,
+                    (pos[1] - origin[1]) as f32,
+                    (pos[2] - origin[2]) as f32,
+                ]
+            })
+            .collect();
+
+        Self {
+            origin,
+            positions,
+            colors,
+            normals,
+        }
+    }
+
+    /// Swaps axes of Z-up data, as used by most LAS files, to the Y-up convention of kajiya.
+    pub fn z_up_to_y_up(&mut self) {
+        let swap = |v: &mut [f32; 3]| *v = [v[0], v[2], -v[1]];
+        self.positions.iter_mut().for_each(swap);
+        self.normals.iter_mut().for_each(swap);
+        self.origin = [self.origin[0], self.origin[2], -self.origin[1]];
+    }
+
+    /// Fits a plane to the neighbors of each point within `radius`, and uses its normal.
+    /// Scans don't record which side of a surface was scanned, so the normals are flipped
+    /// to face `viewpoint`, e.g. the scanner position, in the re-centered space of `positions`.
+    ///
+    /// Points with fewer than three neighbors get normals pointing at `viewpoint`.
+    pub fn estimate_normals(&mut self, radius: f32, viewpoint: [f32; 3]) {
+        assert!(radius > 0.0, "radius must be positive");
+
+        let cell_of = |pos: &[f32; 3]| {
+            [
+                (pos[0] / radius).floor() as i32,
+                (pos[1] / radius).floor() as i32,
+                (pos[2] / radius).floor() as i32,
+            ]
+        };
+
+        let mut grid: HashMap<[i32; 3], Vec<u32>> = HashMap::new();
+        for (i, pos) in self.positions.iter().enumerate() {
+            grid.entry(cell_of(pos)).or_default().push(i as u32);
+        }
+
+        let viewpoint = Vec3::from(viewpoint);
+        let radius2 = radius * radius;
+
+        self.normals = self
+            .positions
+            .iter()
+            .map(|pos| {
+                let center = Vec3::from(*pos);
+                let cell = cell_of(pos);
+
+                let mut neighbors: Vec<Vec3> = Vec::new();
+                for x in -1..=1 {
+                    for y in -1..=1 {
+                        for z in -1..=1 {
+                            let cell = [cell[0] + x, cell[1] + y, cell[2] + z];
+                            for &idx in grid.get(&cell).into_iter().flatten() {
+                                let p = Vec3::from(self.positions[idx as usize]);
+                                if (p - center).length_squared() <= radius2 {
+                                    neighbors.push(p);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                let to_viewpoint = (viewpoint - center).normalize_or_zero();
+                if neighbors.len() < 3 {
+                    return to_viewpoint.into();
+                }
+
+                let normal = plane_normal(&neighbors);
+                let normal = if normal.dot(to_viewpoint) < 0.0 {
+                    -normal
+                } else {
+                    normal
+                };
+
+                normal.into()
+            })
+            .collect();
+    }
+
+    /// Merges the points within each cell of a grid with `voxel_size` edges into one,
+    /// averaging their positions, colors, and normals.
+    pub fn voxelize(&self, voxel_size: f32) -> PointCloud {
+        assert!(voxel_size > 0.0, "voxel size must be positive");
+
+        #[derive(Default)]
+        struct Voxel {
+            position: Vec3,
+            color: Vec3,
+            normal: Vec3,
+            count: u32,
+        }
+
+        let mut voxels: HashMap<[i32; 3], Voxel> = HashMap::new();
+        let mut voxel_order: Vec<[i32; 3]> = Vec::new();
+
+        for (i, pos) in self.positions.iter().enumerate() {
+            let key = [
+                (pos[0] / voxel_size).floor() as i32,
+                (pos[1] / voxel_size).floor() as i32,
+                (pos[2] / voxel_size).floor() as i32,
+            ];
+
+            let voxel = voxels.entry(key).or_insert_with(|| {
+                voxel_order.push(key);
+                Voxel::default()
+            });
+
+            voxel.position += Vec3::from(*pos);
+            voxel.count += 1;
+
+            if let Some(color) = self.colors.get(i) {
+                voxel.color += Vec3::from(*color);
+            }
+            if let Some(normal) = self.normals.get(i) {
+                voxel.normal += Vec3::from(*normal);
+            }
+        }
+
+        let voxels: Vec<&Voxel> = voxel_order.iter().map(|key| &voxels[key]).collect();
+        let mean = |v: Vec3, voxel: &Voxel| -> [f32; 3] { (v / voxel.count as f32).into() };
+
+        PointCloud {
+            origin: self.origin,
+            positions: voxels.iter().map(|v| mean(v.position, v)).collect(),
+            colors: if self.colors.is_empty() {
+                Vec::new()
+            } else {
+                voxels.iter().map(|v| mean(v.color, v)).collect()
+            },
+            normals: if self.normals.is_empty() {
+                Vec::new()
+            } else {
+                voxels
+                    .iter()
+                    .map(|v| v.normal.normalize_or_zero().into())
+                    .collect()
+            },
+        }
+    }
+}
+
+// Eigenvector of the smallest eigenvalue of the points' covariance
+fn plane_normal(points: &[Vec3]) -> Vec3 {
+    let mean = points.iter().copied().sum::<Vec3>() / points.len() as f32;
+
+    let mut cov = [[0.0f64; 3]; 3];
+    for p in points {
+        let d = *p - mean;
+        let d = [d.x as f64, d.y as f64, d.z as f64];
+        for i in 0..3 {
+            for j in 0..3 {
+                cov[i][j] += d[i] * d[j];
+            }
+        }
+    }
+
+    let (eigenvalues, eigenvectors) = symmetric_eigen_3x3(cov);
+
+    let mut smallest = 0;
+    for i in 1..3 {
+        if eigenvalues[i] < eigenvalues[smallest] {
+            smallest = i;
+        }
+    }
+
+    Vec3::new(
+        eigenvectors[0][smallest] as f32,
+        eigenvectors[1][smallest] as f32,
+        eigenvectors[2][smallest] as f32,
+    )
+    .normalize_or_zero()
+}
+
+// Cyclic Jacobi rotations. Returns the eigenvalues, and the eigenvectors as columns.
+fn symmetric_eigen_3x3(mut a: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+    for _ in 0..16 {
+        let off_diagonal = a[0][1].abs() + a[0][2].abs() + a[1][2].abs();
+        if off_diagonal < 1e-20 {
+            break;
+        }
+
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1e-30 {
+                continue;
+            }
+
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+
+            for row in a.iter_mut() {
+                let (akp, akq) = (row[p], row[q]);
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            for k in 0..3 {
+                let (apk, aqk) = (a[p][k], a[q][k]);
+                a[p][k] = c * apk - s * aqk;
+                a[q][k] = s * apk + c * aqk;
+            }
+            for row in v.iter_mut() {
+                let (vkp, vkq) = (row[p], row[q]);
+                row[p] = c * vkp - s * vkq;
+                row[q] = s * vkp + c * vkq;
+            }
+        }
+    }
+
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+pub(crate) fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// ASPRS LAS 1.0 to 1.4, uncompressed
+fn parse_las(data: &[u8]) -> anyhow::Result<PointCloud> {
+    if data.len() < 227 || &data[0..4] != b"LASF" {
+        bail!("Not a LAS file");
+    }
+
+    let version_minor = data[25];
+    let offset_to_points = LittleEndian::read_u32(&data[96..]) as usize;
+    let point_format = data[104];
+    let record_len = LittleEndian::read_u16(&data[105..]) as usize;
+    let legacy_point_count = LittleEndian::read_u32(&data[107..]) as u64;
+
+    if point_format & 0xc0 != 0 {
+        bail!("Compressed LAS point data is not supported");
+    }
+
+    let point_count = if version_minor >= 4 && legacy_point_count == 0 && data.len() >= 255 {
+        LittleEndian::read_u64(&data[247..])
+    } else {
+        legacy_point_count
+    } as usize;
+
+    let read_f64 = |offset: usize| LittleEndian::read_f64(&data[offset..]);
+    let scale = [read_f64(131), read_f64(139), read_f64(147)];
+    let offset = [read_f64(155), read_f64(163), read_f64(171)];
+
+    // Byte offset of RGB in the point record, by point data format
+    let rgb_offset = match point_format {
+        0 | 1 | 4 | 6 | 9 => None,
+        2 => Some(20),
+        3 | 5 => Some(28),
+        7 | 8 | 10 => Some(30),
+        _ => bail!("Unsupported LAS point data format {}", point_format),
+    };
+
+    let min_record_len = rgb_offset.map_or(12, |rgb_offset| rgb_offset + 6);
+    if record_len < min_record_len {
+        bail!("LAS point records are too short: {} bytes", record_len);
+    }
+
+    let points_end = offset_to_points + point_count * record_len;
+    if data.len() < points_end {
+        bail!("LAS point data is truncated");
+    }
+
+    let records = || data[offset_to_points..points_end].chunks_exact(record_len);
+
+    let positions = records()
+        .map(|record| {
+            let mut pos = [0.0; 3];
+            for i in 0..3 {
+                let v = LittleEndian::read_i32(&record[i * 4..]) as f64;
+                pos[i] = v * scale[i] + offset[i];
+            }
+            pos
+        })
+        .collect();
+
+    let colors = if let Some(rgb_offset) = rgb_offset {
+        let rgb: Vec<[u16; 3]> = records()
+            .map(|record| {
+                let mut rgb = [0u16; 3];
+                LittleEndian::read_u16_into(&record[rgb_offset..rgb_offset + 6], &mut rgb);
+                rgb
+            })
+            .collect();
+
+        // The spec calls for 16 bits per channel, but plenty of files store 8 bits.
+        let max_value = if rgb.iter().flatten().any(|&c| c > 255) {
+            65535.0
+        } else {
+            255.0
+        };
+
+        rgb.iter()
+            .map(|rgb| rgb.map(|c| srgb_to_linear(c as f32 / max_value)))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    Ok(PointCloud::from_f64_positions(
+        positions,
+        colors,
+        Vec::new(),
+    ))
+}
+
+fn parse_ply(data: &[u8]) -> anyhow::Result<PointCloud> {
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    let mut normals = Vec::new();
+    let (positions_out, colors_out, normals_out) = (&mut positions, &mut colors, &mut normals);
+
+    read_ply_vertices(data, move |properties| {
+        let property_index = |names: &[&str]| ply_property_index(properties, names);
+
+        let pos_idx = [
+            property_index(&["x"]),
+            property_index(&["y"]),
+            property_index(&["z"]),
+        ];
+        let normal_idx = [
+            property_index(&["nx"]),
+            property_index(&["ny"]),
+            property_index(&["nz"]),
+        ];
+        let color_idx = [
+            property_index(&["red", "r", "diffuse_red"]),
+            property_index(&["green", "g", "diffuse_green"]),
+            property_index(&["blue", "b", "diffuse_blue"]),
+        ];
+
+        if pos_idx.iter().any(Option::is_none) {
+            bail!("PLY vertices have no positions");
+        }
+
+        let has_normals = normal_idx.iter().all(Option::is_some);
+        let color_scales = color_idx.map(|idx| idx.map(|idx| properties[idx].ty.color_scale()));
+        let has_colors = color_scales.iter().all(Option::is_some);
+
+        Ok(move |values: &[f64]| {
+            positions_out.push(pos_idx.map(|idx| values[idx.unwrap()]));
+
+            if has_normals {
+                normals_out.push(normal_idx.map(|idx| values[idx.unwrap()] as f32));
+            }
+
+            if has_colors {
+                colors_out.push([0, 1, 2].map(|i| {
+                    let value = values[color_idx[i].unwrap()] as f32 * color_scales[i].unwrap();
+                    srgb_to_linear(value.clamp(0.0, 1.0))
+                }));
+            }
+        })
+    })?;
+
+    Ok(PointCloud::from_f64_positions(positions, colors, normals))
+}
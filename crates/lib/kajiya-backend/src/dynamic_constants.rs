@@ -2,7 +2,11 @@ use crate::{bytes::as_byte_slice, vulkan};
 use ash::vk;
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
-use std::mem::{align_of, size_of};
+use std::{
+    mem::{align_of, size_of},
+    panic::Location,
+    sync::Arc,
+};
 use vulkan::buffer::Buffer;
 
 pub const DYNAMIC_CONSTANTS_SIZE_BYTES: usize = 1024 * 1024 * 16;
@@ -20,24 +24,180 @@ pub const DYNAMIC_CONSTANTS_ALIGNMENT: usize = 256;
 // For now, just a max size.
 pub const MAX_DYNAMIC_CONSTANTS_STORAGE_BUFFER_BYTES: usize = 1024 * 1024;
 
+/// Constants pushed from one source location within a scope; see `DynamicConstants::begin_scope`.
+#[derive(Clone, Copy, Debug)]
+pub struct DynamicConstantsCallSite {
+    pub location: &'static Location<'static>,
+
+    /// Including alignment padding
+    pub bytes: usize,
+
+    pub pushes: usize,
+}
+
+/// Constants pushed between `DynamicConstants::begin_scope` and `end_scope`.
+#[derive(Clone, Default, Debug)]
+pub struct DynamicConstantsScopeUsage {
+    /// Including alignment padding
+    pub bytes: usize,
+
+    /// Empty unless call site tracking is enabled. Largest first.
+    pub call_sites: Vec<DynamicConstantsCallSite>,
+}
+
+struct UsageScope {
+    start_offset_bytes: usize,
+
+    // Most bytes the scope may push, and its name to report when it doesn't fit
+    cap: Option<(usize, String)>,
+}
+
 pub struct DynamicConstants {
-    pub buffer: Buffer,
+    pub buffer: Arc<Buffer>,
     frame_offset_bytes: usize,
+    // Exclusive end of the range this instance may write to; less than the full frame for forks.
+    frame_end_bytes: usize,
     frame_parity: usize,
+
+    scope: Option<UsageScope>,
+
+    // `Some` when tracking call sites; usage within the current scope.
+    call_sites: Option<Vec<DynamicConstantsCallSite>>,
 }
 
 impl DynamicConstants {
     pub fn new(buffer: Buffer) -> Self {
         Self {
-            buffer,
+            buffer: Arc::new(buffer),
             frame_offset_bytes: 0,
+            frame_end_bytes: DYNAMIC_CONSTANTS_SIZE_BYTES,
             frame_parity: 0,
+            scope: None,
+            call_sites: None,
         }
     }
 
     pub fn advance_frame(&mut self) {
         self.frame_parity = (self.frame_parity + 1) % DYNAMIC_CONSTANTS_BUFFER_COUNT;
         self.frame_offset_bytes = 0;
+        self.frame_end_bytes = DYNAMIC_CONSTANTS_SIZE_BYTES;
+    }
+
+    /// Carve out the next `size_bytes` of this frame's constants into a separate allocator.
+    ///
+    /// Forks write to disjoint ranges of the same buffer, so they can be filled in from
+    /// different threads. They must not outlive the frame.
+    pub fn fork(&mut self, size_bytes: usize) -> Self {
+        let size_bytes = size_bytes & !(DYNAMIC_CONSTANTS_ALIGNMENT - 1);
+        assert!(self.frame_offset_bytes + size_bytes <= self.frame_end_bytes);
+
+        let fork = Self {
+            buffer: self.buffer.clone(),
+            frame_offset_bytes: self.frame_offset_bytes,
+            frame_end_bytes: self.frame_offset_bytes + size_bytes,
+            frame_parity: self.frame_parity,
+            scope: None,
+            call_sites: self.call_sites.as_ref().map(|_| Vec::new()),
+        };
+
+        self.frame_offset_bytes += size_bytes;
+        fork
+    }
+
+    /// Bytes left for this frame (or fork).
+    pub fn remaining_bytes(&self) -> usize {
+        self.frame_end_bytes - self.frame_offset_bytes
+    }
+
+    /// Bytes of this frame's half of the buffer used so far, including ranges handed out to forks.
+    pub fn used_bytes(&self) -> usize {
+        self.frame_offset_bytes
+    }
+
+    /// Which of the `DYNAMIC_CONSTANTS_BUFFER_COUNT` ranges of the buffer this frame writes to.
+    pub fn frame_parity(&self) -> usize {
+        self.frame_parity
+    }
+
+    /// Makes scopes record which source locations pushed their constants. Forks made
+    /// afterwards inherit the setting. Costs a search over the scope's call sites per push.
+    pub fn set_call_site_tracking(&mut self, enabled: bool) {
+        if enabled != self.call_sites.is_some() {
+            self.call_sites = enabled.then(Vec::new);
+        }
+    }
+
+    /// Starts attributing pushes to a new scope, such as a render graph pass, ending the previous one.
+    ///
+    /// With a `cap`, pushing more than that many bytes within the scope panics,
+    /// naming the scope and the offending call site.
+    pub fn begin_scope(&mut self, name: &str, cap: Option<usize>) {
+        self.scope = Some(UsageScope {
+            start_offset_bytes: self.frame_offset_bytes,
+            cap: cap.map(|cap| (cap, name.to_owned())),
+        });
+
+        if let Some(call_sites) = &mut self.call_sites {
+            call_sites.clear();
+        }
+    }
+
+    pub fn end_scope(&mut self) -> DynamicConstantsScopeUsage {
+        let bytes = self.scope.take().map_or(0, |scope| {
+            self.frame_offset_bytes - scope.start_offset_bytes
+        });
+
+        let call_sites = self
+            .call_sites
+            .as_mut()
+            .map_or_else(Vec::new, |call_sites| {
+                let mut call_sites = std::mem::take(call_sites);
+                call_sites.sort_by_key(|site| std::cmp::Reverse(site.bytes));
+                call_sites
+            });
+
+        DynamicConstantsScopeUsage { bytes, call_sites }
+    }
+
+    // Attributes `bytes` about to be pushed from `location` to the current scope.
+    fn account(&mut self, bytes: usize, location: &'static Location<'static>) {
+        if let Some(UsageScope {
+            start_offset_bytes,
+            cap: Some((cap, name)),
+        }) = &self.scope
+        {
+            let scope_bytes = self.frame_offset_bytes - start_offset_bytes + bytes;
+            if scope_bytes > *cap {
+                panic!(
+                    "{:?} exceeded its cap of {} bytes of dynamic constants, reaching {} bytes with a push at {}",
+                    name, cap, scope_bytes, location
+                );
+            }
+        }
+
+        if let Some(call_sites) = &mut self.call_sites {
+            if let Some(site) = call_sites.iter_mut().find(|site| site.location == location) {
+                site.bytes += bytes;
+                site.pushes += 1;
+            } else {
+                call_sites.push(DynamicConstantsCallSite {
+                    location,
+                    bytes,
+                    pushes: 1,
+                });
+            }
+        }
+    }
+
+    fn write_bytes(&self, buffer_offset: usize, bytes: &[u8]) {
+        assert!(buffer_offset + bytes.len() <= self.buffer.desc.size);
+
+        // Safety: the range is in bounds of the persistently mapped buffer, and
+        // `fork` guarantees that no other instance writes to it this frame.
+        unsafe {
+            let dst = self.buffer.allocation.mapped_ptr().unwrap().as_ptr() as *mut u8;
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), dst.add(buffer_offset), bytes.len());
+        }
     }
 
     pub fn current_offset(&self) -> u32 {
@@ -48,45 +208,48 @@ impl DynamicConstants {
         self.buffer.device_address(device) + self.current_offset() as vk::DeviceAddress
     }
 
+    #[track_caller]
     pub fn push<T: Copy>(&mut self, t: &T) -> u32 {
         let t_size = size_of::<T>();
-        assert!(self.frame_offset_bytes + t_size < DYNAMIC_CONSTANTS_SIZE_BYTES);
+        assert!(self.frame_offset_bytes + t_size <= self.frame_end_bytes);
 
         let buffer_offset = self.current_offset() as usize;
-        let dst = &mut self.buffer.allocation.mapped_slice_mut().unwrap()
-            [buffer_offset..buffer_offset + t_size];
-
-        dst.copy_from_slice(as_byte_slice(t));
+        self.write_bytes(buffer_offset, as_byte_slice(t));
 
         let t_size_aligned =
             (t_size + DYNAMIC_CONSTANTS_ALIGNMENT - 1) & !(DYNAMIC_CONSTANTS_ALIGNMENT - 1);
+        self.account(t_size_aligned, Location::caller());
         self.frame_offset_bytes += t_size_aligned;
 
         buffer_offset as _
     }
 
+    #[track_caller]
     pub fn push_from_iter<T: Copy, Iter: Iterator<Item = T>>(&mut self, iter: Iter) -> u32 {
         let t_size = size_of::<T>();
         let t_align = align_of::<T>();
 
-        assert!(self.frame_offset_bytes + t_size < DYNAMIC_CONSTANTS_SIZE_BYTES);
+        assert!(self.frame_offset_bytes + t_size <= self.frame_end_bytes);
         assert!(DYNAMIC_CONSTANTS_ALIGNMENT % t_align == 0);
 
         let buffer_offset = self.current_offset() as usize;
         assert!(buffer_offset % t_align == 0);
 
+        let buffer_end = self.frame_parity * DYNAMIC_CONSTANTS_SIZE_BYTES + self.frame_end_bytes;
+
         let mut dst_offset = buffer_offset;
         for t in iter {
-            let dst = &mut self.buffer.allocation.mapped_slice_mut().unwrap()
-                [dst_offset..dst_offset + t_size];
-            dst.copy_from_slice(as_byte_slice(&t));
+            assert!(dst_offset + t_size <= buffer_end);
+            self.write_bytes(dst_offset, as_byte_slice(&t));
             dst_offset += t_size + t_align - 1;
             dst_offset &= !(t_align - 1);
         }
 
-        self.frame_offset_bytes += dst_offset - buffer_offset;
-        self.frame_offset_bytes += DYNAMIC_CONSTANTS_ALIGNMENT - 1;
-        self.frame_offset_bytes &= !(DYNAMIC_CONSTANTS_ALIGNMENT - 1);
+        // The elements are already written, but the cap still catches the scope early.
+        let size_aligned = (dst_offset - buffer_offset + DYNAMIC_CONSTANTS_ALIGNMENT - 1)
+            & !(DYNAMIC_CONSTANTS_ALIGNMENT - 1);
+        self.account(size_aligned, Location::caller());
+        self.frame_offset_bytes += size_aligned;
 
         buffer_offset as _
     }
@@ -5,6 +5,7 @@ mod error;
 pub mod file;
 pub mod pipeline_cache;
 pub mod rust_shader_compiler;
+mod shader_cache;
 pub mod shader_compiler;
 pub mod transient_resource_cache;
 pub mod vulkan;
@@ -47,14 +47,16 @@ impl LazyWorker for CompilePipelineShaders {
                 }
                 .into_lazy()
                 .eval(&ctx),
-                ShaderSource::Hlsl { path } => CompileShader {
+                ShaderSource::Hlsl { path, defines } => CompileShader {
                     path: path.clone(),
+                    defines: defines.clone(),
                     profile: match desc.stage {
                         ShaderPipelineStage::Vertex => "vs".to_owned(),
                         ShaderPipelineStage::Pixel => "ps".to_owned(),
                         ShaderPipelineStage::RayGen
                         | ShaderPipelineStage::RayMiss
-                        | ShaderPipelineStage::RayClosestHit => "lib".to_owned(),
+                        | ShaderPipelineStage::RayClosestHit
+                        | ShaderPipelineStage::RayAnyHit => "lib".to_owned(),
                     },
                 }
                 .into_lazy()
@@ -127,8 +129,9 @@ impl PipelineCache {
                         entry: entry.clone(),
                     }
                     .into_lazy(),
-                    ShaderSource::Hlsl { path } => CompileShader {
+                    ShaderSource::Hlsl { path, defines } => CompileShader {
                         path: path.clone(),
+                        defines: defines.clone(),
                         profile: "cs".to_owned(),
                     }
                     .into_lazy(),
@@ -376,6 +379,10 @@ impl PipelineCache {
                     }
                 }
             }
+
+            // Hot-reloading and new permutations build pipelines at runtime too; a crash
+            // shouldn't lose them, so this doesn't wait for the device to be dropped.
+            device.save_pipeline_cache();
         }
 
         Ok(())
@@ -0,0 +1,280 @@
+//! On-disk cache of compiled SPIR-V and its reflected descriptor set layouts.
+//!
+//! Entries are keyed by a hash of their inputs: the fully preprocessed shader source
+//! (so any change to an included file produces a new key), the target profile,
+//! the compiler arguments, and the build of DXC doing the compiling. A cold start with
+//! a warm cache skips the compiler altogether, and a hot-reload which doesn't change
+//! the preprocessed source doesn't hit the compiler either.
+//!
+//! The driver's own `VkPipelineCache` is persisted next to it, one file per device and driver
+//! version, so that pipelines built from cached SPIR-V don't get compiled from scratch either.
+//!
+//! Cache failures are never fatal; the shader just gets compiled (or reflected) again.
+
+use crate::normalized_path_from_vfs;
+use ash::vk;
+use bytes::Bytes;
+use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Bump when the format of cached data, or the compiler's output changes.
+const SHADER_CACHE_VERSION: u32 = 1;
+
+const REFLECTION_MAGIC: u32 = 0x7366_6572; // "refs"
+
+type DescriptorSetLayout = HashMap<u32, rspirv_reflect::DescriptorInfo>;
+pub(crate) type StageDescriptorSetLayouts = HashMap<u32, DescriptorSetLayout>;
+
+lazy_static! {
+    // Reflection is requested per pipeline; shaders shared by several pipelines only parse once.
+    static ref REFLECTION_CACHE: Mutex<HashMap<u64, Arc<StageDescriptorSetLayouts>>> =
+        Default::default();
+}
+
+/// FNV-1a over the length-prefixed `parts`. Stable across runs and toolchains,
+/// unlike `std`'s default hasher.
+pub(crate) fn content_hash(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+
+    feed(&SHADER_CACHE_VERSION.to_le_bytes());
+    for part in parts {
+        feed(&(part.len() as u64).to_le_bytes());
+        feed(part);
+    }
+
+    hash
+}
+
+fn cache_file_path(key: u64, ext: &str) -> anyhow::Result<PathBuf> {
+    Ok(normalized_path_from_vfs("/cache/shaders")?.join(format!("{:016x}.{}", key, ext)))
+}
+
+fn read_cache_file(key: u64, ext: &str) -> Option<Vec<u8>> {
+    std::fs::read(cache_file_path(key, ext).ok()?).ok()
+}
+
+fn write_cache_file(key: u64, ext: &str, data: &[u8]) {
+    let result = cache_file_path(key, ext).and_then(|path| write_file_atomic(&path, data));
+
+    if let Err(err) = result {
+        log::warn!("Failed to write the shader cache: {:#}", err);
+    }
+}
+
+fn write_file_atomic(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    std::fs::create_dir_all(path.parent().unwrap())?;
+
+    // Shaders are compiled on multiple threads, and several may write the same entry.
+    static TMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    // Write to a temporary file first, so that concurrent readers never see partial data.
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&tmp_path, data)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+// Driver updates change `pipeline_cache_uuid`, so stale files are simply never read again.
+fn pipeline_cache_file_path(properties: &vk::PhysicalDeviceProperties) -> anyhow::Result<PathBuf> {
+    let uuid: String = properties
+        .pipeline_cache_uuid
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    Ok(normalized_path_from_vfs("/cache/pipelines")?.join(format!(
+        "{:04x}_{:04x}_{}.bin",
+        properties.vendor_id, properties.device_id, uuid
+    )))
+}
+
+/// Contents for `VkPipelineCacheCreateInfo::pInitialData`, saved by `store_pipeline_cache_data`
+/// for the same device and driver. Empty if there's none, or if its header doesn't match.
+pub(crate) fn load_pipeline_cache_data(properties: &vk::PhysicalDeviceProperties) -> Vec<u8> {
+    let data = pipeline_cache_file_path(properties)
+        .ok()
+        .and_then(|path| std::fs::read(path).ok())
+        .unwrap_or_default();
+
+    // Drivers are supposed to validate the header themselves, but not all of them do.
+    let header_matches = {
+        let mut reader = Reader(&data);
+        let header_length = reader.u32();
+        let header_version = reader.u32();
+        let vendor_id = reader.u32();
+        let device_id = reader.u32();
+        let uuid = reader.bytes(vk::UUID_SIZE);
+
+        header_length.map_or(false, |len| len as usize >= 16 + vk::UUID_SIZE)
+            && header_version == Some(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32)
+            && vendor_id == Some(properties.vendor_id)
+            && device_id == Some(properties.device_id)
+            && uuid == Some(&properties.pipeline_cache_uuid[..])
+    };
+
+    if header_matches {
+        log::info!("Loaded {} KiB of pipeline cache", data.len() / 1024);
+        data
+    } else {
+        Vec::new()
+    }
+}
+
+pub(crate) fn store_pipeline_cache_data(properties: &vk::PhysicalDeviceProperties, data: &[u8]) {
+    let result =
+        pipeline_cache_file_path(properties).and_then(|path| write_file_atomic(&path, data));
+
+    if let Err(err) = result {
+        log::warn!("Failed to write the pipeline cache: {:#}", err);
+    }
+}
+
+pub(crate) fn load_spirv(key: u64) -> Option<Bytes> {
+    read_cache_file(key, "spv")
+        .filter(|spirv| !spirv.is_empty() && spirv.len() % 4 == 0)
+        .map(Bytes::from)
+}
+
+pub(crate) fn store_spirv(key: u64, spirv: &[u8]) {
+    write_cache_file(key, "spv", spirv);
+}
+
+/// Reflects the descriptor sets used by `spirv`, going through the in-memory and on-disk caches.
+pub(crate) fn reflect_descriptor_sets(
+    spirv: &[u8],
+) -> anyhow::Result<Arc<StageDescriptorSetLayouts>> {
+    let key = content_hash(&[spirv]);
+
+    if let Some(layouts) = REFLECTION_CACHE.lock().get(&key) {
+        return Ok(layouts.clone());
+    }
+
+    let layouts = match read_cache_file(key, "refl").and_then(|data| deserialize_layouts(&data)) {
+        Some(layouts) => layouts,
+        None => {
+            let layouts = rspirv_reflect::Reflection::new_from_spirv(spirv)
+                .and_then(|refl| refl.get_descriptor_sets())
+                .map_err(|err| anyhow::anyhow!("{:?}", err))?;
+            write_cache_file(key, "refl", &serialize_layouts(&layouts));
+            layouts
+        }
+    };
+
+    let layouts = Arc::new(layouts);
+    REFLECTION_CACHE.lock().insert(key, layouts.clone());
+    Ok(layouts)
+}
+
+fn put_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn serialize_layouts(layouts: &StageDescriptorSetLayouts) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    put_u32(&mut out, REFLECTION_MAGIC);
+    put_u32(&mut out, layouts.len() as u32);
+
+    for (set_idx, set) in layouts {
+        put_u32(&mut out, *set_idx);
+        put_u32(&mut out, set.len() as u32);
+
+        for (binding_idx, binding) in set {
+            let (dim_kind, dim_size) = match binding.dimensionality {
+                rspirv_reflect::DescriptorDimensionality::Single => (0, 0),
+                rspirv_reflect::DescriptorDimensionality::Array(size) => (1, size),
+                rspirv_reflect::DescriptorDimensionality::RuntimeArray => (2, 0),
+            };
+
+            put_u32(&mut out, *binding_idx);
+            put_u32(&mut out, binding.ty.0);
+            put_u32(&mut out, dim_kind);
+            put_u32(&mut out, dim_size);
+            put_u32(&mut out, binding.name.len() as u32);
+            out.extend_from_slice(binding.name.as_bytes());
+        }
+    }
+
+    out
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+}
+
+fn deserialize_layouts(data: &[u8]) -> Option<StageDescriptorSetLayouts> {
+    let mut reader = Reader(data);
+
+    if reader.u32()? != REFLECTION_MAGIC {
+        return None;
+    }
+
+    let mut layouts = StageDescriptorSetLayouts::new();
+
+    let set_count = reader.u32()?;
+    for _ in 0..set_count {
+        let set_idx = reader.u32()?;
+        let binding_count = reader.u32()?;
+        let set = layouts.entry(set_idx).or_default();
+
+        for _ in 0..binding_count {
+            let binding_idx = reader.u32()?;
+            let ty = rspirv_reflect::DescriptorType(reader.u32()?);
+            let dimensionality = match (reader.u32()?, reader.u32()?) {
+                (0, _) => rspirv_reflect::DescriptorDimensionality::Single,
+                (1, size) => rspirv_reflect::DescriptorDimensionality::Array(size),
+                (2, _) => rspirv_reflect::DescriptorDimensionality::RuntimeArray,
+                _ => return None,
+            };
+            let name_len = reader.u32()? as usize;
+            let name = std::str::from_utf8(reader.bytes(name_len)?)
+                .ok()?
+                .to_owned();
+
+            set.insert(
+                binding_idx,
+                rspirv_reflect::DescriptorInfo {
+                    ty,
+                    dimensionality,
+                    name,
+                },
+            );
+        }
+    }
+
+    // Trailing data means the file isn't what we think it is.
+    reader.0.is_empty().then(|| layouts)
+}
@@ -1,8 +1,9 @@
 use crate::file::LoadFile;
 use anyhow::{anyhow, bail, Context, Result};
 use bytes::Bytes;
+use lazy_static::lazy_static;
 use relative_path::RelativePathBuf;
-use std::{path::PathBuf, sync::Arc};
+use std::{ffi::CStr, os::raw::c_char, path::PathBuf, sync::Arc};
 use turbosloth::*;
 
 pub struct CompiledShader {
@@ -14,6 +15,7 @@ pub struct CompiledShader {
 pub struct CompileShader {
     pub path: PathBuf,
     pub profile: String,
+    pub defines: Vec<(String, String)>,
 }
 
 #[async_trait]
@@ -49,8 +51,19 @@ impl LazyWorker for CompileShader {
                 let source = source
                     .map_err(|err| anyhow!("{}", err))
                     .with_context(|| format!("shader path: {:?}", self.path))?;
-                let target_profile = format!("{}_6_4", self.profile);
-                let spirv = compile_generic_shader_hlsl_impl(&name, &source, &target_profile)?;
+                // Inline ray tracing (`RayQuery`) needs 6.5; see `SimpleRenderPass::new_rt`.
+                let uses_ray_query = self
+                    .defines
+                    .iter()
+                    .any(|(name, value)| name == "RT_USE_RAY_QUERY" && value != "0");
+                let shader_model = if uses_ray_query { "6_5" } else { "6_4" };
+                let target_profile = format!("{}_{}", self.profile, shader_model);
+                let spirv = compile_generic_shader_hlsl_impl(
+                    &name,
+                    &source,
+                    &target_profile,
+                    &self.defines,
+                )?;
 
                 Ok(CompiledShader { name, spirv })
             }
@@ -97,8 +110,8 @@ impl LazyWorker for CompileRayTracingShader {
         match ext.as_str() {
             "glsl" => unimplemented!(),
             "hlsl" => {
-                let target_profile = "lib_6_4";
-                let spirv = compile_generic_shader_hlsl_impl(&name, &source, target_profile)?;
+                let target_profile = "lib_6_5";
+                let spirv = compile_generic_shader_hlsl_impl(&name, &source, target_profile, &[])?;
 
                 Ok(RayTracingShader { name, spirv })
             }
@@ -163,35 +176,170 @@ pub fn get_cs_local_size_from_spirv(spirv: &[u32]) -> Result<[u32; 3]> {
     Err(anyhow!("Could not find a ExecutionMode SPIR-V op"))
 }
 
+const DXC_ARGS: &[&str] = &[
+    "-spirv",
+    //"-enable-16bit-types",
+    "-fspv-target-env=vulkan1.2",
+    "-WX",      // warnings as errors
+    "-Ges",     // strict mode
+    "-HV 2021", // HLSL version 2021
+];
+
+// Where `hassle_rs` loads DXC from
+#[cfg(target_os = "windows")]
+const DXC_LIBRARY_PATH: &str = "dxcompiler.dll";
+#[cfg(target_os = "macos")]
+const DXC_LIBRARY_PATH: &str = "./libdxcompiler.dylib";
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const DXC_LIBRARY_PATH: &str = "./libdxcompiler.so";
+
+lazy_static! {
+    // Identifies the compiler version for the shader cache, so that updating DXC
+    // doesn't keep serving SPIR-V from the old one.
+    static ref DXC_VERSION_KEY: String = dxc_version_key().unwrap_or_else(|err| {
+        log::warn!("Could not query the DXC version: {:#}", err);
+        String::new()
+    });
+}
+
+// Version, and where available the commit, reported by `IDxcVersionInfo` of the DXC library
+// `hassle_rs` loads. `hassle_rs` doesn't expose the interface, so it's queried directly.
+fn dxc_version_key() -> Result<String> {
+    #[repr(C)]
+    struct Guid(u32, u16, u16, [u8; 8]);
+
+    const CLSID_DXC_COMPILER: Guid = Guid(
+        0x73e22d93,
+        0xe6ce,
+        0x47f3,
+        [0xb5, 0xbf, 0xf0, 0x66, 0x4f, 0x39, 0xc1, 0xb0],
+    );
+    const IID_IDXC_VERSION_INFO: Guid = Guid(
+        0xb04f5b50,
+        0x2059,
+        0x4f12,
+        [0xa8, 0xff, 0xa1, 0xe0, 0xcd, 0xe1, 0xcc, 0x7e],
+    );
+    const IID_IDXC_VERSION_INFO2: Guid = Guid(
+        0xfb6904c4,
+        0x42f0,
+        0x4b62,
+        [0x9c, 0x46, 0x98, 0x3a, 0xf7, 0xda, 0x7c, 0x83],
+    );
+
+    // `IDxcVersionInfo2`, which extends `IDxcVersionInfo` with `get_commit_info`
+    #[repr(C)]
+    struct VersionInfoVtbl {
+        query_interface: usize,
+        add_ref: usize,
+        release: unsafe extern "system" fn(*mut VersionInfo) -> u32,
+        // Outside of Windows, DXC's `IUnknown` has a virtual destructor
+        #[cfg(not(windows))]
+        destructors: [usize; 2],
+        get_version: unsafe extern "system" fn(*mut VersionInfo, *mut u32, *mut u32) -> i32,
+        get_flags: usize,
+        get_commit_info:
+            unsafe extern "system" fn(*mut VersionInfo, *mut u32, *mut *mut c_char) -> i32,
+    }
+
+    #[repr(C)]
+    struct VersionInfo {
+        vtbl: *const VersionInfoVtbl,
+    }
+
+    type DxcCreateInstance =
+        unsafe extern "system" fn(*const Guid, *const Guid, *mut *mut VersionInfo) -> i32;
+
+    unsafe {
+        let library = libloading::Library::new(DXC_LIBRARY_PATH)?;
+        let create_instance = library.get::<DxcCreateInstance>(b"DxcCreateInstance\0")?;
+
+        let mut info = std::ptr::null_mut();
+        let has_commit_info =
+            create_instance(&CLSID_DXC_COMPILER, &IID_IDXC_VERSION_INFO2, &mut info) >= 0;
+        if !has_commit_info
+            && create_instance(&CLSID_DXC_COMPILER, &IID_IDXC_VERSION_INFO, &mut info) < 0
+        {
+            bail!("DXC doesn't implement IDxcVersionInfo");
+        }
+
+        let vtbl = &*(*info).vtbl;
+
+        let (mut major, mut minor) = (0, 0);
+        (vtbl.get_version)(info, &mut major, &mut minor);
+        let mut key = format!("{}.{}", major, minor);
+
+        if has_commit_info {
+            let mut commit_count = 0;
+            let mut commit_hash = std::ptr::null_mut();
+            if (vtbl.get_commit_info)(info, &mut commit_count, &mut commit_hash) >= 0
+                && !commit_hash.is_null()
+            {
+                // Allocated with `CoTaskMemAlloc`; leaked, as this only runs once.
+                key += &format!(
+                    " {} {}",
+                    commit_count,
+                    CStr::from_ptr(commit_hash).to_string_lossy()
+                );
+            }
+        }
+
+        (vtbl.release)(info);
+
+        Ok(key)
+    }
+}
+
 fn compile_generic_shader_hlsl_impl(
     name: &str,
     source: &[shader_prepper::SourceChunk],
     target_profile: &str,
+    defines: &[(String, String)],
 ) -> Result<Bytes> {
     let mut source_text = String::new();
     for s in source {
         source_text += &s.source;
     }
 
+    let defines_text = defines
+        .iter()
+        .map(|(name, value)| format!("-D{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let cache_key = crate::shader_cache::content_hash(&[
+        DXC_VERSION_KEY.as_bytes(),
+        name.as_bytes(),
+        source_text.as_bytes(),
+        target_profile.as_bytes(),
+        DXC_ARGS.join(" ").as_bytes(),
+        defines_text.as_bytes(),
+    ]);
+
+    if let Some(spirv) = crate::shader_cache::load_spirv(cache_key) {
+        log::trace!("Using cached SPIR-V for {}", name);
+        return Ok(spirv);
+    }
+
     let t0 = std::time::Instant::now();
+    let defines: Vec<(&str, Option<&str>)> = defines
+        .iter()
+        .map(|(name, value)| (name.as_str(), Some(value.as_str())))
+        .collect();
+
     let spirv = hassle_rs::compile_hlsl(
         name,
         &source_text,
         "main",
         target_profile,
-        &[
-            "-spirv",
-            //"-enable-16bit-types",
-            "-fspv-target-env=vulkan1.2",
-            "-WX",      // warnings as errors
-            "-Ges",     // strict mode
-            "-HV 2021", // HLSL version 2021
-        ],
-        &[],
+        DXC_ARGS,
+        &defines,
     )
     .map_err(|err| anyhow!("{}", err))?;
 
     log::trace!("dxc took {:?} for {}", t0.elapsed(), name,);
 
+    crate::shader_cache::store_spirv(cache_key, &spirv);
+
     Ok(spirv.into())
 }
@@ -3,11 +3,45 @@ use crate::BackendError;
 use super::device::Device;
 use ash::vk;
 use gpu_allocator::{AllocationCreateDesc, MemoryLocation};
+use std::ops::{Deref, DerefMut};
 
 pub struct Buffer {
     pub raw: vk::Buffer,
     pub desc: BufferDesc,
-    pub allocation: gpu_allocator::SubAllocation,
+    pub allocation: BufferAllocation,
+}
+
+/// The memory of a `Buffer`. Dereferences to the underlying `SubAllocation`.
+///
+/// `SubAllocation` is only kept from being `Send` and `Sync` by the raw pointer to its
+/// persistently mapped memory. The pointer itself never changes, and the allocation
+/// doesn't access the memory behind it. Safe code can only write to it through `&mut`
+/// (`mapped_slice_mut`); code writing through `mapped_ptr` from a shared reference must
+/// ensure that no other thread accesses the same range at the same time. See
+/// `DynamicConstants::fork` for an example.
+pub struct BufferAllocation(gpu_allocator::SubAllocation);
+
+unsafe impl Send for BufferAllocation {}
+unsafe impl Sync for BufferAllocation {}
+
+impl BufferAllocation {
+    pub fn into_inner(self) -> gpu_allocator::SubAllocation {
+        self.0
+    }
+}
+
+impl Deref for BufferAllocation {
+    type Target = gpu_allocator::SubAllocation;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for BufferAllocation {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
 }
 
 impl Buffer {
@@ -116,7 +150,7 @@ impl Device {
         Ok(Buffer {
             raw: buffer,
             desc,
-            allocation,
+            allocation: BufferAllocation(allocation),
         })
     }
 
@@ -133,6 +167,7 @@ impl Device {
         }
         let buffer =
             Self::create_buffer_impl(&self.raw, &mut self.global_allocator.lock(), desc, &name)?;
+        self.set_object_name(buffer.raw, &name);
 
         if let Some(initial_data) = initial_data {
             let scratch_desc =
@@ -171,7 +206,7 @@ impl Device {
         }
         self.global_allocator
             .lock()
-            .free(buffer.allocation)
+            .free(buffer.allocation.into_inner())
             .expect("buffer memory deallocated");
     }
 }
@@ -19,6 +19,7 @@ use log::{debug, error, info, trace, warn};
 use parking_lot::Mutex;
 use std::{
     collections::{HashMap, HashSet},
+    ffi::CString,
     os::raw::c_char,
     sync::Arc,
 };
@@ -28,9 +29,135 @@ use std::{
 /// in the same shader stage.
 pub const RESERVED_DESCRIPTOR_COUNT: u32 = 32;
 
+/// Upper bound on the number of threads recording command buffers for a single frame.
+pub const MAX_COMMAND_RECORDING_THREADS: usize = 8;
+
 pub struct Queue {
     pub raw: vk::Queue,
     pub family: QueueFamily,
+    pub timeline: QueueTimeline,
+}
+
+/// Timeline semaphore signaled with a new value by every submission to a queue.
+///
+/// Other queues can wait on specific values via `Queue::timeline_wait`,
+/// and the CPU via `Device::wait_for_queue`.
+pub struct QueueTimeline {
+    pub semaphore: vk::Semaphore,
+
+    // Also serializes submissions, so that signaled values increase monotonically.
+    last_submitted_value: Mutex<u64>,
+}
+
+/// A semaphore to wait on before the command buffers of a submission execute.
+#[derive(Clone, Copy)]
+pub struct SemaphoreWait {
+    pub semaphore: vk::Semaphore,
+
+    /// Ignored for binary semaphores
+    pub value: u64,
+    pub dst_stage_mask: vk::PipelineStageFlags,
+}
+
+impl SemaphoreWait {
+    pub fn binary(semaphore: vk::Semaphore, dst_stage_mask: vk::PipelineStageFlags) -> Self {
+        Self {
+            semaphore,
+            value: 0,
+            dst_stage_mask,
+        }
+    }
+}
+
+impl Queue {
+    fn new(device: &ash::Device, family: QueueFamily, queue_index: u32) -> Result<Self> {
+        let mut type_info = vk::SemaphoreTypeCreateInfo::builder()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(0);
+
+        let semaphore = unsafe {
+            device.create_semaphore(
+                &vk::SemaphoreCreateInfo::builder().push_next(&mut type_info),
+                None,
+            )
+        }?;
+
+        Ok(Self {
+            raw: unsafe { device.get_device_queue(family.index, queue_index) },
+            family,
+            timeline: QueueTimeline {
+                semaphore,
+                last_submitted_value: Mutex::new(0),
+            },
+        })
+    }
+
+    /// Timeline value signaled by the most recent submission to this queue.
+    pub fn last_submitted_value(&self) -> u64 {
+        *self.timeline.last_submitted_value.lock()
+    }
+
+    /// Makes a submission on another queue wait until this queue's timeline reaches `value`.
+    pub fn timeline_wait(
+        &self,
+        value: u64,
+        dst_stage_mask: vk::PipelineStageFlags,
+    ) -> SemaphoreWait {
+        SemaphoreWait {
+            semaphore: self.timeline.semaphore,
+            value,
+            dst_stage_mask,
+        }
+    }
+
+    /// Submits `command_buffers`, signaling the next value of the queue's timeline,
+    /// as well as any extra binary `signal_semaphores` (e.g. for presentation).
+    ///
+    /// Returns the timeline value which will be reached once the submission completes.
+    pub fn submit(
+        &self,
+        device: &ash::Device,
+        command_buffers: &[vk::CommandBuffer],
+        waits: &[SemaphoreWait],
+        signal_semaphores: &[vk::Semaphore],
+    ) -> Result<u64, BackendError> {
+        let mut last_submitted_value = self.timeline.last_submitted_value.lock();
+        let value = *last_submitted_value + 1;
+
+        let wait_semaphores: Vec<vk::Semaphore> = waits.iter().map(|w| w.semaphore).collect();
+        let wait_values: Vec<u64> = waits.iter().map(|w| w.value).collect();
+        let wait_stages: Vec<vk::PipelineStageFlags> =
+            waits.iter().map(|w| w.dst_stage_mask).collect();
+
+        let signal_values: Vec<u64> = signal_semaphores
+            .iter()
+            .map(|_| 0)
+            .chain(std::iter::once(value))
+            .collect();
+        let signal_semaphores: Vec<vk::Semaphore> = signal_semaphores
+            .iter()
+            .copied()
+            .chain(std::iter::once(self.timeline.semaphore))
+            .collect();
+
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::builder()
+            .wait_semaphore_values(&wait_values)
+            .signal_semaphore_values(&signal_values);
+
+        let submit_info = vk::SubmitInfo::builder()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(command_buffers)
+            .signal_semaphores(&signal_semaphores)
+            .push_next(&mut timeline_info);
+
+        unsafe {
+            device.queue_submit(self.raw, &[submit_info.build()], vk::Fence::null())?;
+        }
+
+        *last_submitted_value = value;
+        Ok(value)
+    }
 }
 
 pub trait DeferredRelease: Copy {
@@ -64,13 +191,28 @@ pub struct DeviceFrame {
     pub rendering_complete_semaphore: Option<vk::Semaphore>,
     pub main_command_buffer: CommandBuffer,
     pub presentation_command_buffer: CommandBuffer,
+
+    /// Recorded for `Device::async_compute_queue`, if there is one.
+    pub async_compute_command_buffer: Option<CommandBuffer>,
+
+    /// Universal queue work overlapping the async compute command buffer, submitted
+    /// between the main and presentation command buffers.
+    pub async_overlap_command_buffer: CommandBuffer,
+
+    /// Universal queue work waiting for the async compute command buffer,
+    /// submitted right after `async_overlap_command_buffer`.
+    pub async_join_command_buffer: CommandBuffer,
+    /// One per recording thread; see `MAX_COMMAND_RECORDING_THREADS`.
+    pub secondary_command_pools: Vec<Mutex<SecondaryCommandPool>>,
     pub pending_resource_releases: Mutex<PendingResourceReleases>,
     pub profiler_data: VkProfilerData,
+
+    /// Universal queue timeline value reached once all of the frame's submissions complete
+    submitted_timeline_value: u64,
 }
 
 pub struct CommandBuffer {
     pub raw: vk::CommandBuffer,
-    pub submit_done_fence: vk::Fence,
     //pool: vk::CommandPool,
 }
 
@@ -93,29 +235,75 @@ impl CommandBuffer {
                 .unwrap()
         }[0];
 
-        let submit_done_fence = unsafe {
-            device.create_fence(
-                &vk::FenceCreateInfo::builder()
-                    .flags(vk::FenceCreateFlags::SIGNALED)
-                    .build(),
-                None,
-            )
-        }?;
-
         Ok(CommandBuffer {
             raw: cb,
             //pool,
-            submit_done_fence,
         })
     }
 }
 
+/// Secondary command buffers for a single recording thread, recycled every frame.
+pub struct SecondaryCommandPool {
+    pool: vk::CommandPool,
+    command_buffers: Vec<vk::CommandBuffer>,
+    used_count: usize,
+}
+
+impl SecondaryCommandPool {
+    fn new(device: &ash::Device, queue_family: &QueueFamily) -> Self {
+        let pool_create_info = vk::CommandPoolCreateInfo::builder()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(queue_family.index);
+
+        let pool = unsafe { device.create_command_pool(&pool_create_info, None).unwrap() };
+
+        Self {
+            pool,
+            command_buffers: Vec::new(),
+            used_count: 0,
+        }
+    }
+
+    /// Must only be called once the GPU is done with the frame.
+    fn reset(&mut self, device: &ash::Device) {
+        unsafe {
+            device
+                .reset_command_pool(self.pool, vk::CommandPoolResetFlags::empty())
+                .unwrap();
+        }
+
+        self.used_count = 0;
+    }
+
+    /// Returns a secondary command buffer in the initial state, allocating one if needed.
+    pub fn allocate(&mut self, device: &ash::Device) -> vk::CommandBuffer {
+        if self.used_count == self.command_buffers.len() {
+            let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
+                .command_buffer_count(1)
+                .command_pool(self.pool)
+                .level(vk::CommandBufferLevel::SECONDARY);
+
+            self.command_buffers.push(
+                unsafe {
+                    device
+                        .allocate_command_buffers(&command_buffer_allocate_info)
+                        .unwrap()
+                }[0],
+            );
+        }
+
+        self.used_count += 1;
+        self.command_buffers[self.used_count - 1]
+    }
+}
+
 impl DeviceFrame {
     pub fn new(
         pdevice: &PhysicalDevice,
         device: &ash::Device,
         global_allocator: &mut VulkanAllocator,
         queue_family: &QueueFamily,
+        async_compute_queue_family: Option<&QueueFamily>,
     ) -> Self {
         Self {
             /*linear_allocator_pool: global_allocator
@@ -129,6 +317,13 @@ impl DeviceFrame {
             rendering_complete_semaphore: None,
             main_command_buffer: CommandBuffer::new(device, queue_family).unwrap(),
             presentation_command_buffer: CommandBuffer::new(device, queue_family).unwrap(),
+            async_compute_command_buffer: async_compute_queue_family
+                .map(|family| CommandBuffer::new(device, family).unwrap()),
+            async_overlap_command_buffer: CommandBuffer::new(device, queue_family).unwrap(),
+            async_join_command_buffer: CommandBuffer::new(device, queue_family).unwrap(),
+            secondary_command_pools: (0..command_recording_thread_count())
+                .map(|_| Mutex::new(SecondaryCommandPool::new(device, queue_family)))
+                .collect(),
             pending_resource_releases: Default::default(),
             profiler_data: VulkanProfilerFrame::new(
                 device,
@@ -138,17 +333,51 @@ impl DeviceFrame {
                     pdevice.properties.limits.timestamp_period,
                 ),
             ),
+            submitted_timeline_value: 0,
         }
     }
 }
 
+/// Number of threads which may record command buffers for a frame in parallel.
+pub fn command_recording_thread_count() -> usize {
+    std::thread::available_parallelism()
+        .map_or(1, |count| count.get())
+        .min(MAX_COMMAND_RECORDING_THREADS)
+}
+
+/// How passes which support both trace their rays; see `RenderBackendConfig::ray_tracing_mode`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RayTracingMode {
+    /// Ray tracing pipelines, with shader binding tables and hit shaders
+    Pipeline,
+
+    /// Inline ray queries (`VK_KHR_ray_query`) from compute shaders, for drivers which
+    /// misbehave with ray tracing pipelines. Only the passes built with
+    /// `SimpleRenderPass::new_rt_with_ray_query_fallback` in `kajiya-rg` switch;
+    /// the rest keep using pipelines.
+    RayQuery,
+}
+
+impl Default for RayTracingMode {
+    fn default() -> Self {
+        Self::Pipeline
+    }
+}
+
 pub struct Device {
     pub raw: ash::Device,
     pub(crate) pdevice: Arc<PhysicalDevice>,
     pub(crate) instance: Arc<super::instance::Instance>,
     pub universal_queue: Queue,
+
+    /// A second queue for compute work overlapping the universal queue; see
+    /// `PassBuilder::async_compute` in `kajiya-rg`. Only present if the universal queue's family
+    /// has more than one queue: the graph shares resources between the queues without queue
+    /// family ownership transfers, which would be needed with a dedicated compute family.
+    pub async_compute_queue: Option<Queue>,
     pub(crate) global_allocator: Arc<Mutex<VulkanAllocator>>,
     pub(crate) immutable_samplers: HashMap<SamplerDesc, vk::Sampler>,
+    pub(crate) material_samplers: Vec<vk::Sampler>,
     pub(crate) setup_cb: Mutex<CommandBuffer>,
 
     pub(crate) crash_tracking_buffer: Buffer,
@@ -159,9 +388,13 @@ pub struct Device {
     // pub ray_query_ext: khr::RayQuery,
     pub ray_tracing_pipeline_properties: vk::PhysicalDeviceRayTracingPipelinePropertiesKHR,
 
+    /// Used for all pipeline creation; persisted by `save_pipeline_cache`.
+    pub(crate) pipeline_cache: vk::PipelineCache,
+
     frames: [Mutex<Arc<DeviceFrame>>; 2],
 
     ray_tracing_enabled: bool,
+    ray_tracing_mode: RayTracingMode,
 }
 
 // Allowing `Send` on `frames` is technically unsound. There are some checks
@@ -173,7 +406,10 @@ unsafe impl Send for Device {}
 unsafe impl Sync for Device {}
 
 impl Device {
-    pub fn create(pdevice: &Arc<PhysicalDevice>) -> Result<Arc<Self>> {
+    pub fn create(
+        pdevice: &Arc<PhysicalDevice>,
+        ray_tracing_mode: RayTracingMode,
+    ) -> Result<Arc<Self>> {
         let supported_extensions: HashSet<String> = unsafe {
             let extension_properties = pdevice
                 .instance
@@ -247,6 +483,22 @@ impl Device {
             device_extension_names.extend(ray_tracing_extensions.iter());
         }
 
+        let ray_query_enabled =
+            ray_tracing_enabled && ray_tracing_mode == RayTracingMode::RayQuery && {
+                let ext = vk::KhrRayQueryFn::name().to_string_lossy();
+                let supported = supported_extensions.contains(ext.as_ref());
+
+                if !supported {
+                    log::warn!("{} not supported; using ray tracing pipelines", ext);
+                }
+
+                supported
+            };
+
+        if ray_query_enabled {
+            device_extension_names.push(vk::KhrRayQueryFn::name().as_ptr());
+        }
+
         if pdevice.presentation_requested {
             device_extension_names.push(khr::Swapchain::name().as_ptr());
         }
@@ -260,7 +512,7 @@ impl Device {
             }
         }
 
-        let priorities = [1.0];
+        let priorities = [1.0, 1.0];
 
         let universal_queue = pdevice
             .queue_families
@@ -275,9 +527,18 @@ impl Device {
             anyhow::bail!("No suitable render queue found");
         };
 
-        let universal_queue_info = [vk::DeviceQueueCreateInfo::builder()
+        // Another queue of the universal family, if it has one. Resources of the graph, and
+        // bindless ones, are used on both queues without ownership transfers, so a dedicated
+        // compute family, whose resources would need to be exclusive to it, isn't an option.
+        let has_async_compute_queue = universal_queue.properties.queue_count > 1;
+
+        let queue_infos = [vk::DeviceQueueCreateInfo::builder()
             .queue_family_index(universal_queue.index)
-            .queue_priorities(&priorities)
+            .queue_priorities(if has_async_compute_queue {
+                &priorities[..2]
+            } else {
+                &priorities[..1]
+            })
             .build()];
 
         let mut scalar_block = vk::PhysicalDeviceScalarBlockLayoutFeaturesEXT::default();
@@ -286,6 +547,7 @@ impl Device {
             vk::PhysicalDeviceImagelessFramebufferFeaturesKHR::default();
         let mut shader_float16_int8 = vk::PhysicalDeviceShaderFloat16Int8Features::default();
         let mut vulkan_memory_model = vk::PhysicalDeviceVulkanMemoryModelFeaturesKHR::default();
+        let mut timeline_semaphore = vk::PhysicalDeviceTimelineSemaphoreFeatures::default();
         let mut get_buffer_device_address_features =
             ash::vk::PhysicalDeviceBufferDeviceAddressFeatures::default();
 
@@ -295,6 +557,8 @@ impl Device {
         let mut ray_tracing_pipeline_features =
             ash::vk::PhysicalDeviceRayTracingPipelineFeaturesKHR::default();
 
+        let mut ray_query_features = ash::vk::PhysicalDeviceRayQueryFeaturesKHR::default();
+
         unsafe {
             let instance = &pdevice.instance.raw;
 
@@ -304,6 +568,7 @@ impl Device {
                 .push_next(&mut imageless_framebuffer)
                 .push_next(&mut shader_float16_int8)
                 .push_next(&mut vulkan_memory_model)
+                .push_next(&mut timeline_semaphore)
                 .push_next(&mut get_buffer_device_address_features);
 
             if ray_tracing_enabled {
@@ -312,6 +577,10 @@ impl Device {
                     .push_next(&mut ray_tracing_pipeline_features);
             }
 
+            if ray_query_enabled {
+                features2 = features2.push_next(&mut ray_query_features);
+            }
+
             let mut features2 = features2.build();
 
             instance
@@ -323,6 +592,7 @@ impl Device {
             debug!("{:#?}", &imageless_framebuffer);
             debug!("{:#?}", &shader_float16_int8);
             debug!("{:#?}", &vulkan_memory_model);
+            debug!("{:#?}", &timeline_semaphore);
             debug!("{:#?}", &get_buffer_device_address_features);
 
             // The suggested `#[rustfmt::skip]` is not stable
@@ -347,6 +617,8 @@ impl Device {
 
                 assert!(shader_float16_int8.shader_int8 != 0);
 
+                assert!(timeline_semaphore.timeline_semaphore != 0);
+
                 if ray_tracing_enabled {
                     assert!(descriptor_indexing.shader_uniform_buffer_array_non_uniform_indexing != 0);
                     assert!(descriptor_indexing.shader_storage_buffer_array_non_uniform_indexing != 0);
@@ -361,10 +633,14 @@ impl Device {
 
                     assert!(get_buffer_device_address_features.buffer_device_address != 0);
                 }
+
+                if ray_query_enabled {
+                    assert!(ray_query_features.ray_query != 0);
+                }
             }
 
             let device_create_info = vk::DeviceCreateInfo::builder()
-                .queue_create_infos(&universal_queue_info)
+                .queue_create_infos(&queue_infos)
                 .enabled_extension_names(&device_extension_names)
                 .push_next(&mut features2)
                 .build();
@@ -388,26 +664,43 @@ impl Device {
                 buffer_device_address: true,
             });
 
-            let universal_queue = Queue {
-                raw: device.get_device_queue(universal_queue.index, 0),
-                family: universal_queue,
+            let universal_queue = Queue::new(&device, universal_queue, 0)?;
+            let async_compute_queue = if has_async_compute_queue {
+                Some(Queue::new(&device, universal_queue.family, 1)?)
+            } else {
+                None
             };
 
+            info!(
+                "Async compute queue: {}",
+                if has_async_compute_queue {
+                    "second queue of the universal family"
+                } else {
+                    "none"
+                }
+            );
+
             let frame0 = DeviceFrame::new(
                 pdevice,
                 &device,
                 &mut global_allocator,
                 &universal_queue.family,
+                async_compute_queue.as_ref().map(|queue| &queue.family),
             );
             let frame1 = DeviceFrame::new(
                 pdevice,
                 &device,
                 &mut global_allocator,
                 &universal_queue.family,
+                async_compute_queue.as_ref().map(|queue| &queue.family),
             );
             //let frame2 = DeviceFrame::new(&device, &mut global_allocator, &universal_queue.family);
 
             let immutable_samplers = Self::create_samplers(&device);
+            let material_samplers = MATERIAL_SAMPLER_DESCS
+                .iter()
+                .map(|desc| immutable_samplers[desc])
+                .collect();
             let setup_cb = CommandBuffer::new(&device, &universal_queue.family).unwrap();
 
             let acceleration_structure_ext =
@@ -418,6 +711,13 @@ impl Device {
             let ray_tracing_pipeline_properties =
                 khr::RayTracingPipeline::get_properties(&pdevice.instance.raw, pdevice.raw);
 
+            let pipeline_cache_data =
+                crate::shader_cache::load_pipeline_cache_data(&pdevice.properties);
+            let pipeline_cache = device.create_pipeline_cache(
+                &vk::PipelineCacheCreateInfo::builder().initial_data(&pipeline_cache_data),
+                None,
+            )?;
+
             let crash_tracking_buffer = Self::create_buffer_impl(
                 &device,
                 &mut global_allocator,
@@ -430,8 +730,10 @@ impl Device {
                 instance: pdevice.instance.clone(),
                 raw: device,
                 universal_queue,
+                async_compute_queue,
                 global_allocator: Arc::new(Mutex::new(global_allocator)),
                 immutable_samplers,
+                material_samplers,
                 setup_cb: Mutex::new(setup_cb),
                 crash_tracking_buffer,
                 crash_marker_names: Default::default(),
@@ -439,12 +741,18 @@ impl Device {
                 ray_tracing_pipeline_ext,
                 // ray_query_ext,
                 ray_tracing_pipeline_properties,
+                pipeline_cache,
                 frames: [
                     Mutex::new(Arc::new(frame0)),
                     Mutex::new(Arc::new(frame1)),
                     //Mutex::new(Arc::new(frame2)),
                 ],
                 ray_tracing_enabled,
+                ray_tracing_mode: if ray_query_enabled {
+                    RayTracingMode::RayQuery
+                } else {
+                    RayTracingMode::Pipeline
+                },
             }))
         }
     }
@@ -458,39 +766,47 @@ impl Device {
         let address_modes = [
             vk::SamplerAddressMode::REPEAT,
             vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            vk::SamplerAddressMode::MIRRORED_REPEAT,
         ];
 
         let mut result = HashMap::new();
 
         for &texel_filter in &texel_filters {
+            let anisotropy_levels: &[u32] = if texel_filter == vk::Filter::LINEAR {
+                &SAMPLER_ANISOTROPY_LEVELS
+            } else {
+                &[1]
+            };
+
             for &mipmap_mode in &mipmap_modes {
                 for &address_modes in &address_modes {
-                    let anisotropy_enable = texel_filter == vk::Filter::LINEAR;
-
-                    result.insert(
-                        SamplerDesc {
-                            texel_filter,
-                            mipmap_mode,
-                            address_modes,
-                        },
-                        unsafe {
-                            device.create_sampler(
-                                &vk::SamplerCreateInfo::builder()
-                                    .mag_filter(texel_filter)
-                                    .min_filter(texel_filter)
-                                    .mipmap_mode(mipmap_mode)
-                                    .address_mode_u(address_modes)
-                                    .address_mode_v(address_modes)
-                                    .address_mode_w(address_modes)
-                                    .max_lod(vk::LOD_CLAMP_NONE)
-                                    .max_anisotropy(16.0)
-                                    .anisotropy_enable(anisotropy_enable)
-                                    .build(),
-                                None,
-                            )
-                        }
-                        .expect("create_sampler"),
-                    );
+                    for &max_anisotropy in anisotropy_levels {
+                        result.insert(
+                            SamplerDesc {
+                                texel_filter,
+                                mipmap_mode,
+                                address_modes,
+                                max_anisotropy,
+                            },
+                            unsafe {
+                                device.create_sampler(
+                                    &vk::SamplerCreateInfo::builder()
+                                        .mag_filter(texel_filter)
+                                        .min_filter(texel_filter)
+                                        .mipmap_mode(mipmap_mode)
+                                        .address_mode_u(address_modes)
+                                        .address_mode_v(address_modes)
+                                        .address_mode_w(address_modes)
+                                        .max_lod(vk::LOD_CLAMP_NONE)
+                                        .max_anisotropy(max_anisotropy as f32)
+                                        .anisotropy_enable(max_anisotropy > 1)
+                                        .build(),
+                                    None,
+                                )
+                            }
+                            .expect("create_sampler"),
+                        );
+                    }
                 }
             }
         }
@@ -505,6 +821,11 @@ impl Device {
             .unwrap_or_else(|| panic!("Sampler not found: {:?}", desc))
     }
 
+    /// Samplers of `MATERIAL_SAMPLER_DESCS`, in the same order.
+    pub fn material_samplers(&self) -> &[vk::Sampler] {
+        &self.material_samplers
+    }
+
     pub fn begin_frame(&self) -> Arc<DeviceFrame> {
         let mut frame0 = self.frames[0].lock();
         {
@@ -517,24 +838,14 @@ impl Device {
             //
             // We can't use device.frame[0] before this, or we race with the GPU.
             //
-            // TODO: the wait here protects more than the command buffers (such as dynamic constants),
-            // but the fence belongs to command buffers, creating a confusing relationship.
-            unsafe {
+            // Note: this waits for the frame's final value on the queue timeline,
+            // so it covers all of its submissions, and everything they access
+            // (such as dynamic constants), not just the command buffers.
+            {
                 puffin::profile_scope!("wait submit done");
 
-                self.raw
-                    .wait_for_fences(
-                        // Note: need to wait for both command buffers so that the GPU won't
-                        // be accessing frame[0] any more after this.
-                        &[
-                            frame0.main_command_buffer.submit_done_fence,
-                            frame0.presentation_command_buffer.submit_done_fence,
-                        ],
-                        true,
-                        std::u64::MAX,
-                    )
-                    .map_err(|err| self.report_error(err.into()))
-                    .expect("Wait for fence failed.");
+                self.wait_for_queue(&self.universal_queue, frame0.submitted_timeline_value)
+                    .expect("Wait for frame timeline failed.");
             }
 
             puffin::profile_scope!("release pending resources");
@@ -542,6 +853,10 @@ impl Device {
                 .pending_resource_releases
                 .get_mut()
                 .release_all(&self.raw);
+
+            for pool in &mut frame0.secondary_command_pools {
+                pool.get_mut().reset(&self.raw);
+            }
         }
 
         frame0.clone()
@@ -571,22 +886,35 @@ impl Device {
 
         unsafe {
             self.raw.end_command_buffer(cb.raw).unwrap();
+        }
 
-            let submit_info =
-                vk::SubmitInfo::builder().command_buffers(std::slice::from_ref(&cb.raw));
+        let value = self
+            .universal_queue
+            .submit(&self.raw, std::slice::from_ref(&cb.raw), &[], &[])
+            .expect("queue submit failed.");
 
-            self.raw
-                .queue_submit(
-                    self.universal_queue.raw,
-                    &[submit_info.build()],
-                    vk::Fence::null(),
-                )
-                .expect("queue submit failed.");
+        log::trace!("wait for setup cb");
 
-            log::trace!("device_wait_idle");
+        self.wait_for_queue(&self.universal_queue, value)
+    }
+
+    /// Blocks until the timeline of `queue` reaches `value`.
+    pub fn wait_for_queue(&self, queue: &Queue, value: u64) -> Result<(), BackendError> {
+        let wait_info = vk::SemaphoreWaitInfo::builder()
+            .semaphores(std::slice::from_ref(&queue.timeline.semaphore))
+            .values(std::slice::from_ref(&value));
+
+        unsafe { self.raw.wait_semaphores(&wait_info, std::u64::MAX) }
+            .map_err(|err| self.report_error(err.into()))
+    }
 
-            Ok(self.raw.device_wait_idle()?)
+    /// The value the timeline of `queue` has reached so far. Doesn't block.
+    pub fn queue_completed_value(&self, queue: &Queue) -> Result<u64, BackendError> {
+        unsafe {
+            self.raw
+                .get_semaphore_counter_value(queue.timeline.semaphore)
         }
+        .map_err(|err| self.report_error(err.into()))
     }
 
     pub fn finish_frame(&self, frame: Arc<DeviceFrame>) {
@@ -597,6 +925,9 @@ impl Device {
             panic!("Unable to finish frame: frame data is being held by user code")
         });
 
+        // All of the frame's submissions have been made by now.
+        frame0.submitted_timeline_value = self.universal_queue.last_submitted_value();
+
         {
             let mut frame1 = self.frames[1].lock();
             let frame1: &mut DeviceFrame = Arc::get_mut(&mut frame1).unwrap();
@@ -617,6 +948,66 @@ impl Device {
         self.instance.debug_utils.as_ref()
     }
 
+    /// Names `object` in graphics debuggers and validation messages.
+    /// Does nothing unless graphics debugging is enabled.
+    pub fn set_object_name<H: vk::Handle>(&self, object: H, name: &str) {
+        let debug_utils = if let Some(debug_utils) = self.debug_utils() {
+            debug_utils
+        } else {
+            return;
+        };
+
+        let name = CString::new(name.replace('\0', "")).unwrap();
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::builder()
+            .object_type(H::TYPE)
+            .object_handle(object.as_raw())
+            .object_name(&name);
+
+        unsafe {
+            if let Err(err) = debug_utils.debug_utils_set_object_name(self.raw.handle(), &name_info)
+            {
+                warn!("Failed to name {:?} {:?}: {:?}", H::TYPE, name, err);
+            }
+        }
+    }
+
+    /// Opens a labeled region of `cb`, shown as a marker group in graphics debuggers.
+    /// Must be closed with `end_debug_label`. Does nothing unless graphics debugging is enabled.
+    pub fn begin_debug_label(&self, cb: vk::CommandBuffer, name: &str, color: [f32; 4]) {
+        if let Some(debug_utils) = self.debug_utils() {
+            let name = CString::new(name.replace('\0', "")).unwrap();
+            let label = vk::DebugUtilsLabelEXT::builder()
+                .label_name(&name)
+                .color(color);
+
+            unsafe {
+                debug_utils.cmd_begin_debug_utils_label(cb, &label);
+            }
+        }
+    }
+
+    pub fn end_debug_label(&self, cb: vk::CommandBuffer) {
+        if let Some(debug_utils) = self.debug_utils() {
+            unsafe {
+                debug_utils.cmd_end_debug_utils_label(cb);
+            }
+        }
+    }
+
+    /// Places a single marker in `cb`, e.g. to tag a draw within a pass.
+    pub fn insert_debug_label(&self, cb: vk::CommandBuffer, name: &str, color: [f32; 4]) {
+        if let Some(debug_utils) = self.debug_utils() {
+            let name = CString::new(name.replace('\0', "")).unwrap();
+            let label = vk::DebugUtilsLabelEXT::builder()
+                .label_name(&name)
+                .color(color);
+
+            unsafe {
+                debug_utils.cmd_insert_debug_utils_label(cb, &label);
+            }
+        }
+    }
+
     pub fn max_bindless_descriptor_count(&self) -> u32 {
         (512 * 1024).min(
             self.pdevice
@@ -630,6 +1021,23 @@ impl Device {
     pub fn ray_tracing_enabled(&self) -> bool {
         self.ray_tracing_enabled
     }
+
+    /// The `RenderBackendConfig::ray_tracing_mode` in use; `Pipeline` when ray queries
+    /// were requested, but aren't supported.
+    pub fn ray_tracing_mode(&self) -> RayTracingMode {
+        self.ray_tracing_mode
+    }
+
+    /// Writes the driver's pipeline cache to disk, for the next launch to start from.
+    /// Happens when the device is dropped, and after new pipelines are built.
+    pub fn save_pipeline_cache(&self) {
+        match unsafe { self.raw.get_pipeline_cache_data(self.pipeline_cache) } {
+            Ok(data) => {
+                crate::shader_cache::store_pipeline_cache_data(&self.pdevice.properties, &data)
+            }
+            Err(err) => warn!("Failed to get the pipeline cache data: {:?}", err),
+        }
+    }
 }
 
 impl Drop for Device {
@@ -637,6 +1045,9 @@ impl Drop for Device {
         unsafe {
             log::trace!("device_wait_idle");
             let _ = self.raw.device_wait_idle();
+
+            self.save_pipeline_cache();
+            self.raw.destroy_pipeline_cache(self.pipeline_cache, None);
         }
     }
 }
@@ -664,9 +1075,48 @@ impl Drop for Device {
     }
 }*/
 
+/// Anisotropy levels for which linear samplers are created.
+/// Nearest samplers never use anisotropic filtering.
+pub const SAMPLER_ANISOTROPY_LEVELS: [u32; 5] = [1, 2, 4, 8, 16];
+
+/// Sampler states selectable per material map, exposed to shaders
+/// as the `material_samplers` array in `inc/samplers.hlsl`.
+///
+/// Laid out as `address_mode * 2 + filter`, with address modes in the order
+/// repeat, clamp, mirror, and linear filtering before nearest.
+pub const MATERIAL_SAMPLER_DESCS: [SamplerDesc; 6] = [
+    SamplerDesc::material(vk::Filter::LINEAR, vk::SamplerAddressMode::REPEAT),
+    SamplerDesc::material(vk::Filter::NEAREST, vk::SamplerAddressMode::REPEAT),
+    SamplerDesc::material(vk::Filter::LINEAR, vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    SamplerDesc::material(vk::Filter::NEAREST, vk::SamplerAddressMode::CLAMP_TO_EDGE),
+    SamplerDesc::material(vk::Filter::LINEAR, vk::SamplerAddressMode::MIRRORED_REPEAT),
+    SamplerDesc::material(vk::Filter::NEAREST, vk::SamplerAddressMode::MIRRORED_REPEAT),
+];
+
 #[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
 pub struct SamplerDesc {
     pub texel_filter: vk::Filter,
     pub mipmap_mode: vk::SamplerMipmapMode,
     pub address_modes: vk::SamplerAddressMode,
+
+    /// One of `SAMPLER_ANISOTROPY_LEVELS`; 1 disables anisotropic filtering.
+    pub max_anisotropy: u32,
+}
+
+impl SamplerDesc {
+    // Linear filtering matches `sampler_llr`; nearest is fully point-sampled, for pixel art.
+    const fn material(texel_filter: vk::Filter, address_modes: vk::SamplerAddressMode) -> Self {
+        let linear = texel_filter.as_raw() == vk::Filter::LINEAR.as_raw();
+
+        Self {
+            texel_filter,
+            mipmap_mode: if linear {
+                vk::SamplerMipmapMode::LINEAR
+            } else {
+                vk::SamplerMipmapMode::NEAREST
+            },
+            address_modes,
+            max_anisotropy: if linear { 16 } else { 1 },
+        }
+    }
 }
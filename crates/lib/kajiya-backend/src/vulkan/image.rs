@@ -165,7 +165,10 @@ pub struct Image {
     pub raw: vk::Image,
     pub desc: ImageDesc,
     pub views: Mutex<HashMap<ImageViewDesc, vk::ImageView>>,
-    //allocation: gpu_allocator::SubAllocation,
+    /// `None` if the memory isn't owned by us (e.g. swapchain images)
+    pub allocation: Option<gpu_allocator::SubAllocation>,
+    /// Size of the memory bound to the image, or zero if it isn't owned by us (e.g. swapchain images)
+    pub memory_size: u64,
 }
 unsafe impl Send for Image {}
 unsafe impl Sync for Image {}
@@ -210,7 +213,8 @@ impl Image {
                 level_count: desc.level_count.unwrap_or(image_desc.mip_levels as u32),
                 base_array_layer: 0,
                 layer_count: match image_desc.image_type {
-                    ImageType::Cube | ImageType::CubeArray => 6,
+                    ImageType::Cube => 6,
+                    ImageType::CubeArray => 6 * image_desc.array_elements,
                     _ => 1,
                 },
             })
@@ -404,12 +408,31 @@ impl Device {
         ImageHandle(handle)*/
         Ok(Image {
             raw: image,
-            //allocation,
+            allocation: Some(allocation),
             desc,
             views: Default::default(),
+            memory_size: requirements.size,
         })
     }
 
+    /// Destroys `image` and its views right away, and frees its memory.
+    /// The GPU must be done with it.
+    pub fn immediate_destroy_image(&self, image: Image) {
+        unsafe {
+            for view in image.views.lock().values() {
+                self.raw.destroy_image_view(*view, None);
+            }
+            self.raw.destroy_image(image.raw, None);
+        }
+
+        if let Some(allocation) = image.allocation {
+            self.global_allocator
+                .lock()
+                .free(allocation)
+                .expect("image memory deallocated");
+        }
+    }
+
     fn create_image_view(
         &self,
         desc: ImageViewDesc,
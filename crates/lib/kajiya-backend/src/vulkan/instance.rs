@@ -68,13 +68,22 @@ impl Instance {
 
     fn create(builder: DeviceBuilder) -> Result<Self> {
         let entry = unsafe { ash::Entry::new()? };
-        let instance_extensions = builder
+        let mut instance_extensions = builder
             .required_extensions
             .iter()
             .map(|ext| ext.as_ptr())
             .chain(Self::extension_names(&builder).into_iter())
             .collect::<Vec<_>>();
 
+        // Exposes the HDR color spaces of surfaces; see `SwapchainColorSpace`.
+        let supported_extensions = entry.enumerate_instance_extension_properties()?;
+        let swapchain_colorspace = vk::ExtSwapchainColorspaceFn::name();
+        if supported_extensions.iter().any(|ext| unsafe {
+            CStr::from_ptr(ext.extension_name.as_ptr()) == swapchain_colorspace
+        }) {
+            instance_extensions.push(swapchain_colorspace.as_ptr());
+        }
+
         let layer_names = Self::layer_names(&builder);
         let layer_names: Vec<*const i8> = layer_names
             .iter()
@@ -17,14 +17,22 @@ use log::{debug, error, info, trace, warn};
 use raw_window_handle::HasRawWindowHandle;
 use std::sync::Arc;
 
-fn select_surface_format(formats: Vec<vk::SurfaceFormatKHR>) -> Option<vk::SurfaceFormatKHR> {
-    let preferred = vk::SurfaceFormatKHR {
-        format: vk::Format::B8G8R8A8_UNORM,
-        color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
-    };
+// Falls back to SDR if the display or driver don't support the requested HDR color space.
+fn select_surface_format(
+    formats: Vec<vk::SurfaceFormatKHR>,
+    color_space: swapchain::SwapchainColorSpace,
+) -> Option<vk::SurfaceFormatKHR> {
+    let preferred = color_space.surface_format();
+    let fallback = swapchain::SwapchainColorSpace::Srgb.surface_format();
 
     if formats.contains(&preferred) {
         Some(preferred)
+    } else if formats.contains(&fallback) {
+        warn!(
+            "{:?} swapchain output is not supported; falling back to sRGB",
+            color_space
+        );
+        Some(fallback)
     } else {
         None
     }
@@ -42,6 +50,12 @@ pub struct RenderBackendConfig {
     pub vsync: bool,
     pub graphics_debugging: bool,
     pub device_index: Option<usize>,
+
+    /// Requested color space of the swapchain; see `Swapchain::color_space` for the one in use.
+    pub color_space: swapchain::SwapchainColorSpace,
+
+    /// Requested way of tracing rays; see `Device::ray_tracing_mode` for the one in use.
+    pub ray_tracing_mode: device::RayTracingMode,
 }
 
 impl RenderBackend {
@@ -90,7 +104,7 @@ impl RenderBackend {
 
         info!("Selected physical device: {:#?}", *physical_device);
 
-        let device = device::Device::create(&physical_device)?;
+        let device = device::Device::create(&physical_device, config.ray_tracing_mode)?;
         let surface_formats = swapchain::Swapchain::enumerate_surface_formats(&device, &surface)?;
 
         info!("Available surface formats: {:#?}", surface_formats);
@@ -99,7 +113,8 @@ impl RenderBackend {
             &device,
             &surface,
             swapchain::SwapchainDesc {
-                format: select_surface_format(surface_formats).expect("suitable surface format"),
+                format: select_surface_format(surface_formats, config.color_space)
+                    .expect("suitable surface format"),
                 dims: vk::Extent2D {
                     width: config.swapchain_extent[0],
                     height: config.swapchain_extent[1],
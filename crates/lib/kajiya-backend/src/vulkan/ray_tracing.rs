@@ -36,13 +36,33 @@ pub struct RayTracingGeometryDesc {
     pub vertex_format: vk::Format,
     pub vertex_stride: usize,
     pub parts: Vec<RayTracingGeometryPart>,
+
+    /// Address of a row-major 3x4 matrix (`vk::TransformMatrixKHR`) transforming the vertices
+    /// into the space of the acceleration structure, for BLASes combining several meshes.
+    pub transform_buffer: Option<vk::DeviceAddress>,
+
+    /// Skips any-hit shaders on all of the triangles, unless the instance forces them
+    /// to run; see `RayTracingInstanceDesc::opaque`.
+    pub opaque: bool,
 }
 
 #[derive(Clone)]
 pub struct RayTracingInstanceDesc {
-    pub blas: Arc<RayTracingAcceleration>,
+    /// `None` makes the instance inactive: it keeps its index, but is invisible to rays.
+    pub blas: Option<Arc<RayTracingAcceleration>>,
     pub transformation: Affine3A,
     pub mesh_index: u32,
+
+    /// Visibility mask; the instance is only seen by rays whose inclusion mask shares a bit with it.
+    pub mask: u8,
+
+    /// Index of the hit group used for the instance in the shader binding table,
+    /// relative to the one selected by the ray. Zero for the default hit group.
+    pub hit_group_offset: u32,
+
+    /// When `false`, the opacity of the BLAS geometries is left as is, so that the any-hit
+    /// shader of its hit group runs on non-opaque ones, for rays which don't force opacity.
+    pub opaque: bool,
 }
 
 #[derive(Clone)]
@@ -54,6 +74,75 @@ pub struct RayTracingTopAccelerationDesc {
 #[derive(Clone, Debug)]
 pub struct RayTracingBottomAccelerationDesc {
     pub geometries: Vec<RayTracingGeometryDesc>,
+
+    /// Allows the acceleration structure to be refit after its vertices move;
+    /// see `Device::refit_ray_tracing_bottom_acceleration`.
+    pub allow_update: bool,
+}
+
+impl RayTracingBottomAccelerationDesc {
+    fn build_flags(&self) -> vk::BuildAccelerationStructureFlagsKHR {
+        if self.allow_update {
+            vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+                | vk::BuildAccelerationStructureFlagsKHR::ALLOW_UPDATE
+        } else {
+            vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE
+        }
+    }
+
+    fn geometries(&self) -> Vec<vk::AccelerationStructureGeometryKHR> {
+        self.geometries
+            .iter()
+            .map(|desc| {
+                let part: RayTracingGeometryPart = desc.parts[0];
+
+                ash::vk::AccelerationStructureGeometryKHR::builder()
+                    .geometry_type(ash::vk::GeometryTypeKHR::TRIANGLES)
+                    .geometry(ash::vk::AccelerationStructureGeometryDataKHR {
+                        triangles: ash::vk::AccelerationStructureGeometryTrianglesDataKHR::builder(
+                        )
+                        .vertex_data(ash::vk::DeviceOrHostAddressConstKHR {
+                            device_address: desc.vertex_buffer,
+                        })
+                        .vertex_stride(desc.vertex_stride as _)
+                        .max_vertex(part.max_vertex)
+                        .vertex_format(desc.vertex_format)
+                        .index_data(ash::vk::DeviceOrHostAddressConstKHR {
+                            device_address: desc.index_buffer,
+                        })
+                        .index_type(ash::vk::IndexType::UINT32) // TODO
+                        .transform_data(ash::vk::DeviceOrHostAddressConstKHR {
+                            device_address: desc.transform_buffer.unwrap_or(0),
+                        })
+                        .build(),
+                    })
+                    .flags(if desc.opaque {
+                        ash::vk::GeometryFlagsKHR::OPAQUE
+                    } else {
+                        ash::vk::GeometryFlagsKHR::empty()
+                    })
+                    .build()
+            })
+            .collect()
+    }
+
+    fn build_range_infos(&self) -> Vec<vk::AccelerationStructureBuildRangeInfoKHR> {
+        self.geometries
+            .iter()
+            .map(|desc| {
+                ash::vk::AccelerationStructureBuildRangeInfoKHR::builder()
+                    .primitive_count(desc.parts[0].index_count as u32 / 3)
+                    .build()
+            })
+            .collect()
+    }
+
+    fn max_primitive_counts(&self) -> Vec<u32> {
+        self.geometries
+            .iter()
+            .map(|desc| desc.parts[0].index_count as u32 / 3)
+            .collect()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -68,12 +157,27 @@ pub struct RayTracingAcceleration {
     backing_buffer: super::buffer::Buffer,
 }
 
+impl RayTracingAcceleration {
+    pub fn device_address(&self, device: &Device) -> vk::DeviceAddress {
+        unsafe {
+            device
+                .acceleration_structure_ext
+                .get_acceleration_structure_device_address(
+                    &ash::vk::AccelerationStructureDeviceAddressInfoKHR::builder()
+                        .acceleration_structure(self.raw)
+                        .build(),
+                )
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct RayTracingAccelerationScratchBuffer {
     buffer: Arc<Mutex<super::buffer::Buffer>>,
 }
 
-const RT_TLAS_SCRATCH_BUFFER_SIZE: usize = 256 * 1024;
+// Needs to fit TLAS builds with a few hundred thousand instances (see instance batches).
+const RT_TLAS_SCRATCH_BUFFER_SIZE: usize = 16 * 1024 * 1024;
 
 impl Device {
     pub fn create_ray_tracing_acceleration_scratch_buffer(
@@ -93,67 +197,33 @@ impl Device {
         })
     }
 
+    /// Destroys the acceleration structure and frees its memory. The caller must make sure
+    /// the GPU is no longer using it, including through TLAS instances.
+    pub fn immediate_destroy_ray_tracing_acceleration(&self, accel: RayTracingAcceleration) {
+        unsafe {
+            self.acceleration_structure_ext
+                .destroy_acceleration_structure(accel.raw, None);
+        }
+        self.immediate_destroy_buffer(accel.backing_buffer);
+    }
+
     pub fn create_ray_tracing_bottom_acceleration(
         &self,
         desc: &RayTracingBottomAccelerationDesc,
     ) -> Result<RayTracingAcceleration, BackendError> {
         //log::trace!("Creating ray tracing bottom acceleration: {:?}", desc);
 
-        let geometries: Result<Vec<ash::vk::AccelerationStructureGeometryKHR>, BackendError> = desc
-            .geometries
-            .iter()
-            .map(
-                |desc| -> Result<ash::vk::AccelerationStructureGeometryKHR, BackendError> {
-                    let part: RayTracingGeometryPart = desc.parts[0];
-
-                    let geometry = ash::vk::AccelerationStructureGeometryKHR::builder()
-                        .geometry_type(ash::vk::GeometryTypeKHR::TRIANGLES)
-                        .geometry(ash::vk::AccelerationStructureGeometryDataKHR {
-                            triangles:
-                                ash::vk::AccelerationStructureGeometryTrianglesDataKHR::builder()
-                                    .vertex_data(ash::vk::DeviceOrHostAddressConstKHR {
-                                        device_address: desc.vertex_buffer,
-                                    })
-                                    .vertex_stride(desc.vertex_stride as _)
-                                    .max_vertex(part.max_vertex)
-                                    .vertex_format(desc.vertex_format)
-                                    .index_data(ash::vk::DeviceOrHostAddressConstKHR {
-                                        device_address: desc.index_buffer,
-                                    })
-                                    .index_type(ash::vk::IndexType::UINT32) // TODO
-                                    .build(),
-                        })
-                        .flags(ash::vk::GeometryFlagsKHR::OPAQUE)
-                        .build();
-
-                    Ok(geometry)
-                },
-            )
-            .collect();
-        let geometries = geometries?;
-
-        let build_range_infos: Vec<ash::vk::AccelerationStructureBuildRangeInfoKHR> = desc
-            .geometries
-            .iter()
-            .map(|desc| {
-                ash::vk::AccelerationStructureBuildRangeInfoKHR::builder()
-                    .primitive_count(desc.parts[0].index_count as u32 / 3)
-                    .build()
-            })
-            .collect();
+        let geometries = desc.geometries();
+        let build_range_infos = desc.build_range_infos();
 
         let geometry_info = ash::vk::AccelerationStructureBuildGeometryInfoKHR::builder()
             .ty(ash::vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
-            .flags(ash::vk::BuildAccelerationStructureFlagsKHR::PREFER_FAST_TRACE)
+            .flags(desc.build_flags())
             .geometries(geometries.as_slice())
             .mode(vk::BuildAccelerationStructureModeKHR::BUILD)
             .build();
 
-        let max_primitive_counts: Vec<_> = desc
-            .geometries
-            .iter()
-            .map(|desc| desc.parts[0].index_count as u32 / 3)
-            .collect();
+        let max_primitive_counts = desc.max_primitive_counts();
 
         // Create bottom-level acceleration structure
 
@@ -181,14 +251,14 @@ impl Device {
             .instances
             .iter()
             .map(|desc| {
-                let blas_address = unsafe {
+                let blas_address = desc.blas.as_ref().map_or(0, |blas| unsafe {
                     self.acceleration_structure_ext
                         .get_acceleration_structure_device_address(
                             &ash::vk::AccelerationStructureDeviceAddressInfoKHR::builder()
-                                .acceleration_structure(desc.blas.raw)
+                                .acceleration_structure(blas.raw)
                                 .build(),
                         )
-                };
+                });
 
                 let transform = [
                     desc.transformation.x_axis.x,
@@ -208,11 +278,15 @@ impl Device {
                 GeometryInstance::new(
                     transform,
                     desc.mesh_index, /* instance id */
-                    0xff,
-                    0,
+                    desc.mask,
+                    desc.hit_group_offset,
                     /*ash::vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE
                     | */
-                    ash::vk::GeometryInstanceFlagsKHR::FORCE_OPAQUE,
+                    if desc.opaque {
+                        ash::vk::GeometryInstanceFlagsKHR::FORCE_OPAQUE
+                    } else {
+                        ash::vk::GeometryInstanceFlagsKHR::empty()
+                    },
                     blas_address,
                 )
             })
@@ -405,6 +479,7 @@ impl Device {
         res
     }
 
+    #[track_caller]
     pub fn fill_ray_tracing_instance_buffer(
         &self,
         dynamic_constants: &mut DynamicConstants,
@@ -413,14 +488,15 @@ impl Device {
         let instance_buffer_address = dynamic_constants.current_device_address(self);
 
         dynamic_constants.push_from_iter(instances.iter().map(|desc| {
-            let blas_address = unsafe {
+            // A null reference makes the instance inactive.
+            let blas_address = desc.blas.as_ref().map_or(0, |blas| unsafe {
                 self.acceleration_structure_ext
                     .get_acceleration_structure_device_address(
                         &ash::vk::AccelerationStructureDeviceAddressInfoKHR::builder()
-                            .acceleration_structure(desc.blas.raw)
+                            .acceleration_structure(blas.raw)
                             .build(),
                     )
-            };
+            });
 
             let transform = [
                 desc.transformation.x_axis.x,
@@ -440,11 +516,15 @@ impl Device {
             GeometryInstance::new(
                 transform,
                 desc.mesh_index, /* instance id */
-                0xff,
-                0,
+                desc.mask,
+                desc.hit_group_offset,
                 /*ash::vk::GeometryInstanceFlagsKHR::TRIANGLE_FACING_CULL_DISABLE
                 | */
-                ash::vk::GeometryInstanceFlagsKHR::FORCE_OPAQUE,
+                if desc.opaque {
+                    ash::vk::GeometryInstanceFlagsKHR::FORCE_OPAQUE
+                } else {
+                    ash::vk::GeometryInstanceFlagsKHR::empty()
+                },
                 blas_address,
             )
         }));
@@ -496,6 +576,42 @@ impl Device {
         )
     }
 
+    /// Updates a BLAS created with `allow_update` in place, to the current contents of its
+    /// vertex buffer. `desc` must be the one it was created with. Much cheaper than a rebuild,
+    /// but trace performance degrades as the geometry strays from the original build.
+    pub fn refit_ray_tracing_bottom_acceleration(
+        &self,
+        cb: vk::CommandBuffer,
+        desc: &RayTracingBottomAccelerationDesc,
+        blas: &RayTracingAcceleration,
+        scratch_buffer: &RayTracingAccelerationScratchBuffer,
+    ) {
+        assert!(
+            desc.allow_update,
+            "BLAS was not created with `allow_update`"
+        );
+
+        let geometries = desc.geometries();
+        let build_range_infos = desc.build_range_infos();
+
+        let mut geometry_info = ash::vk::AccelerationStructureBuildGeometryInfoKHR::builder()
+            .ty(ash::vk::AccelerationStructureTypeKHR::BOTTOM_LEVEL)
+            .flags(desc.build_flags())
+            .geometries(geometries.as_slice())
+            .mode(vk::BuildAccelerationStructureModeKHR::UPDATE)
+            .build();
+        geometry_info.src_acceleration_structure = blas.raw;
+
+        self.rebuild_ray_tracing_acceleration(
+            cb,
+            geometry_info,
+            &build_range_infos,
+            &desc.max_primitive_counts(),
+            blas,
+            scratch_buffer,
+        )
+    }
+
     fn rebuild_ray_tracing_acceleration(
         &self,
         cb: vk::CommandBuffer,
@@ -716,10 +832,10 @@ pub fn create_ray_tracing_pipeline(
     let stage_layouts = shaders
         .iter()
         .map(|desc| {
-            rspirv_reflect::Reflection::new_from_spirv(&desc.code)
+            crate::shader_cache::reflect_descriptor_sets(&desc.code)
                 .unwrap_or_else(|err| panic!("Failed compiling shader {:?}:\n{:?}", desc.desc, err))
-                .get_descriptor_sets()
-                .unwrap()
+                .as_ref()
+                .clone()
         })
         .collect::<Vec<_>>();
 
@@ -833,6 +949,7 @@ pub fn create_ray_tracing_pipeline(
                     assert!(
                         prev_stage == Some(ShaderPipelineStage::RayMiss)
                             || prev_stage == Some(ShaderPipelineStage::RayClosestHit)
+                            || prev_stage == Some(ShaderPipelineStage::RayAnyHit)
                     );
                     hit_entry_count += 1;
 
@@ -858,6 +975,26 @@ pub fn create_ray_tracing_pipeline(
                     shader_stages.push(stage);
                     shader_groups.push(group);
                 }
+                ShaderPipelineStage::RayAnyHit => {
+                    assert!(prev_stage == Some(ShaderPipelineStage::RayClosestHit));
+
+                    let (module, entry_point) = create_shader_module(desc);
+
+                    entry_points.push(std::ffi::CString::new(entry_point).unwrap());
+                    let entry_point = &**entry_points.last().unwrap();
+
+                    let stage = ash::vk::PipelineShaderStageCreateInfo::builder()
+                        .stage(ash::vk::ShaderStageFlags::ANY_HIT_KHR)
+                        .module(module)
+                        .name(entry_point)
+                        .build();
+
+                    // Completes the hit group of the preceding closest-hit shader.
+                    let group = shader_groups.last_mut().unwrap();
+                    group.any_hit_shader = group_idx as _;
+
+                    shader_stages.push(stage);
+                }
                 _ => unimplemented!(),
             }
 
@@ -871,7 +1008,7 @@ pub fn create_ray_tracing_pipeline(
             .ray_tracing_pipeline_ext
             .create_ray_tracing_pipelines(
                 vk::DeferredOperationKHR::null(),
-                vk::PipelineCache::null(),
+                device.pipeline_cache,
                 &[ash::vk::RayTracingPipelineCreateInfoKHR::builder()
                     .stages(&shader_stages)
                     .groups(&shader_groups)
@@ -934,6 +1071,9 @@ pub struct RayTracingShaderTable {
     pub callable_shader_binding_table: vk::StridedDeviceAddressRegionKHR,
 }
 
+/// Size of a TLAS instance record (`VkAccelerationStructureInstanceKHR`)
+pub const RAY_TRACING_INSTANCE_SIZE: usize = std::mem::size_of::<GeometryInstance>();
+
 #[repr(C)]
 #[derive(Clone, Debug, Copy)]
 struct GeometryInstance {
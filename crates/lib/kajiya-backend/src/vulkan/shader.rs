@@ -4,7 +4,10 @@ use super::{
     device::{Device, SamplerDesc},
     image::ImageDesc,
 };
-use crate::{chunky_list::TempList, shader_compiler::get_cs_local_size_from_spirv};
+use crate::{
+    chunky_list::TempList, shader_cache::StageDescriptorSetLayouts,
+    shader_compiler::get_cs_local_size_from_spirv,
+};
 use arrayvec::ArrayVec;
 use ash::vk;
 use byte_slice_cast::AsSliceOf as _;
@@ -21,7 +24,6 @@ use std::{
 pub const MAX_DESCRIPTOR_SETS: usize = 4;
 
 type DescriptorSetLayout = HashMap<u32, rspirv_reflect::DescriptorInfo>;
-type StageDescriptorSetLayouts = HashMap<u32, DescriptorSetLayout>;
 
 pub struct ShaderPipelineCommon {
     pub pipeline_layout: vk::PipelineLayout,
@@ -206,7 +208,29 @@ pub fn create_descriptor_set_layouts(
                     }
                     rspirv_reflect::DescriptorType::SAMPLER => {
                         let name_prefix = "sampler_";
-                        if let Some(mut spec) = binding.name.strip_prefix(name_prefix) {
+                        if binding.name == "material_samplers" {
+                            let material_samplers = device.material_samplers();
+
+                            assert!(
+                                matches!(
+                                    binding.dimensionality,
+                                    rspirv_reflect::DescriptorDimensionality::Array(size)
+                                        if size as usize == material_samplers.len()
+                                ),
+                                "material_samplers must be an array of {} samplers",
+                                material_samplers.len()
+                            );
+
+                            bindings.push(
+                                vk::DescriptorSetLayoutBinding::builder()
+                                    .descriptor_count(material_samplers.len() as u32)
+                                    .descriptor_type(vk::DescriptorType::SAMPLER)
+                                    .stage_flags(stage_flags)
+                                    .binding(*binding_index)
+                                    .immutable_samplers(material_samplers)
+                                    .build(),
+                            );
+                        } else if let Some(mut spec) = binding.name.strip_prefix(name_prefix) {
                             let texel_filter = match &spec[..1] {
                                 "n" => vk::Filter::NEAREST,
                                 "l" => vk::Filter::LINEAR,
@@ -221,6 +245,25 @@ pub fn create_descriptor_set_layouts(
                             };
                             spec = &spec[1..];
 
+                            // Optional anisotropy suffix, e.g. `sampler_llr_a4`.
+                            // Linear samplers default to 16x anisotropic filtering.
+                            let (spec, max_anisotropy) = match spec.split_once("_a") {
+                                Some((spec, aniso)) => (
+                                    spec,
+                                    aniso.parse::<u32>().unwrap_or_else(|_| {
+                                        panic!("Invalid sampler anisotropy: {}", binding.name)
+                                    }),
+                                ),
+                                None => (
+                                    spec,
+                                    if texel_filter == vk::Filter::LINEAR {
+                                        16
+                                    } else {
+                                        1
+                                    },
+                                ),
+                            };
+
                             let address_modes = match spec {
                                 "r" => vk::SamplerAddressMode::REPEAT,
                                 "mr" => vk::SamplerAddressMode::MIRRORED_REPEAT,
@@ -240,6 +283,7 @@ pub fn create_descriptor_set_layouts(
                                             texel_filter,
                                             mipmap_mode,
                                             address_modes,
+                                            max_anisotropy,
                                         }),
                                     )))
                                     .build(),
@@ -322,8 +366,14 @@ impl DescriptorSetLayoutOpts {
 
 #[derive(Clone, Hash, PartialEq, Eq, Debug)]
 pub enum ShaderSource {
-    Rust { entry: String },
-    Hlsl { path: PathBuf },
+    Rust {
+        entry: String,
+    },
+    Hlsl {
+        path: PathBuf,
+        /// Preprocessor `(name, value)` pairs, as with `-D name=value`
+        defines: Vec<(String, String)>,
+    },
 }
 
 impl ShaderSource {
@@ -334,7 +384,18 @@ impl ShaderSource {
     }
 
     pub fn hlsl(path: impl Into<PathBuf>) -> Self {
-        ShaderSource::Hlsl { path: path.into() }
+        ShaderSource::Hlsl {
+            path: path.into(),
+            defines: Vec::new(),
+        }
+    }
+
+    /// Adds preprocessor defines to an HLSL shader. Rust shaders are returned unchanged.
+    pub fn with_defines(mut self, extra_defines: &[(String, String)]) -> Self {
+        if let ShaderSource::Hlsl { defines, .. } = &mut self {
+            defines.extend_from_slice(extra_defines);
+        }
+        self
     }
 
     pub fn entry(&self) -> &str {
@@ -391,10 +452,7 @@ pub fn create_compute_pipeline(
 ) -> ComputePipeline {
     let (descriptor_set_layouts, set_layout_info) = super::shader::create_descriptor_set_layouts(
         device,
-        &rspirv_reflect::Reflection::new_from_spirv(spirv)
-            .unwrap()
-            .get_descriptor_sets()
-            .unwrap(),
+        &crate::shader_cache::reflect_descriptor_sets(spirv).unwrap(),
         vk::ShaderStageFlags::COMPUTE,
         &desc.descriptor_set_opts,
     );
@@ -441,8 +499,7 @@ pub fn create_compute_pipeline(
 
         let pipeline = device
             .raw
-            // TODO: pipeline cache
-            .create_compute_pipelines(vk::PipelineCache::null(), &[pipeline_info.build()], None)
+            .create_compute_pipelines(device.pipeline_cache, &[pipeline_info.build()], None)
             .expect("pipeline")[0];
 
         let mut descriptor_pool_sizes: Vec<vk::DescriptorPoolSize> = Vec::new();
@@ -481,6 +538,9 @@ pub enum ShaderPipelineStage {
     RayGen,
     RayMiss,
     RayClosestHit,
+
+    /// Joins the hit group of the closest-hit shader right before it.
+    RayAnyHit,
 }
 
 #[derive(Builder, Hash, PartialEq, Eq, Clone, Debug)]
@@ -828,10 +888,10 @@ pub fn create_raster_pipeline(
     let stage_layouts = shaders
         .iter()
         .map(|shader| {
-            rspirv_reflect::Reflection::new_from_spirv(&shader.code)
-                .unwrap()
-                .get_descriptor_sets()
+            crate::shader_cache::reflect_descriptor_sets(&shader.code)
                 .unwrap()
+                .as_ref()
+                .clone()
         })
         .collect::<Vec<_>>();
 
@@ -975,7 +1035,7 @@ pub fn create_raster_pipeline(
         let pipeline = device
             .raw
             .create_graphics_pipelines(
-                vk::PipelineCache::null(),
+                device.pipeline_cache,
                 &[graphic_pipeline_info.build()],
                 None,
             )
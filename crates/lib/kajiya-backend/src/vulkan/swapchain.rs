@@ -5,6 +5,56 @@ use ash::{extensions::khr, vk};
 use log::{debug, error, info, trace, warn};
 use std::sync::Arc;
 
+/// Color space and encoding of the presented images.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SwapchainColorSpace {
+    /// 8-bit sRGB, for SDR displays
+    Srgb,
+
+    /// Linear Rec. 709 primaries in half floats, with 1.0 at 80 nits. Values above one
+    /// reach into HDR, and negative ones into wider gamuts.
+    ScRgb,
+
+    /// HDR10: Rec. 2020 primaries and the PQ (SMPTE ST 2084) transfer function, in 10 bits
+    Hdr10,
+}
+
+impl Default for SwapchainColorSpace {
+    fn default() -> Self {
+        Self::Srgb
+    }
+}
+
+impl SwapchainColorSpace {
+    pub fn surface_format(self) -> vk::SurfaceFormatKHR {
+        match self {
+            Self::Srgb => vk::SurfaceFormatKHR {
+                format: vk::Format::B8G8R8A8_UNORM,
+                color_space: vk::ColorSpaceKHR::SRGB_NONLINEAR,
+            },
+            Self::ScRgb => vk::SurfaceFormatKHR {
+                format: vk::Format::R16G16B16A16_SFLOAT,
+                color_space: vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            },
+            Self::Hdr10 => vk::SurfaceFormatKHR {
+                format: vk::Format::A2B10G10R10_UNORM_PACK32,
+                color_space: vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+            },
+        }
+    }
+
+    pub fn from_surface_format(format: vk::SurfaceFormatKHR) -> Option<Self> {
+        [Self::Srgb, Self::ScRgb, Self::Hdr10]
+            .iter()
+            .copied()
+            .find(|color_space| color_space.surface_format() == format)
+    }
+
+    pub fn is_hdr(self) -> bool {
+        self != Self::Srgb
+    }
+}
+
 #[derive(Clone, Copy, Default)]
 pub struct SwapchainDesc {
     pub format: vk::SurfaceFormatKHR,
@@ -167,13 +217,15 @@ impl Swapchain {
                         image_type: crate::ImageType::Tex2d,
                         usage: vk::ImageUsageFlags::STORAGE,
                         flags: vk::ImageCreateFlags::empty(),
-                        format: vk::Format::B8G8R8A8_UNORM,
+                        format: desc.format.format,
                         extent: [desc.dims.width, desc.dims.height, 0],
                         tiling: vk::ImageTiling::OPTIMAL,
                         mip_levels: 1,
                         array_elements: 1,
                     },
                     views: Default::default(),
+                    allocation: None,
+                    memory_size: 0,
                 })
             })
             .collect();
@@ -219,6 +271,10 @@ impl Swapchain {
         [self.desc.dims.width, self.desc.dims.height]
     }
 
+    pub fn color_space(&self) -> SwapchainColorSpace {
+        SwapchainColorSpace::from_surface_format(self.desc.format).unwrap_or_default()
+    }
+
     pub fn acquire_next_image(
         &mut self,
     ) -> std::result::Result<SwapchainImage, SwapchainAcquireImageErr> {
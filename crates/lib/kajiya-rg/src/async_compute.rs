@@ -0,0 +1,169 @@
+use std::collections::HashSet;
+
+use crate::graph::RecordedPass;
+
+/// Passes of a frame, split around a single batch of async compute passes.
+/// Universal queue passes keep their relative order, and so do the async ones.
+pub(crate) struct AsyncComputeSchedule {
+    /// Universal queue passes which the async passes depend on; submitted first.
+    pub before: Vec<RecordedPass>,
+
+    pub async_passes: Vec<RecordedPass>,
+
+    /// Universal queue passes sharing no resources with the async passes,
+    /// submitted to run alongside them.
+    pub overlap: Vec<RecordedPass>,
+
+    /// Universal queue passes from the first one accessing a resource of the async passes;
+    /// they wait for the async submission.
+    pub after: Vec<RecordedPass>,
+}
+
+/// Schedules the passes marked with `PassBuilder::async_compute`, or gives `passes` back
+/// if there are none. `following` are the passes recorded after these ones, on the universal
+/// queue; their debug groups are adjusted to stay balanced.
+pub(crate) fn schedule_async_compute(
+    passes: Vec<RecordedPass>,
+    following: &mut [RecordedPass],
+) -> Result<AsyncComputeSchedule, Vec<RecordedPass>> {
+    if !passes.iter().any(|pass| pass.async_compute) {
+        return Err(passes);
+    }
+
+    let pass_resources: Vec<HashSet<u32>> = passes
+        .iter()
+        .map(|pass| {
+            pass.read
+                .iter()
+                .chain(pass.write.iter())
+                .map(|res| res.handle.id)
+                .collect()
+        })
+        .collect();
+
+    // The async passes start after the last universal pass sharing resources with any later
+    // async pass. Hazards of all kinds count, so reads of the same resource do too.
+    let mut fork = 0;
+    let mut later_async_resources: HashSet<u32> = HashSet::new();
+    for idx in (0..passes.len()).rev() {
+        if passes[idx].async_compute {
+            later_async_resources.extend(&pass_resources[idx]);
+        } else if !pass_resources[idx].is_disjoint(&later_async_resources) {
+            fork = idx + 1;
+            break;
+        }
+    }
+
+    let async_resources: HashSet<u32> = passes
+        .iter()
+        .zip(&pass_resources)
+        .filter(|(pass, _)| pass.async_compute)
+        .flat_map(|(_, resources)| resources.iter().copied())
+        .collect();
+
+    // Universal passes which come after the fork, and touch any of those, only run
+    // after every async pass. None of the async passes after them touch their resources,
+    // or the fork would be later.
+    let join = (fork..passes.len())
+        .find(|&idx| {
+            !passes[idx].async_compute && !pass_resources[idx].is_disjoint(&async_resources)
+        })
+        .unwrap_or(passes.len());
+
+    let (stacks, final_stack) = debug_group_stacks(&passes);
+
+    let mut schedule = AsyncComputeSchedule {
+        before: Vec::new(),
+        async_passes: Vec::new(),
+        overlap: Vec::new(),
+        after: Vec::new(),
+    };
+
+    let mut universal_stacks: Vec<Vec<String>> = Vec::new();
+
+    for (idx, (mut pass, stack)) in passes.into_iter().zip(stacks).enumerate() {
+        if pass.async_compute {
+            // Async passes are recorded into a command buffer of their own,
+            // so each one re-opens and closes the groups around it.
+            pass.end_debug_group_count = stack.len();
+            pass.begin_debug_groups = stack;
+            schedule.async_passes.push(pass);
+            continue;
+        }
+
+        universal_stacks.push(stack);
+
+        if idx < fork {
+            schedule.before.push(pass);
+        } else if idx < join {
+            schedule.overlap.push(pass);
+        } else {
+            schedule.after.push(pass);
+        }
+    }
+
+    rebalance_debug_groups(
+        schedule
+            .before
+            .iter_mut()
+            .chain(schedule.overlap.iter_mut())
+            .chain(schedule.after.iter_mut())
+            .zip(universal_stacks),
+        &final_stack,
+        following,
+    );
+
+    Ok(schedule)
+}
+
+// Debug groups open during each pass, outermost first, and those left open after the last one.
+fn debug_group_stacks(passes: &[RecordedPass]) -> (Vec<Vec<String>>, Vec<String>) {
+    let mut open: Vec<String> = Vec::new();
+    let stacks = passes
+        .iter()
+        .map(|pass| {
+            open.extend(pass.begin_debug_groups.iter().cloned());
+            let stack = open.clone();
+            open.truncate(open.len() - pass.end_debug_group_count);
+            stack
+        })
+        .collect();
+
+    (stacks, open)
+}
+
+// Re-derives the debug groups opened and closed around passes taken out of order,
+// from the groups which were open during each of them.
+fn rebalance_debug_groups<'a>(
+    passes: impl Iterator<Item = (&'a mut RecordedPass, Vec<String>)>,
+    final_stack: &[String],
+    following: &mut [RecordedPass],
+) {
+    let common_prefix_len =
+        |a: &[String], b: &[String]| a.iter().zip(b).take_while(|(a, b)| a == b).count();
+
+    let mut open: Vec<String> = Vec::new();
+    let mut prev: Option<&'a mut RecordedPass> = None;
+
+    for (pass, stack) in passes {
+        let common = common_prefix_len(&open, &stack);
+        if let Some(prev) = prev {
+            prev.end_debug_group_count = open.len() - common;
+        }
+
+        pass.begin_debug_groups = stack[common..].to_vec();
+        open = stack;
+        prev = Some(pass);
+    }
+
+    let common = common_prefix_len(&open, final_stack);
+    if let Some(prev) = prev {
+        prev.end_debug_group_count = open.len() - common;
+    }
+
+    // Re-open whatever the following passes expect to be open already.
+    if let Some(next) = following.first_mut() {
+        next.begin_debug_groups
+            .splice(0..0, final_stack[common..].iter().cloned());
+    }
+}
@@ -0,0 +1,144 @@
+//! Attribution of dynamic constants to the passes, and optionally the source locations,
+//! which pushed them.
+//!
+//! All of a frame's uniforms, constant blobs, and data uploaded through dynamic constants
+//! (instance parameters, lights, TLAS instances) share one half of the double-buffered
+//! `DYNAMIC_CONSTANTS_SIZE_BYTES` allocation. When it runs out, the frame fails
+//! with a bare assertion; this shows where the budget went.
+
+use std::collections::HashMap;
+
+use kajiya_backend::dynamic_constants::{DynamicConstantsCallSite, DYNAMIC_CONSTANTS_SIZE_BYTES};
+
+/// Name under which `prepare_frame_constants` usage is reported, ahead of the passes.
+pub const FRAME_CONSTANTS_SCOPE_NAME: &str = "frame constants";
+
+/// Dynamic constants pushed by a pass in a single frame.
+#[derive(Clone, Debug)]
+pub struct PassConstantsUsage {
+    pub name: String,
+
+    /// Including alignment padding
+    pub bytes: usize,
+
+    /// Empty unless `DynamicConstantsTracker::track_call_sites` is set. Largest first.
+    pub call_sites: Vec<DynamicConstantsCallSite>,
+}
+
+#[derive(Default)]
+pub struct DynamicConstantsTracker {
+    /// Record which source locations pushed the constants of each pass.
+    pub track_call_sites: bool,
+
+    /// Panic as soon as a single pass pushes more than this many bytes,
+    /// naming the pass and the call site.
+    pub max_bytes_per_pass: Option<usize>,
+
+    last_frame: Vec<PassConstantsUsage>,
+    last_frame_bytes: usize,
+    last_frame_parity: usize,
+    peak_frame_bytes: usize,
+    peak_bytes_per_pass: HashMap<String, usize>,
+}
+
+impl DynamicConstantsTracker {
+    /// Bytes of dynamic constants available to each frame.
+    pub fn budget_bytes(&self) -> usize {
+        DYNAMIC_CONSTANTS_SIZE_BYTES
+    }
+
+    /// Usage of the last executed frame, in the order of the passes. Passes which didn't
+    /// push anything are included.
+    pub fn last_frame(&self) -> &[PassConstantsUsage] {
+        &self.last_frame
+    }
+
+    /// Total usage of the last executed frame. Passes recorded on worker threads
+    /// get ranges of the buffer up front, so this can exceed the sum of the passes.
+    pub fn last_frame_bytes(&self) -> usize {
+        self.last_frame_bytes
+    }
+
+    /// Which half of the double-buffered constants the last frame used.
+    pub fn last_frame_parity(&self) -> usize {
+        self.last_frame_parity
+    }
+
+    pub fn peak_frame_bytes(&self) -> usize {
+        self.peak_frame_bytes
+    }
+
+    /// Most bytes pushed by passes of a given name in a single frame, across all frames so far.
+    pub fn peak_bytes_per_pass(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.peak_bytes_per_pass
+            .iter()
+            .map(|(name, bytes)| (name.as_str(), *bytes))
+    }
+
+    /// Human-readable table of the last frame's usage, largest first.
+    pub fn report(&self) -> String {
+        let mut passes = self
+            .last_frame
+            .iter()
+            .filter(|pass| pass.bytes > 0)
+            .collect::<Vec<_>>();
+        passes.sort_by_key(|pass| std::cmp::Reverse(pass.bytes));
+
+        let mut report = format!(
+            "Dynamic constants: {} of {} used; peak {}\n{:>12} {:>12}  pass\n",
+            format_bytes(self.last_frame_bytes),
+            format_bytes(self.budget_bytes()),
+            format_bytes(self.peak_frame_bytes),
+            "bytes",
+            "peak"
+        );
+
+        for pass in passes {
+            report += &format!(
+                "{:>12} {:>12}  {}\n",
+                format_bytes(pass.bytes),
+                format_bytes(self.peak_bytes_per_pass[&pass.name]),
+                pass.name
+            );
+
+            for site in &pass.call_sites {
+                report += &format!(
+                    "{:>12} {:>12}    {} ({} pushes)\n",
+                    format_bytes(site.bytes),
+                    "",
+                    site.location,
+                    site.pushes
+                );
+            }
+        }
+
+        report
+    }
+
+    pub(crate) fn record_frame(
+        &mut self,
+        passes: Vec<PassConstantsUsage>,
+        frame_bytes: usize,
+        frame_parity: usize,
+    ) {
+        // The same pass name can appear several times in a frame.
+        let mut frame_bytes_per_pass: HashMap<&str, usize> = HashMap::new();
+        for pass in &passes {
+            *frame_bytes_per_pass.entry(pass.name.as_str()).or_default() += pass.bytes;
+        }
+
+        for (name, bytes) in frame_bytes_per_pass {
+            let peak = self.peak_bytes_per_pass.entry(name.to_owned()).or_default();
+            *peak = (*peak).max(bytes);
+        }
+
+        self.last_frame = passes;
+        self.last_frame_bytes = frame_bytes;
+        self.last_frame_parity = frame_parity;
+        self.peak_frame_bytes = self.peak_frame_bytes.max(frame_bytes);
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    format!("{:.1} KiB", bytes as f64 / 1024.0)
+}
@@ -0,0 +1,320 @@
+use std::{collections::HashMap, marker::PhantomData, sync::Arc};
+
+use kajiya_backend::{
+    ash::vk,
+    vk_sync::AccessType,
+    vulkan::{barrier::image_aspect_mask_from_format, device::Device},
+};
+
+use crate::{
+    graph::{GraphResourceCreateInfo, GraphResourceImportInfo, GraphResourceInfo},
+    Buffer, BufferDesc, GpuSrv, GpuUav, GraphRawResourceHandle, GraphResourceDesc, Handle, Image,
+    ImageDesc, Ref, RenderGraph,
+};
+
+#[derive(Clone, Copy, Debug)]
+pub enum CapturedResourceDesc {
+    Image(ImageDesc),
+    Buffer(BufferDesc),
+}
+
+pub struct CapturedResource {
+    /// Key of the temporal resource, or the name of the last pass writing to it.
+    pub name: String,
+    /// Index of the resource in the graph; unique within the frame.
+    pub id: u32,
+    pub desc: CapturedResourceDesc,
+    /// Host-visible copy of the contents at the end of the graph. Images are tightly packed,
+    /// with only the first mip, and array layers (or depth slices) one after another.
+    pub readback: Arc<Buffer>,
+}
+
+pub struct SkippedResource {
+    pub name: String,
+    pub id: u32,
+    pub reason: String,
+}
+
+/// Result of `TemporalRenderGraph::capture_all_resources`. The copies are only valid
+/// once the frame has finished executing on the GPU.
+#[derive(Default)]
+pub struct FrameCapture {
+    pub resources: Vec<CapturedResource>,
+    pub skipped: Vec<SkippedResource>,
+}
+
+/// Bytes per texel of the uncompressed formats which frame captures can copy.
+pub fn format_texel_size(format: vk::Format) -> Option<usize> {
+    Some(match format {
+        vk::Format::R8_UNORM | vk::Format::R8_SNORM | vk::Format::R8_UINT => 1,
+        vk::Format::R8G8_UNORM
+        | vk::Format::R8G8_SNORM
+        | vk::Format::R16_SFLOAT
+        | vk::Format::R16_UNORM
+        | vk::Format::R16_UINT => 2,
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB
+        | vk::Format::A2R10G10B10_UNORM_PACK32
+        | vk::Format::A2B10G10R10_UNORM_PACK32
+        | vk::Format::B10G11R11_UFLOAT_PACK32
+        | vk::Format::E5B9G9R9_UFLOAT_PACK32
+        | vk::Format::R16G16_SFLOAT
+        | vk::Format::R16G16_UNORM
+        | vk::Format::R16G16_SNORM
+        | vk::Format::R32_SFLOAT
+        | vk::Format::R32_UINT
+        | vk::Format::R32_SINT
+        | vk::Format::D32_SFLOAT => 4,
+        vk::Format::R16G16B16A16_SFLOAT
+        | vk::Format::R16G16B16A16_UNORM
+        | vk::Format::R16G16B16A16_SNORM
+        | vk::Format::R32G32_SFLOAT
+        | vk::Format::R32G32_UINT => 8,
+        vk::Format::R32G32B32_SFLOAT | vk::Format::R32G32B32_UINT => 12,
+        vk::Format::R32G32B32A32_SFLOAT | vk::Format::R32G32B32A32_UINT => 16,
+        _ => return None,
+    })
+}
+
+enum CaptureCopy {
+    Image {
+        src: Ref<Image, GpuSrv>,
+        dst: Ref<Buffer, GpuUav>,
+    },
+    Buffer {
+        src: Ref<Buffer, GpuSrv>,
+        dst: Ref<Buffer, GpuUav>,
+    },
+}
+
+impl RenderGraph {
+    pub(crate) fn capture_all_resources_impl(
+        &mut self,
+        device: &Device,
+        temporal_names: &HashMap<u32, String>,
+    ) -> FrameCapture {
+        let mut accessed = vec![false; self.resources.len()];
+        let mut last_writer: Vec<Option<String>> = vec![None; self.resources.len()];
+
+        for pass in &self.passes {
+            for res in &pass.read {
+                accessed[res.handle.id as usize] = true;
+            }
+            for res in &pass.write {
+                accessed[res.handle.id as usize] = true;
+                last_writer[res.handle.id as usize] = Some(pass.name.clone());
+            }
+        }
+
+        let mut capture = FrameCapture::default();
+        let mut sources: Vec<(GraphRawResourceHandle, CapturedResourceDesc, String)> = Vec::new();
+
+        for (id, resource) in self.resources.iter().enumerate() {
+            let id = id as u32;
+            let name = self
+                .resource_names
+                .get(&id)
+                .or_else(|| temporal_names.get(&id))
+                .cloned()
+                .or_else(|| last_writer[id as usize].clone())
+                .unwrap_or_else(|| "unnamed".to_owned());
+
+            let desc: Result<CapturedResourceDesc, &str> = match resource {
+                GraphResourceInfo::Created(GraphResourceCreateInfo { desc }) => match desc {
+                    GraphResourceDesc::Image(desc) => Ok(CapturedResourceDesc::Image(*desc)),
+                    GraphResourceDesc::Buffer(desc) => Ok(CapturedResourceDesc::Buffer(*desc)),
+                    GraphResourceDesc::RayTracingAcceleration(_) => Err("acceleration structure"),
+                },
+
+                // Imported resources come with their own usage flags,
+                // which the graph can't extend with `TRANSFER_SRC`.
+                GraphResourceInfo::Imported(GraphResourceImportInfo::Image {
+                    resource, ..
+                }) => {
+                    if resource
+                        .desc
+                        .usage
+                        .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+                    {
+                        Ok(CapturedResourceDesc::Image(resource.desc))
+                    } else {
+                        Err("imported without TRANSFER_SRC usage")
+                    }
+                }
+                GraphResourceInfo::Imported(GraphResourceImportInfo::Buffer {
+                    resource, ..
+                }) => {
+                    if resource
+                        .desc
+                        .usage
+                        .contains(vk::BufferUsageFlags::TRANSFER_SRC)
+                    {
+                        Ok(CapturedResourceDesc::Buffer(resource.desc))
+                    } else {
+                        Err("imported without TRANSFER_SRC usage")
+                    }
+                }
+                GraphResourceInfo::Imported(GraphResourceImportInfo::RayTracingAcceleration {
+                    ..
+                }) => Err("acceleration structure"),
+                GraphResourceInfo::Imported(GraphResourceImportInfo::SwapchainImage) => {
+                    Err("swapchain image")
+                }
+            };
+
+            let desc = desc.and_then(|desc| {
+                if !accessed[id as usize] {
+                    return Err("not used by any pass");
+                }
+
+                match desc {
+                    CapturedResourceDesc::Image(desc)
+                        if format_texel_size(desc.format).is_none() =>
+                    {
+                        Err("unsupported format")
+                    }
+                    CapturedResourceDesc::Buffer(desc) if desc.size == 0 => Err("empty"),
+                    _ => Ok(desc),
+                }
+            });
+
+            match desc {
+                Ok(desc) => sources.push((GraphRawResourceHandle { id, version: 0 }, desc, name)),
+                Err(reason) => capture.skipped.push(SkippedResource {
+                    name,
+                    id,
+                    reason: reason.to_owned(),
+                }),
+            }
+        }
+
+        let mut copies = Vec::with_capacity(sources.len());
+        let mut pass_resources = Vec::with_capacity(sources.len());
+
+        for (raw, desc, name) in sources {
+            let size = match desc {
+                CapturedResourceDesc::Image(desc) => {
+                    format_texel_size(desc.format).unwrap()
+                        * desc.extent.iter().product::<u32>() as usize
+                        * desc.array_elements as usize
+                }
+                CapturedResourceDesc::Buffer(desc) => desc.size,
+            };
+
+            let readback = match device.create_buffer(
+                BufferDesc::new_gpu_to_cpu(size, vk::BufferUsageFlags::TRANSFER_DST),
+                format!("frame capture: {}", name),
+                None,
+            ) {
+                Ok(buffer) => Arc::new(buffer),
+                Err(err) => {
+                    capture.skipped.push(SkippedResource {
+                        name,
+                        id: raw.id,
+                        reason: format!("{:?}", err),
+                    });
+                    continue;
+                }
+            };
+
+            let dst = self.import(readback.clone(), AccessType::Nothing);
+            pass_resources.push((raw, desc, dst));
+
+            capture.resources.push(CapturedResource {
+                name,
+                id: raw.id,
+                desc,
+                readback,
+            });
+        }
+
+        let mut pass = self.add_pass("frame capture");
+
+        for (raw, desc, mut dst) in pass_resources {
+            let dst = pass.write(&mut dst, AccessType::TransferWrite);
+
+            copies.push(match desc {
+                CapturedResourceDesc::Image(desc) => CaptureCopy::Image {
+                    src: pass.read(
+                        &Handle {
+                            raw,
+                            desc,
+                            marker: PhantomData,
+                        },
+                        AccessType::TransferRead,
+                    ),
+                    dst,
+                },
+                CapturedResourceDesc::Buffer(desc) => CaptureCopy::Buffer {
+                    src: pass.read(
+                        &Handle {
+                            raw,
+                            desc,
+                            marker: PhantomData,
+                        },
+                        AccessType::TransferRead,
+                    ),
+                    dst,
+                },
+            });
+        }
+
+        pass.render(move |api| {
+            let raw_device = &api.device().raw;
+            let cb = api.cb;
+
+            for copy in copies {
+                match copy {
+                    CaptureCopy::Image { src, dst } => {
+                        let desc = *src.desc();
+                        let image = api.resources.image(src);
+                        let buffer = api.resources.buffer(dst);
+
+                        unsafe {
+                            raw_device.cmd_copy_image_to_buffer(
+                                cb.raw,
+                                image.raw,
+                                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                                buffer.raw,
+                                &[vk::BufferImageCopy::builder()
+                                    .image_subresource(vk::ImageSubresourceLayers {
+                                        aspect_mask: image_aspect_mask_from_format(desc.format),
+                                        mip_level: 0,
+                                        base_array_layer: 0,
+                                        layer_count: desc.array_elements,
+                                    })
+                                    .image_extent(vk::Extent3D {
+                                        width: desc.extent[0],
+                                        height: desc.extent[1],
+                                        depth: desc.extent[2],
+                                    })
+                                    .build()],
+                            );
+                        }
+                    }
+                    CaptureCopy::Buffer { src, dst } => {
+                        let size = src.desc().size;
+                        let src = api.resources.buffer(src);
+                        let dst = api.resources.buffer(dst);
+
+                        unsafe {
+                            raw_device.cmd_copy_buffer(
+                                cb.raw,
+                                src.raw,
+                                dst.raw,
+                                &[vk::BufferCopy::builder().size(size as u64).build()],
+                            );
+                        }
+                    }
+                }
+            }
+
+            Ok(())
+        });
+
+        capture
+    }
+}
@@ -3,6 +3,9 @@
 use crate::{renderer::FrameConstantsLayout, resource_registry::PendingRenderResourceInfo};
 
 use super::{
+    async_compute::{schedule_async_compute, AsyncComputeSchedule},
+    constants_tracker::PassConstantsUsage,
+    memory_tracker::RenderGraphMemoryTracker,
     pass_builder::PassBuilder,
     resource::*,
     resource_registry::{
@@ -12,11 +15,8 @@ use super::{
 };
 
 use kajiya_backend::{
-    ash::{
-        extensions::khr::Swapchain,
-        vk::{self, DebugUtilsLabelEXT},
-    },
-    dynamic_constants::DynamicConstants,
+    ash::{extensions::khr::Swapchain, vk},
+    dynamic_constants::{DynamicConstants, DynamicConstantsScopeUsage},
     pipeline_cache::{
         ComputePipelineHandle, PipelineCache, RasterPipelineHandle, RtPipelineHandle,
     },
@@ -28,17 +28,23 @@ use kajiya_backend::{
             get_access_info, image_aspect_mask_from_access_type_and_format, record_image_barrier,
             ImageBarrier,
         },
-        device::{CommandBuffer, Device, VkProfilerData},
+        device::{
+            CommandBuffer, Device, RayTracingMode, SecondaryCommandPool, VkProfilerData,
+            MAX_COMMAND_RECORDING_THREADS,
+        },
         image::ImageViewDesc,
         ray_tracing::{RayTracingAcceleration, RayTracingPipelineDesc},
-        shader::{ComputePipelineDesc, PipelineShader, PipelineShaderDesc, RasterPipelineDesc},
+        shader::{
+            ComputePipelineDesc, PipelineShader, PipelineShaderDesc, RasterPipelineDesc,
+            ShaderSource,
+        },
     },
     BackendError,
 };
 use parking_lot::Mutex;
+use rayon::prelude::*;
 use std::{
     collections::{HashMap, VecDeque},
-    ffi::CString,
     hash::Hash,
     marker::PhantomData,
     path::{Path, PathBuf},
@@ -79,7 +85,7 @@ pub(crate) enum ExportableGraphResource {
 }
 
 impl ExportableGraphResource {
-    fn raw(&self) -> GraphRawResourceHandle {
+    pub(crate) fn raw(&self) -> GraphRawResourceHandle {
         match self {
             ExportableGraphResource::Image(h) => h.raw,
             ExportableGraphResource::Buffer(h) => h.raw,
@@ -120,6 +126,19 @@ pub struct PredefinedDescriptorSet {
     pub bindings: HashMap<u32, rspirv_reflect::DescriptorInfo>,
 }
 
+/// A hit group added to ray tracing pipelines after their own first one; see
+/// `RenderGraph::predefined_hit_groups`.
+#[derive(Clone)]
+pub struct PredefinedHitGroup {
+    /// Closest-hit shader of the hit group this one specializes. Pipelines whose first
+    /// closest-hit shader is different get a copy of their own first hit group instead,
+    /// e.g. because they expect a different ray payload.
+    pub base_closest_hit: ShaderSource,
+
+    pub closest_hit: ShaderSource,
+    pub any_hit: Option<ShaderSource>,
+}
+
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct RenderDebugHook {
     pub name: String,
@@ -132,16 +151,37 @@ pub struct GraphDebugHook {
 }
 
 pub struct RenderGraph {
-    passes: Vec<RecordedPass>,
-    resources: Vec<GraphResourceInfo>,
-    exported_resources: Vec<(ExportableGraphResource, vk_sync::AccessType)>,
+    pub(crate) passes: Vec<RecordedPass>,
+    pub(crate) resources: Vec<GraphResourceInfo>,
+    pub(crate) exported_resources: Vec<(ExportableGraphResource, vk_sync::AccessType)>,
     pub(crate) compute_pipelines: Vec<RgComputePipeline>,
     pub(crate) raster_pipelines: Vec<RgRasterPipeline>,
     pub(crate) rt_pipelines: Vec<RgRtPipeline>,
     pub predefined_descriptor_set_layouts: HashMap<u32, PredefinedDescriptorSet>,
 
+    /// HLSL defines added to the shaders of every raster and ray tracing pipeline,
+    /// e.g. to select material evaluation permutations. Compute pipelines don't get them.
+    pub predefined_shader_defines: Vec<(String, String)>,
+
+    /// Hit groups inserted after the first hit group of every ray tracing pipeline which has
+    /// any, so that TLAS instances can select them with their hit group offset: offset `i + 1`
+    /// picks `predefined_hit_groups[i]`. Their shaders get the predefined defines too.
+    pub predefined_hit_groups: Vec<PredefinedHitGroup>,
+
+    /// Any-hit shader completing every hit group of ray tracing pipelines which lacks one,
+    /// predefined ones included, e.g. for alpha testing. Rays of any payload type may run it,
+    /// so it mustn't access the payload.
+    pub predefined_any_hit: Option<ShaderSource>,
+
+    /// The mode of the device, picking the pipelines of `SimpleRenderPass::new_rt_with_ray_query_fallback`
+    pub(crate) ray_tracing_mode: RayTracingMode,
+
     pub debug_hook: Option<GraphDebugHook>,
     pub debugged_resource: Option<Handle<Image>>,
+
+    pub(crate) resource_names: HashMap<u32, String>,
+    pending_debug_groups: Vec<String>,
+    open_debug_group_count: usize,
 }
 
 pub trait ImportExportToRenderGraph
@@ -309,8 +349,44 @@ impl RenderGraph {
             raster_pipelines: Vec::new(),
             rt_pipelines: Vec::new(),
             predefined_descriptor_set_layouts: HashMap::new(),
+            predefined_shader_defines: Vec::new(),
+            predefined_hit_groups: Vec::new(),
+            predefined_any_hit: None,
+            ray_tracing_mode: RayTracingMode::Pipeline,
             debug_hook: None,
             debugged_resource: None,
+            resource_names: HashMap::new(),
+            pending_debug_groups: Vec::new(),
+            open_debug_group_count: 0,
+        }
+    }
+
+    /// Names a resource in graphics debuggers and frame captures. Resources created by
+    /// the graph are otherwise named after the last pass writing to them.
+    pub fn set_name<Res: Resource>(&mut self, handle: &Handle<Res>, name: impl Into<String>) {
+        self.resource_names.insert(handle.raw.id, name.into());
+    }
+
+    /// Groups the passes added until the matching `end_debug_group` under `name`
+    /// in graphics debuggers, such as RenderDoc and Nsight. Groups can be nested.
+    pub fn begin_debug_group(&mut self, name: &str) {
+        self.pending_debug_groups.push(name.to_owned());
+        self.open_debug_group_count += 1;
+    }
+
+    pub fn end_debug_group(&mut self) {
+        assert!(
+            self.open_debug_group_count > 0,
+            "end_debug_group without a matching begin_debug_group"
+        );
+        self.open_debug_group_count -= 1;
+
+        // Groups without any passes in them are dropped.
+        if self.pending_debug_groups.pop().is_none() {
+            self.passes
+                .last_mut()
+                .expect("debug groups are only pending until a pass is added")
+                .end_debug_group_count += 1;
         }
     }
 
@@ -392,12 +468,15 @@ struct ResourceInfo {
     buffer_usage_flags: Vec<vk::BufferUsageFlags>,
 }
 
+#[derive(Clone, Copy)]
 pub struct RenderGraphExecutionParams<'a> {
     pub device: &'a Device,
-    pub pipeline_cache: &'a mut PipelineCache,
+    pub pipeline_cache: &'a PipelineCache,
     pub frame_descriptor_set: vk::DescriptorSet,
     pub frame_constants_layout: FrameConstantsLayout,
-    pub profiler_data: &'a VkProfilerData,
+
+    /// See `DynamicConstantsTracker::max_bytes_per_pass`
+    pub max_constants_bytes_per_pass: Option<usize>,
 }
 
 pub struct RenderGraphPipelines {
@@ -427,6 +506,27 @@ impl RenderGraph {
         }
     }
 
+    /// Explicit names from `set_name`; resources created by the graph fall back
+    /// to the name of the last pass writing to them.
+    pub(crate) fn resource_debug_names(&self) -> Vec<Option<String>> {
+        let mut names: Vec<Option<String>> = vec![None; self.resources.len()];
+
+        for pass in &self.passes {
+            for res in &pass.write {
+                let id = res.handle.id as usize;
+                if matches!(self.resources[id], GraphResourceInfo::Created(_)) {
+                    names[id] = Some(pass.name.clone());
+                }
+            }
+        }
+
+        for (&id, name) in &self.resource_names {
+            names[id as usize] = Some(name.clone());
+        }
+
+        names
+    }
+
     fn calculate_resource_info(&self) -> ResourceInfo {
         let mut lifetimes: Vec<ResourceLifetime> = self
             .resources
@@ -547,7 +647,18 @@ impl RenderGraph {
         }
     }
 
-    pub fn compile(self, pipeline_cache: &mut PipelineCache) -> CompiledRenderGraph {
+    pub fn compile(mut self, pipeline_cache: &mut PipelineCache) -> CompiledRenderGraph {
+        if self.open_debug_group_count > 0 {
+            log::warn!(
+                "{} debug group(s) left open at the end of the graph",
+                self.open_debug_group_count
+            );
+
+            while self.open_debug_group_count > 0 {
+                self.end_debug_group();
+            }
+        }
+
         let resource_info = self.calculate_resource_info();
         // TODO: alias resources
 
@@ -589,7 +700,9 @@ impl RenderGraph {
         }
     }
 
-    pub(crate) fn record_pass(&mut self, pass: RecordedPass) {
+    pub(crate) fn record_pass(&mut self, mut pass: RecordedPass) {
+        pass.begin_debug_groups = std::mem::take(&mut self.pending_debug_groups);
+
         let debug_pass = self.hook_debug_pass(&pass);
         self.passes.push(pass);
 
@@ -709,7 +822,9 @@ impl CompiledRenderGraph {
     pub fn begin_execute<'exec_params, 'constants>(
         self,
         params: RenderGraphExecutionParams<'exec_params>,
+        profiler_data: &'exec_params VkProfilerData,
         transient_resource_cache: &mut TransientResourceCache,
+        memory_tracker: &mut RenderGraphMemoryTracker,
         dynamic_constants: &'constants mut DynamicConstants,
     ) -> ExecutingRenderGraph<'exec_params, 'constants> {
         let device = params.device;
@@ -785,31 +900,102 @@ impl CompiledRenderGraph {
             })
             .collect();
 
-        let resource_registry = ResourceRegistry {
+        // Transient resources are recycled between frames, so they're renamed every time.
+        if device.debug_utils().is_some() {
+            let names = self.rg.resource_debug_names();
+            for (resource, name) in resources.iter().zip(names) {
+                let name = if let Some(name) = name {
+                    name
+                } else {
+                    continue;
+                };
+
+                match &resource.resource {
+                    AnyRenderResource::OwnedImage(image) => {
+                        device.set_object_name(image.raw, &name)
+                    }
+                    AnyRenderResource::ImportedImage(image) => {
+                        device.set_object_name(image.raw, &name)
+                    }
+                    AnyRenderResource::OwnedBuffer(buffer) => {
+                        device.set_object_name(buffer.raw, &name)
+                    }
+                    AnyRenderResource::ImportedBuffer(buffer) => {
+                        device.set_object_name(buffer.raw, &name)
+                    }
+                    AnyRenderResource::ImportedRayTracingAcceleration(_)
+                    | AnyRenderResource::Pending(_) => {}
+                }
+            }
+        }
+
+        if memory_tracker.leak_frame_threshold > 0 {
+            memory_tracker.record_graph(&self.rg.passes, &resources);
+        }
+
+        ExecutingRenderGraph {
             execution_params: params,
-            resources,
+            profiler_data,
+            registry_resources: resources,
             dynamic_constants,
             pipelines: self.pipelines,
-        };
-
-        ExecutingRenderGraph {
-            resource_registry,
+            constants_usage: Vec::new(),
             passes: self.rg.passes.into(),
             resources: self.rg.resources,
             exported_resources: self.rg.exported_resources,
+            async_compute: None,
         }
     }
 }
 
+// A pass recorded on a worker thread, and the dynamic constants it used
+type SecondaryPassCb = (vk::CommandBuffer, DynamicConstantsScopeUsage);
+
+const PASS_LABEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
+const DEBUG_GROUP_LABEL_COLOR: [f32; 4] = [0.3, 0.6, 1.0, 1.0];
+
+/// Parallel-capable passes per recording thread, at least. With fewer, the overhead
+/// of dispatching to threads and stitching command buffers would eat into the gains.
+const MIN_PARALLEL_PASSES_PER_THREAD: usize = 8;
+
+lazy_static::lazy_static! {
+    // Persistent, so that recording doesn't spawn threads every frame.
+    static ref RECORDING_THREAD_POOL: rayon::ThreadPool = rayon::ThreadPoolBuilder::new()
+        .num_threads(MAX_COMMAND_RECORDING_THREADS)
+        .thread_name(|idx| format!("rg recording {}", idx))
+        .build()
+        .expect("render graph recording thread pool");
+}
+
 pub struct ExecutingRenderGraph<'exec_params, 'constants> {
     passes: VecDeque<RecordedPass>,
     resources: Vec<GraphResourceInfo>,
     exported_resources: Vec<(ExportableGraphResource, vk_sync::AccessType)>,
-    resource_registry: ResourceRegistry<'exec_params, 'constants>,
+    execution_params: RenderGraphExecutionParams<'exec_params>,
+    profiler_data: &'exec_params VkProfilerData,
+    registry_resources: Vec<RegistryResource>,
+    dynamic_constants: &'constants mut DynamicConstants,
+    pipelines: RenderGraphPipelines,
+
+    // In the order the passes were recorded
+    constants_usage: Vec<PassConstantsUsage>,
+
+    // Set by `record_main_cb` if any passes were scheduled on the async compute queue
+    async_compute: Option<AsyncComputeSchedule>,
 }
 
 impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
-    pub fn record_main_cb(&mut self, cb: &CommandBuffer) {
+    /// Records all passes up to the first one which writes to the swapchain image, or if any
+    /// are scheduled on the async compute queue, those which they depend on; see `has_async_compute`.
+    ///
+    /// Passes registered via `PassBuilder::render_parallel` are recorded into secondary
+    /// command buffers on up to `secondary_command_pools.len()` threads, and executed from `cb`
+    /// in graph order. Barriers are still recorded into `cb` on the calling thread.
+    pub fn record_main_cb(
+        &mut self,
+        cb: &CommandBuffer,
+        secondary_command_pools: &[Mutex<SecondaryCommandPool>],
+    ) {
         let mut first_presentation_pass: usize = self.passes.len();
 
         for (pass_idx, pass) in self.passes.iter().enumerate() {
@@ -842,11 +1028,10 @@ impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
                 }
             }
 
-            let params = &self.resource_registry.execution_params;
             for (resource_idx, access) in resource_first_access_states {
-                let resource = &mut self.resource_registry.resources[resource_idx as usize];
+                let resource = &mut self.registry_resources[resource_idx as usize];
                 Self::transition_resource(
-                    params.device,
+                    self.execution_params.device,
                     cb,
                     resource,
                     PassResourceAccessType {
@@ -862,11 +1047,99 @@ impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
             }
         }
 
-        for pass in passes.drain(..first_presentation_pass) {
-            Self::record_pass_cb(pass, &mut self.resource_registry, cb);
+        let mut presentation_passes = passes.split_off(first_presentation_pass);
+
+        if self.async_compute_enabled() {
+            passes = match schedule_async_compute(passes, &mut presentation_passes) {
+                Ok(mut schedule) => {
+                    let before = std::mem::take(&mut schedule.before);
+                    self.async_compute = Some(schedule);
+                    before
+                }
+                Err(passes) => passes,
+            };
+        }
+
+        self.record_passes(passes, cb, secondary_command_pools);
+
+        self.passes = presentation_passes.into();
+    }
+
+    /// Whether `record_main_cb` scheduled passes on `Device::async_compute_queue`. If so,
+    /// the caller must then record and submit, in order:
+    /// * `record_async_compute_cb` on the async compute queue, waiting for the main command buffer,
+    /// * `record_async_overlap_cb` on the universal queue,
+    /// * `record_async_join_cb` on the universal queue, waiting for the async compute command buffer.
+    pub fn has_async_compute(&self) -> bool {
+        self.async_compute.is_some()
+    }
+
+    /// Records the passes scheduled on the async compute queue.
+    pub fn record_async_compute_cb(&mut self, cb: &CommandBuffer) {
+        let passes = std::mem::take(
+            &mut self
+                .async_compute
+                .as_mut()
+                .expect("No passes scheduled on the async compute queue")
+                .async_passes,
+        );
+
+        for pass in passes {
+            self.record_pass_cb(pass, None, cb);
         }
+    }
+
+    /// Records the universal queue passes which run alongside the async compute ones.
+    pub fn record_async_overlap_cb(
+        &mut self,
+        cb: &CommandBuffer,
+        secondary_command_pools: &[Mutex<SecondaryCommandPool>],
+    ) {
+        let passes = std::mem::take(
+            &mut self
+                .async_compute
+                .as_mut()
+                .expect("No passes scheduled on the async compute queue")
+                .overlap,
+        );
+
+        self.record_passes(passes, cb, secondary_command_pools);
+    }
+
+    /// Records the universal queue passes which depend on the async compute ones,
+    /// up to the first one which writes to the swapchain image.
+    pub fn record_async_join_cb(
+        &mut self,
+        cb: &CommandBuffer,
+        secondary_command_pools: &[Mutex<SecondaryCommandPool>],
+    ) {
+        let schedule = self
+            .async_compute
+            .take()
+            .expect("No passes scheduled on the async compute queue");
+
+        self.record_passes(schedule.after, cb, secondary_command_pools);
+    }
+
+    fn async_compute_enabled(&self) -> bool {
+        unsafe { RG_ALLOW_ASYNC_COMPUTE }
+        &&self.execution_params.device.async_compute_queue.is_some()
+    }
+
+    fn record_passes(
+        &mut self,
+        mut passes: Vec<RecordedPass>,
+        cb: &CommandBuffer,
+        secondary_command_pools: &[Mutex<SecondaryCommandPool>],
+    ) {
+        let secondary_cbs = {
+            puffin::profile_scope!("record parallel passes");
+            self.record_parallel_passes(&mut passes, secondary_command_pools)
+        };
 
-        self.passes = passes.into();
+        for (pass, secondary_cb) in passes.into_iter().zip(secondary_cbs) {
+            self.record_pass_cb(pass, secondary_cb, cb);
+        }
     }
 
     #[must_use]
@@ -875,15 +1148,12 @@ impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
         cb: &CommandBuffer,
         swapchain_image: Arc<Image>,
     ) -> RetiredRenderGraph {
-        let params = &self.resource_registry.execution_params;
-
         // Transition exported images to the requested access types
-        for (resource_idx, access_type) in self.exported_resources {
+        for (resource_idx, access_type) in std::mem::take(&mut self.exported_resources) {
             if access_type != vk_sync::AccessType::Nothing {
-                let resource =
-                    &mut self.resource_registry.resources[resource_idx.raw().id as usize];
+                let resource = &mut self.registry_resources[resource_idx.raw().id as usize];
                 Self::transition_resource(
-                    params.device,
+                    self.execution_params.device,
                     cb,
                     resource,
                     PassResourceAccessType {
@@ -896,7 +1166,7 @@ impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
             }
         }
 
-        for res in &mut self.resource_registry.resources {
+        for res in &mut self.registry_resources {
             if let AnyRenderResource::Pending(pending) = &mut res.resource {
                 match pending.resource {
                     GraphResourceInfo::Imported(GraphResourceImportInfo::SwapchainImage) => {
@@ -907,46 +1177,165 @@ impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
             }
         }
 
-        let passes = self.passes;
+        // Presentation passes are few, and touch the swapchain, so they're always recorded inline.
+        let passes = std::mem::take(&mut self.passes);
         for pass in passes {
-            Self::record_pass_cb(pass, &mut self.resource_registry, cb);
+            self.record_pass_cb(pass, None, cb);
         }
 
         RetiredRenderGraph {
-            resources: self.resource_registry.resources,
+            resources: self.registry_resources,
+            constants_usage: self.constants_usage,
         }
     }
 
+    /// Records the render functions of passes which allow it into secondary command buffers,
+    /// spread across threads. Returns a command buffer for each entry in `passes`,
+    /// along with the dynamic constants it used, or `None` for those which must be recorded inline.
+    fn record_parallel_passes(
+        &mut self,
+        passes: &mut [RecordedPass],
+        secondary_command_pools: &[Mutex<SecondaryCommandPool>],
+    ) -> Vec<Option<SecondaryPassCb>> {
+        let mut secondary_cbs: Vec<Option<SecondaryPassCb>> =
+            (0..passes.len()).map(|_| None).collect();
+
+        let parallel_pass_count = passes
+            .iter()
+            .filter(|pass| matches!(pass.render_fn, Some(PassRenderFn::Parallel(_))))
+            .count();
+
+        let thread_count = secondary_command_pools
+            .len()
+            .min(parallel_pass_count / MIN_PARALLEL_PASSES_PER_THREAD);
+
+        if thread_count < 2 {
+            return secondary_cbs;
+        }
+
+        let mut jobs: Vec<(usize, String, Box<DynParallelRenderFn>)> =
+            Vec::with_capacity(parallel_pass_count);
+        for (pass_idx, pass) in passes.iter_mut().enumerate() {
+            if matches!(pass.render_fn, Some(PassRenderFn::Parallel(_))) {
+                if let Some(PassRenderFn::Parallel(render_fn)) = pass.render_fn.take() {
+                    jobs.push((pass_idx, pass.name.clone(), render_fn));
+                }
+            }
+        }
+
+        // Contiguous runs of passes per thread, so that each thread's constants stay together.
+        let jobs_per_thread = (jobs.len() + thread_count - 1) / thread_count;
+        let mut jobs = jobs.into_iter();
+        let thread_jobs: Vec<Vec<_>> = (0..thread_count)
+            .map(|_| jobs.by_ref().take(jobs_per_thread).collect())
+            .collect();
+
+        // Each thread gets a private range of dynamic constants. What's left is split evenly
+        // between the threads, and the passes recorded inline.
+        let dynamic_constants_per_thread =
+            self.dynamic_constants.remaining_bytes() / (thread_count + 1);
+        let thread_dynamic_constants: Vec<DynamicConstants> = (0..thread_count)
+            .map(|_| self.dynamic_constants.fork(dynamic_constants_per_thread))
+            .collect();
+
+        let execution_params = self.execution_params;
+        let registry_resources = self.registry_resources.as_slice();
+        let pipelines = &self.pipelines;
+
+        let recorded: Vec<Vec<(usize, SecondaryPassCb)>> = RECORDING_THREAD_POOL.install(|| {
+            thread_jobs
+                .into_iter()
+                .zip(thread_dynamic_constants)
+                .zip(secondary_command_pools)
+                .collect::<Vec<_>>()
+                .into_par_iter()
+                .map(|((jobs, mut dynamic_constants), command_pool)| {
+                    let device = execution_params.device;
+                    let mut command_pool = command_pool.lock();
+
+                    jobs.into_iter()
+                        .map(|(pass_idx, pass_name, render_fn)| {
+                            let cb = CommandBuffer {
+                                raw: command_pool.allocate(&device.raw),
+                            };
+
+                            // Passes recorded this way never run inside a render pass.
+                            let inheritance_info = vk::CommandBufferInheritanceInfo::default();
+
+                            unsafe {
+                                device
+                                    .raw
+                                    .begin_command_buffer(
+                                        cb.raw,
+                                        &vk::CommandBufferBeginInfo::builder()
+                                            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT)
+                                            .inheritance_info(&inheritance_info),
+                                    )
+                                    .unwrap();
+                            }
+
+                            dynamic_constants.begin_scope(
+                                &pass_name,
+                                execution_params.max_constants_bytes_per_pass,
+                            );
+
+                            let mut resource_registry = ResourceRegistry {
+                                execution_params,
+                                resources: registry_resources,
+                                dynamic_constants: &mut dynamic_constants,
+                                pipelines,
+                            };
+
+                            let mut api = RenderPassApi {
+                                cb: &cb,
+                                resources: &mut resource_registry,
+                            };
+
+                            if let Err(err) = render_fn(&mut api) {
+                                panic!("Pass {:?} failed to render: {:#}", pass_name, err);
+                            }
+
+                            unsafe {
+                                device.raw.end_command_buffer(cb.raw).unwrap();
+                            }
+
+                            (pass_idx, (cb.raw, dynamic_constants.end_scope()))
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        });
+
+        for (pass_idx, cb) in recorded.into_iter().flatten() {
+            secondary_cbs[pass_idx] = Some(cb);
+        }
+
+        secondary_cbs
+    }
+
     fn record_pass_cb(
+        &mut self,
         pass: RecordedPass,
-        resource_registry: &mut ResourceRegistry,
+        secondary_cb: Option<SecondaryPassCb>,
         cb: &CommandBuffer,
     ) {
-        let params = &resource_registry.execution_params;
+        let device = self.execution_params.device;
 
         // Record a crash marker just before this pass
-        params
-            .device
-            .record_crash_marker(cb, format!("begin render pass {:?}", pass.name));
+        device.record_crash_marker(cb, format!("begin render pass {:?}", pass.name));
 
-        if let Some(debug_utils) = params.device.debug_utils() {
-            unsafe {
-                let label: CString = CString::new(pass.name.as_str()).unwrap();
-                let label = DebugUtilsLabelEXT::builder().label_name(&label).build();
-                debug_utils.cmd_begin_debug_utils_label(cb.raw, &label);
-            }
+        for group in &pass.begin_debug_groups {
+            device.begin_debug_label(cb.raw, group, DEBUG_GROUP_LABEL_COLOR);
         }
+        device.begin_debug_label(cb.raw, &pass.name, PASS_LABEL_COLOR);
 
         let vk_scope = {
             let query_id = kajiya_backend::gpu_profiler::profiler().create_scope(&pass.name);
-            params
-                .profiler_data
-                .begin_scope(&params.device.raw, cb.raw, query_id)
+            self.profiler_data
+                .begin_scope(&device.raw, cb.raw, query_id)
         };
 
         {
-            let params = &resource_registry.execution_params;
-
             let mut transitions: Vec<(usize, PassResourceAccessType)> = Vec::new();
             for resource_ref in pass.read.iter() {
                 transitions.push((
@@ -967,47 +1356,70 @@ impl<'exec_params, 'constants> ExecutingRenderGraph<'exec_params, 'constants> {
             // TODO: optimize the barriers
 
             for (resource_idx, access) in transitions {
-                let resource = &mut resource_registry.resources[resource_idx];
+                let resource = &mut self.registry_resources[resource_idx];
 
                 Self::transition_resource(
-                    params.device,
-                    cb,
-                    resource,
-                    access,
-                    //pass.name == "raster simple",
-                    false,
-                    "",
+                    device, cb, resource, access, //pass.name == "raster simple",
+                    false, "",
                 );
             }
         }
 
-        let mut api = RenderPassApi {
-            cb,
-            resources: resource_registry,
-        };
+        let constants_usage = if let Some((secondary_cb, constants_usage)) = secondary_cb {
+            unsafe {
+                device
+                    .raw
+                    .cmd_execute_commands(cb.raw, std::slice::from_ref(&secondary_cb));
+            }
+
+            constants_usage
+        } else if let Some(render_fn) = pass.render_fn {
+            self.dynamic_constants.begin_scope(
+                &pass.name,
+                self.execution_params.max_constants_bytes_per_pass,
+            );
 
-        if let Some(render_fn) = pass.render_fn {
-            if let Err(err) = render_fn(&mut api) {
+            let mut resource_registry = ResourceRegistry {
+                execution_params: self.execution_params,
+                resources: &self.registry_resources,
+                dynamic_constants: &mut *self.dynamic_constants,
+                pipelines: &self.pipelines,
+            };
+
+            let mut api = RenderPassApi {
+                cb,
+                resources: &mut resource_registry,
+            };
+
+            let result = match render_fn {
+                PassRenderFn::MainThread(render_fn) => render_fn(&mut api),
+                PassRenderFn::Parallel(render_fn) => render_fn(&mut api),
+            };
+
+            if let Err(err) = result {
                 panic!("Pass {:?} failed to render: {:#}", pass.name, err);
             }
-        }
 
-        let params = &resource_registry.execution_params;
+            self.dynamic_constants.end_scope()
+        } else {
+            Default::default()
+        };
+
+        self.constants_usage.push(PassConstantsUsage {
+            name: pass.name.clone(),
+            bytes: constants_usage.bytes,
+            call_sites: constants_usage.call_sites,
+        });
 
-        params
-            .profiler_data
-            .end_scope(&params.device.raw, cb.raw, vk_scope);
+        self.profiler_data.end_scope(&device.raw, cb.raw, vk_scope);
 
-        if let Some(debug_utils) = params.device.debug_utils() {
-            unsafe {
-                debug_utils.cmd_end_debug_utils_label(cb.raw);
-            }
+        device.end_debug_label(cb.raw);
+        for _ in 0..pass.end_debug_group_count {
+            device.end_debug_label(cb.raw);
         }
 
         // Record a crash marker just after this pass
-        params
-            .device
-            .record_crash_marker(cb, format!("end render pass {:?}", pass.name));
+        device.record_crash_marker(cb, format!("end render pass {:?}", pass.name));
     }
 
     fn transition_resource(
@@ -1127,6 +1539,7 @@ fn global_barrier(
 
 pub struct RetiredRenderGraph {
     resources: Vec<RegistryResource>,
+    pub(crate) constants_usage: Vec<PassConstantsUsage>,
 }
 
 impl RetiredRenderGraph {
@@ -1160,6 +1573,15 @@ impl RetiredRenderGraph {
 }
 
 type DynRenderFn = dyn FnOnce(&mut RenderPassApi) -> Result<(), BackendError>;
+type DynParallelRenderFn = dyn FnOnce(&mut RenderPassApi) -> Result<(), BackendError> + Send;
+
+pub(crate) enum PassRenderFn {
+    /// Recorded on the thread executing the graph, straight into the primary command buffer.
+    MainThread(Box<DynRenderFn>),
+
+    /// May be recorded on a worker thread, into a secondary command buffer.
+    Parallel(Box<DynParallelRenderFn>),
+}
 
 #[derive(Copy, Clone)]
 pub enum PassResourceAccessSyncType {
@@ -1170,8 +1592,8 @@ pub enum PassResourceAccessSyncType {
 #[derive(Copy, Clone)]
 pub struct PassResourceAccessType {
     // TODO: multiple
-    access_type: vk_sync::AccessType,
-    sync_type: PassResourceAccessSyncType,
+    pub(crate) access_type: vk_sync::AccessType,
+    pub(crate) sync_type: PassResourceAccessSyncType,
 }
 
 impl PassResourceAccessType {
@@ -1191,9 +1613,16 @@ pub(crate) struct PassResourceRef {
 pub(crate) struct RecordedPass {
     pub read: Vec<PassResourceRef>,
     pub write: Vec<PassResourceRef>,
-    pub render_fn: Option<Box<DynRenderFn>>,
+    pub render_fn: Option<PassRenderFn>,
     pub name: String,
     pub idx: usize,
+
+    // See `PassBuilder::async_compute`
+    pub async_compute: bool,
+
+    // Opened just before this pass, and closed just after it; see `begin_debug_group`.
+    pub begin_debug_groups: Vec<String>,
+    pub end_debug_group_count: usize,
 }
 
 impl RecordedPass {
@@ -1204,8 +1633,14 @@ impl RecordedPass {
             render_fn: Default::default(),
             name: name.to_owned(),
             idx,
+            async_compute: false,
+            begin_debug_groups: Vec::new(),
+            end_debug_group_count: 0,
         }
     }
 }
 
 pub static mut RG_ALLOW_PASS_OVERLAP: bool = true;
+
+/// Whether passes marked with `PassBuilder::async_compute` run on the async compute queue.
+pub static mut RG_ALLOW_ASYNC_COMPUTE: bool = true;
@@ -0,0 +1,466 @@
+use std::{collections::HashMap, fmt::Write as _};
+
+use kajiya_backend::vk_sync::AccessType;
+
+use crate::{
+    graph::{
+        ExportableGraphResource, GraphResourceCreateInfo, GraphResourceImportInfo,
+        GraphResourceInfo,
+    },
+    GraphResourceDesc, PassResourceAccessSyncType, RenderGraph, RG_ALLOW_PASS_OVERLAP,
+};
+
+pub struct PassAccessDescription {
+    pub resource: u32,
+    pub access_type: AccessType,
+}
+
+pub struct PassDescription {
+    pub name: String,
+    pub reads: Vec<PassAccessDescription>,
+    pub writes: Vec<PassAccessDescription>,
+
+    /// Debug groups enclosing the pass, outermost first
+    pub debug_groups: Vec<String>,
+
+    /// See `PassBuilder::async_compute`
+    pub async_compute: bool,
+}
+
+pub struct ResourceDescription {
+    /// Same as the index into `GraphDescription::resources`
+    pub id: u32,
+
+    /// Explicit name, temporal resource key, or the last pass writing to it
+    pub name: String,
+
+    /// "image", "buffer", "acceleration structure" or "swapchain image"
+    pub kind: &'static str,
+
+    /// Format, extent and such, if known
+    pub desc: String,
+
+    pub imported: bool,
+
+    /// Exported out of the graph, e.g. as a temporal resource; it stays alive after the last pass.
+    pub exported: bool,
+
+    /// Indices of the first and last passes accessing the resource; `None` if no pass does.
+    pub lifetime: Option<(usize, usize)>,
+}
+
+/// A pipeline barrier the graph issues before a pass, when an access of a resource
+/// differs from the previous one.
+pub struct BarrierDescription {
+    pub resource: u32,
+
+    /// The pass the barrier is issued before
+    pub pass: usize,
+
+    /// The previous pass accessing the resource; `None` for its initial state,
+    /// e.g. the access type an imported resource came with.
+    pub previous_pass: Option<usize>,
+
+    pub src_access: AccessType,
+    pub dst_access: AccessType,
+}
+
+/// Structure of a frame's render graph, for inspecting pass scheduling and resource lifetimes;
+/// see `TemporalRenderGraph::describe_graph`. Write it out with `to_dot` or `to_json`.
+///
+/// Resources aren't aliased yet, so each one gets its own memory for the whole frame;
+/// the lifetimes show how much aliasing could save.
+pub struct GraphDescription {
+    pub passes: Vec<PassDescription>,
+    pub resources: Vec<ResourceDescription>,
+    pub barriers: Vec<BarrierDescription>,
+}
+
+impl RenderGraph {
+    pub(crate) fn describe_graph_impl(
+        &self,
+        temporal_names: &HashMap<u32, String>,
+    ) -> GraphDescription {
+        let debug_names = self.resource_debug_names();
+
+        let mut open_debug_groups: Vec<String> = Vec::new();
+        let passes = self
+            .passes
+            .iter()
+            .map(|pass| {
+                open_debug_groups.extend(pass.begin_debug_groups.iter().cloned());
+                let debug_groups = open_debug_groups.clone();
+                for _ in 0..pass.end_debug_group_count {
+                    open_debug_groups.pop();
+                }
+
+                PassDescription {
+                    name: pass.name.clone(),
+                    reads: pass
+                        .read
+                        .iter()
+                        .map(|res| PassAccessDescription {
+                            resource: res.handle.id,
+                            access_type: res.access.access_type,
+                        })
+                        .collect(),
+                    writes: pass
+                        .write
+                        .iter()
+                        .map(|res| PassAccessDescription {
+                            resource: res.handle.id,
+                            access_type: res.access.access_type,
+                        })
+                        .collect(),
+                    debug_groups,
+                    async_compute: pass.async_compute,
+                }
+            })
+            .collect();
+
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; self.resources.len()];
+        for (pass_idx, pass) in self.passes.iter().enumerate() {
+            for res in pass.read.iter().chain(pass.write.iter()) {
+                let lifetime = &mut lifetimes[res.handle.id as usize];
+                *lifetime = Some((lifetime.map_or(pass_idx, |(first, _)| first), pass_idx));
+            }
+        }
+
+        let mut exported = vec![false; self.resources.len()];
+        for (res, _) in &self.exported_resources {
+            let id = match res {
+                ExportableGraphResource::Image(handle) => handle.raw.id,
+                ExportableGraphResource::Buffer(handle) => handle.raw.id,
+            };
+            exported[id as usize] = true;
+        }
+
+        let resources = self
+            .resources
+            .iter()
+            .enumerate()
+            .map(|(id, resource)| {
+                let (kind, desc) = describe_resource(resource);
+
+                ResourceDescription {
+                    id: id as u32,
+                    name: temporal_names
+                        .get(&(id as u32))
+                        .cloned()
+                        .or_else(|| debug_names[id].clone())
+                        .unwrap_or_else(|| "unnamed".to_owned()),
+                    kind,
+                    desc,
+                    imported: matches!(resource, GraphResourceInfo::Imported(_)),
+                    exported: exported[id],
+                    lifetime: lifetimes[id],
+                }
+            })
+            .collect();
+
+        GraphDescription {
+            passes,
+            resources,
+            barriers: self.describe_barriers(),
+        }
+    }
+
+    // Replays the accesses the way `transition_resource` does at execution time.
+    fn describe_barriers(&self) -> Vec<BarrierDescription> {
+        let mut state: Vec<(AccessType, Option<usize>)> = self
+            .resources
+            .iter()
+            .map(|res| {
+                let access_type = match res {
+                    GraphResourceInfo::Created(_) => AccessType::Nothing,
+                    GraphResourceInfo::Imported(GraphResourceImportInfo::Image {
+                        access_type,
+                        ..
+                    })
+                    | GraphResourceInfo::Imported(GraphResourceImportInfo::Buffer {
+                        access_type,
+                        ..
+                    })
+                    | GraphResourceInfo::Imported(
+                        GraphResourceImportInfo::RayTracingAcceleration { access_type, .. },
+                    ) => *access_type,
+                    GraphResourceInfo::Imported(GraphResourceImportInfo::SwapchainImage) => {
+                        AccessType::ComputeShaderWrite
+                    }
+                };
+                (access_type, None)
+            })
+            .collect();
+
+        let allow_pass_overlap = unsafe { RG_ALLOW_PASS_OVERLAP };
+        let mut barriers = Vec::new();
+
+        for (pass_idx, pass) in self.passes.iter().enumerate() {
+            for res in pass.read.iter().chain(pass.write.iter()) {
+                let (prev_access, prev_pass) = &mut state[res.handle.id as usize];
+
+                let skip = allow_pass_overlap
+                    && *prev_access == res.access.access_type
+                    && matches!(
+                        res.access.sync_type,
+                        PassResourceAccessSyncType::SkipSyncIfSameAccessType
+                    );
+
+                if !skip {
+                    barriers.push(BarrierDescription {
+                        resource: res.handle.id,
+                        pass: pass_idx,
+                        previous_pass: *prev_pass,
+                        src_access: *prev_access,
+                        dst_access: res.access.access_type,
+                    });
+                }
+
+                *prev_access = res.access.access_type;
+                *prev_pass = Some(pass_idx);
+            }
+        }
+
+        barriers
+    }
+}
+
+fn describe_resource(resource: &GraphResourceInfo) -> (&'static str, String) {
+    let image_desc = |desc: &kajiya_backend::vulkan::image::ImageDesc| {
+        format!(
+            "{:?} {}x{}x{}, {} mips, {} layers",
+            desc.format,
+            desc.extent[0],
+            desc.extent[1],
+            desc.extent[2],
+            desc.mip_levels,
+            desc.array_elements
+        )
+    };
+
+    match resource {
+        GraphResourceInfo::Created(GraphResourceCreateInfo { desc }) => match desc {
+            GraphResourceDesc::Image(desc) => ("image", image_desc(desc)),
+            GraphResourceDesc::Buffer(desc) => ("buffer", format!("{} bytes", desc.size)),
+            GraphResourceDesc::RayTracingAcceleration(_) => {
+                ("acceleration structure", String::new())
+            }
+        },
+        GraphResourceInfo::Imported(GraphResourceImportInfo::Image { resource, .. }) => {
+            ("image", image_desc(&resource.desc))
+        }
+        GraphResourceInfo::Imported(GraphResourceImportInfo::Buffer { resource, .. }) => {
+            ("buffer", format!("{} bytes", resource.desc.size))
+        }
+        GraphResourceInfo::Imported(GraphResourceImportInfo::RayTracingAcceleration { .. }) => {
+            ("acceleration structure", String::new())
+        }
+        GraphResourceInfo::Imported(GraphResourceImportInfo::SwapchainImage) => {
+            ("swapchain image", String::new())
+        }
+    }
+}
+
+impl GraphDescription {
+    /// Graphviz graph with passes as boxes, in execution order, and resources as ellipses
+    /// connected to the passes reading and writing them; async compute passes are orange.
+    /// Barriers are drawn as dashed red edges between passes. Render with e.g. `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        dot.push_str("digraph render_graph {\n  rankdir=TB;\n  node [fontname=\"monospace\"];\n");
+
+        for (idx, pass) in self.passes.iter().enumerate() {
+            writeln!(
+                dot,
+                "  p{} [shape=box, style=filled, fillcolor=\"{}\", label={}];",
+                idx,
+                if pass.async_compute {
+                    "#f8e0c8"
+                } else {
+                    "#dde8f8"
+                },
+                dot_string(&format!("#{} {}", idx, pass.name))
+            )
+            .unwrap();
+        }
+
+        // Keeps the passes in execution order, top to bottom.
+        for idx in 1..self.passes.len() {
+            writeln!(dot, "  p{} -> p{} [style=invis, weight=100];", idx - 1, idx).unwrap();
+        }
+
+        for res in &self.resources {
+            if res.lifetime.is_none() {
+                continue;
+            }
+
+            let mut label = format!("{} ({})", res.name, res.kind);
+            if !res.desc.is_empty() {
+                write!(label, "\n{}", res.desc).unwrap();
+            }
+            if let Some((first, last)) = res.lifetime {
+                write!(label, "\npasses {}..={}", first, last).unwrap();
+            }
+            if res.exported {
+                label.push_str(", exported");
+            }
+
+            writeln!(
+                dot,
+                "  r{} [shape=ellipse{}, label={}];",
+                res.id,
+                if res.imported { ", style=dashed" } else { "" },
+                dot_string(&label)
+            )
+            .unwrap();
+        }
+
+        for (idx, pass) in self.passes.iter().enumerate() {
+            for access in &pass.reads {
+                writeln!(
+                    dot,
+                    "  r{} -> p{} [color=\"#4060a0\"];",
+                    access.resource, idx
+                )
+                .unwrap();
+            }
+            for access in &pass.writes {
+                writeln!(
+                    dot,
+                    "  p{} -> r{} [color=\"#a04040\"];",
+                    idx, access.resource
+                )
+                .unwrap();
+            }
+        }
+
+        for barrier in &self.barriers {
+            if let Some(prev) = barrier.previous_pass {
+                writeln!(
+                    dot,
+                    "  p{} -> p{} [style=dashed, color=red, constraint=false, label={}];",
+                    prev,
+                    barrier.pass,
+                    dot_string(&format!(
+                        "{}: {:?} -> {:?}",
+                        self.resources[barrier.resource as usize].name,
+                        barrier.src_access,
+                        barrier.dst_access
+                    ))
+                )
+                .unwrap();
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// `passes`, `resources` and `barriers` arrays mirroring the structs,
+    /// with access types spelled as in `vk_sync::AccessType`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        json.push_str("{\n  \"passes\": [");
+
+        for (idx, pass) in self.passes.iter().enumerate() {
+            json.push_str(if idx == 0 { "\n    " } else { ",\n    " });
+            write!(
+                json,
+                "{{ \"index\": {}, \"name\": {}, \"async_compute\": {}, \"debug_groups\": [{}], \"reads\": [{}], \"writes\": [{}] }}",
+                idx,
+                json_string(&pass.name),
+                pass.async_compute,
+                pass.debug_groups
+                    .iter()
+                    .map(|group| json_string(group))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                json_accesses(&pass.reads),
+                json_accesses(&pass.writes),
+            )
+            .unwrap();
+        }
+
+        json.push_str("\n  ],\n  \"resources\": [");
+
+        for (idx, res) in self.resources.iter().enumerate() {
+            json.push_str(if idx == 0 { "\n    " } else { ",\n    " });
+            let (first_pass, last_pass) = match res.lifetime {
+                Some((first, last)) => (first.to_string(), last.to_string()),
+                None => ("null".to_owned(), "null".to_owned()),
+            };
+            write!(
+                json,
+                "{{ \"id\": {}, \"name\": {}, \"kind\": {}, \"desc\": {}, \"imported\": {}, \"exported\": {}, \"first_pass\": {}, \"last_pass\": {} }}",
+                res.id,
+                json_string(&res.name),
+                json_string(res.kind),
+                json_string(&res.desc),
+                res.imported,
+                res.exported,
+                first_pass,
+                last_pass,
+            )
+            .unwrap();
+        }
+
+        json.push_str("\n  ],\n  \"barriers\": [");
+
+        for (idx, barrier) in self.barriers.iter().enumerate() {
+            json.push_str(if idx == 0 { "\n    " } else { ",\n    " });
+            write!(
+                json,
+                "{{ \"resource\": {}, \"pass\": {}, \"previous_pass\": {}, \"src_access\": \"{:?}\", \"dst_access\": \"{:?}\" }}",
+                barrier.resource,
+                barrier.pass,
+                barrier
+                    .previous_pass
+                    .map_or_else(|| "null".to_owned(), |pass| pass.to_string()),
+                barrier.src_access,
+                barrier.dst_access,
+            )
+            .unwrap();
+        }
+
+        json.push_str("\n  ]\n}\n");
+        json
+    }
+}
+
+fn json_accesses(accesses: &[PassAccessDescription]) -> String {
+    accesses
+        .iter()
+        .map(|access| {
+            format!(
+                "{{ \"resource\": {}, \"access\": \"{:?}\" }}",
+                access.resource, access.access_type
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn dot_string(s: &str) -> String {
+    format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
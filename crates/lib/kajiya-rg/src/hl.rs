@@ -3,6 +3,8 @@ use kajiya_backend::{
     dynamic_constants,
     vk_sync::AccessType,
     vulkan::{
+        barrier::image_aspect_mask_from_format,
+        device::RayTracingMode,
         image::*,
         ray_tracing::{RayTracingAcceleration, RayTracingPipelineDesc},
         shader::{ComputePipelineDesc, PipelineShaderDesc, ShaderPipelineStage, ShaderSource},
@@ -12,11 +14,11 @@ use kajiya_backend::{
 use crate::Image;
 
 use super::{
-    BindRgRef, Buffer, GpuSrv, GpuUav, Handle, PassBuilder, Ref, RenderPassApi, RenderPassBinding,
-    Resource, RgComputePipelineHandle, RgRtPipelineHandle,
+    BindRgRef, Buffer, GpuSrv, GpuUav, Handle, IntoRenderPassPipelineBinding, PassBuilder, Ref,
+    RenderPassApi, RenderPassBinding, Resource, RgComputePipelineHandle, RgRtPipelineHandle,
 };
 
-pub trait ConstBlob {
+pub trait ConstBlob: Send {
     fn push_self(
         self: Box<Self>,
         dynamic_constants: &mut dynamic_constants::DynamicConstants,
@@ -25,7 +27,7 @@ pub trait ConstBlob {
 
 impl<T> ConstBlob for T
 where
-    T: Copy + 'static,
+    T: Copy + Send + 'static,
 {
     fn push_self(
         self: Box<Self>,
@@ -39,7 +41,7 @@ struct VecBlob<T>(Vec<T>);
 
 impl<T> ConstBlob for VecBlob<T>
 where
-    T: Copy + 'static,
+    T: Copy + Send + 'static,
 {
     fn push_self(
         self: Box<Self>,
@@ -116,6 +118,25 @@ impl<'rg> SimpleRenderPass<'rg, RgComputePipelineHandle> {
         }
     }
 
+    /// Like `new_compute`, with preprocessor defines selecting a shader permutation.
+    pub fn new_compute_with_defines(
+        mut pass: PassBuilder<'rg>,
+        pipeline_path: &str,
+        defines: &[(String, String)],
+    ) -> Self {
+        let pipeline = pass.register_compute_pipeline_with_desc(
+            ComputePipelineDesc::builder()
+                .source(ShaderSource::hlsl(pipeline_path).with_defines(defines))
+                .build()
+                .unwrap(),
+        );
+
+        Self {
+            pass,
+            state: SimpleRenderPassState::new(pipeline),
+        }
+    }
+
     pub fn new_compute_rust(mut pass: PassBuilder<'rg>, entry_name: &str) -> Self {
         let pipeline = pass.register_compute_pipeline_with_desc(
             ComputePipelineDesc::builder()
@@ -133,7 +154,7 @@ impl<'rg> SimpleRenderPass<'rg, RgComputePipelineHandle> {
     pub fn dispatch(self, extent: [u32; 3]) {
         let mut state = self.state;
 
-        self.pass.render(move |api| {
+        self.pass.render_parallel(move |api| {
             state.patch_const_blobs(api);
 
             let pipeline = api.bind_compute_pipeline(state.create_pipeline_binding())?;
@@ -148,7 +169,7 @@ impl<'rg> SimpleRenderPass<'rg, RgComputePipelineHandle> {
         let args_buffer_ref = self.pass.read(args_buffer, AccessType::IndirectBuffer);
         let mut state = self.state;
 
-        self.pass.render(move |api| {
+        self.pass.render_parallel(move |api| {
             state.patch_const_blobs(api);
 
             let pipeline = api.bind_compute_pipeline(state.create_pipeline_binding())?;
@@ -160,46 +181,55 @@ impl<'rg> SimpleRenderPass<'rg, RgComputePipelineHandle> {
     }
 }
 
-impl<'rg> SimpleRenderPass<'rg, RgRtPipelineHandle> {
-    pub fn new_rt(
-        mut pass: PassBuilder<'rg>,
-        rgen: ShaderSource,
-        miss: impl IntoIterator<Item = ShaderSource>,
-        hit: impl IntoIterator<Item = ShaderSource>,
-    ) -> Self {
-        let miss = miss.into_iter();
-        let hit = hit.into_iter();
-
-        let mut shaders = Vec::with_capacity(1 + miss.size_hint().0 + hit.size_hint().0);
+fn register_rt_pipeline(
+    pass: &mut PassBuilder,
+    rgen: ShaderSource,
+    miss: impl IntoIterator<Item = ShaderSource>,
+    hit: impl IntoIterator<Item = ShaderSource>,
+) -> RgRtPipelineHandle {
+    let miss = miss.into_iter();
+    let hit = hit.into_iter();
+
+    let mut shaders = Vec::with_capacity(1 + miss.size_hint().0 + hit.size_hint().0);
+
+    shaders.push(
+        PipelineShaderDesc::builder(ShaderPipelineStage::RayGen)
+            .source(rgen)
+            .build()
+            .unwrap(),
+    );
+    for source in miss {
+        shaders.push(
+            PipelineShaderDesc::builder(ShaderPipelineStage::RayMiss)
+                .source(source)
+                .build()
+                .unwrap(),
+        );
+    }
 
+    for source in hit {
         shaders.push(
-            PipelineShaderDesc::builder(ShaderPipelineStage::RayGen)
-                .source(rgen)
+            PipelineShaderDesc::builder(ShaderPipelineStage::RayClosestHit)
+                .source(source)
                 .build()
                 .unwrap(),
         );
-        for source in miss {
-            shaders.push(
-                PipelineShaderDesc::builder(ShaderPipelineStage::RayMiss)
-                    .source(source)
-                    .build()
-                    .unwrap(),
-            );
-        }
+    }
 
-        for source in hit {
-            shaders.push(
-                PipelineShaderDesc::builder(ShaderPipelineStage::RayClosestHit)
-                    .source(source)
-                    .build()
-                    .unwrap(),
-            );
-        }
+    pass.register_ray_tracing_pipeline(
+        &shaders,
+        RayTracingPipelineDesc::default().max_pipeline_ray_recursion_depth(1),
+    )
+}
 
-        let pipeline = pass.register_ray_tracing_pipeline(
-            &shaders,
-            RayTracingPipelineDesc::default().max_pipeline_ray_recursion_depth(1),
-        );
+impl<'rg> SimpleRenderPass<'rg, RgRtPipelineHandle> {
+    pub fn new_rt(
+        mut pass: PassBuilder<'rg>,
+        rgen: ShaderSource,
+        miss: impl IntoIterator<Item = ShaderSource>,
+        hit: impl IntoIterator<Item = ShaderSource>,
+    ) -> Self {
+        let pipeline = register_rt_pipeline(&mut pass, rgen, miss, hit);
 
         Self {
             pass,
@@ -211,7 +241,7 @@ impl<'rg> SimpleRenderPass<'rg, RgRtPipelineHandle> {
         let tlas_ref = self.pass.read(tlas, AccessType::AnyShaderReadOther);
         let mut state = self.state;
 
-        self.pass.render(move |api| {
+        self.pass.render_parallel(move |api| {
             state.patch_const_blobs(api);
 
             let pipeline = api.bind_ray_tracing_pipeline(
@@ -236,7 +266,7 @@ impl<'rg> SimpleRenderPass<'rg, RgRtPipelineHandle> {
         let tlas_ref = self.pass.read(tlas, AccessType::AnyShaderReadOther);
         let mut state = self.state;
 
-        self.pass.render(move |api| {
+        self.pass.render_parallel(move |api| {
             state.patch_const_blobs(api);
 
             let pipeline = api.bind_ray_tracing_pipeline(
@@ -252,7 +282,105 @@ impl<'rg> SimpleRenderPass<'rg, RgRtPipelineHandle> {
     }
 }
 
+/// A ray tracing pipeline, or the compute pipeline standing in for it in `RayTracingMode::RayQuery`
+#[derive(Clone, Copy)]
+pub enum RgTracePipelineHandle {
+    RayTracing(RgRtPipelineHandle),
+    RayQuery(RgComputePipelineHandle),
+}
+
+impl IntoRenderPassPipelineBinding for RgTracePipelineHandle {
+    fn into_binding<'a>(self) -> super::RenderPassPipelineBinding<'a, Self> {
+        super::RenderPassPipelineBinding::new(self)
+    }
+}
+
+impl<'rg> SimpleRenderPass<'rg, RgTracePipelineHandle> {
+    /// Like `new_rt`, but in `RayTracingMode::RayQuery`, `rgen` is compiled as a compute shader
+    /// with `RT_USE_RAY_QUERY` defined, and `miss` and `hit` are unused. The shader must
+    /// declare its entry point with `RAYGEN_MAIN` from `inc/rt.hlsl`, and trace rays through
+    /// `rt_is_shadowed` and `GbufferRaytrace` only.
+    pub fn new_rt_with_ray_query_fallback(
+        mut pass: PassBuilder<'rg>,
+        rgen: ShaderSource,
+        miss: impl IntoIterator<Item = ShaderSource>,
+        hit: impl IntoIterator<Item = ShaderSource>,
+    ) -> Self {
+        let pipeline = match pass.rg.ray_tracing_mode {
+            RayTracingMode::Pipeline => {
+                RgTracePipelineHandle::RayTracing(register_rt_pipeline(&mut pass, rgen, miss, hit))
+            }
+            RayTracingMode::RayQuery => {
+                // Compute pipelines don't get the predefined defines otherwise,
+                // and the hit shading needs the material ones.
+                let source = rgen
+                    .with_defines(&[("RT_USE_RAY_QUERY".to_owned(), "1".to_owned())])
+                    .with_defines(&pass.rg.predefined_shader_defines);
+
+                RgTracePipelineHandle::RayQuery(
+                    pass.register_compute_pipeline_with_desc(
+                        ComputePipelineDesc::builder()
+                            .source(source)
+                            // `rt_dispatch_constants` in `inc/rt.hlsl`
+                            .push_constants_bytes(4 * std::mem::size_of::<u32>())
+                            .build()
+                            .unwrap(),
+                    ),
+                )
+            }
+        };
+
+        Self {
+            pass,
+            state: SimpleRenderPassState::new(pipeline),
+        }
+    }
+
+    pub fn trace_rays(mut self, tlas: &Handle<RayTracingAcceleration>, extent: [u32; 3]) {
+        let tlas_ref = self.pass.read(tlas, AccessType::AnyShaderReadOther);
+        let mut state = self.state;
+
+        self.pass.render_parallel(move |api| {
+            state.patch_const_blobs(api);
+
+            let pipeline = state.pipeline;
+            let tlas_binding = [tlas_ref.bind()];
+            let binding = state
+                .create_pipeline_binding()
+                .descriptor_set(3, &tlas_binding);
+
+            match pipeline {
+                RgTracePipelineHandle::RayTracing(pipeline) => {
+                    api.bind_ray_tracing_pipeline(binding.with_pipeline(pipeline))?
+                        .trace_rays(extent);
+                }
+                RgTracePipelineHandle::RayQuery(pipeline) => {
+                    let cb = api.cb;
+                    let pipeline = api.bind_compute_pipeline(binding.with_pipeline(pipeline))?;
+
+                    let dimensions = [extent[0], extent[1], extent[2], 0u32];
+                    pipeline.push_constants(cb.raw, 0, unsafe {
+                        std::slice::from_raw_parts(
+                            dimensions.as_ptr() as *const u8,
+                            std::mem::size_of_val(&dimensions),
+                        )
+                    });
+                    pipeline.dispatch(extent);
+                }
+            }
+
+            Ok(())
+        });
+    }
+}
+
 impl<'rg, RgPipelineHandle> SimpleRenderPass<'rg, RgPipelineHandle> {
+    /// See `PassBuilder::async_compute`
+    pub fn async_compute(mut self) -> Self {
+        self.pass.async_compute();
+        self
+    }
+
     pub fn read<Res>(mut self, handle: &Handle<Res>) -> Self
     where
         Res: Resource + 'static,
@@ -297,6 +425,19 @@ impl<'rg, RgPipelineHandle> SimpleRenderPass<'rg, RgPipelineHandle> {
         self
     }
 
+    /// Reads a depth buffer, or a depth copy stored in a color image (such as
+    /// the downscaled depth of reduced-resolution pass groups), which only has a color aspect.
+    pub fn read_depth(self, handle: &Handle<Image>) -> Self {
+        let aspect_mask =
+            if image_aspect_mask_from_format(handle.desc().format) == vk::ImageAspectFlags::COLOR {
+                vk::ImageAspectFlags::COLOR
+            } else {
+                vk::ImageAspectFlags::DEPTH
+            };
+
+        self.read_aspect(handle, aspect_mask)
+    }
+
     pub fn read_aspect(
         mut self,
         handle: &Handle<Image>,
@@ -372,7 +513,7 @@ impl<'rg, RgPipelineHandle> SimpleRenderPass<'rg, RgPipelineHandle> {
         self
     }
 
-    pub fn dynamic_storage_buffer_vec<T: Copy + 'static>(mut self, consts: Vec<T>) -> Self {
+    pub fn dynamic_storage_buffer_vec<T: Copy + Send + 'static>(mut self, consts: Vec<T>) -> Self {
         let binding_idx = self.state.bindings.len();
 
         self.state
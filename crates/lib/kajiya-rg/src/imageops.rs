@@ -5,7 +5,7 @@ pub fn clear_depth(rg: &mut RenderGraph, img: &mut rg::Handle<Image>) {
     let mut pass = rg.add_pass("clear depth");
     let output_ref = pass.write(img, AccessType::TransferWrite);
 
-    pass.render(move |api| {
+    pass.render_parallel(move |api| {
         let raw_device = &api.device().raw;
         let cb = api.cb;
 
@@ -37,7 +37,7 @@ pub fn clear_color(rg: &mut RenderGraph, img: &mut rg::Handle<Image>, clear_colo
     let mut pass = rg.add_pass("clear color");
     let output_ref = pass.write(img, AccessType::TransferWrite);
 
-    pass.render(move |api| {
+    pass.render_parallel(move |api| {
         let raw_device = &api.device().raw;
         let cb = api.cb;
 
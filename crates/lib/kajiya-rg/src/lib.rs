@@ -1,5 +1,10 @@
+mod async_compute;
+mod constants_tracker;
+mod frame_capture;
 mod graph;
+mod graph_export;
 mod hl;
+mod memory_tracker;
 mod pass_api;
 mod pass_builder;
 mod resource;
@@ -9,8 +14,12 @@ mod temporal;
 pub mod imageops;
 pub mod renderer;
 
+pub use constants_tracker::*;
+pub use frame_capture::*;
 pub use graph::*;
+pub use graph_export::*;
 pub use hl::*;
+pub use memory_tracker::*;
 pub use pass_api::*;
 pub use pass_builder::*;
 pub use resource::*;
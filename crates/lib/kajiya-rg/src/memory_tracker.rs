@@ -0,0 +1,278 @@
+//! Per-pass GPU memory watermarks, and detection of resources which the render graph
+//! keeps alive without using them.
+//!
+//! Neither temporal resources nor the transient resource cache are ever trimmed, so a pass
+//! which creates a temporal resource under a new key every frame, or asks for transient
+//! resources of ever-changing sizes, slowly leaks GPU memory. With `leak_frame_threshold`
+//! set, such resources are reported once they've gone unused for that many frames.
+
+use std::collections::HashMap;
+
+use kajiya_backend::{vulkan::buffer::BufferDesc, ImageDesc};
+
+use crate::{
+    resource_registry::{AnyRenderResource, RegistryResource},
+    ExportedTemporalRenderGraphState, RecordedPass, TemporalResource, TemporalResourceKey,
+    TemporalResourceState,
+};
+
+/// Peak memory used by passes of a given name, across executions of the graph.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct PassMemoryWatermark {
+    /// Graph-created resources alive during the pass, i.e. accessed both at or before,
+    /// and at or after it. Resources are not aliased yet, so this is an upper bound
+    /// on what the pass actually needs, rather than what it costs.
+    pub transient_bytes: u64,
+
+    /// Imported resources accessed by the pass, such as temporal resources.
+    pub persistent_bytes: u64,
+
+    /// Tracker frame in which the pass last ran.
+    pub last_frame: u64,
+}
+
+/// A resource kept alive by the render graph, but not used by it in a while.
+#[derive(Clone, Debug)]
+pub struct LingeringResource {
+    pub description: String,
+    pub bytes: u64,
+    pub idle_frames: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum TransientResourceDesc {
+    Image(ImageDesc),
+    Buffer(BufferDesc),
+}
+
+struct ResourceUsage {
+    last_frame: u64,
+
+    // For transient resources, all the instances held by the transient resource cache
+    bytes: u64,
+}
+
+#[derive(Default)]
+pub struct RenderGraphMemoryTracker {
+    /// Report resources which haven't been used by the graph for this many frames.
+    /// Zero disables leak detection, along with the rest of the tracking.
+    pub leak_frame_threshold: u64,
+
+    frame: u64,
+    watermarks: HashMap<String, PassMemoryWatermark>,
+    peak_transient_bytes: u64,
+    temporal_usage: HashMap<TemporalResourceKey, ResourceUsage>,
+    transient_usage: HashMap<TransientResourceDesc, ResourceUsage>,
+}
+
+impl RenderGraphMemoryTracker {
+    /// Watermarks of all passes which have run so far, by pass name.
+    pub fn watermarks(&self) -> impl Iterator<Item = (&str, &PassMemoryWatermark)> {
+        self.watermarks
+            .iter()
+            .map(|(name, watermark)| (name.as_str(), watermark))
+    }
+
+    /// Largest total size of graph-created resources in a single frame.
+    pub fn peak_transient_bytes(&self) -> u64 {
+        self.peak_transient_bytes
+    }
+
+    /// Resources which haven't been used for at least `leak_frame_threshold` frames.
+    pub fn lingering_resources(&self) -> Vec<LingeringResource> {
+        if self.leak_frame_threshold == 0 {
+            return Vec::new();
+        }
+
+        let temporal = self
+            .temporal_usage
+            .iter()
+            .map(|(key, usage)| (format!("temporal resource {:?}", key), usage));
+        let transient = self
+            .transient_usage
+            .iter()
+            .map(|(desc, usage)| (format!("cached transient resource {:?}", desc), usage));
+
+        temporal
+            .chain(transient)
+            .filter(|(_, usage)| self.frame - usage.last_frame >= self.leak_frame_threshold)
+            .map(|(description, usage)| LingeringResource {
+                description,
+                bytes: usage.bytes,
+                idle_frames: self.frame - usage.last_frame,
+            })
+            .collect()
+    }
+
+    /// Human-readable table of pass watermarks, largest first.
+    pub fn watermark_report(&self) -> String {
+        let mut watermarks = self.watermarks.iter().collect::<Vec<_>>();
+        watermarks
+            .sort_by_key(|(_, wm)| std::cmp::Reverse(wm.transient_bytes + wm.persistent_bytes));
+
+        let mut report = format!(
+            "Render graph memory watermarks; peak transient total: {}\n{:>12} {:>12}  pass\n",
+            format_bytes(self.peak_transient_bytes),
+            "transient",
+            "persistent"
+        );
+
+        for (name, wm) in watermarks {
+            report += &format!(
+                "{:>12} {:>12}  {}\n",
+                format_bytes(wm.transient_bytes),
+                format_bytes(wm.persistent_bytes),
+                name
+            );
+        }
+
+        report
+    }
+
+    pub(crate) fn record_temporal_state(&mut self, state: &ExportedTemporalRenderGraphState) {
+        let frame = self.frame;
+
+        for (key, resource_state) in &state.0.resources {
+            let (resource, used) = match resource_state {
+                TemporalResourceState::Inert { resource, .. } => (resource, false),
+                TemporalResourceState::Imported { resource, .. }
+                | TemporalResourceState::Exported { resource, .. } => (resource, true),
+            };
+
+            let bytes = match resource {
+                TemporalResource::Image(image) => image.memory_size,
+                TemporalResource::Buffer(buffer) => buffer.desc.size as u64,
+            };
+
+            let usage = self
+                .temporal_usage
+                .entry(key.clone())
+                .or_insert(ResourceUsage {
+                    last_frame: frame,
+                    bytes,
+                });
+
+            usage.bytes = bytes;
+            if used {
+                usage.last_frame = frame;
+            }
+        }
+
+        self.temporal_usage
+            .retain(|key, _| state.0.resources.contains_key(key));
+    }
+
+    pub(crate) fn record_graph(&mut self, passes: &[RecordedPass], resources: &[RegistryResource]) {
+        // Transient bytes, persistent bytes, and the description transient resources are cached by
+        let resource_info: Vec<(u64, u64, Option<TransientResourceDesc>)> = resources
+            .iter()
+            .map(|res| match &res.resource {
+                AnyRenderResource::OwnedImage(image) => (
+                    image.memory_size,
+                    0,
+                    Some(TransientResourceDesc::Image(image.desc)),
+                ),
+                AnyRenderResource::OwnedBuffer(buffer) => (
+                    buffer.desc.size as u64,
+                    0,
+                    Some(TransientResourceDesc::Buffer(buffer.desc)),
+                ),
+                AnyRenderResource::ImportedImage(image) => (0, image.memory_size, None),
+                AnyRenderResource::ImportedBuffer(buffer) => (0, buffer.desc.size as u64, None),
+                AnyRenderResource::ImportedRayTracingAcceleration(_)
+                | AnyRenderResource::Pending(_) => (0, 0, None),
+            })
+            .collect();
+
+        // Lifetimes of transient resources, as a difference array over passes
+        let mut first_access = vec![usize::MAX; resources.len()];
+        let mut last_access = vec![0; resources.len()];
+
+        for pass in passes {
+            for res in pass.read.iter().chain(pass.write.iter()) {
+                let idx = res.handle.id as usize;
+                first_access[idx] = first_access[idx].min(pass.idx);
+                last_access[idx] = last_access[idx].max(pass.idx);
+            }
+        }
+
+        let mut transient_delta = vec![0i64; passes.len() + 1];
+        let mut frame_transient_bytes = 0;
+        for (idx, (transient_bytes, _, _)) in resource_info.iter().enumerate() {
+            if *transient_bytes > 0 && first_access[idx] != usize::MAX {
+                transient_delta[first_access[idx]] += *transient_bytes as i64;
+                transient_delta[last_access[idx] + 1] -= *transient_bytes as i64;
+            }
+            frame_transient_bytes += transient_bytes;
+        }
+
+        self.peak_transient_bytes = self.peak_transient_bytes.max(frame_transient_bytes);
+
+        let mut transient_live: i64 = 0;
+        let mut pass_resources = Vec::new();
+        for (pass, delta) in passes.iter().zip(transient_delta) {
+            transient_live += delta;
+
+            pass_resources.clear();
+            pass_resources.extend(
+                pass.read
+                    .iter()
+                    .chain(pass.write.iter())
+                    .map(|res| res.handle.id as usize),
+            );
+            pass_resources.sort_unstable();
+            pass_resources.dedup();
+
+            let persistent_bytes: u64 =
+                pass_resources.iter().map(|&idx| resource_info[idx].1).sum();
+
+            let watermark = self.watermarks.entry(pass.name.clone()).or_default();
+            watermark.transient_bytes = watermark.transient_bytes.max(transient_live as u64);
+            watermark.persistent_bytes = watermark.persistent_bytes.max(persistent_bytes);
+            watermark.last_frame = self.frame;
+        }
+
+        // Every transient resource goes back to the cache at the end of the frame, where it stays
+        // until a resource with the exact same description is requested again.
+        let mut frame_transient_usage: HashMap<TransientResourceDesc, u64> = HashMap::new();
+        for (bytes, _, desc) in &resource_info {
+            if let Some(desc) = desc {
+                *frame_transient_usage.entry(*desc).or_default() += bytes;
+            }
+        }
+
+        for (desc, bytes) in frame_transient_usage {
+            let usage = self.transient_usage.entry(desc).or_insert(ResourceUsage {
+                last_frame: self.frame,
+                bytes,
+            });
+            usage.last_frame = self.frame;
+            usage.bytes = usage.bytes.max(bytes);
+        }
+
+        self.frame += 1;
+        self.report_lingering_resources();
+    }
+
+    // Warns about every resource once, as it crosses the threshold.
+    fn report_lingering_resources(&self) {
+        if self.leak_frame_threshold == 0 {
+            return;
+        }
+
+        for res in self.lingering_resources() {
+            if res.idle_frames == self.leak_frame_threshold {
+                log::warn!(
+                    "Possible render graph leak: {} ({}) hasn't been used in {} frames",
+                    res.description,
+                    format_bytes(res.bytes),
+                    res.idle_frames
+                );
+            }
+        }
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    format!("{:.2} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
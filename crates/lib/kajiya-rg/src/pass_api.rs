@@ -66,6 +66,17 @@ impl<'a, HandleType> RenderPassPipelineBinding<'a, HandleType> {
         }
     }
 
+    /// The same bindings, for another pipeline
+    pub(crate) fn with_pipeline<Other>(
+        self,
+        pipeline: Other,
+    ) -> RenderPassPipelineBinding<'a, Other> {
+        RenderPassPipelineBinding {
+            pipeline,
+            binding: self.binding,
+        }
+    }
+
     pub fn descriptor_set(mut self, set_idx: u32, bindings: &'a [RenderPassBinding]) -> Self {
         self.binding.bindings.push((set_idx, bindings));
         self
@@ -108,6 +119,23 @@ impl<'a, 'exec_params, 'constants> RenderPassApi<'a, 'exec_params, 'constants> {
         self.resources.dynamic_constants
     }
 
+    /// Opens a labeled region within this pass in graphics debuggers, e.g. around
+    /// each draw of a batch. Must be closed with `end_debug_label` before the pass ends.
+    pub fn begin_debug_label(&self, name: &str) {
+        self.device()
+            .begin_debug_label(self.cb.raw, name, [0.0, 0.0, 0.0, 0.0]);
+    }
+
+    pub fn end_debug_label(&self) {
+        self.device().end_debug_label(self.cb.raw);
+    }
+
+    /// Places a single marker within this pass in graphics debuggers.
+    pub fn insert_debug_label(&self, name: &str) {
+        self.device()
+            .insert_debug_label(self.cb.raw, name, [0.0, 0.0, 0.0, 0.0]);
+    }
+
     pub fn bind_compute_pipeline<'s>(
         &'s mut self,
         binding: RenderPassPipelineBinding<'_, RgComputePipelineHandle>,
@@ -194,6 +222,14 @@ impl<'a, 'exec_params, 'constants> RenderPassApi<'a, 'exec_params, 'constants> {
                             .execution_params
                             .frame_constants_layout
                             .triangle_lights_offset,
+                        self.resources
+                            .execution_params
+                            .frame_constants_layout
+                            .point_lights_offset,
+                        self.resources
+                            .execution_params
+                            .frame_constants_layout
+                            .instance_batch_ends_offset,
                     ],
                 );
             }
@@ -2,9 +2,9 @@ use crate::{PassResourceAccessSyncType, RenderPassApi};
 
 use super::{
     graph::{
-        PassResourceAccessType, PassResourceRef, RecordedPass, RenderGraph, RgComputePipeline,
-        RgComputePipelineHandle, RgRasterPipeline, RgRasterPipelineHandle, RgRtPipeline,
-        RgRtPipelineHandle, TypeEquals,
+        PassRenderFn, PassResourceAccessType, PassResourceRef, RecordedPass, RenderGraph,
+        RgComputePipeline, RgComputePipelineHandle, RgRasterPipeline, RgRasterPipelineHandle,
+        RgRtPipeline, RgRtPipelineHandle, TypeEquals,
     },
     resource::*,
 };
@@ -290,7 +290,7 @@ impl<'rg> PassBuilder<'rg> {
         }
 
         self.rg.raster_pipelines.push(RgRasterPipeline {
-            shaders: shaders.to_vec(),
+            shaders: self.with_predefined_shader_defines(shaders),
             desc,
         });
 
@@ -314,14 +314,119 @@ impl<'rg> PassBuilder<'rg> {
             ));
         }
 
+        let shaders = self.with_predefined_hit_groups(shaders);
         self.rg.rt_pipelines.push(RgRtPipeline {
-            shaders: shaders.to_vec(),
+            shaders: self.with_predefined_shader_defines(&shaders),
             desc,
         });
 
         RgRtPipelineHandle { id }
     }
 
+    fn with_predefined_hit_groups(
+        &self,
+        shaders: &[PipelineShaderDesc],
+    ) -> Vec<PipelineShaderDesc> {
+        let first_hit_group_start = shaders
+            .iter()
+            .position(|shader| shader.stage == ShaderPipelineStage::RayClosestHit);
+
+        let first_hit_group_start = match first_hit_group_start {
+            Some(start) if !self.rg.predefined_hit_groups.is_empty() => start,
+            _ => return self.with_predefined_any_hit(shaders.to_vec()),
+        };
+
+        let first_hit_group_end = shaders[first_hit_group_start + 1..]
+            .iter()
+            .position(|shader| shader.stage != ShaderPipelineStage::RayAnyHit)
+            .map_or(shaders.len(), |len| first_hit_group_start + 1 + len);
+
+        let first_hit_group = &shaders[first_hit_group_start..first_hit_group_end];
+
+        let mut result = shaders[..first_hit_group_end].to_vec();
+
+        for group in &self.rg.predefined_hit_groups {
+            if group.base_closest_hit != first_hit_group[0].source {
+                result.extend_from_slice(first_hit_group);
+                continue;
+            }
+
+            result.push(
+                PipelineShaderDesc::builder(ShaderPipelineStage::RayClosestHit)
+                    .source(group.closest_hit.clone())
+                    .build()
+                    .unwrap(),
+            );
+
+            if let Some(any_hit) = &group.any_hit {
+                result.push(
+                    PipelineShaderDesc::builder(ShaderPipelineStage::RayAnyHit)
+                        .source(any_hit.clone())
+                        .build()
+                        .unwrap(),
+                );
+            }
+        }
+
+        result.extend_from_slice(&shaders[first_hit_group_end..]);
+        self.with_predefined_any_hit(result)
+    }
+
+    fn with_predefined_any_hit(&self, shaders: Vec<PipelineShaderDesc>) -> Vec<PipelineShaderDesc> {
+        let any_hit = match &self.rg.predefined_any_hit {
+            Some(any_hit) => any_hit,
+            None => return shaders,
+        };
+
+        let mut result = Vec::with_capacity(shaders.len() * 2);
+        for (i, shader) in shaders.iter().enumerate() {
+            result.push(shader.clone());
+
+            let has_any_hit = shaders
+                .get(i + 1)
+                .map_or(false, |next| next.stage == ShaderPipelineStage::RayAnyHit);
+            if shader.stage == ShaderPipelineStage::RayClosestHit && !has_any_hit {
+                result.push(
+                    PipelineShaderDesc::builder(ShaderPipelineStage::RayAnyHit)
+                        .source(any_hit.clone())
+                        .build()
+                        .unwrap(),
+                );
+            }
+        }
+
+        result
+    }
+
+    fn with_predefined_shader_defines(
+        &self,
+        shaders: &[PipelineShaderDesc],
+    ) -> Vec<PipelineShaderDesc> {
+        shaders
+            .iter()
+            .map(|shader| PipelineShaderDesc {
+                source: shader
+                    .source
+                    .clone()
+                    .with_defines(&self.rg.predefined_shader_defines),
+                ..shader.clone()
+            })
+            .collect()
+    }
+
+    /// Runs the pass on `Device::async_compute_queue`, overlapping with the passes around it
+    /// which don't share any resources with it. Only for passes which dispatch compute shaders
+    /// or trace rays; raster passes can't run on compute queues.
+    ///
+    /// Async passes are batched into a single submission per frame. It starts once all the
+    /// passes they depend on are done, and later passes which depend on it wait for all of it.
+    /// Cross-queue semaphores are inserted automatically. Both queues are of the same family, so
+    /// resources need no ownership transfers. Without an async compute queue, or with
+    /// `RG_ALLOW_ASYNC_COMPUTE` cleared, async passes run in graph order like any other.
+    pub fn async_compute(&mut self) {
+        self.pass.as_mut().unwrap().async_compute = true;
+    }
+
     pub fn render(
         mut self,
         render: impl (FnOnce(&mut RenderPassApi) -> Result<(), BackendError>) + 'static,
@@ -331,7 +436,26 @@ impl<'rg> PassBuilder<'rg> {
             .as_mut()
             .unwrap()
             .render_fn
-            .replace(Box::new(render));
+            .replace(PassRenderFn::MainThread(Box::new(render)));
+
+        assert!(prev.is_none());
+    }
+
+    /// Like `render`, but lets the graph record the pass on a worker thread, into a secondary
+    /// command buffer. The GPU still executes it in graph order, but the function itself may
+    /// run before those of earlier passes, so it must not depend on their CPU-side effects.
+    ///
+    /// Secondary command buffers can't begin render passes, so raster passes should use `render`.
+    pub fn render_parallel(
+        mut self,
+        render: impl (FnOnce(&mut RenderPassApi) -> Result<(), BackendError>) + Send + 'static,
+    ) {
+        let prev = self
+            .pass
+            .as_mut()
+            .unwrap()
+            .render_fn
+            .replace(PassRenderFn::Parallel(Box::new(render)));
 
         assert!(prev.is_none());
     }
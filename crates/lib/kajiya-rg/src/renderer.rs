@@ -1,16 +1,23 @@
 use crate::{
+    constants_tracker::{DynamicConstantsTracker, PassConstantsUsage, FRAME_CONSTANTS_SCOPE_NAME},
+    memory_tracker::RenderGraphMemoryTracker,
     CompiledRenderGraph, ExecutingRenderGraph, ExportedTemporalRenderGraphState,
     PredefinedDescriptorSet, RenderGraphExecutionParams, TemporalRenderGraph,
     TemporalRenderGraphState, TemporalResourceState,
 };
 use kajiya_backend::{
-    ash::vk,
+    ash::{self, vk},
     dynamic_constants::*,
     pipeline_cache::*,
     rspirv_reflect,
     transient_resource_cache::TransientResourceCache,
     vk_sync,
-    vulkan::{self, swapchain::Swapchain, RenderBackend},
+    vulkan::{
+        self,
+        device::{CommandBuffer, SemaphoreWait},
+        swapchain::Swapchain,
+        RenderBackend,
+    },
     Device,
 };
 #[allow(unused_imports)]
@@ -40,6 +47,9 @@ pub struct Renderer {
 
     compiled_rg: Option<CompiledRenderGraph>,
     temporal_rg_state: TemporalRg,
+
+    pub memory_tracker: RenderGraphMemoryTracker,
+    pub constants_tracker: DynamicConstantsTracker,
 }
 
 lazy_static::lazy_static! {
@@ -71,16 +81,37 @@ lazy_static::lazy_static! {
             name: Default::default(),
         },
     ),
+    // point_lights_dyn
+    (
+        3,
+        rspirv_reflect::DescriptorInfo {
+            ty: rspirv_reflect::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+            dimensionality: rspirv_reflect::DescriptorDimensionality::Single,
+            name: Default::default(),
+        },
+    ),
+    // instance_batch_ends_dyn
+    (
+        4,
+        rspirv_reflect::DescriptorInfo {
+            ty: rspirv_reflect::DescriptorType::STORAGE_BUFFER_DYNAMIC,
+            dimensionality: rspirv_reflect::DescriptorDimensionality::Single,
+            name: Default::default(),
+        },
+    ),
     ]
     .iter()
     .cloned()
     .collect();
 }
 
+#[derive(Clone, Copy)]
 pub struct FrameConstantsLayout {
     pub globals_offset: u32,
     pub instance_dynamic_parameters_offset: u32,
     pub triangle_lights_offset: u32,
+    pub point_lights_offset: u32,
+    pub instance_batch_ends_offset: u32,
 }
 
 impl Renderer {
@@ -91,7 +122,8 @@ impl Renderer {
                     DYNAMIC_CONSTANTS_SIZE_BYTES * DYNAMIC_CONSTANTS_BUFFER_COUNT,
                     vk::BufferUsageFlags::UNIFORM_BUFFER
                         | vk::BufferUsageFlags::STORAGE_BUFFER
-                        | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                        | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                        | vk::BufferUsageFlags::TRANSFER_SRC,
                 ),
                 "dynamic constants buffer",
                 None,
@@ -110,6 +142,8 @@ impl Renderer {
 
             compiled_rg: None,
             temporal_rg_state: Default::default(),
+            memory_tracker: Default::default(),
+            constants_tracker: Default::default(),
         })
     }
 
@@ -136,23 +170,18 @@ impl Renderer {
             &current_frame.main_command_buffer,
             &current_frame.presentation_command_buffer,
         ] {
-            unsafe {
-                raw_device
-                    .reset_command_buffer(cb.raw, vk::CommandBufferResetFlags::default())
-                    .unwrap();
-
-                raw_device
-                    .begin_command_buffer(
-                        cb.raw,
-                        &vk::CommandBufferBeginInfo::builder()
-                            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
-                    )
-                    .unwrap();
-            }
+            begin_command_buffer(raw_device, cb);
         }
 
+        self.dynamic_constants
+            .set_call_site_tracking(self.constants_tracker.track_call_sites);
+        let max_constants_bytes_per_pass = self.constants_tracker.max_bytes_per_pass;
+
         // Now that we can write to GPU data, prepare global frame constants.
+        self.dynamic_constants
+            .begin_scope(FRAME_CONSTANTS_SCOPE_NAME, max_constants_bytes_per_pass);
         let frame_constants_layout = prepare_frame_constants(&mut self.dynamic_constants);
+        let frame_constants_usage = self.dynamic_constants.end_scope();
 
         let mut executing_rg: ExecutingRenderGraph;
 
@@ -170,12 +199,14 @@ impl Renderer {
                 rg.begin_execute(
                     RenderGraphExecutionParams {
                         device: &self.device,
-                        pipeline_cache: &mut self.pipeline_cache,
+                        pipeline_cache: &self.pipeline_cache,
                         frame_descriptor_set: self.frame_descriptor_set,
                         frame_constants_layout,
-                        profiler_data: &current_frame.profiler_data,
+                        max_constants_bytes_per_pass,
                     },
+                    &current_frame.profiler_data,
                     &mut self.transient_resource_cache,
+                    &mut self.memory_tracker,
                     &mut self.dynamic_constants,
                 )
             };
@@ -186,33 +217,82 @@ impl Renderer {
 
                 {
                     puffin::profile_scope!("rg::record_main_cb");
-                    executing_rg.record_main_cb(main_cb)
+                    executing_rg.record_main_cb(main_cb, &current_frame.secondary_command_pools)
                 }
 
                 raw_device.end_command_buffer(main_cb.raw).unwrap();
 
-                let submit_info = [vk::SubmitInfo::builder()
-                    .command_buffers(std::slice::from_ref(&main_cb.raw))
-                    .build()];
-
-                raw_device
-                    .reset_fences(std::slice::from_ref(&main_cb.submit_done_fence))
-                    .expect("reset_fences");
-
                 puffin::profile_scope!("submit main cb");
 
                 // Try to submit the command buffer to the GPU. We might encounter a GPU crash.
-                raw_device
-                    .queue_submit(
-                        self.device.universal_queue.raw,
-                        &submit_info,
-                        main_cb.submit_done_fence,
-                    )
-                    .map_err(|err| device.report_error(err.into()))
+                self.device
+                    .universal_queue
+                    .submit(raw_device, std::slice::from_ref(&main_cb.raw), &[], &[])
+                    .map_err(|err| device.report_error(err))
                     .expect("main queue_submit failed");
             };
         }
 
+        // Passes scheduled on the async compute queue, and those on the universal queue
+        // which run alongside them, and then wait for them.
+        if executing_rg.has_async_compute() {
+            puffin::profile_scope!("async compute cbs");
+
+            let async_compute_queue = device.async_compute_queue.as_ref().unwrap();
+            let async_cb = current_frame.async_compute_command_buffer.as_ref().unwrap();
+            let overlap_cb = &current_frame.async_overlap_command_buffer;
+            let join_cb = &current_frame.async_join_command_buffer;
+
+            begin_command_buffer(raw_device, async_cb);
+            executing_rg.record_async_compute_cb(async_cb);
+
+            begin_command_buffer(raw_device, overlap_cb);
+            executing_rg
+                .record_async_overlap_cb(overlap_cb, &current_frame.secondary_command_pools);
+
+            begin_command_buffer(raw_device, join_cb);
+            executing_rg.record_async_join_cb(join_cb, &current_frame.secondary_command_pools);
+
+            unsafe {
+                for cb in [async_cb, overlap_cb, join_cb] {
+                    raw_device.end_command_buffer(cb.raw).unwrap();
+                }
+
+                let async_compute_done = async_compute_queue
+                    .submit(
+                        raw_device,
+                        std::slice::from_ref(&async_cb.raw),
+                        &[device.universal_queue.timeline_wait(
+                            device.universal_queue.last_submitted_value(),
+                            vk::PipelineStageFlags::ALL_COMMANDS,
+                        )],
+                        &[],
+                    )
+                    .map_err(|err| device.report_error(err))
+                    .expect("async compute queue_submit failed");
+
+                device
+                    .universal_queue
+                    .submit(raw_device, std::slice::from_ref(&overlap_cb.raw), &[], &[])
+                    .map_err(|err| device.report_error(err))
+                    .expect("async overlap queue_submit failed");
+
+                device
+                    .universal_queue
+                    .submit(
+                        raw_device,
+                        std::slice::from_ref(&join_cb.raw),
+                        &[async_compute_queue.timeline_wait(
+                            async_compute_done,
+                            vk::PipelineStageFlags::ALL_COMMANDS,
+                        )],
+                        &[],
+                    )
+                    .map_err(|err| device.report_error(err))
+                    .expect("async join queue_submit failed");
+            }
+        }
+
         // Now that we've done the main submission and the GPU is busy, acquire the presentation image.
         // This can block, so we're doing it as late as possible.
 
@@ -222,7 +302,7 @@ impl Renderer {
             .expect("swapchain image");
 
         // Execute the rest of the render graph, and submit the presentation command buffer.
-        let retired_rg = {
+        let mut retired_rg = {
             puffin::profile_scope!("presentation cb");
 
             let presentation_cb = &current_frame.presentation_command_buffer;
@@ -263,26 +343,19 @@ impl Renderer {
             unsafe {
                 raw_device.end_command_buffer(presentation_cb.raw).unwrap();
 
-                let submit_info = [vk::SubmitInfo::builder()
-                    .wait_semaphores(std::slice::from_ref(&swapchain_image.acquire_semaphore))
-                    .signal_semaphores(std::slice::from_ref(
-                        &swapchain_image.rendering_finished_semaphore,
-                    ))
-                    .wait_dst_stage_mask(&[vk::PipelineStageFlags::COMPUTE_SHADER])
-                    .command_buffers(std::slice::from_ref(&presentation_cb.raw))
-                    .build()];
-                raw_device
-                    .reset_fences(std::slice::from_ref(&presentation_cb.submit_done_fence))
-                    .expect("reset_fences");
-
                 puffin::profile_scope!("submit presentation cb");
-                raw_device
-                    .queue_submit(
-                        self.device.universal_queue.raw,
-                        &submit_info,
-                        presentation_cb.submit_done_fence,
+                self.device
+                    .universal_queue
+                    .submit(
+                        raw_device,
+                        std::slice::from_ref(&presentation_cb.raw),
+                        &[SemaphoreWait::binary(
+                            swapchain_image.acquire_semaphore,
+                            vk::PipelineStageFlags::COMPUTE_SHADER,
+                        )],
+                        std::slice::from_ref(&swapchain_image.rendering_finished_semaphore),
                     )
-                    .map_err(|err| device.report_error(err.into()))
+                    .map_err(|err| device.report_error(err))
                     .expect("presentation queue_submit failed");
             }
 
@@ -298,6 +371,18 @@ impl Renderer {
             TemporalRg::Exported(rg) => TemporalRg::Inert(rg.retire_temporal(&retired_rg)),
         };
 
+        let mut constants_usage = vec![PassConstantsUsage {
+            name: FRAME_CONSTANTS_SCOPE_NAME.to_owned(),
+            bytes: frame_constants_usage.bytes,
+            call_sites: frame_constants_usage.call_sites,
+        }];
+        constants_usage.append(&mut retired_rg.constants_usage);
+        self.constants_tracker.record_frame(
+            constants_usage,
+            self.dynamic_constants.used_bytes(),
+            self.dynamic_constants.frame_parity(),
+        );
+
         retired_rg.release_resources(&mut self.transient_resource_cache);
 
         self.dynamic_constants.advance_frame();
@@ -311,11 +396,7 @@ impl Renderer {
     ) -> vk::DescriptorSet {
         let device = &backend.device.raw;
 
-        let set_binding_flags = [
-            vk::DescriptorBindingFlags::PARTIALLY_BOUND,
-            vk::DescriptorBindingFlags::PARTIALLY_BOUND,
-            vk::DescriptorBindingFlags::PARTIALLY_BOUND,
-        ];
+        let set_binding_flags = [vk::DescriptorBindingFlags::PARTIALLY_BOUND; 5];
 
         let mut binding_flags_create_info =
             vk::DescriptorSetLayoutBindingFlagsCreateInfo::builder()
@@ -348,6 +429,20 @@ impl Renderer {
                                 .stage_flags(vk::ShaderStageFlags::ALL)
                                 .binding(2)
                                 .build(),
+                            // point_lights_dyn
+                            vk::DescriptorSetLayoutBinding::builder()
+                                .descriptor_count(1)
+                                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
+                                .stage_flags(vk::ShaderStageFlags::ALL)
+                                .binding(3)
+                                .build(),
+                            // instance_batch_ends_dyn
+                            vk::DescriptorSetLayoutBinding::builder()
+                                .descriptor_count(1)
+                                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
+                                .stage_flags(vk::ShaderStageFlags::ALL)
+                                .binding(4)
+                                .build(),
                         ])
                         .push_next(&mut binding_flags_create_info)
                         .build(),
@@ -363,7 +458,7 @@ impl Renderer {
             },
             vk::DescriptorPoolSize {
                 ty: vk::DescriptorType::STORAGE_BUFFER_DYNAMIC,
-                descriptor_count: 2,
+                descriptor_count: 4,
             },
         ];
 
@@ -420,6 +515,20 @@ impl Renderer {
                     .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
                     .buffer_info(std::slice::from_ref(&storage_buffer_info))
                     .build(),
+                // `point_lights_dyn`
+                vk::WriteDescriptorSet::builder()
+                    .dst_binding(3)
+                    .dst_set(set)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
+                    .buffer_info(std::slice::from_ref(&storage_buffer_info))
+                    .build(),
+                // `instance_batch_ends_dyn`
+                vk::WriteDescriptorSet::builder()
+                    .dst_binding(4)
+                    .dst_set(set)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER_DYNAMIC)
+                    .buffer_info(std::slice::from_ref(&storage_buffer_info))
+                    .build(),
             ];
 
             unsafe { device.update_descriptor_sets(&descriptor_set_writes, &[]) };
@@ -460,6 +569,10 @@ impl Renderer {
         match self.pipeline_cache.prepare_frame(&self.device) {
             Ok(()) => {
                 // If the frame preparation succeded, update stored temporal rg state and finish
+                if self.memory_tracker.leak_frame_threshold > 0 {
+                    self.memory_tracker
+                        .record_temporal_state(&temporal_rg_state);
+                }
                 self.temporal_rg_state = TemporalRg::Exported(temporal_rg_state);
                 Ok(())
             }
@@ -502,3 +615,19 @@ impl Renderer {
         &self.device
     }
 }
+
+fn begin_command_buffer(raw_device: &ash::Device, cb: &CommandBuffer) {
+    unsafe {
+        raw_device
+            .reset_command_buffer(cb.raw, vk::CommandBufferResetFlags::default())
+            .unwrap();
+
+        raw_device
+            .begin_command_buffer(
+                cb.raw,
+                &vk::CommandBufferBeginInfo::builder()
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT),
+            )
+            .unwrap();
+    }
+}
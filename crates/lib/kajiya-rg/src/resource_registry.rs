@@ -62,11 +62,15 @@ pub(crate) struct RegistryResource {
     pub access_type: vk_sync::AccessType,
 }
 
+/// Resources visible to a pass while it records its commands.
+///
+/// Passes recorded in parallel share everything but `dynamic_constants`,
+/// of which each recording thread gets its own fork.
 pub struct ResourceRegistry<'exec_params, 'constants> {
     pub execution_params: RenderGraphExecutionParams<'exec_params>,
-    pub(crate) resources: Vec<RegistryResource>,
+    pub(crate) resources: &'exec_params [RegistryResource],
     pub dynamic_constants: &'constants mut DynamicConstants,
-    pub pipelines: RenderGraphPipelines,
+    pub pipelines: &'exec_params RenderGraphPipelines,
 }
 
 impl<'exec_params, 'constants> ResourceRegistry<'exec_params, 'constants> {
@@ -5,13 +5,24 @@ use std::{
 
 use anyhow::Context;
 
-use kajiya_backend::{vk_sync::AccessType, Device, Image, ImageDesc};
+use kajiya_backend::{ash::vk, vk_sync::AccessType, Device, Image, ImageDesc};
 
 use super::{
-    Buffer, BufferDesc, ExportableGraphResource, ExportedHandle, Handle, RenderGraph, Resource,
-    ResourceDesc, RetiredRenderGraph, TypeEquals,
+    Buffer, BufferDesc, ExportableGraphResource, ExportedHandle, FrameCapture, GraphDescription,
+    Handle, RenderGraph, Resource, ResourceDesc, RetiredRenderGraph, TypeEquals,
 };
 
+// Imported resources keep the usage flags they were created with, so temporal ones
+// allow copies out of them up front, for `capture_all_resources`.
+fn temporal_image_desc(desc: ImageDesc) -> ImageDesc {
+    desc.usage(desc.usage | vk::ImageUsageFlags::TRANSFER_SRC)
+}
+
+fn temporal_buffer_desc(mut desc: BufferDesc) -> BufferDesc {
+    desc.usage |= vk::BufferUsageFlags::TRANSFER_SRC;
+    desc
+}
+
 pub struct ReadOnlyHandle<ResType: Resource>(Handle<ResType>);
 
 impl<ResType: Resource> std::ops::Deref for ReadOnlyHandle<ResType> {
@@ -125,8 +136,11 @@ impl std::ops::DerefMut for TemporalRenderGraph {
 
 impl TemporalRenderGraph {
     pub fn new(state: TemporalRenderGraphState, device: Arc<Device>) -> Self {
+        let mut rg = RenderGraph::new();
+        rg.ray_tracing_mode = device.ray_tracing_mode();
+
         Self {
-            rg: RenderGraph::new(),
+            rg,
             device,
             temporal_state: state,
         }
@@ -135,6 +149,38 @@ impl TemporalRenderGraph {
     pub fn device(&self) -> &Device {
         self.device.as_ref()
     }
+
+    /// Copies every image and buffer of the frame to host-visible memory, as of the end
+    /// of the graph, for offline inspection. Call after all the passes have been added.
+    ///
+    /// Resources named via `RenderGraph::set_name` keep their names. Temporal resources
+    /// are named after their keys, and the rest after the last pass writing to them. Resources which can't be copied, such as imported ones without
+    /// `TRANSFER_SRC` usage, are listed in `FrameCapture::skipped`.
+    pub fn capture_all_resources(&mut self) -> FrameCapture {
+        let temporal_names = self.temporal_names();
+        self.rg
+            .capture_all_resources_impl(self.device.as_ref(), &temporal_names)
+    }
+
+    /// Passes, resources, their lifetimes and the barriers between passes, as recorded so far,
+    /// for debugging scheduling; call after all the passes have been added. Resources are named
+    /// the same way as in `capture_all_resources`.
+    pub fn describe_graph(&self) -> GraphDescription {
+        self.rg.describe_graph_impl(&self.temporal_names())
+    }
+
+    fn temporal_names(&self) -> HashMap<u32, String> {
+        self.temporal_state
+            .resources
+            .iter()
+            .filter_map(|(key, state)| match state {
+                TemporalResourceState::Imported { handle, .. } => {
+                    Some((handle.raw().id, key.0.clone()))
+                }
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 pub trait GetOrCreateTemporal<Desc: ResourceDesc> {
@@ -166,6 +212,20 @@ impl GetOrCreateTemporal<ImageDesc> for TemporalRenderGraph {
                         resource,
                         access_type,
                     } => {
+                        // The requested size or format changed (e.g. with render scale),
+                        // so the old contents are meaningless. Start over with a new image.
+                        if let TemporalResource::Image(image) = resource {
+                            if image.desc != temporal_image_desc(desc) {
+                                let image = self
+                                    .device
+                                    .create_image(temporal_image_desc(desc), vec![])
+                                    .with_context(|| format!("Creating image {:?}", desc))?;
+                                self.device.set_object_name(image.raw, &key.0);
+                                *resource = TemporalResource::Image(Arc::new(image));
+                                *access_type = AccessType::Nothing;
+                            }
+                        }
+
                         let resource = resource.clone();
 
                         match &resource {
@@ -199,12 +259,13 @@ impl GetOrCreateTemporal<ImageDesc> for TemporalRenderGraph {
                 }
             }
             hash_map::Entry::Vacant(entry) => {
-                let resource = Arc::new(
-                    self.device
-                        // TODO: Zero-init
-                        .create_image(desc, vec![])
-                        .with_context(|| format!("Creating image {:?}", desc))?,
-                );
+                let resource = self
+                    .device
+                    // TODO: Zero-init
+                    .create_image(temporal_image_desc(desc), vec![])
+                    .with_context(|| format!("Creating image {:?}", desc))?;
+                self.device.set_object_name(resource.raw, &key.0);
+                let resource = Arc::new(resource);
                 let handle = self.rg.import(resource.clone(), AccessType::Nothing);
                 entry.insert(TemporalResourceState::Imported {
                     resource: TemporalResource::Image(resource),
@@ -234,6 +295,23 @@ impl GetOrCreateTemporal<BufferDesc> for TemporalRenderGraph {
                         resource,
                         access_type,
                     } => {
+                        // As with images, a change in size means starting over.
+                        if let TemporalResource::Buffer(buffer) = resource {
+                            if buffer.desc != temporal_buffer_desc(desc) {
+                                let buffer = self
+                                    .device
+                                    .create_buffer(
+                                        temporal_buffer_desc(desc),
+                                        &key.0,
+                                        // Zero-init
+                                        Some(vec![0; desc.size].as_slice()),
+                                    )
+                                    .with_context(|| format!("Creating buffer {:?}", desc))?;
+                                *resource = TemporalResource::Buffer(Arc::new(buffer));
+                                *access_type = AccessType::Nothing;
+                            }
+                        }
+
                         let resource = resource.clone();
 
                         match &resource {
@@ -268,7 +346,7 @@ impl GetOrCreateTemporal<BufferDesc> for TemporalRenderGraph {
             }
             hash_map::Entry::Vacant(entry) => {
                 let resource = Arc::new(self.device.create_buffer(
-                    desc,
+                    temporal_buffer_desc(desc),
                     &key.0,
                     // Zero-init
                     Some(vec![0; desc.size].as_slice()),
@@ -52,6 +52,23 @@ impl KeyboardState {
             ks.ticks += 1;
         }
     }
+
+    /// Presses and releases keys from the live view's browsers like local ones.
+    /// Call before `update`, which ticks the keys.
+    #[cfg(feature = "live-view")]
+    pub fn apply_live_view_events(&mut self, events: &[crate::LiveViewEvent]) {
+        for event in events {
+            if let crate::LiveViewEvent::Key { code, pressed } = event {
+                if let Some(vk) = crate::virtual_keycode(code) {
+                    if *pressed {
+                        self.keys_down.entry(vk).or_insert(KeyState { ticks: 0 });
+                    } else {
+                        self.keys_down.remove(&vk);
+                    }
+                }
+            }
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
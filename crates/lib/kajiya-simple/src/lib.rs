@@ -1,4 +1,6 @@
 mod input;
+#[cfg(feature = "live-view")]
+mod live_view;
 mod main_loop;
 
 pub use glam::*;
@@ -6,13 +8,16 @@ pub use input::*;
 pub use kajiya::{
     backend::{
         file::{set_standard_vfs_mount_points, set_vfs_mount_point},
+        vulkan::{device::RayTracingMode, swapchain::SwapchainColorSpace},
         *,
     },
     camera::*,
     frame_desc::WorldFrameDesc,
     math::*,
-    world_renderer::{RenderDebugMode, RenderMode},
+    world_renderer::{HdrOutput, RenderDebugMode, RenderMode, TemporalUpscaler},
 };
+#[cfg(feature = "live-view")]
+pub use live_view::{virtual_keycode, LiveViewConfig, LiveViewEvent};
 pub use log;
 pub use main_loop::*;
 pub use winit::{
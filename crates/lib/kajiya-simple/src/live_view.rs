@@ -0,0 +1,419 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use glam::Vec2;
+use kajiya::{
+    backend::{
+        ash::vk,
+        vk_sync::AccessType,
+        vulkan::{
+            buffer::{Buffer, BufferDesc},
+            image::Image,
+        },
+    },
+    rg::{self, SimpleRenderPass},
+};
+use tungstenite::Message;
+use winit::event::VirtualKeyCode;
+
+// Frame id and extent, padded to 16 bytes. Must match `live_view_readback.hlsl`.
+const READBACK_HEADER_SIZE: usize = 16;
+
+// A readback is only mapped once the GPU is surely done with its frame,
+// which is a few frames after it was recorded.
+const READBACK_LATENCY_FRAMES: u64 = 3;
+const READBACK_SLOT_COUNT: usize = READBACK_LATENCY_FRAMES as usize + 1;
+
+const VIEWER_HTML: &str = include_str!("live_view.html");
+
+/// Input from a browser connected to the live view; see `FrameContext::live_view_events`.
+#[derive(Clone, Debug)]
+pub enum LiveViewEvent {
+    /// The mouse was dragged over the image, by this many CSS pixels.
+    Look { delta: Vec2 },
+
+    /// A key was pressed or released, by its DOM `KeyboardEvent.code`, e.g. `"KeyW"`.
+    /// `virtual_keycode` maps the common ones to winit's.
+    Key { code: String, pressed: bool },
+}
+
+/// Maps a DOM `KeyboardEvent.code` to winit's key code, for letters, digits, arrows,
+/// and the usual modifiers and whitespace keys.
+pub fn virtual_keycode(code: &str) -> Option<VirtualKeyCode> {
+    use VirtualKeyCode::*;
+
+    const LETTERS: [VirtualKeyCode; 26] = [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    ];
+    const DIGITS: [VirtualKeyCode; 10] =
+        [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+
+    let single_char = |prefix: &str| {
+        let rest = code.strip_prefix(prefix)?;
+        (rest.len() == 1).then(|| rest.as_bytes()[0])
+    };
+
+    if let Some(c) = single_char("Key").filter(u8::is_ascii_uppercase) {
+        return Some(LETTERS[(c - b'A') as usize]);
+    }
+    if let Some(c) = single_char("Digit").filter(u8::is_ascii_digit) {
+        return Some(DIGITS[(c - b'0') as usize]);
+    }
+
+    Some(match code {
+        "Space" => Space,
+        "Enter" => Return,
+        "Escape" => Escape,
+        "Tab" => Tab,
+        "Backspace" => Back,
+        "ShiftLeft" => LShift,
+        "ShiftRight" => RShift,
+        "ControlLeft" => LControl,
+        "ControlRight" => RControl,
+        "AltLeft" => LAlt,
+        "AltRight" => RAlt,
+        "ArrowUp" => Up,
+        "ArrowDown" => Down,
+        "ArrowLeft" => Left,
+        "ArrowRight" => Right,
+        _ => return None,
+    })
+}
+
+/// Settings of the live view server; see `SimpleMainLoopBuilder::live_view`.
+#[derive(Clone, Debug)]
+pub struct LiveViewConfig {
+    /// Address to listen on. Defaults to `"127.0.0.1:8787"`, which is only reachable
+    /// from this machine; use e.g. `"0.0.0.0:8787"` to accept remote viewers.
+    pub address: String,
+
+    /// Frames sent to browsers per second, at most
+    pub max_fps: f32,
+
+    /// JPEG quality, `1..=100`
+    pub jpeg_quality: u8,
+}
+
+impl Default for LiveViewConfig {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:8787".to_owned(),
+            max_fps: 30.0,
+            jpeg_quality: 80,
+        }
+    }
+}
+
+struct EncodeRequest {
+    extent: [u32; 2],
+    rgba8: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Shared {
+    // Latest encoded frame, with a sequence number bumped for every frame
+    latest_frame: Mutex<(u64, Option<Arc<Vec<u8>>>)>,
+    events: Mutex<Vec<LiveViewEvent>>,
+    client_count: AtomicUsize,
+}
+
+struct ReadbackSlot {
+    buffer: Arc<Buffer>,
+    pending: Option<(u64, [u32; 2])>,
+}
+
+/// Streams the final frame (without the GUI) to browsers as JPEG images over a WebSocket,
+/// and takes camera input back. Browsers get a minimal viewer page over plain HTTP
+/// at the same address.
+///
+/// Frames are only read back while someone is watching. Encoding runs on a background
+/// thread; frames rendered while it's busy are skipped.
+pub(crate) struct LiveViewServer {
+    config: LiveViewConfig,
+    shared: Arc<Shared>,
+    encoder: mpsc::SyncSender<EncodeRequest>,
+    slots: Vec<ReadbackSlot>,
+    frame_index: u64,
+    last_capture: Option<Instant>,
+}
+
+impl LiveViewServer {
+    pub fn new(config: LiveViewConfig) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(&config.address)?;
+        log::info!("Serving the live view on http://{}", config.address);
+
+        let shared = Arc::new(Shared::default());
+
+        {
+            let shared = shared.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let shared = shared.clone();
+                    std::thread::spawn(move || {
+                        if let Err(err) = serve_connection(stream, &shared) {
+                            log::debug!("Live view connection closed: {:#}", err);
+                        }
+                    });
+                }
+            });
+        }
+
+        let (encoder, encode_requests) = mpsc::sync_channel::<EncodeRequest>(1);
+        {
+            let shared = shared.clone();
+            let quality = config.jpeg_quality.clamp(1, 100);
+            std::thread::spawn(move || {
+                for request in encode_requests {
+                    match encode_jpeg(&request, quality) {
+                        Ok(jpeg) => {
+                            let mut latest = shared.latest_frame.lock().unwrap();
+                            *latest = (latest.0 + 1, Some(Arc::new(jpeg)));
+                        }
+                        Err(err) => log::error!("Failed to encode a live view frame: {:#}", err),
+                    }
+                }
+            });
+        }
+
+        Ok(Self {
+            config,
+            shared,
+            encoder,
+            slots: Vec::new(),
+            frame_index: 0,
+            last_capture: None,
+        })
+    }
+
+    /// Input received since the last call.
+    pub fn take_events(&mut self) -> Vec<LiveViewEvent> {
+        std::mem::take(&mut *self.shared.events.lock().unwrap())
+    }
+
+    /// Hands finished readbacks to the encoder. Must be called once per frame,
+    /// before `capture`.
+    pub fn begin_frame(&mut self) {
+        self.frame_index += 1;
+
+        for slot in &mut self.slots {
+            let (frame_index, extent) = match slot.pending {
+                Some(pending) if pending.0 + READBACK_LATENCY_FRAMES <= self.frame_index => pending,
+                _ => continue,
+            };
+            slot.pending = None;
+
+            let src = if let Some(src) = slot.buffer.allocation.mapped_slice() {
+                src
+            } else {
+                continue;
+            };
+
+            // Frames which failed to prepare never ran, and left stale contents.
+            let header =
+                |idx: usize| u32::from_le_bytes(src[idx * 4..idx * 4 + 4].try_into().unwrap());
+            if header(0) != frame_index as u32 || [header(1), header(2)] != extent {
+                continue;
+            }
+
+            let size = (extent[0] * extent[1]) as usize * 4;
+            let rgba8 = src[READBACK_HEADER_SIZE..READBACK_HEADER_SIZE + size].to_vec();
+
+            // Skipped if the encoder is still busy with an earlier frame.
+            let _ = self.encoder.try_send(EncodeRequest { extent, rgba8 });
+        }
+    }
+
+    /// Reads back `main_img`, the post-processed frame, if anyone is watching
+    /// and it's time for another frame.
+    pub fn capture(&mut self, rg: &mut rg::TemporalRenderGraph, main_img: &rg::Handle<Image>) {
+        if self.shared.client_count.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let interval = Duration::from_secs_f32(1.0 / self.config.max_fps.max(1.0));
+        if matches!(self.last_capture, Some(last) if now - last < interval) {
+            return;
+        }
+
+        let extent = main_img.desc().extent_2d();
+        let size = READBACK_HEADER_SIZE + (extent[0] * extent[1]) as usize * 4;
+
+        if self.slots.len() < READBACK_SLOT_COUNT {
+            self.slots.push(ReadbackSlot {
+                buffer: create_readback_buffer(rg, size),
+                pending: None,
+            });
+        }
+
+        let slot = if let Some(slot) = self.slots.iter_mut().find(|slot| slot.pending.is_none()) {
+            slot
+        } else {
+            return;
+        };
+
+        if slot.buffer.desc.size < size {
+            slot.buffer = create_readback_buffer(rg, size);
+        }
+
+        let mut output = rg.import(slot.buffer.clone(), AccessType::Nothing);
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("live view readback"),
+            "/shaders/live_view_readback.hlsl",
+        )
+        .read(main_img)
+        .write(&mut output)
+        .constants((extent, self.frame_index as u32))
+        .dispatch([extent[0], extent[1], 1]);
+
+        slot.pending = Some((self.frame_index, extent));
+        self.last_capture = Some(now);
+    }
+}
+
+fn create_readback_buffer(rg: &mut rg::TemporalRenderGraph, size: usize) -> Arc<Buffer> {
+    Arc::new(
+        rg.device()
+            .create_buffer(
+                BufferDesc::new_gpu_to_cpu(size, vk::BufferUsageFlags::STORAGE_BUFFER),
+                "live view readback",
+                None,
+            )
+            .expect("live view readback buffer"),
+    )
+}
+
+fn encode_jpeg(request: &EncodeRequest, quality: u8) -> anyhow::Result<Vec<u8>> {
+    let rgb8: Vec<u8> = request
+        .rgba8
+        .chunks_exact(4)
+        .flat_map(|rgba| [rgba[0], rgba[1], rgba[2]])
+        .collect();
+
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, quality).encode(
+        &rgb8,
+        request.extent[0],
+        request.extent[1],
+        image::ColorType::Rgb8,
+    )?;
+
+    Ok(jpeg)
+}
+
+/// Serves the viewer page to plain HTTP requests, and streams frames over WebSocket ones.
+fn serve_connection(mut stream: TcpStream, shared: &Shared) -> anyhow::Result<()> {
+    if !is_websocket_upgrade(&stream)? {
+        // Drain the request, so that closing the socket doesn't reset the connection.
+        let mut request = [0u8; 4096];
+        let _ = stream.read(&mut request)?;
+
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\n\
+             Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            VIEWER_HTML.len(),
+            VIEWER_HTML
+        )?;
+        return Ok(());
+    }
+
+    let mut socket = tungstenite::accept(stream)?;
+
+    // Polled, so that frames can be sent in between.
+    socket
+        .get_mut()
+        .set_read_timeout(Some(Duration::from_millis(5)))?;
+
+    shared.client_count.fetch_add(1, Ordering::Relaxed);
+    let result = stream_frames(&mut socket, shared);
+    shared.client_count.fetch_sub(1, Ordering::Relaxed);
+
+    result
+}
+
+fn stream_frames(
+    socket: &mut tungstenite::WebSocket<TcpStream>,
+    shared: &Shared,
+) -> anyhow::Result<()> {
+    let mut last_sent = 0;
+
+    loop {
+        match socket.read_message() {
+            Ok(Message::Text(text)) => {
+                if let Some(event) = parse_event(&text) {
+                    shared.events.lock().unwrap().push(event);
+                }
+            }
+            Ok(Message::Close(_)) => return Ok(()),
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        let frame = {
+            let latest = shared.latest_frame.lock().unwrap();
+            match &latest.1 {
+                Some(frame) if latest.0 != last_sent => {
+                    last_sent = latest.0;
+                    Some(frame.clone())
+                }
+                _ => None,
+            }
+        };
+
+        if let Some(frame) = frame {
+            socket.write_message(Message::Binary(frame.to_vec()))?;
+        }
+    }
+}
+
+/// Parses a message from `live_view.html`: `look <dx> <dy>`, or `key <code> <0|1>`.
+fn parse_event(text: &str) -> Option<LiveViewEvent> {
+    let mut parts = text.split_whitespace();
+
+    match parts.next()? {
+        "look" => {
+            let dx = parts.next()?.parse().ok()?;
+            let dy = parts.next()?.parse().ok()?;
+            Some(LiveViewEvent::Look {
+                delta: Vec2::new(dx, dy),
+            })
+        }
+        "key" => {
+            let code = parts.next()?.to_owned();
+            let pressed = parts.next()? == "1";
+            Some(LiveViewEvent::Key { code, pressed })
+        }
+        _ => None,
+    }
+}
+
+fn is_websocket_upgrade(stream: &TcpStream) -> anyhow::Result<bool> {
+    let mut buf = [0u8; 4096];
+    let deadline = Instant::now() + Duration::from_secs(5);
+
+    // Wait for the whole request header, without consuming it.
+    loop {
+        let len = stream.peek(&mut buf)?;
+        let header = String::from_utf8_lossy(&buf[..len]).to_ascii_lowercase();
+
+        if header.contains("\r\n\r\n") || len == buf.len() || Instant::now() > deadline {
+            return Ok(header.contains("upgrade: websocket"));
+        }
+        if len == 0 {
+            anyhow::bail!("Connection closed");
+        }
+
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
@@ -1,16 +1,23 @@
 use std::collections::VecDeque;
 
 use kajiya::{
-    backend::{vulkan::RenderBackendConfig, *},
+    backend::{
+        vulkan::{device::RayTracingMode, swapchain::SwapchainColorSpace, RenderBackendConfig},
+        *,
+    },
     frame_desc::WorldFrameDesc,
-    rg,
+    renderers::post,
+    rg::DynamicConstantsTracker,
     ui_renderer::UiRenderer,
-    world_renderer::WorldRenderer,
+    world_renderer::{TemporalUpscaler, WorldRenderer},
 };
 
 #[cfg(feature = "dear-imgui")]
 use kajiya_imgui::ImGuiBackend;
 
+#[cfg(feature = "live-view")]
+use crate::live_view::{LiveViewConfig, LiveViewEvent, LiveViewServer};
+
 use turbosloth::*;
 
 use winit::{
@@ -27,6 +34,14 @@ pub struct FrameContext<'a> {
     pub world_renderer: &'a mut WorldRenderer,
     pub window: &'a winit::window::Window,
 
+    /// Dynamic constants usage of the last frame, per pass
+    pub constants_tracker: &'a mut DynamicConstantsTracker,
+
+    /// Input from browsers watching the live view since the last frame;
+    /// see `SimpleMainLoopBuilder::live_view`. Apps map it to their camera controls.
+    #[cfg(feature = "live-view")]
+    pub live_view_events: &'a [LiveViewEvent],
+
     #[cfg(feature = "dear-imgui")]
     pub imgui: Option<ImguiContext<'a>>,
 }
@@ -67,6 +82,9 @@ struct MainLoopOptional {
 
     #[cfg(feature = "puffin-server")]
     _puffin_server: puffin_http::Server,
+
+    #[cfg(feature = "live-view")]
+    live_view: Option<LiveViewServer>,
 }
 
 pub enum WindowScale {
@@ -86,12 +104,20 @@ pub enum FullscreenMode {
 pub struct SimpleMainLoopBuilder {
     resolution: [u32; 2],
     vsync: bool,
+    color_space: SwapchainColorSpace,
     fullscreen: Option<FullscreenMode>,
     graphics_debugging: bool,
     physical_device_index: Option<usize>,
+    ray_tracing_mode: RayTracingMode,
     default_log_level: log::LevelFilter,
     window_scale: WindowScale,
     temporal_upsampling: f32,
+    temporal_upscaler: Option<TemporalUpscaler>,
+    render_graph_leak_detection_frames: u64,
+    dynamic_constants_call_sites: bool,
+    dynamic_constants_cap_per_pass: Option<usize>,
+    #[cfg(feature = "live-view")]
+    live_view: Option<LiveViewConfig>,
 }
 
 impl Default for SimpleMainLoopBuilder {
@@ -105,12 +131,20 @@ impl SimpleMainLoopBuilder {
         SimpleMainLoopBuilder {
             resolution: [1280, 720],
             vsync: true,
+            color_space: SwapchainColorSpace::Srgb,
             fullscreen: None,
             graphics_debugging: false,
             physical_device_index: None,
+            ray_tracing_mode: RayTracingMode::Pipeline,
             default_log_level: log::LevelFilter::Warn,
             window_scale: WindowScale::SystemNative,
             temporal_upsampling: 1.0,
+            temporal_upscaler: None,
+            render_graph_leak_detection_frames: 0,
+            dynamic_constants_call_sites: false,
+            dynamic_constants_cap_per_pass: None,
+            #[cfg(feature = "live-view")]
+            live_view: None,
         }
     }
 
@@ -124,6 +158,13 @@ impl SimpleMainLoopBuilder {
         self
     }
 
+    /// Requests an HDR swapchain. Falls back to sRGB if the display doesn't support it;
+    /// see `WorldRenderer::hdr_output` for the brightness controls.
+    pub fn color_space(mut self, color_space: SwapchainColorSpace) -> Self {
+        self.color_space = color_space;
+        self
+    }
+
     pub fn graphics_debugging(mut self, graphics_debugging: bool) -> Self {
         self.graphics_debugging = graphics_debugging;
         self
@@ -134,6 +175,13 @@ impl SimpleMainLoopBuilder {
         self
     }
 
+    /// `RayTracingMode::RayQuery` traces shadows, reflections and diffuse GI with inline
+    /// ray queries from compute shaders, for drivers which struggle with ray tracing pipelines.
+    pub fn ray_tracing_mode(mut self, ray_tracing_mode: RayTracingMode) -> Self {
+        self.ray_tracing_mode = ray_tracing_mode;
+        self
+    }
+
     pub fn default_log_level(mut self, default_log_level: log::LevelFilter) -> Self {
         self.default_log_level = default_log_level;
         self
@@ -158,6 +206,42 @@ impl SimpleMainLoopBuilder {
         self
     }
 
+    /// Which upscaler reconstructs the target resolution; see `WorldRenderer::temporal_upscaler`,
+    /// which can also be changed per frame. By default, DLSS when built with it, and TAA otherwise.
+    pub fn temporal_upscaler(mut self, temporal_upscaler: TemporalUpscaler) -> Self {
+        self.temporal_upscaler = Some(temporal_upscaler);
+        self
+    }
+
+    /// Debug mode which warns about render graph resources that go unused for this many frames,
+    /// and logs per-pass GPU memory watermarks on exit. Zero (the default) disables it.
+    pub fn render_graph_leak_detection(mut self, frames: u64) -> Self {
+        self.render_graph_leak_detection_frames = frames;
+        self
+    }
+
+    /// Debug mode which attributes dynamic constants to the source locations which pushed them,
+    /// in addition to passes, and logs the last frame's usage on exit.
+    /// See `FrameContext::constants_tracker`.
+    pub fn dynamic_constants_call_sites(mut self, enabled: bool) -> Self {
+        self.dynamic_constants_call_sites = enabled;
+        self
+    }
+
+    /// Panic as soon as a single pass pushes more than `bytes` of dynamic constants.
+    pub fn dynamic_constants_cap_per_pass(mut self, bytes: usize) -> Self {
+        self.dynamic_constants_cap_per_pass = Some(bytes);
+        self
+    }
+
+    /// Streams the final frame to browsers, which get a viewer page at `config.address`,
+    /// and forwards their input to `FrameContext::live_view_events`.
+    #[cfg(feature = "live-view")]
+    pub fn live_view(mut self, config: LiveViewConfig) -> Self {
+        self.live_view = Some(config);
+        self
+    }
+
     pub fn build(self, window_builder: WindowBuilder) -> anyhow::Result<SimpleMainLoop> {
         SimpleMainLoop::build(self, window_builder)
     }
@@ -247,19 +331,30 @@ impl SimpleMainLoop {
                 vsync: builder.vsync,
                 graphics_debugging: builder.graphics_debugging,
                 device_index: builder.physical_device_index,
+                color_space: builder.color_space,
+                ray_tracing_mode: builder.ray_tracing_mode,
             },
         )?;
 
         let lazy_cache = LazyCache::create();
-        let world_renderer = WorldRenderer::new(
+        let mut world_renderer = WorldRenderer::new(
             render_extent,
             temporal_upscale_extent,
             &render_backend,
             &lazy_cache,
         )?;
+
+        if let Some(temporal_upscaler) = builder.temporal_upscaler {
+            world_renderer.temporal_upscaler = temporal_upscaler;
+        }
+        world_renderer.hdr_output.color_space = render_backend.swapchain.color_space();
         let ui_renderer = UiRenderer::default();
 
-        let rg_renderer = kajiya::rg::renderer::Renderer::new(&render_backend)?;
+        let mut rg_renderer = kajiya::rg::renderer::Renderer::new(&render_backend)?;
+        rg_renderer.memory_tracker.leak_frame_threshold =
+            builder.render_graph_leak_detection_frames;
+        rg_renderer.constants_tracker.track_call_sites = builder.dynamic_constants_call_sites;
+        rg_renderer.constants_tracker.max_bytes_per_pass = builder.dynamic_constants_cap_per_pass;
 
         #[cfg(feature = "dear-imgui")]
         let mut imgui = imgui::Context::create();
@@ -280,6 +375,9 @@ impl SimpleMainLoop {
             puffin_http::Server::new(&server_addr).unwrap()
         };
 
+        #[cfg(feature = "live-view")]
+        let live_view = builder.live_view.map(LiveViewServer::new).transpose()?;
+
         let optional = MainLoopOptional {
             #[cfg(feature = "dear-imgui")]
             imgui_backend,
@@ -287,6 +385,8 @@ impl SimpleMainLoop {
             imgui,
             #[cfg(feature = "puffin-server")]
             _puffin_server: puffin_server,
+            #[cfg(feature = "live-view")]
+            live_view,
         };
 
         Ok(Self {
@@ -419,12 +519,26 @@ impl SimpleMainLoop {
                 }
             };
 
+            #[cfg(feature = "live-view")]
+            let live_view_events = optional
+                .live_view
+                .as_mut()
+                .map(|live_view| {
+                    live_view.begin_frame();
+                    live_view.take_events()
+                })
+                .unwrap_or_default();
+
             let frame_desc = frame_fn(FrameContext {
                 dt_filtered,
                 render_extent,
                 events: &events,
                 world_renderer: &mut world_renderer,
                 window: &window,
+                constants_tracker: &mut rg_renderer.constants_tracker,
+
+                #[cfg(feature = "live-view")]
+                live_view_events: &live_view_events,
 
                 #[cfg(feature = "dear-imgui")]
                 imgui: Some(ImguiContext {
@@ -448,24 +562,20 @@ impl SimpleMainLoop {
                     let main_img = world_renderer.prepare_render_graph(rg, &frame_desc);
                     let ui_img = ui_renderer.prepare_render_graph(rg);
 
+                    #[cfg(feature = "live-view")]
+                    if let Some(live_view) = optional.live_view.as_mut() {
+                        live_view.capture(rg, &main_img);
+                    }
+
                     let mut swap_chain = rg.get_swap_chain();
-                    rg::SimpleRenderPass::new_compute(
-                        rg.add_pass("final blit"),
-                        "/shaders/final_blit.hlsl",
-                    )
-                    .read(&main_img)
-                    .read(&ui_img)
-                    .write(&mut swap_chain)
-                    .constants((
-                        main_img.desc().extent_inv_extent_2d(),
-                        [
-                            swapchain_extent[0] as f32,
-                            swapchain_extent[1] as f32,
-                            1.0 / swapchain_extent[0] as f32,
-                            1.0 / swapchain_extent[1] as f32,
-                        ],
-                    ))
-                    .dispatch([swapchain_extent[0], swapchain_extent[1], 1]);
+                    post::output_transform(
+                        rg,
+                        &main_img,
+                        &ui_img,
+                        &mut swap_chain,
+                        swapchain_extent,
+                        &world_renderer.hdr_output,
+                    );
                 })
             };
 
@@ -500,6 +610,14 @@ impl SimpleMainLoop {
             };
         }
 
+        if rg_renderer.memory_tracker.leak_frame_threshold > 0 {
+            log::info!("{}", rg_renderer.memory_tracker.watermark_report());
+        }
+
+        if rg_renderer.constants_tracker.track_call_sites {
+            log::info!("{}", rg_renderer.constants_tracker.report());
+        }
+
         Ok(())
     }
 }
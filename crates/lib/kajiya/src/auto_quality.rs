@@ -0,0 +1,108 @@
+use log::info;
+
+use crate::{
+    frame_graph_config::{AutoQualityConfig, QualityConfig, QualityTier},
+    world_renderer::WorldRenderer,
+};
+
+/// Progress of the tier selection of `AutoQualityConfig`.
+#[derive(Default)]
+pub(crate) struct AutoQualityState {
+    // `None` until the first frame with automatic selection
+    tier: Option<QualityTier>,
+    gpu_ms_sum: f32,
+    frame_count: u32,
+    skipped_frame_count: u32,
+}
+
+impl AutoQualityState {
+    fn update(&mut self, config: &AutoQualityConfig, gpu_ms: Option<f32>) -> QualityTier {
+        let tier = *self.tier.get_or_insert(QualityTier::Medium);
+        let frame_window = config.frame_window.max(1);
+
+        let gpu_ms = if let Some(gpu_ms) = gpu_ms {
+            gpu_ms
+        } else {
+            return tier;
+        };
+
+        // Frames in flight, and shader compilation hitches after a switch.
+        if self.skipped_frame_count < frame_window {
+            self.skipped_frame_count += 1;
+            return tier;
+        }
+
+        self.gpu_ms_sum += gpu_ms;
+        self.frame_count += 1;
+
+        if self.frame_count < frame_window {
+            return tier;
+        }
+
+        let average_gpu_ms = self.gpu_ms_sum / self.frame_count as f32;
+        self.gpu_ms_sum = 0.0;
+        self.frame_count = 0;
+
+        let next_tier = if average_gpu_ms > config.target_gpu_ms {
+            tier.lower()
+        } else if average_gpu_ms < config.target_gpu_ms * config.raise_threshold {
+            tier.higher()
+        } else {
+            None
+        };
+
+        if let Some(next_tier) = next_tier {
+            info!(
+                "Auto quality: {} -> {} at {:.2}ms GPU time",
+                tier.name(),
+                next_tier.name(),
+                average_gpu_ms
+            );
+
+            self.tier = Some(next_tier);
+            self.skipped_frame_count = 0;
+            next_tier
+        } else {
+            tier
+        }
+    }
+}
+
+impl WorldRenderer {
+    /// Tiers used for the frame being prepared: `frame_graph_config.quality`, or with
+    /// `auto`, the tier selected for all the groups.
+    pub fn active_quality(&self) -> QualityConfig {
+        let config = self.frame_graph_config.quality;
+
+        match (config.auto, self.auto_quality.tier) {
+            (Some(auto), Some(tier)) => QualityConfig {
+                auto: Some(auto),
+                ..QualityConfig::uniform(tier)
+            },
+            _ => config,
+        }
+    }
+
+    /// Advances automatic tier selection with the last GPU timings, and hands the active
+    /// tiers over to the renderers.
+    pub(crate) fn update_quality_tiers(&mut self) {
+        if let Some(auto) = self.frame_graph_config.quality.auto {
+            let timings = self.last_frame_gpu_timings();
+            let gpu_ms = if timings.is_empty() {
+                None
+            } else {
+                Some(timings.iter().map(|(_, ms)| ms).sum())
+            };
+
+            self.auto_quality.update(&auto, gpu_ms);
+        } else {
+            self.auto_quality = Default::default();
+        }
+
+        let quality = self.active_quality();
+        self.rtr.quality = quality.rtr;
+        self.rtdgi.quality = quality.rtdgi;
+        self.ssgi.quality = quality.ssgi;
+        self.volumetric_fog.quality = quality.volumetrics;
+    }
+}
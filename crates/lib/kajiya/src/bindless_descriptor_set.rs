@@ -22,6 +22,12 @@ lazy_static::lazy_static! {
             dimensionality: rspirv_reflect::DescriptorDimensionality::Single,
             name: Default::default(),
         }),
+        // `materials`
+        (3, rspirv_reflect::DescriptorInfo {
+            ty: rspirv_reflect::DescriptorType::STORAGE_BUFFER,
+            dimensionality: rspirv_reflect::DescriptorDimensionality::Single,
+            name: Default::default(),
+        }),
         // `bindless_textures`
         (BINDLESS_TEXURES_BINDING_INDEX as u32, rspirv_reflect::DescriptorInfo {
             ty: rspirv_reflect::DescriptorType::SAMPLED_IMAGE,
@@ -34,7 +40,7 @@ lazy_static::lazy_static! {
     .collect();
 }
 
-pub const BINDLESS_TEXURES_BINDING_INDEX: usize = 3;
+pub const BINDLESS_TEXURES_BINDING_INDEX: usize = 4;
 
 pub fn create_bindless_descriptor_set(device: &device::Device) -> vk::DescriptorSet {
     let raw_device = &device.raw;
@@ -43,6 +49,7 @@ pub fn create_bindless_descriptor_set(device: &device::Device) -> vk::Descriptor
         vk::DescriptorBindingFlags::PARTIALLY_BOUND,
         vk::DescriptorBindingFlags::PARTIALLY_BOUND,
         vk::DescriptorBindingFlags::PARTIALLY_BOUND,
+        vk::DescriptorBindingFlags::PARTIALLY_BOUND,
         vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
             | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING
             | vk::DescriptorBindingFlags::PARTIALLY_BOUND
@@ -79,6 +86,13 @@ pub fn create_bindless_descriptor_set(device: &device::Device) -> vk::Descriptor
                             .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
                             .stage_flags(vk::ShaderStageFlags::ALL)
                             .build(),
+                        // `materials`
+                        vk::DescriptorSetLayoutBinding::builder()
+                            .binding(3)
+                            .descriptor_count(1)
+                            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                            .stage_flags(vk::ShaderStageFlags::ALL)
+                            .build(),
                         // `bindless_textures`
                         vk::DescriptorSetLayoutBinding::builder()
                             .binding(BINDLESS_TEXURES_BINDING_INDEX as _)
@@ -98,7 +112,7 @@ pub fn create_bindless_descriptor_set(device: &device::Device) -> vk::Descriptor
     let descriptor_sizes = [
         vk::DescriptorPoolSize {
             ty: vk::DescriptorType::STORAGE_BUFFER,
-            descriptor_count: 3,
+            descriptor_count: 4,
         },
         vk::DescriptorPoolSize {
             ty: vk::DescriptorType::SAMPLED_IMAGE,
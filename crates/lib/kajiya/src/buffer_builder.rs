@@ -43,6 +43,21 @@ impl<T: Copy> BufferDataSource for Vec<T> {
         std::mem::align_of::<T>() as u64
     }
 }
+impl<T: Copy> BufferDataSource for std::borrow::Cow<'static, [T]> {
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self.as_ptr() as *const u8,
+                self.len() * std::mem::size_of::<T>(),
+            )
+        }
+    }
+
+    fn alignment(&self) -> u64 {
+        std::mem::align_of::<T>() as u64
+    }
+}
+
 pub struct BufferBuilder {
     //buf_slice: &'a mut [u8],
     pending_uploads: Vec<PendingBufferUpload>,
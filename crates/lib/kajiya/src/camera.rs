@@ -1,5 +1,5 @@
 use crate::math::*;
-use rust_shaders_shared::camera::CameraMatrices;
+use rust_shaders_shared::camera::{CameraMatrices, PhysicalCameraParams};
 
 pub trait IntoCameraBodyMatrices {
     fn into_camera_body_matrices(self) -> CameraBodyMatrices;
@@ -42,6 +42,14 @@ pub struct CameraLens {
     pub near_plane_distance: f32,
     pub aspect_ratio: f32,
     pub vertical_fov: f32,
+
+    /// Only affects depth of field; the field of view is independent of it.
+    pub focal_length: f32,
+
+    /// Ratio of the focal length to the aperture diameter. Zero (or infinity) for a pinhole camera.
+    pub f_number: f32,
+
+    pub focus_distance: f32,
 }
 
 impl Default for CameraLens {
@@ -50,6 +58,9 @@ impl Default for CameraLens {
             near_plane_distance: 0.01, // 1mm
             aspect_ratio: 1.0,
             vertical_fov: 52.0,
+            focal_length: 0.05, // 50mm
+            f_number: 0.0,
+            focus_distance: 10.0,
         }
     }
 }
@@ -85,6 +96,31 @@ impl CameraBodyMatrices {
 }
 
 impl CameraLens {
+    /// Scales the lengths of the lens, which default to meters, to scene units;
+    /// see `WorldRenderer::world_units_per_meter`.
+    pub fn with_world_units_per_meter(mut self, world_units_per_meter: f32) -> Self {
+        self.near_plane_distance *= world_units_per_meter;
+        self.focal_length *= world_units_per_meter;
+        self.focus_distance *= world_units_per_meter;
+        self
+    }
+
+    /// Thin lens parameters for `WorldFrameDesc::physical_camera`.
+    pub fn physical_camera(&self) -> PhysicalCameraParams {
+        let aperture_diameter = if self.f_number > 0.0 {
+            self.focal_length / self.f_number
+        } else {
+            0.0
+        };
+
+        PhysicalCameraParams {
+            focal_length: self.focal_length,
+            aperture_diameter,
+            // Can't focus closer than the focal length.
+            focus_distance: self.focus_distance.max(self.focal_length * 1.001),
+        }
+    }
+
     fn calc_matrices(&self) -> CameraLensMatrices {
         let fov = self.vertical_fov.to_radians();
         let znear = self.near_plane_distance;
@@ -0,0 +1,170 @@
+//! Memory layout of the per-frame data bound to every shader at descriptor set 2: `FrameConstants`
+//! and the instance dynamic parameters. Meant for projects maintaining their own HLSL against
+//! `inc/frame_constants.hlsl`, to check that their copy of the structs still matches kajiya's.
+//!
+//! `CONSTANTS_LAYOUT_VERSION` is also defined as `FRAME_CONSTANTS_LAYOUT_VERSION` in
+//! `frame_constants.hlsl`, so shaders can check it with `#if`. On the CPU, compare
+//! `ConstantsLayout::fingerprint` against a stored value, e.g. in a test, to also catch
+//! changes which didn't bump the version.
+
+use std::mem::size_of;
+
+use rust_shaders_shared::frame_constants::FrameConstants;
+
+use crate::world_renderer::InstanceDynamicParameters;
+
+/// Bumped whenever `FrameConstants` or `InstanceDynamicParameters` change layout, or the
+/// meaning of a field changes. Must match `FRAME_CONSTANTS_LAYOUT_VERSION` in `frame_constants.hlsl`.
+pub const CONSTANTS_LAYOUT_VERSION: u32 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FieldLayout {
+    /// Field name, as in the HLSL struct. Members of nested structs are joined with dots,
+    /// e.g. `view_constants.world_to_view`.
+    pub name: &'static str,
+
+    /// Byte offset from the start of the struct
+    pub offset: usize,
+
+    /// Size in bytes
+    pub size: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ConstantsLayout {
+    /// Name of the HLSL struct
+    pub name: &'static str,
+
+    /// See `CONSTANTS_LAYOUT_VERSION`
+    pub version: u32,
+
+    /// Size of the struct in bytes, including padding; also the stride in arrays.
+    pub size: usize,
+
+    /// Fields in memory order. Nested structs are listed along with their members.
+    pub fields: Vec<FieldLayout>,
+}
+
+impl ConstantsLayout {
+    pub fn field(&self, name: &str) -> Option<&FieldLayout> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// FNV-1a hash of the size and of the names, offsets and sizes of the fields.
+    /// Stable across builds and platforms.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut feed = |bytes: &[u8]| {
+            for b in bytes {
+                hash ^= *b as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        };
+
+        feed(&(self.size as u64).to_le_bytes());
+        for field in &self.fields {
+            feed(field.name.as_bytes());
+            feed(&(field.offset as u64).to_le_bytes());
+            feed(&(field.size as u64).to_le_bytes());
+        }
+
+        hash
+    }
+}
+
+fn size_of_pointee<T>(_: *const T) -> usize {
+    size_of::<T>()
+}
+
+// Offset and size of `$($field).+` in `$ty`, named after the field path.
+macro_rules! field_layout {
+    ($ty:ty, $($field:ident).+) => {{
+        let uninit = std::mem::MaybeUninit::<$ty>::uninit();
+        let base = uninit.as_ptr();
+
+        // Only takes the address of the field, without reading it.
+        let field = unsafe { std::ptr::addr_of!((*base).$($field).+) };
+
+        FieldLayout {
+            name: stringify!($($field).+),
+            offset: field as usize - base as usize,
+            size: size_of_pointee(field),
+        }
+    }};
+}
+
+/// Layout of `FrameConstants`, bound as `frame_constants`.
+pub fn frame_constants_layout() -> ConstantsLayout {
+    let fields = vec![
+        field_layout!(FrameConstants, view_constants),
+        field_layout!(FrameConstants, view_constants.view_to_clip),
+        field_layout!(FrameConstants, view_constants.clip_to_view),
+        field_layout!(FrameConstants, view_constants.view_to_sample),
+        field_layout!(FrameConstants, view_constants.sample_to_view),
+        field_layout!(FrameConstants, view_constants.world_to_view),
+        field_layout!(FrameConstants, view_constants.view_to_world),
+        field_layout!(FrameConstants, view_constants.clip_to_prev_clip),
+        field_layout!(FrameConstants, view_constants.prev_view_to_prev_clip),
+        field_layout!(FrameConstants, view_constants.prev_clip_to_prev_view),
+        field_layout!(FrameConstants, view_constants.prev_world_to_prev_view),
+        field_layout!(FrameConstants, view_constants.prev_view_to_prev_world),
+        field_layout!(FrameConstants, view_constants.sample_offset_pixels),
+        field_layout!(FrameConstants, view_constants.sample_offset_clip),
+        field_layout!(FrameConstants, view_constants.focal_length),
+        field_layout!(FrameConstants, view_constants.aperture_diameter),
+        field_layout!(FrameConstants, view_constants.focus_distance),
+        field_layout!(FrameConstants, sun_direction),
+        field_layout!(FrameConstants, frame_index),
+        field_layout!(FrameConstants, delta_time_seconds),
+        field_layout!(FrameConstants, sun_angular_radius_cos),
+        field_layout!(FrameConstants, triangle_light_count),
+        field_layout!(FrameConstants, sun_color_multiplier),
+        field_layout!(FrameConstants, sky_ambient),
+        field_layout!(FrameConstants, pre_exposure),
+        field_layout!(FrameConstants, pre_exposure_prev),
+        field_layout!(FrameConstants, pre_exposure_delta),
+        field_layout!(FrameConstants, gi_history_scale),
+        field_layout!(FrameConstants, render_overrides),
+        field_layout!(FrameConstants, render_overrides.flags),
+        field_layout!(FrameConstants, render_overrides.material_roughness_scale),
+        field_layout!(FrameConstants, point_light_count),
+        field_layout!(FrameConstants, world_units_per_meter),
+        field_layout!(FrameConstants, environment_map_rotation),
+        field_layout!(FrameConstants, environment_map_intensity),
+        field_layout!(FrameConstants, ircache_grid_center),
+        field_layout!(FrameConstants, ircache_cascades),
+        field_layout!(FrameConstants, material_categories),
+        field_layout!(FrameConstants, tonemapper),
+        field_layout!(FrameConstants, wind_gust_texture),
+        field_layout!(FrameConstants, wind_gust_strength),
+        field_layout!(FrameConstants, wind_gust_scale),
+        field_layout!(FrameConstants, wind_direction_speed),
+        field_layout!(FrameConstants, wind_offset_time),
+        field_layout!(FrameConstants, instance_count),
+        field_layout!(FrameConstants, instance_batch_count),
+    ];
+
+    ConstantsLayout {
+        name: "FrameConstants",
+        version: CONSTANTS_LAYOUT_VERSION,
+        size: size_of::<FrameConstants>(),
+        fields,
+    }
+}
+
+/// Layout of `InstanceDynamicParameters`, an element of `instance_dynamic_parameters_dyn`.
+/// Its entries are indexed as described in `FrameConstants::instance_count`.
+pub fn instance_dynamic_parameters_layout() -> ConstantsLayout {
+    ConstantsLayout {
+        name: "InstanceDynamicConstants",
+        version: CONSTANTS_LAYOUT_VERSION,
+        size: size_of::<InstanceDynamicParameters>(),
+        fields: vec![
+            field_layout!(InstanceDynamicParameters, emissive_multiplier),
+            field_layout!(InstanceDynamicParameters, mip_bias),
+            field_layout!(InstanceDynamicParameters, max_anisotropy),
+            field_layout!(InstanceDynamicParameters, flags),
+            field_layout!(InstanceDynamicParameters, gi_reception),
+        ],
+    }
+}
@@ -0,0 +1,274 @@
+use std::sync::Arc;
+
+use glam::Affine3A;
+use kajiya_backend::{
+    ash::vk,
+    vk_sync::AccessType,
+    vulkan::buffer::{Buffer, BufferDesc},
+};
+use kajiya_rg::{self as rg, SimpleRenderPass};
+use log::error;
+
+use crate::world_renderer::{InstanceBatchHandle, MeshHandle, WorldRenderer};
+
+// Enough for one buffer being written by the application, and two frames in flight.
+const CROWD_BUFFER_COUNT: usize = 3;
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub struct CrowdHandle(pub usize);
+
+/// Per-instance record written by the application into the mapped crowd buffers.
+// Must match `CrowdInstance` in `crowd_expand.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CrowdInstance {
+    /// Object-to-world transform, as a row-major 3x4 matrix.
+    pub transform: [f32; 12],
+
+    /// World-space velocity in world units per second; used for motion vectors.
+    pub velocity: [f32; 3],
+
+    pub _pad: u32,
+}
+
+impl CrowdInstance {
+    pub fn new(transform: &Affine3A, velocity: [f32; 3]) -> Self {
+        Self {
+            transform: crate::world_renderer::affine_to_row_major_3x4(transform),
+            velocity,
+            _pad: 0,
+        }
+    }
+}
+
+struct CrowdBuffer {
+    buffer: Arc<Buffer>,
+    count: u32,
+
+    // Value of the universal queue timeline once the GPU is done reading the buffer
+    gpu_done_timeline_value: u64,
+}
+
+pub(crate) struct Crowd {
+    pub(crate) batch: InstanceBatchHandle,
+    capacity: u32,
+    buffers: Vec<CrowdBuffer>,
+
+    // Index into `buffers` of the most recently published one, and its generation
+    latest: Option<(usize, u64)>,
+    next_generation: u64,
+
+    // Generation expanded by the last prepared frame, and the buffer it read
+    expanded_generation: Option<u64>,
+    read_by_pending_frame: Option<usize>,
+}
+
+/// Write access to one of the buffers of a crowd, obtained via `WorldRenderer::begin_crowd_update`.
+///
+/// The memory is mapped, and read directly by the GPU; nothing is copied on the CPU.
+/// Until `publish` is called, frames keep drawing the previously published instances.
+pub struct CrowdUpdate<'a> {
+    crowd: &'a mut Crowd,
+    buffer_index: usize,
+    instances: &'a mut [CrowdInstance],
+}
+
+impl<'a> CrowdUpdate<'a> {
+    /// All `capacity` records of the buffer. Contents are left over from an earlier update,
+    /// so write every instance which will be published. Can be split across threads.
+    pub fn instances(&mut self) -> &mut [CrowdInstance] {
+        self.instances
+    }
+
+    /// Makes the first `count` instances visible from the next frame on; the rest are hidden.
+    pub fn publish(self, count: u32) {
+        assert!(count <= self.crowd.capacity, "crowd capacity exceeded");
+
+        let generation = self.crowd.next_generation;
+        self.crowd.next_generation += 1;
+
+        self.crowd.buffers[self.buffer_index].count = count;
+        self.crowd.latest = Some((self.buffer_index, generation));
+    }
+}
+
+impl WorldRenderer {
+    /// Adds a crowd of up to `capacity` instances of `mesh`, for traffic and crowd simulations
+    /// which move many instances every frame. Instances start hidden; see `begin_crowd_update`.
+    ///
+    /// A crowd is an instance batch, and `crowd_instance_batch` returns it for setting dynamic
+    /// parameters and mapping TLAS indices. Its transforms only exist on the GPU though,
+    /// so `get_instance_batch_transforms` doesn't reflect them. The capacities of all crowds and
+    /// the sizes of all instance batches together are limited to 262,144 instances.
+    pub fn add_crowd(&mut self, mesh: MeshHandle, capacity: u32) -> CrowdHandle {
+        let batch = self.add_instance_batch(mesh, &vec![Affine3A::ZERO; capacity as usize]);
+
+        let buffers = (0..CROWD_BUFFER_COUNT)
+            .map(|_| CrowdBuffer {
+                buffer: Arc::new(
+                    self.device
+                        .create_buffer(
+                            BufferDesc::new_cpu_to_gpu(
+                                capacity.max(1) as usize * std::mem::size_of::<CrowdInstance>(),
+                                vk::BufferUsageFlags::STORAGE_BUFFER,
+                            ),
+                            "crowd instances",
+                            None,
+                        )
+                        .expect("create_buffer"),
+                ),
+                count: 0,
+                gpu_done_timeline_value: 0,
+            })
+            .collect();
+
+        let handle = CrowdHandle(self.next_crowd_handle);
+        self.next_crowd_handle += 1;
+
+        self.crowds.insert(
+            handle,
+            Crowd {
+                batch,
+                capacity,
+                buffers,
+                latest: None,
+                next_generation: 0,
+                expanded_generation: None,
+                read_by_pending_frame: None,
+            },
+        );
+
+        handle
+    }
+
+    /// Stalls until the GPU is done with the crowd's buffers.
+    pub fn remove_crowd(&mut self, crowd: CrowdHandle) {
+        // Removed first, so that `remove_instance_batch` accepts its batch.
+        let crowd = self.crowds.remove(&crowd).expect("no such crowd");
+        self.remove_instance_batch(crowd.batch);
+
+        let gpu_done_timeline_value = crowd
+            .buffers
+            .iter()
+            .map(|buffer| buffer.gpu_done_timeline_value)
+            .max()
+            .unwrap_or_default();
+
+        if let Err(err) = self
+            .device
+            .wait_for_queue(&self.device.universal_queue, gpu_done_timeline_value)
+        {
+            error!("Failed to wait for the GPU to release a crowd: {:?}", err);
+            return;
+        }
+
+        for buffer in crowd.buffers {
+            if let Ok(buffer) = Arc::try_unwrap(buffer.buffer) {
+                self.device.immediate_destroy_buffer(buffer);
+            }
+        }
+    }
+
+    pub fn crowd_instance_batch(&self, crowd: CrowdHandle) -> InstanceBatchHandle {
+        self.crowds[&crowd].batch
+    }
+
+    /// Returns a buffer of the crowd which the GPU is no longer reading, for the application
+    /// to write instances into. This only blocks if the crowd is updated more than once per
+    /// frame, in which case it waits for the GPU to finish a frame.
+    pub fn begin_crowd_update(&mut self, crowd: CrowdHandle) -> CrowdUpdate<'_> {
+        let crowd = self.crowds.get_mut(&crowd).expect("no such crowd");
+        let latest = crowd.latest.map(|(idx, _)| idx);
+
+        let buffer_index = (0..CROWD_BUFFER_COUNT)
+            .filter(|&idx| Some(idx) != latest)
+            .min_by_key(|&idx| crowd.buffers[idx].gpu_done_timeline_value)
+            .unwrap();
+
+        if let Err(err) = self.device.wait_for_queue(
+            &self.device.universal_queue,
+            crowd.buffers[buffer_index].gpu_done_timeline_value,
+        ) {
+            error!(
+                "Failed to wait for the GPU to release a crowd buffer: {:?}",
+                err
+            );
+        }
+
+        let buffer = &crowd.buffers[buffer_index].buffer;
+        let instances = unsafe {
+            std::slice::from_raw_parts_mut(
+                buffer.allocation.mapped_ptr().unwrap().as_ptr() as *mut CrowdInstance,
+                crowd.capacity as usize,
+            )
+        };
+
+        CrowdUpdate {
+            crowd,
+            buffer_index,
+            instances,
+        }
+    }
+
+    /// Writes the latest published instances of every crowd into the batch transforms.
+    /// Returns whether any were written, in which case later passes must import
+    /// the batch transforms as written by a compute shader.
+    pub(crate) fn expand_crowds(&mut self, rg: &mut rg::TemporalRenderGraph) -> bool {
+        if self.crowds.values().all(|crowd| crowd.latest.is_none()) {
+            return false;
+        }
+
+        let mut batch_transforms = rg.import(
+            self.instance_batch_transform_buffer.lock().clone(),
+            AccessType::AnyShaderReadOther,
+        );
+
+        for crowd in self.crowds.values_mut() {
+            let (buffer_index, generation) = if let Some(latest) = crowd.latest {
+                latest
+            } else {
+                continue;
+            };
+
+            let batch =
+                &mut self.instance_batches[self.instance_batch_handle_to_index[&crowd.batch]];
+
+            // Motion vectors only apply on the frame after an update; otherwise the crowd is static.
+            let moved = crowd.expanded_generation != Some(generation);
+            batch.moved = moved;
+
+            let buffer = &crowd.buffers[buffer_index];
+            let instances = rg.import(buffer.buffer.clone(), AccessType::AnyShaderReadOther);
+
+            SimpleRenderPass::new_compute(
+                rg.add_pass("crowd expand"),
+                "/shaders/crowd_expand.hlsl",
+            )
+            .read(&instances)
+            .write(&mut batch_transforms)
+            .constants((
+                batch.transform_offset,
+                crowd.capacity,
+                buffer.count,
+                moved as u32,
+            ))
+            .dispatch([crowd.capacity, 1, 1]);
+
+            crowd.expanded_generation = Some(generation);
+            crowd.read_by_pending_frame = Some(buffer_index);
+        }
+
+        true
+    }
+
+    /// Called once the frame which last expanded the crowds has been submitted.
+    pub(crate) fn retire_crowds(&mut self) {
+        let timeline_value = self.device.universal_queue.last_submitted_value();
+
+        for crowd in self.crowds.values_mut() {
+            if let Some(buffer_index) = crowd.read_by_pending_frame.take() {
+                crowd.buffers[buffer_index].gpu_done_timeline_value = timeline_value;
+            }
+        }
+    }
+}
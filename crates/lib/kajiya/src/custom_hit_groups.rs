@@ -0,0 +1,94 @@
+use kajiya_backend::vulkan::shader::ShaderSource;
+use kajiya_rg as rg;
+
+use crate::world_renderer::{MeshHandle, WorldRenderer};
+
+// Hit shader of all scene geometry, unless assigned a custom hit group
+const DEFAULT_CLOSEST_HIT: &str = "/shaders/rt/gbuffer.rchit.hlsl";
+
+// Any-hit shader of hit groups which don't have their own
+pub(crate) const DEFAULT_ANY_HIT: &str = "/shaders/rt/alpha_test.rahit.hlsl";
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub struct HitGroupHandle(pub usize);
+
+/// Shaders standing in for `rt/gbuffer.rchit.hlsl` on the meshes assigned to them
+/// with `WorldRenderer::set_mesh_hit_group`, e.g. for procedural or stylized materials.
+///
+/// The closest-hit shader must fill in a `GbufferRayPayload` like the default one does,
+/// and gets the same bindings: the TLAS in set 3, and the bindless set 1 with the frame
+/// constants. Ray tracing passes with other payloads, such as `RayQueryRenderer`, keep using
+/// their own hit shaders on these meshes.
+///
+/// The any-hit shader runs for every candidate hit along G-buffer and shadow rays, and can
+/// reject them with `IgnoreHit()`, e.g. for custom cutouts. It replaces the albedo alpha test
+/// of `rt/alpha_test.rahit.hlsl`, which hit groups without one get. Triangles which the baker
+/// found the alpha test never cuts into skip it, as they're built into an opaque BLAS geometry.
+/// Shadow rays carry a `ShadowRayPayload`, so it mustn't access the payload.
+#[derive(Clone)]
+pub struct CustomHitGroup {
+    pub closest_hit: ShaderSource,
+    pub any_hit: Option<ShaderSource>,
+}
+
+impl CustomHitGroup {
+    pub fn new(closest_hit: ShaderSource) -> Self {
+        Self {
+            closest_hit,
+            any_hit: None,
+        }
+    }
+
+    pub fn any_hit(mut self, any_hit: ShaderSource) -> Self {
+        self.any_hit = Some(any_hit);
+        self
+    }
+}
+
+impl WorldRenderer {
+    /// Adds a hit group for `set_mesh_hit_group`. Every ray tracing pipeline with hit shaders
+    /// gets a hit group for each one registered, so keep their number small.
+    pub fn register_hit_group(&mut self, hit_group: CustomHitGroup) -> HitGroupHandle {
+        let handle = HitGroupHandle(self.custom_hit_groups.len());
+        self.custom_hit_groups.push(hit_group);
+        handle
+    }
+
+    /// Shades ray hits against the mesh with a custom hit group, or the default one with `None`.
+    /// Applies to instances ray traced with this mesh: ones using it as their ray tracing proxy,
+    /// but not ones of it which have a proxy. Such instances are kept out of `instance_aggregation`.
+    pub fn set_mesh_hit_group(&mut self, mesh: MeshHandle, hit_group: Option<HitGroupHandle>) {
+        if let Some(hit_group) = hit_group {
+            assert!(
+                hit_group.0 < self.custom_hit_groups.len(),
+                "no such hit group"
+            );
+            self.mesh_hit_groups.insert(mesh, hit_group);
+        } else {
+            self.mesh_hit_groups.remove(&mesh);
+        }
+    }
+
+    pub(crate) fn has_custom_hit_group(&self, mesh: MeshHandle) -> bool {
+        self.mesh_hit_groups.contains_key(&mesh)
+    }
+
+    /// TLAS hit group offset of instances tracing the mesh.
+    /// Hit group `i` is at offset `i + 1`, after the default one.
+    pub(crate) fn mesh_hit_group_offset(&self, mesh: MeshHandle) -> u32 {
+        self.mesh_hit_groups
+            .get(&mesh)
+            .map_or(0, |hit_group| hit_group.0 as u32 + 1)
+    }
+
+    pub(crate) fn predefined_hit_groups(&self) -> Vec<rg::PredefinedHitGroup> {
+        self.custom_hit_groups
+            .iter()
+            .map(|hit_group| rg::PredefinedHitGroup {
+                base_closest_hit: ShaderSource::hlsl(DEFAULT_CLOSEST_HIT),
+                closest_hit: hit_group.closest_hit.clone(),
+                any_hit: hit_group.any_hit.clone(),
+            })
+            .collect()
+    }
+}
@@ -1,13 +1,63 @@
 use glam::Vec3;
 
-use rust_shaders_shared::camera::CameraMatrices;
+use rust_shaders_shared::camera::{CameraMatrices, PhysicalCameraParams};
 
 pub struct WorldFrameDesc {
     pub camera_matrices: CameraMatrices,
 
+    /// Lens parameters for depth of field; see `CameraLens::physical_camera`.
+    pub physical_camera: PhysicalCameraParams,
+
     /// Internal render resolution, before any upsampling
     pub render_extent: [u32; 2],
 
     /// Direction _towards_ the sun.
     pub sun_direction: Vec3,
+
+    /// Angular size of the sun relative to the one seen from Earth (0.53°). Shadow rays sample
+    /// the disk, so this controls the width of penumbrae; zero gives hard shadows.
+    pub sun_size_multiplier: f32,
+
+    /// `RenderFeatureFlags` the view opts out of, on top of those disabled in
+    /// `FrameGraphConfig` and `WorldRenderer::disabled_features`. Zero renders everything.
+    pub disabled_features: u32,
+}
+
+/// Passes of the standard frame graph which can be skipped, with their consumers getting
+/// a cheap stand-in. Auxiliary views (a minimap, a spectator screen) opt out of them via
+/// `WorldFrameDesc::disabled_features`; `WorldRenderer::disabled_features` applies to
+/// every frame, for performance scaling and A/B comparisons.
+pub struct RenderFeatureFlags;
+impl RenderFeatureFlags {
+    /// Ray-traced reflections; lighting then sees no indirect specular.
+    pub const RTR: u32 = 1;
+
+    /// Volumetric fog and light shafts.
+    pub const VOLUMETRICS: u32 = 2;
+
+    /// Full-quality diffuse GI. Without it, screen-space AO is skipped, and ray-traced GI
+    /// does a single spatial reuse pass, without ray-traced reservoir visibility.
+    pub const FULL_QUALITY_GI: u32 = 4;
+
+    /// Screen-space AO; GI then sees an unoccluded AO term.
+    pub const SSGI: u32 = 8;
+
+    /// Ray-traced diffuse GI; lighting then sees no indirect diffuse.
+    pub const RTDGI: u32 = 16;
+
+    /// Ray-traced sun shadows; the sun is then unoccluded.
+    pub const SUN_SHADOWS: u32 = 32;
+
+    /// Temporal anti-aliasing or upscaling, whichever `WorldRenderer::temporal_upscaler`
+    /// selects. Without it, the jittered image is bilinearly resampled to the output extent.
+    pub const TAA: u32 = 64;
+
+    pub const BLOOM: u32 = 128;
+    pub const MOTION_BLUR: u32 = 256;
+}
+
+impl WorldFrameDesc {
+    pub fn is_feature_enabled(&self, feature: u32) -> bool {
+        (self.disabled_features & feature) == 0
+    }
 }
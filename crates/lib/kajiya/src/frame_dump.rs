@@ -0,0 +1,370 @@
+use anyhow::Context;
+use half::f16;
+use kajiya_backend::{ash::vk, vulkan::image::ImageDesc};
+use kajiya_rg::{
+    format_texel_size, CapturedResource, CapturedResourceDesc, FrameCapture, GraphDescription,
+};
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+/// Writes `render_graph.dot` and `render_graph.json` into `dir`; see `GraphDescription`.
+pub fn write_graph_description(graph: &GraphDescription, dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Creating {:?}", dir))?;
+    std::fs::write(dir.join("render_graph.dot"), graph.to_dot())
+        .context("Writing render_graph.dot")?;
+    std::fs::write(dir.join("render_graph.json"), graph.to_json())
+        .context("Writing render_graph.json")?;
+    Ok(())
+}
+
+/// Writes the resources of a `FrameCapture` into `dir`, along with a `frame.json` index.
+///
+/// Images are written per array layer (or depth slice), with channels decoded from their
+/// format: 8-bit normalized color as PNG, and everything else as RGBA EXR, using
+/// 32-bit unsigned integer channels for integer formats. Buffers are written as raw `.bin`
+/// files, each described by a `.json` file next to it.
+pub fn write_frame_capture(capture: &FrameCapture, dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Creating {:?}", dir))?;
+
+    let mut index = String::new();
+    index.push_str("{\n  \"resources\": [");
+
+    for (i, resource) in capture.resources.iter().enumerate() {
+        let data = resource
+            .readback
+            .allocation
+            .mapped_slice()
+            .context("Frame capture readback is not host-visible")?;
+
+        let base_name = format!("{:04}_{}", resource.id, sanitize_file_name(&resource.name));
+
+        let entry = match resource.desc {
+            CapturedResourceDesc::Image(desc) => {
+                let files = write_image(dir, &base_name, &desc, data)
+                    .with_context(|| format!("Writing {:?}", resource.name))?;
+                image_json(resource, &desc, &files)
+            }
+            CapturedResourceDesc::Buffer(desc) => {
+                let file = format!("{}.bin", base_name);
+                std::fs::write(dir.join(&file), data)
+                    .with_context(|| format!("Writing {:?}", file))?;
+
+                let entry = buffer_json(resource, desc.size, desc.usage, &file);
+                std::fs::write(dir.join(format!("{}.json", base_name)), &entry)?;
+                entry
+            }
+        };
+
+        index.push_str(if i == 0 { "\n    " } else { ",\n    " });
+        index.push_str(&entry);
+    }
+
+    index.push_str("\n  ],\n  \"skipped\": [");
+
+    for (i, skipped) in capture.skipped.iter().enumerate() {
+        index.push_str(if i == 0 { "\n    " } else { ",\n    " });
+        write!(
+            index,
+            "{{ \"id\": {}, \"name\": {}, \"reason\": {} }}",
+            skipped.id,
+            json_string(&skipped.name),
+            json_string(&skipped.reason)
+        )?;
+    }
+
+    index.push_str("\n  ]\n}\n");
+    std::fs::write(dir.join("frame.json"), index)?;
+
+    Ok(())
+}
+
+/// A fresh directory for a frame dump, named after the current time.
+pub fn default_frame_dump_dir() -> PathBuf {
+    PathBuf::from("frame_dumps").join(
+        chrono::Local::now()
+            .format("%Y-%m-%d_%H-%M-%S%.3f")
+            .to_string(),
+    )
+}
+
+fn image_json(resource: &CapturedResource, desc: &ImageDesc, files: &[String]) -> String {
+    format!(
+        "{{ \"id\": {}, \"name\": {}, \"kind\": \"image\", \"format\": \"{:?}\", \"image_type\": \"{:?}\", \"extent\": [{}, {}, {}], \"array_layers\": {}, \"mip_levels\": {}, \"files\": [{}] }}",
+        resource.id,
+        json_string(&resource.name),
+        desc.format,
+        desc.image_type,
+        desc.extent[0],
+        desc.extent[1],
+        desc.extent[2],
+        desc.array_elements,
+        desc.mip_levels,
+        files
+            .iter()
+            .map(|file| json_string(file))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+fn buffer_json(
+    resource: &CapturedResource,
+    size: usize,
+    usage: vk::BufferUsageFlags,
+    file: &str,
+) -> String {
+    // The graph doesn't know what buffers contain, so the layout is only described in bytes.
+    format!(
+        "{{ \"id\": {}, \"name\": {}, \"kind\": \"buffer\", \"size\": {}, \"usage\": {}, \"file\": {} }}",
+        resource.id,
+        json_string(&resource.name),
+        size,
+        json_string(&format!("{:?}", usage)),
+        json_string(file)
+    )
+}
+
+enum DecodedTexels {
+    Float(Vec<[f32; 4]>),
+    Uint(Vec<[u32; 4]>),
+    Unorm8(Vec<[u8; 4]>),
+}
+
+/// Returns the names of the files written.
+fn write_image(
+    dir: &Path,
+    base_name: &str,
+    desc: &ImageDesc,
+    data: &[u8],
+) -> anyhow::Result<Vec<String>> {
+    let texel_size = format_texel_size(desc.format).context("Unsupported format")?;
+    let [width, height, depth] = desc.extent;
+    let slice_count = (depth * desc.array_elements) as usize;
+    let slice_size = (width * height) as usize * texel_size;
+
+    let mut files = Vec::with_capacity(slice_count);
+
+    for slice in 0..slice_count {
+        let slice_data = &data[slice * slice_size..(slice + 1) * slice_size];
+        let stem = if slice_count > 1 {
+            format!("{}_{}", base_name, slice)
+        } else {
+            base_name.to_owned()
+        };
+
+        let file = match decode_texels(desc.format, slice_data, texel_size) {
+            DecodedTexels::Unorm8(texels) => {
+                let file = format!("{}.png", stem);
+                image::save_buffer(
+                    dir.join(&file),
+                    bytemuck::cast_slice(&texels),
+                    width,
+                    height,
+                    image::ColorType::Rgba8,
+                )?;
+                file
+            }
+            DecodedTexels::Float(texels) => {
+                let file = format!("{}.exr", stem);
+                exr::prelude::write_rgba_file(
+                    dir.join(&file),
+                    width as usize,
+                    height as usize,
+                    |x, y| {
+                        let [r, g, b, a] = texels[y * width as usize + x];
+                        (r, g, b, a)
+                    },
+                )?;
+                file
+            }
+            DecodedTexels::Uint(texels) => {
+                let file = format!("{}.exr", stem);
+                exr::prelude::write_rgba_file(
+                    dir.join(&file),
+                    width as usize,
+                    height as usize,
+                    |x, y| {
+                        let [r, g, b, a] = texels[y * width as usize + x];
+                        (r, g, b, a)
+                    },
+                )?;
+                file
+            }
+        };
+
+        files.push(file);
+    }
+
+    Ok(files)
+}
+
+fn decode_texels(format: vk::Format, data: &[u8], texel_size: usize) -> DecodedTexels {
+    let texels = data.chunks_exact(texel_size);
+
+    let u8s = |texel: &[u8]| -> [u8; 4] {
+        let mut res = [0, 0, 0, 255];
+        res[..texel.len()].copy_from_slice(texel);
+        res
+    };
+
+    let floats = |texel: &[u8], decode: &dyn Fn(&[u8]) -> f32, size: usize| -> [f32; 4] {
+        let mut res = [0.0, 0.0, 0.0, 1.0];
+        for (dst, src) in res.iter_mut().zip(texel.chunks_exact(size)) {
+            *dst = decode(src);
+        }
+        res
+    };
+
+    let f16_at = |src: &[u8]| f16::from_le_bytes([src[0], src[1]]).to_f32();
+    let f32_at = |src: &[u8]| f32::from_le_bytes([src[0], src[1], src[2], src[3]]);
+    let u16_unorm_at = |src: &[u8]| u16::from_le_bytes([src[0], src[1]]) as f32 / 65535.0;
+    let i16_snorm_at =
+        |src: &[u8]| (i16::from_le_bytes([src[0], src[1]]) as f32 / 32767.0).max(-1.0);
+    let i8_snorm_at = |src: &[u8]| (src[0] as i8 as f32 / 127.0).max(-1.0);
+    let u32_at = |texel: &[u8]| u32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]]);
+
+    match format {
+        vk::Format::R8_UNORM => {
+            DecodedTexels::Unorm8(texels.map(|t| [t[0], t[0], t[0], 255]).collect())
+        }
+        vk::Format::R8G8_UNORM | vk::Format::R8G8B8A8_UNORM | vk::Format::R8G8B8A8_SRGB => {
+            DecodedTexels::Unorm8(texels.map(u8s).collect())
+        }
+        vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB => {
+            DecodedTexels::Unorm8(texels.map(|t| [t[2], t[1], t[0], t[3]]).collect())
+        }
+
+        vk::Format::R8_SNORM | vk::Format::R8G8_SNORM | vk::Format::R8G8B8A8_SNORM => {
+            DecodedTexels::Float(texels.map(|t| floats(t, &i8_snorm_at, 1)).collect())
+        }
+        vk::Format::R16_SFLOAT | vk::Format::R16G16_SFLOAT | vk::Format::R16G16B16A16_SFLOAT => {
+            DecodedTexels::Float(texels.map(|t| floats(t, &f16_at, 2)).collect())
+        }
+        vk::Format::R16_UNORM | vk::Format::R16G16_UNORM | vk::Format::R16G16B16A16_UNORM => {
+            DecodedTexels::Float(texels.map(|t| floats(t, &u16_unorm_at, 2)).collect())
+        }
+        vk::Format::R16G16_SNORM | vk::Format::R16G16B16A16_SNORM => {
+            DecodedTexels::Float(texels.map(|t| floats(t, &i16_snorm_at, 2)).collect())
+        }
+        vk::Format::R32_SFLOAT
+        | vk::Format::D32_SFLOAT
+        | vk::Format::R32G32_SFLOAT
+        | vk::Format::R32G32B32_SFLOAT
+        | vk::Format::R32G32B32A32_SFLOAT => {
+            DecodedTexels::Float(texels.map(|t| floats(t, &f32_at, 4)).collect())
+        }
+
+        vk::Format::A2R10G10B10_UNORM_PACK32 | vk::Format::A2B10G10R10_UNORM_PACK32 => {
+            DecodedTexels::Float(
+                texels
+                    .map(|t| {
+                        let v = u32_at(t);
+                        let lo = (v & 1023) as f32 / 1023.0;
+                        let mid = ((v >> 10) & 1023) as f32 / 1023.0;
+                        let hi = ((v >> 20) & 1023) as f32 / 1023.0;
+                        let a = (v >> 30) as f32 / 3.0;
+
+                        if format == vk::Format::A2R10G10B10_UNORM_PACK32 {
+                            [hi, mid, lo, a]
+                        } else {
+                            [lo, mid, hi, a]
+                        }
+                    })
+                    .collect(),
+            )
+        }
+        vk::Format::B10G11R11_UFLOAT_PACK32 => DecodedTexels::Float(
+            texels
+                .map(|t| {
+                    let v = u32_at(t);
+                    [
+                        unpack_ufloat(v & 0x7ff, 6),
+                        unpack_ufloat((v >> 11) & 0x7ff, 6),
+                        unpack_ufloat(v >> 22, 5),
+                        1.0,
+                    ]
+                })
+                .collect(),
+        ),
+        vk::Format::E5B9G9R9_UFLOAT_PACK32 => DecodedTexels::Float(
+            texels
+                .map(|t| {
+                    let v = u32_at(t);
+                    let scale = 2.0f32.powi((v >> 27) as i32 - 15 - 9);
+                    [
+                        (v & 511) as f32 * scale,
+                        ((v >> 9) & 511) as f32 * scale,
+                        ((v >> 18) & 511) as f32 * scale,
+                        1.0,
+                    ]
+                })
+                .collect(),
+        ),
+
+        // Integer formats
+        _ => {
+            let (channel_size, decode): (usize, &dyn Fn(&[u8]) -> u32) = match format {
+                vk::Format::R8_UINT => (1, &|c: &[u8]| c[0] as u32),
+                vk::Format::R16_UINT => (2, &|c: &[u8]| u16::from_le_bytes([c[0], c[1]]) as u32),
+                _ => (4, &u32_at),
+            };
+
+            DecodedTexels::Uint(
+                texels
+                    .map(|t| {
+                        let mut res = [0u32; 4];
+                        for (dst, src) in res.iter_mut().zip(t.chunks_exact(channel_size)) {
+                            *dst = decode(src);
+                        }
+                        res
+                    })
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// Unsigned float with a 5-bit exponent, as used by packed formats.
+fn unpack_ufloat(bits: u32, mantissa_bits: u32) -> f32 {
+    let mantissa = bits & ((1 << mantissa_bits) - 1);
+    let exponent = bits >> mantissa_bits;
+    let mantissa_scale = (1 << mantissa_bits) as f32;
+
+    match exponent {
+        0 => mantissa as f32 / mantissa_scale * 2.0f32.powi(-14),
+        31 if mantissa == 0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa as f32 / mantissa_scale) * 2.0f32.powi(exponent as i32 - 15),
+    }
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn json_string(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(res, "\\u{:04x}", c as u32);
+            }
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
@@ -0,0 +1,307 @@
+/// Optional passes of the standard frame graph, and their parameters.
+///
+/// Meant to be swapped at runtime (e.g. loaded from a RON or TOML file),
+/// so that different feature sets don't require separate builds.
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct FrameGraphConfig {
+    /// Screen-space ambient occlusion used by diffuse GI. When disabled,
+    /// GI passes see an unoccluded AO term.
+    pub ssgi: bool,
+
+    /// Ray-traced diffuse GI. When disabled, lighting sees no indirect diffuse.
+    pub rtdgi: bool,
+
+    /// Ray-traced reflections. When disabled, lighting sees no indirect specular,
+    /// though triangle lights still contribute specular highlights.
+    pub rtr: bool,
+
+    /// The irradiance cache (successor of CSGI) providing multi-bounce lighting to the other
+    /// GI passes. When disabled, it's no longer traced, and lookups into it return black.
+    pub ircache: bool,
+
+    /// Ray-traced sun shadows. When disabled, the sun is unoccluded.
+    pub ray_traced_shadows: bool,
+
+    pub bloom: BloomConfig,
+    pub dof: DofConfig,
+    pub render_scale: RenderScaleConfig,
+    pub quality: QualityConfig,
+    pub debug_overlays: DebugOverlayConfig,
+
+    /// Produce a jitter-free depth buffer at the output resolution, for depth-tested
+    /// compositing of external content. See `WorldRenderer::prepare_render_graph_with_depth`.
+    pub output_depth: bool,
+}
+
+impl Default for FrameGraphConfig {
+    fn default() -> Self {
+        Self {
+            ssgi: true,
+            rtdgi: true,
+            rtr: true,
+            ircache: true,
+            ray_traced_shadows: true,
+            bloom: Default::default(),
+            dof: Default::default(),
+            render_scale: Default::default(),
+            quality: Default::default(),
+            debug_overlays: Default::default(),
+            output_depth: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct BloomConfig {
+    pub enabled: bool,
+
+    /// Fraction of the blurred image blended over the sharp one. With a `threshold`,
+    /// the blurred highlights are added on top of the sharp image instead.
+    pub amount: f32,
+
+    /// Exposed brightness (one being white before the display transform) below which
+    /// pixels don't bloom. Zero blooms the whole image.
+    pub threshold: f32,
+
+    /// Width of the soft transition around `threshold`, as a fraction of it.
+    pub knee: f32,
+
+    /// Number of blur pyramid mips the bloom is gathered from, starting with the sharpest.
+    /// Fewer mips give a tighter glow. Zero uses all of them.
+    pub mip_count: u32,
+
+    /// Weight of the coarser mips when each level is upsampled into the next finer one.
+    /// Higher values spread the glow wider.
+    pub scatter: f32,
+
+    /// Strength of the lens dirt texture set in `WorldRenderer::bloom_lens_dirt`,
+    /// which modulates an additional, additive copy of the bloom.
+    pub lens_dirt_intensity: f32,
+}
+
+impl Default for BloomConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            amount: 0.05,
+            threshold: 0.0,
+            knee: 0.5,
+            mip_count: 0,
+            scatter: 0.7,
+            lens_dirt_intensity: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DofConfig {
+    /// Blur out-of-focus regions according to `WorldFrameDesc::physical_camera`.
+    /// Has no effect with a pinhole camera (zero aperture).
+    pub enabled: bool,
+
+    /// Focus on whatever is at the center of the screen, instead of the focus distance of the lens.
+    pub autofocus: bool,
+
+    /// How much out-of-focus foreground spills over sharper content behind it (`0..=1`).
+    pub near_field_bleed: f32,
+
+    /// Upper bound on the radius of the circle of confusion, as a fraction of the image height.
+    pub max_coc_radius: f32,
+
+    /// Number of aperture blades shaping the bokeh; fewer than 3 means a round aperture.
+    pub bokeh_blade_count: u32,
+
+    pub bokeh_rotation_degrees: f32,
+
+    /// Width of the bokeh relative to its height (`1.0..`); above 1 mimics anamorphic lenses.
+    pub bokeh_aspect_ratio: f32,
+}
+
+impl Default for DofConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            autofocus: true,
+            near_field_bleed: 1.0,
+            max_coc_radius: 0.015,
+            bokeh_blade_count: 6,
+            bokeh_rotation_degrees: 0.0,
+            bokeh_aspect_ratio: 1.0,
+        }
+    }
+}
+
+/// Resolution of expensive pass groups, relative to the render extent (`0.25..=1.0`).
+///
+/// Each group traces and denoises at its own scale, and is upsampled back to the render
+/// extent with a depth-aware filter before lighting. Post-processing always runs at full
+/// resolution. Changing a scale resets the temporal history of its group.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RenderScaleConfig {
+    /// Ray-traced diffuse GI
+    pub rtdgi: f32,
+
+    /// Ray-traced reflections. Rays traced by RTDGI are only reused
+    /// when both groups run at the same scale.
+    pub rtr: f32,
+}
+
+impl Default for RenderScaleConfig {
+    fn default() -> Self {
+        Self {
+            rtdgi: 1.0,
+            rtr: 1.0,
+        }
+    }
+}
+
+/// Cost and fidelity of a pass group. Each tier compiles the group's shaders
+/// with a different value of its `*_QUALITY` define: 0, 1 or 2.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum QualityTier {
+    Low,
+    Medium,
+    High,
+}
+
+impl Default for QualityTier {
+    fn default() -> Self {
+        Self::Medium
+    }
+}
+
+impl QualityTier {
+    pub const ALL: [QualityTier; 3] = [Self::Low, Self::Medium, Self::High];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Low => "Low",
+            Self::Medium => "Medium",
+            Self::High => "High",
+        }
+    }
+
+    pub fn lower(self) -> Option<Self> {
+        match self {
+            Self::Low => None,
+            Self::Medium => Some(Self::Low),
+            Self::High => Some(Self::Medium),
+        }
+    }
+
+    pub fn higher(self) -> Option<Self> {
+        match self {
+            Self::Low => Some(Self::Medium),
+            Self::Medium => Some(Self::High),
+            Self::High => None,
+        }
+    }
+
+    /// `define` set to the index of the tier, for `ShaderSource::with_defines`.
+    pub fn shader_define(self, define: &str) -> (String, String) {
+        (define.to_owned(), (self as u32).to_string())
+    }
+}
+
+/// Shader quality tiers of the expensive pass groups, the scalability knobs of the renderer.
+///
+/// Every tier of a group is a separate shader permutation, compiled the first time it's used,
+/// so switching to a new one hitches for a moment. Temporal history is kept.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct QualityConfig {
+    /// Ray-traced reflections. `Low` traces hard sun shadows at hit points, and skips triangle
+    /// lights at hits outside of the screen; `High` biases BRDF sampling less, for sharper
+    /// but noisier glossy reflections.
+    pub rtr: QualityTier,
+
+    /// Ray-traced diffuse GI. `Low` skips triangle lights at hits outside of the screen,
+    /// leaving them to the irradiance cache; `High` traces soft sun shadows at hit points.
+    pub rtdgi: QualityTier,
+
+    /// Screen-space AO: 3, 6 or 10 samples per direction.
+    pub ssgi: QualityTier,
+
+    /// Volumetric fog froxel resolution and sun shadow rays; see `VolumetricFogRenderer`.
+    pub volumetrics: QualityTier,
+
+    /// Ray-traced sun shadows: hard with `Low`, and with one or two soft shadow rays per pixel.
+    pub shadows: QualityTier,
+
+    /// Select a single tier for all the groups above from the measured GPU frame time,
+    /// in place of their own tiers. See `WorldRenderer::active_quality`.
+    pub auto: Option<AutoQualityConfig>,
+}
+
+impl QualityConfig {
+    /// `tier` for every group, with automatic selection off
+    pub fn uniform(tier: QualityTier) -> Self {
+        Self {
+            rtr: tier,
+            rtdgi: tier,
+            ssgi: tier,
+            volumetrics: tier,
+            shadows: tier,
+            auto: None,
+        }
+    }
+}
+
+/// Automatic selection of `QualityConfig` tiers. The tier is lowered when the average GPU
+/// frame time over `frame_window` frames exceeds `target_gpu_ms`, and raised when it falls
+/// below `raise_threshold` times that. After each change, the next `frame_window` frames
+/// are skipped, as they still include frames rendered at the previous tier.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct AutoQualityConfig {
+    /// GPU time budget of a frame, in milliseconds
+    pub target_gpu_ms: f32,
+
+    /// Fraction of `target_gpu_ms` below which the tier is raised (`0..1`). Higher tiers
+    /// cost more than the headroom left by lower values, so the selection doesn't oscillate.
+    pub raise_threshold: f32,
+
+    pub frame_window: u32,
+}
+
+impl Default for AutoQualityConfig {
+    fn default() -> Self {
+        Self {
+            target_gpu_ms: 16.0,
+            raise_threshold: 0.6,
+            frame_window: 30,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct DebugOverlayConfig {
+    /// Plot the luminance histogram used for dynamic exposure on top of the image.
+    pub luminance_histogram: bool,
+
+    /// Replace the final image with a false-color map of scene luminance in EV stops
+    /// relative to middle grey (after exposure), with a legend along the bottom edge.
+    pub ev_false_color: bool,
+
+    /// Replace the final image with the motion vectors used by temporal filters, colored
+    /// by direction, with a legend in the bottom right corner.
+    /// See `MotionVectorDebugRenderer::visualization_scale_pixels`.
+    pub motion_vectors: bool,
+
+    /// Read back a histogram of motion vector magnitudes;
+    /// see `MotionVectorDebugRenderer::histogram`.
+    pub motion_vector_histogram: bool,
+}
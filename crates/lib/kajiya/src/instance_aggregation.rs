@@ -0,0 +1,288 @@
+use std::{collections::HashSet, sync::Arc};
+
+use glam::Affine3A;
+use kajiya_backend::vulkan::ray_tracing::{
+    RayTracingAcceleration, RayTracingBottomAccelerationDesc, RayTracingGeometryDesc,
+    RayTracingInstanceDesc,
+};
+use log::warn;
+
+use crate::{
+    buffer_builder::BufferBuilder,
+    world_renderer::{
+        affine_to_row_major_3x4, InstanceFlags, InstanceHandle, MeshHandle, WorldRenderer,
+        MESH_RELEASE_DELAY_FRAMES, TLAS_MASK_SCENE,
+    },
+};
+
+// Must match `RT_INSTANCE_ID_STATIC_AGGREGATE` in `rt_hit.hlsl`
+const TLAS_INSTANCE_ID_STATIC_AGGREGATE: u32 = 1 << 23;
+
+// The geometry table is addressed in multiples of this by the low 23 bits
+// of the instance custom index, which covers the whole 1 GiB vertex buffer.
+const AGGREGATE_TABLE_ALIGNMENT: u64 = 128;
+
+/// Merges small static instances of different meshes into one combined BLAS, to keep
+/// the TLAS small in scenes with tens of thousands of props. Only instances marked with
+/// `WorldRenderer::set_instance_static` are considered.
+///
+/// The BLAS is rebuilt whenever the set of aggregated instances, or any of their transforms,
+/// changes, so only mark instances which are placed once. Aggregated instances keep their TLAS
+/// indices, dynamic parameters and ray query results; they're just inactive in the TLAS.
+#[derive(Clone, Copy, Debug)]
+pub struct InstanceAggregationSettings {
+    pub enabled: bool,
+
+    /// Instances of meshes with more triangles than this are left in the TLAS on their own.
+    pub max_triangle_count: u32,
+}
+
+impl Default for InstanceAggregationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_triangle_count: 4096,
+        }
+    }
+}
+
+// Must match `RtHitInstance::from_ids` in `rt_hit.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuAggregateGeometry {
+    mesh_index: u32,
+    instance_index: u32,
+
+    // Added to `PrimitiveIndex()`; see `mesh_rt_geometries`
+    primitive_offset: u32,
+    _pad: u32,
+
+    // Also read by the BLAS build, as `vk::TransformMatrixKHR`
+    transform: [f32; 12],
+}
+
+// Byte offset of `transform` in `GpuAggregateGeometry`
+const AGGREGATE_GEOMETRY_TRANSFORM_OFFSET: u64 = 16;
+
+pub(crate) struct InstanceAggregate {
+    // Instance index, ray tracing mesh and transform of every aggregated instance
+    key: Vec<(usize, MeshHandle, [f32; 12])>,
+    aggregated: HashSet<usize>,
+    blas: Arc<RayTracingAcceleration>,
+    vertex_range: std::ops::Range<u64>,
+    instance_id: u32,
+}
+
+impl InstanceAggregate {
+    pub(crate) fn contains(&self, instance_index: usize) -> bool {
+        self.aggregated.contains(&instance_index)
+    }
+
+    pub(crate) fn tlas_instance(&self) -> RayTracingInstanceDesc {
+        RayTracingInstanceDesc {
+            blas: Some(self.blas.clone()),
+            transformation: Affine3A::IDENTITY,
+            mesh_index: self.instance_id,
+            mask: TLAS_MASK_SCENE,
+            hit_group_offset: 0,
+            opaque: false,
+        }
+    }
+}
+
+pub(crate) struct PendingAggregateRelease {
+    aggregate: InstanceAggregate,
+    frames_left: u32,
+}
+
+impl WorldRenderer {
+    /// Marks an instance as never moving, making it eligible for `instance_aggregation`.
+    pub fn set_instance_static(&mut self, inst: InstanceHandle, is_static: bool) {
+        assert!(
+            self.instance_handle_to_index.contains_key(&inst),
+            "no such instance"
+        );
+
+        if is_static {
+            self.static_instances.insert(inst);
+        } else {
+            self.static_instances.remove(&inst);
+        }
+    }
+
+    /// Number of instances currently merged into the combined BLAS.
+    pub fn aggregated_instance_count(&self) -> usize {
+        self.instance_aggregate
+            .as_ref()
+            .map_or(0, |aggregate| aggregate.key.len())
+    }
+
+    /// Rebuilds the combined BLAS if the set of static instances changed.
+    /// Must run before the vertex buffer is imported into the render graph.
+    pub(crate) fn update_instance_aggregate(&mut self) {
+        let mut key: Vec<(usize, MeshHandle, [f32; 12])> =
+            if self.instance_aggregation.enabled && self.device.ray_tracing_enabled() {
+                let max_index_count = self.instance_aggregation.max_triangle_count as usize * 3;
+
+                self.static_instances
+                    .iter()
+                    .filter_map(|inst| {
+                        let index = *self.instance_handle_to_index.get(inst)?;
+                        let instance = &self.instances[index];
+
+                        // Viewmodels are invisible to rays, and the combined BLAS is visible
+                        // to all of them; keep those out, as well as ones hidden from GI.
+                        if instance.dynamic_parameters.flags
+                            & (InstanceFlags::VIEWMODEL | InstanceFlags::NO_GI_CONTRIBUTION)
+                            != 0
+                        {
+                            return None;
+                        }
+
+                        // Proxies need separate TLAS instances for shadow and GI rays.
+                        if instance.ray_tracing_proxy.is_some() {
+                            return None;
+                        }
+
+                        // The combined BLAS is shaded by the default hit group.
+                        let mesh = instance.mesh;
+                        if self.has_custom_hit_group(mesh) {
+                            return None;
+                        }
+
+                        let index_count: usize = self
+                            .static_mesh_rt_geometry
+                            .get(&mesh)?
+                            .iter()
+                            .map(|geometry| geometry.parts[0].index_count)
+                            .sum();
+
+                        (index_count <= max_index_count)
+                            .then(|| (index, mesh, affine_to_row_major_3x4(&instance.transform)))
+                    })
+                    .collect()
+            } else {
+                Vec::new()
+            };
+
+        // Merging a single instance gains nothing.
+        if key.len() < 2 {
+            key.clear();
+        }
+
+        key.sort_by_key(|(index, ..)| *index);
+
+        let unchanged = self
+            .instance_aggregate
+            .as_ref()
+            .map_or(key.is_empty(), |aggregate| aggregate.key == key);
+        if unchanged {
+            return;
+        }
+
+        // Frames in flight may still be tracing against the old one.
+        if let Some(aggregate) = self.instance_aggregate.take() {
+            self.pending_aggregate_releases
+                .push(PendingAggregateRelease {
+                    aggregate,
+                    frames_left: MESH_RELEASE_DELAY_FRAMES,
+                });
+        }
+
+        if key.is_empty() {
+            return;
+        }
+
+        // One entry per BLAS geometry, of which meshes have one or two.
+        let mut table: Vec<GpuAggregateGeometry> = Vec::new();
+        let mut geometries: Vec<RayTracingGeometryDesc> = Vec::new();
+        for &(index, mesh, transform) in &key {
+            let mut primitive_offset = 0;
+            for geometry in &self.static_mesh_rt_geometry[&mesh] {
+                table.push(GpuAggregateGeometry {
+                    mesh_index: mesh.0 as u32,
+                    instance_index: index as u32,
+                    primitive_offset,
+                    _pad: 0,
+                    transform,
+                });
+                geometries.push(geometry.clone());
+                primitive_offset += (geometry.parts[0].index_count / 3) as u32;
+            }
+        }
+
+        let table_size = (table.len() * std::mem::size_of::<GpuAggregateGeometry>()) as u64;
+        let vertex_range =
+            self.allocate_vertex_buffer_range(table_size + AGGREGATE_TABLE_ALIGNMENT);
+        let table_offset =
+            (vertex_range.start + AGGREGATE_TABLE_ALIGNMENT - 1) & !(AGGREGATE_TABLE_ALIGNMENT - 1);
+
+        let mut buffer_builder = BufferBuilder::new();
+        buffer_builder.append(table);
+
+        let mut vertex_buffer = self.vertex_buffer.lock();
+        buffer_builder
+            .upload(
+                self.device.as_ref(),
+                Arc::get_mut(&mut *vertex_buffer).expect("refs may not be retained"),
+                table_offset,
+            )
+            .map_err(|err| self.device.report_error(err))
+            .unwrap();
+
+        let table_da = vertex_buffer.device_address(&self.device) + table_offset;
+        drop(vertex_buffer);
+
+        for (i, geometry) in geometries.iter_mut().enumerate() {
+            geometry.transform_buffer = Some(
+                table_da
+                    + (i * std::mem::size_of::<GpuAggregateGeometry>()) as u64
+                    + AGGREGATE_GEOMETRY_TRANSFORM_OFFSET,
+            );
+        }
+
+        let blas = self
+            .device
+            .create_ray_tracing_bottom_acceleration(&RayTracingBottomAccelerationDesc {
+                geometries,
+                allow_update: false,
+            })
+            .expect("blas");
+
+        let table_index = table_offset / AGGREGATE_TABLE_ALIGNMENT;
+        assert!(table_index < TLAS_INSTANCE_ID_STATIC_AGGREGATE as u64);
+
+        self.instance_aggregate = Some(InstanceAggregate {
+            aggregated: key.iter().map(|(index, ..)| *index).collect(),
+            key,
+            blas: Arc::new(blas),
+            vertex_range,
+            instance_id: TLAS_INSTANCE_ID_STATIC_AGGREGATE | table_index as u32,
+        });
+    }
+
+    // Reclaims combined BLASes which are no longer used by any frame in flight.
+    pub(crate) fn release_stale_instance_aggregates(&mut self) {
+        for pending in &mut self.pending_aggregate_releases {
+            pending.frames_left = pending.frames_left.saturating_sub(1);
+        }
+
+        let (released, pending): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut self.pending_aggregate_releases)
+                .into_iter()
+                .partition(|pending| pending.frames_left == 0);
+        self.pending_aggregate_releases = pending;
+
+        for released in released {
+            let aggregate = released.aggregate;
+            self.vertex_buffer_allocator.free(aggregate.vertex_range);
+
+            match Arc::try_unwrap(aggregate.blas) {
+                Ok(blas) => self.device.immediate_destroy_ray_tracing_acceleration(blas),
+                Err(_) => {
+                    warn!("Combined BLAS of static instances is still referenced; leaking it")
+                }
+            }
+        }
+    }
+}
@@ -1,20 +1,36 @@
 pub mod camera;
+pub mod constants_layout;
+pub mod crowd;
+pub mod custom_hit_groups;
 pub mod default_world_renderer;
 pub mod frame_desc;
+pub mod frame_dump;
+pub mod frame_graph_config;
 pub mod image_cache;
 pub mod image_lut;
+pub mod instance_aggregation;
 pub mod logging;
 pub mod lut_renderers;
 pub mod math;
+pub mod mesh_builder;
 pub mod mmap;
+pub mod point_cloud;
+pub mod reference_state;
 pub mod renderers;
+pub mod scene_snapshot;
+pub mod time_of_day;
 pub mod ui_renderer;
+pub mod units;
+pub mod video_texture;
+pub mod wind;
 pub mod world_render_passes;
 pub mod world_renderer;
 pub mod world_renderer_mmap_adapter;
 
+mod auto_quality;
 mod bindless_descriptor_set;
 mod buffer_builder;
+mod range_allocator;
 
 pub use kajiya_asset as asset;
 pub use kajiya_backend as backend;
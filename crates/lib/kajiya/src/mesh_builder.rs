@@ -0,0 +1,263 @@
+use std::borrow::Cow;
+
+use kajiya_asset::mesh::{
+    calculate_tangents, MeshGeometryScale, MeshMaterial, PackedVertex, TexSampler,
+};
+
+use crate::world_renderer::{
+    AddMeshOptions, BindlessImageHandle, MeshHandle, MeshSource, WorldRenderer,
+};
+
+const IDENTITY_MAP_TRANSFORM: [f32; 6] = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Material of a mesh built at runtime. Maps are images added with `WorldRenderer::add_image`;
+/// missing ones are replaced by constant placeholders, leaving just the factors.
+#[derive(Clone, Copy, Debug)]
+pub struct MeshBuilderMaterial {
+    /// Linear RGBA, multiplied by the albedo map
+    pub base_color: [f32; 4],
+    pub roughness: f32,
+    pub metalness: f32,
+
+    /// Linear radiance, multiplied by the emissive map
+    pub emissive: [f32; 3],
+
+    pub albedo_map: Option<BindlessImageHandle>,
+    pub normal_map: Option<BindlessImageHandle>,
+
+    /// Roughness in the red channel, and metalness in green, as in baked meshes
+    pub spec_map: Option<BindlessImageHandle>,
+    pub emissive_map: Option<BindlessImageHandle>,
+}
+
+impl Default for MeshBuilderMaterial {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            roughness: 0.5,
+            metalness: 0.0,
+            emissive: [0.0; 3],
+            albedo_map: None,
+            normal_map: None,
+            spec_map: None,
+            emissive_map: None,
+        }
+    }
+}
+
+impl MeshBuilderMaterial {
+    fn to_mesh_material(self, world_renderer: &mut WorldRenderer) -> MeshMaterial {
+        let mut map_or_placeholder = |map: Option<BindlessImageHandle>, values| {
+            map.unwrap_or_else(|| world_renderer.placeholder_image(values))
+                .0
+        };
+
+        MeshMaterial {
+            base_color_mult: self.base_color,
+            maps: [
+                map_or_placeholder(self.normal_map, [127, 127, 255, 255]),
+                map_or_placeholder(self.spec_map, [255, 255, 127, 255]),
+                map_or_placeholder(self.albedo_map, [255, 255, 255, 255]),
+                map_or_placeholder(self.emissive_map, [255, 255, 255, 255]),
+                map_or_placeholder(None, [0, 0, 0, 255]),
+            ],
+            roughness_mult: self.roughness,
+            metalness_factor: self.metalness,
+            emissive: self.emissive,
+            flags: 0,
+            map_transforms: [IDENTITY_MAP_TRANSFORM; 4],
+            ao_strength: 1.0,
+            map_samplers: [TexSampler::default().index(); 4],
+            transmission: 0.0,
+        }
+    }
+}
+
+/// Geometry and materials of a mesh created at runtime (procedural terrain, CSG, editor
+/// primitives) rather than baked from a glTF file; see `WorldRenderer::add_mesh_from_data`.
+///
+/// `positions` and `normals` are required, and all other per-vertex streams must either
+/// match them in length, or be empty to use defaults: zero UVs, white vertex colors,
+/// and the first material. Missing tangents are calculated from the UVs.
+#[derive(Clone, Default)]
+pub struct MeshBuilder {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub colors: Vec<[f32; 4]>,
+
+    /// Tangent in `xyz`, and bitangent sign in `w`
+    pub tangents: Vec<[f32; 4]>,
+
+    /// Counter-clockwise triangles
+    pub indices: Vec<u32>,
+
+    /// Per vertex, indexing `materials`
+    pub material_ids: Vec<u32>,
+
+    /// A default material is used if empty.
+    pub materials: Vec<MeshBuilderMaterial>,
+}
+
+impl MeshBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn positions(mut self, positions: Vec<[f32; 3]>) -> Self {
+        self.positions = positions;
+        self
+    }
+
+    pub fn normals(mut self, normals: Vec<[f32; 3]>) -> Self {
+        self.normals = normals;
+        self
+    }
+
+    pub fn uvs(mut self, uvs: Vec<[f32; 2]>) -> Self {
+        self.uvs = uvs;
+        self
+    }
+
+    pub fn colors(mut self, colors: Vec<[f32; 4]>) -> Self {
+        self.colors = colors;
+        self
+    }
+
+    pub fn tangents(mut self, tangents: Vec<[f32; 4]>) -> Self {
+        self.tangents = tangents;
+        self
+    }
+
+    pub fn indices(mut self, indices: Vec<u32>) -> Self {
+        self.indices = indices;
+        self
+    }
+
+    pub fn material_ids(mut self, material_ids: Vec<u32>) -> Self {
+        self.material_ids = material_ids;
+        self
+    }
+
+    pub fn material(mut self, material: MeshBuilderMaterial) -> Self {
+        self.materials.push(material);
+        self
+    }
+
+    fn validate(&self) {
+        let vertex_count = self.positions.len();
+        let stream_ok = |len: usize| len == 0 || len == vertex_count;
+
+        assert!(vertex_count > 0, "mesh must not be empty");
+        assert_eq!(
+            self.normals.len(),
+            vertex_count,
+            "normals must match positions"
+        );
+        assert!(stream_ok(self.uvs.len()), "uvs must match positions");
+        assert!(stream_ok(self.colors.len()), "colors must match positions");
+        assert!(
+            stream_ok(self.tangents.len()),
+            "tangents must match positions"
+        );
+        assert!(
+            stream_ok(self.material_ids.len()),
+            "material ids must match positions"
+        );
+
+        assert!(
+            !self.indices.is_empty() && self.indices.len() % 3 == 0,
+            "indices must form triangles"
+        );
+        assert!(
+            self.indices
+                .iter()
+                .all(|&idx| (idx as usize) < vertex_count),
+            "index out of range"
+        );
+
+        let material_count = self.materials.len().max(1);
+        assert!(
+            self.material_ids
+                .iter()
+                .all(|&id| (id as usize) < material_count),
+            "material id out of range"
+        );
+    }
+}
+
+impl WorldRenderer {
+    /// Adds a mesh built at runtime, without going through the asset baking pipeline.
+    /// It's otherwise the same as a baked mesh, and can be instanced, removed, and used
+    /// with `AddMeshOptions::dynamic`.
+    ///
+    /// Panics if the streams of `mesh` don't match up, or if it's empty.
+    pub fn add_mesh_from_data(&mut self, mesh: MeshBuilder, opts: AddMeshOptions) -> MeshHandle {
+        mesh.validate();
+
+        let MeshBuilder {
+            positions,
+            normals,
+            mut uvs,
+            mut colors,
+            mut tangents,
+            indices,
+            mut material_ids,
+            mut materials,
+        } = mesh;
+
+        let vertex_count = positions.len();
+
+        if tangents.is_empty() {
+            tangents = if uvs.is_empty() {
+                vec![[1.0, 0.0, 0.0, 1.0]; vertex_count]
+            } else {
+                calculate_tangents(&indices, &positions, &normals, &uvs)
+            };
+        }
+
+        if uvs.is_empty() {
+            uvs = vec![[0.0; 2]; vertex_count];
+        }
+        if colors.is_empty() {
+            colors = vec![[1.0; 4]; vertex_count];
+        }
+        if material_ids.is_empty() {
+            material_ids = vec![0; vertex_count];
+        }
+        if materials.is_empty() {
+            materials.push(MeshBuilderMaterial::default());
+        }
+
+        let geometry_scale = MeshGeometryScale::compute(&positions, &normals, &indices);
+
+        let verts: Vec<PackedVertex> = positions
+            .iter()
+            .zip(&normals)
+            .map(|(&pos, &normal)| PackedVertex::new(pos, normal))
+            .collect();
+
+        let materials = materials
+            .into_iter()
+            .map(|material| material.to_mesh_material(self))
+            .collect();
+
+        self.add_mesh_impl(
+            MeshSource {
+                verts: Cow::Owned(verts),
+                uvs: Cow::Owned(uvs),
+                tangents: Cow::Owned(tangents),
+                colors: Cow::Owned(colors),
+                // Materials built at runtime aren't classified, so all triangles are alpha-tested.
+                opaque_index_count: 0,
+                indices: Cow::Owned(indices),
+                material_ids: Cow::Owned(material_ids),
+                materials,
+                material_variants: Cow::Borrowed(&[]),
+                geometry_scale,
+            },
+            opts,
+            None,
+        )
+    }
+}
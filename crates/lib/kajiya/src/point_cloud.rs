@@ -0,0 +1,149 @@
+use glam::{Affine3A, Vec3};
+use kajiya_asset::point_cloud::PointCloud;
+
+use crate::{
+    mesh_builder::{MeshBuilder, MeshBuilderMaterial},
+    world_renderer::{AddMeshOptions, InstanceHandle, MeshHandle, WorldRenderer},
+};
+
+/// Shading of point clouds added with `WorldRenderer::add_point_cloud`.
+#[derive(Clone, Copy, Debug)]
+pub struct PointCloudOptions {
+    /// Half the edge length of the square surfel drawn for each point.
+    /// Should be around the point spacing, so that the surfels close up into surfaces.
+    pub splat_radius: f32,
+
+    /// Edge length of the voxels merged into each surfel of the ray tracing proxy.
+    /// Larger voxels make ray tracing cheaper, at the cost of blurrier shadows and GI.
+    pub proxy_voxel_size: f32,
+
+    pub roughness: f32,
+
+    /// Linear RGB albedo of points without colors
+    pub default_color: [f32; 3],
+}
+
+impl Default for PointCloudOptions {
+    fn default() -> Self {
+        Self {
+            splat_radius: 0.01,
+            proxy_voxel_size: 0.05,
+            roughness: 0.8,
+            default_color: [0.5; 3],
+        }
+    }
+}
+
+/// Meshes of a point cloud; instance them with `WorldRenderer::add_point_cloud_instance`.
+#[derive(Clone, Copy, Debug)]
+pub struct PointCloudMeshes {
+    /// One surfel per point, for the rasterizer
+    pub splats: MeshHandle,
+
+    /// Surfels of the voxelized cloud, used for shadows, GI, and reflections
+    pub proxy: MeshHandle,
+}
+
+impl WorldRenderer {
+    /// Turns a point cloud into meshes of surfels. Points with normals get a single surfel
+    /// facing along the normal. Points without them get three crossed surfels facing
+    /// the axes, which look fuzzier; see `PointCloud::estimate_normals`.
+    ///
+    /// Ray tracing uses a voxelized version of the cloud, since millions of surfels
+    /// would make for a huge BLAS, and the detail is lost in GI anyway.
+    pub fn add_point_cloud(
+        &mut self,
+        cloud: &PointCloud,
+        opts: PointCloudOptions,
+    ) -> PointCloudMeshes {
+        assert!(!cloud.positions.is_empty(), "point cloud must not be empty");
+
+        let material = MeshBuilderMaterial {
+            roughness: opts.roughness,
+            ..Default::default()
+        };
+
+        let splats = self.add_mesh_from_data(
+            surfel_mesh(cloud, opts.splat_radius, opts.default_color).material(material),
+            AddMeshOptions::default(),
+        );
+
+        // Slightly larger than the voxels, so that neighboring surfels overlap.
+        let proxy = self.add_mesh_from_data(
+            surfel_mesh(
+                &cloud.voxelize(opts.proxy_voxel_size),
+                opts.proxy_voxel_size * 0.6,
+                opts.default_color,
+            )
+            .material(material),
+            AddMeshOptions::default(),
+        );
+
+        PointCloudMeshes { splats, proxy }
+    }
+
+    /// Adds an instance drawing the surfels of a point cloud, and ray tracing its proxy.
+    pub fn add_point_cloud_instance(
+        &mut self,
+        meshes: PointCloudMeshes,
+        transform: Affine3A,
+    ) -> InstanceHandle {
+        let inst = self.add_instance(meshes.splats, transform);
+        self.set_instance_ray_tracing_proxy(inst, Some(meshes.proxy));
+        inst
+    }
+}
+
+fn surfel_mesh(cloud: &PointCloud, radius: f32, default_color: [f32; 3]) -> MeshBuilder {
+    let mut mesh = MeshBuilder::new();
+
+    let mut add_surfel = |center: Vec3, tangent: Vec3, bitangent: Vec3, color: [f32; 3]| {
+        let normal = tangent.cross(bitangent);
+        let base = mesh.positions.len() as u32;
+
+        for (u, v) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            let pos = center + (tangent * u + bitangent * v) * radius;
+            mesh.positions.push(pos.into());
+            mesh.normals.push(normal.into());
+            mesh.tangents.push(tangent.extend(1.0).into());
+            mesh.colors.push([color[0], color[1], color[2], 1.0]);
+        }
+
+        mesh.indices
+            .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    };
+
+    for (i, pos) in cloud.positions.iter().enumerate() {
+        let center = Vec3::from(*pos);
+        let color = cloud.colors.get(i).copied().unwrap_or(default_color);
+
+        let normal = cloud
+            .normals
+            .get(i)
+            .map(|normal| Vec3::from(*normal).normalize_or_zero())
+            .filter(|normal| *normal != Vec3::ZERO);
+
+        if let Some(normal) = normal {
+            let (tangent, bitangent) = orthonormal_basis(normal);
+            add_surfel(center, tangent, bitangent, color);
+        } else {
+            add_surfel(center, Vec3::Y, Vec3::Z, color);
+            add_surfel(center, Vec3::Z, Vec3::X, color);
+            add_surfel(center, Vec3::X, Vec3::Y, color);
+        }
+    }
+
+    mesh
+}
+
+// "Building an Orthonormal Basis, Revisited" by Duff et al.; `tangent` x `bitangent` = `n`
+fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0f32.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+
+    let tangent = Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x);
+    let bitangent = Vec3::new(b, sign + n.y * n.y * a, -n.y);
+
+    (tangent, bitangent)
+}
@@ -0,0 +1,141 @@
+use std::ops::Range;
+
+/// First-fit allocator of ranges in a linear buffer, reusing the holes left by freed ranges.
+#[derive(Default)]
+pub(crate) struct RangeAllocator {
+    // End of the used part of the buffer
+    used_end: u64,
+
+    // Holes below `used_end`; sorted, and never adjacent
+    free_ranges: Vec<Range<u64>>,
+}
+
+impl RangeAllocator {
+    // First fit in the holes left by freed ranges, or else at the end of the buffer.
+    pub fn allocate(&mut self, size: u64) -> Range<u64> {
+        if size == 0 {
+            return self.used_end..self.used_end;
+        }
+
+        if let Some(idx) = self
+            .free_ranges
+            .iter()
+            .position(|range| range.end - range.start >= size)
+        {
+            let hole = &mut self.free_ranges[idx];
+            let start = hole.start;
+            hole.start += size;
+
+            if hole.start == hole.end {
+                self.free_ranges.remove(idx);
+            }
+
+            return start..start + size;
+        }
+
+        let start = self.used_end;
+        self.used_end += size;
+        start..start + size
+    }
+
+    pub fn free(&mut self, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+
+        let ranges = &mut self.free_ranges;
+        let mut idx = ranges.partition_point(|hole| hole.start < range.start);
+        ranges.insert(idx, range);
+
+        // Merge with the following hole, and then the preceding one.
+        if idx + 1 < ranges.len() && ranges[idx].end == ranges[idx + 1].start {
+            ranges[idx].end = ranges.remove(idx + 1).end;
+        }
+        if idx > 0 && ranges[idx - 1].end == ranges[idx].start {
+            ranges[idx - 1].end = ranges.remove(idx).end;
+            idx -= 1;
+        }
+
+        // A hole at the very end just shrinks the used part of the buffer.
+        if ranges[idx].end == self.used_end {
+            self.used_end = ranges.remove(idx).start;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_consecutively() {
+        let mut allocator = RangeAllocator::default();
+        assert_eq!(allocator.allocate(16), 0..16);
+        assert_eq!(allocator.allocate(32), 16..48);
+        assert_eq!(allocator.allocate(0), 48..48);
+        assert_eq!(allocator.allocate(8), 48..56);
+    }
+
+    #[test]
+    fn reuses_holes_first_fit() {
+        let mut allocator = RangeAllocator::default();
+        let a = allocator.allocate(16);
+        let b = allocator.allocate(16);
+        let _c = allocator.allocate(16);
+
+        allocator.free(a);
+        allocator.free(b);
+
+        // The two holes merged, so the whole of them can be reused.
+        assert_eq!(allocator.allocate(24), 0..24);
+        assert_eq!(allocator.allocate(8), 24..32);
+        assert_eq!(allocator.allocate(8), 48..56);
+    }
+
+    #[test]
+    fn skips_holes_which_are_too_small() {
+        let mut allocator = RangeAllocator::default();
+        let a = allocator.allocate(8);
+        let _b = allocator.allocate(16);
+
+        allocator.free(a);
+        assert_eq!(allocator.allocate(16), 24..40);
+        assert_eq!(allocator.allocate(8), 0..8);
+    }
+
+    #[test]
+    fn merges_holes_on_both_sides() {
+        let mut allocator = RangeAllocator::default();
+        let a = allocator.allocate(8);
+        let b = allocator.allocate(8);
+        let c = allocator.allocate(8);
+        let _d = allocator.allocate(8);
+
+        allocator.free(a);
+        allocator.free(c);
+        allocator.free(b);
+
+        assert_eq!(allocator.free_ranges, vec![0..24]);
+        assert_eq!(allocator.allocate(24), 0..24);
+        assert!(allocator.free_ranges.is_empty());
+    }
+
+    #[test]
+    fn shrinks_when_freeing_the_end() {
+        let mut allocator = RangeAllocator::default();
+        let a = allocator.allocate(8);
+        let b = allocator.allocate(8);
+        let c = allocator.allocate(8);
+
+        allocator.free(b);
+        allocator.free(c);
+
+        // The hole left by `b` reached the end too, so it's merged into the unused part.
+        assert_eq!(allocator.used_end, 8);
+        assert!(allocator.free_ranges.is_empty());
+
+        allocator.free(a);
+        assert_eq!(allocator.used_end, 0);
+        assert_eq!(allocator.allocate(4), 0..4);
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::Context;
+use exr::prelude::*;
+use std::path::Path;
+
+use crate::renderers::reference::ReferenceReadback;
+
+// Custom header attribute holding `ReferenceReadback::sample_count`
+const SAMPLE_COUNT_ATTRIBUTE: &str = "kajiyaReferenceSampleCount";
+
+const BEAUTY_LAYER: &str = "beauty";
+const ALBEDO_LAYER: &str = "albedo";
+const NORMAL_LAYER: &str = "normal";
+
+/// Writes the reference accumulation as a multi-layer RGB EXR with `beauty`, `albedo`
+/// and `normal` layers, and the sample count in a custom header attribute.
+/// The beauty layer holds the mean radiance, viewable as is in any EXR viewer.
+pub fn write_reference_state(state: &ReferenceReadback, path: &Path) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).with_context(|| format!("Creating {:?}", dir))?;
+    }
+
+    let size = (state.extent[0] as usize, state.extent[1] as usize);
+
+    let mut image = Image::from_layers(
+        ImageAttributes::new(IntegerBounds::from_dimensions(size)),
+        vec![
+            rgb_layer(BEAUTY_LAYER, &state.beauty, size),
+            rgb_layer(ALBEDO_LAYER, &state.albedo, size),
+            rgb_layer(NORMAL_LAYER, &state.normal, size),
+        ],
+    );
+
+    image.attributes.other.insert(
+        Text::from(SAMPLE_COUNT_ATTRIBUTE),
+        AttributeValue::I32(state.sample_count.min(i32::MAX as u32) as i32),
+    );
+
+    image.write().to_file(path)?;
+    Ok(())
+}
+
+/// Reads a file written by `write_reference_state`.
+pub fn read_reference_state(path: &Path) -> anyhow::Result<ReferenceReadback> {
+    let image = read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .rgb_channels(
+            |resolution, _channels: &RgbChannels| {
+                (
+                    resolution.width(),
+                    vec![[0.0f32; 3]; resolution.width() * resolution.height()],
+                )
+            },
+            |(width, pixels), position, (r, g, b): (f32, f32, f32)| {
+                pixels[position.y() * *width + position.x()] = [r, g, b];
+            },
+        )
+        .all_layers()
+        .all_attributes()
+        .from_file(path)
+        .with_context(|| format!("Reading {:?}", path))?;
+
+    let sample_count = match image
+        .attributes
+        .other
+        .get(&Text::from(SAMPLE_COUNT_ATTRIBUTE))
+    {
+        Some(AttributeValue::I32(count)) if *count >= 0 => *count as u32,
+        _ => anyhow::bail!(
+            "{:?} has no reference sample count; not a saved reference?",
+            path
+        ),
+    };
+
+    let extent = image
+        .layer_data
+        .first()
+        .map(|layer| [layer.size.width() as u32, layer.size.height() as u32])
+        .context("No layers")?;
+
+    let layer_pixels = |name: &str| -> anyhow::Result<Vec<[f32; 3]>> {
+        let layer = image
+            .layer_data
+            .iter()
+            .find(|layer| {
+                layer
+                    .attributes
+                    .layer_name
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .as_deref()
+                    == Some(name)
+            })
+            .with_context(|| format!("No {:?} layer", name))?;
+
+        if [layer.size.width() as u32, layer.size.height() as u32] != extent {
+            anyhow::bail!("The {:?} layer differs in size from the others", name);
+        }
+
+        Ok(layer.channel_data.pixels.1.clone())
+    };
+
+    Ok(ReferenceReadback {
+        extent,
+        sample_count,
+        beauty: layer_pixels(BEAUTY_LAYER)?,
+        albedo: layer_pixels(ALBEDO_LAYER)?,
+        normal: layer_pixels(NORMAL_LAYER)?,
+    })
+}
+
+fn rgb_layer<'a>(
+    name: &str,
+    pixels: &'a [[f32; 3]],
+    size: (usize, usize),
+) -> Layer<SpecificChannels<impl Fn(Vec2<usize>) -> (f32, f32, f32) + Sync + 'a, RgbChannels>> {
+    Layer::new(
+        size,
+        LayerAttributes::named(name),
+        Encoding::FAST_LOSSLESS,
+        SpecificChannels::rgb(move |position: Vec2<usize>| {
+            let [r, g, b] = pixels[position.y() * size.0 + position.x()];
+            (r, g, b)
+        }),
+    )
+}
@@ -0,0 +1,78 @@
+use kajiya_backend::{ash::vk, vulkan::image::*};
+use kajiya_rg::{self as rg, SimpleRenderPass};
+
+use super::GbufferDepth;
+
+/// Which occlusion terms feed shading. Without ray tracing support, only SSGI is used.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AmbientOcclusionMode {
+    /// Screen-space, see `SsgiRenderer`
+    Ssgi,
+
+    /// Ray-traced, see `RtaoRenderer`. Cheaper and more stable than SSGI,
+    /// but only covers the near field.
+    Rtao,
+
+    /// The product of both: RTAO fills in what SSGI can't see.
+    SsgiTimesRtao,
+}
+
+impl Default for AmbientOcclusionMode {
+    fn default() -> Self {
+        Self::Ssgi
+    }
+}
+
+impl AmbientOcclusionMode {
+    pub const ALL: [AmbientOcclusionMode; 3] = [Self::Ssgi, Self::Rtao, Self::SsgiTimesRtao];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ssgi => "SSGI",
+            Self::Rtao => "RTAO",
+            Self::SsgiTimesRtao => "SSGI * RTAO",
+        }
+    }
+
+    pub fn uses_ssgi(self) -> bool {
+        matches!(self, Self::Ssgi | Self::SsgiTimesRtao)
+    }
+
+    pub fn uses_rtao(self) -> bool {
+        matches!(self, Self::Rtao | Self::SsgiTimesRtao)
+    }
+}
+
+/// Combines the available occlusion terms into the one used for shading,
+/// and applies the per-material `ao_strength`. The screen-space term is multiplied
+/// by the ray-traced one, if any.
+///
+/// The result is a full-res `R8_UNORM` texture, with 1.0 meaning unoccluded.
+/// Material and custom passes should read this rather than the raw SSGI or RTAO output.
+pub fn combine_ao(
+    rg: &mut rg::RenderGraph,
+    gbuffer_depth: &GbufferDepth,
+    ssgi_tex: &rg::Handle<Image>,
+    rtao_tex: Option<&rg::Handle<Image>>,
+    velocity_img: &rg::Handle<Image>,
+) -> rg::ReadOnlyHandle<Image> {
+    let mut output_tex = rg.create(
+        gbuffer_depth
+            .gbuffer
+            .desc()
+            .usage(vk::ImageUsageFlags::empty())
+            .format(vk::Format::R8_UNORM),
+    );
+
+    SimpleRenderPass::new_compute(rg.add_pass("combine ao"), "/shaders/ssgi/combine_ao.hlsl")
+        .read_depth(&gbuffer_depth.depth)
+        .read(ssgi_tex)
+        // Bound again in lieu of the missing ray-traced term, which the shader then skips.
+        .read(rtao_tex.unwrap_or(ssgi_tex))
+        .read(velocity_img)
+        .write(&mut output_tex)
+        .constants(rtao_tex.is_some() as u32)
+        .dispatch(output_tex.desc().extent);
+
+    output_tex.into()
+}
@@ -0,0 +1,232 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::Context;
+use half::f16;
+use kajiya_backend::{
+    ash::vk::{self, ImageUsageFlags},
+    vk_sync::AccessType,
+    vulkan::image::*,
+    Device,
+};
+use kajiya_rg::{self as rg, RenderGraph};
+
+/// Largest `LUT_3D_SIZE` accepted by `CubeLut::parse`.
+pub const MAX_CUBE_LUT_SIZE: u32 = 256;
+
+/// A 3D LUT in the Adobe/Resolve `.cube` format.
+#[derive(Clone, Debug)]
+pub struct CubeLut {
+    /// Entries along each axis
+    pub size: u32,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+
+    /// `size`^3 entries, with red changing fastest, then green, then blue.
+    pub data: Vec<[f32; 3]>,
+}
+
+impl CubeLut {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+        Self::parse(&text).with_context(|| format!("failed to parse {:?}", path))
+    }
+
+    /// Only 3D LUTs are supported; 1D shaper LUTs are rejected.
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut size = None;
+        let mut domain_min = [0.0; 3];
+        let mut domain_max = [1.0; 3];
+        let mut data = Vec::new();
+
+        let parse_rgb = |args: &[&str]| -> anyhow::Result<[f32; 3]> {
+            if args.len() != 3 {
+                anyhow::bail!("expected three values, got {:?}", args);
+            }
+
+            let mut res = [0.0; 3];
+            for (dst, arg) in res.iter_mut().zip(args) {
+                *dst = arg
+                    .parse()
+                    .with_context(|| format!("invalid number: {:?}", arg))?;
+            }
+            Ok(res)
+        };
+
+        for (line_idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            let result = match tokens[0] {
+                "TITLE" => Ok(()),
+                "LUT_1D_SIZE" => Err(anyhow::anyhow!("1D LUTs are not supported")),
+                "LUT_3D_SIZE" => tokens
+                    .get(1)
+                    .and_then(|arg| arg.parse::<u32>().ok())
+                    .filter(|size| (2..=MAX_CUBE_LUT_SIZE).contains(size))
+                    .map(|parsed| size = Some(parsed))
+                    .ok_or_else(|| anyhow::anyhow!("invalid LUT size")),
+                "DOMAIN_MIN" => parse_rgb(&tokens[1..]).map(|v| domain_min = v),
+                "DOMAIN_MAX" => parse_rgb(&tokens[1..]).map(|v| domain_max = v),
+                _ => parse_rgb(&tokens).map(|v| data.push(v)),
+            };
+
+            result.with_context(|| format!("line {}", line_idx + 1))?;
+        }
+
+        let size = size.ok_or_else(|| anyhow::anyhow!("missing LUT_3D_SIZE"))?;
+        let expected_len = (size * size * size) as usize;
+        if data.len() != expected_len {
+            anyhow::bail!(
+                "expected {} entries for a LUT of size {}, got {}",
+                expected_len,
+                size,
+                data.len()
+            );
+        }
+
+        if (0..3).any(|i| domain_max[i] <= domain_min[i]) {
+            anyhow::bail!("empty domain: {:?} to {:?}", domain_min, domain_max);
+        }
+
+        Ok(Self {
+            size,
+            domain_min,
+            domain_max,
+            data,
+        })
+    }
+}
+
+/// Either a `.cube` LUT to upload, or an existing 3D texture, indexed by
+/// display-encoded (sRGB) color in [0, 1] on all axes.
+pub enum ColorGradingLutSource {
+    Cube(CubeLut),
+    Image(Arc<Image>),
+}
+
+impl From<CubeLut> for ColorGradingLutSource {
+    fn from(lut: CubeLut) -> Self {
+        Self::Cube(lut)
+    }
+}
+
+impl From<Arc<Image>> for ColorGradingLutSource {
+    fn from(image: Arc<Image>) -> Self {
+        Self::Image(image)
+    }
+}
+
+/// A LUT ready for `PostProcessRenderer`.
+#[derive(Clone)]
+pub struct ColorGradingLut {
+    image: Arc<Image>,
+    domain_min: [f32; 3],
+    domain_max: [f32; 3],
+}
+
+impl ColorGradingLut {
+    pub fn new(device: &Device, source: ColorGradingLutSource) -> anyhow::Result<Self> {
+        match source {
+            ColorGradingLutSource::Cube(lut) => {
+                let data: Vec<f16> = lut
+                    .data
+                    .iter()
+                    .flat_map(|rgb| {
+                        [
+                            f16::from_f32(rgb[0]),
+                            f16::from_f32(rgb[1]),
+                            f16::from_f32(rgb[2]),
+                            f16::ONE,
+                        ]
+                    })
+                    .collect();
+
+                const PIXEL_BYTES: usize = 8;
+                let size = lut.size as usize;
+
+                let image = device.create_image(
+                    ImageDesc::new_3d(vk::Format::R16G16B16A16_SFLOAT, [lut.size; 3])
+                        .usage(ImageUsageFlags::SAMPLED),
+                    vec![ImageSubResourceData {
+                        data: bytemuck::checked::cast_slice(data.as_slice()),
+                        row_pitch: size * PIXEL_BYTES,
+                        slice_pitch: size * size * PIXEL_BYTES,
+                    }],
+                )?;
+
+                Ok(Self {
+                    image: Arc::new(image),
+                    domain_min: lut.domain_min,
+                    domain_max: lut.domain_max,
+                })
+            }
+            ColorGradingLutSource::Image(image) => {
+                if image.desc.image_type != ImageType::Tex3d {
+                    anyhow::bail!(
+                        "Color grading LUTs must be 3D images; got {:?}",
+                        image.desc.image_type
+                    );
+                }
+
+                Ok(Self {
+                    image,
+                    domain_min: [0.0; 3],
+                    domain_max: [1.0; 3],
+                })
+            }
+        }
+    }
+
+    pub(crate) fn import(&self, rg: &mut RenderGraph) -> rg::Handle<Image> {
+        rg.import(
+            self.image.clone(),
+            AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        )
+    }
+
+    /// Domain min and max, with the LUT size in the last component of the former.
+    pub(crate) fn domain_constants(&self) -> ([f32; 4], [f32; 4]) {
+        let [min_r, min_g, min_b] = self.domain_min;
+        let [max_r, max_g, max_b] = self.domain_max;
+
+        (
+            [min_r, min_g, min_b, self.image.desc.extent[0] as f32],
+            [max_r, max_g, max_b, 0.0],
+        )
+    }
+}
+
+/// The current color grade, and the one being faded out of; see
+/// `WorldRenderer::set_color_grading_lut`.
+pub struct ColorGrading {
+    pub(crate) lut: Option<ColorGradingLut>,
+    pub(crate) previous_lut: Option<ColorGradingLut>,
+
+    /// Cross-fade from the previous grade (or none) at 0, to the current one at 1.
+    pub blend: f32,
+}
+
+impl Default for ColorGrading {
+    fn default() -> Self {
+        Self {
+            lut: None,
+            previous_lut: None,
+            blend: 1.0,
+        }
+    }
+}
+
+impl ColorGrading {
+    pub(crate) fn set_lut(&mut self, lut: Option<ColorGradingLut>) {
+        self.previous_lut = std::mem::replace(&mut self.lut, lut);
+    }
+
+    pub fn has_lut(&self) -> bool {
+        self.lut.is_some()
+    }
+}
@@ -0,0 +1,142 @@
+//! Helpers for compositing externally rendered content (UI, gizmos, passes of
+//! other renderers) over the output of `WorldRenderer::prepare_render_graph`.
+//!
+//! The output is temporally upsampled to `temporal_upscale_extent`, and has the
+//! sub-pixel jitter resolved. Overlays should therefore be rendered at the output
+//! resolution with the un-jittered camera matrices passed in `WorldFrameDesc`,
+//! and composited after kajiya's passes, so that TAA doesn't smear them.
+
+use kajiya_backend::{ash::vk, vk_sync::AccessType, vulkan::image::*};
+use kajiya_rg::{self as rg, BufferDesc, SimpleRenderPass};
+
+/// Resamples the jittered, render-resolution depth buffer to `output_extent`,
+/// undoing the sub-pixel jitter of the current frame. The result is an `R32_SFLOAT`
+/// image with the same reverse-Z convention, matching the un-jittered camera.
+///
+/// Depth is point-sampled, so edges are as sharp as the render resolution allows.
+pub fn unjitter_depth(
+    rg: &mut rg::RenderGraph,
+    depth: &rg::Handle<Image>,
+    output_extent: [u32; 2],
+) -> rg::Handle<Image> {
+    let mut output_tex = rg.create(ImageDesc::new_2d(vk::Format::R32_SFLOAT, output_extent));
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("unjitter depth"),
+        "/shaders/composite/unjitter_depth.hlsl",
+    )
+    .read_depth(depth)
+    .write(&mut output_tex)
+    .constants((
+        depth.desc().extent_inv_extent_2d(),
+        output_tex.desc().extent_inv_extent_2d(),
+    ))
+    .dispatch(output_tex.desc().extent);
+
+    output_tex
+}
+
+/// Blends `overlay` (linear, premultiplied alpha) over `main`; both must have the same extent.
+///
+/// With `depth_test` set to `(main_depth, overlay_depth)`, overlay pixels behind the scene
+/// are discarded. `main_depth` is typically from `WorldRenderer::prepare_render_graph_with_depth`, and
+/// `overlay_depth` uses the same reverse-Z convention, with 0 meaning no overlay geometry.
+pub fn composite_overlay(
+    rg: &mut rg::RenderGraph,
+    main: &rg::Handle<Image>,
+    overlay: &rg::Handle<Image>,
+    depth_test: Option<(&rg::Handle<Image>, &rg::Handle<Image>)>,
+) -> rg::Handle<Image> {
+    let mut output_tex = rg.create(main.desc().usage(vk::ImageUsageFlags::empty()));
+
+    // The depth-tested variant of the shader is the only one binding depth images.
+    let shader = if depth_test.is_some() {
+        "/shaders/composite/composite_overlay_depth_test.hlsl"
+    } else {
+        "/shaders/composite/composite_overlay.hlsl"
+    };
+
+    let mut pass = SimpleRenderPass::new_compute(rg.add_pass("composite overlay"), shader)
+        .read(main)
+        .read(overlay)
+        .write(&mut output_tex);
+
+    if let Some((main_depth, overlay_depth)) = depth_test {
+        pass = pass.read_depth(main_depth).read_depth(overlay_depth);
+    }
+
+    pass.dispatch(output_tex.desc().extent);
+
+    output_tex
+}
+
+/// Copies `depth` (as from `unjitter_depth`) into a `D32_SFLOAT` image, for use as
+/// the depth attachment of raster passes which depth-test against the scene, e.g. in
+/// `WorldRenderer::forward_passes`. Reverse-Z, so use a `GREATER_OR_EQUAL` depth test.
+pub fn depth_attachment(rg: &mut rg::RenderGraph, depth: &rg::Handle<Image>) -> rg::Handle<Image> {
+    let [width, height] = depth.desc().extent_2d();
+
+    // Depth images can't be written from shaders, and only transfer to and from buffers.
+    let mut staging = rg.create(BufferDesc::new_gpu_only(
+        (width * height) as usize * std::mem::size_of::<f32>(),
+        vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+    ));
+    let mut output_tex = rg.create(ImageDesc::new_2d(vk::Format::D32_SFLOAT, [width, height]));
+
+    let copy_region = move |aspect_mask: vk::ImageAspectFlags| vk::BufferImageCopy {
+        image_subresource: vk::ImageSubresourceLayers {
+            aspect_mask,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        },
+        image_extent: vk::Extent3D {
+            width,
+            height,
+            depth: 1,
+        },
+        ..Default::default()
+    };
+
+    {
+        let mut pass = rg.add_pass("depth to buffer");
+        let src_ref = pass.read(depth, AccessType::TransferRead);
+        let dst_ref = pass.write(&mut staging, AccessType::TransferWrite);
+
+        pass.render_parallel(move |api| {
+            unsafe {
+                api.device().raw.cmd_copy_image_to_buffer(
+                    api.cb.raw,
+                    api.resources.image(src_ref).raw,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    api.resources.buffer(dst_ref).raw,
+                    &[copy_region(vk::ImageAspectFlags::COLOR)],
+                );
+            }
+
+            Ok(())
+        });
+    }
+
+    {
+        let mut pass = rg.add_pass("buffer to depth attachment");
+        let src_ref = pass.read(&staging, AccessType::TransferRead);
+        let dst_ref = pass.write(&mut output_tex, AccessType::TransferWrite);
+
+        pass.render_parallel(move |api| {
+            unsafe {
+                api.device().raw.cmd_copy_buffer_to_image(
+                    api.cb.raw,
+                    api.resources.buffer(src_ref).raw,
+                    api.resources.image(dst_ref).raw,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[copy_region(vk::ImageAspectFlags::DEPTH)],
+                );
+            }
+
+            Ok(())
+        });
+    }
+
+    output_tex
+}
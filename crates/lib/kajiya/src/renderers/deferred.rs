@@ -2,7 +2,23 @@ use kajiya_backend::{ash::vk, vulkan::image::*};
 use kajiya_rg::{self as rg};
 use rg::{RenderGraph, SimpleRenderPass};
 
-use super::{ircache::IrcacheRenderState, wrc::WrcRenderState, GbufferDepth};
+use super::{
+    ircache::IrcacheRenderState,
+    sky_visibility::{SkyVisibilityConstants, SkyVisibilityOutput},
+    wrc::WrcRenderState,
+    GbufferDepth,
+};
+
+// Mirrored in `light_gbuffer.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LightGbufferConstants {
+    output_tex_size: [f32; 4],
+    debug_shading_mode: u32,
+    debug_show_wrc: u32,
+    _pad: [u32; 2],
+    sky_visibility: SkyVisibilityConstants,
+}
 
 #[allow(clippy::too_many_arguments)]
 pub fn light_gbuffer(
@@ -17,13 +33,16 @@ pub fn light_gbuffer(
     output: &mut rg::Handle<Image>,
     sky_cube: &rg::Handle<Image>,
     convolved_sky_cube: &rg::Handle<Image>,
+    ao: &rg::Handle<Image>,
+    local_lights: &rg::Handle<Image>,
+    sky_visibility: &SkyVisibilityOutput,
     bindless_descriptor_set: vk::DescriptorSet,
     debug_shading_mode: usize,
     debug_show_wrc: bool,
 ) {
     SimpleRenderPass::new_compute(rg.add_pass("light gbuffer"), "/shaders/light_gbuffer.hlsl")
         .read(&gbuffer_depth.gbuffer)
-        .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(&gbuffer_depth.depth)
         .read(shadow_mask)
         .read(rtr)
         .read(rtdgi)
@@ -33,11 +52,17 @@ pub fn light_gbuffer(
         .write(output)
         .read(sky_cube)
         .read(convolved_sky_cube)
-        .constants((
-            gbuffer_depth.gbuffer.desc().extent_inv_extent_2d(),
-            debug_shading_mode as u32,
-            debug_show_wrc as u32,
-        ))
+        .read(ao)
+        .read(local_lights)
+        .read(&sky_visibility.heightmap)
+        .read(&sky_visibility.visibility)
+        .constants(LightGbufferConstants {
+            output_tex_size: gbuffer_depth.gbuffer.desc().extent_inv_extent_2d(),
+            debug_shading_mode: debug_shading_mode as u32,
+            debug_show_wrc: debug_show_wrc as u32,
+            _pad: [0; 2],
+            sky_visibility: sky_visibility.constants,
+        })
         .raw_descriptor_set(1, bindless_descriptor_set)
         .dispatch(gbuffer_depth.gbuffer.desc().extent);
 }
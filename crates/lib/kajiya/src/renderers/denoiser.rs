@@ -0,0 +1,142 @@
+use kajiya_backend::{ash::vk, vulkan::image::*};
+use kajiya_rg::{self as rg, SimpleRenderPass};
+
+use super::GbufferDepth;
+
+/// A noisy signal of the standard frame graph, which can be filtered by a `Denoiser`
+/// other than the built-in one.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum DenoiserSignal {
+    /// Sun visibility in the red channel: one where unshadowed
+    SunShadows,
+
+    /// Pre-exposed specular radiance in RGB
+    Reflections,
+
+    /// Pre-exposed irradiance in RGB
+    DiffuseGi,
+}
+
+impl DenoiserSignal {
+    pub const ALL: [DenoiserSignal; 3] = [Self::SunShadows, Self::Reflections, Self::DiffuseGi];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::SunShadows => "Sun shadows",
+            Self::Reflections => "Reflections",
+            Self::DiffuseGi => "Diffuse GI",
+        }
+    }
+}
+
+/// What a `Denoiser` gets to work with. All the images are at the resolution of the signal's
+/// pass group (see `FrameGraphConfig::render_scale`).
+pub struct DenoiserInput<'a> {
+    pub signal: DenoiserSignal,
+    pub noisy: &'a rg::Handle<Image>,
+    pub gbuffer_depth: &'a GbufferDepth,
+
+    /// See `reprojection::calculate_reprojection_map`
+    pub reprojection_map: &'a rg::Handle<Image>,
+
+    /// Reflections only: the length of the traced rays, in the red channel
+    pub hit_distance: Option<&'a rg::Handle<Image>>,
+
+    pub bindless_descriptor_set: vk::DescriptorSet,
+}
+
+/// Filters one of the noisy signals of the standard frame graph in place of the built-in
+/// denoiser, e.g. a ReBLUR-style filter, or a vendor library. Registered with `Denoisers::add`.
+pub trait Denoiser {
+    /// Shown when comparing denoisers, e.g. in the `view` app
+    fn name(&self) -> &str;
+
+    /// Records the passes filtering `input.noisy`, and returns the result, with the signal
+    /// in the same channels, at the same extent. Any temporal history is kept by the denoiser,
+    /// e.g. in a `PingPongTemporalResource` named after it.
+    fn denoise(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        input: &DenoiserInput,
+    ) -> rg::Handle<Image>;
+
+    /// Called when the denoiser becomes active, as its temporal history may be stale by then.
+    fn reset(&mut self) {}
+}
+
+/// The denoisers of a `WorldRenderer`, and which of them is active for each signal.
+/// Without an active one, a signal is filtered by the built-in denoiser.
+#[derive(Default)]
+pub struct Denoisers {
+    denoisers: [Vec<Box<dyn Denoiser>>; 3],
+    active: [Option<usize>; 3],
+    reset_pending: [bool; 3],
+}
+
+impl Denoisers {
+    /// Registers a denoiser for `signal`, without activating it; returns its index.
+    pub fn add(&mut self, signal: DenoiserSignal, denoiser: Box<dyn Denoiser>) -> usize {
+        let denoisers = &mut self.denoisers[signal as usize];
+        denoisers.push(denoiser);
+        denoisers.len() - 1
+    }
+
+    pub fn names(&self, signal: DenoiserSignal) -> impl Iterator<Item = &str> {
+        self.denoisers[signal as usize]
+            .iter()
+            .map(|denoiser| denoiser.name())
+    }
+
+    /// The index of the active denoiser for `signal`, or `None` for the built-in one.
+    pub fn active(&self, signal: DenoiserSignal) -> Option<usize> {
+        self.active[signal as usize]
+    }
+
+    /// Switches `signal` to the denoiser at `index`, or back to the built-in one with `None`.
+    /// Takes effect from the next frame, and resets the newly active denoiser.
+    pub fn set_active(&mut self, signal: DenoiserSignal, index: Option<usize>) {
+        if let Some(index) = index {
+            assert!(
+                index < self.denoisers[signal as usize].len(),
+                "No {} denoiser at index {}",
+                signal.name(),
+                index
+            );
+        }
+
+        if self.active[signal as usize] != index {
+            self.active[signal as usize] = index;
+            self.reset_pending[signal as usize] = true;
+        }
+    }
+
+    /// Runs the active denoiser of `input.signal`, if any; `None` means the caller should
+    /// run the built-in one.
+    pub(crate) fn denoise(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        input: &DenoiserInput,
+    ) -> Option<rg::Handle<Image>> {
+        let signal = input.signal as usize;
+        let denoiser = &mut self.denoisers[signal][self.active[signal]?];
+
+        if std::mem::take(&mut self.reset_pending[signal]) {
+            denoiser.reset();
+        }
+
+        Some(denoiser.denoise(rg, input))
+    }
+}
+
+/// Writes the output of a `Denoiser` into the temporal history of the built-in one,
+/// which the tracing passes of the signal read back, e.g. for ReSTIR validation.
+pub(super) fn copy_to_history(
+    rg: &mut rg::TemporalRenderGraph,
+    denoised: &rg::Handle<Image>,
+    history: &mut rg::Handle<Image>,
+) {
+    SimpleRenderPass::new_compute(rg.add_pass("denoiser history"), "/shaders/copy_color.hlsl")
+        .read(denoised)
+        .write(history)
+        .dispatch(history.desc().extent);
+}
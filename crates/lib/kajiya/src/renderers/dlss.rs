@@ -255,6 +255,11 @@ impl DlssRenderer {
         }
     }
 
+    /// Drops the history, e.g. when switching to DLSS from another upscaler.
+    pub fn reset(&mut self) {
+        self.frame_idx = 0;
+    }
+
     pub fn render(
         &mut self,
         rg: &mut rg::TemporalRenderGraph,
@@ -2,15 +2,22 @@ use kajiya_backend::{ash::vk, vulkan::image::*};
 use kajiya_rg::{self as rg};
 use rg::{RenderGraph, SimpleRenderPass};
 
+use crate::frame_graph_config::DofConfig;
+
+/// Thin lens depth of field, applied after temporal anti-aliasing and upsampling,
+/// so that the blur is stable, and sized in output pixels.
+///
+/// `depth` is at render resolution, and doesn't need to match the extent of `input`.
 pub fn dof(
     rg: &mut RenderGraph,
     input: &rg::Handle<Image>,
     depth: &rg::Handle<Image>,
+    config: &DofConfig,
 ) -> rg::Handle<Image> {
-    let mut coc = rg.create(ImageDesc::new_2d(
-        vk::Format::R16_SFLOAT,
-        input.desc().extent_2d(),
-    ));
+    let output_extent = input.desc().extent_2d();
+    let max_coc_px = (config.max_coc_radius * output_extent[1] as f32).max(0.0);
+
+    let mut coc = rg.create(ImageDesc::new_2d(vk::Format::R16_SFLOAT, output_extent));
 
     let mut coc_tiles = rg.create(ImageDesc::new_2d(
         vk::Format::R16_SFLOAT,
@@ -18,23 +25,49 @@ pub fn dof(
     ));
 
     SimpleRenderPass::new_compute(rg.add_pass("coc"), "/shaders/dof/coc.hlsl")
-        .read_aspect(depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(depth)
         .write(&mut coc)
         .write(&mut coc_tiles)
+        .constants((
+            coc.desc().extent_inv_extent_2d(),
+            max_coc_px,
+            config.autofocus as u32,
+        ))
         .dispatch(coc.desc().extent);
 
+    let mut dilated_coc_tiles = rg.create(*coc_tiles.desc());
+    let tile_extent = coc_tiles.desc().extent_2d();
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("coc tile dilate"),
+        "/shaders/dof/tile_dilate.hlsl",
+    )
+    .read(&coc_tiles)
+    .write(&mut dilated_coc_tiles)
+    .constants((
+        tile_extent[0] as i32,
+        tile_extent[1] as i32,
+        (max_coc_px / 8.0).ceil() as i32,
+    ))
+    .dispatch(dilated_coc_tiles.desc().extent);
+
     let mut dof = rg.create(ImageDesc::new_2d(
         vk::Format::R16G16B16A16_SFLOAT,
-        input.desc().extent_2d(),
+        output_extent,
     ));
 
     SimpleRenderPass::new_compute(rg.add_pass("dof gather"), "/shaders/dof/gather.hlsl")
-        .read_aspect(depth, vk::ImageAspectFlags::DEPTH)
         .read(input)
         .read(&coc)
-        .read(&coc_tiles)
+        .read(&dilated_coc_tiles)
         .write(&mut dof)
-        .constants(dof.desc().extent_inv_extent_2d())
+        .constants((
+            dof.desc().extent_inv_extent_2d(),
+            config.near_field_bleed.clamp(0.0, 1.0),
+            config.bokeh_blade_count,
+            config.bokeh_rotation_degrees.to_radians(),
+            config.bokeh_aspect_ratio.max(1.0),
+        ))
         .dispatch(dof.desc().extent);
 
     dof
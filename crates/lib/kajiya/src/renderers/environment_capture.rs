@@ -0,0 +1,118 @@
+use glam::Vec3;
+use kajiya_backend::{
+    ash::vk,
+    vulkan::{image::*, ray_tracing::RayTracingAcceleration},
+};
+use kajiya_rg::{self as rg, GetOrCreateTemporal};
+
+use super::{ircache::IrcacheRenderState, reflection_probes::ReflectionProbeRenderer};
+
+const CAPTURE_RESOLUTION: u32 = 32;
+
+// Mip `i` is prefiltered for the perceptual roughness `i / (CAPTURE_MIP_COUNT - 1)`.
+// Must match `ENVIRONMENT_CAPTURE_MIP_COUNT` in `inc/environment_capture.hlsl`
+const CAPTURE_MIP_COUNT: u16 = 6;
+
+pub struct EnvironmentCaptureOutput {
+    /// Prefiltered radiance around `position`, as a single-element cube array.
+    /// Sample with `sample_environment_capture` from `inc/environment_capture.hlsl`.
+    pub cube: rg::ReadOnlyHandle<Image>,
+
+    /// Where the cube was captured from; not necessarily the current camera position.
+    pub position: Vec3,
+}
+
+/// A low-res cube map of the surroundings of the camera, recaptured every few frames,
+/// as a cheap source of specular reflections for translucent surfaces and particles drawn
+/// in `WorldRenderer::forward_passes`, which can't afford reflection rays of their own.
+///
+/// Captured with the same ray-traced path as `ReflectionProbeRenderer`: lit by the sun,
+/// the irradiance cache and the sky, and prefiltered for a range of roughness values.
+///
+/// Requires ray tracing; without it, nothing is captured.
+pub struct EnvironmentCaptureRenderer {
+    pub enabled: bool,
+
+    /// Frames between captures. Lower values follow the camera and changing lighting more
+    /// closely, at the cost of tracing a cube map more often.
+    pub capture_interval: u32,
+
+    last_capture: Option<(Vec3, u32)>,
+}
+
+impl Default for EnvironmentCaptureRenderer {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capture_interval: 8,
+            last_capture: None,
+        }
+    }
+}
+
+impl EnvironmentCaptureRenderer {
+    /// Forgets the capture, e.g. after a camera cut, and recaptures next frame.
+    pub fn invalidate(&mut self) {
+        self.last_capture = None;
+    }
+
+    /// Recaptures the cube if due, and returns the latest capture, if any. The irradiance
+    /// cache must already be prepared for sampling.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        eye_position: Vec3,
+        frame_index: u32,
+        sky_cube: &rg::Handle<Image>,
+        ircache: &mut IrcacheRenderState,
+        tlas: Option<&rg::Handle<RayTracingAcceleration>>,
+        bindless_descriptor_set: vk::DescriptorSet,
+    ) -> Option<EnvironmentCaptureOutput> {
+        let tlas = match tlas {
+            Some(tlas) if self.enabled => tlas,
+            _ => {
+                self.last_capture = None;
+                return None;
+            }
+        };
+
+        let mut cube = rg
+            .get_or_create_temporal(
+                "environment_capture.cube",
+                ImageDesc::new_cube(vk::Format::R16G16B16A16_SFLOAT, CAPTURE_RESOLUTION)
+                    .image_type(ImageType::CubeArray)
+                    .mip_levels(CAPTURE_MIP_COUNT)
+                    .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE),
+            )
+            .unwrap();
+
+        let capture_due = match self.last_capture {
+            Some((_, captured_frame)) => {
+                frame_index.wrapping_sub(captured_frame) >= self.capture_interval.max(1)
+            }
+            None => true,
+        };
+
+        if capture_due {
+            ReflectionProbeRenderer::capture(
+                rg,
+                eye_position,
+                CAPTURE_RESOLUTION,
+                0,
+                &mut cube,
+                sky_cube,
+                ircache,
+                tlas,
+                bindless_descriptor_set,
+            );
+            self.last_capture = Some((eye_position, frame_index));
+        }
+
+        self.last_capture
+            .map(|(position, _)| EnvironmentCaptureOutput {
+                cube: cube.into(),
+                position,
+            })
+    }
+}
@@ -0,0 +1,309 @@
+use std::{intrinsics::transmute, ptr, time::Instant};
+
+use ffx_fsr2::*;
+use glam::Vec2;
+use kajiya_backend::{ash::vk, vk_sync::AccessType, vulkan::image::*, RenderBackend};
+use kajiya_rg::{self as rg, SimpleRenderPass};
+use rust_shaders_shared::camera::CameraMatrices;
+
+/// AMD FidelityFX Super Resolution 2, as an alternative to `TaaRenderer` for temporal upscaling.
+pub struct Fsr2Renderer {
+    // Boxed, since FSR 2 keeps pointers into both.
+    context: Box<FfxFsr2Context>,
+    _scratch_buffer: Vec<u8>,
+
+    pub current_supersample_offset: Vec2,
+
+    /// Strength of the built-in RCAS sharpening; 0 disables it.
+    pub sharpness: f32,
+
+    frame_idx: u32,
+    last_dispatch: Option<Instant>,
+}
+
+macro_rules! ffx_checked {
+    ($($t:tt)*) => {
+        assert_eq!(FFX_OK as FfxErrorCode, $($t)*)
+    };
+}
+
+impl Fsr2Renderer {
+    pub fn new(
+        backend: &RenderBackend,
+        input_resolution: [u32; 2],
+        target_resolution: [u32; 2],
+    ) -> Self {
+        unsafe {
+            let physical_device = &backend.device.physical_device();
+
+            let scratch_size = ffxFsr2GetScratchMemorySizeVK(transmute(physical_device.raw));
+            let mut scratch_buffer = vec![0u8; scratch_size];
+
+            let mut callbacks: FfxFsr2Interface = std::mem::zeroed();
+            ffx_checked!(ffxFsr2GetInterfaceVK(
+                &mut callbacks,
+                scratch_buffer.as_mut_ptr() as _,
+                scratch_size,
+                transmute(physical_device.raw),
+                transmute(physical_device.instance.raw.fp_v1_0().get_device_proc_addr),
+            ));
+
+            log::info!(
+                "Creating an FSR 2 context to produce {:?} output from {:?} input",
+                target_resolution,
+                input_resolution
+            );
+
+            let context_desc = FfxFsr2ContextDescription {
+                flags: (FfxFsr2InitializationFlagBits_FFX_FSR2_ENABLE_HIGH_DYNAMIC_RANGE
+                    | FfxFsr2InitializationFlagBits_FFX_FSR2_ENABLE_DEPTH_INVERTED
+                    | FfxFsr2InitializationFlagBits_FFX_FSR2_ENABLE_DEPTH_INFINITE
+                    | FfxFsr2InitializationFlagBits_FFX_FSR2_ENABLE_AUTO_EXPOSURE)
+                    as _,
+                maxRenderSize: FfxDimensions2D {
+                    width: input_resolution[0],
+                    height: input_resolution[1],
+                },
+                displaySize: FfxDimensions2D {
+                    width: target_resolution[0],
+                    height: target_resolution[1],
+                },
+                callbacks,
+                device: ffxGetDeviceVK(transmute(backend.device.raw.handle())),
+                ..std::mem::zeroed()
+            };
+
+            let mut context: Box<FfxFsr2Context> = Box::new(std::mem::zeroed());
+            ffx_checked!(ffxFsr2ContextCreate(context.as_mut(), &context_desc));
+
+            Self {
+                context,
+                _scratch_buffer: scratch_buffer,
+                current_supersample_offset: Vec2::ZERO,
+                sharpness: 0.0,
+                frame_idx: 0,
+                last_dispatch: None,
+            }
+        }
+    }
+
+    /// Drops the history, e.g. when switching to FSR 2 from another upscaler.
+    pub fn reset(&mut self) {
+        self.frame_idx = 0;
+    }
+
+    /// `reactive_mask` and `transparency_mask` are input-resolution masks, as from
+    /// `taa::reactive_mask` and `fsr2::transparency_mask`. `pre_exposure` is the exposure
+    /// already applied to `input`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        input: &rg::Handle<Image>,
+        reprojection_map: &rg::Handle<Image>,
+        depth: &rg::Handle<Image>,
+        reactive_mask: &rg::Handle<Image>,
+        transparency_mask: &rg::Handle<Image>,
+        camera_matrices: &CameraMatrices,
+        pre_exposure: f32,
+        output_extent: [u32; 2],
+    ) -> rg::Handle<Image> {
+        let mut output = rg.create(
+            ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, output_extent).usage(
+                vk::ImageUsageFlags::SAMPLED
+                    | vk::ImageUsageFlags::STORAGE
+                    | vk::ImageUsageFlags::TRANSFER_DST,
+            ),
+        );
+
+        // With `motionVectorScale` set to the input extent, the reprojection map can be used directly.
+        let motion_vectors = reprojection_map;
+
+        let mut pass = rg.add_pass("fsr2");
+        let input_ref = pass.read(
+            input,
+            AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        );
+        let depth_ref = pass.read(
+            depth,
+            AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        );
+        let motion_vectors_ref = pass.read(
+            motion_vectors,
+            AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        );
+        let reactive_mask_ref = pass.read(
+            reactive_mask,
+            AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        );
+        let transparency_mask_ref = pass.read(
+            transparency_mask,
+            AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        );
+        let output_ref = pass.write(&mut output, AccessType::AnyShaderWrite);
+
+        let input_extent = input.desc().extent_2d();
+        let current_supersample_offset = self.current_supersample_offset;
+        let context: *mut FfxFsr2Context = self.context.as_mut();
+        let should_reset = self.frame_idx == 0;
+        let sharpness = self.sharpness;
+
+        let now = Instant::now();
+        let frame_time_delta_ms = self
+            .last_dispatch
+            .map_or(1000.0 / 60.0, |last| (now - last).as_secs_f32() * 1000.0);
+        self.last_dispatch = Some(now);
+
+        // Reverse-Z with an infinite far plane: clip-space `z` is the near plane distance.
+        let view_to_clip = camera_matrices.view_to_clip;
+        let camera_near = view_to_clip.w_axis.z;
+        let camera_fov_vertical = 2.0 * (1.0 / view_to_clip.y_axis.y).atan();
+
+        pass.render(move |api| {
+            let cb = api.cb;
+
+            unsafe {
+                let color = image_to_ffx(
+                    api,
+                    context,
+                    input_ref,
+                    ImageViewDesc::default(),
+                    FfxResourceStates_FFX_RESOURCE_STATE_COMPUTE_READ,
+                );
+                let depth = image_to_ffx(
+                    api,
+                    context,
+                    depth_ref,
+                    ImageViewDesc {
+                        aspect_mask: vk::ImageAspectFlags::DEPTH,
+                        ..Default::default()
+                    },
+                    FfxResourceStates_FFX_RESOURCE_STATE_COMPUTE_READ,
+                );
+                let motion_vectors = image_to_ffx(
+                    api,
+                    context,
+                    motion_vectors_ref,
+                    ImageViewDesc::default(),
+                    FfxResourceStates_FFX_RESOURCE_STATE_COMPUTE_READ,
+                );
+                let reactive = image_to_ffx(
+                    api,
+                    context,
+                    reactive_mask_ref,
+                    ImageViewDesc::default(),
+                    FfxResourceStates_FFX_RESOURCE_STATE_COMPUTE_READ,
+                );
+                let transparency_and_composition = image_to_ffx(
+                    api,
+                    context,
+                    transparency_mask_ref,
+                    ImageViewDesc::default(),
+                    FfxResourceStates_FFX_RESOURCE_STATE_COMPUTE_READ,
+                );
+                let output = image_to_ffx(
+                    api,
+                    context,
+                    output_ref,
+                    ImageViewDesc::default(),
+                    FfxResourceStates_FFX_RESOURCE_STATE_UNORDERED_ACCESS,
+                );
+
+                let dispatch_desc = FfxFsr2DispatchDescription {
+                    commandList: ffxGetCommandListVK(transmute(cb.raw)),
+                    color,
+                    depth,
+                    motionVectors: motion_vectors,
+                    reactive,
+                    transparencyAndComposition: transparency_and_composition,
+                    output,
+                    jitterOffset: FfxFloatCoords2D {
+                        x: -current_supersample_offset.x,
+                        y: current_supersample_offset.y,
+                    },
+                    motionVectorScale: FfxFloatCoords2D {
+                        x: input_extent[0] as f32,
+                        y: input_extent[1] as f32,
+                    },
+                    renderSize: FfxDimensions2D {
+                        width: input_extent[0],
+                        height: input_extent[1],
+                    },
+                    enableSharpening: sharpness > 0.0,
+                    sharpness: sharpness.clamp(0.0, 1.0),
+                    frameTimeDelta: frame_time_delta_ms,
+                    preExposure: pre_exposure,
+                    reset: should_reset,
+                    cameraNear: camera_near,
+                    cameraFar: f32::MAX,
+                    cameraFovAngleVertical: camera_fov_vertical,
+                    // No exposure texture; FSR 2 computes its own with `ENABLE_AUTO_EXPOSURE`.
+                    ..std::mem::zeroed()
+                };
+
+                ffx_checked!(ffxFsr2ContextDispatch(context, &dispatch_desc));
+            }
+
+            Ok(())
+        });
+
+        self.frame_idx += 1;
+
+        output
+    }
+}
+
+impl Drop for Fsr2Renderer {
+    fn drop(&mut self) {
+        unsafe {
+            ffxFsr2ContextDestroy(self.context.as_mut());
+        }
+    }
+}
+
+/// Mask of surfaces FSR 2 should treat as not fully opaque, for which it trusts history
+/// less. Generated from the gbuffer: the transmission of thin translucent surfaces.
+pub fn transparency_mask(
+    rg: &mut rg::RenderGraph,
+    gbuffer: &rg::Handle<Image>,
+) -> rg::Handle<Image> {
+    let mut mask = rg.create(ImageDesc::new_2d(
+        vk::Format::R8_UNORM,
+        gbuffer.desc().extent_2d(),
+    ));
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("fsr2 transparency mask"),
+        "/shaders/fsr2/transparency_mask.hlsl",
+    )
+    .read(gbuffer)
+    .write(&mut mask)
+    .dispatch(mask.desc().extent);
+
+    mask
+}
+
+unsafe fn image_to_ffx<ViewType: rg::GpuViewType>(
+    api: &rg::RenderPassApi,
+    context: *mut FfxFsr2Context,
+    image_ref: rg::Ref<Image, ViewType>,
+    view_desc: ImageViewDesc,
+    state: FfxResourceStates,
+) -> FfxResource {
+    let device = api.device();
+    let image = api.resources.image(image_ref);
+
+    let view = image.view(device, &view_desc).unwrap();
+    let view_desc = image.view_desc(&view_desc);
+
+    ffxGetTextureResourceVK(
+        context,
+        transmute(image.raw),
+        transmute(view),
+        image.desc.extent[0],
+        image.desc.extent[1],
+        transmute(view_desc.format),
+        ptr::null(),
+        state,
+    )
+}
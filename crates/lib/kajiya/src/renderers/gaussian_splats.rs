@@ -0,0 +1,282 @@
+use std::{collections::HashMap, mem::size_of, sync::Arc};
+
+use glam::Affine3A;
+use kajiya_asset::gaussian_splats::GaussianSplats;
+use kajiya_backend::{
+    ash::vk,
+    vk_sync::AccessType,
+    vulkan::buffer::{Buffer, BufferDesc},
+    BackendError, Device, Image,
+};
+use kajiya_rg::{self as rg, SimpleRenderPass};
+
+use crate::world_renderer::affine_to_row_major_3x4;
+
+const TILE_SIZE: u32 = 16;
+
+// Entries sorted in group shared memory by `gaussian_splats/sort_local.hlsl`
+const SORT_BLOCK_SIZE: usize = 1024;
+
+// 64 MB of (key, splat) pairs; splats which don't fit are dropped.
+const MAX_TILE_ENTRIES: usize = 1 << 23;
+
+// Tile indices are packed into the top 16 bits of the sort keys, with all ones reserved.
+const MAX_TILES: u32 = 0xffff;
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub struct GaussianSplatHandle(pub usize);
+
+// Position and opacity, covariance, and color; mirrored in `gaussian_splats/splat.hlsl`.
+type GpuGaussianSplat = [[f32; 4]; 4];
+
+// Center, depth and opacity, conic, and color; mirrored in `gaussian_splats/splat.hlsl`.
+const PROJECTED_SPLAT_SIZE: usize = 3 * 4 * size_of::<f32>();
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ProjectConstants {
+    transform: [f32; 12],
+    output_extent: [u32; 2],
+    tile_count: [u32; 2],
+    splat_count: u32,
+    output_offset: u32,
+    entry_capacity: u32,
+    brightness: f32,
+}
+
+struct GaussianSplatSet {
+    buffer: Arc<Buffer>,
+    splat_count: usize,
+    transform: Affine3A,
+}
+
+/// Draws 3D Gaussian splats on top of the lit frame, sorted per screen tile, and occluded
+/// by the geometry in the depth buffer.
+///
+/// Experimental: the splats only show up in the main view, and don't cast shadows,
+/// reflect, or receive lighting; they carry their own captured radiance instead.
+#[derive(Default)]
+pub struct GaussianSplatRenderer {
+    sets: HashMap<GaussianSplatHandle, GaussianSplatSet>,
+    next_handle: usize,
+
+    /// Scales the radiance of all splats, to match their brightness to the scene's exposure.
+    pub brightness: f32,
+}
+
+impl GaussianSplatRenderer {
+    pub fn new() -> Self {
+        Self {
+            brightness: 1.0,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sets.is_empty()
+    }
+
+    pub(crate) fn add(
+        &mut self,
+        device: &Device,
+        splats: &GaussianSplats,
+        transform: Affine3A,
+    ) -> Result<GaussianSplatHandle, BackendError> {
+        let gpu_splats: Vec<GpuGaussianSplat> = (0..splats.len())
+            .map(|i| {
+                let pos = splats.positions[i];
+                let cov = splats.covariance(i);
+                let color = splats.colors[i];
+
+                [
+                    [pos[0], pos[1], pos[2], splats.opacities[i]],
+                    [cov[0][0], cov[0][1], cov[0][2], cov[1][1]],
+                    [cov[1][2], cov[2][2], 0.0, 0.0],
+                    [color[0], color[1], color[2], 0.0],
+                ]
+            })
+            .collect();
+
+        let buffer = device.create_buffer(
+            BufferDesc::new_gpu_only(
+                gpu_splats.len() * size_of::<GpuGaussianSplat>(),
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+            ),
+            "gaussian splats",
+            Some(bytemuck::cast_slice(&gpu_splats)),
+        )?;
+
+        let handle = GaussianSplatHandle(self.next_handle);
+        self.next_handle += 1;
+
+        self.sets.insert(
+            handle,
+            GaussianSplatSet {
+                buffer: Arc::new(buffer),
+                splat_count: gpu_splats.len(),
+                transform,
+            },
+        );
+
+        Ok(handle)
+    }
+
+    pub(crate) fn set_transform(&mut self, handle: GaussianSplatHandle, transform: Affine3A) {
+        self.sets
+            .get_mut(&handle)
+            .expect("no such Gaussian splats")
+            .transform = transform;
+    }
+
+    pub(crate) fn remove(&mut self, handle: GaussianSplatHandle) {
+        self.sets.remove(&handle).expect("no such Gaussian splats");
+    }
+
+    /// Composites the splats over `color`, which is in pre-exposed linear units.
+    /// `depth` is the un-jittered reverse-Z depth of the opaque geometry.
+    pub fn render(
+        &self,
+        rg: &mut rg::RenderGraph,
+        color: &mut rg::Handle<Image>,
+        depth: &rg::Handle<Image>,
+    ) {
+        let total_splat_count: usize = self.sets.values().map(|set| set.splat_count).sum();
+        if total_splat_count == 0 {
+            return;
+        }
+
+        let output_extent = color.desc().extent_2d();
+        let tile_count = [
+            (output_extent[0] + TILE_SIZE - 1) / TILE_SIZE,
+            (output_extent[1] + TILE_SIZE - 1) / TILE_SIZE,
+        ];
+        let total_tile_count = tile_count[0] * tile_count[1];
+
+        if total_tile_count >= MAX_TILES {
+            return;
+        }
+
+        // Most splats are small, and only touch a handful of tiles.
+        let entry_capacity = (total_splat_count * 4)
+            .next_power_of_two()
+            .clamp(SORT_BLOCK_SIZE * 2, MAX_TILE_ENTRIES);
+
+        let mut projected = rg.create(BufferDesc::new_gpu_only(
+            total_splat_count * PROJECTED_SPLAT_SIZE,
+            vk::BufferUsageFlags::empty(),
+        ));
+        let mut entries = rg.create(BufferDesc::new_gpu_only(
+            entry_capacity * 2 * size_of::<u32>(),
+            vk::BufferUsageFlags::empty(),
+        ));
+        let mut tile_ranges = rg.create(BufferDesc::new_gpu_only(
+            total_tile_count as usize * 2 * size_of::<u32>(),
+            vk::BufferUsageFlags::empty(),
+        ));
+        let mut entry_counter = rg.create(BufferDesc::new_gpu_only(
+            size_of::<u32>(),
+            vk::BufferUsageFlags::empty(),
+        ));
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("_gaussian splats clear"),
+            "/shaders/gaussian_splats/clear.hlsl",
+        )
+        .write(&mut entries)
+        .write(&mut tile_ranges)
+        .write(&mut entry_counter)
+        .constants((entry_capacity as u32, total_tile_count))
+        .dispatch([entry_capacity.max(total_tile_count as usize) as u32, 1, 1]);
+
+        let mut output_offset = 0;
+        for set in self.sets.values() {
+            let splats = rg.import(set.buffer.clone(), AccessType::AnyShaderReadOther);
+
+            SimpleRenderPass::new_compute(
+                rg.add_pass("gaussian splats project"),
+                "/shaders/gaussian_splats/project.hlsl",
+            )
+            .read(&splats)
+            .write(&mut projected)
+            .write(&mut entries)
+            .write(&mut entry_counter)
+            .constants(ProjectConstants {
+                transform: affine_to_row_major_3x4(&set.transform),
+                output_extent,
+                tile_count,
+                splat_count: set.splat_count as u32,
+                output_offset: output_offset as u32,
+                entry_capacity: entry_capacity as u32,
+                brightness: self.brightness,
+            })
+            .dispatch([set.splat_count as u32, 1, 1]);
+
+            output_offset += set.splat_count;
+        }
+
+        sort_tile_entries(rg, &mut entries, entry_capacity);
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("_gaussian splats tile ranges"),
+            "/shaders/gaussian_splats/tile_ranges.hlsl",
+        )
+        .read(&entries)
+        .read(&entry_counter)
+        .write(&mut tile_ranges)
+        .constants((entry_capacity as u32, total_tile_count))
+        .dispatch([entry_capacity as u32, 1, 1]);
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("gaussian splats render"),
+            "/shaders/gaussian_splats/render.hlsl",
+        )
+        .read(&projected)
+        .read(&entries)
+        .read(&tile_ranges)
+        .read(depth)
+        .write(color)
+        .constants((output_extent, tile_count[0]))
+        .dispatch(color.desc().extent);
+    }
+}
+
+// Bitonic sort of (key, splat) pairs by key; `entry_count` must be a power of two.
+// Blocks which fit in group shared memory are sorted and merged locally,
+// and only the larger merge strides go through global memory.
+fn sort_tile_entries(
+    rg: &mut rg::RenderGraph,
+    entries: &mut rg::Handle<Buffer>,
+    entry_count: usize,
+) {
+    let sort_local = |rg: &mut rg::RenderGraph, entries: &mut rg::Handle<Buffer>, size: u32| {
+        SimpleRenderPass::new_compute(
+            rg.add_pass("_gaussian splats sort local"),
+            "/shaders/gaussian_splats/sort_local.hlsl",
+        )
+        .write(entries)
+        .constants(size)
+        .dispatch([(entry_count / 2) as u32, 1, 1]);
+    };
+
+    // Zero sorts whole blocks
+    sort_local(rg, entries, 0);
+
+    let mut size = SORT_BLOCK_SIZE * 2;
+    while size <= entry_count {
+        let mut stride = size / 2;
+        while stride >= SORT_BLOCK_SIZE {
+            SimpleRenderPass::new_compute(
+                rg.add_pass("_gaussian splats sort global"),
+                "/shaders/gaussian_splats/sort_global.hlsl",
+            )
+            .write(entries)
+            .constants((size as u32, stride as u32))
+            .dispatch([(entry_count / 2) as u32, 1, 1]);
+
+            stride /= 2;
+        }
+
+        sort_local(rg, entries, size as u32);
+        size *= 2;
+    }
+}
@@ -37,7 +37,7 @@ pub fn extract_half_res_depth(
         rg.add_pass("extract half depth"),
         "/shaders/extract_half_res_depth.hlsl",
     )
-    .read_aspect(depth, vk::ImageAspectFlags::DEPTH)
+    .read_depth(depth)
     .write(&mut output_tex)
     .dispatch(output_tex.desc().extent);
     output_tex
@@ -4,15 +4,50 @@ use half::f16;
 use std::{fs::File, io::BufReader, path::Path, sync::Arc};
 
 use kajiya_backend::{
-    ash::vk::{self, ImageUsageFlags},
-    vulkan::image::*,
+    ash::vk::{self, BufferUsageFlags, ImageUsageFlags},
+    vulkan::{
+        buffer::{Buffer, BufferDesc},
+        image::*,
+    },
 };
 use kajiya_rg::{self as rg, SimpleRenderPass};
 
-#[derive(Default)]
+// Must match `ENVIRONMENT_MAP_CDF_*` in `environment_map.hlsl`
+const ENVIRONMENT_MAP_CDF_WIDTH: u32 = 512;
+const ENVIRONMENT_MAP_CDF_HEIGHT: u32 = 256;
+const ENVIRONMENT_MAP_CDF_SIZE: usize = (ENVIRONMENT_MAP_CDF_WIDTH * ENVIRONMENT_MAP_CDF_HEIGHT
+    + ENVIRONMENT_MAP_CDF_HEIGHT
+    + 1) as usize;
+
 pub struct IblRenderer {
     image: Option<ImageRgba16f>,
     texture: Option<Arc<Image>>,
+    sampling_cdf_dirty: bool,
+
+    /// Rotation of the environment map about +Y, in radians.
+    pub rotation: f32,
+    /// Linear multiplier for the radiance of the environment map.
+    pub intensity: f32,
+}
+
+impl Default for IblRenderer {
+    fn default() -> Self {
+        Self {
+            image: None,
+            texture: None,
+            sampling_cdf_dirty: false,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+}
+
+pub struct IblRenderOutput {
+    pub cube: rg::ReadOnlyHandle<Image>,
+
+    /// Luminance-based CDF over the lat-long map, for importance sampling it;
+    /// see `environment_map.hlsl` for the layout.
+    pub sampling_cdf: rg::ReadOnlyHandle<Buffer>,
 }
 
 impl IblRenderer {
@@ -22,6 +57,13 @@ impl IblRenderer {
         self.texture = None;
     }
 
+    /// Uses an already uploaded lat-long HDR image, which must be `SAMPLED`.
+    pub fn set_texture(&mut self, texture: Arc<Image>) {
+        self.image = None;
+        self.texture = Some(texture);
+        self.sampling_cdf_dirty = true;
+    }
+
     pub fn load_image(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
         let img = load_image(path.as_ref())?;
 
@@ -34,10 +76,7 @@ impl IblRenderer {
         Ok(())
     }
 
-    pub fn render(
-        &mut self,
-        rg: &mut rg::TemporalRenderGraph,
-    ) -> Option<rg::ReadOnlyHandle<Image>> {
+    pub fn render(&mut self, rg: &mut rg::TemporalRenderGraph) -> Option<IblRenderOutput> {
         if self.texture.is_none() {
             const PIXEL_BYTES: u32 = 8;
 
@@ -55,6 +94,7 @@ impl IblRenderer {
                         )
                         .expect("create_image"),
                 ));
+                self.sampling_cdf_dirty = true;
             }
         }
 
@@ -77,7 +117,50 @@ impl IblRenderer {
                 .constants(width)
                 .dispatch([width, width, 6]);
 
-            Some(cube_tex.into())
+            let mut sampling_cdf = rg
+                .get_or_create_temporal(
+                    "ibl.sampling_cdf",
+                    BufferDesc::new_gpu_only(
+                        ENVIRONMENT_MAP_CDF_SIZE * std::mem::size_of::<f32>(),
+                        BufferUsageFlags::STORAGE_BUFFER,
+                    ),
+                )
+                .unwrap();
+
+            // Rotation and intensity are applied when sampling, so this only
+            // depends on the image.
+            if std::mem::take(&mut self.sampling_cdf_dirty) {
+                SimpleRenderPass::new_compute(
+                    rg.add_pass("ibl cdf weights"),
+                    "/shaders/ibl/ibl_cdf_weights.hlsl",
+                )
+                .read(&texture)
+                .write(&mut sampling_cdf)
+                .dispatch([
+                    ENVIRONMENT_MAP_CDF_WIDTH,
+                    ENVIRONMENT_MAP_CDF_HEIGHT,
+                    1,
+                ]);
+
+                SimpleRenderPass::new_compute(
+                    rg.add_pass("ibl cdf rows"),
+                    "/shaders/ibl/ibl_cdf_rows.hlsl",
+                )
+                .write(&mut sampling_cdf)
+                .dispatch([ENVIRONMENT_MAP_CDF_HEIGHT, 1, 1]);
+
+                SimpleRenderPass::new_compute(
+                    rg.add_pass("ibl cdf marginal"),
+                    "/shaders/ibl/ibl_cdf_marginal.hlsl",
+                )
+                .write(&mut sampling_cdf)
+                .dispatch([1, 1, 1]);
+            }
+
+            Some(IblRenderOutput {
+                cube: cube_tex.into(),
+                sampling_cdf: sampling_cdf.into(),
+            })
         } else {
             None
         }
@@ -103,7 +186,7 @@ impl ImageRgba16f {
     }
 }
 
-fn load_image(path: &Path) -> anyhow::Result<ImageRgba16f> {
+pub(crate) fn load_image(path: &Path) -> anyhow::Result<ImageRgba16f> {
     let ext = path
         .extension()
         .map(|ext| ext.to_string_lossy().as_ref().to_owned());
@@ -0,0 +1,352 @@
+use std::{collections::VecDeque, path::Path, sync::Arc};
+
+use half::f16;
+use kajiya_backend::{
+    ash::vk::{self, BufferUsageFlags, ImageUsageFlags},
+    vk_sync::AccessType,
+    vulkan::{buffer::*, image::*},
+    BackendError, Device,
+};
+use kajiya_rg::{self as rg, SimpleRenderPass};
+
+use super::ibl::{self, ImageRgba16f};
+
+// Must match `READBACK_MAGIC` in `reduce_final.hlsl`
+const IMAGE_METRICS_READBACK_MAGIC: u32 = 0x464c4950;
+
+// Must match `GROUP_SIZE` in `reduce.hlsl`
+const REDUCE_TILE_SIZE: u32 = 16;
+
+/// Frames kept in `ImageMetricsRenderer::history`; older ones are dropped.
+pub const MAX_IMAGE_METRICS_HISTORY_LEN: usize = 65536;
+
+/// Difference between a rendered frame and the reference image.
+#[derive(Clone, Copy, Debug)]
+pub struct ImageMetrics {
+    /// Index of the frame the metrics were computed for; see `WorldRenderer::retire_frame`.
+    pub frame_index: u32,
+    /// Root mean square error of the sRGB-encoded color channels, in [0, 1].
+    pub rmse: f32,
+    /// Mean of the per-pixel LDR-FLIP error, in [0, 1].
+    pub mean_flip: f32,
+}
+
+/// Compares the final image against a reference, and reads back the error of every frame,
+/// for convergence plots and tracking quality across changes to the denoisers.
+///
+/// Both images are compared as they'd be shown, i.e. clamped to [0, 1] before the sRGB OETF.
+/// Results lag the displayed frame by the number of frames in flight.
+pub struct ImageMetricsRenderer {
+    reference_image: Option<ImageRgba16f>,
+    reference_texture: Option<Arc<Image>>,
+
+    // Replaced reference textures, and the timeline value at which the GPU is done with them,
+    // once known. Released in `render`.
+    retired_reference_textures: Vec<(Arc<Image>, Option<u64>)>,
+
+    readback_buffer: Arc<Buffer>,
+    history: VecDeque<ImageMetrics>,
+    history_dropped_len: usize,
+    warned_about_extent_mismatch: bool,
+}
+
+impl ImageMetricsRenderer {
+    pub fn new(device: &Device) -> Result<Self, BackendError> {
+        Ok(Self {
+            reference_image: None,
+            reference_texture: None,
+            retired_reference_textures: Vec::new(),
+            readback_buffer: Arc::new(device.create_buffer(
+                BufferDesc::new_gpu_to_cpu(
+                    4 * std::mem::size_of::<u32>(),
+                    BufferUsageFlags::STORAGE_BUFFER,
+                ),
+                "image metrics readback",
+                None,
+            )?),
+            history: VecDeque::new(),
+            history_dropped_len: 0,
+            warned_about_extent_mismatch: false,
+        })
+    }
+
+    /// Loads the image to compare frames against. EXR and HDR files are taken as linear,
+    /// and anything else (e.g. PNG screenshots) as sRGB-encoded.
+    pub fn load_reference_image(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+
+        let image = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("exr") | Some("hdr") => ibl::load_image(path)?,
+            _ => load_srgb_image(path)?,
+        };
+
+        self.reference_image = Some(image);
+        self.retire_reference_texture();
+        self.warned_about_extent_mismatch = false;
+
+        Ok(())
+    }
+
+    pub fn clear_reference_image(&mut self) {
+        self.reference_image = None;
+        self.retire_reference_texture();
+    }
+
+    fn retire_reference_texture(&mut self) {
+        if let Some(texture) = self.reference_texture.take() {
+            self.retired_reference_textures.push((texture, None));
+        }
+    }
+
+    /// Frees the retired reference textures which the GPU is done with.
+    fn release_retired_reference_textures(&mut self, device: &Device) {
+        if self.retired_reference_textures.is_empty() {
+            return;
+        }
+
+        // All the frames which used the textures have been submitted by now.
+        let last_submitted_value = device.universal_queue.last_submitted_value();
+        let completed_value = device
+            .queue_completed_value(&device.universal_queue)
+            .unwrap_or(0);
+
+        for (texture, gpu_done_timeline_value) in
+            std::mem::take(&mut self.retired_reference_textures)
+        {
+            let gpu_done_timeline_value = gpu_done_timeline_value.unwrap_or(last_submitted_value);
+
+            // The render graph of the last frame may still be holding on to it.
+            let texture = if gpu_done_timeline_value <= completed_value {
+                match Arc::try_unwrap(texture) {
+                    Ok(image) => {
+                        device.immediate_destroy_image(image);
+                        continue;
+                    }
+                    Err(texture) => texture,
+                }
+            } else {
+                texture
+            };
+
+            self.retired_reference_textures
+                .push((texture, Some(gpu_done_timeline_value)));
+        }
+    }
+
+    pub fn has_reference_image(&self) -> bool {
+        self.reference_image.is_some() || self.reference_texture.is_some()
+    }
+
+    /// Metrics of the most recent frame read back from the GPU.
+    pub fn latest(&self) -> Option<ImageMetrics> {
+        self.history.back().copied()
+    }
+
+    /// Metrics of every frame since the reference image was loaded, or `clear_history` called,
+    /// up to the last `MAX_IMAGE_METRICS_HISTORY_LEN`.
+    pub fn history(&self) -> &VecDeque<ImageMetrics> {
+        &self.history
+    }
+
+    /// Number of frames dropped from the front of `history` since it was last cleared.
+    pub fn history_dropped_len(&self) -> usize {
+        self.history_dropped_len
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+        self.history_dropped_len = 0;
+    }
+
+    fn read_back_metrics(&mut self) {
+        let src = if let Some(src) = self.readback_buffer.allocation.mapped_slice() {
+            bytemuck::checked::cast_slice::<u8, u32>(src)
+        } else {
+            return;
+        };
+
+        if src[0] != IMAGE_METRICS_READBACK_MAGIC {
+            return;
+        }
+
+        let metrics = ImageMetrics {
+            frame_index: src[1],
+            mean_flip: f32::from_bits(src[2]),
+            rmse: f32::from_bits(src[3]),
+        };
+
+        // The buffer is not written on frames without a reference.
+        if self
+            .history
+            .back()
+            .map_or(false, |last| last.frame_index == metrics.frame_index)
+        {
+            return;
+        }
+
+        if self.history.len() == MAX_IMAGE_METRICS_HISTORY_LEN {
+            self.history.pop_front();
+            self.history_dropped_len += 1;
+        }
+
+        self.history.push_back(metrics);
+    }
+
+    pub fn render(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        input: &rg::Handle<Image>,
+        frame_index: u32,
+    ) {
+        self.read_back_metrics();
+        self.release_retired_reference_textures(rg.device());
+
+        if self.reference_texture.is_none() {
+            const PIXEL_BYTES: u32 = 8;
+
+            if let Some(image) = self.reference_image.take() {
+                self.reference_texture = Some(Arc::new(
+                    rg.device()
+                        .create_image(
+                            ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, image.size)
+                                .usage(ImageUsageFlags::SAMPLED),
+                            vec![ImageSubResourceData {
+                                data: bytemuck::checked::cast_slice(image.data.as_slice()),
+                                row_pitch: (image.size[0] * PIXEL_BYTES) as usize,
+                                slice_pitch: (image.size[0] * image.size[1] * PIXEL_BYTES) as usize,
+                            }],
+                        )
+                        .expect("create_image"),
+                ));
+                self.clear_history();
+            }
+        }
+
+        let reference_texture = if let Some(texture) = self.reference_texture.clone() {
+            texture
+        } else {
+            return;
+        };
+
+        let extent = input.desc().extent_2d();
+        let reference_extent = reference_texture.desc.extent_2d();
+
+        if extent != reference_extent {
+            if !self.warned_about_extent_mismatch {
+                self.warned_about_extent_mismatch = true;
+                log::warn!(
+                    "Image metrics: the reference image is {:?}, but frames are {:?}; skipping",
+                    reference_extent,
+                    extent
+                );
+            }
+            return;
+        }
+
+        let reference = rg.import(
+            reference_texture,
+            AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        );
+
+        let filtered_desc = ImageDesc::new_2d(vk::Format::R32G32B32A32_SFLOAT, extent);
+
+        let filter_x = |rg: &mut rg::TemporalRenderGraph, src: &rg::Handle<Image>| {
+            let mut color = rg.create(filtered_desc);
+            let mut feature = rg.create(filtered_desc);
+
+            SimpleRenderPass::new_compute(
+                rg.add_pass("image metrics filter x"),
+                "/shaders/image_metrics/flip_filter_x.hlsl",
+            )
+            .read(src)
+            .write(&mut color)
+            .write(&mut feature)
+            .constants(extent)
+            .dispatch(color.desc().extent);
+
+            (color, feature)
+        };
+
+        let (test_color, test_feature) = filter_x(rg, input);
+        let (reference_color, reference_feature) = filter_x(rg, &reference);
+
+        let mut error_tex = rg.create(ImageDesc::new_2d(vk::Format::R32G32_SFLOAT, extent));
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("image metrics error"),
+            "/shaders/image_metrics/flip_filter_y.hlsl",
+        )
+        .read(&test_color)
+        .read(&test_feature)
+        .read(&reference_color)
+        .read(&reference_feature)
+        .read(input)
+        .read(&reference)
+        .write(&mut error_tex)
+        .constants(extent)
+        .dispatch(error_tex.desc().extent);
+
+        let tile_count = [
+            (extent[0] + REDUCE_TILE_SIZE - 1) / REDUCE_TILE_SIZE,
+            (extent[1] + REDUCE_TILE_SIZE - 1) / REDUCE_TILE_SIZE,
+        ];
+        let partial_sum_count = tile_count[0] * tile_count[1];
+
+        let mut partial_sums = rg.create(BufferDesc::new_gpu_only(
+            partial_sum_count as usize * 2 * std::mem::size_of::<f32>(),
+            BufferUsageFlags::STORAGE_BUFFER,
+        ));
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("image metrics reduce"),
+            "/shaders/image_metrics/reduce.hlsl",
+        )
+        .read(&error_tex)
+        .write(&mut partial_sums)
+        .constants(extent)
+        .dispatch(error_tex.desc().extent);
+
+        let mut readback = rg.import(self.readback_buffer.clone(), AccessType::Nothing);
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("image metrics reduce final"),
+            "/shaders/image_metrics/reduce_final.hlsl",
+        )
+        .read(&partial_sums)
+        .write(&mut readback)
+        .constants((extent, partial_sum_count, frame_index))
+        .dispatch([1, 1, 1]);
+    }
+}
+
+fn load_srgb_image(path: &Path) -> anyhow::Result<ImageRgba16f> {
+    let image = image::open(path)?.into_rgba8();
+
+    let data = image
+        .as_raw()
+        .chunks_exact(4)
+        .flat_map(|px| {
+            [
+                f16::from_f32(srgb_to_linear(px[0])),
+                f16::from_f32(srgb_to_linear(px[1])),
+                f16::from_f32(srgb_to_linear(px[2])),
+                f16::ONE,
+            ]
+            .into_iter()
+        })
+        .collect();
+
+    Ok(ImageRgba16f {
+        size: [image.width(), image.height()],
+        data,
+    })
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
@@ -27,7 +27,7 @@ const MAX_GRID_CELLS: usize =
     IRCACHE_CASCADE_SIZE * IRCACHE_CASCADE_SIZE * IRCACHE_CASCADE_SIZE * IRCACHE_CASCADE_COUNT;
 const MAX_ENTRIES: usize = 1024 * 64;
 
-// Must match GPU side
+// Must match GPU side. In meters, scaled by `WorldRenderer::world_units_per_meter`.
 const IRCACHE_GRID_CELL_DIAMETER: f32 = 0.16 * 0.125;
 const IRCACHE_CASCADE_SIZE: usize = 32;
 const IRCACHE_SAMPLES_PER_FRAME: usize = 4;
@@ -121,7 +121,7 @@ impl IrcacheRenderer {
         }
     }
 
-    pub fn update_eye_position(&mut self, eye_position: Vec3) {
+    pub fn update_eye_position(&mut self, eye_position: Vec3, world_units_per_meter: f32) {
         if !self.enable_scroll {
             return;
         }
@@ -129,7 +129,8 @@ impl IrcacheRenderer {
         self.grid_center = eye_position;
 
         for cascade in 0..IRCACHE_CASCADE_COUNT {
-            let cell_diameter = IRCACHE_GRID_CELL_DIAMETER * (1 << cascade) as f32;
+            let cell_diameter =
+                IRCACHE_GRID_CELL_DIAMETER * world_units_per_meter * (1 << cascade) as f32;
             let cascade_center = (eye_position / cell_diameter).floor().as_ivec3();
             let cascade_origin = cascade_center - IVec3::splat(IRCACHE_CASCADE_SIZE as i32 / 2);
 
@@ -277,7 +278,7 @@ impl IrcacheRenderer {
             "/shaders/ircache/ircache_draw_debug.hlsl",
         )
         .read(&gbuffer_depth.gbuffer)
-        .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(&gbuffer_depth.depth)
         .read(&gbuffer_depth.geometric_normal)
         .write(&mut state.ircache_meta_buf)
         .write(&mut state.ircache_grid_meta_buf)
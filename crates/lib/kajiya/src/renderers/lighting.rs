@@ -1,16 +1,37 @@
+use std::mem::size_of;
+
 use kajiya_backend::{
     ash::vk,
-    vulkan::{image::*, ray_tracing::RayTracingAcceleration, shader::ShaderSource},
+    vulkan::{
+        buffer::BufferDesc, image::*, ray_tracing::RayTracingAcceleration, shader::ShaderSource,
+    },
 };
 use kajiya_rg::{self as rg, SimpleRenderPass};
 
 use super::{rtr::SPATIAL_RESOLVE_OFFSETS, GbufferDepth};
+use crate::world_renderer::GpuRectLight;
+
+// Must match `lighting/point_light_tiles.hlsl`
+const POINT_LIGHT_TILE_SIZE: u32 = 16;
+const POINT_LIGHT_TILE_STRIDE: usize = 64;
 
-pub struct LightingRenderer {}
+pub struct LightingRenderer {
+    /// Irradiance, in renderer units, below which a point light stops lighting surfaces.
+    /// Bounds each light's range, so that screen tiles only shade the lights reaching them.
+    /// Zero gives lights unlimited range.
+    pub point_light_cutoff: f32,
+
+    /// How many of the point lights reaching a screen tile get ray-traced shadows there,
+    /// picked by their estimated irradiance at the tile. The rest are shaded unshadowed.
+    pub shadowed_point_lights_per_tile: u32,
+}
 
 impl LightingRenderer {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            point_light_cutoff: 1e-3,
+            shadowed_point_lights_per_tile: 4,
+        }
     }
 }
 
@@ -55,7 +76,7 @@ impl LightingRenderer {
             ],
             [ShaderSource::hlsl("/shaders/rt/gbuffer.rchit.hlsl")],
         )
-        .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(&gbuffer_depth.depth)
         .write(&mut refl0_tex)
         .write(&mut refl1_tex)
         .write(&mut refl2_tex)
@@ -71,7 +92,7 @@ impl LightingRenderer {
             "/shaders/lighting/spatial_reuse_lights.hlsl",
         )
         .read(&gbuffer_depth.gbuffer)
-        .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(&gbuffer_depth.depth)
         .read(&refl0_tex)
         .read(&refl1_tex)
         .read(&refl2_tex)
@@ -85,4 +106,126 @@ impl LightingRenderer {
         ))
         .dispatch(output_tex.desc().extent);
     }
+
+    /// Direct lighting from `WorldRenderer` point lights. The lights are culled per screen tile,
+    /// and only the most important ones in each tile get a ray-traced shadow.
+    /// Their indirect contribution is gathered by the GI tracers instead.
+    pub fn render_point_lights(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        gbuffer_depth: &GbufferDepth,
+        bindless_descriptor_set: vk::DescriptorSet,
+        tlas: &rg::Handle<RayTracingAcceleration>,
+    ) -> rg::Handle<Image> {
+        let mut output_tex = rg.create(
+            gbuffer_depth
+                .gbuffer
+                .desc()
+                .usage(vk::ImageUsageFlags::empty())
+                .format(vk::Format::R16G16B16A16_SFLOAT),
+        );
+
+        let output_extent = output_tex.desc().extent_2d();
+        let tile_count = [
+            (output_extent[0] + POINT_LIGHT_TILE_SIZE - 1) / POINT_LIGHT_TILE_SIZE,
+            (output_extent[1] + POINT_LIGHT_TILE_SIZE - 1) / POINT_LIGHT_TILE_SIZE,
+        ];
+
+        let mut tile_lights = rg.create(BufferDesc::new_gpu_only(
+            (tile_count[0] * tile_count[1]) as usize * POINT_LIGHT_TILE_STRIDE * size_of::<u32>(),
+            vk::BufferUsageFlags::empty(),
+        ));
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("point light cull"),
+            "/shaders/lighting/point_light_cull.hlsl",
+        )
+        .read_depth(&gbuffer_depth.depth)
+        .write(&mut tile_lights)
+        .constants((output_extent, tile_count))
+        .dispatch(output_tex.desc().extent);
+
+        SimpleRenderPass::new_rt(
+            rg.add_pass("point lights"),
+            ShaderSource::hlsl("/shaders/lighting/point_lights.rgen.hlsl"),
+            [
+                // Duplicated because `rt.hlsl` hardcodes miss index to 1
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+            ],
+            std::iter::empty(),
+        )
+        .read_depth(&gbuffer_depth.depth)
+        .read(&gbuffer_depth.geometric_normal)
+        .read(&gbuffer_depth.gbuffer)
+        .read(&tile_lights)
+        .write(&mut output_tex)
+        .constants((tile_count[0], self.shadowed_point_lights_per_tile))
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .trace_rays(tlas, output_tex.desc().extent);
+
+        output_tex
+    }
+
+    /// Traces one shadow ray per pixel towards a random point on one of the rect lights,
+    /// for `ShadowDenoiseRenderer` to turn into their average visibility.
+    pub fn trace_rect_light_shadow_mask(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        gbuffer_depth: &GbufferDepth,
+        rect_lights: Vec<GpuRectLight>,
+        bindless_descriptor_set: vk::DescriptorSet,
+        tlas: &rg::Handle<RayTracingAcceleration>,
+    ) -> rg::Handle<Image> {
+        let rect_light_count = rect_lights.len() as u32;
+        let mut output_tex = rg.create(gbuffer_depth.depth.desc().format(vk::Format::R8_UNORM));
+
+        SimpleRenderPass::new_rt(
+            rg.add_pass("rect light shadow mask"),
+            ShaderSource::hlsl("/shaders/lighting/rect_light_shadow_mask.rgen.hlsl"),
+            [
+                // Duplicated because `rt.hlsl` hardcodes miss index to 1
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+            ],
+            std::iter::empty(),
+        )
+        .read_depth(&gbuffer_depth.depth)
+        .read(&gbuffer_depth.geometric_normal)
+        .read(&gbuffer_depth.gbuffer)
+        .dynamic_storage_buffer_vec(rect_lights)
+        .write(&mut output_tex)
+        .constants(rect_light_count)
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .trace_rays(tlas, output_tex.desc().extent);
+
+        output_tex
+    }
+
+    /// Analytic direct lighting from rect lights, scaled by their denoised `visibility`,
+    /// and added to `output_tex`.
+    pub fn render_rect_lights(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        gbuffer_depth: &GbufferDepth,
+        visibility: &rg::Handle<Image>,
+        rect_lights: Vec<GpuRectLight>,
+        bindless_descriptor_set: vk::DescriptorSet,
+        output_tex: &mut rg::Handle<Image>,
+    ) {
+        let rect_light_count = rect_lights.len() as u32;
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("rect lights"),
+            "/shaders/lighting/rect_lights.hlsl",
+        )
+        .read_depth(&gbuffer_depth.depth)
+        .read(&gbuffer_depth.gbuffer)
+        .read(visibility)
+        .dynamic_storage_buffer_vec(rect_lights)
+        .write(output_tex)
+        .constants((output_tex.desc().extent_inv_extent_2d(), rect_light_count))
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .dispatch(output_tex.desc().extent);
+    }
 }
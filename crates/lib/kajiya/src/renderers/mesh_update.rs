@@ -0,0 +1,162 @@
+use std::sync::Arc;
+
+use kajiya_asset::mesh::PackedVertex;
+use kajiya_backend::{
+    ash::vk,
+    vk_sync::AccessType,
+    vulkan::{
+        buffer::BufferDesc,
+        ray_tracing::{
+            RayTracingAcceleration, RayTracingAccelerationScratchBuffer,
+            RayTracingBottomAccelerationDesc,
+        },
+    },
+    Device,
+};
+use kajiya_rg::{self as rg};
+use rg::Buffer;
+
+/// Vertex bytes which `VertexStaging` uploads per frame. A single update can't be larger;
+/// see `WorldRenderer::update_mesh_vertices`.
+pub const MAX_VERTEX_UPLOAD_BYTES_PER_FRAME: usize = 32 * 1024 * 1024;
+
+/// Host-visible buffer through which new vertices of dynamic meshes reach the vertex buffer.
+/// Like the dynamic constants, it's split in two halves, used by alternating frames,
+/// so that the CPU doesn't overwrite vertices the GPU is still copying.
+pub struct VertexStaging {
+    // Created on first use
+    buffer: Option<Arc<Buffer>>,
+    frame_parity: usize,
+    frame_offset_bytes: usize,
+}
+
+impl VertexStaging {
+    pub fn new() -> Self {
+        Self {
+            buffer: None,
+            frame_parity: 1,
+            frame_offset_bytes: 0,
+        }
+    }
+}
+
+impl Default for VertexStaging {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VertexStaging {
+    /// Switches to the other half of the buffer. Must be called at most once per frame.
+    pub fn begin_frame(&mut self) {
+        self.frame_parity = 1 - self.frame_parity;
+        self.frame_offset_bytes = 0;
+    }
+
+    /// Bytes still available for `upload_vertices` in this frame.
+    pub fn remaining_bytes(&self) -> usize {
+        MAX_VERTEX_UPLOAD_BYTES_PER_FRAME - self.frame_offset_bytes
+    }
+
+    /// Copies `verts` into the staging buffer, and records a pass copying them
+    /// to the vertex buffer at `dst_offset` (in bytes). They must fit in `remaining_bytes`.
+    pub fn upload_vertices(
+        &mut self,
+        device: &Device,
+        rg: &mut rg::RenderGraph,
+        vertex_buffer: &mut rg::Handle<Buffer>,
+        dst_offset: u32,
+        verts: &[PackedVertex],
+    ) {
+        let size = std::mem::size_of_val(verts);
+        assert!(
+            size <= self.remaining_bytes(),
+            "vertex uploads exceed MAX_VERTEX_UPLOAD_BYTES_PER_FRAME"
+        );
+
+        let buffer = self
+            .buffer
+            .get_or_insert_with(|| {
+                Arc::new(
+                    device
+                        .create_buffer(
+                            BufferDesc::new_cpu_to_gpu(
+                                2 * MAX_VERTEX_UPLOAD_BYTES_PER_FRAME,
+                                vk::BufferUsageFlags::TRANSFER_SRC,
+                            ),
+                            "vertex staging",
+                            None,
+                        )
+                        .expect("create_buffer"),
+                )
+            })
+            .clone();
+
+        let src_offset =
+            self.frame_parity * MAX_VERTEX_UPLOAD_BYTES_PER_FRAME + self.frame_offset_bytes;
+        self.frame_offset_bytes += size;
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                verts.as_ptr() as *const u8,
+                buffer
+                    .allocation
+                    .mapped_ptr()
+                    .unwrap()
+                    .as_ptr()
+                    .cast::<u8>()
+                    .add(src_offset),
+                size,
+            );
+        }
+
+        let mut pass = rg.add_pass("upload vertices");
+        let vertex_buffer_ref = pass.write(vertex_buffer, AccessType::TransferWrite);
+
+        pass.render(move |api| {
+            let dst_buffer = api.resources.buffer(vertex_buffer_ref).raw;
+
+            unsafe {
+                api.device().raw.cmd_copy_buffer(
+                    api.cb.raw,
+                    buffer.raw,
+                    dst_buffer,
+                    &[vk::BufferCopy {
+                        src_offset: src_offset as u64,
+                        dst_offset: dst_offset as u64,
+                        size: size as u64,
+                    }],
+                );
+            }
+
+            Ok(())
+        });
+    }
+}
+
+/// Refits bottom-level acceleration structures to vertices changed on the GPU.
+///
+/// Always records a pass, as it also makes the changed vertices visible to shaders reading them
+/// through the bindless descriptor set, which the graph doesn't track.
+pub fn refit_blas(
+    rg: &mut rg::RenderGraph,
+    vertex_buffer: &rg::Handle<Buffer>,
+    refits: Vec<(
+        RayTracingBottomAccelerationDesc,
+        Arc<RayTracingAcceleration>,
+    )>,
+    accel_scratch: RayTracingAccelerationScratchBuffer,
+) {
+    let mut pass = rg.add_pass("refit blas");
+    let _vertex_buffer_ref = pass.read(vertex_buffer, AccessType::AnyShaderReadOther);
+
+    pass.render(move |api| {
+        let cb = api.cb.raw;
+        for (desc, blas) in &refits {
+            api.device()
+                .refit_ray_tracing_bottom_acceleration(cb, desc, blas, &accel_scratch);
+        }
+
+        Ok(())
+    });
+}
@@ -3,31 +3,56 @@ use std::cell::{Ref, RefCell};
 use kajiya_backend::Image;
 use kajiya_rg::{self as rg, GetOrCreateTemporal};
 
+pub mod ao;
+pub mod color_grading;
+pub mod composite;
 pub mod deferred;
+pub mod denoiser;
 pub mod dof;
+pub mod environment_capture;
+pub mod gaussian_splats;
 pub mod half_res;
 pub mod ibl;
+pub mod image_metrics;
 pub mod ircache;
 pub mod lighting;
+pub mod mesh_update;
 pub mod motion_blur;
+pub mod motion_vectors;
+pub mod photo_mode;
 pub mod post;
 pub mod prefix_scan;
 pub mod raster_meshes;
+pub mod ray_queries;
 pub mod reference;
+pub mod reflection_probes;
+pub mod render_scale;
 pub mod reprojection;
+pub mod rtao;
 pub mod rtdgi;
 pub mod rtr;
 pub mod shadow_denoise;
 pub mod shadows;
+pub mod skinning;
 pub mod sky;
+pub mod sky_visibility;
 pub mod ssgi;
 pub mod taa;
+pub mod thumbnail;
+pub mod translucent_emissives;
 pub mod ussgi;
+pub mod volumetric_fog;
 pub mod wrc;
 
 #[cfg(feature = "dlss")]
 pub mod dlss;
 
+#[cfg(feature = "fsr2")]
+pub mod fsr2;
+
+#[cfg(feature = "oidn")]
+pub mod oidn_denoise;
+
 pub struct GbufferDepth {
     pub geometric_normal: rg::Handle<Image>,
     pub gbuffer: rg::Handle<Image>,
@@ -59,7 +59,7 @@ pub fn motion_blur(
         .read(input)
         .read(reprojection_map)
         .read(&velocity_dilated)
-        .read_aspect(depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(depth)
         .write(&mut output)
         .constants((
             depth.desc().extent_inv_extent_2d(),
@@ -70,3 +70,27 @@ pub fn motion_blur(
 
     output
 }
+
+/// Overrides `reprojection_map` with the shutter-interval motion of instances which have
+/// `MeshInstance::shutter_transforms`. The result should only be used for motion blur.
+pub fn shutter_reprojection_map(
+    rg: &mut RenderGraph,
+    depth: &rg::Handle<Image>,
+    shutter_velocity: &rg::Handle<Image>,
+    reprojection_map: &rg::Handle<Image>,
+) -> rg::Handle<Image> {
+    let mut output = rg.create(*reprojection_map.desc());
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("shutter reprojection"),
+        "/shaders/motion_blur/shutter_reprojection.hlsl",
+    )
+    .read_depth(depth)
+    .read(shutter_velocity)
+    .read(reprojection_map)
+    .write(&mut output)
+    .constants(output.desc().extent_inv_extent_2d())
+    .dispatch(output.desc().extent);
+
+    output
+}
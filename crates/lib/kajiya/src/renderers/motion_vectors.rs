@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use kajiya_backend::{
+    ash::vk,
+    vk_sync::AccessType,
+    vulkan::{buffer::*, image::*},
+    BackendError, Device,
+};
+use kajiya_rg::{self as rg, RenderGraph, SimpleRenderPass};
+
+use crate::frame_graph_config::DebugOverlayConfig;
+
+// Must match `MOTION_VECTOR_HISTOGRAM_*` in `histogram_common.hlsl`
+pub const MOTION_VECTOR_HISTOGRAM_BIN_COUNT: usize = 16;
+const MOTION_VECTOR_HISTOGRAM_MIN_LOG2: i32 = -4;
+
+/// Distribution of on-screen velocities, in render-resolution pixels per frame.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MotionVectorHistogram {
+    /// Number of pixels in each bin; see `bin_range_pixels`.
+    pub bins: [u32; MOTION_VECTOR_HISTOGRAM_BIN_COUNT],
+    pub max_velocity_pixels: f32,
+}
+
+impl MotionVectorHistogram {
+    /// Range of speeds counted in the bin at `idx`. Bins are spaced in powers of two;
+    /// the first one starts at zero, and the last one is unbounded.
+    pub fn bin_range_pixels(idx: usize) -> (f32, f32) {
+        let lower_edge =
+            |idx: usize| 2.0f32.powi(idx as i32 - 1 + MOTION_VECTOR_HISTOGRAM_MIN_LOG2);
+
+        let min = if idx == 0 { 0.0 } else { lower_edge(idx) };
+        let max = if idx + 1 >= MOTION_VECTOR_HISTOGRAM_BIN_COUNT {
+            f32::INFINITY
+        } else {
+            lower_edge(idx + 1)
+        };
+
+        (min, max)
+    }
+
+    pub fn pixel_count(&self) -> u32 {
+        self.bins.iter().sum()
+    }
+}
+
+/// Tools for diagnosing ghosting: a visualization of the motion vectors used by
+/// temporal filters, and a histogram of their magnitudes read back to the CPU.
+///
+/// Both are controlled by `DebugOverlayConfig`, and cost nothing when disabled.
+/// The histogram lags the displayed frame by the number of frames in flight.
+pub struct MotionVectorDebugRenderer {
+    histogram_buffer: Arc<Buffer>,
+    histogram: Option<MotionVectorHistogram>,
+
+    /// Speed in pixels per frame shown at full saturation by the visualization.
+    pub visualization_scale_pixels: f32,
+}
+
+impl MotionVectorDebugRenderer {
+    pub fn new(device: &Device) -> Result<Self, BackendError> {
+        Ok(Self {
+            histogram_buffer: Arc::new(device.create_buffer(
+                BufferDesc::new_gpu_to_cpu(
+                    std::mem::size_of::<u32>() * (MOTION_VECTOR_HISTOGRAM_BIN_COUNT + 1),
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                ),
+                "motion vector histogram",
+                None,
+            )?),
+            histogram: None,
+            visualization_scale_pixels: 16.0,
+        })
+    }
+
+    /// The most recent readback, if `DebugOverlayConfig::motion_vector_histogram` is enabled.
+    pub fn histogram(&self) -> Option<&MotionVectorHistogram> {
+        self.histogram.as_ref()
+    }
+
+    fn read_back_histogram(&mut self) {
+        let src = if let Some(src) = self.histogram_buffer.allocation.mapped_slice() {
+            bytemuck::checked::cast_slice::<u8, u32>(src)
+        } else {
+            return;
+        };
+
+        let mut histogram = MotionVectorHistogram::default();
+        histogram
+            .bins
+            .copy_from_slice(&src[..MOTION_VECTOR_HISTOGRAM_BIN_COUNT]);
+        histogram.max_velocity_pixels = f32::from_bits(src[MOTION_VECTOR_HISTOGRAM_BIN_COUNT]);
+
+        self.histogram = Some(histogram);
+    }
+
+    fn calculate_histogram(&mut self, rg: &mut RenderGraph, reprojection_map: &rg::Handle<Image>) {
+        let mut dst_histogram = rg.import(self.histogram_buffer.clone(), AccessType::Nothing);
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("_clear motion vector histogram"),
+            "/shaders/motion_vectors/histogram_clear.hlsl",
+        )
+        .write(&mut dst_histogram)
+        .dispatch([MOTION_VECTOR_HISTOGRAM_BIN_COUNT as u32 + 1, 1, 1]);
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("motion vector histogram"),
+            "/shaders/motion_vectors/histogram_calculate.hlsl",
+        )
+        .read(reprojection_map)
+        .write(&mut dst_histogram)
+        .constants(reprojection_map.desc().extent_inv_extent_2d())
+        .dispatch(reprojection_map.desc().extent);
+    }
+
+    /// Returns `input`, or its motion vector visualization if enabled in `debug_overlays`.
+    pub fn render(
+        &mut self,
+        rg: &mut RenderGraph,
+        reprojection_map: &rg::Handle<Image>,
+        input: rg::Handle<Image>,
+        debug_overlays: DebugOverlayConfig,
+    ) -> rg::Handle<Image> {
+        if debug_overlays.motion_vector_histogram {
+            self.read_back_histogram();
+            self.calculate_histogram(rg, reprojection_map);
+        } else {
+            self.histogram = None;
+        }
+
+        if !debug_overlays.motion_vectors {
+            return input;
+        }
+
+        let mut output = rg.create(*input.desc());
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("motion vector visualization"),
+            "/shaders/motion_vectors/visualize.hlsl",
+        )
+        .read(reprojection_map)
+        .read(&input)
+        .write(&mut output)
+        .constants((
+            reprojection_map.desc().extent_inv_extent_2d(),
+            output.desc().extent_inv_extent_2d(),
+            self.visualization_scale_pixels.max(1e-3),
+        ))
+        .dispatch(output.desc().extent);
+
+        output
+    }
+}
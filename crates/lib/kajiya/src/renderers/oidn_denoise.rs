@@ -0,0 +1,205 @@
+use std::sync::{mpsc, Arc};
+
+use kajiya_backend::{ash::vk, vk_sync::AccessType, vulkan::image::*, Device};
+use kajiya_rg::{self as rg, SimpleRenderPass};
+
+use super::reference::ReferenceReadback;
+
+// Linear RGB and a constant alpha, as four `f32`s
+const UPLOAD_TEXEL_SIZE: usize = 16;
+
+struct DenoisedReference {
+    generation: u32,
+    extent: [u32; 2],
+    sample_count: u32,
+    pixels: Vec<[f32; 4]>,
+}
+
+/// Denoises the reference accumulation (`RenderMode::Reference`) on the CPU with Intel
+/// Open Image Denoise, guided by its albedo and normal AOVs, and shows the result in place
+/// of the noisy image. Requires the `oidn` feature, and the OIDN library at build time.
+///
+/// Denoising runs on a background thread, and takes from a fraction of a second to a few
+/// seconds depending on the resolution and CPU. Accumulation carries on meanwhile, and the
+/// previous result stays on screen until the next one is ready.
+pub struct OidnDenoiser {
+    pub enabled: bool,
+
+    /// Samples per pixel accumulated between denoises, while the image converges. The final
+    /// image at `ReferencePathTraceConfig::target_sample_count` is denoised regardless.
+    pub sample_interval: u32,
+
+    // Bumped when accumulation restarts, so that results of the old one are dropped.
+    generation: u32,
+    last_requested_sample_count: Option<u32>,
+    in_flight: Option<mpsc::Receiver<anyhow::Result<DenoisedReference>>>,
+    denoised: Option<(u32, Arc<Image>)>,
+}
+
+impl Default for OidnDenoiser {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sample_interval: 64,
+            generation: 0,
+            last_requested_sample_count: None,
+            in_flight: None,
+            denoised: None,
+        }
+    }
+}
+
+impl OidnDenoiser {
+    /// Samples per pixel of the image on screen, if any has been denoised yet.
+    pub fn denoised_sample_count(&self) -> Option<u32> {
+        self.denoised
+            .as_ref()
+            .map(|(sample_count, _)| *sample_count)
+    }
+
+    pub fn is_denoising(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// Forgets the denoised image when reference accumulation restarts.
+    pub(crate) fn invalidate(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        self.last_requested_sample_count = None;
+        self.denoised = None;
+    }
+
+    /// Whether the accumulation should be read back for `denoise` this frame.
+    pub(crate) fn wants_readback(&self, sample_count: u32, is_complete: bool) -> bool {
+        if !self.enabled || self.in_flight.is_some() || sample_count == 0 {
+            return false;
+        }
+
+        match self.last_requested_sample_count {
+            Some(last) => {
+                sample_count >= last.saturating_add(self.sample_interval.max(1))
+                    || (is_complete && sample_count != last)
+            }
+            None => true,
+        }
+    }
+
+    pub(crate) fn denoise(&mut self, readback: ReferenceReadback) {
+        self.last_requested_sample_count = Some(readback.sample_count);
+
+        let generation = self.generation;
+        let (sender, receiver) = mpsc::channel();
+        self.in_flight = Some(receiver);
+
+        std::thread::spawn(move || {
+            let result = run_oidn(&readback).map(|denoised| DenoisedReference {
+                generation,
+                extent: readback.extent,
+                sample_count: readback.sample_count,
+                pixels: denoised
+                    .chunks_exact(3)
+                    .map(|rgb| [rgb[0], rgb[1], rgb[2], 1.0])
+                    .collect(),
+            });
+
+            // The denoiser may have been dropped meanwhile.
+            let _ = sender.send(result);
+        });
+    }
+
+    fn receive_denoised(&mut self, device: &Device) {
+        let result = match self.in_flight.as_ref().map(mpsc::Receiver::try_recv) {
+            Some(Ok(result)) => result,
+            Some(Err(mpsc::TryRecvError::Empty)) | None => return,
+            Some(Err(mpsc::TryRecvError::Disconnected)) => {
+                self.in_flight = None;
+                return;
+            }
+        };
+        self.in_flight = None;
+
+        let denoised = match result {
+            Ok(denoised) => denoised,
+            Err(err) => {
+                log::error!("Failed to denoise the reference image: {:#}", err);
+                return;
+            }
+        };
+
+        if denoised.generation != self.generation {
+            return;
+        }
+
+        let [width, height] = denoised.extent;
+        let image = device
+            .create_image(
+                ImageDesc::new_2d(vk::Format::R32G32B32A32_SFLOAT, denoised.extent)
+                    .usage(vk::ImageUsageFlags::SAMPLED),
+                vec![ImageSubResourceData {
+                    data: bytemuck::cast_slice(denoised.pixels.as_slice()),
+                    row_pitch: width as usize * UPLOAD_TEXEL_SIZE,
+                    slice_pitch: (width * height) as usize * UPLOAD_TEXEL_SIZE,
+                }],
+            )
+            .expect("create_image");
+
+        self.denoised = Some((denoised.sample_count, Arc::new(image)));
+    }
+
+    /// The latest denoised image, pre-exposed to match the accumulation, if enabled.
+    pub fn render(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        pre_exposure: f32,
+    ) -> Option<rg::Handle<Image>> {
+        self.receive_denoised(rg.device());
+
+        if !self.enabled {
+            return None;
+        }
+
+        let denoised = rg.import(
+            self.denoised.as_ref()?.1.clone(),
+            AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        );
+
+        let mut output = rg.create(
+            denoised
+                .desc()
+                .usage(vk::ImageUsageFlags::empty())
+                .format(vk::Format::R32G32B32A32_SFLOAT),
+        );
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("expose denoised reference"),
+            "/shaders/rt/expose_denoised_reference.hlsl",
+        )
+        .read(&denoised)
+        .write(&mut output)
+        .constants(pre_exposure)
+        .dispatch(output.desc().extent);
+
+        Some(output)
+    }
+}
+
+fn run_oidn(readback: &ReferenceReadback) -> anyhow::Result<Vec<f32>> {
+    let beauty: &[f32] = bytemuck::cast_slice(&readback.beauty);
+    let albedo: &[f32] = bytemuck::cast_slice(&readback.albedo);
+    let normal: &[f32] = bytemuck::cast_slice(&readback.normal);
+    let mut output = vec![0.0f32; beauty.len()];
+
+    let device = oidn::Device::new();
+    oidn::RayTracing::new(&device)
+        .hdr(true)
+        .srgb(false)
+        .image_dimensions(readback.extent[0] as usize, readback.extent[1] as usize)
+        .albedo_normal(albedo, normal)
+        .filter(beauty, &mut output)
+        .map_err(|err| anyhow::anyhow!("{:?}", err))?;
+
+    if let Err((_, message)) = device.get_error() {
+        anyhow::bail!("{}", message);
+    }
+
+    Ok(output)
+}
@@ -0,0 +1,115 @@
+use kajiya_backend::{ash::vk, vulkan::image::*};
+use kajiya_rg::{self as rg, GetOrCreateTemporal, SimpleRenderPass};
+use rust_shaders_shared::camera::CameraMatrices;
+
+/// Accumulates frames of the standard renderer into a still image, for screenshots of near
+/// reference quality without switching to the path tracer.
+///
+/// Each frame is rendered as usual, with a different sub-pixel jitter, and averaged with
+/// the previous ones after TAA and before post-processing. This supersamples geometry,
+/// and averages out the remaining noise of the denoisers. Once `sample_count` frames have
+/// been accumulated, the still is held on screen until `stop` is called.
+///
+/// The camera and the scene should stay still meanwhile; apps are expected to freeze time
+/// while `is_active`. Accumulation restarts if the camera or the render extent changes,
+/// and can be restarted manually with `restart`, e.g. after editing the scene.
+pub struct PhotoModeRenderer {
+    sample_count: u32,
+    accumulated: u32,
+    active: bool,
+    camera_matrices: Option<CameraMatrices>,
+    extent: [u32; 2],
+}
+
+impl Default for PhotoModeRenderer {
+    fn default() -> Self {
+        Self {
+            sample_count: 64,
+            accumulated: 0,
+            active: false,
+            camera_matrices: None,
+            extent: [0, 0],
+        }
+    }
+}
+
+impl PhotoModeRenderer {
+    /// Starts accumulating a still from `sample_count` frames.
+    pub fn start(&mut self, sample_count: u32) {
+        self.sample_count = sample_count.max(1);
+        self.active = true;
+        self.restart();
+    }
+
+    /// Discards the accumulated frames, and starts over.
+    pub fn restart(&mut self) {
+        self.accumulated = 0;
+        self.camera_matrices = None;
+    }
+
+    /// Goes back to regular rendering.
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.accumulated = 0;
+        self.camera_matrices = None;
+    }
+
+    /// Whether a still is being accumulated, or held on screen.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.active && self.accumulated >= self.sample_count
+    }
+
+    /// Frames accumulated so far, and the number of frames in the still.
+    pub fn progress(&self) -> (u32, u32) {
+        (self.accumulated, self.sample_count)
+    }
+
+    /// Averages `input`, the pre-exposed HDR image of the frame, into the still,
+    /// and returns the still, pre-exposed like the input.
+    pub(crate) fn accumulate(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        input: &rg::Handle<Image>,
+        camera_matrices: &CameraMatrices,
+    ) -> rg::Handle<Image> {
+        let extent = input.desc().extent_2d();
+        if self.camera_matrices.as_ref() != Some(camera_matrices) || self.extent != extent {
+            self.camera_matrices = Some(*camera_matrices);
+            self.extent = extent;
+            self.accumulated = 0;
+        }
+
+        // Accumulated without pre-exposure, so that its adaptation doesn't leak into the still.
+        let mut accum_img = rg
+            .get_or_create_temporal(
+                "photo_mode.accum",
+                ImageDesc::new_2d(vk::Format::R32G32B32A32_SFLOAT, extent)
+                    .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE),
+            )
+            .unwrap();
+
+        let mut output = rg.create(*input.desc());
+
+        let accumulate = !self.is_complete();
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("photo mode accumulate"),
+            "/shaders/photo_mode/accumulate.hlsl",
+        )
+        .read(input)
+        .write(&mut accum_img)
+        .write(&mut output)
+        .constants((self.accumulated, accumulate as u32))
+        .dispatch(output.desc().extent);
+
+        if accumulate {
+            self.accumulated += 1;
+        }
+
+        output
+    }
+}
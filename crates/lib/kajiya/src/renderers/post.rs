@@ -1,10 +1,20 @@
 use std::sync::Arc;
 
-use kajiya_backend::{ash::vk, vk_sync::AccessType, vulkan::image::*, BackendError, Device};
+use kajiya_backend::{
+    ash::vk,
+    vk_sync::AccessType,
+    vulkan::{image::*, swapchain::SwapchainColorSpace},
+    BackendError, Device,
+};
 use kajiya_rg::{self as rg};
 use rg::{Buffer, BufferDesc, RenderGraph, SimpleRenderPass};
 
-use crate::world_renderer::HistogramClipping;
+use crate::{
+    frame_graph_config::{BloomConfig, DebugOverlayConfig},
+    world_renderer::{AutoExposure, BindlessImageHandle, HdrOutput, HistogramClipping},
+};
+
+use super::color_grading::{ColorGrading, ColorGradingLut};
 
 pub fn blur_pyramid(rg: &mut RenderGraph, input: &rg::Handle<Image>) -> rg::Handle<Image> {
     let skip_n_bottom_mips = 1;
@@ -61,25 +71,82 @@ pub fn blur_pyramid(rg: &mut RenderGraph, input: &rg::Handle<Image>) -> rg::Hand
     output
 }
 
-pub fn rev_blur_pyramid(rg: &mut RenderGraph, in_pyramid: &rg::Handle<Image>) -> rg::Handle<Image> {
+/// Keeps the parts of `input` brighter than `bloom.threshold` once exposed by `exposure_mult`,
+/// with a soft knee, so that only highlights bloom. `emissive`, which must be the size of
+/// `input`, is added whole.
+fn bloom_prefilter(
+    rg: &mut RenderGraph,
+    input: &rg::Handle<Image>,
+    emissive: Option<&rg::Handle<Image>>,
+    exposure_mult: f32,
+    bloom: &BloomConfig,
+) -> rg::Handle<Image> {
+    let mut output = rg.create(input.desc().format(vk::Format::B10G11R11_UFLOAT_PACK32));
+
+    let black;
+    let emissive_tex = if let Some(emissive) = emissive {
+        emissive
+    } else {
+        let mut img = rg.create(ImageDesc::new_2d(vk::Format::R16G16B16A16_SFLOAT, [1, 1]));
+        rg::imageops::clear_color(rg, &mut img, [0.0; 4]);
+        black = img;
+        &black
+    };
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("bloom prefilter"),
+        "/shaders/post/bloom_prefilter.hlsl",
+    )
+    .read(input)
+    .read(emissive_tex)
+    .write(&mut output)
+    .constants((
+        exposure_mult,
+        bloom.threshold,
+        bloom.threshold * bloom.knee.clamp(0.0, 1.0),
+        emissive.is_some() as u32,
+    ))
+    .dispatch(output.desc().extent);
+
+    output
+}
+
+/// Gathers the `mip_count` sharpest mips of `in_pyramid` (all for zero) into mip 0 of the result,
+/// upsampling from the coarsest one, and blending in each finer level with the weight of
+/// one minus `scatter`.
+pub fn rev_blur_pyramid(
+    rg: &mut RenderGraph,
+    in_pyramid: &rg::Handle<Image>,
+    mip_count: u32,
+    scatter: f32,
+) -> rg::Handle<Image> {
     let mut output = rg.create(*in_pyramid.desc());
 
-    for target_mip in (0..(output.desc().mip_levels as u32 - 1)).rev() {
+    let pyramid_mip_count = output.desc().mip_levels as u32;
+    let used_mip_count = if mip_count == 0 {
+        pyramid_mip_count
+    } else {
+        mip_count.max(2).min(pyramid_mip_count)
+    };
+
+    for target_mip in (0..used_mip_count.saturating_sub(1)).rev() {
         let downsample_amount = 1 << target_mip;
         let output_extent: [u32; 3] = output
             .desc()
             .div_extent([downsample_amount, downsample_amount, 1])
             .extent;
         let src_mip: u32 = target_mip + 1;
-        let self_weight = if src_mip == output.desc().mip_levels as u32 {
-            0.0f32
+
+        // The coarsest level used has nothing above it to blend with, so it's read as-is.
+        let src = if src_mip + 1 == used_mip_count {
+            in_pyramid
         } else {
-            0.5f32
+            &output
         };
 
-        SimpleRenderPass::new_compute_rust(
+        SimpleRenderPass::new_compute(
             rg.add_pass(&format!("_rev_blur{}", target_mip)),
-            "rev_blur::rev_blur_cs",
+            "/shaders/post/bloom_upsample.hlsl",
         )
         .read_view(
             in_pyramid,
@@ -88,7 +155,7 @@ pub fn rev_blur_pyramid(rg: &mut RenderGraph, in_pyramid: &rg::Handle<Image>) ->
                 .level_count(Some(1)),
         )
         .read_view(
-            &output,
+            src,
             ImageViewDesc::builder()
                 .base_mip_level(src_mip)
                 .level_count(Some(1)),
@@ -99,7 +166,7 @@ pub fn rev_blur_pyramid(rg: &mut RenderGraph, in_pyramid: &rg::Handle<Image>) ->
                 .base_mip_level(target_mip)
                 .level_count(Some(1)),
         )
-        .constants((output_extent[0], output_extent[1], self_weight))
+        .constants((output_extent[0], output_extent[1], scatter.clamp(0.0, 1.0)))
         .dispatch(output_extent);
     }
 
@@ -110,9 +177,120 @@ const LUMINANCE_HISTOGRAM_BIN_COUNT: usize = 256;
 const LUMINANCE_HISTOGRAM_MIN_LOG2: f64 = -16.0;
 const LUMINANCE_HISTOGRAM_MAX_LOG2: f64 = 16.0;
 
+/// Upper bound on either dimension of `PostProcessRenderer::luminance_grid_size`.
+pub const MAX_LUMINANCE_GRID_SIZE: u32 = 32;
+
+// Pixels of the blur pyramid mip sampled per grid cell along each axis, at most.
+const LUMINANCE_GRID_PX_PER_CELL: u32 = 4;
+
+/// Average scene luminance over a grid of screen regions, read back from the GPU.
+///
+/// Values are in scene-referred units, independent of exposure, so they can be compared
+/// against fixed thresholds (e.g. "is the player standing in the dark"). The data lags
+/// the displayed frame by the number of frames in flight.
+#[derive(Clone, Debug, Default)]
+pub struct LuminanceGrid {
+    size: [u32; 2],
+    cells: Vec<f32>,
+}
+
+impl LuminanceGrid {
+    /// Width and height of the grid; `[0, 0]` until the first readback arrives.
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Per-cell luminance in row-major order, with the first row at the top of the screen.
+    pub fn cells(&self) -> &[f32] {
+        &self.cells
+    }
+
+    pub fn cell(&self, x: u32, y: u32) -> f32 {
+        self.cells[(y * self.size[0] + x) as usize]
+    }
+
+    /// Mean luminance of the whole image.
+    pub fn average(&self) -> f32 {
+        if self.cells.is_empty() {
+            0.0
+        } else {
+            self.cells.iter().sum::<f32>() / self.cells.len() as f32
+        }
+    }
+
+    /// Luminance of the cell containing the normalized screen coordinate `uv`.
+    pub fn sample(&self, uv: [f32; 2]) -> f32 {
+        if self.cells.is_empty() {
+            return 0.0;
+        }
+
+        let x = ((uv[0].clamp(0.0, 1.0) * self.size[0] as f32) as u32).min(self.size[0] - 1);
+        let y = ((uv[1].clamp(0.0, 1.0) * self.size[1] as f32) as u32).min(self.size[1] - 1);
+        self.cell(x, y)
+    }
+
+    /// Mean luminance of the cells overlapping the normalized screen rectangle `uv_min..uv_max`.
+    pub fn region_average(&self, uv_min: [f32; 2], uv_max: [f32; 2]) -> f32 {
+        if self.cells.is_empty() {
+            return 0.0;
+        }
+
+        let cell_range = |axis: usize| {
+            let size = self.size[axis];
+            let begin = ((uv_min[axis].clamp(0.0, 1.0) * size as f32) as u32).min(size - 1);
+            let end =
+                ((uv_max[axis].clamp(0.0, 1.0) * size as f32).ceil() as u32).clamp(begin + 1, size);
+            begin..end
+        };
+
+        let mut sum = 0.0;
+        let mut count = 0;
+        for y in cell_range(1) {
+            for x in cell_range(0) {
+                sum += self.cell(x, y);
+                count += 1;
+            }
+        }
+
+        sum / count as f32
+    }
+}
+
+// Mirrored in `post_combine.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct PostCombineConstants {
+    output_tex_size: [f32; 4],
+    input_multiplier: f32,
+    contrast: f32,
+    glare_amount: f32,
+    show_histogram_overlay: u32,
+    ev_false_color_enabled: u32,
+    sharp_weight: f32,
+    // Bindless index, or `!0` for none
+    lens_dirt_tex: u32,
+    lens_dirt_intensity: f32,
+    // See `ColorGradingLut::domain_constants`
+    lut_domain_min: [f32; 4],
+    lut_domain_max: [f32; 4],
+    prev_lut_domain_min: [f32; 4],
+    prev_lut_domain_max: [f32; 4],
+    // Bit 0: `lut_tex` is valid; bit 1: `prev_lut_tex` is valid
+    lut_mask: u32,
+    lut_blend: f32,
+    // Display-referred peak, relative to paper white; see `HdrOutput::peak`
+    output_peak: f32,
+    _pad: u32,
+}
+
 pub struct PostProcessRenderer {
     histogram_buffer: Arc<Buffer>,
+    luminance_grid_buffer: Arc<Buffer>,
+    luminance_grid: LuminanceGrid,
     pub image_log2_lum: f32,
+
+    /// Resolution of the grid exposed via `luminance_grid`. Clamped to `MAX_LUMINANCE_GRID_SIZE`.
+    pub luminance_grid_size: [u32; 2],
 }
 
 impl PostProcessRenderer {
@@ -126,14 +304,31 @@ impl PostProcessRenderer {
                 "luminance histogram",
                 None,
             )?),
+            luminance_grid_buffer: Arc::new(device.create_buffer(
+                BufferDesc::new_gpu_to_cpu(
+                    std::mem::size_of::<u32>()
+                        * (2 + (MAX_LUMINANCE_GRID_SIZE * MAX_LUMINANCE_GRID_SIZE) as usize),
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                ),
+                "luminance grid",
+                None,
+            )?),
+            luminance_grid: Default::default(),
             image_log2_lum: 0.0,
+            luminance_grid_size: [8, 8],
         })
     }
 
+    /// Scene luminance from the most recent readback. See `LuminanceGrid`.
+    pub fn luminance_grid(&self) -> &LuminanceGrid {
+        &self.luminance_grid
+    }
+
     fn calculate_luminance_histogram(
         &mut self,
         rg: &mut RenderGraph,
         blur_pyramid: &rg::Handle<Image>,
+        center_weighted: bool,
     ) -> rg::Handle<Buffer> {
         let mut tmp_histogram = rg.create(BufferDesc::new_gpu_only(
             std::mem::size_of::<u32>() * LUMINANCE_HISTOGRAM_BIN_COUNT,
@@ -166,7 +361,7 @@ impl PostProcessRenderer {
                 .level_count(Some(1)),
         )
         .write(&mut tmp_histogram)
-        .constants([mip_extent[0], mip_extent[1]])
+        .constants([mip_extent[0], mip_extent[1], center_weighted as u32])
         .dispatch(mip_extent);
 
         let mut dst_histogram = rg.import(self.histogram_buffer.clone(), AccessType::Nothing);
@@ -181,6 +376,70 @@ impl PostProcessRenderer {
         tmp_histogram
     }
 
+    fn calculate_luminance_grid(&mut self, rg: &mut RenderGraph, blur_pyramid: &rg::Handle<Image>) {
+        let grid_size = self
+            .luminance_grid_size
+            .map(|size| size.clamp(1, MAX_LUMINANCE_GRID_SIZE));
+
+        // Pick the smallest mip which still has a few pixels per cell.
+        let pyramid_extent = blur_pyramid.desc().extent;
+        let input_mip_level: u32 = (0..2)
+            .map(|axis| {
+                let px_per_cell = (pyramid_extent[axis] / grid_size[axis]).max(1);
+                let downsample = (px_per_cell / LUMINANCE_GRID_PX_PER_CELL).max(1);
+                31 - downsample.leading_zeros()
+            })
+            .min()
+            .unwrap()
+            .min(blur_pyramid.desc().mip_levels as u32 - 1);
+
+        let mip_extent = blur_pyramid
+            .desc()
+            .div_up_extent([1 << input_mip_level, 1 << input_mip_level, 1])
+            .extent;
+
+        let mut dst_grid = rg.import(self.luminance_grid_buffer.clone(), AccessType::Nothing);
+        SimpleRenderPass::new_compute(
+            rg.add_pass("luminance grid"),
+            "/shaders/post/luminance_grid.hlsl",
+        )
+        .read_view(
+            blur_pyramid,
+            ImageViewDesc::builder()
+                .base_mip_level(input_mip_level)
+                .level_count(Some(1)),
+        )
+        .write(&mut dst_grid)
+        .constants([mip_extent[0], mip_extent[1], grid_size[0], grid_size[1]])
+        .dispatch([grid_size[0], grid_size[1], 1]);
+    }
+
+    fn read_back_luminance_grid(&mut self) {
+        let src = if let Some(src) = self.luminance_grid_buffer.allocation.mapped_slice() {
+            bytemuck::checked::cast_slice::<u8, u32>(src)
+        } else {
+            return;
+        };
+
+        // The grid is prefixed with its size, which may differ from `luminance_grid_size`
+        // if that was changed while the frame was in flight.
+        let size = [src[0], src[1]];
+        if size[0] == 0
+            || size[1] == 0
+            || size[0] > MAX_LUMINANCE_GRID_SIZE
+            || size[1] > MAX_LUMINANCE_GRID_SIZE
+        {
+            return;
+        }
+
+        let cell_count = (size[0] * size[1]) as usize;
+        self.luminance_grid.size = size;
+        self.luminance_grid.cells.clear();
+        self.luminance_grid
+            .cells
+            .extend(src[2..2 + cell_count].iter().copied().map(f32::from_bits));
+    }
+
     fn read_back_histogram(&mut self, exposure_histogram_clipping: HistogramClipping) {
         let mut histogram = [0u32; LUMINANCE_HISTOGRAM_BIN_COUNT];
         {
@@ -230,22 +489,61 @@ impl PostProcessRenderer {
         // log::info!("mean log lum: {}", self.image_log2_lum);
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn render(
         &mut self,
         rg: &mut RenderGraph,
         input: &rg::Handle<Image>,
         //debug_input: &rg::Handle<Image>,
+        bloom_emissive: Option<&rg::Handle<Image>>,
         bindless_descriptor_set: vk::DescriptorSet,
         post_exposure_mult: f32,
         contrast: f32,
-        exposure_histogram_clipping: HistogramClipping,
+        auto_exposure: &AutoExposure,
+        bloom: BloomConfig,
+        bloom_lens_dirt: Option<BindlessImageHandle>,
+        color_grading: &ColorGrading,
+        hdr_output: &HdrOutput,
+        debug_overlays: DebugOverlayConfig,
     ) -> rg::Handle<Image> {
-        self.read_back_histogram(exposure_histogram_clipping);
+        self.read_back_histogram(auto_exposure.histogram_clipping());
+        self.read_back_luminance_grid();
 
         let blur_pyramid = blur_pyramid(rg, input);
-        let histogram = self.calculate_luminance_histogram(rg, &blur_pyramid);
+        let histogram = self.calculate_luminance_histogram(
+            rg,
+            &blur_pyramid,
+            auto_exposure.metering_mode.is_center_weighted(),
+        );
+        self.calculate_luminance_grid(rg, &blur_pyramid);
+
+        let (rev_blur_pyramid, glare_amount) = if bloom.enabled {
+            // The unthresholded pyramid also feeds exposure, so thresholding needs its own.
+            // Without a threshold, the emissives in `bloom_emissive` already bloom as part of `input`.
+            let rev_blur_pyramid = if bloom.threshold > 0.0 {
+                let prefiltered =
+                    bloom_prefilter(rg, input, bloom_emissive, post_exposure_mult, &bloom);
+                let bloom_pyramid = self::blur_pyramid(rg, &prefiltered);
+                rev_blur_pyramid(rg, &bloom_pyramid, bloom.mip_count, bloom.scatter)
+            } else {
+                rev_blur_pyramid(rg, &blur_pyramid, bloom.mip_count, bloom.scatter)
+            };
+
+            (rev_blur_pyramid, bloom.amount)
+        } else {
+            let mut black = rg.create(ImageDesc::new_2d(
+                vk::Format::B10G11R11_UFLOAT_PACK32,
+                [1, 1],
+            ));
+            rg::imageops::clear_color(rg, &mut black, [0.0, 0.0, 0.0, 0.0]);
+            (black, 0.0)
+        };
 
-        let rev_blur_pyramid = rev_blur_pyramid(rg, &blur_pyramid);
+        let (lut, lut_domain) = import_color_grading_lut(rg, color_grading.lut.as_ref());
+        let (prev_lut, prev_lut_domain) =
+            import_color_grading_lut(rg, color_grading.previous_lut.as_ref());
+        let lut_mask = color_grading.lut.is_some() as u32
+            | ((color_grading.previous_lut.is_some() as u32) << 1);
 
         let mut output = rg.create(input.desc().format(vk::Format::B10G11R11_UFLOAT_PACK32));
 
@@ -258,15 +556,111 @@ impl PostProcessRenderer {
             .read(&rev_blur_pyramid)
             .read(&histogram)
             //.read(&blurred_luminance)
+            .read(&lut)
+            .read(&prev_lut)
             .write(&mut output)
             .raw_descriptor_set(1, bindless_descriptor_set)
-            .constants((
-                output.desc().extent_inv_extent_2d(),
-                post_exposure_mult,
+            .constants(PostCombineConstants {
+                output_tex_size: output.desc().extent_inv_extent_2d(),
+                input_multiplier: post_exposure_mult,
                 contrast,
-            ))
+                glare_amount,
+                show_histogram_overlay: debug_overlays.luminance_histogram as u32,
+                ev_false_color_enabled: debug_overlays.ev_false_color as u32,
+                // Thresholded bloom only holds the highlights, and is added on top.
+                sharp_weight: if bloom.threshold > 0.0 {
+                    1.0
+                } else {
+                    1.0 - glare_amount
+                },
+                lens_dirt_tex: bloom_lens_dirt
+                    .filter(|_| bloom.enabled)
+                    .map_or(!0, |handle| handle.0),
+                lens_dirt_intensity: bloom.lens_dirt_intensity,
+                lut_domain_min: lut_domain.0,
+                lut_domain_max: lut_domain.1,
+                prev_lut_domain_min: prev_lut_domain.0,
+                prev_lut_domain_max: prev_lut_domain.1,
+                lut_mask,
+                lut_blend: color_grading.blend.clamp(0.0, 1.0),
+                output_peak: hdr_output.peak(),
+                _pad: 0,
+            })
             .dispatch(output.desc().extent);
 
         output
     }
 }
+
+// Returns a black placeholder when there's no LUT, as the shader still needs an image bound.
+fn import_color_grading_lut(
+    rg: &mut RenderGraph,
+    lut: Option<&ColorGradingLut>,
+) -> (rg::Handle<Image>, ([f32; 4], [f32; 4])) {
+    if let Some(lut) = lut {
+        (lut.import(rg), lut.domain_constants())
+    } else {
+        let mut black = rg.create(ImageDesc::new_3d(vk::Format::R8G8B8A8_UNORM, [1, 1, 1]));
+        rg::imageops::clear_color(rg, &mut black, [0.0, 0.0, 0.0, 0.0]);
+        (black, ([0.0; 4], [1.0; 4]))
+    }
+}
+
+/// Composites the `ui` over the post-processed `main` image, and encodes the result into
+/// `output`, e.g. the swapchain, for `hdr_output.color_space`. Resamples `main` with
+/// Catmull-Rom if it's not at `output_extent`.
+pub fn output_transform(
+    rg: &mut RenderGraph,
+    main: &rg::Handle<Image>,
+    ui: &rg::Handle<Image>,
+    output: &mut rg::Handle<Image>,
+    output_extent: [u32; 2],
+    hdr_output: &HdrOutput,
+) {
+    // Must match `final_blit.hlsl`
+    let output_color_space: u32 = match hdr_output.color_space {
+        SwapchainColorSpace::Srgb => 0,
+        SwapchainColorSpace::ScRgb => 1,
+        SwapchainColorSpace::Hdr10 => 2,
+    };
+
+    SimpleRenderPass::new_compute(rg.add_pass("final blit"), "/shaders/final_blit.hlsl")
+        .read(main)
+        .read(ui)
+        .write(output)
+        .constants((
+            main.desc().extent_inv_extent_2d(),
+            [
+                output_extent[0] as f32,
+                output_extent[1] as f32,
+                1.0 / output_extent[0] as f32,
+                1.0 / output_extent[1] as f32,
+            ],
+            output_color_space,
+            hdr_output.paper_white_nits,
+        ))
+        .dispatch([output_extent[0], output_extent[1], 1]);
+}
+
+/// Contrast-adaptive sharpening (FidelityFX CAS) of the post-processed image, to recover
+/// detail softened by temporal upscaling. `sharpness` is in `[0, 1]`.
+pub fn contrast_adaptive_sharpening(
+    rg: &mut RenderGraph,
+    input: &rg::Handle<Image>,
+    sharpness: f32,
+    hdr_output: &HdrOutput,
+) -> rg::Handle<Image> {
+    let mut output = rg.create(*input.desc());
+
+    SimpleRenderPass::new_compute(rg.add_pass("cas"), "/shaders/post/cas.hlsl")
+        .read(input)
+        .write(&mut output)
+        .constants((
+            input.desc().extent_2d(),
+            sharpness.clamp(0.0, 1.0),
+            hdr_output.peak(),
+        ))
+        .dispatch(input.desc().extent);
+
+    output
+}
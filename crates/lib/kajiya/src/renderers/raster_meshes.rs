@@ -2,13 +2,14 @@ use std::sync::Arc;
 
 use kajiya_backend::{
     ash::vk,
+    dynamic_constants::MAX_DYNAMIC_CONSTANTS_STORAGE_BUFFER_BYTES,
     vk_sync::AccessType,
     vulkan::{buffer::*, image::*, shader::*},
 };
 use kajiya_rg::{self as rg};
-use rg::{IntoRenderPassPipelineBinding, RenderGraph, RenderPassBinding};
+use rg::{BindRgRef, IntoRenderPassPipelineBinding, RenderGraph, RenderPassBinding};
 
-use crate::world_renderer::MeshInstance;
+use crate::world_renderer::{affine_to_row_major_3x4, InstanceBatch, MeshInstance};
 
 use super::GbufferDepth;
 
@@ -18,11 +19,31 @@ pub struct UploadedTriMesh {
     pub index_count: u32,
 }
 
+// Must match `InstanceShutterTransform` in `raster_simple_vs.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuInstanceShutterTransform {
+    open: [f32; 12],
+    close: [f32; 12],
+}
+
+// Regular instances whose current and previous transforms fit in a dynamic constants
+// storage buffer. Shutter transforms are the same size, and only pushed for instances with them.
+const MAX_REGULAR_INSTANCES_PER_CHUNK: usize =
+    MAX_DYNAMIC_CONSTANTS_STORAGE_BUFFER_BYTES / std::mem::size_of::<GpuInstanceShutterTransform>();
+
 pub struct RasterMeshesData<'a> {
     pub meshes: &'a [UploadedTriMesh],
     pub instances: &'a [MeshInstance],
+    pub instance_batches: &'a [InstanceBatch],
+    pub instance_batch_transform_buffer: Arc<Buffer>,
+    /// Last access to `instance_batch_transform_buffer` earlier in the frame
+    pub instance_batch_transform_access: AccessType,
     pub vertex_buffer: Arc<Buffer>,
     pub bindless_descriptor_set: vk::DescriptorSet,
+
+    /// View-space scale of `InstanceFlags::VIEWMODEL` instances
+    pub viewmodel_view_scale: [f32; 3],
 }
 
 pub fn raster_meshes(
@@ -30,31 +51,52 @@ pub fn raster_meshes(
     render_pass: Arc<RenderPass>,
     gbuffer_depth: &mut GbufferDepth,
     velocity_img: &mut rg::Handle<Image>,
+    // Only needed when some instance has `MeshInstance::shutter_transforms`, in which case
+    // `render_pass` must have a fourth color attachment for it.
+    shutter_velocity_img: Option<&mut rg::Handle<Image>>,
     mesh_data: RasterMeshesData<'_>,
 ) {
     let mut pass = rg.add_pass("raster simple");
 
+    let (vs_source, ps_source) = if shutter_velocity_img.is_some() {
+        (
+            "/shaders/raster_simple_shutter_vs.hlsl",
+            "/shaders/raster_simple_shutter_ps.hlsl",
+        )
+    } else {
+        (
+            "/shaders/raster_simple_vs.hlsl",
+            "/shaders/raster_simple_ps.hlsl",
+        )
+    };
+
     let pipeline = pass.register_raster_pipeline(
         &[
             PipelineShaderDesc::builder(ShaderPipelineStage::Vertex)
                 // .rust_source("raster_simple::raster_simple_vs")
-                .hlsl_source("/shaders/raster_simple_vs.hlsl")
+                .hlsl_source(vs_source)
                 .build()
                 .unwrap(),
             PipelineShaderDesc::builder(ShaderPipelineStage::Pixel)
                 // .rust_source("raster_simple::raster_simple_fs")
-                .hlsl_source("/shaders/raster_simple_ps.hlsl")
+                .hlsl_source(ps_source)
                 .build()
                 .unwrap(),
         ],
         RasterPipelineDesc::builder()
             .render_pass(render_pass.clone())
             .face_cull(false)
-            .push_constants_bytes(2 * std::mem::size_of::<u32>()),
+            .push_constants_bytes(6 * std::mem::size_of::<u32>()),
     );
 
     let meshes: Vec<UploadedTriMesh> = mesh_data.meshes.to_vec();
     let instances: Vec<MeshInstance> = mesh_data.instances.to_vec();
+    let instance_batches: Vec<InstanceBatch> = mesh_data.instance_batches.to_vec();
+
+    let instance_batch_transforms = rg.import(
+        mesh_data.instance_batch_transform_buffer,
+        mesh_data.instance_batch_transform_access,
+    );
 
     let depth_ref = pass.raster(
         &mut gbuffer_depth.depth,
@@ -67,57 +109,41 @@ pub fn raster_meshes(
     );
     let gbuffer_ref = pass.raster(&mut gbuffer_depth.gbuffer, AccessType::ColorAttachmentWrite);
     let velocity_ref = pass.raster(velocity_img, AccessType::ColorAttachmentWrite);
+    let shutter_velocity_ref =
+        shutter_velocity_img.map(|img| pass.raster(img, AccessType::ColorAttachmentWrite));
+    let instance_batch_transforms_ref = pass.read(
+        &instance_batch_transforms,
+        AccessType::VertexShaderReadOther,
+    );
 
     let vertex_buffer = mesh_data.vertex_buffer.clone();
     let bindless_descriptor_set = mesh_data.bindless_descriptor_set;
+    let viewmodel_view_scale = mesh_data.viewmodel_view_scale;
 
     pass.render(move |api| {
         let [width, height, _] = gbuffer_ref.desc().extent;
 
-        let instance_transforms_offset =
-            api.dynamic_constants()
-                .push_from_iter(instances.iter().map(|inst| {
-                    let transform = [
-                        inst.transform.x_axis.x,
-                        inst.transform.y_axis.x,
-                        inst.transform.z_axis.x,
-                        inst.transform.translation.x,
-                        inst.transform.x_axis.y,
-                        inst.transform.y_axis.y,
-                        inst.transform.z_axis.y,
-                        inst.transform.translation.y,
-                        inst.transform.x_axis.z,
-                        inst.transform.y_axis.z,
-                        inst.transform.z_axis.z,
-                        inst.transform.translation.z,
-                    ];
-
-                    let prev_transform = [
-                        inst.prev_transform.x_axis.x,
-                        inst.prev_transform.y_axis.x,
-                        inst.prev_transform.z_axis.x,
-                        inst.prev_transform.translation.x,
-                        inst.prev_transform.x_axis.y,
-                        inst.prev_transform.y_axis.y,
-                        inst.prev_transform.z_axis.y,
-                        inst.prev_transform.translation.y,
-                        inst.prev_transform.x_axis.z,
-                        inst.prev_transform.y_axis.z,
-                        inst.prev_transform.z_axis.z,
-                        inst.prev_transform.translation.z,
-                    ];
-
-                    (transform, prev_transform)
-                }));
+        let viewmodel_constants_offset = api.dynamic_constants().push(&[
+            viewmodel_view_scale[0],
+            viewmodel_view_scale[1],
+            viewmodel_view_scale[2],
+            0.0,
+        ]);
+
+        let view_desc = ImageViewDesc::default();
+        let mut color_attachments = vec![
+            (geometric_normal_ref, &view_desc),
+            (gbuffer_ref, &view_desc),
+            (velocity_ref, &view_desc),
+        ];
+        if let Some(shutter_velocity_ref) = shutter_velocity_ref {
+            color_attachments.push((shutter_velocity_ref, &view_desc));
+        }
 
         api.begin_render_pass(
             &render_pass,
             [width, height],
-            &[
-                (geometric_normal_ref, &ImageViewDesc::default()),
-                (gbuffer_ref, &ImageViewDesc::default()),
-                (velocity_ref, &ImageViewDesc::default()),
-            ],
+            &color_attachments,
             Some((
                 depth_ref,
                 &ImageViewDesc::builder()
@@ -129,24 +155,114 @@ pub fn raster_meshes(
 
         api.set_default_view_and_scissor([width, height]);
 
-        let pipeline = api.bind_raster_pipeline(
-            pipeline
-                .into_binding()
-                .descriptor_set(
-                    0,
-                    &[RenderPassBinding::DynamicConstantsStorageBuffer(
-                        instance_transforms_offset,
-                    )],
-                )
-                .raw_descriptor_set(1, bindless_descriptor_set),
-        )?;
+        // Transforms of regular instances are uploaded to dynamic constants storage buffers,
+        // which only fit so many of them, so they're drawn in chunks. Batches only need
+        // the binding for their transforms, and are drawn with that of the last chunk.
+        let mut chunk_start = 0;
+        let pipeline = loop {
+            let chunk_end = (chunk_start + MAX_REGULAR_INSTANCES_PER_CHUNK).min(instances.len());
+            let chunk = &instances[chunk_start..chunk_end];
+
+            let instance_transforms_offset =
+                api.dynamic_constants()
+                    .push_from_iter(chunk.iter().map(|inst| {
+                        (
+                            affine_to_row_major_3x4(&inst.transform),
+                            affine_to_row_major_3x4(&inst.prev_transform),
+                        )
+                    }));
+
+            // Sparse: only instances with shutter transforms get an entry, found via
+            // the `shutter_transform_index` push constant.
+            let instance_shutter_transforms_offset = api.dynamic_constants().push_from_iter(
+                chunk
+                    .iter()
+                    .filter_map(|inst| inst.shutter_transforms)
+                    .map(|[open, close]| GpuInstanceShutterTransform {
+                        open: affine_to_row_major_3x4(&open),
+                        close: affine_to_row_major_3x4(&close),
+                    }),
+            );
+
+            let pipeline = api.bind_raster_pipeline(
+                pipeline
+                    .into_binding()
+                    .descriptor_set(
+                        0,
+                        &[
+                            RenderPassBinding::DynamicConstantsStorageBuffer(
+                                instance_transforms_offset,
+                            ),
+                            instance_batch_transforms_ref.bind(),
+                            RenderPassBinding::DynamicConstantsStorageBuffer(
+                                instance_shutter_transforms_offset,
+                            ),
+                            RenderPassBinding::DynamicConstants(viewmodel_constants_offset),
+                        ],
+                    )
+                    .raw_descriptor_set(1, bindless_descriptor_set),
+            )?;
+
+            unsafe {
+                let raw_device = &api.device().raw;
+                let cb = api.cb;
+
+                let mut shutter_transform_count = 0u32;
+
+                for (chunk_idx, instance) in chunk.iter().enumerate() {
+                    let mesh = &meshes[instance.mesh.0];
+
+                    let shutter_transform_index = if instance.shutter_transforms.is_some() {
+                        shutter_transform_count += 1;
+                        shutter_transform_count - 1
+                    } else {
+                        !0u32
+                    };
+
+                    raw_device.cmd_bind_index_buffer(
+                        cb.raw,
+                        vertex_buffer.raw,
+                        mesh.index_buffer_offset,
+                        vk::IndexType::UINT32,
+                    );
+
+                    let push_constants = (
+                        (chunk_start + chunk_idx) as u32,
+                        instance.mesh.0 as u32,
+                        !0u32,
+                        0u32,
+                        chunk_idx as u32,
+                        shutter_transform_index,
+                    );
+
+                    pipeline.push_constants(
+                        cb.raw,
+                        vk::ShaderStageFlags::ALL_GRAPHICS,
+                        0,
+                        std::slice::from_raw_parts(
+                            &push_constants as *const _ as *const u8,
+                            std::mem::size_of_val(&push_constants),
+                        ),
+                    );
+
+                    raw_device.cmd_draw_indexed(cb.raw, mesh.index_count, 1, 0, 0, 0);
+                }
+            }
+
+            chunk_start = chunk_end;
+            if chunk_start == instances.len() {
+                break pipeline;
+            }
+        };
 
         unsafe {
             let raw_device = &api.device().raw;
             let cb = api.cb;
 
-            for (draw_idx, instance) in instances.into_iter().enumerate() {
-                let mesh = &meshes[instance.mesh.0];
+            // Batches follow the regular instances in `instance_dynamic_parameters`,
+            // with one entry each.
+            for (batch_idx, batch) in instance_batches.iter().enumerate() {
+                let mesh = &meshes[batch.mesh.0];
 
                 raw_device.cmd_bind_index_buffer(
                     cb.raw,
@@ -155,7 +271,14 @@ pub fn raster_meshes(
                     vk::IndexType::UINT32,
                 );
 
-                let push_constants = (draw_idx as u32, instance.mesh.0 as u32);
+                let push_constants = (
+                    (instances.len() + batch_idx) as u32,
+                    batch.mesh.0 as u32,
+                    batch.transform_offset,
+                    batch.moved as u32,
+                    0u32,
+                    !0u32,
+                );
 
                 pipeline.push_constants(
                     cb.raw,
@@ -167,7 +290,14 @@ pub fn raster_meshes(
                     ),
                 );
 
-                raw_device.cmd_draw_indexed(cb.raw, mesh.index_count, 1, 0, 0, 0);
+                raw_device.cmd_draw_indexed(
+                    cb.raw,
+                    mesh.index_count,
+                    batch.instance_count,
+                    0,
+                    0,
+                    0,
+                );
             }
         }
 
@@ -0,0 +1,311 @@
+use std::sync::Arc;
+
+use kajiya_backend::{
+    ash::vk,
+    vk_sync::AccessType,
+    vulkan::{ray_tracing::RayTracingAcceleration, shader::ShaderSource},
+    BackendError, Device,
+};
+use kajiya_rg::{self as rg};
+use rg::{Buffer, BufferDesc, SimpleRenderPass};
+
+pub const MAX_RAY_QUERIES: usize = 16384;
+
+// Batch id and ray count, padded to 16 bytes.
+const RESULTS_HEADER_SIZE: usize = 16;
+
+// Enough for one buffer being read back on the CPU, and two frames in flight.
+const RESULTS_BUFFER_COUNT: usize = 3;
+
+/// A ray to trace against the scene. Mirrored in `rt/ray_query.rgen.hlsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct RayQuery {
+    pub origin: [f32; 3],
+    pub t_min: f32,
+    pub direction: [f32; 3],
+    pub t_max: f32,
+}
+
+impl RayQuery {
+    /// `direction` doesn't need to be normalized, but must not be zero.
+    pub fn new(origin: [f32; 3], direction: [f32; 3], t_max: f32) -> Self {
+        Self {
+            origin,
+            t_min: 0.0,
+            direction,
+            t_max,
+        }
+    }
+}
+
+/// Closest hit of a `RayQuery`. Mirrored in `rt/ray_query.rgen.hlsl`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RayQueryHit {
+    pub distance: f32,
+
+    /// Maps back to the scene via `WorldRenderer::instance_from_tlas_index`.
+    /// `u32::MAX` if the ray didn't hit anything.
+    pub tlas_instance_index: u32,
+
+    /// Mesh that was hit; the ray tracing proxy if the instance has one
+    pub mesh_index: u32,
+
+    /// Index of the material within the mesh
+    pub material_index: u32,
+
+    /// World-space geometric normal of the hit triangle
+    pub normal: [f32; 3],
+
+    /// Index of the hit triangle within the mesh
+    pub primitive_index: u32,
+}
+
+impl RayQueryHit {
+    pub fn is_hit(&self) -> bool {
+        self.tlas_instance_index != u32::MAX
+    }
+}
+
+#[derive(Default)]
+pub struct RayQueryResults {
+    /// As returned by `RayQueryRenderer::submit`
+    pub batch_id: u32,
+
+    /// One entry per submitted ray, in submission order
+    pub hits: Vec<RayQueryHit>,
+}
+
+struct ResultsBuffer {
+    buffer: Arc<Buffer>,
+
+    /// Batch traced into the buffer and not read back yet; zero if none
+    batch_id: u32,
+    gpu_done_timeline_value: u64,
+}
+
+/// Traces batches of application-provided rays against the TLAS, and reads the closest
+/// hits back to the CPU. Meant for audio propagation, gameplay sensors and similar
+/// queries which want to reuse the renderer's scene.
+///
+/// Results become available a couple of frames after submission; compare
+/// `RayQueryResults::batch_id` with the id returned from `submit` to match them up.
+pub struct RayQueryRenderer {
+    results_buffers: Vec<ResultsBuffer>,
+    written_by_pending_frame: Option<usize>,
+    pending_rays: Vec<RayQuery>,
+    pending_batch_id: u32,
+    next_batch_id: u32,
+    results: RayQueryResults,
+}
+
+impl RayQueryRenderer {
+    pub fn new(device: &Device) -> Result<Self, BackendError> {
+        let results_buffers = (0..RESULTS_BUFFER_COUNT)
+            .map(|_| {
+                Ok(ResultsBuffer {
+                    buffer: Arc::new(device.create_buffer(
+                        BufferDesc::new_gpu_to_cpu(
+                            RESULTS_HEADER_SIZE
+                                + std::mem::size_of::<RayQueryHit>() * MAX_RAY_QUERIES,
+                            vk::BufferUsageFlags::STORAGE_BUFFER,
+                        ),
+                        "ray query results",
+                        None,
+                    )?),
+                    batch_id: 0,
+                    gpu_done_timeline_value: 0,
+                })
+            })
+            .collect::<Result<Vec<_>, BackendError>>()?;
+
+        Ok(Self {
+            results_buffers,
+            written_by_pending_frame: None,
+            pending_rays: Vec::new(),
+            pending_batch_id: 0,
+            next_batch_id: 1,
+            results: Default::default(),
+        })
+    }
+
+    /// Queues rays to be traced with the next frame, replacing any batch not yet traced.
+    /// At most `MAX_RAY_QUERIES` rays are traced; the rest are dropped.
+    ///
+    /// Returns the id of the batch, which will appear in `results` once it's been read back.
+    /// Fails without replacing the pending batch if any of the rays has a zero direction.
+    pub fn submit(&mut self, rays: &[RayQuery]) -> anyhow::Result<u32> {
+        if let Some(idx) = rays.iter().position(|ray| ray.direction == [0.0; 3]) {
+            anyhow::bail!("Ray query {} has a zero direction", idx);
+        }
+
+        if rays.len() > MAX_RAY_QUERIES {
+            log::warn!(
+                "Too many ray queries: {}; only the first {} will be traced",
+                rays.len(),
+                MAX_RAY_QUERIES
+            );
+        }
+
+        self.pending_rays.clear();
+        self.pending_rays
+            .extend_from_slice(&rays[..rays.len().min(MAX_RAY_QUERIES)]);
+
+        self.pending_batch_id = self.next_batch_id;
+        self.next_batch_id = self.next_batch_id.wrapping_add(1).max(1);
+        Ok(self.pending_batch_id)
+    }
+
+    /// The most recently read back batch. `batch_id` is zero until the first one arrives.
+    pub fn results(&self) -> &RayQueryResults {
+        &self.results
+    }
+
+    /// Reads back the latest batch the GPU has finished tracing, if any.
+    fn read_back_results(&mut self, device: &Device) {
+        let completed_value = match device.queue_completed_value(&device.universal_queue) {
+            Ok(value) => value,
+            Err(err) => {
+                log::error!("Failed to query the GPU timeline: {:?}", err);
+                return;
+            }
+        };
+
+        // Older completed batches are superseded by the latest one.
+        let latest = self
+            .results_buffers
+            .iter_mut()
+            .filter(|buf| buf.batch_id != 0 && buf.gpu_done_timeline_value <= completed_value)
+            .map(|buf| (std::mem::take(&mut buf.batch_id), buf))
+            .max_by_key(|(_, buf)| buf.gpu_done_timeline_value);
+
+        let (batch_id, results_buffer) = if let Some(latest) = latest {
+            latest
+        } else {
+            return;
+        };
+
+        let src = if let Some(src) = results_buffer.buffer.allocation.mapped_slice() {
+            bytemuck::checked::cast_slice::<u8, u32>(src)
+        } else {
+            return;
+        };
+
+        let ray_count = (src[1] as usize).min(MAX_RAY_QUERIES);
+        let hit_words = std::mem::size_of::<RayQueryHit>() / std::mem::size_of::<u32>();
+
+        self.results.batch_id = batch_id;
+        self.results.hits = src[RESULTS_HEADER_SIZE / std::mem::size_of::<u32>()..]
+            .chunks_exact(hit_words)
+            .take(ray_count)
+            .map(|hit| RayQueryHit {
+                distance: f32::from_bits(hit[0]),
+                tlas_instance_index: hit[1],
+                mesh_index: hit[2],
+                material_index: hit[3],
+                normal: [
+                    f32::from_bits(hit[4]),
+                    f32::from_bits(hit[5]),
+                    f32::from_bits(hit[6]),
+                ],
+                primitive_index: hit[7],
+            })
+            .collect();
+    }
+
+    pub fn trace(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        tlas: &rg::Handle<RayTracingAcceleration>,
+        bindless_descriptor_set: vk::DescriptorSet,
+    ) {
+        self.read_back_results(rg.device());
+
+        if self.pending_rays.is_empty() {
+            return;
+        }
+
+        // Trace into the buffer the GPU has been done with the longest,
+        // so that a buffer is never read back while it's still being written to.
+        let buffer_index = (0..self.results_buffers.len())
+            .min_by_key(|&i| self.results_buffers[i].gpu_done_timeline_value)
+            .unwrap();
+
+        let gpu_done_timeline_value = self.results_buffers[buffer_index].gpu_done_timeline_value;
+        if let Err(err) = rg
+            .device()
+            .wait_for_queue(&rg.device().universal_queue, gpu_done_timeline_value)
+        {
+            log::error!("Failed to wait for the ray query results buffer: {:?}", err);
+            return;
+        }
+
+        // Might have been the latest batch to complete.
+        self.read_back_results(rg.device());
+
+        let rays = std::mem::take(&mut self.pending_rays);
+        let ray_count = rays.len();
+        let batch_id = self.pending_batch_id;
+
+        let mut ray_buffer = rg.create(BufferDesc::new_gpu_only(
+            ray_count * std::mem::size_of::<RayQuery>(),
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+        ));
+
+        {
+            let mut pass = rg.add_pass("upload ray queries");
+            let ray_buffer_ref = pass.write(&mut ray_buffer, AccessType::TransferWrite);
+
+            pass.render(move |api| {
+                let src_offset = api
+                    .resources
+                    .dynamic_constants
+                    .push_from_iter(rays.iter().copied());
+
+                let src_buffer = api.resources.dynamic_constants.buffer.raw;
+                let dst_buffer = api.resources.buffer(ray_buffer_ref).raw;
+
+                unsafe {
+                    api.device().raw.cmd_copy_buffer(
+                        api.cb.raw,
+                        src_buffer,
+                        dst_buffer,
+                        &[vk::BufferCopy {
+                            src_offset: src_offset as u64,
+                            dst_offset: 0,
+                            size: (rays.len() * std::mem::size_of::<RayQuery>()) as u64,
+                        }],
+                    );
+                }
+
+                Ok(())
+            });
+        }
+
+        let results_buffer = &mut self.results_buffers[buffer_index];
+        results_buffer.batch_id = batch_id;
+        self.written_by_pending_frame = Some(buffer_index);
+
+        let mut results = rg.import(results_buffer.buffer.clone(), AccessType::Nothing);
+
+        SimpleRenderPass::new_rt(
+            rg.add_pass("trace ray queries"),
+            ShaderSource::hlsl("/shaders/rt/ray_query.rgen.hlsl"),
+            [ShaderSource::hlsl("/shaders/rt/ray_query.rmiss.hlsl")],
+            [ShaderSource::hlsl("/shaders/rt/ray_query.rchit.hlsl")],
+        )
+        .read(&ray_buffer)
+        .write(&mut results)
+        .constants((ray_count as u32, batch_id))
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .trace_rays(tlas, [ray_count as u32, 1, 1]);
+    }
+
+    /// Called once the frame which last traced the rays has been submitted.
+    pub(crate) fn retire(&mut self, timeline_value: u64) {
+        if let Some(buffer_index) = self.written_by_pending_frame.take() {
+            self.results_buffers[buffer_index].gpu_done_timeline_value = timeline_value;
+        }
+    }
+}
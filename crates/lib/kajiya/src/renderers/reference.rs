@@ -1,19 +1,328 @@
+use std::{mem::size_of, sync::Arc};
+
 use kajiya_backend::{
     ash::vk,
-    vulkan::{image::*, ray_tracing::RayTracingAcceleration, shader::ShaderSource},
+    vk_sync::AccessType,
+    vulkan::{
+        buffer::{Buffer, BufferDesc},
+        image::*,
+        ray_tracing::RayTracingAcceleration,
+        shader::ShaderSource,
+    },
 };
 use kajiya_rg::{self as rg};
 use rg::{RenderGraph, SimpleRenderPass};
 
+use super::ibl::IblRenderOutput;
+
+// Linear RGB, as three `f32`s
+const READBACK_TEXEL_SIZE: usize = 12;
+
+// Linear RGB and a constant alpha, as four `f32`s
+const RESTORE_TEXEL_SIZE: usize = 16;
+
+// Must match `PHOTON_MAP_CELL_COUNT` in `photon_map.hlsl`
+const PHOTON_MAP_CELL_COUNT: usize = 1 << 20;
+
+// `PhotonPacked` in `photon_map.hlsl`
+const PHOTON_SIZE: usize = 32;
+
+// Photons can bounce several times before being absorbed or escaping, storing one
+// at every hit, so the map has room for more than one per photon emitted.
+const PHOTONS_STORED_PER_EMITTED: usize = 2;
+
+/// How reduced-resolution reference accumulation is stretched to the render extent.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReferencePreviewFilter {
+    /// Blocky, but shows exactly what's been accumulated
+    Nearest,
+    Bilinear,
+}
+
+/// Controls of the reference path tracer (`RenderMode::Reference`).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ReferencePathTraceConfig {
+    /// Bounces after the primary hit, plus one. Sun shadow rays don't count.
+    pub max_path_length: u32,
+
+    /// Paths this long or longer are randomly terminated based on surface albedo.
+    /// Set to `max_path_length` or above to disable Russian roulette.
+    pub russian_roulette_start_path_length: u32,
+
+    /// Clamps the luminance of individual path samples, in pre-exposed units, trading bias
+    /// for fewer fireflies. `None` keeps the estimate unbiased.
+    pub firefly_clamp: Option<f32>,
+
+    /// Samples per pixel after which accumulation stops, leaving the image as is.
+    /// `None` accumulates indefinitely.
+    pub target_sample_count: Option<u32>,
+
+    /// Traces each path at three wavelengths instead of in RGB; `None` renders in RGB.
+    pub spectral: Option<ReferenceSpectralConfig>,
+
+    pub integrator: ReferenceIntegrator,
+
+    /// Used with `ReferenceIntegrator::PhotonMapping`
+    pub photon_mapping: ReferencePhotonMappingConfig,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReferenceIntegrator {
+    /// Unidirectional path tracing with next event estimation. Caustics seen via diffuse
+    /// surfaces only come from rays happening to reflect towards lights, and converge slowly.
+    PathTracing,
+
+    /// Path tracing, with caustics gathered from a photon map traced every frame instead
+    /// (progressive photon mapping; Hachisuka et al. 2008, Knaus and Zwicker 2011).
+    /// Only paths reflecting light off smooth surfaces (see `max_specular_roughness`)
+    /// onto anything else go through photons; the path tracer handles the remaining ones.
+    PhotonMapping,
+}
+
+impl ReferenceIntegrator {
+    pub const ALL: [ReferenceIntegrator; 2] = [Self::PathTracing, Self::PhotonMapping];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PathTracing => "Path tracing",
+            Self::PhotonMapping => "Photon mapping",
+        }
+    }
+}
+
+/// Controls of the caustic photon map of `ReferenceIntegrator::PhotonMapping`.
+/// Lengths are in meters, scaled by `WorldRenderer::world_units_per_meter`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ReferencePhotonMappingConfig {
+    /// Photons emitted per frame, split evenly between the sun, triangle lights and point lights.
+    pub photon_count: u32,
+
+    /// Radius around shaded points within which photons are gathered in the first frame.
+    /// It shrinks with the sample count, so the blur and bias of caustics vanish over time,
+    /// at the cost of more noise.
+    pub initial_radius: f32,
+
+    /// Surfaces at most this rough reflect light into caustics.
+    pub max_specular_roughness: f32,
+
+    /// The sun is shining through a disk of this radius around the camera, so its
+    /// caustics only form within about that distance.
+    pub sun_photon_radius: f32,
+}
+
+impl Default for ReferencePhotonMappingConfig {
+    fn default() -> Self {
+        Self {
+            photon_count: 1 << 18,
+            initial_radius: 0.05,
+            max_specular_roughness: 0.1,
+            sun_photon_radius: 20.0,
+        }
+    }
+}
+
+impl ReferencePhotonMappingConfig {
+    /// The gather radius for sample number `sample_idx` (counting from zero). Following
+    /// Knaus and Zwicker, the area shrinks as `i^(alpha - 1)` with `alpha = 2/3`.
+    fn gather_radius(&self, sample_idx: u32) -> f32 {
+        const ALPHA: f32 = 2.0 / 3.0;
+        self.initial_radius * ((sample_idx + 1) as f32).powf((ALPHA - 1.0) * 0.5)
+    }
+}
+
+/// Hero-wavelength spectral rendering (Wilkie et al. 2014). Each path carries a random
+/// wavelength and two more spread evenly across the visible range. The linear sRGB colors of
+/// materials, lights and the sky are upsampled to smooth spectra, with white light being D65,
+/// and the result is projected back to sRGB via the CIE 1931 observer. Colors of a single
+/// bounce come back within a few percent of the RGB render; the spectral effects show
+/// in interreflections, and with dispersion.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ReferenceSpectralConfig {
+    /// Abbe number of dielectrics, making their index of refraction (1.5 at 587.6 nm) vary
+    /// with wavelength; lower is more dispersive. Crown glass is around 60, and flint glass
+    /// around 35. There are no refractive materials yet, so this only affects the Fresnel
+    /// reflectance of non-metals. `None` keeps the index constant.
+    pub abbe_number: Option<f32>,
+}
+
+impl Default for ReferencePathTraceConfig {
+    fn default() -> Self {
+        Self {
+            max_path_length: 16,
+            russian_roulette_start_path_length: 3,
+            firefly_clamp: None,
+            target_sample_count: Some(1000),
+            spectral: None,
+            integrator: ReferenceIntegrator::PathTracing,
+            photon_mapping: Default::default(),
+        }
+    }
+}
+
+impl ReferencePathTraceConfig {
+    /// Whether switching from `other` to `self` changes the estimate, which then needs
+    /// to restart accumulating. Raising or lowering the target sample count doesn't,
+    /// and neither do photon mapping controls without photon mapping.
+    pub fn invalidates_accumulation(&self, other: &Self) -> bool {
+        let photon_mapping = if self.integrator == ReferenceIntegrator::PhotonMapping {
+            self.photon_mapping
+        } else {
+            other.photon_mapping
+        };
+
+        Self {
+            target_sample_count: other.target_sample_count,
+            photon_mapping,
+            ..*self
+        } != *other
+    }
+}
+
+/// Accumulated first-hit albedo and world-space normal, for external denoisers.
+/// Pixels which see the sky get its color as albedo, and a zero normal.
+pub struct ReferenceAovs {
+    pub albedo: rg::Handle<Image>,
+    pub normal: rg::Handle<Image>,
+}
+
+/// The reference accumulation read back to the CPU; see `WorldRenderer::request_reference_readback`.
+/// Also what `WorldRenderer::save_reference_state` writes to disk, and `load_reference_state` restores.
+/// Each buffer holds linear RGB values row by row, starting at the top.
+#[derive(Clone)]
+pub struct ReferenceReadback {
+    pub extent: [u32; 2],
+
+    /// Samples per pixel accumulated when the readback was requested
+    pub sample_count: u32,
+
+    /// Scene-referred radiance, without pre-exposure
+    pub beauty: Vec<[f32; 3]>,
+    pub albedo: Vec<[f32; 3]>,
+    pub normal: Vec<[f32; 3]>,
+}
+
+struct PhotonMap {
+    photons: rg::Handle<Buffer>,
+    photon_next: rg::Handle<Buffer>,
+    cell_heads: rg::Handle<Buffer>,
+}
+
+fn trace_photons(
+    rg: &mut RenderGraph,
+    config: &ReferencePhotonMappingConfig,
+    max_path_length: u32,
+    gather_radius: f32,
+    bindless_descriptor_set: vk::DescriptorSet,
+    tlas: &rg::Handle<RayTracingAcceleration>,
+) -> PhotonMap {
+    let photon_count = config.photon_count.max(1);
+    let photon_capacity = photon_count as usize * PHOTONS_STORED_PER_EMITTED;
+
+    let mut photons = rg.create(BufferDesc::new_gpu_only(
+        photon_capacity * PHOTON_SIZE,
+        vk::BufferUsageFlags::empty(),
+    ));
+    let mut photon_next = rg.create(BufferDesc::new_gpu_only(
+        photon_capacity * size_of::<u32>(),
+        vk::BufferUsageFlags::empty(),
+    ));
+    let mut cell_heads = rg.create(BufferDesc::new_gpu_only(
+        PHOTON_MAP_CELL_COUNT * size_of::<u32>(),
+        vk::BufferUsageFlags::empty(),
+    ));
+    let mut photon_counter = rg.create(BufferDesc::new_gpu_only(
+        size_of::<u32>(),
+        vk::BufferUsageFlags::empty(),
+    ));
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("photon map clear"),
+        "/shaders/rt/photon_map_clear.hlsl",
+    )
+    .write(&mut cell_heads)
+    .write(&mut photon_counter)
+    .dispatch([PHOTON_MAP_CELL_COUNT as u32, 1, 1]);
+
+    SimpleRenderPass::new_rt(
+        rg.add_pass("photon trace"),
+        ShaderSource::hlsl("/shaders/rt/photon_trace.rgen.hlsl"),
+        [
+            ShaderSource::hlsl("/shaders/rt/gbuffer.rmiss.hlsl"),
+            ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+        ],
+        [ShaderSource::hlsl("/shaders/rt/gbuffer.rchit.hlsl")],
+    )
+    .write(&mut photons)
+    .write(&mut photon_next)
+    .write(&mut cell_heads)
+    .write(&mut photon_counter)
+    .constants((
+        photon_count,
+        photon_capacity as u32,
+        gather_radius,
+        config.sun_photon_radius.max(0.0),
+        max_path_length,
+        config.max_specular_roughness,
+    ))
+    .raw_descriptor_set(1, bindless_descriptor_set)
+    .trace_rays(tlas, [photon_count, 1, 1]);
+
+    PhotonMap {
+        photons,
+        photon_next,
+        cell_heads,
+    }
+}
+
+/// With an environment map, it's importance-sampled for next event estimation;
+/// otherwise the procedural sky is only hit by BRDF-sampled rays.
+///
+/// `sample_idx` is the number of samples accumulated so far, which progressive
+/// photon mapping shrinks its gather radius with.
+#[allow(clippy::too_many_arguments)]
 pub fn reference_path_trace(
     rg: &mut RenderGraph,
     output_img: &mut rg::Handle<Image>,
+    aovs: &mut ReferenceAovs,
+    config: &ReferencePathTraceConfig,
+    sample_idx: u32,
+    ibl: Option<&IblRenderOutput>,
     bindless_descriptor_set: vk::DescriptorSet,
     tlas: &rg::Handle<RayTracingAcceleration>,
 ) {
-    SimpleRenderPass::new_rt(
+    let dummy_cube;
+    let dummy_cdf;
+    let (environment_cube, environment_cdf) = if let Some(ibl) = ibl {
+        (&*ibl.cube, &*ibl.sampling_cdf)
+    } else {
+        dummy_cube = rg.create(ImageDesc::new_cube(vk::Format::R16G16B16A16_SFLOAT, 1));
+        dummy_cdf = rg.create(BufferDesc::new_gpu_only(
+            4,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        ));
+        (&dummy_cube, &dummy_cdf)
+    };
+
+    let photon_mapping = config.integrator == ReferenceIntegrator::PhotonMapping;
+    let gather_radius = config.photon_mapping.gather_radius(sample_idx);
+
+    let photon_map = photon_mapping.then(|| {
+        trace_photons(
+            rg,
+            &config.photon_mapping,
+            config.max_path_length.max(1),
+            gather_radius,
+            bindless_descriptor_set,
+            tlas,
+        )
+    });
+
+    let mut pass = SimpleRenderPass::new_rt(
         rg.add_pass("reference pt"),
-        ShaderSource::hlsl("/shaders/rt/reference_path_trace.rgen.hlsl"),
+        ShaderSource::hlsl("/shaders/rt/reference_path_trace.rgen.hlsl").with_defines(&[(
+            "PATH_TRACE_PHOTON_MAPPING".to_owned(),
+            (photon_mapping as u32).to_string(),
+        )]),
         [
             ShaderSource::hlsl("/shaders/rt/gbuffer.rmiss.hlsl"),
             ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
@@ -21,6 +330,174 @@ pub fn reference_path_trace(
         [ShaderSource::hlsl("/shaders/rt/gbuffer.rchit.hlsl")],
     )
     .write(output_img)
-    .raw_descriptor_set(1, bindless_descriptor_set)
-    .trace_rays(tlas, output_img.desc().extent);
+    .read(environment_cube)
+    .read(environment_cdf)
+    .constants((
+        ibl.is_some() as u32,
+        config.max_path_length.max(1),
+        config.russian_roulette_start_path_length,
+        config.firefly_clamp.unwrap_or(0.0).max(0.0),
+        config.target_sample_count.unwrap_or(u32::MAX),
+        config.spectral.is_some() as u32,
+        config
+            .spectral
+            .and_then(|spectral| spectral.abbe_number)
+            .unwrap_or(0.0)
+            .max(0.0),
+        gather_radius,
+        if photon_mapping {
+            config.photon_mapping.max_specular_roughness
+        } else {
+            -1.0
+        },
+    ))
+    .write(&mut aovs.albedo)
+    .write(&mut aovs.normal);
+
+    if let Some(photon_map) = photon_map.as_ref() {
+        pass = pass
+            .read(&photon_map.photons)
+            .read(&photon_map.photon_next)
+            .read(&photon_map.cell_heads);
+    }
+
+    pass.raw_descriptor_set(1, bindless_descriptor_set)
+        .trace_rays(tlas, output_img.desc().extent);
+}
+
+/// Copies the accumulation and its AOVs into a new host-visible buffer, to be unpacked
+/// with `unpack_reference_readback` once the frame has finished on the GPU.
+pub(crate) fn read_back_reference(
+    rg: &mut RenderGraph,
+    beauty: &rg::Handle<Image>,
+    aovs: &ReferenceAovs,
+    radiance_scale: f32,
+) -> Arc<Buffer> {
+    let [width, height, _] = beauty.desc().extent;
+    let buffer = Arc::new(
+        rg.device()
+            .create_buffer(
+                BufferDesc::new_gpu_to_cpu(
+                    (width * height) as usize * READBACK_TEXEL_SIZE * 3,
+                    vk::BufferUsageFlags::STORAGE_BUFFER,
+                ),
+                "reference readback",
+                None,
+            )
+            .expect("reference readback buffer"),
+    );
+
+    let mut output = rg.import(buffer.clone(), AccessType::Nothing);
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("reference readback"),
+        "/shaders/rt/reference_readback.hlsl",
+    )
+    .read(beauty)
+    .read(&aovs.albedo)
+    .read(&aovs.normal)
+    .write(&mut output)
+    .constants((width, height, radiance_scale))
+    .dispatch([width, height, 1]);
+
+    buffer
+}
+
+pub(crate) fn unpack_reference_readback(
+    buffer: &Buffer,
+    extent: [u32; 2],
+    sample_count: u32,
+) -> Option<ReferenceReadback> {
+    let plane_size = (extent[0] * extent[1]) as usize;
+
+    let src = buffer.allocation.mapped_slice()?;
+    let src =
+        bytemuck::checked::cast_slice::<u8, [f32; 3]>(&src[..plane_size * READBACK_TEXEL_SIZE * 3]);
+    let plane = |idx: usize| src[plane_size * idx..plane_size * (idx + 1)].to_vec();
+
+    Some(ReferenceReadback {
+        extent,
+        sample_count,
+        beauty: plane(0),
+        albedo: plane(1),
+        normal: plane(2),
+    })
+}
+
+/// Overwrites the accumulation and its AOVs with a previously read back state,
+/// e.g. one loaded by `WorldRenderer::load_reference_state`.
+pub(crate) fn restore_reference(
+    rg: &mut RenderGraph,
+    state: &ReferenceReadback,
+    accum_img: &mut rg::Handle<Image>,
+    aovs: &mut ReferenceAovs,
+    pre_exposure: f32,
+) {
+    let mut upload = |plane: &[[f32; 3]]| {
+        let [width, height] = state.extent;
+        let texels: Vec<[f32; 4]> = plane.iter().map(|&[r, g, b]| [r, g, b, 1.0]).collect();
+
+        let image = rg
+            .device()
+            .create_image(
+                ImageDesc::new_2d(vk::Format::R32G32B32A32_SFLOAT, state.extent)
+                    .usage(vk::ImageUsageFlags::SAMPLED),
+                vec![ImageSubResourceData {
+                    data: bytemuck::cast_slice(texels.as_slice()),
+                    row_pitch: width as usize * RESTORE_TEXEL_SIZE,
+                    slice_pitch: (width * height) as usize * RESTORE_TEXEL_SIZE,
+                }],
+            )
+            .expect("create_image");
+
+        rg.import(
+            Arc::new(image),
+            AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        )
+    };
+
+    let beauty = upload(&state.beauty);
+    let albedo = upload(&state.albedo);
+    let normal = upload(&state.normal);
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("reference restore"),
+        "/shaders/rt/reference_restore.hlsl",
+    )
+    .read(&beauty)
+    .read(&albedo)
+    .read(&normal)
+    .write(accum_img)
+    .write(&mut aovs.albedo)
+    .write(&mut aovs.normal)
+    .constants((pre_exposure, state.sample_count as f32))
+    .dispatch(accum_img.desc().extent);
+}
+
+pub fn upsample_reference_preview(
+    rg: &mut RenderGraph,
+    input: &rg::Handle<Image>,
+    extent: [u32; 2],
+    filter: ReferencePreviewFilter,
+) -> rg::Handle<Image> {
+    let mut output_img = rg.create(
+        input
+            .desc()
+            .usage(vk::ImageUsageFlags::empty())
+            .extent([extent[0], extent[1], 1]),
+    );
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("upsample reference preview"),
+        "/shaders/render_scale/upsample_reference_preview.hlsl",
+    )
+    .read(input)
+    .write(&mut output_img)
+    .constants((
+        output_img.desc().extent_inv_extent_2d(),
+        (filter == ReferencePreviewFilter::Nearest) as u32,
+    ))
+    .dispatch(output_img.desc().extent);
+
+    output_img
 }
@@ -0,0 +1,308 @@
+use std::collections::VecDeque;
+
+use glam::Vec3;
+use kajiya_backend::{
+    ash::vk,
+    vulkan::{image::*, ray_tracing::RayTracingAcceleration, shader::ShaderSource},
+};
+use kajiya_rg::{self as rg, GetOrCreateTemporal, SimpleRenderPass};
+
+use super::ircache::IrcacheRenderState;
+
+// Must match `MAX_REFLECTION_PROBES` in `inc/reflection_probes.hlsl`
+pub const MAX_REFLECTION_PROBES: usize = 8;
+
+const PROBE_RESOLUTION: u32 = 128;
+
+// Mip `i` is prefiltered for the perceptual roughness `i / (PROBE_MIP_COUNT - 1)`.
+const PROBE_MIP_COUNT: u16 = 6;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ReflectionProbeHandle(pub u32);
+
+/// Mirrored in `inc/reflection_probes.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ReflectionProbeConstants {
+    // World-space position in `xyz`, and radius of influence in `w`. Zero radius for empty slots,
+    // and probes which haven't been captured yet.
+    position_radius: [[f32; 4]; MAX_REFLECTION_PROBES],
+    probe_count: u32,
+    mip_count: u32,
+    _pad: [u32; 2],
+}
+
+pub struct ReflectionProbesOutput {
+    /// Prefiltered radiance of every probe slot, as a cube array.
+    pub cubes: rg::ReadOnlyHandle<Image>,
+    pub constants: ReflectionProbeConstants,
+}
+
+struct ReflectionProbe {
+    handle: ReflectionProbeHandle,
+    position: Vec3,
+    radius: f32,
+    captured: bool,
+}
+
+/// Local cube map probes used by reflections in place of the sky, where reflection rays
+/// miss the scene; for example for geometry which isn't ray traced, or lies beyond the
+/// reach of reflection rays. Within its radius, each probe reprojects its cube onto a sphere
+/// of that radius, which lines up with the surroundings better than an infinitely distant cube.
+///
+/// Probes are ray traced from their position, lit by the sun, the irradiance cache and the sky,
+/// and prefiltered for a range of roughness values. Captures are queued, and one probe is
+/// processed per frame. Probes aren't updated automatically when the scene or lighting changes;
+/// call `recapture` or `recapture_all` to refresh them.
+///
+/// Requires ray tracing; without it, probes are never captured, and have no effect.
+pub struct ReflectionProbeRenderer {
+    pub enabled: bool,
+
+    probes: [Option<ReflectionProbe>; MAX_REFLECTION_PROBES],
+    capture_queue: VecDeque<ReflectionProbeHandle>,
+    next_handle: u32,
+}
+
+impl Default for ReflectionProbeRenderer {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            probes: Default::default(),
+            capture_queue: Default::default(),
+            next_handle: 1,
+        }
+    }
+}
+
+impl ReflectionProbeRenderer {
+    /// Places a probe at `position`, influencing reflections within `radius` world units,
+    /// and queues its capture. Returns `None` if all `MAX_REFLECTION_PROBES` slots are in use.
+    pub fn add(&mut self, position: Vec3, radius: f32) -> Option<ReflectionProbeHandle> {
+        let slot = self.probes.iter_mut().find(|probe| probe.is_none())?;
+
+        let handle = ReflectionProbeHandle(self.next_handle);
+        self.next_handle = self.next_handle.wrapping_add(1).max(1);
+
+        *slot = Some(ReflectionProbe {
+            handle,
+            position,
+            radius: radius.max(0.0),
+            captured: false,
+        });
+        self.capture_queue.push_back(handle);
+
+        Some(handle)
+    }
+
+    pub fn remove(&mut self, handle: ReflectionProbeHandle) {
+        for slot in &mut self.probes {
+            if matches!(slot, Some(probe) if probe.handle == handle) {
+                *slot = None;
+            }
+        }
+
+        self.capture_queue.retain(|queued| *queued != handle);
+    }
+
+    /// Moves a probe, and queues its capture. Until then, the old capture is used at the new position.
+    pub fn set_position(&mut self, handle: ReflectionProbeHandle, position: Vec3, radius: f32) {
+        if let Some(probe) = self.probe_mut(handle) {
+            probe.position = position;
+            probe.radius = radius.max(0.0);
+            self.recapture(handle);
+        }
+    }
+
+    /// Queues a capture of the probe, e.g. after the scene around it, or the lighting, changed.
+    pub fn recapture(&mut self, handle: ReflectionProbeHandle) {
+        if self.probe_mut(handle).is_some() && !self.capture_queue.contains(&handle) {
+            self.capture_queue.push_back(handle);
+        }
+    }
+
+    pub fn recapture_all(&mut self) {
+        let handles: Vec<_> = self
+            .probes
+            .iter()
+            .flatten()
+            .map(|probe| probe.handle)
+            .collect();
+
+        for handle in handles {
+            self.recapture(handle);
+        }
+    }
+
+    pub fn probe_count(&self) -> usize {
+        self.probes.iter().flatten().count()
+    }
+
+    fn probe_mut(&mut self, handle: ReflectionProbeHandle) -> Option<&mut ReflectionProbe> {
+        self.probes
+            .iter_mut()
+            .flatten()
+            .find(|probe| probe.handle == handle)
+    }
+
+    /// Captures and filters the next queued probe, if any. The irradiance cache must
+    /// already be prepared for sampling.
+    pub fn render(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        sky_cube: &rg::Handle<Image>,
+        ircache: &mut IrcacheRenderState,
+        tlas: Option<&rg::Handle<RayTracingAcceleration>>,
+        bindless_descriptor_set: vk::DescriptorSet,
+    ) -> ReflectionProbesOutput {
+        let mut cubes = rg
+            .get_or_create_temporal(
+                "reflection_probes.cubes",
+                ImageDesc::new_cube(vk::Format::R16G16B16A16_SFLOAT, PROBE_RESOLUTION)
+                    .image_type(ImageType::CubeArray)
+                    .array_elements(MAX_REFLECTION_PROBES as u32)
+                    .mip_levels(PROBE_MIP_COUNT)
+                    .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE),
+            )
+            .unwrap();
+
+        if let Some(tlas) = tlas.filter(|_| self.enabled) {
+            if let Some(handle) = self.capture_queue.pop_front() {
+                let slot = self
+                    .probes
+                    .iter()
+                    .position(|probe| matches!(probe, Some(probe) if probe.handle == handle))
+                    .expect("queued probes are removed from the queue with their slot");
+
+                let probe = self.probes[slot].as_mut().unwrap();
+                Self::capture(
+                    rg,
+                    probe.position,
+                    PROBE_RESOLUTION,
+                    slot as u32,
+                    &mut cubes,
+                    sky_cube,
+                    ircache,
+                    tlas,
+                    bindless_descriptor_set,
+                );
+                probe.captured = true;
+            }
+        }
+
+        let mut position_radius = [[0.0f32; 4]; MAX_REFLECTION_PROBES];
+        if self.enabled {
+            for (dst, probe) in position_radius.iter_mut().zip(&self.probes) {
+                if let Some(probe) = probe.as_ref().filter(|probe| probe.captured) {
+                    *dst = probe.position.extend(probe.radius).to_array();
+                }
+            }
+        }
+
+        let probe_count = position_radius
+            .iter()
+            .rposition(|probe| probe[3] > 0.0)
+            .map_or(0, |last| last + 1) as u32;
+
+        ReflectionProbesOutput {
+            cubes: cubes.into(),
+            constants: ReflectionProbeConstants {
+                position_radius,
+                probe_count,
+                mip_count: PROBE_MIP_COUNT as u32,
+                _pad: [0; 2],
+            },
+        }
+    }
+
+    /// Ray traces the surroundings of `position` into a cube map of `resolution`, and prefilters
+    /// it into `slot` of the `cubes` array, with mips for increasing roughness. Also used
+    /// by `EnvironmentCaptureRenderer`.
+    #[allow(clippy::too_many_arguments)]
+    pub(super) fn capture(
+        rg: &mut rg::TemporalRenderGraph,
+        position: Vec3,
+        resolution: u32,
+        slot: u32,
+        cubes: &mut rg::Handle<Image>,
+        sky_cube: &rg::Handle<Image>,
+        ircache: &mut IrcacheRenderState,
+        tlas: &rg::Handle<RayTracingAcceleration>,
+        bindless_descriptor_set: vk::DescriptorSet,
+    ) {
+        // Captured radiance with a full mip chain, for filtered importance sampling.
+        let mut capture_tex = rg.create(
+            ImageDesc::new_cube(vk::Format::R16G16B16A16_SFLOAT, resolution)
+                .all_mip_levels()
+                .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE),
+        );
+
+        SimpleRenderPass::new_rt(
+            rg.add_pass("reflection probe capture"),
+            ShaderSource::hlsl("/shaders/reflection_probes/capture.rgen.hlsl"),
+            [
+                ShaderSource::hlsl("/shaders/rt/gbuffer.rmiss.hlsl"),
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+            ],
+            [ShaderSource::hlsl("/shaders/rt/gbuffer.rchit.hlsl")],
+        )
+        .read(sky_cube)
+        .bind_mut(ircache)
+        .write_view(
+            &mut capture_tex,
+            ImageViewDesc::builder()
+                .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                .base_mip_level(0)
+                .level_count(Some(1)),
+        )
+        .constants((position.extend(1.0), resolution))
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .trace_rays(tlas, [resolution, resolution, 6]);
+
+        for target_mip in 1..(capture_tex.desc().mip_levels as u32) {
+            let target_width = (resolution >> target_mip).max(1);
+
+            SimpleRenderPass::new_compute(
+                rg.add_pass("reflection probe downsample"),
+                "/shaders/reflection_probes/downsample.hlsl",
+            )
+            .read_view(
+                &capture_tex,
+                ImageViewDesc::builder()
+                    .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                    .base_mip_level(target_mip - 1)
+                    .level_count(Some(1)),
+            )
+            .write_view(
+                &mut capture_tex,
+                ImageViewDesc::builder()
+                    .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                    .base_mip_level(target_mip)
+                    .level_count(Some(1)),
+            )
+            .constants(target_width)
+            .dispatch([target_width, target_width, 6]);
+        }
+
+        let mip_count = cubes.desc().mip_levels as u32;
+        for target_mip in 0..mip_count {
+            let target_width = (resolution >> target_mip).max(1);
+            let perceptual_roughness = target_mip as f32 / (mip_count - 1).max(1) as f32;
+
+            SimpleRenderPass::new_compute(
+                rg.add_pass("reflection probe filter"),
+                "/shaders/reflection_probes/filter.hlsl",
+            )
+            .read(&capture_tex)
+            .write_view(
+                cubes,
+                ImageViewDesc::builder()
+                    .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                    .base_mip_level(target_mip)
+                    .level_count(Some(1)),
+            )
+            .constants((target_width, slot, perceptual_roughness, resolution))
+            .dispatch([target_width, target_width, 6]);
+        }
+    }
+}
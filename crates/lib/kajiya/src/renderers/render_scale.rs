@@ -0,0 +1,137 @@
+use kajiya_backend::{ash::vk, vulkan::image::*};
+use kajiya_rg::{self as rg, SimpleRenderPass};
+
+use super::GbufferDepth;
+
+/// Frame inputs of a pass group rendered at a reduced resolution.
+pub struct ScaledFrameInputs {
+    pub gbuffer_depth: GbufferDepth,
+    pub reprojection_map: rg::Handle<Image>,
+    pub ao: rg::Handle<Image>,
+}
+
+pub fn scaled_extent(extent: [u32; 2], scale: f32) -> [u32; 2] {
+    [
+        ((extent[0] as f32 * scale).round() as u32).max(1),
+        ((extent[1] as f32 * scale).round() as u32).max(1),
+    ]
+}
+
+/// Point-samples the gbuffer, depth, reprojection map and AO down to `scale` times
+/// the render extent, so that pass groups can run at a lower resolution with unchanged
+/// shaders. The depth is stored in an `R32_SFLOAT` image rather than a depth buffer.
+///
+/// Returns `None` at full resolution, in which case the original inputs should be used.
+pub fn scale_frame_inputs(
+    rg: &mut rg::RenderGraph,
+    gbuffer_depth: &GbufferDepth,
+    reprojection_map: &rg::Handle<Image>,
+    ao: &rg::Handle<Image>,
+    scale: f32,
+) -> Option<ScaledFrameInputs> {
+    let full_extent = gbuffer_depth.gbuffer.desc().extent_2d();
+    let extent = scaled_extent(full_extent, scale.clamp(0.25, 1.0));
+
+    if extent == full_extent {
+        return None;
+    }
+
+    let scaled_desc = |desc: &ImageDesc| {
+        desc.usage(vk::ImageUsageFlags::empty())
+            .extent([extent[0], extent[1], 1])
+    };
+
+    let mut gbuffer = rg.create(scaled_desc(gbuffer_depth.gbuffer.desc()));
+    let mut depth =
+        rg.create(scaled_desc(gbuffer_depth.depth.desc()).format(vk::Format::R32_SFLOAT));
+    let mut geometric_normal = rg.create(
+        scaled_desc(gbuffer_depth.geometric_normal.desc()).format(vk::Format::R16G16B16A16_SFLOAT),
+    );
+    let mut scaled_reprojection_map = rg.create(scaled_desc(reprojection_map.desc()));
+    let mut scaled_ao = rg.create(scaled_desc(ao.desc()));
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("scale frame inputs"),
+        "/shaders/render_scale/downscale_frame_inputs.hlsl",
+    )
+    .read(&gbuffer_depth.gbuffer)
+    .read_depth(&gbuffer_depth.depth)
+    .read(&gbuffer_depth.geometric_normal)
+    .read(reprojection_map)
+    .read(ao)
+    .write(&mut gbuffer)
+    .write(&mut depth)
+    .write(&mut geometric_normal)
+    .write(&mut scaled_reprojection_map)
+    .write(&mut scaled_ao)
+    .constants((
+        gbuffer_depth.gbuffer.desc().extent_inv_extent_2d(),
+        gbuffer.desc().extent_inv_extent_2d(),
+    ))
+    .dispatch(gbuffer.desc().extent);
+
+    Some(ScaledFrameInputs {
+        gbuffer_depth: GbufferDepth::new(geometric_normal, gbuffer, depth),
+        reprojection_map: scaled_reprojection_map,
+        ao: scaled_ao,
+    })
+}
+
+/// Resamples `input` from the resolution of `input_depth` to that of `output_depth`,
+/// weighting the bilinear footprint by depth similarity to avoid bleeding across edges.
+///
+/// Both depth images can be either depth buffers or depth copies in color images.
+pub fn upsample_depth_aware(
+    rg: &mut rg::RenderGraph,
+    input: &rg::Handle<Image>,
+    input_depth: &rg::Handle<Image>,
+    output_depth: &rg::Handle<Image>,
+) -> rg::Handle<Image> {
+    let mut output_tex = rg.create(
+        input
+            .desc()
+            .usage(vk::ImageUsageFlags::empty())
+            .extent(output_depth.desc().extent),
+    );
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("upsample depth-aware"),
+        "/shaders/render_scale/upsample_depth_aware.hlsl",
+    )
+    .read(input)
+    .read_depth(input_depth)
+    .read_depth(output_depth)
+    .write(&mut output_tex)
+    .constants((
+        input.desc().extent_inv_extent_2d(),
+        output_tex.desc().extent_inv_extent_2d(),
+    ))
+    .dispatch(output_tex.desc().extent);
+
+    output_tex
+}
+
+/// Bilinearly resamples `input` to `extent`, without any temporal accumulation.
+pub fn upsample_bilinear(
+    rg: &mut rg::RenderGraph,
+    input: &rg::Handle<Image>,
+    extent: [u32; 2],
+) -> rg::Handle<Image> {
+    let mut output_tex = rg.create(
+        input
+            .desc()
+            .usage(vk::ImageUsageFlags::empty())
+            .extent([extent[0], extent[1], 1]),
+    );
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("upsample bilinear"),
+        "/shaders/render_scale/upsample_bilinear.hlsl",
+    )
+    .read(input)
+    .write(&mut output_tex)
+    .constants((output_tex.desc().extent_inv_extent_2d(),))
+    .dispatch(output_tex.desc().extent);
+
+    output_tex
+}
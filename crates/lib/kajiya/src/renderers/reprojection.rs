@@ -32,7 +32,7 @@ pub fn calculate_reprojection_map(
         rg.add_pass("reprojection map"),
         "/shaders/calculate_reprojection_map.hlsl",
     )
-    .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+    .read_depth(&gbuffer_depth.depth)
     .read(&gbuffer_depth.geometric_normal)
     .read(&prev_depth)
     .read(velocity_img)
@@ -44,7 +44,7 @@ pub fn calculate_reprojection_map(
         rg.add_pass("copy depth"),
         "copy_depth_to_r::copy_depth_to_r_cs",
     )
-    .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+    .read_depth(&gbuffer_depth.depth)
     .write(&mut prev_depth)
     .dispatch(prev_depth.desc().extent);
 
@@ -0,0 +1,84 @@
+use super::{ssgi::SsgiRenderer, GbufferDepth, PingPongTemporalResource};
+use kajiya_backend::{
+    ash::vk,
+    vulkan::{image::*, ray_tracing::RayTracingAcceleration, shader::ShaderSource},
+};
+use kajiya_rg::{self as rg, SimpleRenderPass};
+
+/// Ray-traced ambient occlusion: short rays from half-res G-buffer samples, denoised
+/// with the same spatial and temporal filters as SSGI.
+///
+/// Unlike SSGI, it sees occluders which are off-screen or hidden behind other surfaces,
+/// and doesn't change as they move in and out of view. Which of the two feeds shading
+/// is chosen by `WorldRenderer::ambient_occlusion`.
+pub struct RtaoRenderer {
+    /// Length of the occlusion rays in meters. Longer rays darken larger creases,
+    /// and cost more to trace.
+    pub ray_length: f32,
+
+    /// Rays traced for each half-res pixel every frame.
+    pub rays_per_pixel: u32,
+
+    rtao_tex: PingPongTemporalResource,
+}
+
+impl Default for RtaoRenderer {
+    fn default() -> Self {
+        Self {
+            ray_length: 0.5,
+            rays_per_pixel: 1,
+            rtao_tex: PingPongTemporalResource::new("rtao"),
+        }
+    }
+}
+
+impl RtaoRenderer {
+    pub fn render(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        gbuffer_depth: &GbufferDepth,
+        reprojection_map: &rg::Handle<Image>,
+        tlas: &rg::Handle<RayTracingAcceleration>,
+        bindless_descriptor_set: vk::DescriptorSet,
+    ) -> rg::ReadOnlyHandle<Image> {
+        let gbuffer_desc = gbuffer_depth.gbuffer.desc();
+        let half_view_normal_tex = gbuffer_depth.half_view_normal(rg);
+
+        let mut rtao_tex = rg.create(
+            gbuffer_desc
+                .usage(vk::ImageUsageFlags::empty())
+                .half_res()
+                .format(vk::Format::R16_SFLOAT),
+        );
+
+        SimpleRenderPass::new_rt(
+            rg.add_pass("rtao trace"),
+            ShaderSource::hlsl("/shaders/rtao/trace_rtao.rgen.hlsl"),
+            [
+                // Duplicated because `rt.hlsl` hardcodes miss index to 1
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+            ],
+            std::iter::empty(),
+        )
+        .read(&*half_view_normal_tex)
+        .read_depth(&gbuffer_depth.depth)
+        .write(&mut rtao_tex)
+        .constants((
+            gbuffer_desc.extent_inv_extent_2d(),
+            self.ray_length.max(0.0),
+            self.rays_per_pixel.max(1),
+        ))
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .trace_rays(tlas, rtao_tex.desc().extent);
+
+        SsgiRenderer::filter_ssgi(
+            rg,
+            "rtao",
+            &rtao_tex,
+            gbuffer_depth,
+            reprojection_map,
+            &mut self.rtao_tex,
+        )
+    }
+}
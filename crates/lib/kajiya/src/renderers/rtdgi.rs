@@ -5,8 +5,12 @@ use kajiya_backend::{
 use kajiya_rg::{self as rg, SimpleRenderPass};
 
 use super::{
-    ircache::IrcacheRenderState, wrc::WrcRenderState, GbufferDepth, PingPongTemporalResource,
+    denoiser::{copy_to_history, DenoiserInput, DenoiserSignal, Denoisers},
+    ircache::IrcacheRenderState,
+    wrc::WrcRenderState,
+    GbufferDepth, PingPongTemporalResource,
 };
+use crate::frame_graph_config::QualityTier;
 
 pub struct RtdgiRenderer {
     temporal_radiance_tex: PingPongTemporalResource,
@@ -22,7 +26,56 @@ pub struct RtdgiRenderer {
     temporal_hit_normal_tex: PingPongTemporalResource,
 
     pub spatial_reuse_pass_count: u32,
-    pub use_raytraced_reservoir_visibility: bool,
+
+    /// Neighbors resampled per pixel in the first spatial reuse pass,
+    /// and in the subsequent ones.
+    pub spatial_reuse_sample_count: [u32; 2],
+
+    /// Cap on the sample count (M) of temporal reservoirs, in frames' worth of candidates.
+    /// Higher values reduce noise, but make the GI slower to respond to lighting changes.
+    pub temporal_reservoir_m_clamp: f32,
+
+    pub bias_correction: RtdgiBiasCorrection,
+
+    /// Set from `FrameGraphConfig::quality` by `WorldRenderer` every frame.
+    pub quality: QualityTier,
+}
+
+/// How visibility between a pixel and the hit points of reused neighbor samples is accounted for.
+/// Without it, spatial reuse leaks light through thin occluders and into corners.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RtdgiBiasCorrection {
+    /// No visibility checks. Cheapest, but biased.
+    None,
+
+    /// Screen-space ray march towards neighbor hit points in the last spatial reuse pass.
+    ScreenSpace,
+
+    /// Screen-space ray march to guide resampling, followed by a ray-traced
+    /// visibility check of the final reservoirs.
+    RayTraced,
+}
+
+impl RtdgiBiasCorrection {
+    pub const ALL: [RtdgiBiasCorrection; 3] = [
+        RtdgiBiasCorrection::None,
+        RtdgiBiasCorrection::ScreenSpace,
+        RtdgiBiasCorrection::RayTraced,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RtdgiBiasCorrection::None => "None",
+            RtdgiBiasCorrection::ScreenSpace => "Screen-space",
+            RtdgiBiasCorrection::RayTraced => "Ray-traced",
+        }
+    }
+}
+
+impl Default for RtdgiBiasCorrection {
+    fn default() -> Self {
+        Self::ScreenSpace
+    }
 }
 
 const COLOR_BUFFER_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
@@ -40,7 +93,10 @@ impl Default for RtdgiRenderer {
             temporal2_variance_tex: PingPongTemporalResource::new("rtdgi.temporal2_var"),
             temporal_hit_normal_tex: PingPongTemporalResource::new("rtdgi.hit_normal"),
             spatial_reuse_pass_count: 2,
-            use_raytraced_reservoir_visibility: false,
+            spatial_reuse_sample_count: [8, 5],
+            temporal_reservoir_m_clamp: 20.0,
+            bias_correction: RtdgiBiasCorrection::default(),
+            quality: QualityTier::default(),
         }
     }
 }
@@ -54,6 +110,31 @@ pub struct RtdgiCandidates {
     pub candidate_radiance_tex: rg::Handle<Image>,
     pub candidate_normal_tex: rg::Handle<Image>,
     pub candidate_hit_tex: rg::Handle<Image>,
+
+    // False if allocated without running RTDGI, in which case there are no rays to reuse.
+    pub(super) contains_rtdgi_rays: bool,
+}
+
+impl RtdgiCandidates {
+    /// Uninitialized candidate images for the half-res of `gbuffer_desc`, for use when
+    /// reflections and RTDGI run at different resolutions.
+    pub fn allocate(rg: &mut rg::RenderGraph, gbuffer_desc: &ImageDesc) -> Self {
+        Self {
+            candidate_radiance_tex: rg.create(
+                gbuffer_desc
+                    .half_res()
+                    .format(vk::Format::R16G16B16A16_SFLOAT),
+            ),
+            candidate_normal_tex: rg
+                .create(gbuffer_desc.half_res().format(vk::Format::R8G8B8A8_SNORM)),
+            candidate_hit_tex: rg.create(
+                gbuffer_desc
+                    .half_res()
+                    .format(vk::Format::R16G16B16A16_SFLOAT),
+            ),
+            contains_rtdgi_rays: false,
+        }
+    }
 }
 
 pub struct RtdgiOutput {
@@ -129,7 +210,7 @@ impl RtdgiRenderer {
             "/shaders/rtdgi/spatial_filter.hlsl",
         )
         .read(input_color)
-        .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(&gbuffer_depth.depth)
         .read(ssao_tex)
         .read(&gbuffer_depth.geometric_normal)
         .write(&mut spatial_filtered_tex)
@@ -175,7 +256,7 @@ impl RtdgiRenderer {
         rg: &mut rg::TemporalRenderGraph,
         ReprojectedRtdgi {
             reprojected_history_tex,
-            temporal_output_tex,
+            mut temporal_output_tex,
         }: ReprojectedRtdgi,
         gbuffer_depth: &GbufferDepth,
         reprojection_map: &rg::Handle<Image>,
@@ -185,7 +266,21 @@ impl RtdgiRenderer {
         wrc: &WrcRenderState,
         tlas: &rg::Handle<RayTracingAcceleration>,
         ssao_tex: &rg::Handle<Image>,
+        denoisers: &mut Denoisers,
+        full_quality: bool,
     ) -> RtdgiOutput {
+        // Reduced quality keeps at most one spatial reuse pass, and skips ray-traced
+        // occlusion checks; see `RenderFeatureFlags::FULL_QUALITY_GI`.
+        let (spatial_reuse_pass_count, bias_correction) = if full_quality {
+            (self.spatial_reuse_pass_count, self.bias_correction)
+        } else {
+            let bias_correction = match self.bias_correction {
+                RtdgiBiasCorrection::RayTraced => RtdgiBiasCorrection::ScreenSpace,
+                other => other,
+            };
+            (self.spatial_reuse_pass_count.min(1), bias_correction)
+        };
+
         let mut half_ssao_tex = rg.create(
             ssao_tex
                 .desc()
@@ -224,20 +319,12 @@ impl RtdgiRenderer {
                 .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE),
             );
 
-        let mut candidate_radiance_tex = rg.create(
-            gbuffer_desc
-                .half_res()
-                .format(vk::Format::R16G16B16A16_SFLOAT),
-        );
-
-        let mut candidate_normal_tex =
-            rg.create(gbuffer_desc.half_res().format(vk::Format::R8G8B8A8_SNORM));
-
-        let mut candidate_hit_tex = rg.create(
-            gbuffer_desc
-                .half_res()
-                .format(vk::Format::R16G16B16A16_SFLOAT),
-        );
+        let RtdgiCandidates {
+            mut candidate_radiance_tex,
+            mut candidate_normal_tex,
+            mut candidate_hit_tex,
+            ..
+        } = RtdgiCandidates::allocate(rg, gbuffer_desc);
 
         let mut temporal_reservoir_packed_tex = rg.create(
             gbuffer_desc
@@ -290,9 +377,10 @@ impl RtdgiRenderer {
                         .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE),
                 );
 
-            SimpleRenderPass::new_rt(
+            SimpleRenderPass::new_rt_with_ray_query_fallback(
                 rg.add_pass("rtdgi validate"),
-                ShaderSource::hlsl("/shaders/rtdgi/diffuse_validate.rgen.hlsl"),
+                ShaderSource::hlsl("/shaders/rtdgi/diffuse_validate.rgen.hlsl")
+                    .with_defines(&[self.quality.shader_define("RTDGI_QUALITY")]),
                 [
                     ShaderSource::hlsl("/shaders/rt/gbuffer.rmiss.hlsl"),
                     ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
@@ -300,7 +388,7 @@ impl RtdgiRenderer {
                 [ShaderSource::hlsl("/shaders/rt/gbuffer.rchit.hlsl")],
             )
             .read(&*half_view_normal_tex)
-            .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+            .read_depth(&gbuffer_depth.depth)
             .read(&reprojected_history_tex)
             .write(&mut reservoir_history_tex)
             .read(&ray_history_tex)
@@ -318,17 +406,19 @@ impl RtdgiRenderer {
             let mut rt_history_validity_input_tex =
                 rg.create(gbuffer_desc.half_res().format(vk::Format::R8_UNORM));
 
-            SimpleRenderPass::new_rt(
+            SimpleRenderPass::new_rt_with_ray_query_fallback(
                 rg.add_pass("rtdgi trace"),
-                ShaderSource::hlsl("/shaders/rtdgi/trace_diffuse.rgen.hlsl"),
+                ShaderSource::hlsl("/shaders/rtdgi/trace_diffuse.rgen.hlsl")
+                    .with_defines(&[self.quality.shader_define("RTDGI_QUALITY")]),
                 [
                     ShaderSource::hlsl("/shaders/rt/gbuffer.rmiss.hlsl"),
                     ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
                 ],
                 [ShaderSource::hlsl("/shaders/rt/gbuffer.rchit.hlsl")],
             )
+            .async_compute()
             .read(&*half_view_normal_tex)
-            .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+            .read_depth(&gbuffer_depth.depth)
             .read(&reprojected_history_tex)
             .read(reprojection_map)
             .bind_mut(ircache)
@@ -365,7 +455,7 @@ impl RtdgiRenderer {
                 "/shaders/rtdgi/restir_temporal.hlsl",
             )
             .read(&*half_view_normal_tex)
-            .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+            .read_depth(&gbuffer_depth.depth)
             .read(&candidate_radiance_tex)
             .read(&candidate_normal_tex)
             .read(&candidate_hit_tex)
@@ -384,7 +474,10 @@ impl RtdgiRenderer {
             .write(&mut reservoir_output_tex)
             .write(&mut candidate_output_tex)
             .write(&mut temporal_reservoir_packed_tex)
-            .constants((gbuffer_desc.extent_inv_extent_2d(),))
+            .constants((
+                gbuffer_desc.extent_inv_extent_2d(),
+                self.temporal_reservoir_m_clamp.max(1.0),
+            ))
             .raw_descriptor_set(1, bindless_descriptor_set)
             .dispatch(radiance_output_tex.desc().extent);
 
@@ -425,18 +518,20 @@ impl RtdgiRenderer {
             let mut reservoir_input_tex = &mut temporal_reservoir_tex;
             let mut bounced_radiance_input_tex = &radiance_tex;
 
-            for spatial_reuse_pass_idx in 0..self.spatial_reuse_pass_count {
+            for spatial_reuse_pass_idx in 0..spatial_reuse_pass_count {
                 // Only do occlusion checks in the final resampling pass.
                 // Otherwise we get accumulation of darkening.
-                let perform_occulsion_raymarch: u32 =
-                    if spatial_reuse_pass_idx + 1 == self.spatial_reuse_pass_count {
-                        1
-                    } else {
-                        0
-                    };
+                let perform_occulsion_raymarch: u32 = if spatial_reuse_pass_idx + 1
+                    == spatial_reuse_pass_count
+                    && bias_correction != RtdgiBiasCorrection::None
+                {
+                    1
+                } else {
+                    0
+                };
 
                 let occlusion_raymarch_importance_only: u32 =
-                    if self.use_raytraced_reservoir_visibility {
+                    if bias_correction == RtdgiBiasCorrection::RayTraced {
                         1
                     } else {
                         0
@@ -450,7 +545,7 @@ impl RtdgiRenderer {
                 .read(bounced_radiance_input_tex)
                 .read(&*half_view_normal_tex)
                 .read(&*half_depth_tex)
-                .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+                .read_depth(&gbuffer_depth.depth)
                 .read(&half_ssao_tex)
                 .read(&temporal_reservoir_packed_tex)
                 .read(&reprojected_history_tex)
@@ -462,6 +557,9 @@ impl RtdgiRenderer {
                     spatial_reuse_pass_idx,
                     perform_occulsion_raymarch,
                     occlusion_raymarch_importance_only,
+                    self.spatial_reuse_sample_count[0].max(1),
+                    self.spatial_reuse_sample_count[1].max(1),
+                    self.temporal_reservoir_m_clamp.max(1.0),
                 ))
                 .dispatch(reservoir_output_tex0.desc().extent);
 
@@ -475,8 +573,8 @@ impl RtdgiRenderer {
                 bounced_radiance_input_tex = &mut bounced_radiance_output_tex1;
             }
 
-            if self.use_raytraced_reservoir_visibility {
-                SimpleRenderPass::new_rt(
+            if bias_correction == RtdgiBiasCorrection::RayTraced {
+                SimpleRenderPass::new_rt_with_ray_query_fallback(
                     rg.add_pass("restir check"),
                     ShaderSource::hlsl("/shaders/rtdgi/restir_check.rgen.hlsl"),
                     [
@@ -506,7 +604,7 @@ impl RtdgiRenderer {
             .read(&radiance_tex)
             .read(reservoir_input_tex)
             .read(&gbuffer_depth.gbuffer)
-            .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+            .read_depth(&gbuffer_depth.depth)
             .read(&*half_view_normal_tex)
             .read(&*half_depth_tex)
             .read(ssao_tex)
@@ -525,23 +623,40 @@ impl RtdgiRenderer {
             irradiance_output_tex
         };
 
-        let filtered_tex = self.temporal(
+        let denoised_tex = denoisers.denoise(
             rg,
-            &irradiance_tex,
-            gbuffer_depth,
-            reprojection_map,
-            &reprojected_history_tex,
-            &invalidity_output_tex,
-            temporal_output_tex,
+            &DenoiserInput {
+                signal: DenoiserSignal::DiffuseGi,
+                noisy: &irradiance_tex,
+                gbuffer_depth,
+                reprojection_map,
+                hit_distance: None,
+                bindless_descriptor_set,
+            },
         );
 
-        let filtered_tex = Self::spatial(
-            rg,
-            &filtered_tex,
-            gbuffer_depth,
-            ssao_tex,
-            bindless_descriptor_set,
-        );
+        let filtered_tex = if let Some(denoised_tex) = denoised_tex {
+            copy_to_history(rg, &denoised_tex, &mut temporal_output_tex);
+            denoised_tex
+        } else {
+            let filtered_tex = self.temporal(
+                rg,
+                &irradiance_tex,
+                gbuffer_depth,
+                reprojection_map,
+                &reprojected_history_tex,
+                &invalidity_output_tex,
+                temporal_output_tex,
+            );
+
+            Self::spatial(
+                rg,
+                &filtered_tex,
+                gbuffer_depth,
+                ssao_tex,
+                bindless_descriptor_set,
+            )
+        };
 
         RtdgiOutput {
             screen_irradiance_tex: filtered_tex.into(),
@@ -549,6 +664,7 @@ impl RtdgiRenderer {
                 candidate_radiance_tex,
                 candidate_normal_tex,
                 candidate_hit_tex,
+                contains_rtdgi_rays: true,
             },
         }
     }
@@ -9,9 +9,14 @@ use kajiya_backend::{
 use kajiya_rg::{self as rg, SimpleRenderPass};
 
 use super::{
-    ircache::IrcacheRenderState, rtdgi::RtdgiCandidates, wrc::WrcRenderState, GbufferDepth,
-    PingPongTemporalResource,
+    denoiser::copy_to_history,
+    ircache::IrcacheRenderState,
+    reflection_probes::{ReflectionProbeConstants, ReflectionProbesOutput},
+    rtdgi::RtdgiCandidates,
+    wrc::WrcRenderState,
+    GbufferDepth, PingPongTemporalResource,
 };
+use crate::frame_graph_config::QualityTier;
 
 use blue_noise_sampler::spp64::*;
 
@@ -31,6 +36,27 @@ pub struct RtrRenderer {
     sobol_buf: Arc<Buffer>,
 
     pub reuse_rtdgi_rays: bool,
+
+    /// Set from `FrameGraphConfig::quality` by `WorldRenderer` every frame.
+    pub quality: QualityTier,
+}
+
+// Mirrored in `rtr/reflection.rgen.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ReflectionTraceConstants {
+    gbuffer_tex_size: [f32; 4],
+    reuse_rtdgi_rays: u32,
+    _pad: [u32; 3],
+    reflection_probes: ReflectionProbeConstants,
+}
+
+// Mirrored in `rtr/reflection_validate.rgen.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ReflectionValidateConstants {
+    gbuffer_tex_size: [f32; 4],
+    reflection_probes: ReflectionProbeConstants,
 }
 
 fn as_byte_slice_unchecked<T: Copy>(v: &[T]) -> &[u8] {
@@ -68,6 +94,7 @@ impl RtrRenderer {
             sobol_buf: make_lut_buffer(device, SOBOL)?,
 
             reuse_rtdgi_rays: true,
+            quality: QualityTier::default(),
         })
     }
 }
@@ -99,6 +126,7 @@ impl RtrRenderer {
         rtdgi_candidates: RtdgiCandidates,
         ircache: &mut IrcacheRenderState,
         wrc: &WrcRenderState,
+        reflection_probes: &ReflectionProbesOutput,
     ) -> TracedRtr {
         let gbuffer_desc = gbuffer_depth.gbuffer.desc();
 
@@ -106,6 +134,7 @@ impl RtrRenderer {
             candidate_radiance_tex: mut refl0_tex,
             candidate_hit_tex: mut refl1_tex,
             candidate_normal_tex: mut refl2_tex,
+            contains_rtdgi_rays,
         } = rtdgi_candidates;
 
         let ranking_tile_buf = rg.import(
@@ -127,11 +156,16 @@ impl RtrRenderer {
                 .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE),
         );
 
-        let reuse_rtdgi_rays_u32 = if self.reuse_rtdgi_rays { 1u32 } else { 0u32 };
+        let reuse_rtdgi_rays_u32 = if self.reuse_rtdgi_rays && contains_rtdgi_rays {
+            1u32
+        } else {
+            0u32
+        };
 
-        SimpleRenderPass::new_rt(
+        SimpleRenderPass::new_rt_with_ray_query_fallback(
             rg.add_pass("reflection trace"),
-            ShaderSource::hlsl("/shaders/rtr/reflection.rgen.hlsl"),
+            ShaderSource::hlsl("/shaders/rtr/reflection.rgen.hlsl")
+                .with_defines(&[self.quality.shader_define("RTR_QUALITY")]),
             [
                 ShaderSource::hlsl("/shaders/rt/gbuffer.rmiss.hlsl"),
                 ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
@@ -139,7 +173,7 @@ impl RtrRenderer {
             [ShaderSource::hlsl("/shaders/rt/gbuffer.rchit.hlsl")],
         )
         .read(&gbuffer_depth.gbuffer)
-        .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(&gbuffer_depth.depth)
         .read(&ranking_tile_buf)
         .read(&scambling_tile_buf)
         .read(&sobol_buf)
@@ -151,7 +185,13 @@ impl RtrRenderer {
         .write(&mut refl1_tex)
         .write(&mut refl2_tex)
         .write(&mut rng_output_tex)
-        .constants((gbuffer_desc.extent_inv_extent_2d(), reuse_rtdgi_rays_u32))
+        .read(&reflection_probes.cubes)
+        .constants(ReflectionTraceConstants {
+            gbuffer_tex_size: gbuffer_desc.extent_inv_extent_2d(),
+            reuse_rtdgi_rays: reuse_rtdgi_rays_u32,
+            _pad: [0; 3],
+            reflection_probes: reflection_probes.constants,
+        })
         .raw_descriptor_set(1, bindless_descriptor_set)
         .trace_rays(tlas, refl0_tex.desc().extent);
 
@@ -205,9 +245,10 @@ impl RtrRenderer {
                         .format(vk::Format::R16G16B16A16_SFLOAT),
                 );
 
-            SimpleRenderPass::new_rt(
+            SimpleRenderPass::new_rt_with_ray_query_fallback(
                 rg.add_pass("reflection validate"),
-                ShaderSource::hlsl("/shaders/rtr/reflection_validate.rgen.hlsl"),
+                ShaderSource::hlsl("/shaders/rtr/reflection_validate.rgen.hlsl")
+                    .with_defines(&[self.quality.shader_define("RTR_QUALITY")]),
                 [
                     ShaderSource::hlsl("/shaders/rt/gbuffer.rmiss.hlsl"),
                     ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
@@ -215,7 +256,7 @@ impl RtrRenderer {
                 [ShaderSource::hlsl("/shaders/rt/gbuffer.rchit.hlsl")],
             )
             .read(&gbuffer_depth.gbuffer)
-            .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+            .read_depth(&gbuffer_depth.depth)
             .read(rtdgi_irradiance)
             .read(sky_cube)
             .write(&mut refl_restir_invalidity_tex)
@@ -226,7 +267,11 @@ impl RtrRenderer {
             .read(&rng_history_tex)
             .write(&mut irradiance_history_tex)
             .write(&mut reservoir_history_tex)
-            .constants((gbuffer_desc.extent_inv_extent_2d(),))
+            .read(&reflection_probes.cubes)
+            .constants(ReflectionValidateConstants {
+                gbuffer_tex_size: gbuffer_desc.extent_inv_extent_2d(),
+                reflection_probes: reflection_probes.constants,
+            })
             .raw_descriptor_set(1, bindless_descriptor_set)
             .trace_rays(tlas, refl0_tex.desc().half_res().extent);
             //.trace_rays(tlas, refl0_tex.desc().extent);
@@ -237,7 +282,7 @@ impl RtrRenderer {
             )
             .read(&gbuffer_depth.gbuffer)
             .read(&*half_view_normal_tex)
-            .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+            .read_depth(&gbuffer_depth.depth)
             .read(&refl0_tex)
             .read(&refl1_tex)
             .read(&refl2_tex)
@@ -292,7 +337,7 @@ impl RtrRenderer {
             "/shaders/rtr/resolve.hlsl",
         )
         .read(&gbuffer_depth.gbuffer)
-        .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(&gbuffer_depth.depth)
         .read(&refl0_tex)
         .read(&refl1_tex)
         .read(&refl2_tex)
@@ -331,13 +376,15 @@ impl RtrRenderer {
     ) -> TracedRtr {
         let gbuffer_desc = gbuffer_depth.gbuffer.desc();
 
-        let resolved_tex = rg.create(
+        // Black, so that triangle light specular can still be added on top.
+        let mut resolved_tex = rg.create(
             gbuffer_depth
                 .gbuffer
                 .desc()
                 .usage(vk::ImageUsageFlags::empty())
-                .format(vk::Format::R8G8B8A8_UNORM),
+                .format(vk::Format::B10G11R11_UFLOAT_PACK32),
         );
+        rg::imageops::clear_color(rg, &mut resolved_tex, [0.0; 4]);
 
         let (temporal_output_tex, history_tex) = self
             .temporal_tex
@@ -375,7 +422,7 @@ impl TracedRtr {
         )
         .read(&self.resolved_tex)
         .read(&self.history_tex)
-        .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(&gbuffer_depth.depth)
         .read(&self.ray_len_tex)
         .read(reprojection_map)
         .read(&self.refl_restir_invalidity_tex)
@@ -389,7 +436,7 @@ impl TracedRtr {
             "/shaders/rtr/spatial_cleanup.hlsl",
         )
         .read(&self.temporal_output_tex)
-        .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(&gbuffer_depth.depth)
         .read(&gbuffer_depth.geometric_normal)
         .write(&mut self.resolved_tex) // reuse
         .constants(SPATIAL_RESOLVE_OFFSETS)
@@ -397,6 +444,21 @@ impl TracedRtr {
 
         self.resolved_tex
     }
+
+    /// Length of the reflection rays, for `DenoiserInput::hit_distance`
+    pub fn ray_len_tex(&self) -> &rg::Handle<Image> {
+        &self.ray_len_tex
+    }
+
+    /// Takes the output of a `Denoiser` instead of running `filter_temporal`.
+    pub fn use_denoised(
+        mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        denoised: rg::Handle<Image>,
+    ) -> rg::Handle<Image> {
+        copy_to_history(rg, &denoised, &mut self.temporal_output_tex);
+        denoised
+    }
 }
 
 pub const SPATIAL_RESOLVE_OFFSETS: [(i32, i32, i32, i32); 16 * 4 * 8] = [
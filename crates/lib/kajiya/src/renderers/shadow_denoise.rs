@@ -9,20 +9,28 @@ pub struct ShadowDenoiseRenderer {
 
 impl Default for ShadowDenoiseRenderer {
     fn default() -> Self {
-        Self {
-            accum: PingPongTemporalResource::new("shadow_denoise_accum"),
-            moments: PingPongTemporalResource::new("shadow_denoise_moments"),
-        }
+        Self::new("shadow_denoise")
     }
 }
 
 impl ShadowDenoiseRenderer {
+    /// `name` keeps the temporal history apart from that of other instances.
+    pub fn new(name: &str) -> Self {
+        Self {
+            accum: PingPongTemporalResource::new(&format!("{}_accum", name)),
+            moments: PingPongTemporalResource::new(&format!("{}_moments", name)),
+        }
+    }
+
+    /// `spatial_pass_count` is the number of à-trous filter passes, from 1 to 3, each doubling
+    /// the footprint; fewer keep narrow penumbrae sharp.
     pub fn render(
         &mut self,
         rg: &mut rg::TemporalRenderGraph,
         gbuffer_depth: &GbufferDepth,
         shadow_mask: &rg::Handle<Image>,
         reprojection_map: &rg::Handle<Image>,
+        spatial_pass_count: u32,
     ) -> rg::ReadOnlyHandle<Image> {
         let gbuffer_desc = gbuffer_depth.gbuffer.desc();
 
@@ -87,7 +95,7 @@ impl ShadowDenoiseRenderer {
         ))
         .dispatch(gbuffer_desc.extent);
 
-        let mut temp = rg.create(spatial_image_desc);
+        // The first pass feeds the temporal history, so it always runs.
         Self::filter_spatial(
             rg,
             1,
@@ -98,6 +106,11 @@ impl ShadowDenoiseRenderer {
             bitpacked_shadow_mask_extent,
         );
 
+        if spatial_pass_count <= 1 {
+            return accum_image.into();
+        }
+
+        let mut temp = rg.create(spatial_image_desc);
         Self::filter_spatial(
             rg,
             2,
@@ -108,6 +121,10 @@ impl ShadowDenoiseRenderer {
             bitpacked_shadow_mask_extent,
         );
 
+        if spatial_pass_count == 2 {
+            return temp.into();
+        }
+
         Self::filter_spatial(
             rg,
             4,
@@ -137,7 +154,7 @@ impl ShadowDenoiseRenderer {
         .read(input_image)
         .read(metadata_image)
         .read(&gbuffer_depth.geometric_normal)
-        .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(&gbuffer_depth.depth)
         .write(output_image)
         .constants((
             output_image.desc().extent_inv_extent_2d(),
@@ -6,18 +6,21 @@ use kajiya_rg::{self as rg};
 use rg::{RenderGraph, SimpleRenderPass};
 
 use super::GbufferDepth;
+use crate::frame_graph_config::QualityTier;
 
 pub fn trace_sun_shadow_mask(
     rg: &mut RenderGraph,
     gbuffer_depth: &GbufferDepth,
     tlas: &rg::Handle<RayTracingAcceleration>,
     bindless_descriptor_set: vk::DescriptorSet,
+    quality: QualityTier,
 ) -> rg::Handle<Image> {
     let mut output_img = rg.create(gbuffer_depth.depth.desc().format(vk::Format::R8_UNORM));
 
-    SimpleRenderPass::new_rt(
+    SimpleRenderPass::new_rt_with_ray_query_fallback(
         rg.add_pass("trace shadow mask"),
-        ShaderSource::hlsl("/shaders/rt/trace_sun_shadow_mask.rgen.hlsl"),
+        ShaderSource::hlsl("/shaders/rt/trace_sun_shadow_mask.rgen.hlsl")
+            .with_defines(&[quality.shader_define("SHADOW_QUALITY")]),
         [
             // Duplicated because `rt.hlsl` hardcodes miss index to 1
             ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
@@ -25,8 +28,9 @@ pub fn trace_sun_shadow_mask(
         ],
         std::iter::empty(),
     )
-    .read_aspect(&gbuffer_depth.depth, vk::ImageAspectFlags::DEPTH)
+    .read_depth(&gbuffer_depth.depth)
     .read(&gbuffer_depth.geometric_normal)
+    .read(&gbuffer_depth.gbuffer)
     .write(&mut output_img)
     .raw_descriptor_set(1, bindless_descriptor_set)
     .trace_rays(tlas, output_img.desc().extent);
@@ -0,0 +1,46 @@
+use kajiya_rg::{self as rg};
+use rg::{Buffer, SimpleRenderPass};
+
+/// Byte offsets into the vertex buffer used by the skinning of one mesh instance.
+#[derive(Clone, Copy)]
+pub struct SkinnedVertexOffsets {
+    pub vertex_count: u32,
+
+    // Bind pose, as uploaded with the mesh
+    pub src_core: u32,
+    pub src_tangent: u32,
+
+    // Deformed copy owned by the instance. `dst_tangent` is zero for meshes without tangents.
+    pub dst_core: u32,
+    pub dst_tangent: u32,
+
+    // Joint indices and weights
+    pub joints: u32,
+    pub weights: u32,
+}
+
+/// Deforms the bind pose vertices of a mesh by `joint_transforms` (row-major 3x4),
+/// and writes them to the instance's region of the vertex buffer.
+pub fn skin_vertices(
+    rg: &mut rg::RenderGraph,
+    vertex_buffer: &mut rg::Handle<Buffer>,
+    offsets: SkinnedVertexOffsets,
+    joint_transforms: Vec<[f32; 12]>,
+) {
+    SimpleRenderPass::new_compute(
+        rg.add_pass("skin vertices"),
+        "/shaders/skinning/skin_vertices.hlsl",
+    )
+    .write(vertex_buffer)
+    .dynamic_storage_buffer_vec(joint_transforms)
+    .constants((
+        offsets.vertex_count,
+        offsets.src_core,
+        offsets.src_tangent,
+        offsets.dst_core,
+        offsets.dst_tangent,
+        offsets.joints,
+        offsets.weights,
+    ))
+    .dispatch([offsets.vertex_count, 1, 1]);
+}
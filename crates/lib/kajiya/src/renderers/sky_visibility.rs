@@ -0,0 +1,210 @@
+use glam::Vec3;
+use kajiya_backend::{
+    ash::vk,
+    vulkan::{image::*, ray_tracing::RayTracingAcceleration, shader::ShaderSource},
+};
+use kajiya_rg::{self as rg, GetOrCreateTemporal, SimpleRenderPass};
+
+// Must match `SKY_VISIBILITY_RESOLUTION` in `inc/sky_visibility.hlsl`
+pub const SKY_VISIBILITY_RESOLUTION: u32 = 256;
+
+/// Mirrored in `inc/sky_visibility.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct SkyVisibilityConstants {
+    // Integer XZ coordinates of the field's first cell in `xy`, cell size in meters in `z`,
+    // and `1` in `w` if the field is valid; `0` makes lookups return full visibility.
+    origin_cell_size: [f32; 4],
+    // Distance at which the field starts to apply, inverse of the distance over which it fades in,
+    // and its strength.
+    fade_start: f32,
+    fade_inv_length: f32,
+    strength: f32,
+    _pad: u32,
+}
+
+pub struct SkyVisibilityOutput {
+    /// Height of the top-most surface in each cell, addressed toroidally.
+    pub heightmap: rg::ReadOnlyHandle<Image>,
+
+    /// Fraction of the sky visible from the top of each cell, addressed toroidally.
+    pub visibility: rg::ReadOnlyHandle<Image>,
+
+    pub constants: SkyVisibilityConstants,
+}
+
+/// A coarse world-space field of sky visibility (large-scale ambient occlusion) around the camera,
+/// which darkens sky lighting on distant geometry in valleys, under cliffs and tree canopies,
+/// where screen-space and ray-traced diffuse GI lose detail.
+///
+/// The field is a heightmap of the top-most surfaces, built from top-down rays, from which
+/// horizon angles are measured. Heights are refreshed progressively, a quarter of the cells per
+/// frame, and cells entering the field as the camera moves are traced right away. Points below
+/// the top-most surface of their cell are treated as being under an overhang.
+///
+/// Requires ray tracing; without it, the field has no effect.
+pub struct SkyVisibilityRenderer {
+    pub enabled: bool,
+
+    /// Size of a field cell in meters. The field spans `SKY_VISIBILITY_RESOLUTION` cells
+    /// on each side, centered on the camera.
+    pub cell_size: f32,
+
+    /// Height above the camera from which the top-down rays start. Geometry above is ignored.
+    pub max_height: f32,
+
+    /// Distance from the camera in meters beyond which the field darkens sky lighting.
+    pub fade_start: f32,
+
+    /// Distance over which the field fades in past `fade_start`.
+    pub fade_length: f32,
+
+    /// 0 disables the darkening, 1 applies the full visibility.
+    pub strength: f32,
+
+    history: Option<SkyVisibilityHistory>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct SkyVisibilityHistory {
+    origin_cell: [i32; 2],
+    cell_size: f32,
+}
+
+impl Default for SkyVisibilityRenderer {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cell_size: 4.0,
+            max_height: 500.0,
+            fade_start: 50.0,
+            fade_length: 50.0,
+            strength: 1.0,
+            history: None,
+        }
+    }
+}
+
+impl SkyVisibilityRenderer {
+    /// Forgets the heightmap, e.g. after large changes to the scene, and retraces all of it next frame.
+    pub fn invalidate(&mut self) {
+        self.history = None;
+    }
+
+    pub fn render(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        eye_position: Vec3,
+        frame_index: u32,
+        tlas: Option<&rg::Handle<RayTracingAcceleration>>,
+        bindless_descriptor_set: vk::DescriptorSet,
+    ) -> SkyVisibilityOutput {
+        let tlas = match tlas {
+            Some(tlas) if self.enabled => tlas,
+            _ => {
+                self.history = None;
+                return Self::disabled_output(rg);
+            }
+        };
+
+        let cell_size = self.cell_size.max(0.01);
+        let half_resolution = (SKY_VISIBILITY_RESOLUTION / 2) as i32;
+        let origin_cell = [
+            (eye_position.x / cell_size).floor() as i32 - half_resolution,
+            (eye_position.z / cell_size).floor() as i32 - half_resolution,
+        ];
+
+        let current = SkyVisibilityHistory {
+            origin_cell,
+            cell_size,
+        };
+
+        // Cells are stored at their coordinates modulo the resolution, so when the camera moves,
+        // only those entering the field need tracing. A new cell size invalidates all of them.
+        let prev = self.history.filter(|prev| prev.cell_size == cell_size);
+        let history_valid = prev.is_some();
+        let prev_origin_cell = prev.map_or(origin_cell, |prev| prev.origin_cell);
+        self.history = Some(current);
+
+        let mut heightmap = rg
+            .get_or_create_temporal(
+                "sky_visibility.heightmap",
+                ImageDesc::new_2d(
+                    vk::Format::R32_SFLOAT,
+                    [SKY_VISIBILITY_RESOLUTION, SKY_VISIBILITY_RESOLUTION],
+                )
+                .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE),
+            )
+            .unwrap();
+
+        SimpleRenderPass::new_rt(
+            rg.add_pass("sky visibility heightmap"),
+            ShaderSource::hlsl("/shaders/sky_visibility/trace_heightmap.rgen.hlsl"),
+            [
+                ShaderSource::hlsl("/shaders/rt/gbuffer.rmiss.hlsl"),
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+            ],
+            [ShaderSource::hlsl("/shaders/rt/gbuffer.rchit.hlsl")],
+        )
+        .write(&mut heightmap)
+        .constants((
+            [origin_cell[0], origin_cell[1]],
+            [prev_origin_cell[0], prev_origin_cell[1]],
+            cell_size,
+            eye_position.y + self.max_height.max(0.0),
+            frame_index,
+            history_valid as u32,
+        ))
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .trace_rays(
+            tlas,
+            [SKY_VISIBILITY_RESOLUTION, SKY_VISIBILITY_RESOLUTION, 1],
+        );
+
+        let mut visibility = rg.create(ImageDesc::new_2d(
+            vk::Format::R8_UNORM,
+            [SKY_VISIBILITY_RESOLUTION, SKY_VISIBILITY_RESOLUTION],
+        ));
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("sky visibility"),
+            "/shaders/sky_visibility/visibility.hlsl",
+        )
+        .read(&heightmap)
+        .write(&mut visibility)
+        .constants(([origin_cell[0], origin_cell[1]], cell_size))
+        .dispatch([SKY_VISIBILITY_RESOLUTION, SKY_VISIBILITY_RESOLUTION, 1]);
+
+        SkyVisibilityOutput {
+            heightmap: heightmap.into(),
+            visibility: visibility.into(),
+            constants: SkyVisibilityConstants {
+                origin_cell_size: [origin_cell[0] as f32, origin_cell[1] as f32, cell_size, 1.0],
+                fade_start: self.fade_start.max(0.0),
+                fade_inv_length: 1.0 / self.fade_length.max(1e-3),
+                strength: self.strength.clamp(0.0, 1.0),
+                _pad: 0,
+            },
+        }
+    }
+
+    fn disabled_output(rg: &mut rg::TemporalRenderGraph) -> SkyVisibilityOutput {
+        let mut heightmap = rg.create(ImageDesc::new_2d(vk::Format::R32_SFLOAT, [1, 1]));
+        rg::imageops::clear_color(rg, &mut heightmap, [0.0; 4]);
+
+        let mut visibility = rg.create(ImageDesc::new_2d(vk::Format::R8_UNORM, [1, 1]));
+        rg::imageops::clear_color(rg, &mut visibility, [1.0; 4]);
+
+        SkyVisibilityOutput {
+            heightmap: heightmap.into(),
+            visibility: visibility.into(),
+            constants: SkyVisibilityConstants {
+                origin_cell_size: [0.0; 4],
+                fade_start: 0.0,
+                fade_inv_length: 0.0,
+                strength: 0.0,
+                _pad: 0,
+            },
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use super::{GbufferDepth, PingPongTemporalResource};
+use crate::frame_graph_config::QualityTier;
 use kajiya_backend::{ash::vk, vulkan::image::*};
 use kajiya_rg::{self as rg, SimpleRenderPass, TemporalRenderGraph};
 use rust_shaders_shared::ssgi::SsgiConstants;
@@ -8,12 +9,17 @@ const USE_RUST_SHADERS: bool = false;
 
 pub struct SsgiRenderer {
     ssgi_tex: PingPongTemporalResource,
+
+    /// Set from `FrameGraphConfig::quality` by `WorldRenderer` every frame.
+    /// Only affects the HLSL shaders.
+    pub quality: QualityTier,
 }
 
 impl Default for SsgiRenderer {
     fn default() -> Self {
         Self {
             ssgi_tex: PingPongTemporalResource::new("ssgi"),
+            quality: QualityTier::default(),
         }
     }
 }
@@ -56,23 +62,28 @@ impl SsgiRenderer {
                 // .raw_descriptor_set(1, bindless_descriptor_set)
                 .dispatch(ssgi_tex.desc().extent);
         } else {
-            SimpleRenderPass::new_compute(rg.add_pass("ssao"), "/shaders/ssgi/ssgi.hlsl")
-                .read(&gbuffer_depth.gbuffer)
-                .read(&*half_depth_tex)
-                .read(&*half_view_normal_tex)
-                .read(prev_radiance)
-                .read(reprojection_map)
-                .write(&mut ssgi_tex)
-                .constants((
-                    gbuffer_desc.extent_inv_extent_2d(),
-                    ssgi_tex.desc().extent_inv_extent_2d(),
-                ))
-                .raw_descriptor_set(1, bindless_descriptor_set)
-                .dispatch(ssgi_tex.desc().extent);
+            SimpleRenderPass::new_compute_with_defines(
+                rg.add_pass("ssao"),
+                "/shaders/ssgi/ssgi.hlsl",
+                &[self.quality.shader_define("SSGI_QUALITY")],
+            )
+            .read(&gbuffer_depth.gbuffer)
+            .read(&*half_depth_tex)
+            .read(&*half_view_normal_tex)
+            .read(prev_radiance)
+            .read(reprojection_map)
+            .write(&mut ssgi_tex)
+            .constants((
+                gbuffer_desc.extent_inv_extent_2d(),
+                ssgi_tex.desc().extent_inv_extent_2d(),
+            ))
+            .raw_descriptor_set(1, bindless_descriptor_set)
+            .dispatch(ssgi_tex.desc().extent);
         }
 
         Self::filter_ssgi(
             rg,
+            "ssao",
             &ssgi_tex,
             gbuffer_depth,
             reprojection_map,
@@ -80,8 +91,30 @@ impl SsgiRenderer {
         )
     }
 
-    fn filter_ssgi(
+    /// Stand-in for `render` when SSGI is disabled: a fully unoccluded AO term
+    /// in the same format as the filtered output.
+    pub fn create_unoccluded_output(
+        &self,
+        rg: &mut rg::TemporalRenderGraph,
+        gbuffer_depth: &GbufferDepth,
+    ) -> rg::ReadOnlyHandle<Image> {
+        let mut output_tex = rg.create(
+            gbuffer_depth
+                .gbuffer
+                .desc()
+                .usage(vk::ImageUsageFlags::empty())
+                .format(FINAL_TEX_FMT),
+        );
+        rg::imageops::clear_color(rg, &mut output_tex, [1.0, 1.0, 1.0, 1.0]);
+
+        output_tex.into()
+    }
+
+    /// Denoises a half-res occlusion term into the full-res `FINAL_TEX_FMT` output.
+    /// Shared with `RtaoRenderer`; pass names start with `pass_prefix`.
+    pub(super) fn filter_ssgi(
         rg: &mut TemporalRenderGraph,
+        pass_prefix: &str,
         input: &rg::Handle<Image>,
         gbuffer_depth: &GbufferDepth,
         reprojection_map: &rg::Handle<Image>,
@@ -101,12 +134,12 @@ impl SsgiRenderer {
 
             if USE_RUST_SHADERS {
                 SimpleRenderPass::new_compute_rust(
-                    rg.add_pass("ssao spatial"),
+                    rg.add_pass(&format!("{} spatial", pass_prefix)),
                     "ssgi::spatial_filter_cs",
                 )
             } else {
                 SimpleRenderPass::new_compute(
-                    rg.add_pass("ssao spatial"),
+                    rg.add_pass(&format!("{} spatial", pass_prefix)),
                     "/shaders/ssgi/spatial_filter.hlsl",
                 )
             }
@@ -118,6 +151,7 @@ impl SsgiRenderer {
 
             Self::upsample_ssgi(
                 rg,
+                pass_prefix,
                 &spatially_filtered_tex,
                 &gbuffer_depth.depth,
                 &gbuffer_depth.gbuffer,
@@ -131,12 +165,12 @@ impl SsgiRenderer {
 
         if USE_RUST_SHADERS {
             SimpleRenderPass::new_compute_rust(
-                rg.add_pass("ssao temporal"),
+                rg.add_pass(&format!("{} temporal", pass_prefix)),
                 "ssgi::temporal_filter_cs",
             )
         } else {
             SimpleRenderPass::new_compute(
-                rg.add_pass("ssao temporal"),
+                rg.add_pass(&format!("{} temporal", pass_prefix)),
                 "/shaders/ssgi/temporal_filter.hlsl",
             )
         }
@@ -158,6 +192,7 @@ impl SsgiRenderer {
 
     fn upsample_ssgi(
         rg: &mut rg::RenderGraph,
+        pass_prefix: &str,
         ssgi: &rg::Handle<Image>,
         depth: &rg::Handle<Image>,
         gbuffer: &rg::Handle<Image>,
@@ -165,15 +200,18 @@ impl SsgiRenderer {
         let mut output_tex = rg.create(gbuffer.desc().format(INTERNAL_TEX_FMT));
 
         if USE_RUST_SHADERS {
-            SimpleRenderPass::new_compute_rust(rg.add_pass("ssao upsample"), "ssgi::upsample_cs")
+            SimpleRenderPass::new_compute_rust(
+                rg.add_pass(&format!("{} upsample", pass_prefix)),
+                "ssgi::upsample_cs",
+            )
         } else {
             SimpleRenderPass::new_compute(
-                rg.add_pass("ssao upsample"),
+                rg.add_pass(&format!("{} upsample", pass_prefix)),
                 "/shaders/ssgi/upsample.hlsl",
             )
         }
         .read(ssgi)
-        .read_aspect(depth, vk::ImageAspectFlags::DEPTH)
+        .read_depth(depth)
         .read(gbuffer)
         .write(&mut output_tex)
         .dispatch(output_tex.desc().extent);
@@ -8,6 +8,73 @@ pub struct TaaRenderer {
     temporal_velocity_tex: PingPongTemporalResource,
     temporal_smooth_var_tex: PingPongTemporalResource,
     pub current_supersample_offset: Vec2,
+
+    /// Screen regions which favor the current frame over history; kept until changed.
+    /// Not used when DLSS replaces TAA.
+    pub responsive_regions: Vec<TaaResponsiveRegion>,
+
+    /// Radius in input pixels of the window searched for the closest depth, whose velocity
+    /// is then used to reproject history. Larger radii keep thin fast-moving geometry from
+    /// ghosting, at the cost of thicker edges in motion. Zero disables dilation.
+    /// Clamped to `MAX_VELOCITY_DILATION_RADIUS`.
+    pub velocity_dilation_radius: u32,
+
+    /// Dilate everywhere, rather than only where velocity varies around the pixel. The
+    /// variation check only looks at the immediate neighbors, so it may miss thin geometry
+    /// further away when `velocity_dilation_radius` is above one.
+    pub always_dilate_velocity: bool,
+}
+
+pub const MAX_VELOCITY_DILATION_RADIUS: u32 = 4;
+
+/// A screen region, such as the one covered by a first-person weapon or a cockpit, whose
+/// camera-locked content would smear under full temporal accumulation.
+///
+/// Only pixels moving on screen slower than `max_velocity_pixels` are affected, so the world
+/// seen around and through the geometry in the region keeps the full quality of TAA.
+#[derive(Clone, Copy, Debug)]
+pub struct TaaResponsiveRegion {
+    /// Top-left corner, in UV coordinates
+    pub uv_min: Vec2,
+
+    /// Bottom-right corner, in UV coordinates
+    pub uv_max: Vec2,
+
+    /// Multiplier of the weight of history. Zero disables accumulation, and one leaves TAA as is.
+    pub history_weight: f32,
+
+    /// Screen-space speed, in output pixels per frame, up to which pixels count as camera-locked.
+    pub max_velocity_pixels: f32,
+}
+
+impl TaaResponsiveRegion {
+    pub fn new(uv_min: Vec2, uv_max: Vec2) -> Self {
+        Self {
+            uv_min,
+            uv_max,
+            history_weight: 0.25,
+            max_velocity_pixels: 0.5,
+        }
+    }
+
+    pub fn history_weight(mut self, v: f32) -> Self {
+        self.history_weight = v;
+        self
+    }
+
+    pub fn max_velocity_pixels(mut self, v: f32) -> Self {
+        self.max_velocity_pixels = v;
+        self
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuTaaResponsiveRegion {
+    uv_min_max: [f32; 4],
+    history_weight: f32,
+    max_velocity_pixels: f32,
+    _pad: [f32; 2],
 }
 
 impl Default for TaaRenderer {
@@ -23,10 +90,37 @@ impl TaaRenderer {
             temporal_velocity_tex: PingPongTemporalResource::new("taa.velocity"),
             temporal_smooth_var_tex: PingPongTemporalResource::new("taa.smooth_var"),
             current_supersample_offset: Vec2::ZERO,
+            responsive_regions: Vec::new(),
+            velocity_dilation_radius: 1,
+            always_dilate_velocity: false,
         }
     }
 }
 
+/// Fraction of the radiance of each pixel of `lit_tex` which comes from emissive surfaces
+/// in `gbuffer`, used by `TaaRenderer::render` to preserve small bright emitters.
+pub fn reactive_mask(
+    rg: &mut rg::RenderGraph,
+    gbuffer: &rg::Handle<Image>,
+    lit_tex: &rg::Handle<Image>,
+) -> rg::Handle<Image> {
+    let mut mask = rg.create(ImageDesc::new_2d(
+        vk::Format::R8_UNORM,
+        lit_tex.desc().extent_2d(),
+    ));
+
+    SimpleRenderPass::new_compute(
+        rg.add_pass("taa reactive mask"),
+        "/shaders/taa/reactive_mask.hlsl",
+    )
+    .read(gbuffer)
+    .read(lit_tex)
+    .write(&mut mask)
+    .dispatch(mask.desc().extent);
+
+    mask
+}
+
 pub struct TaaOutput {
     pub temporal_out: rg::ReadOnlyHandle<Image>,
     pub this_frame_out: rg::Handle<Image>,
@@ -38,12 +132,14 @@ impl TaaRenderer {
             .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE)
     }
 
+    /// `reactive_mask` is an input-resolution mask of emissive content, as from `reactive_mask`.
     pub fn render(
         &mut self,
         rg: &mut rg::TemporalRenderGraph,
         input_tex: &rg::Handle<Image>,
         reprojection_map: &rg::Handle<Image>,
         depth_tex: &rg::Handle<Image>,
+        reactive_mask: &rg::Handle<Image>,
         output_extent: [u32; 2],
     ) -> TaaOutput {
         //let input_extent = input_tex.desc().extent_2d();
@@ -69,12 +165,15 @@ impl TaaRenderer {
         )
         .read(&history_tex)
         .read(reprojection_map)
-        .read_aspect(depth_tex, vk::ImageAspectFlags::DEPTH)
+        .read_depth(depth_tex)
         .write(&mut reprojected_history_img)
         .write(&mut closest_velocity_img)
         .constants((
             input_tex.desc().extent_inv_extent_2d(),
             reprojected_history_img.desc().extent_inv_extent_2d(),
+            self.velocity_dilation_radius
+                .min(MAX_VELOCITY_DILATION_RADIUS),
+            self.always_dilate_velocity as u32,
         ))
         .dispatch(reprojected_history_img.desc().extent);
 
@@ -100,7 +199,7 @@ impl TaaRenderer {
             "/shaders/taa/filter_input.hlsl",
         )
         .read(input_tex)
-        .read_aspect(depth_tex, vk::ImageAspectFlags::DEPTH)
+        .read_depth(depth_tex)
         .write(&mut filtered_input_img)
         .write(&mut filtered_input_deviation_img)
         .dispatch(filtered_input_img.desc().extent);
@@ -136,7 +235,7 @@ impl TaaRenderer {
             .read(&reprojected_history_img)
             .read(&filtered_history_img)
             .read(reprojection_map)
-            .read_aspect(depth_tex, vk::ImageAspectFlags::DEPTH)
+            .read_depth(depth_tex)
             .read(&smooth_var_history_tex)
             .read(&velocity_history_tex)
             .write(&mut input_prob_img)
@@ -164,6 +263,23 @@ impl TaaRenderer {
             prob_filtered2_img
         };
 
+        let responsive_regions: Vec<GpuTaaResponsiveRegion> = self
+            .responsive_regions
+            .iter()
+            .map(|region| GpuTaaResponsiveRegion {
+                uv_min_max: [
+                    region.uv_min.x,
+                    region.uv_min.y,
+                    region.uv_max.x,
+                    region.uv_max.y,
+                ],
+                history_weight: region.history_weight.clamp(0.0, 1.0),
+                max_velocity_pixels: region.max_velocity_pixels,
+                _pad: [0.0; 2],
+            })
+            .collect();
+        let responsive_region_count = responsive_regions.len() as u32;
+
         let mut this_frame_output_img = rg.create(Self::temporal_tex_desc(output_extent));
         SimpleRenderPass::new_compute(rg.add_pass("taa"), "/shaders/taa/taa.hlsl")
             .read(input_tex)
@@ -171,16 +287,19 @@ impl TaaRenderer {
             .read(reprojection_map)
             .read(&closest_velocity_img)
             .read(&velocity_history_tex)
-            .read_aspect(depth_tex, vk::ImageAspectFlags::DEPTH)
+            .read_depth(depth_tex)
             .read(&smooth_var_history_tex)
             .read(&input_prob_img)
+            .read(reactive_mask)
             .write(&mut temporal_output_tex)
             .write(&mut this_frame_output_img)
             .write(&mut smooth_var_output_tex)
             .write(&mut temporal_velocity_output_tex)
+            .dynamic_storage_buffer_vec(responsive_regions)
             .constants((
                 input_tex.desc().extent_inv_extent_2d(),
                 temporal_output_tex.desc().extent_inv_extent_2d(),
+                responsive_region_count,
             ))
             .dispatch(temporal_output_tex.desc().extent);
 
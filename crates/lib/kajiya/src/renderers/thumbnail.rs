@@ -0,0 +1,319 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use glam::{Vec3, Vec4};
+use kajiya_backend::{
+    ash::vk,
+    vk_sync::AccessType,
+    vulkan::{image::*, ray_tracing::RayTracingAcceleration, shader::ShaderSource},
+    Device,
+};
+use kajiya_rg::{self as rg, GetOrCreateTemporal};
+use rg::{Buffer, BufferDesc, SimpleRenderPass};
+
+use crate::world_renderer::InstanceHandle;
+
+/// Number of frames accumulated for each thumbnail. Every frame traces 16 samples per pixel.
+const CAPTURE_FRAME_COUNT: u32 = 16;
+
+const THUMBNAIL_VERTICAL_FOV_DEGREES: f32 = 30.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ThumbnailHandle(pub u32);
+
+pub struct ThumbnailImage {
+    pub extent: [u32; 2],
+
+    /// sRGB-encoded RGBA8, row by row, starting at the top.
+    pub pixels: Vec<[u8; 4]>,
+}
+
+/// Camera for a thumbnail; mirrored in `thumbnail/path_trace.rgen.hlsl`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ThumbnailConstants {
+    eye_position: Vec4,
+    // Scaled by the tangent of the half field of view, and the aspect ratio
+    eye_right: Vec4,
+    eye_up: Vec4,
+    eye_forward: Vec4,
+    capture_id: u32,
+    frame_index: u32,
+    _pad: [u32; 2],
+}
+
+struct ThumbnailCapture {
+    handle: ThumbnailHandle,
+    extent: [u32; 2],
+    instances: Vec<InstanceHandle>,
+    constants: ThumbnailConstants,
+    frames_rendered: u32,
+}
+
+struct ResultsBuffer {
+    buffer: Arc<Buffer>,
+
+    /// Reached once the resolve of the active capture has finished on the GPU.
+    /// `None` until the frame which resolved it has been submitted.
+    gpu_done_timeline_value: Option<u64>,
+}
+
+/// Renders thumbnails of instances off-screen, for editor asset previews, save-game
+/// previews and the like.
+///
+/// The instances are framed from a fixed three-quarter view, and path traced in isolation
+/// from the rest of the scene, but lit by its sky and sun. Thumbnails are resolved at a fixed
+/// exposure, so that they're consistent regardless of where the main camera is looking.
+///
+/// Captures are processed one at a time over a number of frames, and read back to the CPU;
+/// poll `WorldRenderer::take_thumbnail` for the result.
+pub struct ThumbnailRenderer {
+    /// Exposure thumbnails are resolved at, in stops applied to scene radiance.
+    pub ev: f32,
+
+    queue: VecDeque<ThumbnailCapture>,
+    active: Option<ThumbnailCapture>,
+    finished: HashMap<ThumbnailHandle, ThumbnailImage>,
+    results_buffer: Option<ResultsBuffer>,
+    next_handle: u32,
+}
+
+impl Default for ThumbnailRenderer {
+    fn default() -> Self {
+        Self {
+            ev: -2.0,
+            queue: Default::default(),
+            active: None,
+            finished: Default::default(),
+            results_buffer: None,
+            next_handle: 1,
+        }
+    }
+}
+
+impl ThumbnailRenderer {
+    /// Queues a capture of `instances`, framing their world-space bounds (`bounds_min`..`bounds_max`).
+    pub(crate) fn capture(
+        &mut self,
+        extent: [u32; 2],
+        instances: &[InstanceHandle],
+        bounds_min: Vec3,
+        bounds_max: Vec3,
+    ) -> ThumbnailHandle {
+        let extent = [extent[0].max(1), extent[1].max(1)];
+        let handle = ThumbnailHandle(self.next_handle);
+        self.next_handle = self.next_handle.wrapping_add(1).max(1);
+
+        self.queue.push_back(ThumbnailCapture {
+            handle,
+            extent,
+            instances: instances.to_vec(),
+            constants: frame_bounds(bounds_min, bounds_max, extent, handle.0),
+            frames_rendered: 0,
+        });
+
+        handle
+    }
+
+    pub(crate) fn take(&mut self, handle: ThumbnailHandle) -> Option<ThumbnailImage> {
+        self.finished.remove(&handle)
+    }
+
+    /// Reads back finished captures, and starts the next one if idle. Must be called once per frame,
+    /// before the TLAS is built.
+    ///
+    /// Returns the instances being captured this frame, which must be included in the TLAS
+    /// with the thumbnail mask bit set.
+    pub(crate) fn begin_frame(&mut self, device: &Device) -> &[InstanceHandle] {
+        if matches!(&self.active, Some(capture) if capture.frames_rendered == CAPTURE_FRAME_COUNT) {
+            self.read_back_results(device);
+        }
+
+        if self.active.is_none() {
+            self.active = self.queue.pop_front();
+        }
+
+        match &self.active {
+            Some(capture) if capture.frames_rendered < CAPTURE_FRAME_COUNT => &capture.instances,
+            _ => &[],
+        }
+    }
+
+    fn read_back_results(&mut self, device: &Device) {
+        let capture = self.active.as_ref().unwrap();
+        let results_buffer = self.results_buffer.as_ref().unwrap();
+
+        // The resolve may not have finished on the GPU yet.
+        let gpu_done = match results_buffer.gpu_done_timeline_value {
+            Some(value) => device
+                .queue_completed_value(&device.universal_queue)
+                .map_or(false, |completed| completed >= value),
+            None => false,
+        };
+
+        if !gpu_done {
+            return;
+        }
+
+        let src = if let Some(src) = results_buffer.buffer.allocation.mapped_slice() {
+            bytemuck::checked::cast_slice::<u8, u32>(src)
+        } else {
+            return;
+        };
+
+        let [width, height] = capture.extent;
+        let pixels = src
+            .iter()
+            .take((width * height) as usize)
+            .map(|px| px.to_le_bytes())
+            .collect();
+
+        let capture = self.active.take().unwrap();
+        self.finished.insert(
+            capture.handle,
+            ThumbnailImage {
+                extent: capture.extent,
+                pixels,
+            },
+        );
+    }
+
+    pub(crate) fn render(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        tlas: &rg::Handle<RayTracingAcceleration>,
+        bindless_descriptor_set: vk::DescriptorSet,
+    ) {
+        let capture = match &mut self.active {
+            Some(capture) if capture.frames_rendered < CAPTURE_FRAME_COUNT => capture,
+            _ => return,
+        };
+
+        let mut accum_img = rg
+            .get_or_create_temporal(
+                "thumbnail.accum",
+                ImageDesc::new_2d(vk::Format::R32G32B32A32_SFLOAT, capture.extent).usage(
+                    vk::ImageUsageFlags::SAMPLED
+                        | vk::ImageUsageFlags::STORAGE
+                        | vk::ImageUsageFlags::TRANSFER_DST,
+                ),
+            )
+            .unwrap();
+
+        if capture.frames_rendered == 0 {
+            rg::imageops::clear_color(rg, &mut accum_img, [0.0, 0.0, 0.0, 0.0]);
+        }
+
+        SimpleRenderPass::new_rt(
+            rg.add_pass("thumbnail pt"),
+            ShaderSource::hlsl("/shaders/thumbnail/path_trace.rgen.hlsl"),
+            [
+                ShaderSource::hlsl("/shaders/rt/gbuffer.rmiss.hlsl"),
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+            ],
+            [ShaderSource::hlsl("/shaders/rt/gbuffer.rchit.hlsl")],
+        )
+        .write(&mut accum_img)
+        .constants(ThumbnailConstants {
+            frame_index: capture.frames_rendered,
+            ..capture.constants
+        })
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .trace_rays(tlas, accum_img.desc().extent);
+
+        capture.frames_rendered += 1;
+        if capture.frames_rendered < CAPTURE_FRAME_COUNT {
+            return;
+        }
+
+        let [width, height] = capture.extent;
+        let results_size = (width * height) as usize * 4;
+
+        // Not read by the CPU until the resolve is done with it, and only reused by the next
+        // capture, which starts once this one has been read back.
+        if self
+            .results_buffer
+            .as_ref()
+            .map_or(true, |buf| buf.buffer.desc.size < results_size)
+        {
+            self.results_buffer = Some(ResultsBuffer {
+                buffer: Arc::new(
+                    rg.device()
+                        .create_buffer(
+                            BufferDesc::new_gpu_to_cpu(
+                                results_size,
+                                vk::BufferUsageFlags::STORAGE_BUFFER,
+                            ),
+                            "thumbnail results",
+                            None,
+                        )
+                        .expect("thumbnail results buffer"),
+                ),
+                gpu_done_timeline_value: None,
+            });
+        }
+
+        let results_buffer = self.results_buffer.as_mut().unwrap();
+        results_buffer.gpu_done_timeline_value = None;
+        let mut results = rg.import(results_buffer.buffer.clone(), AccessType::Nothing);
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("thumbnail resolve"),
+            "/shaders/thumbnail/resolve.hlsl",
+        )
+        .read(&accum_img)
+        .write(&mut results)
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .constants((width, height, self.ev.exp2()))
+        .dispatch([width, height, 1]);
+    }
+
+    /// Called once the frame has been submitted.
+    pub(crate) fn retire(&mut self, timeline_value: u64) {
+        let resolved_this_frame = matches!(
+            &self.active,
+            Some(capture) if capture.frames_rendered == CAPTURE_FRAME_COUNT
+        );
+
+        if let Some(results_buffer) = &mut self.results_buffer {
+            if resolved_this_frame && results_buffer.gpu_done_timeline_value.is_none() {
+                results_buffer.gpu_done_timeline_value = Some(timeline_value);
+            }
+        }
+    }
+}
+
+/// Places the camera such that the bounding sphere of the box fits in the view.
+fn frame_bounds(
+    bounds_min: Vec3,
+    bounds_max: Vec3,
+    extent: [u32; 2],
+    capture_id: u32,
+) -> ThumbnailConstants {
+    let center = (bounds_min + bounds_max) * 0.5;
+    let radius = ((bounds_max - bounds_min).length() * 0.5).max(1e-3);
+
+    let aspect_ratio = extent[0] as f32 / extent[1] as f32;
+    let tan_half_fov = (THUMBNAIL_VERTICAL_FOV_DEGREES.to_radians() * 0.5).tan();
+
+    // Limited by the narrower of the two fields of view.
+    let half_fov = (tan_half_fov * aspect_ratio.min(1.0)).atan();
+    let distance = radius / half_fov.sin();
+
+    // Three-quarter view, from the front right, and a bit above.
+    let forward = -Vec3::new(0.6, 0.5, 1.0).normalize();
+    let right = forward.cross(Vec3::Y).normalize();
+    let up = right.cross(forward);
+
+    ThumbnailConstants {
+        eye_position: (center - forward * distance).extend(1.0),
+        eye_right: (right * tan_half_fov * aspect_ratio).extend(0.0),
+        eye_up: (up * tan_half_fov).extend(0.0),
+        eye_forward: forward.extend(0.0),
+        capture_id,
+        frame_index: 0,
+        _pad: [0; 2],
+    }
+}
@@ -0,0 +1,280 @@
+use std::mem::size_of;
+
+use glam::Vec3;
+use kajiya_backend::{
+    ash::vk,
+    vulkan::{buffer::BufferDesc, image::*},
+};
+use kajiya_rg::{self as rg, SimpleRenderPass};
+
+use crate::world_renderer::{PointLight, PointLightUnit};
+
+pub const MAX_TRANSLUCENT_EMISSIVES: usize = 1024;
+
+// Must match `translucent_emissives/clusters.hlsl`
+const CLUSTER_TILE_SIZE: u32 = 32;
+const CLUSTER_SLICE_COUNT: u32 = 16;
+const CLUSTER_STRIDE: usize = 32;
+
+// Radiant intensity of a glow of unit radiance and radius, over `PI`: the density profile
+// in `translucent_emissives/shade.hlsl` averages to 0.8 of the peak over the projected disk.
+const GLOW_PROJECTED_AREA_FRACTION: f32 = 0.8;
+
+/// A small glowing translucent volume, such as a flame, a spark, or an energy bolt.
+/// It's drawn as a soft sphere whose emission fades out towards its radius, and is
+/// occluded by opaque geometry, but not by other emissives.
+#[derive(Clone, Copy, Debug)]
+pub struct TranslucentEmissive {
+    pub position: Vec3,
+    pub radius: f32,
+
+    /// Linear RGB; multiplied by `intensity`
+    pub color: Vec3,
+
+    /// Radiance seen through the center of the glow
+    pub intensity: f32,
+}
+
+impl TranslucentEmissive {
+    pub fn new(position: Vec3, radius: f32) -> Self {
+        Self {
+            position,
+            radius,
+            color: Vec3::ONE,
+            intensity: 1.0,
+        }
+    }
+
+    pub fn color(mut self, v: Vec3) -> Self {
+        self.color = v;
+        self
+    }
+
+    pub fn intensity(mut self, v: f32) -> Self {
+        self.intensity = v;
+        self
+    }
+
+    fn radiant_intensity(&self) -> Vec3 {
+        self.color
+            * self.intensity
+            * (std::f32::consts::PI * self.radius * self.radius * GLOW_PROJECTED_AREA_FRACTION)
+    }
+}
+
+// Mirrored in `translucent_emissives/clusters.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuTranslucentEmissive {
+    position_radius: [f32; 4],
+    radiance: [f32; 4],
+}
+
+/// Forward-shaded glows for small emissive translucent objects, which would be costly to push
+/// through a full transparency pipeline. The emissives are binned into clusters (screen tiles
+/// by exponential depth slices), and every pixel integrates the glows along its view ray up to
+/// the opaque geometry, into a separate emissive buffer. The buffer is added to the frame, and
+/// also feeds bloom directly, so that glows bloom even below `BloomConfig::threshold`.
+///
+/// The emissives also light the scene, merged into a handful of point lights which are only
+/// updated every `gi_update_interval` frames. Like point lights, those need ray tracing.
+pub struct TranslucentEmissiveRenderer {
+    /// Drawn every frame until changed. Only the first `MAX_TRANSLUCENT_EMISSIVES` are used,
+    /// and those beyond 31 in a single cluster are dropped there.
+    pub emissives: Vec<TranslucentEmissive>,
+
+    /// Multiplier of the light the emissives cast into the scene; 0 disables it.
+    pub gi_intensity: f32,
+
+    /// Frames between updates of the lights the emissives cast; 1 updates them every frame.
+    pub gi_update_interval: u32,
+
+    /// Upper bound on the number of point lights the emissives are merged into.
+    /// Each of them costs a shadow ray per pixel.
+    pub max_gi_lights: usize,
+
+    /// Distance within which emissives are merged into the same light.
+    pub gi_merge_distance: f32,
+
+    gi_lights: Vec<PointLight>,
+    frames_until_gi_update: u32,
+}
+
+impl Default for TranslucentEmissiveRenderer {
+    fn default() -> Self {
+        Self {
+            emissives: Vec::new(),
+            gi_intensity: 1.0,
+            gi_update_interval: 4,
+            max_gi_lights: 4,
+            gi_merge_distance: 1.0,
+            gi_lights: Vec::new(),
+            frames_until_gi_update: 0,
+        }
+    }
+}
+
+impl TranslucentEmissiveRenderer {
+    pub fn is_empty(&self) -> bool {
+        self.emissives.is_empty()
+    }
+
+    /// Point lights standing in for the emissives, as of the last update.
+    pub fn gi_lights(&self) -> &[PointLight] {
+        &self.gi_lights
+    }
+
+    /// Re-merges the emissives into `gi_lights` once every `gi_update_interval` frames.
+    pub(crate) fn update_gi_lights(&mut self) {
+        if self.gi_intensity <= 0.0 || self.emissives.is_empty() {
+            self.gi_lights.clear();
+            self.frames_until_gi_update = 0;
+            return;
+        }
+
+        if self.frames_until_gi_update > 0 {
+            self.frames_until_gi_update -= 1;
+            return;
+        }
+
+        self.frames_until_gi_update = self.gi_update_interval.max(1) - 1;
+        self.gi_lights = self.merge_into_lights();
+    }
+
+    // Greedily, brightest first, so that the dim ones get merged into the bright ones.
+    fn merge_into_lights(&self) -> Vec<PointLight> {
+        struct LightCluster {
+            weighted_position: Vec3,
+            weight: f32,
+            radiant_intensity: Vec3,
+            radius: f32,
+        }
+
+        impl LightCluster {
+            fn position(&self) -> Vec3 {
+                self.weighted_position / self.weight
+            }
+        }
+
+        let mut emissives: Vec<(&TranslucentEmissive, Vec3)> = self
+            .emissives
+            .iter()
+            .take(MAX_TRANSLUCENT_EMISSIVES)
+            .map(|emissive| (emissive, emissive.radiant_intensity() * self.gi_intensity))
+            .filter(|(_, intensity)| intensity.max_element() > 0.0)
+            .collect();
+
+        emissives.sort_by(|(_, a), (_, b)| b.max_element().partial_cmp(&a.max_element()).unwrap());
+
+        let max_lights = self.max_gi_lights.max(1);
+        let mut clusters: Vec<LightCluster> = Vec::new();
+
+        for (emissive, radiant_intensity) in emissives {
+            let weight = radiant_intensity.max_element();
+
+            let nearest = clusters
+                .iter()
+                .enumerate()
+                .map(|(idx, cluster)| (cluster.position().distance(emissive.position), idx))
+                .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap());
+
+            match nearest {
+                Some((distance, idx))
+                    if distance <= self.gi_merge_distance || clusters.len() >= max_lights =>
+                {
+                    let cluster = &mut clusters[idx];
+                    cluster.weighted_position += emissive.position * weight;
+                    cluster.weight += weight;
+                    cluster.radiant_intensity += radiant_intensity;
+                    cluster.radius = cluster.radius.max(distance + emissive.radius);
+                }
+                _ => clusters.push(LightCluster {
+                    weighted_position: emissive.position * weight,
+                    weight,
+                    radiant_intensity,
+                    radius: emissive.radius,
+                }),
+            }
+        }
+
+        clusters
+            .into_iter()
+            .map(|cluster| {
+                let intensity = cluster.radiant_intensity.max_element();
+
+                PointLight {
+                    position: cluster.position(),
+                    color: cluster.radiant_intensity / intensity,
+                    intensity,
+                    unit: PointLightUnit::Renderer,
+                    radius: cluster.radius,
+                    spot: None,
+                    // The glows themselves aren't in the fog either.
+                    volumetric_intensity: 0.0,
+                }
+            })
+            .collect()
+    }
+
+    /// Adds the glows to `color`, which is in pre-exposed linear units, and returns them
+    /// in a separate buffer of the same size. `depth` is the un-jittered reverse-Z depth
+    /// of the opaque geometry.
+    pub fn render(
+        &self,
+        rg: &mut rg::RenderGraph,
+        color: &mut rg::Handle<Image>,
+        depth: &rg::Handle<Image>,
+    ) -> rg::Handle<Image> {
+        let output_extent = color.desc().extent_2d();
+        let mut emissive = rg.create(ImageDesc::new_2d(
+            vk::Format::R16G16B16A16_SFLOAT,
+            output_extent,
+        ));
+
+        let emissives: Vec<GpuTranslucentEmissive> = self
+            .emissives
+            .iter()
+            .take(MAX_TRANSLUCENT_EMISSIVES)
+            .filter(|emissive| emissive.radius > 0.0)
+            .map(|emissive| GpuTranslucentEmissive {
+                position_radius: emissive.position.extend(emissive.radius).into(),
+                radiance: (emissive.color * emissive.intensity).extend(0.0).into(),
+            })
+            .collect();
+        let emissive_count = emissives.len() as u32;
+
+        let tile_count = [
+            (output_extent[0] + CLUSTER_TILE_SIZE - 1) / CLUSTER_TILE_SIZE,
+            (output_extent[1] + CLUSTER_TILE_SIZE - 1) / CLUSTER_TILE_SIZE,
+        ];
+        let cluster_count = (tile_count[0] * tile_count[1] * CLUSTER_SLICE_COUNT) as usize;
+
+        let mut clusters = rg.create(BufferDesc::new_gpu_only(
+            cluster_count * CLUSTER_STRIDE * size_of::<u32>(),
+            vk::BufferUsageFlags::empty(),
+        ));
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("translucent emissives bin"),
+            "/shaders/translucent_emissives/bin.hlsl",
+        )
+        .write(&mut clusters)
+        .dynamic_storage_buffer_vec(emissives.clone())
+        .constants((output_extent, tile_count, emissive_count))
+        .dispatch([tile_count[0], tile_count[1], CLUSTER_SLICE_COUNT]);
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("translucent emissives shade"),
+            "/shaders/translucent_emissives/shade.hlsl",
+        )
+        .read(&clusters)
+        .read(depth)
+        .write(color)
+        .write(&mut emissive)
+        .dynamic_storage_buffer_vec(emissives)
+        .constants((output_extent, tile_count))
+        .dispatch(emissive.desc().extent);
+
+        emissive
+    }
+}
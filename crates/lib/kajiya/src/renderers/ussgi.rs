@@ -1,8 +1,5 @@
 use super::{GbufferDepth, PingPongTemporalResource};
-use kajiya_backend::{
-    ash::vk::{self, ImageAspectFlags},
-    vulkan::image::*,
-};
+use kajiya_backend::{ash::vk, vulkan::image::*};
 use kajiya_rg::{self as rg, SimpleRenderPass};
 
 pub struct UssgiRenderer {
@@ -39,7 +36,7 @@ impl UssgiRenderer {
 
         SimpleRenderPass::new_compute(rg.add_pass("ussgi"), "/shaders/ssgi/ussgi.hlsl")
             .read(&gbuffer_depth.gbuffer)
-            .read_aspect(&gbuffer_depth.depth, ImageAspectFlags::DEPTH)
+            .read_depth(&gbuffer_depth.depth)
             .read(&*half_view_normal_tex)
             .read(prev_radiance)
             .read(reprojection_map)
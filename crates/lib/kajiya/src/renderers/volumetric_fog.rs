@@ -0,0 +1,211 @@
+use glam::Vec3;
+use kajiya_backend::{
+    ash::vk,
+    vulkan::{image::*, ray_tracing::RayTracingAcceleration, shader::ShaderSource},
+};
+use kajiya_rg::{self as rg, SimpleRenderPass};
+
+use super::{ircache::IrcacheRenderState, PingPongTemporalResource};
+use crate::frame_graph_config::QualityTier;
+
+fn tile_size(quality: QualityTier) -> u32 {
+    match quality {
+        QualityTier::Low => 16,
+        QualityTier::Medium | QualityTier::High => 8,
+    }
+}
+
+fn slice_count(quality: QualityTier) -> u32 {
+    match quality {
+        QualityTier::Low => 32,
+        QualityTier::Medium => 64,
+        QualityTier::High => 128,
+    }
+}
+
+fn shadow_ray_count(quality: QualityTier) -> u32 {
+    match quality {
+        QualityTier::Low | QualityTier::Medium => 1,
+        QualityTier::High => 2,
+    }
+}
+
+/// Height fog lit by the sun, point and spot lights, and the irradiance cache, evaluated
+/// in a froxel volume (screen tiles by exponentially distributed depth slices), and composited
+/// before TAA. Ray-traced sun shadows in the volume produce light shafts through windows
+/// and foliage, and shadowed point lights visible halos and cones.
+///
+/// Requires ray tracing for sun shadows; without it, no fog is rendered.
+pub struct VolumetricFogRenderer {
+    pub enabled: bool,
+
+    /// Extinction coefficient at and below `height_fog_base`, per meter.
+    pub density: f32,
+
+    /// Henyey-Greenstein asymmetry: 0 scatters uniformly, positive values towards the light.
+    pub anisotropy: f32,
+
+    /// Fraction of the extinguished light which is scattered rather than absorbed.
+    pub albedo: Vec3,
+
+    /// World-space height in meters below which the fog has full density.
+    pub height_fog_base: f32,
+
+    /// Rate at which density falls off above `height_fog_base`, per meter.
+    pub height_fog_falloff: f32,
+
+    /// Distance in meters covered by the froxel volume. Sky pixels get fog up to here.
+    pub max_distance: f32,
+
+    /// Scales light scattered from the sun, i.e. the strength of light shafts
+    /// (god rays) through gaps in its shadow, without affecting ambient fog.
+    pub sun_scattering: f32,
+
+    /// Scales light scattered from point and spot lights, on top of their own
+    /// `PointLight::volumetric_intensity`. Every light with a non-zero volumetric intensity
+    /// costs a shadow ray per froxel; zero skips them all.
+    pub point_light_scattering: f32,
+
+    /// Resolution of the froxel volume, and the number of sun shadow rays per froxel:
+    /// 16x16 pixel tiles and 32 slices with `Low`, 8x8 pixel tiles and 64 slices with `Medium`,
+    /// and 128 slices with two shadow rays per froxel with `High`. Light shafts through small
+    /// openings need the finer grids to stay sharp.
+    ///
+    /// Set from `FrameGraphConfig::quality` by `WorldRenderer` every frame.
+    pub quality: QualityTier,
+
+    froxel_tex: PingPongTemporalResource,
+    history_extent: Option<[u32; 3]>,
+}
+
+impl Default for VolumetricFogRenderer {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            density: 0.02,
+            anisotropy: 0.6,
+            albedo: Vec3::ONE,
+            height_fog_base: 0.0,
+            height_fog_falloff: 0.1,
+            max_distance: 200.0,
+            sun_scattering: 1.0,
+            point_light_scattering: 1.0,
+            quality: QualityTier::Medium,
+            froxel_tex: PingPongTemporalResource::new("volumetric_fog.froxel"),
+            history_extent: None,
+        }
+    }
+}
+
+impl VolumetricFogRenderer {
+    #[allow(clippy::too_many_arguments)]
+    pub fn render(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        depth: &rg::Handle<Image>,
+        ircache: &mut IrcacheRenderState,
+        convolved_sky_cube: &rg::Handle<Image>,
+        bindless_descriptor_set: vk::DescriptorSet,
+        tlas: Option<&rg::Handle<RayTracingAcceleration>>,
+        output: &mut rg::Handle<Image>,
+        reactive_mask: Option<&mut rg::Handle<Image>>,
+    ) {
+        let tlas = match tlas {
+            Some(tlas) if self.enabled => tlas,
+            _ => {
+                self.history_extent = None;
+                return;
+            }
+        };
+
+        let tile_size = tile_size(self.quality);
+        let screen_extent = output.desc().extent_2d();
+        let volume_extent = [
+            (screen_extent[0] + tile_size - 1) / tile_size,
+            (screen_extent[1] + tile_size - 1) / tile_size,
+            slice_count(self.quality),
+        ];
+
+        let (mut froxel_tex, history_tex) = self.froxel_tex.get_output_and_history(
+            rg,
+            ImageDesc::new_3d(vk::Format::R16G16B16A16_SFLOAT, volume_extent)
+                .usage(vk::ImageUsageFlags::empty()),
+        );
+
+        let history_valid = self.history_extent == Some(volume_extent);
+        self.history_extent = Some(volume_extent);
+
+        let max_distance = self.max_distance.max(1.0);
+
+        SimpleRenderPass::new_rt(
+            rg.add_pass("fog inject"),
+            ShaderSource::hlsl("/shaders/volumetric_fog/fog_inject.rgen.hlsl"),
+            [
+                // Duplicated because `rt.hlsl` hardcodes miss index to 1
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+                ShaderSource::hlsl("/shaders/rt/shadow.rmiss.hlsl"),
+            ],
+            std::iter::empty(),
+        )
+        .read(&history_tex)
+        .write(&mut froxel_tex)
+        .bind_mut(ircache)
+        .read(convolved_sky_cube)
+        .constants((
+            output.desc().extent_inv_extent_2d(),
+            self.density.max(0.0),
+            self.anisotropy.clamp(-0.99, 0.99),
+            self.height_fog_base,
+            self.height_fog_falloff.max(0.0),
+            self.albedo
+                .clamp(Vec3::ZERO, Vec3::ONE)
+                .extend(max_distance),
+            self.sun_scattering.max(0.0),
+            shadow_ray_count(self.quality),
+            tile_size,
+            history_valid as u32,
+            self.point_light_scattering.max(0.0),
+        ))
+        .raw_descriptor_set(1, bindless_descriptor_set)
+        .trace_rays(tlas, volume_extent);
+
+        let mut integrated_tex = rg.create(ImageDesc::new_3d(
+            vk::Format::R16G16B16A16_SFLOAT,
+            volume_extent,
+        ));
+
+        SimpleRenderPass::new_compute(
+            rg.add_pass("fog integrate"),
+            "/shaders/volumetric_fog/fog_integrate.hlsl",
+        )
+        .read(&froxel_tex)
+        .write(&mut integrated_tex)
+        .constants(max_distance)
+        .dispatch([volume_extent[0], volume_extent[1], 1]);
+
+        let mut pass = SimpleRenderPass::new_compute_with_defines(
+            rg.add_pass("fog apply"),
+            "/shaders/volumetric_fog/fog_apply.hlsl",
+            &[(
+                "FOG_APPLY_REACTIVE_MASK".to_owned(),
+                (reactive_mask.is_some() as u32).to_string(),
+            )],
+        )
+        .read_depth(depth)
+        .read(&integrated_tex)
+        .write(output)
+        .constants((
+            output.desc().extent_inv_extent_2d(),
+            integrated_tex.desc().extent_inv_extent_2d(),
+            max_distance,
+            tile_size,
+        ));
+
+        // Fog dims emitters along with everything else, and its own radiance isn't emitted.
+        if let Some(reactive_mask) = reactive_mask {
+            pass = pass.write(reactive_mask);
+        }
+
+        pass.dispatch(output.desc().extent);
+    }
+}
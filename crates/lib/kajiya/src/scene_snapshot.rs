@@ -0,0 +1,371 @@
+use std::{collections::HashMap, hash::Hash};
+
+use glam::Affine3A;
+
+use crate::world_renderer::{
+    InstanceDynamicParameters, InstanceHandle, LightHandle, MeshHandle, PointLight, RectLight,
+    RectLightHandle, WorldRenderer,
+};
+
+/// Editable state of an instance, as captured by `WorldRenderer::snapshot_scene`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct InstanceSnapshot {
+    /// The mesh passed to `add_instance`
+    pub mesh: MeshHandle,
+    pub transform: Affine3A,
+    pub ray_tracing_proxy: Option<MeshHandle>,
+    pub dynamic_parameters: InstanceDynamicParameters,
+
+    /// See `WorldRenderer::set_instance_static`
+    pub is_static: bool,
+}
+
+/// The instances and lights of a `WorldRenderer`, by handle, for editor undo stacks
+/// and for replicating scene edits to another renderer; see `SceneSnapshot::diff`.
+///
+/// Meshes, materials, images, instance batches, crowds and skinning poses aren't included.
+/// A snapshot is a copy of a few plain values per object, cheap enough to take every frame.
+#[derive(Clone, Default, Debug)]
+pub struct SceneSnapshot {
+    pub instances: HashMap<InstanceHandle, InstanceSnapshot>,
+    pub point_lights: HashMap<LightHandle, PointLight>,
+    pub rect_lights: HashMap<RectLightHandle, RectLight>,
+}
+
+/// Objects added, changed or removed between two snapshots, with their new state.
+#[derive(Clone, Debug)]
+pub struct SceneChanges<K, V> {
+    pub added: Vec<(K, V)>,
+    pub changed: Vec<(K, V)>,
+    pub removed: Vec<K>,
+}
+
+impl<K, V> Default for SceneChanges<K, V> {
+    fn default() -> Self {
+        Self {
+            added: Vec::new(),
+            changed: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+}
+
+impl<K, V> SceneChanges<K, V> {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.changed.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Turns one `SceneSnapshot` into another when applied with `WorldRenderer::apply_scene_diff`.
+///
+/// It only holds the new state of objects, so it can't be reverted by itself. For undo,
+/// keep the snapshots, and apply `current.diff(&previous)`.
+#[derive(Clone, Default, Debug)]
+pub struct SceneDiff {
+    pub instances: SceneChanges<InstanceHandle, InstanceSnapshot>,
+    pub point_lights: SceneChanges<LightHandle, PointLight>,
+    pub rect_lights: SceneChanges<RectLightHandle, RectLight>,
+}
+
+impl SceneDiff {
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty() && self.point_lights.is_empty() && self.rect_lights.is_empty()
+    }
+}
+
+impl SceneSnapshot {
+    /// Changes taking `self` to `to`.
+    pub fn diff(&self, to: &SceneSnapshot) -> SceneDiff {
+        let mut instances = diff_maps(&self.instances, &to.instances);
+
+        // Instances can't switch meshes; they're re-created instead.
+        let (changed, recreated): (Vec<_>, Vec<_>) = instances
+            .changed
+            .into_iter()
+            .partition(|(handle, instance)| self.instances[handle].mesh == instance.mesh);
+        instances.changed = changed;
+        instances
+            .removed
+            .extend(recreated.iter().map(|(handle, _)| *handle));
+        instances.added.extend(recreated);
+
+        SceneDiff {
+            instances,
+            point_lights: diff_maps(&self.point_lights, &to.point_lights),
+            rect_lights: diff_maps(&self.rect_lights, &to.rect_lights),
+        }
+    }
+}
+
+fn diff_maps<K: Copy + Eq + Hash, V: Copy + PartialEq>(
+    from: &HashMap<K, V>,
+    to: &HashMap<K, V>,
+) -> SceneChanges<K, V> {
+    let mut changes = SceneChanges::default();
+
+    for (&handle, &value) in to {
+        match from.get(&handle) {
+            Some(prev) if *prev == value => {}
+            Some(_) => changes.changed.push((handle, value)),
+            None => changes.added.push((handle, value)),
+        }
+    }
+
+    changes.removed = from
+        .keys()
+        .filter(|handle| !to.contains_key(handle))
+        .copied()
+        .collect();
+
+    changes
+}
+
+impl WorldRenderer {
+    pub fn snapshot_scene(&self) -> SceneSnapshot {
+        let instances = self
+            .instance_handles
+            .iter()
+            .zip(&self.instances)
+            .map(|(&handle, instance)| {
+                (
+                    handle,
+                    InstanceSnapshot {
+                        mesh: self.instance_source_mesh(handle),
+                        transform: instance.transform,
+                        ray_tracing_proxy: instance.ray_tracing_proxy,
+                        dynamic_parameters: instance.dynamic_parameters,
+                        is_static: self.static_instances.contains(&handle),
+                    },
+                )
+            })
+            .collect();
+
+        SceneSnapshot {
+            instances,
+            point_lights: self.point_lights.clone(),
+            rect_lights: self.rect_lights.clone(),
+        }
+    }
+
+    /// Applies a diff computed against this renderer's scene, or against one replicated
+    /// to it, e.g. a remote viewer fed diffs of an editor's snapshots.
+    ///
+    /// Added objects keep the handles from the diff, which must be unused, and handles allocated
+    /// afterwards come after them. Handles of meshes and images must mean the same as where
+    /// the diff was computed, e.g. with the same assets loaded in the same order.
+    pub fn apply_scene_diff(&mut self, diff: &SceneDiff) {
+        for &handle in &diff.instances.removed {
+            self.remove_instance(handle);
+        }
+        for &(handle, instance) in &diff.instances.added {
+            self.add_instance_with_handle(handle, instance.mesh, instance.transform);
+            self.apply_instance_snapshot(handle, &instance);
+        }
+        for &(handle, instance) in &diff.instances.changed {
+            self.apply_instance_snapshot(handle, &instance);
+        }
+
+        for &handle in &diff.point_lights.removed {
+            self.remove_light(handle);
+        }
+        for &(handle, light) in &diff.point_lights.added {
+            self.add_light_with_handle(handle, light);
+        }
+        for &(handle, light) in &diff.point_lights.changed {
+            self.set_light(handle, light);
+        }
+
+        for &handle in &diff.rect_lights.removed {
+            self.remove_rect_light(handle);
+        }
+        for &(handle, light) in &diff.rect_lights.added {
+            self.add_rect_light_with_handle(handle, light);
+        }
+        for &(handle, light) in &diff.rect_lights.changed {
+            self.set_rect_light(handle, light);
+        }
+    }
+
+    fn apply_instance_snapshot(&mut self, handle: InstanceHandle, instance: &InstanceSnapshot) {
+        self.set_instance_transform(handle, instance.transform);
+        self.set_instance_ray_tracing_proxy(handle, instance.ray_tracing_proxy);
+        *self.get_instance_dynamic_parameters_mut(handle) = instance.dynamic_parameters;
+        self.set_instance_static(handle, instance.is_static);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    fn instance(mesh: usize, x: f32) -> InstanceSnapshot {
+        InstanceSnapshot {
+            mesh: MeshHandle(mesh),
+            transform: Affine3A::from_translation(Vec3::new(x, 0.0, 0.0)),
+            ray_tracing_proxy: None,
+            dynamic_parameters: Default::default(),
+            is_static: false,
+        }
+    }
+
+    fn point_light(intensity: f32) -> PointLight {
+        PointLight {
+            intensity,
+            ..Default::default()
+        }
+    }
+
+    // What `WorldRenderer::apply_scene_diff` does to the scene, in terms of snapshots
+    fn apply_changes<K: Copy + Eq + Hash, V: Copy>(
+        map: &mut HashMap<K, V>,
+        changes: &SceneChanges<K, V>,
+    ) {
+        for handle in &changes.removed {
+            assert!(map.remove(handle).is_some());
+        }
+        for &(handle, value) in &changes.added {
+            assert!(map.insert(handle, value).is_none());
+        }
+        for &(handle, value) in &changes.changed {
+            assert!(map.insert(handle, value).is_some());
+        }
+    }
+
+    fn apply(snapshot: &SceneSnapshot, diff: &SceneDiff) -> SceneSnapshot {
+        let mut snapshot = snapshot.clone();
+        apply_changes(&mut snapshot.instances, &diff.instances);
+        apply_changes(&mut snapshot.point_lights, &diff.point_lights);
+        apply_changes(&mut snapshot.rect_lights, &diff.rect_lights);
+        snapshot
+    }
+
+    fn assert_snapshots_eq(a: &SceneSnapshot, b: &SceneSnapshot) {
+        assert_eq!(a.instances, b.instances);
+        assert_eq!(a.point_lights, b.point_lights);
+        assert_eq!(a.rect_lights, b.rect_lights);
+    }
+
+    fn scenes() -> (SceneSnapshot, SceneSnapshot) {
+        let before = SceneSnapshot {
+            instances: [
+                (InstanceHandle(0), instance(0, 0.0)),
+                (InstanceHandle(1), instance(0, 1.0)),
+                (InstanceHandle(2), instance(1, 2.0)),
+                (InstanceHandle(3), instance(1, 3.0)),
+            ]
+            .into_iter()
+            .collect(),
+            point_lights: [
+                (LightHandle(0), point_light(1.0)),
+                (LightHandle(1), point_light(2.0)),
+            ]
+            .into_iter()
+            .collect(),
+            rect_lights: Default::default(),
+        };
+
+        let mut after = before.clone();
+        // Moved
+        after
+            .instances
+            .get_mut(&InstanceHandle(1))
+            .unwrap()
+            .transform = Affine3A::from_translation(Vec3::new(5.0, 0.0, 0.0));
+        // Switched meshes
+        after.instances.get_mut(&InstanceHandle(2)).unwrap().mesh = MeshHandle(2);
+        after.instances.remove(&InstanceHandle(3));
+        after.instances.insert(InstanceHandle(4), instance(2, 4.0));
+        after
+            .point_lights
+            .get_mut(&LightHandle(1))
+            .unwrap()
+            .intensity = 3.0;
+        after
+            .rect_lights
+            .insert(RectLightHandle(0), RectLight::default());
+
+        (before, after)
+    }
+
+    fn sorted<T: Ord>(mut items: Vec<T>) -> Vec<T> {
+        items.sort();
+        items
+    }
+
+    #[test]
+    fn diff_of_identical_snapshots_is_empty() {
+        let (before, _) = scenes();
+        assert!(before.diff(&before.clone()).is_empty());
+    }
+
+    #[test]
+    fn diff_lists_added_changed_and_removed_objects() {
+        let (before, after) = scenes();
+        let diff = before.diff(&after);
+
+        let handles = |items: &[(InstanceHandle, InstanceSnapshot)]| {
+            sorted(items.iter().map(|(handle, _)| handle.0).collect())
+        };
+        assert_eq!(handles(&diff.instances.added), vec![2, 4]);
+        assert_eq!(handles(&diff.instances.changed), vec![1]);
+        assert_eq!(
+            sorted(
+                diff.instances
+                    .removed
+                    .iter()
+                    .map(|handle| handle.0)
+                    .collect()
+            ),
+            vec![2, 3]
+        );
+
+        assert_eq!(
+            diff.instances.changed[0].1,
+            after.instances[&InstanceHandle(1)]
+        );
+        assert!(diff
+            .instances
+            .added
+            .contains(&(InstanceHandle(4), instance(2, 4.0))));
+
+        assert!(diff.point_lights.added.is_empty());
+        assert!(diff.point_lights.removed.is_empty());
+        assert_eq!(
+            diff.point_lights.changed,
+            vec![(LightHandle(1), point_light(3.0))]
+        );
+
+        assert_eq!(diff.rect_lights.added.len(), 1);
+        assert!(diff.rect_lights.changed.is_empty());
+        assert!(diff.rect_lights.removed.is_empty());
+    }
+
+    #[test]
+    fn diff_recreates_instances_which_switch_meshes() {
+        let (before, after) = scenes();
+        let diff = before.diff(&after);
+
+        assert!(diff.instances.removed.contains(&InstanceHandle(2)));
+        assert!(diff
+            .instances
+            .added
+            .contains(&(InstanceHandle(2), after.instances[&InstanceHandle(2)])));
+        assert!(diff
+            .instances
+            .changed
+            .iter()
+            .all(|(handle, _)| *handle != InstanceHandle(2)));
+    }
+
+    #[test]
+    fn diff_round_trips_for_undo() {
+        let (before, after) = scenes();
+
+        let redone = apply(&before, &before.diff(&after));
+        assert_snapshots_eq(&redone, &after);
+
+        let undone = apply(&redone, &redone.diff(&before));
+        assert_snapshots_eq(&undone, &before);
+    }
+}
@@ -0,0 +1,92 @@
+use glam::{Quat, Vec3};
+
+use crate::world_renderer::WorldRenderer;
+
+/// Drives the sun from a date, time and location on Earth.
+///
+/// The world is assumed to be laid out with +Y up, +X east, and -Z north,
+/// optionally rotated by `north_offset_degrees` around the up axis.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct TimeOfDay {
+    pub latitude_degrees: f32,
+
+    /// 1 for January 1st, 365 for December 31st
+    pub day_of_year: f32,
+
+    /// Local solar time, with the sun highest at 12
+    pub hours: f32,
+
+    /// In-game hours advanced per real-time second by `advance`
+    pub hours_per_second: f32,
+
+    pub north_offset_degrees: f32,
+
+    /// Ambient sky term blended in as the sun sets, so that nights aren't pitch black
+    pub night_sky_ambient: [f32; 3],
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        Self {
+            latitude_degrees: 45.0,
+            day_of_year: 172.0,
+            hours: 10.0,
+            hours_per_second: 0.0,
+            north_offset_degrees: 0.0,
+            night_sky_ambient: [0.002, 0.003, 0.006],
+        }
+    }
+}
+
+impl TimeOfDay {
+    pub fn advance(&mut self, dt_seconds: f32) {
+        self.hours += self.hours_per_second * dt_seconds;
+
+        if !(0.0..24.0).contains(&self.hours) {
+            let days = (self.hours / 24.0).floor();
+            self.hours -= days * 24.0;
+            self.day_of_year = (self.day_of_year - 1.0 + days).rem_euclid(365.0) + 1.0;
+        }
+    }
+
+    /// Unit vector towards the sun, suitable for `WorldFrameDesc::sun_direction`.
+    pub fn sun_direction(&self) -> Vec3 {
+        let latitude = self.latitude_degrees.to_radians();
+
+        // Approximate solar declination; good to about a degree.
+        let declination = -23.44f32.to_radians()
+            * ((self.day_of_year + 10.0) * std::f32::consts::TAU / 365.0).cos();
+        let hour_angle = ((self.hours - 12.0) * 15.0).to_radians();
+
+        let east = -declination.cos() * hour_angle.sin();
+        let north = latitude.cos() * declination.sin()
+            - latitude.sin() * declination.cos() * hour_angle.cos();
+        let up = latitude.sin() * declination.sin()
+            + latitude.cos() * declination.cos() * hour_angle.cos();
+
+        Quat::from_rotation_y(self.north_offset_degrees.to_radians())
+            * Vec3::new(east, up, -north).normalize()
+    }
+
+    /// Sun elevation above the horizon, in degrees.
+    pub fn sun_elevation_degrees(&self) -> f32 {
+        self.sun_direction().y.clamp(-1.0, 1.0).asin().to_degrees()
+    }
+
+    /// Fades the sun out as it dips below the horizon, and the night sky ambient in.
+    /// The sky model handles the color of the sun and sky itself.
+    pub fn apply_sky_params(&self, world_renderer: &mut WorldRenderer) {
+        let elevation = self.sun_elevation_degrees();
+        let day = smoothstep(-6.0, 2.0, elevation);
+
+        world_renderer.sun_color_multiplier = Vec3::splat(day);
+        world_renderer.sky_ambient = Vec3::from(self.night_sky_ambient) * (1.0 - day);
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
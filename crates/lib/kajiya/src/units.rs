@@ -0,0 +1,69 @@
+//! Conversions between photometric units and the ones the renderer works in.
+//!
+//! Shading is done in arbitrary radiance units, scaled so that the sun, with a
+//! `WorldRenderer::sun_color_multiplier` of one, has an illuminance of 20 units before
+//! entering the atmosphere; that is `SOLAR_ILLUMINANCE_LUX`. One unit of radiance is then
+//! `NITS_PER_RADIANCE_UNIT` nits (candela per square meter), and one unit of irradiance
+//! as many lux. Convert values in real-world units with the functions here, or set
+//! the `unit` of `PointLight` and `RectLight`, and pick a matching exposure with
+//! `ev_shift_for_ev100`.
+//!
+//! Color is kept separate: the photometric quantities here are the luminance
+//! of the emission, with its linear RGB color normalized to a luminance of one.
+
+/// Illuminance of the sun outside the atmosphere, in lux
+// Must match `SOLAR_ILLUMINANCE_LUX` in `inc/units.hlsl`
+pub const SOLAR_ILLUMINANCE_LUX: f32 = 128_000.0;
+
+/// Luminance, in nits, of one unit of radiance the renderer works in
+// Must match `NITS_PER_RADIANCE_UNIT` in `inc/units.hlsl`
+pub const NITS_PER_RADIANCE_UNIT: f32 = 6_400.0;
+
+/// Converts luminance in nits (cd/m²), e.g. of a screen or of an emissive material,
+/// to renderer radiance, as used by `MeshMaterial::emissive` and `RectLight`.
+pub fn nits(luminance: f32) -> f32 {
+    luminance / NITS_PER_RADIANCE_UNIT
+}
+
+/// Converts illuminance in lux to renderer irradiance.
+pub fn lux(illuminance: f32) -> f32 {
+    illuminance / NITS_PER_RADIANCE_UNIT
+}
+
+/// Converts a luminous intensity in candela to the intensity of a `PointLight`
+/// in renderer units: irradiance at a distance of one scene unit.
+pub fn candela(intensity: f32, world_units_per_meter: f32) -> f32 {
+    lux(intensity) * world_units_per_meter * world_units_per_meter
+}
+
+/// Luminous intensity, in candela, of a light bulb emitting `lumens` evenly in all directions.
+pub fn isotropic_lumens_to_candela(lumens: f32) -> f32 {
+    lumens / (4.0 * std::f32::consts::PI)
+}
+
+/// Luminous intensity, in candela, of a spot light emitting `lumens` evenly
+/// within a cone of half-angle `outer_angle`, in radians.
+pub fn spot_lumens_to_candela(lumens: f32, outer_angle: f32) -> f32 {
+    let solid_angle = 2.0 * std::f32::consts::PI * (1.0 - outer_angle.cos());
+    lumens / solid_angle.max(1e-6)
+}
+
+/// Luminance, in nits, of a one-sided Lambertian emitter of `area` square meters
+/// emitting `lumens`, e.g. a light panel.
+pub fn lambertian_lumens_to_nits(lumens: f32, area: f32) -> f32 {
+    lumens / (std::f32::consts::PI * area.max(1e-6))
+}
+
+/// Exposure value at ISO 100 of a camera with the given settings. Bright sunlight
+/// is around 15, overcast days 12, well-lit interiors 7, and streets at night 3.
+pub fn ev100(f_number: f32, shutter_seconds: f32, iso: f32) -> f32 {
+    (f_number * f_number / shutter_seconds * 100.0 / iso).log2()
+}
+
+/// The `WorldRenderer::ev_shift` exposing the image like a camera at `ev100` would,
+/// with the saturation-based sensitivity of ISO 12232 (which is what the light meters of
+/// most cameras follow): scene luminance of `1.2 * 2^ev100` nits maps to an exposed value of one.
+/// Automatic exposure is added on top of `ev_shift`, so disable it for a fixed camera exposure.
+pub fn ev_shift_for_ev100(ev100: f32) -> f32 {
+    (NITS_PER_RADIANCE_UNIT / 1.2).log2() - ev100
+}
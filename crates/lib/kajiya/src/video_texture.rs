@@ -0,0 +1,645 @@
+use std::sync::{mpsc, Arc};
+
+use kajiya_backend::{
+    ash::vk,
+    vk_sync::AccessType,
+    vulkan::{
+        buffer::{Buffer, BufferDesc},
+        image::*,
+    },
+};
+use kajiya_rg as rg;
+use log::error;
+
+use crate::world_renderer::{BindlessImageHandle, WorldRenderer};
+
+// Enough for one buffer being written on the CPU, and two frames in flight.
+const VIDEO_STAGING_BUFFER_COUNT: usize = 3;
+
+// Decoded frames buffered ahead of playback
+const DECODED_FRAME_QUEUE_LENGTH: usize = 2;
+
+// Pixels skipped between samples of the mean luminance of a frame
+const LUMINANCE_SAMPLE_STRIDE: usize = 61;
+
+/// A decoded frame: 8-bit sRGB-encoded RGBA, row by row, without padding.
+pub struct VideoFrame {
+    pub extent: [u32; 2],
+    pub rgba8: Vec<u8>,
+}
+
+/// Source of frames for `WorldRenderer::add_video_texture`; runs on a thread of its own.
+///
+/// `open_video_file` provides one for H.264 and H.265 streams, decoded on the CPU. Implement this
+/// to plug in other codecs, containers, or hardware decoders.
+pub trait VideoDecoder: Send {
+    /// Decodes the next frame, or returns `None` at the end of the stream.
+    fn next_frame(&mut self) -> anyhow::Result<Option<VideoFrame>>;
+
+    /// Starts over from the first frame, for looping playback.
+    fn rewind(&mut self) -> anyhow::Result<()>;
+}
+
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub struct VideoTextureHandle(pub usize);
+
+#[derive(Clone, Copy, Debug)]
+pub struct VideoTextureParams {
+    /// Elementary streams carry no timing, so this sets the playback rate.
+    pub frames_per_second: f32,
+
+    /// Start over at the end of the stream, instead of holding the last frame
+    pub looping: bool,
+}
+
+impl Default for VideoTextureParams {
+    fn default() -> Self {
+        Self {
+            frames_per_second: 30.0,
+            looping: true,
+        }
+    }
+}
+
+struct StagingBuffer {
+    buffer: Arc<Buffer>,
+
+    // Value of the universal queue timeline once the GPU is done reading the buffer
+    gpu_done_timeline_value: u64,
+}
+
+struct DecodedFrame {
+    frame: VideoFrame,
+    mean_luminance: f32,
+}
+
+pub(crate) struct VideoTexture {
+    image: Arc<Image>,
+    bindless_handle: BindlessImageHandle,
+    params: VideoTextureParams,
+    playing: bool,
+
+    // Closed by the decoder thread at the end of a stream which doesn't loop, or on errors.
+    frames: mpsc::Receiver<anyhow::Result<DecodedFrame>>,
+    finished: bool,
+
+    time_seconds: f64,
+    shown_frame_count: u64,
+    mean_luminance: f32,
+
+    staging_buffers: Vec<StagingBuffer>,
+    read_by_pending_frame: Option<usize>,
+}
+
+impl WorldRenderer {
+    /// Adds an image which plays back a video, updated on the GPU as frames are due.
+    /// Bind the returned image like any other, e.g. as the emissive map (`maps[3]`) of a screen
+    /// material via `get_material_mut`, with the material's `emissive` factor as the brightness.
+    ///
+    /// Ray-traced GI and reflections pick up the current frame wherever they hit the screen,
+    /// and GI history is shortened on cuts and flashes, so that indirect lighting keeps up.
+    /// Meshes added with `AddMeshOptions::use_lights` light the scene through triangle lights
+    /// instead, which only know the material's `emissive` factor; leave it off for screens.
+    ///
+    /// The first frame is decoded before returning, and sets the resolution of the texture;
+    /// the rest are decoded on a background thread. If decoding falls behind, playback slows
+    /// down rather than skipping frames. The image has no mips.
+    pub fn add_video_texture(
+        &mut self,
+        mut decoder: Box<dyn VideoDecoder>,
+        params: VideoTextureParams,
+    ) -> anyhow::Result<(VideoTextureHandle, BindlessImageHandle)> {
+        let first_frame = decoder
+            .next_frame()?
+            .ok_or_else(|| anyhow::anyhow!("The video has no frames"))?;
+        let extent = first_frame.extent;
+        let frame_bytes = (extent[0] * extent[1]) as usize * 4;
+        anyhow::ensure!(
+            first_frame.rgba8.len() == frame_bytes,
+            "Video frame size doesn't match its extent"
+        );
+
+        let image = Arc::new(
+            self.device.create_image(
+                ImageDesc::new_2d(vk::Format::R8G8B8A8_SRGB, extent)
+                    .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST),
+                vec![ImageSubResourceData {
+                    data: &first_frame.rgba8,
+                    row_pitch: extent[0] as usize * 4,
+                    slice_pitch: frame_bytes,
+                }],
+            )?,
+        );
+        let bindless_handle = self.add_image(image.clone());
+
+        let staging_buffers = (0..VIDEO_STAGING_BUFFER_COUNT)
+            .map(|_| {
+                Ok(StagingBuffer {
+                    buffer: Arc::new(self.device.create_buffer(
+                        BufferDesc::new_cpu_to_gpu(frame_bytes, vk::BufferUsageFlags::TRANSFER_SRC),
+                        "video texture staging",
+                        None,
+                    )?),
+                    gpu_done_timeline_value: 0,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let (sender, frames) = mpsc::sync_channel(DECODED_FRAME_QUEUE_LENGTH);
+        let looping = params.looping;
+        std::thread::spawn(move || run_decoder(decoder, looping, sender));
+
+        let handle = VideoTextureHandle(self.next_video_texture_handle);
+        self.next_video_texture_handle += 1;
+
+        self.video_textures.insert(
+            handle,
+            VideoTexture {
+                image,
+                bindless_handle,
+                params,
+                playing: true,
+                frames,
+                finished: false,
+                time_seconds: 0.0,
+                shown_frame_count: 1,
+                mean_luminance: mean_luminance(&first_frame.rgba8),
+                staging_buffers,
+                read_by_pending_frame: None,
+            },
+        );
+
+        Ok((handle, bindless_handle))
+    }
+
+    /// Stops decoding. Bindless images can't be removed, so the image stays valid,
+    /// holding the last frame shown. Stalls until the GPU is done with the staging buffers.
+    pub fn remove_video_texture(&mut self, video: VideoTextureHandle) {
+        let video = self
+            .video_textures
+            .remove(&video)
+            .expect("no such video texture");
+
+        let gpu_done_timeline_value = video
+            .staging_buffers
+            .iter()
+            .map(|buffer| buffer.gpu_done_timeline_value)
+            .max()
+            .unwrap_or_default();
+
+        if let Err(err) = self
+            .device
+            .wait_for_queue(&self.device.universal_queue, gpu_done_timeline_value)
+        {
+            error!(
+                "Failed to wait for the GPU to release a video texture: {:?}",
+                err
+            );
+            return;
+        }
+
+        for buffer in video.staging_buffers {
+            if let Ok(buffer) = Arc::try_unwrap(buffer.buffer) {
+                self.device.immediate_destroy_buffer(buffer);
+            }
+        }
+    }
+
+    pub fn video_texture_image(&self, video: VideoTextureHandle) -> BindlessImageHandle {
+        self.video_textures[&video].bindless_handle
+    }
+
+    /// Pauses or resumes playback.
+    pub fn set_video_playing(&mut self, video: VideoTextureHandle, playing: bool) {
+        self.video_textures
+            .get_mut(&video)
+            .expect("no such video texture")
+            .playing = playing;
+    }
+
+    /// Whether a video which doesn't loop has shown its last frame, or failed to decode.
+    pub fn is_video_finished(&self, video: VideoTextureHandle) -> bool {
+        self.video_textures[&video].finished
+    }
+
+    pub(crate) fn advance_video_textures(&mut self, delta_time_seconds: f32) {
+        for video in self.video_textures.values_mut() {
+            if video.playing && !video.finished {
+                video.time_seconds += delta_time_seconds.max(0.0) as f64;
+            }
+        }
+    }
+
+    /// Copies the frames which are due into the video images. Runs ahead of all passes
+    /// which could sample them through the bindless table.
+    pub(crate) fn update_video_textures(&mut self, rg: &mut rg::TemporalRenderGraph) {
+        let mut luminance_changes = Vec::new();
+
+        for video in self.video_textures.values_mut() {
+            let fps = video.params.frames_per_second.max(1e-3) as f64;
+            let due_frame_count = (video.time_seconds * fps) as u64 + 1;
+
+            let mut latest = None;
+            while !video.finished && video.shown_frame_count < due_frame_count {
+                match video.frames.try_recv() {
+                    Ok(Ok(frame)) => {
+                        latest = Some(frame);
+                        video.shown_frame_count += 1;
+                    }
+                    Ok(Err(err)) => {
+                        error!("Failed to decode a video frame: {:#}", err);
+                        video.finished = true;
+                    }
+                    Err(mpsc::TryRecvError::Empty) => {
+                        // Wait for the decoder instead of skipping frames once it catches up.
+                        video.time_seconds = video.shown_frame_count as f64 / fps;
+                        break;
+                    }
+                    Err(mpsc::TryRecvError::Disconnected) => video.finished = true,
+                }
+            }
+
+            let DecodedFrame {
+                frame,
+                mean_luminance,
+            } = if let Some(latest) = latest {
+                latest
+            } else {
+                continue;
+            };
+
+            let [width, height] = video.image.desc.extent_2d();
+            if frame.extent != [width, height] || frame.rgba8.len() != (width * height) as usize * 4
+            {
+                error!(
+                    "Video frame is {:?}, but the texture is {:?}; skipping",
+                    frame.extent,
+                    [width, height]
+                );
+                continue;
+            }
+
+            let buffer_index = (0..VIDEO_STAGING_BUFFER_COUNT)
+                .min_by_key(|&idx| video.staging_buffers[idx].gpu_done_timeline_value)
+                .unwrap();
+            let staging = &video.staging_buffers[buffer_index];
+
+            if let Err(err) = self.device.wait_for_queue(
+                &self.device.universal_queue,
+                staging.gpu_done_timeline_value,
+            ) {
+                error!(
+                    "Failed to wait for the GPU to release a video staging buffer: {:?}",
+                    err
+                );
+                continue;
+            }
+
+            staging
+                .buffer
+                .allocation
+                .mapped_slice_mut()
+                .expect("video staging buffer is not host-visible")[..frame.rgba8.len()]
+                .copy_from_slice(&frame.rgba8);
+
+            let staging = rg.import(staging.buffer.clone(), AccessType::Nothing);
+            let mut image = rg.import(
+                video.image.clone(),
+                AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+            );
+
+            {
+                let mut pass = rg.add_pass("video texture upload");
+                let src_ref = pass.read(&staging, AccessType::TransferRead);
+                let dst_ref = pass.write(&mut image, AccessType::TransferWrite);
+
+                pass.render(move |api| {
+                    let region = vk::BufferImageCopy::builder()
+                        .image_subresource(vk::ImageSubresourceLayers {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            mip_level: 0,
+                            base_array_layer: 0,
+                            layer_count: 1,
+                        })
+                        .image_extent(vk::Extent3D {
+                            width,
+                            height,
+                            depth: 1,
+                        })
+                        .build();
+
+                    unsafe {
+                        api.device().raw.cmd_copy_buffer_to_image(
+                            api.cb.raw,
+                            api.resources.buffer(src_ref).raw,
+                            api.resources.image(dst_ref).raw,
+                            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                            &[region],
+                        );
+                    }
+
+                    Ok(())
+                });
+            }
+
+            // Bindless reads aren't tracked by the graph, so transition back right away.
+            {
+                let mut pass = rg.add_pass("video texture ready");
+                pass.read(
+                    &image,
+                    AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+                );
+            }
+
+            rg.export(
+                image,
+                AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+            );
+
+            video.read_by_pending_frame = Some(buffer_index);
+            luminance_changes.push((video.mean_luminance, mean_luminance));
+            video.mean_luminance = mean_luminance;
+        }
+
+        for (prev, current) in luminance_changes {
+            self.emissive_changed(prev, current);
+        }
+    }
+
+    /// Called once the frame which last uploaded video frames has been submitted.
+    pub(crate) fn retire_video_textures(&mut self) {
+        let timeline_value = self.device.universal_queue.last_submitted_value();
+
+        for video in self.video_textures.values_mut() {
+            if let Some(buffer_index) = video.read_by_pending_frame.take() {
+                video.staging_buffers[buffer_index].gpu_done_timeline_value = timeline_value;
+            }
+        }
+    }
+}
+
+fn run_decoder(
+    mut decoder: Box<dyn VideoDecoder>,
+    looping: bool,
+    sender: mpsc::SyncSender<anyhow::Result<DecodedFrame>>,
+) {
+    loop {
+        let frame = match decoder.next_frame() {
+            Ok(Some(frame)) => frame,
+            Ok(None) if looping => match decoder.rewind() {
+                Ok(()) => continue,
+                Err(err) => {
+                    let _ = sender.send(Err(err));
+                    return;
+                }
+            },
+            Ok(None) => return,
+            Err(err) => {
+                let _ = sender.send(Err(err));
+                return;
+            }
+        };
+
+        let mean_luminance = mean_luminance(&frame.rgba8);
+
+        // Fails once the video texture has been removed.
+        if sender
+            .send(Ok(DecodedFrame {
+                frame,
+                mean_luminance,
+            }))
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+// Of a sparse subset of the pixels, in linear units
+fn mean_luminance(rgba8: &[u8]) -> f32 {
+    let (sum, count) = rgba8.chunks_exact(4).step_by(LUMINANCE_SAMPLE_STRIDE).fold(
+        (0.0f32, 0u32),
+        |(sum, count), px| {
+            let linear = |c: u8| (c as f32 / 255.0).powf(2.2);
+            let luminance =
+                0.2126 * linear(px[0]) + 0.7152 * linear(px[1]) + 0.0722 * linear(px[2]);
+            (sum + luminance, count + 1)
+        },
+    );
+
+    sum / count.max(1) as f32
+}
+
+/// Opens a raw H.264 or H.265 elementary stream (Annex B, as written by e.g.
+/// `ffmpeg -i in.mp4 -c:v libx264 -bsf:v h264_mp4toannexb out.h264`, or with `libx265`,
+/// `hevc_mp4toannexb` and `.h265`), decoded on the CPU. H.264 is decoded by OpenH264,
+/// and H.265 by FFmpeg, whose libraries must be installed. Requires the `video` feature.
+///
+/// Hardware decoding isn't supported, as the Vulkan bindings in use predate Vulkan Video;
+/// it can be added as a `VideoDecoder`.
+#[cfg(feature = "video")]
+pub fn open_video_file(path: &std::path::Path) -> anyhow::Result<Box<dyn VideoDecoder>> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+
+    match extension.as_deref() {
+        Some("h264" | "264") => Ok(Box::new(H264Decoder::new(std::fs::read(path)?)?)),
+        Some("h265" | "265" | "hevc") => Ok(Box::new(H265Decoder::new(path.to_owned())?)),
+        _ => anyhow::bail!(
+            "{:?}: expected a raw H.264 or H.265 stream, with the .h264 or .h265 extension",
+            path
+        ),
+    }
+}
+
+#[cfg(feature = "video")]
+struct H264Decoder {
+    stream: Vec<u8>,
+    offset: usize,
+    decoder: openh264::decoder::Decoder,
+}
+
+#[cfg(feature = "video")]
+impl H264Decoder {
+    fn new(stream: Vec<u8>) -> anyhow::Result<Self> {
+        Ok(Self {
+            stream,
+            offset: 0,
+            decoder: openh264::decoder::Decoder::new()
+                .map_err(|err| anyhow::anyhow!("Creating the H.264 decoder: {:?}", err))?,
+        })
+    }
+}
+
+#[cfg(feature = "video")]
+impl VideoDecoder for H264Decoder {
+    fn next_frame(&mut self) -> anyhow::Result<Option<VideoFrame>> {
+        while let Some(nal_unit) = next_nal_unit(&self.stream, &mut self.offset) {
+            let yuv = self
+                .decoder
+                .decode(nal_unit)
+                .map_err(|err| anyhow::anyhow!("Decoding H.264: {:?}", err))?;
+
+            if let Some(yuv) = yuv {
+                let (width, height) = yuv.dimension_rgb();
+                let mut rgba8 = vec![0u8; width * height * 4];
+                yuv.write_rgba8(&mut rgba8);
+
+                return Ok(Some(VideoFrame {
+                    extent: [width as u32, height as u32],
+                    rgba8,
+                }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn rewind(&mut self) -> anyhow::Result<()> {
+        self.offset = 0;
+        self.decoder = openh264::decoder::Decoder::new()
+            .map_err(|err| anyhow::anyhow!("Creating the H.264 decoder: {:?}", err))?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "video")]
+struct H265Decoder {
+    path: std::path::PathBuf,
+    input: ffmpeg_next::format::context::Input,
+    stream_index: usize,
+    decoder: ffmpeg_next::decoder::Video,
+
+    // Converts decoded frames to RGBA; recreated when their format or size changes
+    scaler: Option<ffmpeg_next::software::scaling::Context>,
+}
+
+// The FFmpeg contexts are owned by the decoder, and only used by the thread it's on.
+#[cfg(feature = "video")]
+unsafe impl Send for H265Decoder {}
+
+#[cfg(feature = "video")]
+impl H265Decoder {
+    fn new(path: std::path::PathBuf) -> anyhow::Result<Self> {
+        ffmpeg_next::init()?;
+
+        let input = ffmpeg_next::format::input(&path)?;
+        let stream = input
+            .streams()
+            .best(ffmpeg_next::media::Type::Video)
+            .ok_or_else(|| anyhow::anyhow!("{:?}: no video stream", path))?;
+        let stream_index = stream.index();
+        let decoder = ffmpeg_next::codec::context::Context::from_parameters(stream.parameters())?
+            .decoder()
+            .video()?;
+
+        Ok(Self {
+            path,
+            input,
+            stream_index,
+            decoder,
+            scaler: None,
+        })
+    }
+
+    fn convert_frame(&mut self, decoded: &ffmpeg_next::frame::Video) -> anyhow::Result<VideoFrame> {
+        let (width, height) = (decoded.width(), decoded.height());
+
+        let scaler_matches = self.scaler.as_ref().map_or(false, |scaler| {
+            let input = scaler.input();
+            input.format == decoded.format() && input.width == width && input.height == height
+        });
+        if !scaler_matches {
+            self.scaler = Some(ffmpeg_next::software::scaling::Context::get(
+                decoded.format(),
+                width,
+                height,
+                ffmpeg_next::format::Pixel::RGBA,
+                width,
+                height,
+                ffmpeg_next::software::scaling::Flags::BILINEAR,
+            )?);
+        }
+
+        let mut converted = ffmpeg_next::frame::Video::empty();
+        self.scaler.as_mut().unwrap().run(decoded, &mut converted)?;
+
+        // Rows of the converted frame can be padded.
+        let row_bytes = width as usize * 4;
+        let stride = converted.stride(0);
+        let rgba8 = converted
+            .data(0)
+            .chunks(stride)
+            .take(height as usize)
+            .flat_map(|row| &row[..row_bytes])
+            .copied()
+            .collect();
+
+        Ok(VideoFrame {
+            extent: [width, height],
+            rgba8,
+        })
+    }
+}
+
+#[cfg(feature = "video")]
+impl VideoDecoder for H265Decoder {
+    fn next_frame(&mut self) -> anyhow::Result<Option<VideoFrame>> {
+        let mut decoded = ffmpeg_next::frame::Video::empty();
+
+        loop {
+            match self.decoder.receive_frame(&mut decoded) {
+                Ok(()) => return self.convert_frame(&decoded).map(Some),
+                Err(ffmpeg_next::Error::Eof) => return Ok(None),
+                Err(ffmpeg_next::Error::Other {
+                    errno: ffmpeg_next::error::EAGAIN,
+                }) => {}
+                Err(err) => return Err(anyhow::anyhow!("Decoding H.265: {}", err)),
+            }
+
+            // The decoder needs more data: feed it the next packet, or flush it at the end.
+            let stream_index = self.stream_index;
+            match self
+                .input
+                .packets()
+                .find(|(stream, _)| stream.index() == stream_index)
+            {
+                Some((_, packet)) => self.decoder.send_packet(&packet)?,
+                None => self.decoder.send_eof()?,
+            }
+        }
+    }
+
+    fn rewind(&mut self) -> anyhow::Result<()> {
+        // Raw streams have no index to seek with, so start over with a fresh input.
+        *self = Self::new(self.path.clone())?;
+        Ok(())
+    }
+}
+
+// The next NAL unit of an Annex B stream at or after `offset`, including its start code.
+#[cfg(feature = "video")]
+fn next_nal_unit<'a>(stream: &'a [u8], offset: &mut usize) -> Option<&'a [u8]> {
+    let find_start_code = |from: usize| {
+        stream
+            .get(from..)?
+            .windows(3)
+            .position(|w| w == [0, 0, 1])
+            .map(|pos| from + pos)
+    };
+
+    let start = find_start_code(*offset)?;
+    let end = find_start_code(start + 3).map_or(stream.len(), |next| {
+        // Four-byte start codes have an extra leading zero.
+        if next > start + 3 && stream[next - 1] == 0 {
+            next - 1
+        } else {
+            next
+        }
+    });
+
+    *offset = end;
+    Some(&stream[start..end])
+}
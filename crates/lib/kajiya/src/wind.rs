@@ -0,0 +1,78 @@
+use glam::{Vec3, Vec4};
+
+use crate::world_renderer::BindlessImageHandle;
+
+// The time passed to shaders wraps around after this long, to keep its precision.
+// Must match `WIND_TIME_WRAP_SECONDS` in `inc/wind.hlsl`
+const WIND_TIME_WRAP_SECONDS: f32 = 3600.0;
+
+/// Global wind, exposed to all shaders through the frame constants, and sampled with
+/// the helpers in `inc/wind.hlsl`; meant for vertex animation of foliage, cloth and the like.
+/// None of kajiya's built-in passes animate anything with it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Wind {
+    /// Direction the wind blows towards. Doesn't need to be normalized; zero means no wind.
+    pub direction: Vec3,
+
+    /// Meters per second
+    pub speed: f32,
+
+    /// Image from `WorldRenderer::add_image` whose red channel, in `[0, 1]`, modulates the wind
+    /// strength. It's tiled over the XZ plane, and scrolls along with the wind, so that gusts
+    /// sweep across the scene. `None` for a steady wind.
+    pub gust_texture: Option<BindlessImageHandle>,
+
+    /// Size of one tile of `gust_texture`, in meters
+    pub gust_scale: f32,
+
+    /// Gusts add up to this much of `speed` on top of it.
+    pub gust_strength: f32,
+}
+
+impl Default for Wind {
+    fn default() -> Self {
+        Self {
+            direction: Vec3::X,
+            speed: 0.0,
+            gust_texture: None,
+            gust_scale: 20.0,
+            gust_strength: 1.0,
+        }
+    }
+}
+
+/// How far the air has moved, integrated over frames so that changing the speed
+/// doesn't make the gusts jump.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct WindState {
+    // In world units; wrapped to a tile of the gust texture, which makes no visible difference.
+    offset: Vec3,
+    time_seconds: f32,
+}
+
+impl WindState {
+    pub(crate) fn advance(&mut self, wind: &Wind, world_units_per_meter: f32, dt_seconds: f32) {
+        let offset = self.offset
+            + wind.direction.normalize_or_zero() * wind.speed * world_units_per_meter * dt_seconds;
+        let tile_size = wind.gust_scale.max(1e-3) * world_units_per_meter;
+        self.offset = Vec3::new(
+            offset.x.rem_euclid(tile_size),
+            offset.y.rem_euclid(tile_size),
+            offset.z.rem_euclid(tile_size),
+        );
+
+        self.time_seconds = (self.time_seconds + dt_seconds).rem_euclid(WIND_TIME_WRAP_SECONDS);
+    }
+
+    /// `FrameConstants::wind_direction_speed`
+    pub(crate) fn direction_speed(wind: &Wind) -> Vec4 {
+        wind.direction
+            .normalize_or_zero()
+            .extend(wind.speed.max(0.0))
+    }
+
+    /// `FrameConstants::wind_offset_time`
+    pub(crate) fn offset_time(&self) -> Vec4 {
+        self.offset.extend(self.time_seconds)
+    }
+}
@@ -1,22 +1,51 @@
 use crate::{
-    frame_desc::WorldFrameDesc,
+    frame_desc::{RenderFeatureFlags, WorldFrameDesc},
+    frame_graph_config::BloomConfig,
     renderers::{
-        deferred::light_gbuffer, motion_blur::motion_blur, raster_meshes::*,
-        reference::reference_path_trace, shadows::trace_sun_shadow_mask, GbufferDepth,
+        ao::AmbientOcclusionMode,
+        deferred::light_gbuffer,
+        denoiser::{DenoiserInput, DenoiserSignal},
+        motion_blur::{motion_blur, shutter_reprojection_map},
+        raster_meshes::*,
+        reference::{
+            read_back_reference, reference_path_trace, restore_reference,
+            upsample_reference_preview, ReferenceAovs,
+        },
+        render_scale::{
+            scale_frame_inputs, scaled_extent, upsample_bilinear, upsample_depth_aware,
+        },
+        rtdgi::RtdgiCandidates,
+        shadows::trace_sun_shadow_mask,
+        GbufferDepth,
+    },
+    world_renderer::{
+        ForwardPassData, GpuRectLight, PendingReferenceReadback, PreUpscalePassData,
+        RenderDebugMode, TemporalUpscaler, WorldRenderOutput, WorldRenderer,
     },
-    world_renderer::{RenderDebugMode, WorldRenderer},
 };
 use kajiya_backend::{ash::vk, vulkan::image::*};
 use kajiya_rg::{self as rg, GetOrCreateTemporal};
+use rust_shaders_shared::render_overrides::RenderOverrideFlags;
 
 impl WorldRenderer {
     pub(super) fn prepare_render_graph_standard(
         &mut self,
         rg: &mut rg::TemporalRenderGraph,
         frame_desc: &WorldFrameDesc,
-    ) -> rg::Handle<Image> {
+    ) -> WorldRenderOutput {
+        self.update_quality_tiers();
+
+        // A pass runs if it's enabled in the config, and disabled neither for this view
+        // nor for all of them.
+        let disabled_features = frame_desc.disabled_features | self.disabled_features;
+        let feature_enabled = |feature: u32| (disabled_features & feature) == 0;
+        let pass_enabled =
+            |config_enabled: bool, feature: u32| config_enabled && feature_enabled(feature);
+
         let tlas = if rg.device().ray_tracing_enabled() {
-            Some(self.prepare_top_level_acceleration(rg))
+            let tlas = self.prepare_top_level_acceleration(rg);
+            self.record_scene_ray_tracing_passes(rg, &tlas);
+            Some(tlas)
         } else {
             None
         };
@@ -35,11 +64,21 @@ impl WorldRenderer {
         let sky_cube = self
             .ibl
             .render(rg)
+            .map(|ibl| ibl.cube)
             .unwrap_or_else(|| crate::renderers::sky::render_sky_cube(rg).into());
 
         let convolved_sky_cube = crate::renderers::sky::convolve_cube(rg, &sky_cube);
 
-        let (gbuffer_depth, velocity_img) = {
+        // Shutter velocity is only rasterized when motion blur would read it.
+        let use_shutter_velocity = feature_enabled(RenderFeatureFlags::MOTION_BLUR)
+            && self
+                .instances
+                .iter()
+                .any(|inst| inst.shutter_transforms.is_some());
+
+        rg.begin_debug_group("gbuffer");
+
+        let (gbuffer_depth, velocity_img, shutter_velocity_img) = {
             let mut gbuffer_depth = {
                 let normal = rg.create(ImageDesc::new_2d(
                     vk::Format::A2R10G10B10_UNORM_PACK32,
@@ -57,6 +96,10 @@ impl WorldRenderer {
                 ));
                 rg::imageops::clear_depth(rg, &mut depth_img);
 
+                rg.set_name(&normal, "gbuffer.geometric_normal");
+                rg.set_name(&gbuffer, "gbuffer");
+                rg.set_name(&depth_img, "gbuffer.depth");
+
                 GbufferDepth::new(normal, gbuffer, depth_img)
             };
 
@@ -65,20 +108,45 @@ impl WorldRenderer {
                 frame_desc.render_extent,
             ));
 
+            rg.set_name(&velocity_img, "gbuffer.velocity");
+
+            let mut shutter_velocity_img = use_shutter_velocity.then(|| {
+                let img = rg.create(ImageDesc::new_2d(
+                    vk::Format::R16G16B16A16_SFLOAT,
+                    frame_desc.render_extent,
+                ));
+                rg.set_name(&img, "gbuffer.shutter_velocity");
+                img
+            });
+
             raster_meshes(
                 rg,
-                self.raster_simple_render_pass.clone(),
+                if use_shutter_velocity {
+                    self.raster_simple_shutter_render_pass.clone()
+                } else {
+                    self.raster_simple_render_pass.clone()
+                },
                 &mut gbuffer_depth,
                 &mut velocity_img,
+                shutter_velocity_img.as_mut(),
                 RasterMeshesData {
                     meshes: self.meshes.as_slice(),
                     instances: self.instances.as_slice(),
+                    instance_batches: self.instance_batches.as_slice(),
+                    instance_batch_transform_buffer: self
+                        .instance_batch_transform_buffer
+                        .lock()
+                        .clone(),
+                    instance_batch_transform_access: self.instance_batch_transforms_import_access(),
                     vertex_buffer: self.vertex_buffer.lock().clone(),
                     bindless_descriptor_set: self.bindless_descriptor_set,
+                    viewmodel_view_scale: self
+                        .viewmodel
+                        .view_space_scale(&frame_desc.camera_matrices.view_to_clip),
                 },
             );
 
-            (gbuffer_depth, velocity_img)
+            (gbuffer_depth, velocity_img, shutter_velocity_img)
         };
 
         let reprojection_map = crate::renderers::reprojection::calculate_reprojection_map(
@@ -86,15 +154,53 @@ impl WorldRenderer {
             &gbuffer_depth,
             &velocity_img,
         );
+        rg.set_name(&reprojection_map, "reprojection_map");
+
+        rg.end_debug_group();
+
+        let full_quality_gi = feature_enabled(RenderFeatureFlags::FULL_QUALITY_GI);
+
+        // Ray-traced AO isn't available without ray tracing; fall back to screen-space.
+        let ao_mode = if tlas.is_some() {
+            self.ambient_occlusion
+        } else {
+            AmbientOcclusionMode::Ssgi
+        };
+
+        let ssgi_tex = if pass_enabled(self.frame_graph_config.ssgi, RenderFeatureFlags::SSGI)
+            && full_quality_gi
+            && ao_mode.uses_ssgi()
+        {
+            self.ssgi.render(
+                rg,
+                &gbuffer_depth,
+                &reprojection_map,
+                &accum_img,
+                self.bindless_descriptor_set,
+            )
+        } else {
+            self.ssgi.create_unoccluded_output(rg, &gbuffer_depth)
+        };
+        //let ssgi_tex = rg.create(ImageDesc::new_2d(vk::Format::R8_UNORM, [1, 1]));
+
+        // Unlike SSGI, RTAO is kept with low-quality GI, as the cheaper of the two.
+        let rtao_tex = tlas.as_ref().filter(|_| ao_mode.uses_rtao()).map(|tlas| {
+            self.rtao.render(
+                rg,
+                &gbuffer_depth,
+                &reprojection_map,
+                tlas,
+                self.bindless_descriptor_set,
+            )
+        });
 
-        let ssgi_tex = self.ssgi.render(
+        let ao_tex = crate::renderers::ao::combine_ao(
             rg,
             &gbuffer_depth,
-            &reprojection_map,
-            &accum_img,
-            self.bindless_descriptor_set,
+            &ssgi_tex,
+            rtao_tex.as_deref(),
+            &velocity_img,
         );
-        //let ssgi_tex = rg.create(ImageDesc::new_2d(vk::Format::R8_UNORM, [1, 1]));
 
         let mut ircache_state = self.ircache.prepare(rg);
 
@@ -110,7 +216,28 @@ impl WorldRenderer {
             crate::renderers::wrc::allocate_dummy_output(rg)
         };
 
-        let traced_ircache = tlas.as_ref().map(|tlas| {
+        // Each of the ray-traced pass groups can be disabled independently. Their consumers
+        // then get neutral inputs: an unshadowed sun, and no indirect lighting.
+        let ircache_tlas = tlas.as_ref().filter(|_| {
+            self.frame_graph_config.ircache
+                && !self
+                    .render_overrides
+                    .has_flag(RenderOverrideFlags::NO_IRCACHE)
+        });
+        let shadow_tlas = tlas.as_ref().filter(|_| {
+            pass_enabled(
+                self.frame_graph_config.ray_traced_shadows,
+                RenderFeatureFlags::SUN_SHADOWS,
+            )
+        });
+        let rtdgi_tlas = tlas
+            .as_ref()
+            .filter(|_| pass_enabled(self.frame_graph_config.rtdgi, RenderFeatureFlags::RTDGI));
+        let rtr_tlas = tlas
+            .as_ref()
+            .filter(|_| pass_enabled(self.frame_graph_config.rtr, RenderFeatureFlags::RTR));
+
+        let traced_ircache = ircache_tlas.map(|tlas| {
             ircache_state.trace_irradiance(
                 rg,
                 &convolved_sky_cube,
@@ -120,41 +247,127 @@ impl WorldRenderer {
             )
         });
 
-        let sun_shadow_mask = if let Some(tlas) = tlas.as_ref() {
-            trace_sun_shadow_mask(rg, &gbuffer_depth, tlas, self.bindless_descriptor_set)
+        let sun_shadow_mask = if let Some(tlas) = shadow_tlas {
+            trace_sun_shadow_mask(
+                rg,
+                &gbuffer_depth,
+                tlas,
+                self.bindless_descriptor_set,
+                self.active_quality().shadows,
+            )
         } else {
-            rg.create(gbuffer_depth.depth.desc().format(vk::Format::R8_UNORM))
+            let mut unshadowed = rg.create(gbuffer_depth.depth.desc().format(vk::Format::R8_UNORM));
+            rg::imageops::clear_color(rg, &mut unshadowed, [1.0; 4]);
+            unshadowed
         };
 
-        let reprojected_rtdgi = self.rtdgi.reproject(rg, &reprojection_map);
+        let render_scale = self.frame_graph_config.render_scale;
 
-        let denoised_shadow_mask = if self.sun_size_multiplier > 0.0f32 {
-            self.shadow_denoise
-                .render(rg, &gbuffer_depth, &sun_shadow_mask, &reprojection_map)
-        } else {
-            sun_shadow_mask.into()
+        // Inputs of the pass groups rendering below the full resolution.
+        let rtdgi_scaled = scale_frame_inputs(
+            rg,
+            &gbuffer_depth,
+            &reprojection_map,
+            &ao_tex,
+            render_scale.rtdgi,
+        );
+        let rtr_scaled = scale_frame_inputs(
+            rg,
+            &gbuffer_depth,
+            &reprojection_map,
+            &ao_tex,
+            render_scale.rtr,
+        );
+
+        let (rtdgi_gbuffer_depth, rtdgi_reprojection_map, rtdgi_ao) = match &rtdgi_scaled {
+            Some(scaled) => (&scaled.gbuffer_depth, &scaled.reprojection_map, &scaled.ao),
+            None => (&gbuffer_depth, &reprojection_map, &*ao_tex),
+        };
+        let (rtr_gbuffer_depth, rtr_reprojection_map) = match &rtr_scaled {
+            Some(scaled) => (&scaled.gbuffer_depth, &scaled.reprojection_map),
+            None => (&gbuffer_depth, &reprojection_map),
         };
 
+        let reprojected_rtdgi = self.rtdgi.reproject(rg, rtdgi_reprojection_map);
+
+        let denoised_shadow_mask =
+            if shadow_tlas.is_some() && frame_desc.sun_size_multiplier > 0.0f32 {
+                // Penumbrae of a small sun are only a few pixels wide; the full filter would smear them.
+                let spatial_pass_count = if frame_desc.sun_size_multiplier < 0.25 {
+                    1
+                } else if frame_desc.sun_size_multiplier < 0.5 {
+                    2
+                } else {
+                    3
+                };
+
+                let denoised = self.denoisers.denoise(
+                    rg,
+                    &DenoiserInput {
+                        signal: DenoiserSignal::SunShadows,
+                        noisy: &sun_shadow_mask,
+                        gbuffer_depth: &gbuffer_depth,
+                        reprojection_map: &reprojection_map,
+                        hit_distance: None,
+                        bindless_descriptor_set: self.bindless_descriptor_set,
+                    },
+                );
+
+                match denoised {
+                    Some(denoised) => denoised.into(),
+                    None => self.shadow_denoise.render(
+                        rg,
+                        &gbuffer_depth,
+                        &sun_shadow_mask,
+                        &reprojection_map,
+                        spatial_pass_count,
+                    ),
+                }
+            } else {
+                sun_shadow_mask.into()
+            };
+
         if let Some(traced_ircache) = traced_ircache {
             ircache_state.sum_up_irradiance_for_sampling(rg, traced_ircache);
         }
 
+        let reflection_probes = self.reflection_probes.render(
+            rg,
+            &sky_cube,
+            &mut ircache_state,
+            rtr_tlas,
+            self.bindless_descriptor_set,
+        );
+
+        let environment_capture = self.environment_capture.render(
+            rg,
+            frame_desc.camera_matrices.eye_position(),
+            self.frame_idx,
+            &sky_cube,
+            &mut ircache_state,
+            tlas.as_ref(),
+            self.bindless_descriptor_set,
+        );
+
         let rtdgi_irradiance;
         let rtdgi_candidates;
 
-        if let Some(tlas) = tlas.as_ref() {
+        if let Some(tlas) = rtdgi_tlas {
             let rtdgi = self.rtdgi.render(
                 rg,
                 reprojected_rtdgi,
-                &gbuffer_depth,
-                &reprojection_map,
+                rtdgi_gbuffer_depth,
+                rtdgi_reprojection_map,
                 &convolved_sky_cube,
                 self.bindless_descriptor_set,
                 &mut ircache_state,
                 &wrc,
                 tlas,
-                &ssgi_tex,
+                rtdgi_ao,
+                &mut self.denoisers,
+                full_quality_gi,
             );
+
             rtdgi_irradiance = Some(rtdgi.screen_irradiance_tex);
             rtdgi_candidates = Some(rtdgi.candidates);
         } else {
@@ -166,17 +379,43 @@ impl WorldRenderer {
         let any_triangle_lights = self
             .instances
             .iter()
-            .any(|inst| !self.mesh_lights[inst.mesh.0].lights.is_empty());
+            .any(|inst| !self.instance_triangle_lights(inst).is_empty());
+
+        // Reflections sample diffuse GI at their own resolution, and can only
+        // reuse RTDGI's ray candidates when the two resolutions match.
+        let same_gi_scale =
+            rtdgi_gbuffer_depth.gbuffer.desc().extent == rtr_gbuffer_depth.gbuffer.desc().extent;
+
+        let mut rtr = if let Some(tlas) = rtr_tlas {
+            let resampled_rtdgi_irradiance: rg::ReadOnlyHandle<Image>;
+            let (rtdgi_irradiance, rtdgi_candidates) =
+                match (rtdgi_irradiance.as_ref(), rtdgi_candidates) {
+                    (Some(rtdgi_irradiance), Some(rtdgi_candidates)) if same_gi_scale => {
+                        (rtdgi_irradiance, rtdgi_candidates)
+                    }
+                    (rtdgi_irradiance, _) => {
+                        resampled_rtdgi_irradiance = match rtdgi_irradiance {
+                            Some(rtdgi_irradiance) => upsample_depth_aware(
+                                rg,
+                                rtdgi_irradiance,
+                                &rtdgi_gbuffer_depth.depth,
+                                &rtr_gbuffer_depth.depth,
+                            )
+                            .into(),
+                            None => black_irradiance(rg, rtr_gbuffer_depth).into(),
+                        };
+
+                        (
+                            &resampled_rtdgi_irradiance,
+                            RtdgiCandidates::allocate(rg, rtr_gbuffer_depth.gbuffer.desc()),
+                        )
+                    }
+                };
 
-        let mut rtr = if let Some(((tlas, rtdgi_irradiance), rtdgi_candidates)) = tlas
-            .as_ref()
-            .zip(rtdgi_irradiance.as_ref())
-            .zip(rtdgi_candidates)
-        {
             self.rtr.trace(
                 rg,
-                &gbuffer_depth,
-                &reprojection_map,
+                rtr_gbuffer_depth,
+                rtr_reprojection_map,
                 &sky_cube,
                 self.bindless_descriptor_set,
                 tlas,
@@ -184,9 +423,10 @@ impl WorldRenderer {
                 rtdgi_candidates,
                 &mut ircache_state,
                 &wrc,
+                &reflection_probes,
             )
         } else {
-            self.rtr.create_dummy_output(rg, &gbuffer_depth)
+            self.rtr.create_dummy_output(rg, rtr_gbuffer_depth)
         };
 
         if any_triangle_lights {
@@ -195,14 +435,33 @@ impl WorldRenderer {
                 self.lighting.render_specular(
                     &mut rtr.resolved_tex,
                     rg,
-                    &gbuffer_depth,
+                    rtr_gbuffer_depth,
                     self.bindless_descriptor_set,
                     tlas,
                 );
             }
         }
 
-        let rtr = rtr.filter_temporal(rg, &gbuffer_depth, &reprojection_map);
+        let denoised_rtr = self.denoisers.denoise(
+            rg,
+            &DenoiserInput {
+                signal: DenoiserSignal::Reflections,
+                noisy: &rtr.resolved_tex,
+                gbuffer_depth: rtr_gbuffer_depth,
+                reprojection_map: rtr_reprojection_map,
+                hit_distance: Some(rtr.ray_len_tex()),
+                bindless_descriptor_set: self.bindless_descriptor_set,
+            },
+        );
+
+        let mut rtr = match denoised_rtr {
+            Some(denoised) => rtr.use_denoised(rg, denoised),
+            None => rtr.filter_temporal(rg, rtr_gbuffer_depth, rtr_reprojection_map),
+        };
+
+        if rtr_scaled.is_some() {
+            rtr = upsample_depth_aware(rg, &rtr, &rtr_gbuffer_depth.depth, &gbuffer_depth.depth);
+        }
 
         let mut debug_out_tex = rg.create(ImageDesc::new_2d(
             vk::Format::R16G16B16A16_SFLOAT,
@@ -210,12 +469,69 @@ impl WorldRenderer {
         ));
 
         let rtdgi = match rtdgi_irradiance {
+            Some(rtdgi) if rtdgi_scaled.is_some() => {
+                upsample_depth_aware(rg, &rtdgi, &rtdgi_gbuffer_depth.depth, &gbuffer_depth.depth)
+                    .into()
+            }
             Some(rtdgi) => rtdgi,
-            None => rg
-                .create(ImageDesc::new_2d(vk::Format::R8G8B8A8_UNORM, [1, 1]))
-                .into(),
+            None => black_irradiance(rg, &gbuffer_depth).into(),
+        };
+
+        let mut local_lights = match tlas.as_ref() {
+            Some(tlas)
+                if !self.point_lights.is_empty()
+                    || !self.translucent_emissives.gi_lights().is_empty() =>
+            {
+                self.lighting.render_point_lights(
+                    rg,
+                    &gbuffer_depth,
+                    self.bindless_descriptor_set,
+                    tlas,
+                )
+            }
+            _ => black_irradiance(rg, &gbuffer_depth),
         };
 
+        if let Some(tlas) = tlas.as_ref().filter(|_| !self.rect_lights.is_empty()) {
+            let rect_lights: Vec<GpuRectLight> = self
+                .rect_lights
+                .values()
+                .map(|light| GpuRectLight::new(light, self.world_units_per_meter))
+                .collect();
+
+            let rect_light_shadow_mask = self.lighting.trace_rect_light_shadow_mask(
+                rg,
+                &gbuffer_depth,
+                rect_lights.clone(),
+                self.bindless_descriptor_set,
+                tlas,
+            );
+            let rect_light_visibility = self.rect_light_shadow_denoise.render(
+                rg,
+                &gbuffer_depth,
+                &rect_light_shadow_mask,
+                &reprojection_map,
+                3,
+            );
+
+            self.lighting.render_rect_lights(
+                rg,
+                &gbuffer_depth,
+                &rect_light_visibility,
+                rect_lights,
+                self.bindless_descriptor_set,
+                &mut local_lights,
+            );
+        }
+
+        let sky_visibility = self.sky_visibility.render(
+            rg,
+            frame_desc.camera_matrices.eye_position(),
+            self.frame_idx,
+            tlas.as_ref(),
+            self.bindless_descriptor_set,
+        );
+
         light_gbuffer(
             rg,
             &gbuffer_depth,
@@ -228,42 +544,155 @@ impl WorldRenderer {
             &mut debug_out_tex,
             &sky_cube,
             &convolved_sky_cube,
+            &ao_tex,
+            &local_lights,
+            &sky_visibility,
             self.bindless_descriptor_set,
             self.debug_shading_mode,
             self.debug_show_wrc,
         );
 
-        #[allow(unused_mut)]
-        let mut anti_aliased = None;
+        let taa_enabled = feature_enabled(RenderFeatureFlags::TAA);
 
-        #[cfg(feature = "dlss")]
-        if self.use_dlss {
-            anti_aliased = Some(self.dlss.render(
-                rg,
-                &debug_out_tex,
-                &reprojection_map,
-                &gbuffer_depth.depth,
-                self.temporal_upscale_extent,
-            ));
+        // Starts out with the emissive surfaces of the gbuffer. Fog and application passes
+        // drawing before the upscaler update it along with the color.
+        let mut reactive_mask = taa_enabled.then(|| {
+            crate::renderers::taa::reactive_mask(rg, &gbuffer_depth.gbuffer, &debug_out_tex)
+        });
+
+        self.volumetric_fog.render(
+            rg,
+            &gbuffer_depth.depth,
+            &mut ircache_state,
+            &convolved_sky_cube,
+            self.bindless_descriptor_set,
+            tlas.as_ref()
+                .filter(|_| feature_enabled(RenderFeatureFlags::VOLUMETRICS)),
+            &mut debug_out_tex,
+            reactive_mask.as_mut(),
+        );
+
+        if !self.pre_upscale_passes.is_empty() {
+            let mut data = PreUpscalePassData {
+                color: &mut debug_out_tex,
+                depth: &gbuffer_depth.depth,
+                reactive_mask: reactive_mask.as_mut(),
+                pre_exposure: self.exposure_state().pre_mult,
+                bindless_descriptor_set: self.bindless_descriptor_set,
+            };
+
+            rg.begin_debug_group("app pre-upscale passes");
+            for pass in &mut self.pre_upscale_passes {
+                pass(rg, &mut data);
+            }
+            rg.end_debug_group();
         }
 
-        //let dof = crate::renderers::dof::dof(rg, &debug_out_tex, &gbuffer_depth.depth);
+        // While disabled, the upscaler is forgotten, so that its history is reset once it's back.
+        let switched_upscaler = self.prev_temporal_upscaler != Some(self.temporal_upscaler);
+        self.prev_temporal_upscaler = taa_enabled.then(|| self.temporal_upscaler);
+
+        let anti_aliased = if let Some(reactive_mask) = reactive_mask {
+            match self.temporal_upscaler {
+                TemporalUpscaler::Taa => {
+                    self.taa
+                        .render(
+                            rg,
+                            &debug_out_tex,
+                            &reprojection_map,
+                            &gbuffer_depth.depth,
+                            &reactive_mask,
+                            self.temporal_upscale_extent,
+                        )
+                        .this_frame_out
+                }
+                #[cfg(feature = "dlss")]
+                TemporalUpscaler::Dlss => {
+                    if switched_upscaler {
+                        self.dlss.reset();
+                    }
+
+                    self.dlss.render(
+                        rg,
+                        &debug_out_tex,
+                        &reprojection_map,
+                        &gbuffer_depth.depth,
+                        self.temporal_upscale_extent,
+                    )
+                }
+                #[cfg(feature = "fsr2")]
+                TemporalUpscaler::Fsr2 => {
+                    if switched_upscaler {
+                        self.fsr2.reset();
+                    }
+
+                    let transparency_mask =
+                        crate::renderers::fsr2::transparency_mask(rg, &gbuffer_depth.gbuffer);
+
+                    self.fsr2.render(
+                        rg,
+                        &debug_out_tex,
+                        &reprojection_map,
+                        &gbuffer_depth.depth,
+                        &reactive_mask,
+                        &transparency_mask,
+                        &frame_desc.camera_matrices,
+                        self.exposure_state().pre_mult,
+                        self.temporal_upscale_extent,
+                    )
+                }
+            }
+        } else if debug_out_tex.desc().extent_2d() == self.temporal_upscale_extent {
+            debug_out_tex
+        } else {
+            upsample_bilinear(rg, &debug_out_tex, self.temporal_upscale_extent)
+        };
 
-        let anti_aliased = anti_aliased.unwrap_or_else(|| {
-            self.taa
-                .render(
-                    rg,
-                    //&dof,
-                    &debug_out_tex,
-                    &reprojection_map,
-                    &gbuffer_depth.depth,
-                    self.temporal_upscale_extent,
-                )
-                .this_frame_out
-        });
+        let anti_aliased = if self.frame_graph_config.dof.enabled
+            && frame_desc.physical_camera.aperture_diameter > 0.0
+        {
+            crate::renderers::dof::dof(
+                rg,
+                &anti_aliased,
+                &gbuffer_depth.depth,
+                &self.frame_graph_config.dof,
+            )
+        } else {
+            anti_aliased
+        };
 
-        let mut final_post_input =
-            motion_blur(rg, &anti_aliased, &gbuffer_depth.depth, &reprojection_map);
+        let mut final_post_input = if !feature_enabled(RenderFeatureFlags::MOTION_BLUR) {
+            anti_aliased
+        } else if let Some(shutter_velocity_img) = &shutter_velocity_img {
+            let motion_blur_reprojection_map = shutter_reprojection_map(
+                rg,
+                &gbuffer_depth.depth,
+                shutter_velocity_img,
+                &reprojection_map,
+            );
+            motion_blur(
+                rg,
+                &anti_aliased,
+                &gbuffer_depth.depth,
+                &motion_blur_reprojection_map,
+            )
+        } else {
+            motion_blur(rg, &anti_aliased, &gbuffer_depth.depth, &reprojection_map)
+        };
+
+        let output_depth = if self.frame_graph_config.output_depth
+            || !self.forward_passes.is_empty()
+            || !self.gaussian_splats.is_empty()
+            || !self.translucent_emissives.is_empty()
+        {
+            Some(crate::renderers::composite::unjitter_depth(
+                rg,
+                &gbuffer_depth.depth,
+                final_post_input.desc().extent_2d(),
+            ))
+        } else {
+            None
+        };
 
         if let Some(tlas) = tlas.as_ref() {
             if matches!(self.debug_mode, RenderDebugMode::WorldRadianceCache) {
@@ -278,28 +707,106 @@ impl WorldRenderer {
             }
         }
 
+        let mut translucent_emissive = None;
+
+        if let Some(depth) = output_depth.as_ref() {
+            self.gaussian_splats
+                .render(rg, &mut final_post_input, depth);
+
+            if !self.translucent_emissives.is_empty() {
+                translucent_emissive = Some(self.translucent_emissives.render(
+                    rg,
+                    &mut final_post_input,
+                    depth,
+                ));
+            }
+
+            let mut data = ForwardPassData {
+                color: &mut final_post_input,
+                depth,
+                pre_exposure: self.exposure_state().pre_mult,
+                environment_capture: environment_capture.as_ref(),
+                ao: &ao_tex,
+                bindless_descriptor_set: self.bindless_descriptor_set,
+            };
+
+            rg.begin_debug_group("app forward passes");
+            for pass in &mut self.forward_passes {
+                pass(rg, &mut data);
+            }
+            rg.end_debug_group();
+        }
+
+        let output_depth = output_depth.filter(|_| self.frame_graph_config.output_depth);
+
+        if self.photo_mode.is_active() {
+            final_post_input =
+                self.photo_mode
+                    .accumulate(rg, &final_post_input, &frame_desc.camera_matrices);
+        }
+
+        rg.begin_debug_group("post");
         let post_processed = self.post.render(
             rg,
             &final_post_input,
             //&anti_aliased,
+            translucent_emissive.as_ref(),
             self.bindless_descriptor_set,
             self.exposure_state().post_mult,
             self.contrast,
-            self.dynamic_exposure.histogram_clipping,
+            &self.auto_exposure,
+            BloomConfig {
+                enabled: pass_enabled(
+                    self.frame_graph_config.bloom.enabled,
+                    RenderFeatureFlags::BLOOM,
+                ),
+                ..self.frame_graph_config.bloom
+            },
+            self.bloom_lens_dirt,
+            &self.color_grading,
+            &self.hdr_output,
+            self.frame_graph_config.debug_overlays,
+        );
+
+        let post_processed = if self.sharpness > 0.0 {
+            crate::renderers::post::contrast_adaptive_sharpening(
+                rg,
+                &post_processed,
+                self.sharpness,
+                &self.hdr_output,
+            )
+        } else {
+            post_processed
+        };
+        rg.end_debug_group();
+
+        let post_processed = self.motion_vectors.render(
+            rg,
+            &reprojection_map,
+            post_processed,
+            self.frame_graph_config.debug_overlays,
         );
 
-        rg.debugged_resource.take().unwrap_or(post_processed)
+        WorldRenderOutput {
+            color: rg.debugged_resource.take().unwrap_or(post_processed),
+            depth: output_depth,
+        }
     }
 
     pub(super) fn prepare_render_graph_reference(
         &mut self,
         rg: &mut rg::TemporalRenderGraph,
         frame_desc: &WorldFrameDesc,
-    ) -> rg::Handle<Image> {
+    ) -> WorldRenderOutput {
+        let accum_extent = scaled_extent(
+            frame_desc.render_extent,
+            self.reference_preview_scale.clamp(0.125, 1.0),
+        );
+
         let mut accum_img = rg
             .get_or_create_temporal(
                 "refpt.accum",
-                ImageDesc::new_2d(vk::Format::R32G32B32A32_SFLOAT, frame_desc.render_extent).usage(
+                ImageDesc::new_2d(vk::Format::R32G32B32A32_SFLOAT, accum_extent).usage(
                     vk::ImageUsageFlags::SAMPLED
                         | vk::ImageUsageFlags::STORAGE
                         | vk::ImageUsageFlags::TRANSFER_DST,
@@ -307,25 +814,175 @@ impl WorldRenderer {
             )
             .unwrap();
 
-        if self.reset_reference_accumulation {
+        let aov_desc = ImageDesc::new_2d(vk::Format::R32G32B32A32_SFLOAT, accum_extent).usage(
+            vk::ImageUsageFlags::SAMPLED
+                | vk::ImageUsageFlags::STORAGE
+                | vk::ImageUsageFlags::TRANSFER_DST,
+        );
+        let mut aovs = ReferenceAovs {
+            albedo: rg.get_or_create_temporal("refpt.albedo", aov_desc).unwrap(),
+            normal: rg.get_or_create_temporal("refpt.normal", aov_desc).unwrap(),
+        };
+
+        // The accumulation image gets re-created with undefined contents when its size changes.
+        if accum_extent != self.reference_accum_extent {
+            self.reference_accum_extent = accum_extent;
+            self.reset_reference_accumulation = true;
+        }
+
+        if self
+            .reference_config
+            .invalidates_accumulation(&self.reference_accum_config)
+        {
+            self.reference_accum_config = self.reference_config;
+            self.reset_reference_accumulation = true;
+        }
+
+        let restore = self.pending_reference_restore.take().filter(|state| {
+            let matches = state.extent == accum_extent;
+            if !matches {
+                log::error!(
+                    "Can't resume the reference state: it's {:?}, but the accumulation is {:?}",
+                    state.extent,
+                    accum_extent
+                );
+            }
+            matches
+        });
+
+        if let Some(state) = restore {
+            self.reset_reference_accumulation = false;
+            self.reference_sample_count = state.sample_count;
+            #[cfg(feature = "oidn")]
+            self.oidn.invalidate();
+            restore_reference(
+                rg,
+                &state,
+                &mut accum_img,
+                &mut aovs,
+                self.exposure_state().pre_mult,
+            );
+        } else if self.reset_reference_accumulation {
             self.reset_reference_accumulation = false;
+            self.reference_sample_count = 0;
+            #[cfg(feature = "oidn")]
+            self.oidn.invalidate();
             rg::imageops::clear_color(rg, &mut accum_img, [0.0, 0.0, 0.0, 0.0]);
+            rg::imageops::clear_color(rg, &mut aovs.albedo, [0.0, 0.0, 0.0, 0.0]);
+            rg::imageops::clear_color(rg, &mut aovs.normal, [0.0, 0.0, 0.0, 0.0]);
         }
 
         if rg.device().ray_tracing_enabled() {
             let tlas = self.prepare_top_level_acceleration(rg);
+            self.record_scene_ray_tracing_passes(rg, &tlas);
+
+            if !self.is_reference_complete() {
+                let ibl = self.ibl.render(rg);
 
-            reference_path_trace(rg, &mut accum_img, self.bindless_descriptor_set, &tlas);
+                reference_path_trace(
+                    rg,
+                    &mut accum_img,
+                    &mut aovs,
+                    &self.reference_config,
+                    self.reference_sample_count,
+                    ibl.as_ref(),
+                    self.bindless_descriptor_set,
+                    &tlas,
+                );
+
+                self.reference_sample_count += 1;
+            }
         }
 
-        self.post.render(
+        #[cfg(feature = "oidn")]
+        let for_denoiser = self
+            .oidn
+            .wants_readback(self.reference_sample_count, self.is_reference_complete());
+        #[cfg(not(feature = "oidn"))]
+        let for_denoiser = false;
+
+        // A frame which fails to prepare is never retired, so the request stays until one is.
+        if self.reference_readback_requested
+            || self.reference_save_request.is_some()
+            || for_denoiser
+        {
+            let buffer =
+                read_back_reference(rg, &accum_img, &aovs, 1.0 / self.exposure_state().pre_mult);
+            self.pending_reference_readback = Some(PendingReferenceReadback {
+                buffer,
+                extent: accum_extent,
+                sample_count: self.reference_sample_count,
+                for_application: self.reference_readback_requested,
+                save_to: self.reference_save_request.clone(),
+                #[cfg(feature = "oidn")]
+                for_denoiser,
+            });
+        }
+
+        #[cfg(feature = "oidn")]
+        let accum_img = self
+            .oidn
+            .render(rg, self.exposure_state().pre_mult)
+            .unwrap_or(accum_img);
+
+        let accum_img = if accum_extent != frame_desc.render_extent {
+            upsample_reference_preview(
+                rg,
+                &accum_img,
+                frame_desc.render_extent,
+                self.reference_preview_filter,
+            )
+        } else {
+            accum_img
+        };
+
+        let output_depth = self.frame_graph_config.output_depth.then(|| {
+            // The path tracer has no depth buffer; everything composites as if over the sky.
+            let mut output_depth = rg.create(
+                accum_img
+                    .desc()
+                    .usage(vk::ImageUsageFlags::empty())
+                    .format(vk::Format::R32_SFLOAT),
+            );
+            rg::imageops::clear_color(rg, &mut output_depth, [0.0; 4]);
+            output_depth
+        });
+
+        let color = self.post.render(
             rg,
             &accum_img,
             //&accum_img, // hack
+            None,
             self.bindless_descriptor_set,
             self.exposure_state().post_mult,
             self.contrast,
-            self.dynamic_exposure.histogram_clipping,
-        )
+            &self.auto_exposure,
+            self.frame_graph_config.bloom,
+            self.bloom_lens_dirt,
+            &self.color_grading,
+            &self.hdr_output,
+            self.frame_graph_config.debug_overlays,
+        );
+
+        WorldRenderOutput {
+            color,
+            depth: output_depth,
+        }
     }
 }
+
+/// Stands in for the diffuse GI of disabled or unavailable RTDGI, and for absent point lights.
+fn black_irradiance(
+    rg: &mut rg::TemporalRenderGraph,
+    gbuffer_depth: &GbufferDepth,
+) -> rg::Handle<Image> {
+    let mut irradiance = rg.create(
+        gbuffer_depth
+            .gbuffer
+            .desc()
+            .usage(vk::ImageUsageFlags::empty())
+            .format(vk::Format::R16G16B16A16_SFLOAT),
+    );
+    rg::imageops::clear_color(rg, &mut irradiance, [0.0; 4]);
+    irradiance
+}
@@ -1,45 +1,112 @@
 use crate::{
+    auto_quality::AutoQualityState,
     bindless_descriptor_set::{
         create_bindless_descriptor_set, BINDLESS_DESCRIPTOR_SET_LAYOUT,
         BINDLESS_TEXURES_BINDING_INDEX,
     },
     buffer_builder::BufferBuilder,
+    crowd::{Crowd, CrowdHandle},
+    custom_hit_groups::{CustomHitGroup, HitGroupHandle, DEFAULT_ANY_HIT},
     frame_desc::WorldFrameDesc,
+    frame_graph_config::FrameGraphConfig,
     image_lut::{ComputeImageLut, ImageLut},
+    instance_aggregation::{
+        InstanceAggregate, InstanceAggregationSettings, PendingAggregateRelease,
+    },
+    range_allocator::RangeAllocator,
     renderers::{
-        ibl::IblRenderer, ircache::IrcacheRenderer, lighting::LightingRenderer,
-        post::PostProcessRenderer, raster_meshes::*, rtdgi::RtdgiRenderer, rtr::*,
-        shadow_denoise::ShadowDenoiseRenderer, ssgi::*, taa::TaaRenderer,
+        ao::AmbientOcclusionMode,
+        color_grading::{ColorGrading, ColorGradingLut, ColorGradingLutSource},
+        denoiser::Denoisers,
+        environment_capture::{EnvironmentCaptureOutput, EnvironmentCaptureRenderer},
+        gaussian_splats::{GaussianSplatHandle, GaussianSplatRenderer},
+        ibl::IblRenderer,
+        image_metrics::ImageMetricsRenderer,
+        ircache::IrcacheRenderer,
+        lighting::LightingRenderer,
+        mesh_update,
+        motion_vectors::MotionVectorDebugRenderer,
+        photo_mode::PhotoModeRenderer,
+        post::PostProcessRenderer,
+        raster_meshes::*,
+        ray_queries::RayQueryRenderer,
+        reference::{
+            unpack_reference_readback, ReferencePathTraceConfig, ReferencePreviewFilter,
+            ReferenceReadback,
+        },
+        reflection_probes::ReflectionProbeRenderer,
+        rtao::RtaoRenderer,
+        rtdgi::RtdgiRenderer,
+        rtr::*,
+        shadow_denoise::ShadowDenoiseRenderer,
+        skinning::{self, SkinnedVertexOffsets},
+        sky_visibility::SkyVisibilityRenderer,
+        ssgi::*,
+        taa::TaaRenderer,
+        thumbnail::{ThumbnailHandle, ThumbnailImage, ThumbnailRenderer},
+        translucent_emissives::TranslucentEmissiveRenderer,
+        volumetric_fog::VolumetricFogRenderer,
+    },
+    units,
+    video_texture::{VideoTexture, VideoTextureHandle},
+    wind::{Wind, WindState},
+};
+use glam::{Affine3A, Mat4, Quat, Vec2, Vec3};
+use kajiya_asset::{
+    gaussian_splats::GaussianSplats,
+    mesh::{
+        material_variant_id, AssetRef, GpuImage, MaterialCategory, MeshGeometryScale, MeshMaterial,
+        MeshMaterialFlags, MeshMaterialVariant, PackedTriMesh, PackedVertex,
     },
 };
-use glam::{Affine3A, Vec2, Vec3};
-use kajiya_asset::mesh::{AssetRef, GpuImage, MeshMaterialFlags, PackedTriMesh, PackedVertex};
 use kajiya_backend::{
     ash::vk::{self, ImageView},
-    dynamic_constants::DynamicConstants,
+    dynamic_constants::{DynamicConstants, MAX_DYNAMIC_CONSTANTS_STORAGE_BUFFER_BYTES},
+    gpu_profiler,
     vk_sync::{self, AccessType},
-    vulkan::{self, device, image::*, ray_tracing::*, shader::*, RenderBackend},
+    vulkan::{
+        self, device, image::*, ray_tracing::*, shader::*, swapchain::SwapchainColorSpace,
+        RenderBackend,
+    },
     BackendError,
 };
-use kajiya_rg::{self as rg};
+use kajiya_rg::{self as rg, GetOrCreateTemporal};
 #[allow(unused_imports)]
 use log::{debug, error, info, trace, warn};
 use parking_lot::Mutex;
 use rg::renderer::FrameConstantsLayout;
 use rust_shaders_shared::{
     camera::CameraMatrices,
-    frame_constants::{FrameConstants, IrcacheCascadeConstants, IRCACHE_CASCADE_COUNT},
-    render_overrides::RenderOverrides,
+    frame_constants::{
+        FrameConstants, IrcacheCascadeConstants, MaterialCategoryConstants, IRCACHE_CASCADE_COUNT,
+    },
+    render_overrides::{RenderOverrideFlags, RenderOverrides},
     view_constants::ViewConstants,
 };
-use std::{collections::HashMap, mem::size_of, sync::Arc};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    mem::size_of,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use vulkan::buffer::{Buffer, BufferDesc};
 
 const USE_TAA_JITTER: bool = true;
 
+// Sun angular speed (degrees per second) at which GI temporal accumulation is halved.
+// Keeps indirect lighting from lagging behind a moving sun, e.g. with time of day.
+const GI_HISTORY_HALVING_SUN_SPEED_DEG: f32 = 1.0;
+
 #[cfg(feature = "dlss")]
 use crate::renderers::dlss::DlssRenderer;
 
+#[cfg(feature = "fsr2")]
+use crate::renderers::fsr2::Fsr2Renderer;
+
+#[cfg(feature = "oidn")]
+use crate::renderers::oidn_denoise::OidnDenoiser;
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 struct GpuMesh {
@@ -49,8 +116,16 @@ struct GpuMesh {
     vertex_aux_offset: u32,
     vertex_tangent_offset: u32,
 
-    mat_data_offset: u32,
+    // Index of the first material of the mesh in `material_buffer`
+    material_offset: u32,
     index_offset: u32,
+
+    // Offset of rays leaving the surface, in mesh units; see `MeshGeometryScale::ray_bias`
+    ray_bias: f32,
+
+    // Triangles in the opaque BLAS geometry, which come first in the index buffer;
+    // see `mesh_rt_geometries`
+    opaque_triangle_count: u32,
 }
 
 #[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
@@ -59,6 +134,64 @@ pub struct MeshHandle(pub usize);
 #[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
 pub struct InstanceHandle(pub usize);
 
+/// A material of a mesh, shared by all instances of it.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub struct MaterialHandle {
+    pub mesh: MeshHandle,
+
+    /// Index into the mesh's materials
+    pub material_index: u32,
+}
+
+/// Thin-surface diffuse transmission of a material, for leaves, paper, lamp shades and such.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MaterialTransmission {
+    /// Fraction of diffuse light arriving at the back of the surface which passes through
+    /// to the front, tinted by the albedo. Zero makes the material opaque.
+    pub factor: f32,
+
+    /// Thickness in the red channel, with zero being thin, and one blocking transmission.
+    /// Uses the UV transform and sampler of the albedo map. `None` keeps the mesh's own map.
+    pub thickness_map: Option<BindlessImageHandle>,
+}
+
+/// Look of all materials of a `MaterialCategory`, tuned at runtime without editing
+/// the materials themselves; see `WorldRenderer::material_categories`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MaterialCategorySettings {
+    /// Scales the diffuse GI of on-screen surfaces, including GI transmitted
+    /// from their back side.
+    pub gi_strength: f32,
+
+    /// Scales the thin-surface transmission of the materials (see `MaterialTransmission`),
+    /// which is how light bleeds through skin, leaves and fabric here. Applies wherever
+    /// the surfaces are shaded, including in GI and reflections.
+    pub transmission_scale: f32,
+
+    /// Strength of a grazing-angle sheen tinted by the albedo, like that of velvet or
+    /// peach fuzz. Only applied to on-screen surfaces. Zero disables it.
+    pub sheen: f32,
+}
+
+impl Default for MaterialCategorySettings {
+    fn default() -> Self {
+        Self {
+            gi_strength: 1.0,
+            transmission_scale: 1.0,
+            sheen: 0.0,
+        }
+    }
+}
+
+impl MaterialHandle {
+    pub fn new(mesh: MeshHandle, material_index: u32) -> Self {
+        Self {
+            mesh,
+            material_index,
+        }
+    }
+}
+
 impl InstanceHandle {
     pub const INVALID: InstanceHandle = InstanceHandle(!0);
 
@@ -73,764 +206,3642 @@ impl Default for InstanceHandle {
     }
 }
 
-const MAX_GPU_MESHES: usize = 1024;
-const VERTEX_BUFFER_CAPACITY: usize = 1024 * 1024 * 1024;
-const TLAS_PREALLOCATE_BYTES: usize = 1024 * 1024 * 32;
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub struct InstanceBatchHandle(pub usize);
 
-#[derive(Clone, Copy)]
-pub struct InstanceDynamicParameters {
-    pub emissive_multiplier: f32,
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub struct LightHandle(pub usize);
+
+/// A punctual light for lamps, torches and such, which don't have emissive geometry.
+/// It's shadowed with ray tracing, and contributes to diffuse GI and reflections,
+/// so it needs ray tracing support to have any effect.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PointLight {
+    pub position: Vec3,
+
+    /// Linear RGB; multiplied by `intensity`
+    pub color: Vec3,
+
+    /// In the units given by `unit`; by default, irradiance at a distance of one scene unit.
+    /// It falls off with the square of the distance.
+    pub intensity: f32,
+
+    pub unit: PointLightUnit,
+
+    /// Radius of the light's sphere. Larger values soften the shadows. Irradiance is also
+    /// clamped within it, so that surfaces very close to the light don't blow out.
+    pub radius: f32,
+
+    /// Restricts the light to a cone, making it a spot light.
+    pub spot: Option<SpotCone>,
+
+    /// Scales the light scattered by `WorldRenderer::volumetric_fog`, i.e. how visible
+    /// the light's cone or halo is in fog. Zero keeps it out of the fog, which saves
+    /// a shadow ray per froxel.
+    pub volumetric_intensity: f32,
 }
 
-impl Default for InstanceDynamicParameters {
-    fn default() -> Self {
-        Self {
-            emissive_multiplier: 1.0,
+/// What `PointLight::intensity` is measured in; see `crate::units`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PointLightUnit {
+    /// Irradiance at a distance of one scene unit
+    Renderer,
+
+    /// Luminous intensity
+    Candela,
+
+    /// Luminous power: emitted in all directions by point lights, and within the outer
+    /// angle of the cone by spot lights, so narrowing a spot makes it brighter.
+    Lumens,
+}
+
+impl PointLightUnit {
+    pub const ALL: [PointLightUnit; 3] = [Self::Renderer, Self::Candela, Self::Lumens];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Renderer => "Renderer",
+            Self::Candela => "Candela",
+            Self::Lumens => "Lumens",
         }
     }
 }
 
-#[derive(Clone, Copy)]
-pub struct MeshInstance {
-    pub transform: Affine3A,
-    pub prev_transform: Affine3A,
-    pub mesh: MeshHandle,
-    pub dynamic_parameters: InstanceDynamicParameters,
+impl Default for PointLightUnit {
+    fn default() -> Self {
+        Self::Renderer
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub enum RenderDebugMode {
-    None,
-    WorldRadianceCache,
-}
+/// Cone of a spot light. The intensity falls off smoothly between the two angles.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SpotCone {
+    /// Direction the light shines in; needn't be normalized.
+    pub direction: Vec3,
 
-#[derive(Clone, Copy)]
-#[repr(C)]
-pub struct TriangleLight {
-    pub verts: [[f32; 3]; 3],
-    pub radiance: [f32; 3],
+    /// Half-angle of the cone, in radians, within which the light is at full intensity
+    pub inner_angle: f32,
+
+    /// Half-angle of the cone, in radians, beyond which there is no light
+    pub outer_angle: f32,
 }
 
-impl TriangleLight {
-    pub fn transform(
-        self,
-        translation: Vec3,
-        rotation: impl core::ops::Mul<Vec3, Output = Vec3> + Copy,
-    ) -> Self {
+impl Default for PointLight {
+    fn default() -> Self {
         Self {
-            verts: [
-                (rotation * Vec3::from(self.verts[0]) + translation).into(),
-                (rotation * Vec3::from(self.verts[1]) + translation).into(),
-                (rotation * Vec3::from(self.verts[2]) + translation).into(),
-            ],
-            radiance: self.radiance,
+            position: Vec3::ZERO,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            unit: PointLightUnit::Renderer,
+            radius: 0.05,
+            spot: None,
+            volumetric_intensity: 1.0,
         }
     }
+}
 
-    pub fn scale_radiance(self, scale: Vec3) -> Self {
-        Self {
-            verts: self.verts,
-            radiance: (Vec3::from(self.radiance) * scale).into(),
-        }
+impl PointLight {
+    /// `intensity` in renderer units: irradiance at a distance of one scene unit.
+    pub fn renderer_intensity(&self, world_units_per_meter: f32) -> f32 {
+        let candela = match self.unit {
+            PointLightUnit::Renderer => return self.intensity,
+            PointLightUnit::Candela => self.intensity,
+            PointLightUnit::Lumens => match self.spot {
+                Some(spot) => units::spot_lumens_to_candela(self.intensity, spot.outer_angle),
+                None => units::isotropic_lumens_to_candela(self.intensity),
+            },
+        };
+
+        units::candela(candela, world_units_per_meter)
     }
-}
 
-pub struct MeshLightSet {
-    pub lights: Vec<TriangleLight>,
+    fn peak_intensity(&self, world_units_per_meter: f32) -> f32 {
+        (self.color * self.renderer_intensity(world_units_per_meter)).max_element()
+    }
 }
 
-pub struct WorldRenderer {
-    device: Arc<device::Device>,
-
-    pub(super) raster_simple_render_pass: Arc<RenderPass>,
-    pub(super) bindless_descriptor_set: vk::DescriptorSet,
-    pub(super) meshes: Vec<UploadedTriMesh>,
+// Must match `PointLightPacked` in `lights/packed.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuPointLight {
+    position_radius: [f32; 4],
 
-    pub(super) mesh_lights: Vec<MeshLightSet>,
+    // Cosine of the spot cone's outer angle in `w`; below -1 without a cone
+    intensity_spot_cos_outer: [f32; 4],
 
-    // ----
-    // SoA
-    pub(super) instances: Vec<MeshInstance>,
-    pub(super) instance_handles: Vec<InstanceHandle>,
-    // ----
+    // Cosine of the spot cone's inner angle in `w`
+    spot_direction_cos_inner: [f32; 4],
 
-    // The `usize` indexes into `instances` and `instance_handles`
-    pub(super) instance_handle_to_index: HashMap<InstanceHandle, usize>,
+    // `PointLight::volumetric_intensity` in `x`, and the culling range in `y`
+    volumetric_intensity_range: [f32; 4],
+}
 
-    pub(super) vertex_buffer: Mutex<Arc<Buffer>>,
-    vertex_buffer_written: u64,
+impl GpuPointLight {
+    // `cutoff` is the irradiance below which the light is culled; zero never culls it.
+    fn new(light: &PointLight, world_units_per_meter: f32, cutoff: f32) -> Self {
+        let (spot_direction, cos_inner, cos_outer) = if let Some(spot) = light.spot {
+            let cos_outer = spot.outer_angle.cos();
 
-    mesh_buffer: Mutex<Arc<Buffer>>,
+            // Keep the falloff well-defined for hard-edged cones.
+            let cos_inner = spot
+                .inner_angle
+                .min(spot.outer_angle)
+                .cos()
+                .max(cos_outer + 1e-4);
 
-    mesh_blas: Vec<Arc<RayTracingAcceleration>>,
-    tlas: Option<Arc<RayTracingAcceleration>>,
-    accel_scratch: RayTracingAccelerationScratchBuffer,
+            (spot.direction.normalize_or_zero(), cos_inner, cos_outer)
+        } else {
+            (Vec3::ZERO, -2.0, -2.0)
+        };
 
-    bindless_images: Vec<Arc<Image>>,
-    next_bindless_image_id: usize,
-    next_instance_handle: usize,
-    bindless_texture_sizes: Buffer,
+        let intensity = light.color * light.renderer_intensity(world_units_per_meter);
 
-    image_luts: Vec<ImageLut>,
-    frame_idx: u32,
-    prev_camera_matrices: Option<CameraMatrices>,
-    pub(crate) temporal_upscale_extent: [u32; 2],
+        let range = if cutoff > 0.0 {
+            light.radius + (light.peak_intensity(world_units_per_meter) / cutoff).sqrt()
+        } else {
+            f32::MAX
+        };
 
-    supersample_offsets: Vec<Vec2>,
+        Self {
+            position_radius: light.position.extend(light.radius).into(),
+            intensity_spot_cos_outer: intensity.extend(cos_outer).into(),
+            spot_direction_cos_inner: spot_direction.extend(cos_inner).into(),
+            volumetric_intensity_range: [light.volumetric_intensity.max(0.0), range, 0.0, 0.0],
+        }
+    }
+}
 
-    pub rg_debug_hook: Option<rg::GraphDebugHook>,
-    pub render_mode: RenderMode,
-    pub reset_reference_accumulation: bool,
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+pub struct RectLightHandle(pub usize);
+
+/// A rectangular area light, for windows, screens, softboxes and light panels.
+/// It lies in the local XY plane, and emits from its front, towards local +Z.
+///
+/// Shaded analytically, with stochastic ray-traced shadows run through a denoiser;
+/// it needs ray tracing support to have any effect. Unlike point lights, it only lights
+/// surfaces directly, and doesn't contribute to GI or reflections.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RectLight {
+    /// Center of the rectangle
+    pub position: Vec3,
+    pub rotation: Quat,
+
+    /// Width along local X, and height along local Y
+    pub extent: Vec2,
+
+    /// Linear RGB; multiplied by `intensity`
+    pub color: Vec3,
+
+    /// Emitted radiance, in the units given by `unit`
+    pub intensity: f32,
+
+    pub unit: RectLightUnit,
+
+    /// Multiplies the emission, with UVs running along local +X and -Y from the top left corner.
+    /// Should have mips, which stand in for the blur of rough reflections and diffuse shading.
+    pub emission_map: Option<BindlessImageHandle>,
+}
 
-    pub post: PostProcessRenderer,
-    pub ssgi: SsgiRenderer,
-    pub rtr: RtrRenderer,
-    pub lighting: LightingRenderer,
-    pub ircache: IrcacheRenderer,
-    pub rtdgi: RtdgiRenderer,
-    pub taa: TaaRenderer,
-    pub shadow_denoise: ShadowDenoiseRenderer,
-    pub ibl: IblRenderer,
+/// What `RectLight::intensity` is measured in; see `crate::units`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RectLightUnit {
+    /// Radiance, as used by emissive materials
+    Renderer,
 
-    #[cfg(feature = "dlss")]
-    pub dlss: DlssRenderer,
-    #[cfg(feature = "dlss")]
-    pub use_dlss: bool,
+    /// Luminance, e.g. of a screen
+    Nits,
 
-    pub debug_mode: RenderDebugMode,
-    pub debug_shading_mode: usize,
-    pub debug_show_wrc: bool,
-    pub ev_shift: f32,
-    pub dynamic_exposure: DynamicExposureState,
-    pub contrast: f32,
+    /// Luminous power, emitted from the front side, spread over the area of the light
+    Lumens,
+}
 
-    pub sun_size_multiplier: f32,
-    pub sun_color_multiplier: Vec3,
-    pub sky_ambient: Vec3,
+impl RectLightUnit {
+    pub const ALL: [RectLightUnit; 3] = [Self::Renderer, Self::Nits, Self::Lumens];
 
-    pub render_overrides: RenderOverrides,
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Renderer => "Renderer",
+            Self::Nits => "Nits",
+            Self::Lumens => "Lumens",
+        }
+    }
+}
 
-    // One for each render mode
-    pub(crate) exposure_state: [ExposureState; 2],
+impl Default for RectLightUnit {
+    fn default() -> Self {
+        Self::Renderer
+    }
 }
 
-#[derive(Default, Clone, Copy)]
-pub struct HistogramClipping {
-    pub low: f32,
-    pub high: f32,
+impl Default for RectLight {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            extent: Vec2::ONE,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            unit: RectLightUnit::Renderer,
+            emission_map: None,
+        }
+    }
 }
 
-#[derive(Default)]
-pub struct DynamicExposureState {
-    pub enabled: bool,
-    pub speed_log2: f32,
-    pub histogram_clipping: HistogramClipping,
+impl RectLight {
+    /// `intensity` in renderer units: radiance.
+    pub fn renderer_intensity(&self, world_units_per_meter: f32) -> f32 {
+        match self.unit {
+            RectLightUnit::Renderer => self.intensity,
+            RectLightUnit::Nits => units::nits(self.intensity),
+            RectLightUnit::Lumens => {
+                let area =
+                    self.extent.x * self.extent.y / (world_units_per_meter * world_units_per_meter);
+                units::nits(units::lambertian_lumens_to_nits(self.intensity, area))
+            }
+        }
+    }
+}
 
-    ev_fast: f32,
-    ev_slow: f32,
+// Must match `RectLightPacked` in `lights/packed.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct GpuRectLight {
+    // Bindless index of the emission map in `w`, or all ones without one
+    center_emission_map: [f32; 4],
+    half_extent_x: [f32; 4],
+    half_extent_y: [f32; 4],
+    radiance: [f32; 4],
 }
 
-const DYNAMIC_EXPOSURE_BIAS: f32 = -2.0;
+impl GpuRectLight {
+    pub(crate) fn new(light: &RectLight, world_units_per_meter: f32) -> Self {
+        let emission_map = light.emission_map.map_or(u32::MAX, |map| map.0);
+        let half_extent_x = light.rotation * Vec3::X * (light.extent.x * 0.5);
+        let half_extent_y = light.rotation * Vec3::Y * (light.extent.y * 0.5);
 
-impl DynamicExposureState {
-    pub fn ev_smoothed(&self) -> f32 {
-        if self.enabled {
-            (self.ev_slow + self.ev_fast) * 0.5 + DYNAMIC_EXPOSURE_BIAS
-        } else {
-            0.0
+        Self {
+            center_emission_map: light.position.extend(f32::from_bits(emission_map)).into(),
+            half_extent_x: half_extent_x.extend(0.0).into(),
+            half_extent_y: half_extent_y.extend(0.0).into(),
+            radiance: (light.color * light.renderer_intensity(world_units_per_meter))
+                .extend(0.0)
+                .into(),
         }
     }
+}
 
-    pub fn update(&mut self, ev: f32, dt: f32) {
-        if !self.enabled {
-            return;
-        }
+const MAX_GPU_MESHES: usize = 1024;
+const MAX_GPU_MATERIALS: usize = 16 * 1024;
+const VERTEX_BUFFER_CAPACITY: usize = 1024 * 1024 * 1024;
+// Enough for `MAX_INSTANCES` and `MAX_BATCHED_INSTANCES` together.
+const TLAS_PREALLOCATE_BYTES: usize = 1024 * 1024 * 64;
+
+// Mesh data in the vertex buffer is allocated in multiples of this.
+const VERTEX_BUFFER_ALIGNMENT: u64 = 16;
+
+// Frames a removed mesh waits before its memory is reused. The GPU may still be
+// working on two frames when it's removed, and graph preparation runs ahead of the wait.
+pub(super) const MESH_RELEASE_DELAY_FRAMES: u32 = 3;
+
+// Ray tracing instance masks; see `RT_INSTANCE_INCLUSION_MASK` in `rt.hlsl`.
+pub(super) const TLAS_MASK_SCENE: u8 = 0x7f;
+// Part of `TLAS_MASK_SCENE`; cleared for instances which don't contribute to diffuse GI.
+const TLAS_MASK_GI: u8 = 0x01;
+// Part of `TLAS_MASK_SCENE`; the only part kept by instances with a ray tracing proxy,
+// whose proxy gets the rest. Shadow rays leave it out, while other non-GI rays are limited to it.
+const TLAS_MASK_REFLECTION: u8 = 0x02;
+const TLAS_MASK_THUMBNAIL: u8 = 0x80;
+
+// What a slot of the TLAS instance buffer was last filled with.
+#[derive(Clone, Copy, PartialEq)]
+struct TlasInstanceSlot {
+    // Zero for inactive instances
+    blas_address: vk::DeviceAddress,
+    transform: Affine3A,
+    mesh_index: u32,
+    mask: u8,
+    hit_group_offset: u32,
+}
 
-        let ev = ev.clamp(-16.0, 16.0);
+struct PendingTlasInstanceSlots {
+    capacity: usize,
+    uploaded: Vec<(usize, TlasInstanceSlot)>,
 
-        let dt = dt * self.speed_log2.exp2();
+    // Slots written by compute shaders, rather than uploaded
+    gpu_written: std::ops::Range<usize>,
+}
 
-        let t_fast = 1.0 - (-1.0 * dt).exp();
-        self.ev_fast = (ev - self.ev_fast) * t_fast + self.ev_fast;
+// Shared by all instance batches. The transform buffers grow in powers of two
+// as batches are added, starting at `MIN_BATCHED_INSTANCE_CAPACITY`.
+const MAX_BATCHED_INSTANCES: usize = 256 * 1024;
+const MIN_BATCHED_INSTANCE_CAPACITY: usize = 1024;
 
-        let t_slow = 1.0 - (-0.25 * dt).exp();
-        self.ev_slow = (ev - self.ev_slow) * t_slow + self.ev_slow;
-    }
-}
+// Regular instances and instance batches together, as each has an entry in the dynamic
+// parameters uploaded to a dynamic constants storage buffer every frame. Batched instances
+// share the entry of their batch, so they don't count towards this.
+const MAX_INSTANCES: usize =
+    MAX_DYNAMIC_CONSTANTS_STORAGE_BUFFER_BYTES / size_of::<InstanceDynamicParameters>();
 
-#[derive(Clone, Copy)]
-pub struct ExposureState {
-    /// A value to multiply all lighting by in order to apply exposure compensation
-    /// early in the pipeline, such that lighting values fit in small texture formats.
-    pub pre_mult: f32,
+// Enough for one buffer being written on the CPU, and two frames in flight.
+const INSTANCE_BATCH_TRANSFORM_BUFFER_COUNT: usize = 3;
 
-    /// The remaining multiplier to apply in post.
-    pub post_mult: f32,
+// Must match `InstanceDynamicConstants` in `frame_constants.hlsl`.
+// Changes must bump `CONSTANTS_LAYOUT_VERSION`; see `constants_layout.rs`.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct InstanceDynamicParameters {
+    pub emissive_multiplier: f32,
 
-    // The pre-multiplier in the previous frame.
-    pub pre_mult_prev: f32,
+    /// Added to the mip level used when sampling material textures.
+    /// Negative values sharpen (e.g. scopes and magnifiers), positive ones blur.
+    pub mip_bias: f32,
 
-    // `pre_mult / pre_mult_prev`
-    pub pre_mult_delta: f32,
+    /// Upper bound on anisotropic filtering of material textures in the rasterizer.
+    /// Rounded down to a power of two in `1..=16`; 1 disables anisotropic filtering.
+    pub max_anisotropy: u32,
+
+    /// Combination of `InstanceFlags`
+    pub flags: u32,
+
+    /// Multiplier of the diffuse GI the instance's surfaces receive. 0 leaves them lit
+    /// by direct and emissive light, and reflections only.
+    pub gi_reception: f32,
 }
 
-impl Default for ExposureState {
+pub struct InstanceFlags;
+impl InstanceFlags {
+    /// Shade the instance as pure emission, e.g. for practical light fixtures. Material maps
+    /// other than emissive are skipped, and the emissive triangles are sampled as lights,
+    /// even if the mesh wasn't added with `AddMeshOptions::use_lights`.
+    pub const EMISSIVE_ONLY: u32 = 1;
+
+    /// First-person geometry such as weapons and hands, rasterized as configured by
+    /// `WorldRenderer::viewmodel`. It's lit by the world, including shadows and GI,
+    /// but is invisible to rays, so it doesn't shadow the world or show in reflections.
+    /// Batched instances ignore this flag, and the reference path tracer doesn't draw it.
+    pub const VIEWMODEL: u32 = 2;
+
+    /// Hide the instance from diffuse GI rays (rtdgi and the irradiance cache), so that it
+    /// neither bounces light nor occludes it. Meant for dynamic objects such as characters,
+    /// whose bounce and occlusion would otherwise linger in the cache behind them as they move.
+    /// The instance still casts direct shadows and shows in reflections.
+    /// Batched instances ignore this flag.
+    pub const NO_GI_CONTRIBUTION: u32 = 4;
+}
+
+/// Parts of material evaluation which can be compiled out of the raster and ray tracing
+/// shaders, for projects which don't use them, such as stylized ones. Disabled features
+/// also skip their vertex streams when meshes are added. See `WorldRenderer::set_material_features`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MaterialFeatures {
+    /// Per-vertex colors multiplying the base color, stored in the "aux" vertex stream
+    pub vertex_colors: bool,
+
+    /// Tangent-space normal maps, along with the tangent vertex stream
+    pub normal_maps: bool,
+}
+
+impl Default for MaterialFeatures {
     fn default() -> Self {
         Self {
-            pre_mult: 1.0,
-            post_mult: 1.0,
-            pre_mult_prev: 1.0,
-            pre_mult_delta: 1.0,
+            vertex_colors: true,
+            normal_maps: true,
         }
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-pub enum RenderMode {
-    Standard = 0,
-    Reference = 1,
+impl MaterialFeatures {
+    // Must match `inc/material_features.hlsl`
+    fn shader_defines(self) -> Vec<(String, String)> {
+        vec![
+            (
+                "MATERIAL_FEATURE_VERTEX_COLORS".to_owned(),
+                (self.vertex_colors as u32).to_string(),
+            ),
+            (
+                "MATERIAL_FEATURE_NORMAL_MAPS".to_owned(),
+                (self.normal_maps as u32).to_string(),
+            ),
+        ]
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
-pub struct BindlessImageHandle(pub u32);
+/// Rasterization of instances flagged with `InstanceFlags::VIEWMODEL`.
+#[derive(Clone, Copy)]
+pub struct ViewmodelSettings {
+    /// Vertical field of view in degrees; `None` uses the camera's.
+    pub vertical_fov: Option<f32>,
+
+    /// Scale of view-space positions. It pulls the geometry towards the camera without changing
+    /// its appearance, so that it stays in front of walls it would otherwise clip into.
+    /// Lighting is computed at the scaled positions. Must keep the geometry past the camera's
+    /// near plane.
+    pub depth_scale: f32,
+}
 
-fn load_gpu_image_asset(
-    device: Arc<kajiya_backend::Device>,
-    asset: AssetRef<GpuImage::Flat>,
-) -> Arc<Image> {
-    let asset = crate::mmap::mmapped_asset::<GpuImage::Flat, _>(&format!(
-        "/cache/{:8.8x}.image",
-        asset.identity()
-    ))
-    .unwrap();
+impl Default for ViewmodelSettings {
+    fn default() -> Self {
+        Self {
+            vertical_fov: None,
+            depth_scale: 0.1,
+        }
+    }
+}
 
-    let desc = ImageDesc::new_2d(asset.format, [asset.extent[0], asset.extent[1]])
-        .usage(vk::ImageUsageFlags::SAMPLED)
-        .mip_levels(asset.mips.len() as _);
+impl ViewmodelSettings {
+    // View-space scale applied to viewmodel vertices
+    pub(crate) fn view_space_scale(&self, view_to_clip: &Mat4) -> [f32; 3] {
+        let xy_scale = self.vertical_fov.map_or(1.0, |fov| {
+            let focal_length = 1.0 / (0.5 * fov.to_radians()).tan();
+            focal_length / view_to_clip.y_axis.y
+        });
 
-    let initial_data = asset
-        .mips
-        .iter()
-        .enumerate()
-        .map(|(mip_level, mip)| ImageSubResourceData {
-            data: mip.as_slice(),
-            row_pitch: ((desc.extent[0] as usize) >> mip_level).max(1) * 4,
-            slice_pitch: 0,
-        })
-        .collect::<Vec<_>>();
+        [
+            xy_scale * self.depth_scale,
+            xy_scale * self.depth_scale,
+            self.depth_scale,
+        ]
+    }
+}
 
-    Arc::new(device.create_image(desc, initial_data).unwrap())
+impl Default for InstanceDynamicParameters {
+    fn default() -> Self {
+        Self {
+            emissive_multiplier: 1.0,
+            mip_bias: 0.0,
+            max_anisotropy: 16,
+            flags: 0,
+            gi_reception: 1.0,
+        }
+    }
 }
 
-#[derive(Default)]
-pub struct AddMeshOptions {
-    pub use_lights: bool,
+#[derive(Clone, Copy)]
+pub struct MeshInstance {
+    pub transform: Affine3A,
+    pub prev_transform: Affine3A,
+    pub mesh: MeshHandle,
+
+    /// Simplified mesh which shadow and diffuse GI rays see in place of `mesh`.
+    /// The rasterizer and reflections always use `mesh`.
+    pub ray_tracing_proxy: Option<MeshHandle>,
+    pub dynamic_parameters: InstanceDynamicParameters,
+
+    /// Transforms at shutter open and close, used for motion blur instead of
+    /// `prev_transform` and `transform`. Reset every frame.
+    /// Instance batches and crowds don't support these, and blur along their frame-to-frame motion.
+    pub shutter_transforms: Option<[Affine3A; 2]>,
 }
 
-impl AddMeshOptions {
-    pub fn new() -> Self {
-        Self::default()
-    }
+/// Many instances of the same mesh, drawn with hardware instancing.
+///
+/// The transforms live in a GPU buffer shared by all batches, from which
+/// both the rasterizer and the TLAS build read them.
+#[derive(Clone, Copy)]
+pub struct InstanceBatch {
+    pub mesh: MeshHandle,
+    pub dynamic_parameters: InstanceDynamicParameters,
 
-    pub fn use_lights(mut self, v: bool) -> Self {
-        self.use_lights = v;
-        self
-    }
+    // Range in `instance_batch_transforms`
+    pub(crate) transform_offset: u32,
+    pub(crate) instance_count: u32,
+
+    // Whether the transforms have changed since the last frame,
+    // and the previous ones should be used for motion vectors.
+    pub(crate) moved: bool,
 }
 
-impl WorldRenderer {
-    pub(crate) fn new_empty(
-        // Internal render resolution, before any upsampling
-        #[allow(unused_variables)] render_extent: [u32; 2],
-        temporal_upscale_extent: [u32; 2],
-        backend: &RenderBackend,
-    ) -> Result<Self, BackendError> {
-        let raster_simple_render_pass = create_render_pass(
-            &backend.device,
-            RenderPassDesc {
-                color_attachments: &[
-                    // view-space geometry normal; * 2 - 1 to decode
-                    RenderPassAttachmentDesc::new(vk::Format::A2R10G10B10_UNORM_PACK32)
-                        .garbage_input(),
-                    // gbuffer
-                    RenderPassAttachmentDesc::new(vk::Format::R32G32B32A32_SFLOAT).garbage_input(),
-                    // velocity
-                    RenderPassAttachmentDesc::new(vk::Format::R16G16B16A16_SFLOAT).garbage_input(),
-                ],
-                depth_attachment: Some(RenderPassAttachmentDesc::new(vk::Format::D32_SFLOAT)),
-            },
-        );
+// Current and previous transform, as 3x4 row-major matrices.
+type GpuInstanceBatchTransform = [[f32; 12]; 2];
 
-        let mesh_buffer = backend.device.create_buffer(
-            BufferDesc::new_cpu_to_gpu(
-                MAX_GPU_MESHES * size_of::<GpuMesh>(),
-                vk::BufferUsageFlags::STORAGE_BUFFER,
-            ),
-            "mesh buffer",
-            None,
-        )?;
+struct InstanceBatchTransformBuffer {
+    buffer: Arc<Buffer>,
 
-        let vertex_buffer = backend.device.create_buffer(
-            BufferDesc::new_gpu_only(
-                VERTEX_BUFFER_CAPACITY,
-                vk::BufferUsageFlags::STORAGE_BUFFER
-                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
-                    | vk::BufferUsageFlags::INDEX_BUFFER
-                    | vk::BufferUsageFlags::TRANSFER_DST
-                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
-            ),
-            "vertex buffer",
-            None,
-        )?;
+    // `instance_batch_transforms_generation` of the contents
+    generation: u64,
 
-        let bindless_texture_sizes = backend
-            .device
-            .create_buffer(
-                BufferDesc::new_cpu_to_gpu(
-                    backend.device.max_bindless_descriptor_count() as usize
-                        * std::mem::size_of::<[f32; 4]>(),
-                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
-                ),
-                "bindless_texture_sizes",
-                None,
-            )
-            .unwrap();
+    // Value of the universal queue timeline once the GPU is done reading the buffer
+    gpu_done_timeline_value: u64,
+}
 
-        let bindless_descriptor_set = create_bindless_descriptor_set(backend.device.as_ref());
+/// Scene data for application-side ray tracing passes (sensors, audio rays, etc.)
+///
+/// Shaders should bind `tlas` as a `RaytracingAccelerationStructure`, and
+/// `bindless_descriptor_set` as descriptor set 1. The latter provides `meshes`,
+/// `vertices` and `bindless_textures`, as declared in `inc/bindless.hlsl`.
+///
+/// In hit shaders, `InstanceID()` is the index of the mesh (or its ray tracing proxy)
+/// in `meshes`, and `InstanceIndex()` maps back to the scene via
+/// `WorldRenderer::instance_from_tlas_index`.
+pub struct SceneRayTracingData<'a> {
+    pub tlas: &'a rg::Handle<RayTracingAcceleration>,
+    pub bindless_descriptor_set: vk::DescriptorSet,
+}
 
-        // `meshes`
-        Self::write_descriptor_set_buffer(
-            &backend.device.raw,
-            bindless_descriptor_set,
-            0,
-            &mesh_buffer,
-        );
+/// Records application passes into the render graph, after the TLAS has been rebuilt.
+/// Invoked every frame in which ray tracing is enabled.
+pub type SceneRayTracingPass =
+    Box<dyn FnMut(&mut rg::TemporalRenderGraph, &SceneRayTracingData) + 'static>;
 
-        // `vertices`
-        Self::write_descriptor_set_buffer(
-            &backend.device.raw,
-            bindless_descriptor_set,
-            1,
-            &vertex_buffer,
-        );
+/// The images of a frame, from `WorldRenderer::prepare_render_graph_with_depth`.
+/// Both belong to the graph being built, and are only valid within it.
+pub struct WorldRenderOutput {
+    /// What `WorldRenderer::prepare_render_graph` returns
+    pub color: rg::Handle<Image>,
 
-        // `bindless_texture_sizes`
-        Self::write_descriptor_set_buffer(
-            &backend.device.raw,
-            bindless_descriptor_set,
-            2,
-            &bindless_texture_sizes,
-        );
+    /// Jitter-free depth matching `color`, for compositing external content with
+    /// `renderers::composite`. Only with `FrameGraphConfig::output_depth` enabled.
+    pub depth: Option<rg::Handle<Image>>,
+}
 
-        let supersample_count = 128;
-        let supersample_offsets = (1..=supersample_count)
-            .map(|i| Vec2::new(radical_inverse(i, 2) - 0.5, radical_inverse(i, 3) - 0.5))
-            .collect();
-        //let supersample_offsets = vec![Vec2::new(0.0, -0.5), Vec2::new(0.0, 0.5)];
-        /*let supersample_offsets = vec![
-            Vec2::new(0.25, 0.25),
-            Vec2::new(0.25, -0.25),
-            Vec2::new(-0.25, 0.25),
-            Vec2::new(-0.25, -0.25),
-        ];*/
+/// Scene data for application passes drawing before temporal upscaling, e.g. particles
+/// or emissive decals which should be anti-aliased along with the scene.
+///
+/// Images are at the render resolution, and jittered like the rest of the frame.
+pub struct PreUpscalePassData<'a> {
+    /// Linear, pre-exposed scene radiance, with volumetric fog applied.
+    pub color: &'a mut rg::Handle<Image>,
 
-        let accel_scratch = backend
-            .device
-            .create_ray_tracing_acceleration_scratch_buffer()?;
+    /// Reverse-Z depth of the opaque scene.
+    pub depth: &'a rg::Handle<Image>,
 
-        #[cfg(feature = "dlss")]
-        let dlss = DlssRenderer::new(backend, render_extent, temporal_upscale_extent);
+    /// `R8_UNORM` fraction of each pixel's radiance which the temporal upscaler should take
+    /// from the current frame rather than clamp its history to; see `renderers::taa::reactive_mask`.
+    /// Passes drawing small emitters or transparent content should raise it where they draw,
+    /// so that their content doesn't flicker or ghost. `None` with TAA disabled.
+    pub reactive_mask: Option<&'a mut rg::Handle<Image>>,
 
-        Ok(Self {
-            raster_simple_render_pass,
+    /// Radiance written to `color` must be multiplied by this.
+    pub pre_exposure: f32,
 
-            reset_reference_accumulation: false,
-            //cube_index_buffer: Arc::new(cube_index_buffer),
-            device: backend.device.clone(),
-            meshes: Default::default(),
-            instances: Default::default(),
-            instance_handles: Default::default(),
-            instance_handle_to_index: Default::default(),
+    pub bindless_descriptor_set: vk::DescriptorSet,
+}
 
-            mesh_lights: Default::default(),
+/// Records application passes into the render graph, before the temporal upscaler.
+/// Invoked every frame in `RenderMode::Standard`.
+pub type PreUpscalePass =
+    Box<dyn FnMut(&mut rg::TemporalRenderGraph, &mut PreUpscalePassData) + 'static>;
+
+/// Scene data for application-side forward passes (lasers, holograms, particles, etc.)
+///
+/// Passes draw into `color`, which then goes through post-processing along with the rest
+/// of the scene. Both images are at the output resolution, with the sub-pixel jitter
+/// resolved, so passes should use the camera matrices passed in `WorldFrameDesc`.
+/// Like the built-in splat and translucent emissive passes, they come after the temporal
+/// upscaler, so its history clamping doesn't affect them; content which should be upscaled
+/// goes in `WorldRenderer::pre_upscale_passes` instead.
+pub struct ForwardPassData<'a> {
+    /// Linear, pre-exposed scene radiance, after temporal upsampling and motion blur.
+    pub color: &'a mut rg::Handle<Image>,
+
+    /// Reverse-Z depth matching `color`, as from `renderers::composite::unjitter_depth`.
+    /// Use `renderers::composite::depth_attachment` to depth-test rasterized geometry against it.
+    pub depth: &'a rg::Handle<Image>,
+
+    /// Radiance written to `color` must be multiplied by this.
+    pub pre_exposure: f32,
+
+    /// Fallback specular for translucent surfaces; see `WorldRenderer::environment_capture`.
+    pub environment_capture: Option<&'a EnvironmentCaptureOutput>,
+
+    /// The combined ambient occlusion term from `renderers::ao::combine_ao`, with 1.0 meaning
+    /// unoccluded. Unlike `color`, it's at the render resolution and jittered, so sample it
+    /// with normalized coordinates.
+    pub ao: &'a rg::Handle<Image>,
+
+    pub bindless_descriptor_set: vk::DescriptorSet,
+}
 
-            mesh_blas: Default::default(),
-            tlas: Default::default(),
-            accel_scratch,
+/// Records application passes into the render graph, before post-processing.
+/// Invoked every frame in `RenderMode::Standard`.
+pub type ForwardPass = Box<dyn FnMut(&mut rg::TemporalRenderGraph, &mut ForwardPassData) + 'static>;
 
-            mesh_buffer: Mutex::new(Arc::new(mesh_buffer)),
-            vertex_buffer: Mutex::new(Arc::new(vertex_buffer)),
-            vertex_buffer_written: 0,
-            bindless_descriptor_set,
-            bindless_images: Default::default(),
-            image_luts: Default::default(),
+pub(crate) struct PendingReferenceReadback {
+    pub buffer: Arc<Buffer>,
+    pub extent: [u32; 2],
+    pub sample_count: u32,
 
-            next_bindless_image_id: 0,
-            next_instance_handle: 0,
-            bindless_texture_sizes,
+    // Requested with `request_reference_readback`, `save_reference_state`, or by `oidn`
+    pub for_application: bool,
+    pub save_to: Option<PathBuf>,
+    #[cfg(feature = "oidn")]
+    pub for_denoiser: bool,
+}
 
-            rg_debug_hook: None,
-            render_mode: RenderMode::Standard,
-            frame_idx: 0u32,
-            prev_camera_matrices: None,
+/// Scene object corresponding to an instance in the TLAS.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TlasInstance {
+    Instance(InstanceHandle),
 
-            supersample_offsets,
+    /// Batch, and index of the instance within it
+    BatchInstance(InstanceBatchHandle, u32),
+}
 
-            post: PostProcessRenderer::new(backend.device.as_ref())?,
-            ssgi: SsgiRenderer::default(),
-            rtr: RtrRenderer::new(backend.device.as_ref())?,
-            lighting: LightingRenderer::new(),
-            ircache: IrcacheRenderer::new(backend.device.as_ref()),
-            rtdgi: RtdgiRenderer::default(),
-            taa: TaaRenderer::new(),
-            shadow_denoise: ShadowDenoiseRenderer::default(),
-            ibl: IblRenderer::default(),
+/// Curve mapping exposed scene-referred color to the display in `PostProcessRenderer`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Tonemapper {
+    /// kajiya's perceptual display transform, which compresses brightness
+    /// while preserving hue, and desaturates towards white.
+    Filmic = 0,
+    /// Stephen Hill's fit of the ACES RRT and sRGB ODT
+    Aces = 1,
+    /// Troy Sobotka's AgX, in the polynomial approximation of its base look
+    AgX = 2,
+    /// Khronos PBR Neutral, which keeps base colors accurate up to 0.8
+    KhronosPbrNeutral = 3,
+    /// Reinhard applied to luminance
+    Reinhard = 4,
+}
+
+impl Default for Tonemapper {
+    fn default() -> Self {
+        Tonemapper::Filmic
+    }
+}
+
+impl Tonemapper {
+    pub const ALL: [Tonemapper; 5] = [
+        Tonemapper::Filmic,
+        Tonemapper::Aces,
+        Tonemapper::AgX,
+        Tonemapper::KhronosPbrNeutral,
+        Tonemapper::Reinhard,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tonemapper::Filmic => "Filmic",
+            Tonemapper::Aces => "ACES",
+            Tonemapper::AgX => "AgX",
+            Tonemapper::KhronosPbrNeutral => "Khronos PBR Neutral",
+            Tonemapper::Reinhard => "Reinhard",
+        }
+    }
+}
+
+/// Display-referred range of the final image, for HDR swapchains. With an SDR color space,
+/// the tonemapper maps to `[0, 1]` as usual; with an HDR one, it maps to `[0, peak]`, where
+/// 1.0 is paper white, and `final_blit` encodes that for the display.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HdrOutput {
+    /// Normally the color space of the swapchain, as `kajiya-simple` sets it.
+    pub color_space: SwapchainColorSpace,
+
+    /// Brightness of diffuse white, and of the UI
+    pub paper_white_nits: f32,
+
+    /// Brightest value the display can show; highlights are tonemapped towards it.
+    pub max_nits: f32,
+}
+
+impl Default for HdrOutput {
+    fn default() -> Self {
+        Self {
+            color_space: SwapchainColorSpace::Srgb,
+            paper_white_nits: 200.0,
+            max_nits: 1000.0,
+        }
+    }
+}
+
+impl HdrOutput {
+    /// Brightest display-referred value, relative to paper white
+    pub fn peak(&self) -> f32 {
+        if self.color_space.is_hdr() {
+            (self.max_nits / self.paper_white_nits.max(1.0)).max(1.0)
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Reconstructs the `temporal_upscale_extent` image from jittered frames at the render extent.
+/// Can be switched per frame; DLSS and FSR 2 drop their history when switched to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TemporalUpscaler {
+    /// kajiya's built-in TAA, with upsampling
+    Taa,
+    /// Nvidia DLSS; see `docs/using-dlss.md`
+    #[cfg(feature = "dlss")]
+    Dlss,
+    /// AMD FidelityFX Super Resolution 2; see `docs/using-fsr2.md`
+    #[cfg(feature = "fsr2")]
+    Fsr2,
+}
+
+impl Default for TemporalUpscaler {
+    #[allow(unreachable_code)]
+    fn default() -> Self {
+        #[cfg(feature = "dlss")]
+        return TemporalUpscaler::Dlss;
+
+        TemporalUpscaler::Taa
+    }
+}
 
+impl TemporalUpscaler {
+    /// The upscalers available in this build.
+    pub fn all() -> Vec<TemporalUpscaler> {
+        vec![
+            TemporalUpscaler::Taa,
             #[cfg(feature = "dlss")]
-            dlss,
+            TemporalUpscaler::Dlss,
+            #[cfg(feature = "fsr2")]
+            TemporalUpscaler::Fsr2,
+        ]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TemporalUpscaler::Taa => "TAA",
             #[cfg(feature = "dlss")]
-            use_dlss: true,
+            TemporalUpscaler::Dlss => "DLSS",
+            #[cfg(feature = "fsr2")]
+            TemporalUpscaler::Fsr2 => "FSR 2",
+        }
+    }
+}
 
-            temporal_upscale_extent,
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RenderDebugMode {
+    None,
+    WorldRadianceCache,
+}
 
-            debug_mode: RenderDebugMode::None,
-            debug_shading_mode: if backend.device.ray_tracing_enabled() {
-                0
-            } else {
-                // RTX OFF; HACK: reflections buffers currently smear without ray tracing.
-                4
-            },
-            debug_show_wrc: false,
-            ev_shift: 0.0,
-            dynamic_exposure: Default::default(),
-            contrast: 1.0,
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct TriangleLight {
+    pub verts: [[f32; 3]; 3],
+    pub radiance: [f32; 3],
+}
+
+impl TriangleLight {
+    pub fn transform(self, transform: &Affine3A) -> Self {
+        Self {
+            verts: [
+                transform.transform_point3(self.verts[0].into()).into(),
+                transform.transform_point3(self.verts[1].into()).into(),
+                transform.transform_point3(self.verts[2].into()).into(),
+            ],
+            radiance: self.radiance,
+        }
+    }
+
+    pub fn scale_radiance(self, scale: Vec3) -> Self {
+        Self {
+            verts: self.verts,
+            radiance: (Vec3::from(self.radiance) * scale).into(),
+        }
+    }
+
+    /// Emitted power, up to a constant factor; used to pick lights proportionally to it.
+    fn power(&self) -> f32 {
+        let [v0, v1, v2] = self.verts.map(Vec3::from);
+        let area = 0.5 * (v1 - v0).cross(v2 - v0).length();
+        let luminance = Vec3::from(self.radiance).dot(Vec3::new(0.2126, 0.7152, 0.0722));
+
+        (area * luminance).max(0.0)
+    }
+}
+
+// Must match `TriangleLightPacked` in `lights/packed.hlsl`
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuTriangleLight {
+    light: TriangleLight,
+
+    // Alias table entry: the probability of keeping this light when it's picked
+    // uniformly, and the light to switch to otherwise.
+    alias_prob: f32,
+    alias_idx: u32,
+
+    // Probability of picking this light through the alias table.
+    selection_pmf: f32,
+    _pad: u32,
+}
+
+/// Packs the lights along with an alias table for picking them proportionally to their power,
+/// with a constant-time lookup (Vose's method).
+fn build_triangle_light_alias_table(lights: Vec<TriangleLight>) -> Vec<GpuTriangleLight> {
+    let count = lights.len();
+    let powers: Vec<f32> = lights.iter().map(TriangleLight::power).collect();
+    let total_power: f32 = powers.iter().sum();
+
+    let mut res: Vec<GpuTriangleLight> = lights
+        .into_iter()
+        .enumerate()
+        .map(|(i, light)| GpuTriangleLight {
+            light,
+            alias_prob: 1.0,
+            alias_idx: i as u32,
+            selection_pmf: 1.0 / count as f32,
+            _pad: 0,
+        })
+        .collect();
+
+    // Nothing emits any light; keep picking uniformly.
+    if total_power <= 0.0 || !total_power.is_finite() {
+        return res;
+    }
+
+    let mut scaled: Vec<f32> = powers
+        .iter()
+        .map(|p| p * count as f32 / total_power)
+        .collect();
+
+    for (entry, power) in res.iter_mut().zip(&powers) {
+        entry.selection_pmf = power / total_power;
+    }
+
+    let (mut small, mut large): (Vec<usize>, Vec<usize>) =
+        (0..count).partition(|&i| scaled[i] < 1.0);
+
+    while !small.is_empty() && !large.is_empty() {
+        let s = small.pop().unwrap();
+        let l = *large.last().unwrap();
+
+        res[s].alias_prob = scaled[s];
+        res[s].alias_idx = l as u32;
+
+        scaled[l] -= 1.0 - scaled[s];
+        if scaled[l] < 1.0 {
+            large.pop();
+            small.push(l);
+        }
+    }
+
+    // Whatever remains is at one, up to rounding errors. Lights without any power can only
+    // be left over by those though, and mustn't be picked, so their bucket goes to the brightest.
+    let brightest = (0..count)
+        .max_by(|&a, &b| powers[a].partial_cmp(&powers[b]).unwrap())
+        .unwrap();
+    for i in small.into_iter().chain(large) {
+        if powers[i] > 0.0 {
+            res[i].alias_prob = 1.0;
+            res[i].alias_idx = i as u32;
+        } else {
+            res[i].alias_prob = 0.0;
+            res[i].alias_idx = brightest as u32;
+        }
+    }
+
+    res
+}
+
+pub struct MeshLightSet {
+    /// All emissive triangles of the mesh
+    pub lights: Vec<TriangleLight>,
+
+    /// Index of the material of each of `lights`
+    pub light_materials: Vec<u32>,
+
+    /// Whether the mesh was added with `AddMeshOptions::use_lights`. Otherwise the lights
+    /// are only used by `InstanceFlags::EMISSIVE_ONLY` instances.
+    pub use_lights: bool,
+}
+
+pub struct WorldRenderer {
+    pub(super) device: Arc<device::Device>,
+
+    pub(super) raster_simple_render_pass: Arc<RenderPass>,
+    // With an extra shutter velocity attachment, for when instances have shutter transforms
+    pub(super) raster_simple_shutter_render_pass: Arc<RenderPass>,
+    pub(super) bindless_descriptor_set: vk::DescriptorSet,
+    pub(super) meshes: Vec<UploadedTriMesh>,
+
+    // Object-space bounding boxes of `meshes`, as `[min, max]`
+    mesh_bounds: Vec<[Vec3; 2]>,
+
+    pub(super) mesh_lights: Vec<MeshLightSet>,
+    mesh_materials: Vec<Vec<UploadedMaterial>>,
+    dirty_materials: HashSet<MaterialHandle>,
+
+    // Byte range of each of `meshes` in `vertex_buffer`; `None` once removed
+    mesh_vertex_ranges: Vec<Option<std::ops::Range<u64>>>,
+
+    // Slots of removed meshes, reused by `add_mesh`
+    free_mesh_slots: Vec<usize>,
+
+    // Meshes added with `AddMeshOptions::dynamic`
+    dynamic_meshes: HashMap<MeshHandle, DynamicMesh>,
+    vertex_staging: mesh_update::VertexStaging,
+
+    // Meshes added with `add_skinned_mesh`
+    mesh_skins: HashMap<MeshHandle, SkinnedMesh>,
+    pending_mesh_releases: Vec<PendingMeshRelease>,
+
+    // Largest relative change in material appearance since the last frame
+    material_change: f32,
+
+    pub(super) point_lights: HashMap<LightHandle, PointLight>,
+    pub(super) rect_lights: HashMap<RectLightHandle, RectLight>,
+
+    // ----
+    // SoA
+    pub(super) instances: Vec<MeshInstance>,
+    pub(super) instance_handles: Vec<InstanceHandle>,
+    // ----
+
+    // The `usize` indexes into `instances` and `instance_handles`
+    pub(super) instance_handle_to_index: HashMap<InstanceHandle, usize>,
+
+    // Instances of skinned meshes, each drawing its own deformed copy of the mesh
+    skinned_instances: HashMap<InstanceHandle, SkinnedInstance>,
+
+    // Candidates for `instance_aggregation`; see `set_instance_static`
+    pub(super) static_instances: HashSet<InstanceHandle>,
+    pub(super) instance_aggregate: Option<InstanceAggregate>,
+    pub(super) pending_aggregate_releases: Vec<PendingAggregateRelease>,
+
+    // See `register_hit_group` and `set_mesh_hit_group`
+    pub(super) custom_hit_groups: Vec<CustomHitGroup>,
+    pub(super) mesh_hit_groups: HashMap<MeshHandle, HitGroupHandle>,
+
+    // ----
+    // SoA, in the order of `InstanceBatch::transform_offset`, which is also
+    // their order in the TLAS and in `instance_dynamic_parameters`.
+    pub(super) instance_batches: Vec<InstanceBatch>,
+    instance_batch_handles: Vec<InstanceBatchHandle>,
+    // ----
+
+    // The `usize` indexes into `instance_batches` and `instance_batch_handles`
+    pub(super) instance_batch_handle_to_index: HashMap<InstanceBatchHandle, usize>,
+
+    pub(super) crowds: HashMap<CrowdHandle, Crowd>,
+    pub(super) next_crowd_handle: usize,
+
+    pub(super) video_textures: HashMap<VideoTextureHandle, VideoTexture>,
+    pub(super) next_video_texture_handle: usize,
+
+    pub(super) auto_quality: AutoQualityState,
+
+    // See `last_frame_gpu_timings`
+    gpu_timings: Vec<(String, f32)>,
+
+    // Whether `expand_crowds` wrote to `instance_batch_transform_buffer` in the frame being prepared
+    pub(super) instance_batch_transforms_written_on_gpu: bool,
+
+    // CPU mirror of the current transforms in `instance_batch_transform_buffer`
+    instance_batch_transforms: Vec<Affine3A>,
+
+    // What `update_instance_batch_transform_buffer` uploads, bumping the generation on changes
+    gpu_instance_batch_transforms: Vec<GpuInstanceBatchTransform>,
+    instance_batch_transforms_generation: u64,
+
+    // Frames in flight read their own copy, so that the CPU never writes to one in use
+    instance_batch_transform_buffers: Vec<InstanceBatchTransformBuffer>,
+    instance_batch_transform_buffer_index: usize,
+
+    // The one in `instance_batch_transform_buffers` used by the frame being prepared
+    pub(super) instance_batch_transform_buffer: Mutex<Arc<Buffer>>,
+
+    pub(super) vertex_buffer: Mutex<Arc<Buffer>>,
+    pub(super) vertex_buffer_allocator: RangeAllocator,
+
+    // Host-visible, and written in place; see `get_material_mut`
+    material_buffer: Mutex<Arc<Buffer>>,
+    material_buffer_allocator: RangeAllocator,
+
+    mesh_buffer: Mutex<Arc<Buffer>>,
+
+    // `None` for removed meshes
+    mesh_blas: Vec<Option<Arc<RayTracingAcceleration>>>,
+
+    // BLAS geometries of meshes which are neither dynamic nor skinned, for combining into
+    // the BLAS of `instance_aggregate`
+    pub(super) static_mesh_rt_geometry: HashMap<MeshHandle, Vec<RayTracingGeometryDesc>>,
+    tlas: Option<Arc<RayTracingAcceleration>>,
+    accel_scratch: RayTracingAccelerationScratchBuffer,
+
+    // Contents of the persistent TLAS instance buffer as of the last rendered frame,
+    // by TLAS instance index; `None` where unknown. As long as the buffer keeps its capacity,
+    // only the slots which differ from this are uploaded.
+    tlas_instance_slots: Vec<Option<TlasInstanceSlot>>,
+
+    // Changes to `tlas_instance_slots` made by the frame being prepared, committed in
+    // `retire_frame`. Frames which fail to prepare don't run, and leave the buffer intact.
+    pending_tlas_instance_slots: Option<PendingTlasInstanceSlots>,
+
+    bindless_images: Vec<Arc<Image>>,
+
+    // 1x1 images standing in for maps missing from meshes built at runtime, by texel value
+    placeholder_images: HashMap<[u8; 4], BindlessImageHandle>,
+    next_bindless_image_id: usize,
+    next_instance_handle: usize,
+    next_instance_batch_handle: usize,
+    next_light_handle: usize,
+    bindless_texture_sizes: Buffer,
+
+    image_luts: Vec<ImageLut>,
+    frame_idx: u32,
+    prev_camera_matrices: Option<CameraMatrices>,
+    prev_sun_direction: Option<Vec3>,
+    gi_history_scale: f32,
+    pub(crate) temporal_upscale_extent: [u32; 2],
+
+    supersample_offsets: Vec<Vec2>,
+
+    pub rg_debug_hook: Option<rg::GraphDebugHook>,
+
+    // See `dump_frame`
+    frame_dump_request: Option<PathBuf>,
+    pending_frame_dump: Option<(PathBuf, rg::FrameCapture, rg::GraphDescription)>,
+
+    pub scene_ray_tracing_passes: Vec<SceneRayTracingPass>,
+    pub pre_upscale_passes: Vec<PreUpscalePass>,
+    pub forward_passes: Vec<ForwardPass>,
+    pub render_mode: RenderMode,
+    pub reset_reference_accumulation: bool,
+
+    /// Reference mode accumulates at this fraction of the render resolution, for a quick look
+    /// at lighting before committing to a full ground-truth render. Changing it restarts accumulation.
+    pub reference_preview_scale: f32,
+    pub reference_preview_filter: ReferencePreviewFilter,
+    pub(super) reference_accum_extent: [u32; 2],
+
+    /// Changes other than to the target sample count restart accumulation.
+    pub reference_config: ReferencePathTraceConfig,
+    pub(super) reference_accum_config: ReferencePathTraceConfig,
+    pub(super) reference_sample_count: u32,
+
+    // See `request_reference_readback`
+    pub(super) reference_readback_requested: bool,
+    pub(super) pending_reference_readback: Option<PendingReferenceReadback>,
+    finished_reference_readback: Option<ReferenceReadback>,
+
+    // See `save_reference_state` and `load_reference_state`
+    pub(super) reference_save_request: Option<PathBuf>,
+    pub(super) pending_reference_restore: Option<ReferenceReadback>,
+
+    pub post: PostProcessRenderer,
+    pub ray_queries: RayQueryRenderer,
+    pub gaussian_splats: GaussianSplatRenderer,
+    pub translucent_emissives: TranslucentEmissiveRenderer,
+    pub thumbnails: ThumbnailRenderer,
+    pub ssgi: SsgiRenderer,
+    pub rtao: RtaoRenderer,
+
+    /// Whether shading is occluded by SSGI, RTAO, or both.
+    pub ambient_occlusion: AmbientOcclusionMode,
+
+    pub rtr: RtrRenderer,
+    pub lighting: LightingRenderer,
+    pub ircache: IrcacheRenderer,
+    pub rtdgi: RtdgiRenderer,
+    pub taa: TaaRenderer,
+    pub viewmodel: ViewmodelSettings,
+
+    /// Indexed by `MaterialCategory`; see `set_material_category`.
+    pub material_categories: [MaterialCategorySettings; MaterialCategory::COUNT],
+
+    pub instance_aggregation: InstanceAggregationSettings,
+    pub shadow_denoise: ShadowDenoiseRenderer,
+
+    /// Alternatives to the built-in denoisers of sun shadows, reflections and diffuse GI
+    pub denoisers: Denoisers,
+
+    pub rect_light_shadow_denoise: ShadowDenoiseRenderer,
+    pub ibl: IblRenderer,
+    pub image_metrics: ImageMetricsRenderer,
+    pub volumetric_fog: VolumetricFogRenderer,
+    pub reflection_probes: ReflectionProbeRenderer,
+    pub environment_capture: EnvironmentCaptureRenderer,
+    pub sky_visibility: SkyVisibilityRenderer,
+    pub photo_mode: PhotoModeRenderer,
+    pub motion_vectors: MotionVectorDebugRenderer,
+
+    #[cfg(feature = "dlss")]
+    pub dlss: DlssRenderer,
+    #[cfg(feature = "fsr2")]
+    pub fsr2: Fsr2Renderer,
+
+    /// Denoises the reference image; see `docs/using-kajiya.md`
+    #[cfg(feature = "oidn")]
+    pub oidn: OidnDenoiser,
+
+    pub temporal_upscaler: TemporalUpscaler,
+    pub(super) prev_temporal_upscaler: Option<TemporalUpscaler>,
+
+    pub debug_mode: RenderDebugMode,
+    pub debug_shading_mode: usize,
+    pub debug_show_wrc: bool,
+
+    /// Manual exposure, in stops; `units::ev_shift_for_ev100` matches a physical camera.
+    pub ev_shift: f32,
+
+    /// Lens dirt modulating the bloom, e.g. smudges and dust lit up by bright lights;
+    /// an image from `add_image`, stretched over the screen. Tuned via `FrameGraphConfig::bloom`.
+    pub bloom_lens_dirt: Option<BindlessImageHandle>,
+
+    pub auto_exposure: AutoExposure,
+    pub(crate) dynamic_exposure: DynamicExposureState,
+    pub contrast: f32,
+
+    material_features: MaterialFeatures,
+    pub tonemapper: Tonemapper,
+
+    /// Global wind for vertex animation; see `inc/wind.hlsl`.
+    pub wind: Wind,
+    wind_state: WindState,
+    pub hdr_output: HdrOutput,
+
+    /// Contrast-adaptive sharpening after post-processing, in `[0, 1]`; 0 disables it.
+    /// Recovers detail softened by temporal upscaling.
+    pub sharpness: f32,
+
+    /// Applied after tonemapping; see `set_color_grading_lut`.
+    pub color_grading: ColorGrading,
+
+    /// One gives the sun an illuminance of `units::SOLAR_ILLUMINANCE_LUX` before it enters
+    /// the atmosphere.
+    pub sun_color_multiplier: Vec3,
+    pub sky_ambient: Vec3,
+
+    /// Length of a meter in scene units, e.g. 100 for scenes authored in centimeters.
+    /// Scales the lengths which the renderer tunes in meters: irradiance cache voxels
+    /// and ray tracing biases. Set it along with the scene, before rendering it.
+    pub world_units_per_meter: f32,
+
+    /// Debug and performance knobs applied every frame. Unlike `frame_graph_config`, these
+    /// aren't meant to be persisted.
+    pub render_overrides: RenderOverrides,
+
+    /// `RenderFeatureFlags` skipped in every frame, on top of those disabled in
+    /// `frame_graph_config` and `WorldFrameDesc::disabled_features`. Not persisted either.
+    pub disabled_features: u32,
+    pub frame_graph_config: FrameGraphConfig,
+
+    // One for each render mode
+    pub(crate) exposure_state: [ExposureState; 2],
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HistogramClipping {
+    pub low: f32,
+    pub high: f32,
+}
+
+/// How `AutoExposure` measures the brightness of the image.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ExposureMeteringMode {
+    /// Mean log luminance of the whole image.
+    Average = 0,
+    /// Mean log luminance, weighted towards the center of the screen.
+    CenterWeighted = 1,
+    /// Center-weighted, with the darkest and brightest pixels rejected
+    /// according to `AutoExposure::histogram_clipping`.
+    Histogram = 2,
+}
+
+impl Default for ExposureMeteringMode {
+    fn default() -> Self {
+        ExposureMeteringMode::Histogram
+    }
+}
+
+impl ExposureMeteringMode {
+    pub const ALL: [ExposureMeteringMode; 3] = [
+        ExposureMeteringMode::Average,
+        ExposureMeteringMode::CenterWeighted,
+        ExposureMeteringMode::Histogram,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExposureMeteringMode::Average => "Average",
+            ExposureMeteringMode::CenterWeighted => "Center-weighted",
+            ExposureMeteringMode::Histogram => "Histogram",
+        }
+    }
+
+    pub(crate) fn is_center_weighted(self) -> bool {
+        self != ExposureMeteringMode::Average
+    }
+}
+
+/// Automatic exposure, adapting to the luminance measured in post.
+/// Its result is added to `WorldRenderer::ev_shift`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct AutoExposure {
+    pub enabled: bool,
+    pub metering_mode: ExposureMeteringMode,
+
+    /// Fractions of the darkest and brightest pixels ignored by `ExposureMeteringMode::Histogram`.
+    pub histogram_clipping: HistogramClipping,
+
+    /// Adaptation speed (log2) while the exposure increases, i.e. when moving into darker areas.
+    pub speed_up_log2: f32,
+
+    /// Adaptation speed (log2) while the exposure decreases, i.e. when moving into brighter areas.
+    pub speed_down_log2: f32,
+
+    /// Range of the EV chosen by adaptation, before `ev_shift`.
+    pub min_ev: f32,
+    pub max_ev: f32,
+
+    /// Holds the current adaptation, e.g. to compare shots at a fixed exposure.
+    pub locked: bool,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            metering_mode: ExposureMeteringMode::default(),
+            histogram_clipping: HistogramClipping::default(),
+            speed_up_log2: 0.0,
+            speed_down_log2: 0.0,
+            min_ev: -16.0,
+            max_ev: 16.0,
+            locked: false,
+        }
+    }
+}
+
+impl AutoExposure {
+    pub(crate) fn histogram_clipping(&self) -> HistogramClipping {
+        if self.metering_mode == ExposureMeteringMode::Histogram {
+            self.histogram_clipping
+        } else {
+            HistogramClipping::default()
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct DynamicExposureState {
+    ev_fast: f32,
+    ev_slow: f32,
+}
+
+const DYNAMIC_EXPOSURE_BIAS: f32 = -2.0;
+
+impl DynamicExposureState {
+    pub fn ev_smoothed(&self, config: &AutoExposure) -> f32 {
+        if config.enabled {
+            (self.ev_slow + self.ev_fast) * 0.5
+        } else {
+            0.0
+        }
+    }
+
+    pub fn update(&mut self, config: &AutoExposure, ev: f32, dt: f32) {
+        if !config.enabled || config.locked {
+            return;
+        }
+
+        let ev =
+            (ev + DYNAMIC_EXPOSURE_BIAS).clamp(config.min_ev, config.max_ev.max(config.min_ev));
+
+        let adapt = |current: &mut f32, rate: f32| {
+            let speed_log2 = if ev > *current {
+                config.speed_up_log2
+            } else {
+                config.speed_down_log2
+            };
+
+            let t = 1.0 - (-rate * dt * speed_log2.exp2()).exp();
+            *current = (ev - *current) * t + *current;
+        };
+
+        adapt(&mut self.ev_fast, 1.0);
+        adapt(&mut self.ev_slow, 0.25);
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ExposureState {
+    /// A value to multiply all lighting by in order to apply exposure compensation
+    /// early in the pipeline, such that lighting values fit in small texture formats.
+    pub pre_mult: f32,
+
+    /// The remaining multiplier to apply in post.
+    pub post_mult: f32,
+
+    // The pre-multiplier in the previous frame.
+    pub pre_mult_prev: f32,
+
+    // `pre_mult / pre_mult_prev`
+    pub pre_mult_delta: f32,
+}
+
+impl Default for ExposureState {
+    fn default() -> Self {
+        Self {
+            pre_mult: 1.0,
+            post_mult: 1.0,
+            pre_mult_prev: 1.0,
+            pre_mult_delta: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RenderMode {
+    Standard = 0,
+    Reference = 1,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BindlessImageHandle(pub u32);
+
+fn load_gpu_image_asset(
+    device: Arc<kajiya_backend::Device>,
+    asset: AssetRef<GpuImage::Flat>,
+) -> Arc<Image> {
+    let asset = crate::mmap::mmapped_asset::<GpuImage::Flat, _>(&format!(
+        "/cache/{:8.8x}.image",
+        asset.identity()
+    ))
+    .unwrap();
+
+    let desc = ImageDesc::new_2d(asset.format, [asset.extent[0], asset.extent[1]])
+        .usage(vk::ImageUsageFlags::SAMPLED)
+        .mip_levels(asset.mips.len() as _);
+
+    let initial_data = asset
+        .mips
+        .iter()
+        .enumerate()
+        .map(|(mip_level, mip)| ImageSubResourceData {
+            data: mip.as_slice(),
+            row_pitch: ((desc.extent[0] as usize) >> mip_level).max(1) * 4,
+            slice_pitch: 0,
+        })
+        .collect::<Vec<_>>();
+
+    Arc::new(device.create_image(desc, initial_data).unwrap())
+}
+
+// CPU mirror of a material record in `material_buffer`
+struct UploadedMaterial {
+    // Index of the record in `material_buffer`
+    material_buffer_index: u32,
+
+    // As uploaded by `add_mesh`, with bindless image handles for maps
+    authored: MeshMaterial,
+
+    // `authored`, or that of the replacement material of the active variant
+    active: MeshMaterial,
+    active_variant: Option<u32>,
+
+    // Variants replacing this material
+    variants: Vec<MeshMaterialVariant>,
+    emissive_multiplier: Vec3,
+    transmission: Option<MaterialTransmission>,
+    category: Option<MaterialCategory>,
+}
+
+impl UploadedMaterial {
+    fn gpu_record(&self) -> MeshMaterial {
+        let mut record = self.active;
+        record.emissive = (Vec3::from(record.emissive) * self.emissive_multiplier).into();
+
+        if let Some(transmission) = self.transmission {
+            record.transmission = transmission.factor;
+            if let Some(thickness_map) = transmission.thickness_map {
+                record.maps[4] = thickness_map.0;
+            }
+        }
+
+        if let Some(category) = self.category {
+            record.set_category(category);
+        }
+
+        record
+    }
+}
+
+struct PendingMeshRelease {
+    mesh_idx: usize,
+    vertex_range: std::ops::Range<u64>,
+    material_range: std::ops::Range<u64>,
+    blas: Option<Arc<RayTracingAcceleration>>,
+    frames_left: u32,
+}
+
+// A mesh added with `AddMeshOptions::dynamic`
+struct DynamicMesh {
+    vertex_count: usize,
+
+    // Byte offset of the vertices in `vertex_buffer`
+    vertex_core_offset: u32,
+
+    // Refittable BLAS of the mesh; `None` without ray tracing
+    blas_desc: Option<RayTracingBottomAccelerationDesc>,
+
+    // Uploaded on the next frame
+    pending_verts: Option<Vec<PackedVertex>>,
+}
+
+// Geometry and materials passed to `add_mesh_impl`. Baked meshes are borrowed from their
+// memory-mapped assets; meshes built at runtime own their data.
+pub(crate) struct MeshSource {
+    pub verts: Cow<'static, [PackedVertex]>,
+    pub uvs: Cow<'static, [[f32; 2]]>,
+    pub tangents: Cow<'static, [[f32; 4]]>,
+    pub colors: Cow<'static, [[f32; 4]]>,
+    pub indices: Cow<'static, [u32]>,
+    pub material_ids: Cow<'static, [u32]>,
+
+    // Leading indices of triangles which the alpha test never cuts into; see
+    // `pack_triangle_mesh`. The rest are alpha-tested by ray tracing any-hit shaders.
+    pub opaque_index_count: usize,
+
+    // With bindless image handles for maps
+    pub materials: Vec<MeshMaterial>,
+    pub material_variants: Cow<'static, [MeshMaterialVariant]>,
+    pub geometry_scale: MeshGeometryScale,
+}
+
+// Skinning data of a mesh added with `add_skinned_mesh`
+struct SkinnedMesh {
+    // Bind pose
+    verts: Cow<'static, [PackedVertex]>,
+    tangents: Cow<'static, [[f32; 4]]>,
+    indices: Cow<'static, [u32]>,
+
+    joint_count: usize,
+
+    // Byte offsets of the packed joint indices and weights in `vertex_buffer`
+    joint_offset: u32,
+    weight_offset: u32,
+
+    // See `MeshSource::opaque_index_count`
+    opaque_index_count: usize,
+}
+
+// An instance of a skinned mesh. It's drawn and traced as a mesh of its own, sharing everything
+// but the positions, normals and tangents with the skinned mesh.
+struct SkinnedInstance {
+    skinned_mesh: MeshHandle,
+    deformed_mesh: MeshHandle,
+    offsets: SkinnedVertexOffsets,
+
+    // Refittable BLAS of `deformed_mesh`; `None` without ray tracing
+    blas_desc: Option<RayTracingBottomAccelerationDesc>,
+
+    // Row-major joint transforms, skinned on the next frame if `pose_dirty`
+    pose: Vec<[f32; 12]>,
+    pose_dirty: bool,
+}
+
+/// Per-vertex joint influences of a mesh; see `WorldRenderer::add_skinned_mesh`.
+#[derive(Clone, Default)]
+pub struct MeshSkin {
+    /// Up to four joints influencing each vertex, as indices into the pose
+    /// passed to `WorldRenderer::set_instance_pose`.
+    pub joints: Vec<[u16; 4]>,
+
+    /// Weights of `joints`, summing to one for each vertex.
+    pub weights: Vec<[f32; 4]>,
+}
+
+#[derive(Default)]
+pub struct AddMeshOptions {
+    pub use_lights: bool,
+
+    /// Allow changing the vertices with `WorldRenderer::update_mesh_vertices`.
+    pub dynamic: bool,
+}
+
+impl AddMeshOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn use_lights(mut self, v: bool) -> Self {
+        self.use_lights = v;
+        self
+    }
+
+    pub fn dynamic(mut self, v: bool) -> Self {
+        self.dynamic = v;
+        self
+    }
+}
+
+impl WorldRenderer {
+    pub(crate) fn new_empty(
+        // Internal render resolution, before any upsampling
+        #[allow(unused_variables)] render_extent: [u32; 2],
+        temporal_upscale_extent: [u32; 2],
+        backend: &RenderBackend,
+    ) -> Result<Self, BackendError> {
+        let raster_simple_color_attachments = [
+            // view-space geometry normal; * 2 - 1 to decode
+            RenderPassAttachmentDesc::new(vk::Format::A2R10G10B10_UNORM_PACK32).garbage_input(),
+            // gbuffer
+            RenderPassAttachmentDesc::new(vk::Format::R32G32B32A32_SFLOAT).garbage_input(),
+            // velocity
+            RenderPassAttachmentDesc::new(vk::Format::R16G16B16A16_SFLOAT).garbage_input(),
+            // shutter velocity
+            RenderPassAttachmentDesc::new(vk::Format::R16G16B16A16_SFLOAT).garbage_input(),
+        ];
+
+        let raster_simple_render_pass = create_render_pass(
+            &backend.device,
+            RenderPassDesc {
+                color_attachments: &raster_simple_color_attachments[..3],
+                depth_attachment: Some(RenderPassAttachmentDesc::new(vk::Format::D32_SFLOAT)),
+            },
+        );
+
+        let raster_simple_shutter_render_pass = create_render_pass(
+            &backend.device,
+            RenderPassDesc {
+                color_attachments: &raster_simple_color_attachments,
+                depth_attachment: Some(RenderPassAttachmentDesc::new(vk::Format::D32_SFLOAT)),
+            },
+        );
+
+        let mesh_buffer = backend.device.create_buffer(
+            BufferDesc::new_cpu_to_gpu(
+                MAX_GPU_MESHES * size_of::<GpuMesh>(),
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+            ),
+            "mesh buffer",
+            None,
+        )?;
+
+        let vertex_buffer = backend.device.create_buffer(
+            BufferDesc::new_gpu_only(
+                VERTEX_BUFFER_CAPACITY,
+                vk::BufferUsageFlags::STORAGE_BUFFER
+                    | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                    | vk::BufferUsageFlags::INDEX_BUFFER
+                    | vk::BufferUsageFlags::TRANSFER_DST
+                    | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
+            ),
+            "vertex buffer",
+            None,
+        )?;
+
+        let material_buffer = backend.device.create_buffer(
+            BufferDesc::new_cpu_to_gpu(
+                MAX_GPU_MATERIALS * size_of::<MeshMaterial>(),
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            ),
+            "material buffer",
+            None,
+        )?;
+
+        let instance_batch_transform_buffers = (0..INSTANCE_BATCH_TRANSFORM_BUFFER_COUNT)
+            .map(|_| {
+                let buffer = create_instance_batch_transform_buffer(
+                    &backend.device,
+                    MIN_BATCHED_INSTANCE_CAPACITY,
+                )?;
+
+                Ok(InstanceBatchTransformBuffer {
+                    buffer: Arc::new(buffer),
+                    generation: 0,
+                    gpu_done_timeline_value: 0,
+                })
+            })
+            .collect::<Result<Vec<_>, BackendError>>()?;
+
+        let bindless_texture_sizes = backend
+            .device
+            .create_buffer(
+                BufferDesc::new_cpu_to_gpu(
+                    backend.device.max_bindless_descriptor_count() as usize
+                        * std::mem::size_of::<[f32; 4]>(),
+                    vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                ),
+                "bindless_texture_sizes",
+                None,
+            )
+            .unwrap();
+
+        let bindless_descriptor_set = create_bindless_descriptor_set(backend.device.as_ref());
+
+        // `meshes`
+        Self::write_descriptor_set_buffer(
+            &backend.device.raw,
+            bindless_descriptor_set,
+            0,
+            &mesh_buffer,
+        );
+
+        // `vertices`
+        Self::write_descriptor_set_buffer(
+            &backend.device.raw,
+            bindless_descriptor_set,
+            1,
+            &vertex_buffer,
+        );
+
+        // `bindless_texture_sizes`
+        Self::write_descriptor_set_buffer(
+            &backend.device.raw,
+            bindless_descriptor_set,
+            2,
+            &bindless_texture_sizes,
+        );
+
+        // `materials`
+        Self::write_descriptor_set_buffer(
+            &backend.device.raw,
+            bindless_descriptor_set,
+            3,
+            &material_buffer,
+        );
+
+        let supersample_count = 128;
+        let supersample_offsets = (1..=supersample_count)
+            .map(|i| Vec2::new(radical_inverse(i, 2) - 0.5, radical_inverse(i, 3) - 0.5))
+            .collect();
+        //let supersample_offsets = vec![Vec2::new(0.0, -0.5), Vec2::new(0.0, 0.5)];
+        /*let supersample_offsets = vec![
+            Vec2::new(0.25, 0.25),
+            Vec2::new(0.25, -0.25),
+            Vec2::new(-0.25, 0.25),
+            Vec2::new(-0.25, -0.25),
+        ];*/
+
+        let accel_scratch = backend
+            .device
+            .create_ray_tracing_acceleration_scratch_buffer()?;
+
+        #[cfg(feature = "dlss")]
+        let dlss = DlssRenderer::new(backend, render_extent, temporal_upscale_extent);
+
+        #[cfg(feature = "fsr2")]
+        let fsr2 = Fsr2Renderer::new(backend, render_extent, temporal_upscale_extent);
+
+        Ok(Self {
+            raster_simple_render_pass,
+            raster_simple_shutter_render_pass,
+
+            reset_reference_accumulation: false,
+            reference_preview_scale: 1.0,
+            reference_preview_filter: ReferencePreviewFilter::Nearest,
+            reference_accum_extent: [0, 0],
+            reference_config: Default::default(),
+            reference_accum_config: Default::default(),
+            reference_sample_count: 0,
+            reference_readback_requested: false,
+            pending_reference_readback: None,
+            finished_reference_readback: None,
+            reference_save_request: None,
+            pending_reference_restore: None,
+            //cube_index_buffer: Arc::new(cube_index_buffer),
+            device: backend.device.clone(),
+            meshes: Default::default(),
+            mesh_bounds: Default::default(),
+            instances: Default::default(),
+            instance_handles: Default::default(),
+            instance_handle_to_index: Default::default(),
+            skinned_instances: Default::default(),
+            static_instances: Default::default(),
+            instance_aggregate: None,
+            pending_aggregate_releases: Vec::new(),
+            custom_hit_groups: Vec::new(),
+            mesh_hit_groups: Default::default(),
+            instance_batches: Default::default(),
+            instance_batch_handles: Default::default(),
+            instance_batch_handle_to_index: Default::default(),
+            crowds: Default::default(),
+            next_crowd_handle: 0,
+            video_textures: Default::default(),
+            next_video_texture_handle: 0,
+            auto_quality: Default::default(),
+            gpu_timings: Default::default(),
+            instance_batch_transforms_written_on_gpu: false,
+            instance_batch_transforms: Default::default(),
+            gpu_instance_batch_transforms: Default::default(),
+            instance_batch_transforms_generation: 0,
+            instance_batch_transform_buffer: Mutex::new(
+                instance_batch_transform_buffers[0].buffer.clone(),
+            ),
+            instance_batch_transform_buffers,
+            instance_batch_transform_buffer_index: 0,
+
+            mesh_lights: Default::default(),
+            mesh_materials: Default::default(),
+            dirty_materials: Default::default(),
+            material_change: 0.0,
+            point_lights: Default::default(),
+            rect_lights: Default::default(),
+
+            mesh_vertex_ranges: Default::default(),
+            free_mesh_slots: Default::default(),
+            dynamic_meshes: Default::default(),
+            vertex_staging: Default::default(),
+            mesh_skins: Default::default(),
+            pending_mesh_releases: Default::default(),
+
+            mesh_blas: Default::default(),
+            tlas: Default::default(),
+            accel_scratch,
+            tlas_instance_slots: Default::default(),
+            pending_tlas_instance_slots: None,
+
+            mesh_buffer: Mutex::new(Arc::new(mesh_buffer)),
+            vertex_buffer: Mutex::new(Arc::new(vertex_buffer)),
+            vertex_buffer_allocator: Default::default(),
+            material_buffer: Mutex::new(Arc::new(material_buffer)),
+            material_buffer_allocator: Default::default(),
+            bindless_descriptor_set,
+            bindless_images: Default::default(),
+            placeholder_images: Default::default(),
+            image_luts: Default::default(),
+
+            next_bindless_image_id: 0,
+            next_instance_handle: 0,
+            next_light_handle: 0,
+            next_instance_batch_handle: 0,
+            bindless_texture_sizes,
+
+            rg_debug_hook: None,
+            frame_dump_request: None,
+            pending_frame_dump: None,
+            scene_ray_tracing_passes: Vec::new(),
+            pre_upscale_passes: Vec::new(),
+            forward_passes: Vec::new(),
+            render_mode: RenderMode::Standard,
+            frame_idx: 0u32,
+            prev_camera_matrices: None,
+            prev_sun_direction: None,
+            gi_history_scale: 1.0,
+
+            supersample_offsets,
+
+            post: PostProcessRenderer::new(backend.device.as_ref())?,
+            ray_queries: RayQueryRenderer::new(backend.device.as_ref())?,
+            gaussian_splats: GaussianSplatRenderer::new(),
+            translucent_emissives: TranslucentEmissiveRenderer::default(),
+            thumbnails: ThumbnailRenderer::default(),
+            ssgi: SsgiRenderer::default(),
+            rtao: RtaoRenderer::default(),
+            ambient_occlusion: AmbientOcclusionMode::default(),
+            rtr: RtrRenderer::new(backend.device.as_ref())?,
+            lighting: LightingRenderer::new(),
+            ircache: IrcacheRenderer::new(backend.device.as_ref()),
+            rtdgi: RtdgiRenderer::default(),
+            taa: TaaRenderer::new(),
+            viewmodel: Default::default(),
+            material_categories: Default::default(),
+            shadow_denoise: ShadowDenoiseRenderer::default(),
+            denoisers: Default::default(),
+            rect_light_shadow_denoise: ShadowDenoiseRenderer::new("rect_light_shadow_denoise"),
+            ibl: IblRenderer::default(),
+            image_metrics: ImageMetricsRenderer::new(backend.device.as_ref())?,
+            volumetric_fog: VolumetricFogRenderer::default(),
+            reflection_probes: ReflectionProbeRenderer::default(),
+            environment_capture: EnvironmentCaptureRenderer::default(),
+            sky_visibility: SkyVisibilityRenderer::default(),
+            photo_mode: PhotoModeRenderer::default(),
+            motion_vectors: MotionVectorDebugRenderer::new(backend.device.as_ref())?,
+
+            #[cfg(feature = "dlss")]
+            dlss,
+            #[cfg(feature = "fsr2")]
+            fsr2,
+
+            #[cfg(feature = "oidn")]
+            oidn: OidnDenoiser::default(),
+
+            temporal_upscaler: TemporalUpscaler::default(),
+            prev_temporal_upscaler: None,
+
+            temporal_upscale_extent,
+
+            debug_mode: RenderDebugMode::None,
+            debug_shading_mode: if backend.device.ray_tracing_enabled() {
+                0
+            } else {
+                // RTX OFF; HACK: reflections buffers currently smear without ray tracing.
+                4
+            },
+            debug_show_wrc: false,
+            ev_shift: 0.0,
+            bloom_lens_dirt: None,
+            auto_exposure: Default::default(),
+            dynamic_exposure: Default::default(),
+            material_features: Default::default(),
+            contrast: 1.0,
+            tonemapper: Tonemapper::default(),
+            wind: Wind::default(),
+            wind_state: WindState::default(),
+            hdr_output: Default::default(),
+            sharpness: 0.0,
+            color_grading: Default::default(),
+
+            sun_color_multiplier: Vec3::ONE,
+            sky_ambient: Vec3::ZERO,
+            world_units_per_meter: 1.0,
+
+            render_overrides: Default::default(),
+            disabled_features: 0,
+            frame_graph_config: Default::default(),
+
+            exposure_state: Default::default(),
+        })
+    }
+
+    fn write_descriptor_set_buffer(
+        device: &kajiya_backend::ash::Device,
+        set: vk::DescriptorSet,
+        dst_binding: u32,
+        buffer: &Buffer,
+    ) {
+        let buffer_info = vk::DescriptorBufferInfo::builder()
+            .buffer(buffer.raw)
+            .range(vk::WHOLE_SIZE)
+            .build();
+
+        let write_descriptor_set = vk::WriteDescriptorSet::builder()
+            .dst_set(set)
+            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+            .dst_binding(dst_binding)
+            .buffer_info(std::slice::from_ref(&buffer_info))
+            .build();
+
+        unsafe {
+            device.update_descriptor_sets(std::slice::from_ref(&write_descriptor_set), &[]);
+        }
+    }
+
+    fn add_bindless_image_view(&mut self, view: ImageView) -> BindlessImageHandle {
+        let handle = BindlessImageHandle(self.next_bindless_image_id as _);
+        self.next_bindless_image_id += 1;
+
+        let image_info = vk::DescriptorImageInfo::builder()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(view)
+            .build();
+
+        let write_descriptor_set = vk::WriteDescriptorSet::builder()
+            .dst_set(self.bindless_descriptor_set)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .dst_binding(BINDLESS_TEXURES_BINDING_INDEX as _)
+            .dst_array_element(handle.0 as _)
+            .image_info(std::slice::from_ref(&image_info))
+            .build();
+
+        unsafe {
+            self.device
+                .raw
+                .update_descriptor_sets(std::slice::from_ref(&write_descriptor_set), &[]);
+        }
+
+        handle
+    }
+
+    pub fn add_image_lut(&mut self, computer: impl ComputeImageLut + 'static, id: usize) {
+        self.image_luts
+            .push(ImageLut::new(self.device.as_ref(), Box::new(computer)));
+
+        let handle = self.add_bindless_image_view(
+            self.image_luts
+                .last()
+                .unwrap()
+                .backing_image()
+                .view(self.device.as_ref(), &ImageViewDesc::default())
+                .unwrap(),
+        );
+
+        assert_eq!(handle.0 as usize, id);
+    }
+
+    pub fn add_image(&mut self, image: Arc<Image>) -> BindlessImageHandle {
+        let image_size: [f32; 4] = image.desc.extent_inv_extent_2d();
+
+        let handle = self.add_bindless_image_view(
+            image
+                .view(self.device.as_ref(), &ImageViewDesc::default())
+                .unwrap(),
+        );
+
+        self.bindless_images.push(image);
+
+        bytemuck::checked::cast_slice_mut::<u8, [f32; 4]>(
+            self.bindless_texture_sizes
+                .allocation
+                .mapped_slice_mut()
+                .unwrap(),
+        )[handle.0 as usize] = image_size;
+
+        handle
+    }
+
+    pub fn material_features(&self) -> MaterialFeatures {
+        self.material_features
+    }
+
+    /// Switches the raster and ray tracing shaders to the permutation for `features`, which
+    /// compiles on first use. Vertex streams are only skipped for meshes added afterwards,
+    /// so set this before loading the scene; re-enabling a feature later leaves the meshes
+    /// added without it unaffected by it.
+    pub fn set_material_features(&mut self, features: MaterialFeatures) {
+        self.material_features = features;
+    }
+
+    /// Replaces the color grading LUT, or removes it with `None`. The LUT is indexed by
+    /// the tonemapped image, sRGB-encoded, and its output decoded the same way.
+    ///
+    /// The previous LUT is kept around for cross-fading: set `color_grading.blend` to zero,
+    /// and ramp it up to one to transition. Otherwise, it stays at one, and the new grade
+    /// applies immediately.
+    pub fn set_color_grading_lut(
+        &mut self,
+        lut: Option<ColorGradingLutSource>,
+    ) -> anyhow::Result<()> {
+        let lut = lut
+            .map(|source| ColorGradingLut::new(self.device.as_ref(), source))
+            .transpose()?;
+        self.color_grading.set_lut(lut);
+        Ok(())
+    }
+
+    // A shared 1x1 linear image with the given texel value.
+    pub(crate) fn placeholder_image(&mut self, values: [u8; 4]) -> BindlessImageHandle {
+        if let Some(&handle) = self.placeholder_images.get(&values) {
+            return handle;
+        }
+
+        let image = self
+            .device
+            .create_image(
+                ImageDesc::new_2d(vk::Format::R8G8B8A8_UNORM, [1, 1])
+                    .usage(vk::ImageUsageFlags::SAMPLED),
+                vec![ImageSubResourceData {
+                    data: &values,
+                    row_pitch: 4,
+                    slice_pitch: 0,
+                }],
+            )
+            .unwrap();
+
+        let handle = self.add_image(Arc::new(image));
+        self.placeholder_images.insert(values, handle);
+        handle
+    }
+
+    pub fn add_mesh(
+        &mut self,
+        mesh: &'static PackedTriMesh::Flat,
+        opts: AddMeshOptions,
+    ) -> MeshHandle {
+        let source = self.baked_mesh_source(mesh);
+        self.add_mesh_impl(source, opts, None)
+    }
+
+    /// Adds a mesh deformed by a skeleton. Every instance of it gets its own copy of the vertices,
+    /// skinned on the GPU by the pose given to `set_instance_pose`, and for ray tracing, its own
+    /// BLAS refit to them. Instances start out in the bind pose.
+    ///
+    /// Emissive triangles of skinned meshes are not used as lights. Bounds are those of the bind
+    /// pose, and velocity only accounts for the instance transform, so fast animation may ghost
+    /// a little under temporal filtering. Instance batches of the mesh are drawn in the bind pose.
+    pub fn add_skinned_mesh(
+        &mut self,
+        mesh: &'static PackedTriMesh::Flat,
+        skin: &MeshSkin,
+        opts: AddMeshOptions,
+    ) -> MeshHandle {
+        assert_eq!(
+            skin.joints.len(),
+            mesh.verts.len(),
+            "skin joints must match mesh vertices"
+        );
+        assert_eq!(
+            skin.weights.len(),
+            mesh.verts.len(),
+            "skin weights must match mesh vertices"
+        );
+
+        let source = self.baked_mesh_source(mesh);
+        self.add_mesh_impl(source, opts, Some(skin))
+    }
+
+    // Loads the textures of a baked mesh, and points its materials at them.
+    fn baked_mesh_source(&mut self, mesh: &'static PackedTriMesh::Flat) -> MeshSource {
+        let mut unique_images: Vec<AssetRef<GpuImage::Flat>> = mesh.maps.as_slice().to_vec();
+        unique_images.sort();
+        unique_images.dedup();
+
+        let loaded_images = {
+            let device = self.device.clone();
+            easy_parallel::Parallel::new()
+                .each(unique_images.iter(), |&asset| {
+                    load_gpu_image_asset(device, asset)
+                })
+                .run()
+        };
+        /*let loaded_images = {
+            let device = self.device.clone();
+            unique_images
+                .iter()
+                .map(|&asset| load_gpu_image_asset(device.clone(), asset))
+                .collect::<Vec<_>>()
+        };*/
+        let loaded_images = loaded_images.into_iter().map(|img| self.add_image(img));
+
+        let material_map_to_image: HashMap<AssetRef<GpuImage::Flat>, BindlessImageHandle> =
+            unique_images.into_iter().zip(loaded_images).collect();
+
+        let mut materials = mesh.materials.as_slice().to_vec();
+        {
+            let mesh_map_gpu_ids: Vec<BindlessImageHandle> = mesh
+                .maps
+                .as_slice()
+                .iter()
+                .map(|map| material_map_to_image[map])
+                .collect();
+
+            for mat in &mut materials {
+                for m in &mut mat.maps {
+                    *m = mesh_map_gpu_ids[*m as usize].0;
+                }
+            }
+        }
+
+        MeshSource {
+            verts: Cow::Borrowed(mesh.verts.as_slice()),
+            uvs: Cow::Borrowed(mesh.uvs.as_slice()),
+            tangents: Cow::Borrowed(mesh.tangents.as_slice()),
+            colors: Cow::Borrowed(mesh.colors.as_slice()),
+            indices: Cow::Borrowed(mesh.indices.as_slice()),
+            opaque_index_count: mesh.opaque_index_count as usize,
+            material_ids: Cow::Borrowed(mesh.material_ids.as_slice()),
+            materials,
+            material_variants: Cow::Borrowed(mesh.material_variants.as_slice()),
+            geometry_scale: mesh.geometry_scale,
+        }
+    }
+
+    pub(crate) fn add_mesh_impl(
+        &mut self,
+        mesh: MeshSource,
+        opts: AddMeshOptions,
+        skin: Option<&MeshSkin>,
+    ) -> MeshHandle {
+        let mesh_idx = self.allocate_mesh_slot();
+        let mut materials = mesh.materials.clone();
+
+        // If using emissives as lights, flag it in the material parameters
+        if opts.use_lights {
+            for mat in materials.iter_mut() {
+                mat.flags |= MeshMaterialFlags::MESH_MATERIAL_FLAG_EMISSIVE_USED_AS_LIGHT;
+            }
+        }
+
+        let mut buffer_builder = BufferBuilder::new();
+        let vertex_index_offset = buffer_builder.append(mesh.indices.clone()) as u32;
+        let vertex_core_offset = buffer_builder.append(mesh.verts.clone()) as u32;
+        let vertex_uv_offset = buffer_builder.append(mesh.uvs.clone()) as u32;
+        let vertex_mat_offset = buffer_builder.append(mesh.material_ids.clone()) as u32;
+        let vertex_aux_offset = self
+            .material_features
+            .vertex_colors
+            .then(|| buffer_builder.append(mesh.colors.clone()) as u32);
+        let vertex_tangent_offset = self
+            .material_features
+            .normal_maps
+            .then(|| buffer_builder.append(mesh.tangents.clone()) as u32);
+
+        // Four 16-bit joint indices per vertex, packed in pairs
+        let skin_offsets = skin.map(|skin| {
+            let joints: Vec<[u32; 2]> = skin
+                .joints
+                .iter()
+                .map(|j| {
+                    [
+                        j[0] as u32 | (j[1] as u32) << 16,
+                        j[2] as u32 | (j[3] as u32) << 16,
+                    ]
+                })
+                .collect();
+
+            (
+                buffer_builder.append(joints) as u32,
+                buffer_builder.append(skin.weights.clone()) as u32,
+            )
+        });
+
+        let vertex_range = self.allocate_vertex_buffer_range(buffer_builder.current_offset());
+        let vertex_data_offset = vertex_range.start as u32;
+
+        let vertex_index_offset = vertex_index_offset + vertex_data_offset;
+        let vertex_core_offset = vertex_core_offset + vertex_data_offset;
+        let vertex_uv_offset = vertex_uv_offset + vertex_data_offset;
+        let vertex_mat_offset = vertex_mat_offset + vertex_data_offset;
+        // Zero offsets mark the streams as absent; see `MaterialFeatures`.
+        let vertex_aux_offset = vertex_aux_offset.map_or(0, |offset| offset + vertex_data_offset);
+        let vertex_tangent_offset =
+            vertex_tangent_offset.map_or(0, |offset| offset + vertex_data_offset);
+
+        let material_range = self
+            .material_buffer_allocator
+            .allocate(materials.len() as u64);
+        assert!(
+            material_range.end as usize <= MAX_GPU_MATERIALS,
+            "too many materials"
+        );
+        let material_offset = material_range.start as u32;
+        self.write_material_records(
+            material_range
+                .clone()
+                .map(|idx| idx as u32)
+                .zip(materials.iter().copied()),
+        );
+
+        let mut vertex_buffer = self.vertex_buffer.lock();
+        buffer_builder
+            .upload(
+                self.device.as_ref(),
+                Arc::get_mut(&mut *vertex_buffer).expect("refs may not be retained"),
+                vertex_range.start,
+            )
+            .map_err(|err| self.device.report_error(err))
+            .unwrap();
+
+        let mesh_buffer_dst = unsafe {
+            let mut mesh_buffer = self.mesh_buffer.lock();
+            let mesh_buffer = Arc::get_mut(&mut *mesh_buffer).expect("refs may not be retained");
+            let mesh_buffer_dst =
+                mesh_buffer.allocation.mapped_ptr().unwrap().as_ptr() as *mut GpuMesh;
+            std::slice::from_raw_parts_mut(mesh_buffer_dst, MAX_GPU_MESHES)
+        };
+
+        let blas_desc = if self.device.ray_tracing_enabled() {
+            let base_da = vertex_buffer.device_address(&self.device);
+
+            let desc = RayTracingBottomAccelerationDesc {
+                geometries: mesh_rt_geometries(
+                    base_da + vertex_core_offset as u64,
+                    base_da + vertex_index_offset as u64,
+                    mesh.indices.len(),
+                    mesh.opaque_index_count,
+                    mesh.indices
+                        .iter()
+                        .copied()
+                        .max()
+                        .expect("mesh must not be empty"),
+                ),
+                allow_update: opts.dynamic,
+            };
+
+            let blas = self
+                .device
+                .create_ray_tracing_bottom_acceleration(&desc)
+                .expect("blas");
+
+            set_or_push(&mut self.mesh_blas, mesh_idx, Some(Arc::new(blas)));
+
+            if !opts.dynamic && skin.is_none() {
+                self.static_mesh_rt_geometry
+                    .insert(MeshHandle(mesh_idx), desc.geometries.clone());
+            }
+
+            Some(desc)
+        } else {
+            None
+        };
+
+        mesh_buffer_dst[mesh_idx] = GpuMesh {
+            vertex_core_offset,
+            vertex_uv_offset,
+            vertex_mat_offset,
+            vertex_aux_offset,
+            vertex_tangent_offset,
+            material_offset,
+            index_offset: vertex_index_offset,
+            ray_bias: mesh.geometry_scale.ray_bias(),
+            opaque_triangle_count: (mesh.opaque_index_count / 3) as u32,
+        };
+
+        set_or_push(
+            &mut self.meshes,
+            mesh_idx,
+            UploadedTriMesh {
+                index_buffer_offset: vertex_index_offset as u64,
+                index_count: mesh.indices.len() as _,
+            },
+        );
+
+        set_or_push(&mut self.mesh_bounds, mesh_idx, vertex_bounds(&mesh.verts));
+
+        set_or_push(&mut self.mesh_vertex_ranges, mesh_idx, Some(vertex_range));
+
+        let mesh_lights = {
+            let is_emissive = |mat: &MeshMaterial| mat.emissive.iter().any(|&e| e > 0.0);
+
+            // Include materials which only become emissive in some variants,
+            // so that switching to them lights up the scene.
+            let mut emissive_materials = mesh
+                .materials
+                .iter()
+                .map(is_emissive)
+                .collect::<Vec<bool>>();
+            for variant in mesh.material_variants.iter() {
+                if is_emissive(&mesh.materials[variant.replacement as usize]) {
+                    emissive_materials[variant.material as usize] = true;
+                }
+            }
+
+            let mut mesh_lights: Vec<TriangleLight> = Vec::new();
+            let mut light_materials: Vec<u32> = Vec::new();
+            for indices in mesh.indices.chunks_exact(3) {
+                let mat_idx = mesh.material_ids[indices[0] as usize] as usize;
+                if !emissive_materials[mat_idx] {
+                    continue;
+                }
+
+                let v0 = mesh.verts[indices[0] as usize].pos;
+                let v1 = mesh.verts[indices[1] as usize].pos;
+                let v2 = mesh.verts[indices[2] as usize].pos;
+                let radiance = mesh.materials[mat_idx].emissive;
+
+                mesh_lights.push(TriangleLight {
+                    verts: [v0, v1, v2],
+                    radiance,
+                });
+                light_materials.push(mat_idx as u32);
+            }
+
+            MeshLightSet {
+                lights: mesh_lights,
+                light_materials,
+                use_lights: opts.use_lights,
+            }
+        };
+
+        set_or_push(&mut self.mesh_lights, mesh_idx, mesh_lights);
+
+        set_or_push(
+            &mut self.mesh_materials,
+            mesh_idx,
+            materials
+                .into_iter()
+                .enumerate()
+                .map(|(i, authored)| UploadedMaterial {
+                    material_buffer_index: material_offset + i as u32,
+                    authored,
+                    active: authored,
+                    active_variant: None,
+                    variants: mesh
+                        .material_variants
+                        .iter()
+                        .filter(|variant| variant.material == i as u32)
+                        .copied()
+                        .collect(),
+                    emissive_multiplier: Vec3::ONE,
+                    transmission: None,
+                    category: None,
+                })
+                .collect(),
+        );
+
+        if opts.dynamic {
+            self.dynamic_meshes.insert(
+                MeshHandle(mesh_idx),
+                DynamicMesh {
+                    vertex_count: mesh.verts.len(),
+                    vertex_core_offset,
+                    blas_desc,
+                    pending_verts: None,
+                },
+            );
+        }
+
+        if let (Some(skin), Some((joint_offset, weight_offset))) = (skin, skin_offsets) {
+            self.mesh_skins.insert(
+                MeshHandle(mesh_idx),
+                SkinnedMesh {
+                    verts: mesh.verts,
+                    tangents: mesh.tangents,
+                    indices: mesh.indices,
+                    joint_count: skin
+                        .joints
+                        .iter()
+                        .flatten()
+                        .max()
+                        .map_or(0, |&joint| joint as usize + 1),
+                    joint_offset: joint_offset + vertex_data_offset,
+                    weight_offset: weight_offset + vertex_data_offset,
+                    opaque_index_count: mesh.opaque_index_count,
+                },
+            );
+        }
+
+        MeshHandle(mesh_idx)
+    }
+
+    /// Replaces the vertices of a mesh added with `AddMeshOptions::dynamic`, e.g. for cloth or
+    /// water simulated on the CPU. The vertices are uploaded, and the BLAS refit, in the next frame;
+    /// of several updates before it, only the last one is used.
+    ///
+    /// Uploads go through a staging buffer of `MAX_VERTEX_UPLOAD_BYTES_PER_FRAME` (32 MiB),
+    /// or about 2M vertices, per frame. A single update can't be larger than that, and meshes
+    /// whose updates don't fit along with others are uploaded in a later frame.
+    ///
+    /// Indices, UVs and tangents stay as they were, and so do the emissive triangle lights.
+    pub fn update_mesh_vertices(&mut self, mesh: MeshHandle, verts: &[PackedVertex]) {
+        let dynamic = self
+            .dynamic_meshes
+            .get_mut(&mesh)
+            .expect("mesh must be added with AddMeshOptions::dynamic");
+        assert_eq!(
+            verts.len(),
+            dynamic.vertex_count,
+            "the vertex count of a mesh can't change"
+        );
+        assert!(
+            std::mem::size_of_val(verts) <= mesh_update::MAX_VERTEX_UPLOAD_BYTES_PER_FRAME,
+            "updates of {} vertices exceed the per-frame upload limit of {} bytes",
+            verts.len(),
+            mesh_update::MAX_VERTEX_UPLOAD_BYTES_PER_FRAME
+        );
+
+        dynamic.pending_verts = Some(verts.to_vec());
+        self.mesh_bounds[mesh.0] = vertex_bounds(verts);
+    }
+
+    // Adds a mesh sharing everything with a skinned mesh but a copy of its bind pose vertices,
+    // which an instance then deforms. The copy gets a refittable BLAS.
+    fn add_deformed_mesh(&mut self, skinned_mesh: MeshHandle) -> SkinnedInstance {
+        let mesh_idx = self.allocate_mesh_slot();
+        let skin = &self.mesh_skins[&skinned_mesh];
+        let (joint_count, joint_offset, weight_offset, opaque_index_count) = (
+            skin.joint_count,
+            skin.joint_offset,
+            skin.weight_offset,
+            skin.opaque_index_count,
+        );
+        let vertex_count = skin.verts.len();
+        let index_count = skin.indices.len();
+        let max_vertex = skin
+            .indices
+            .iter()
+            .copied()
+            .max()
+            .expect("mesh must not be empty");
+
+        let mut buffer_builder = BufferBuilder::new();
+        let core_offset = buffer_builder.append(skin.verts.clone()) as u32;
+        let tangent_offset = (!skin.tangents.is_empty() && self.material_features.normal_maps)
+            .then(|| buffer_builder.append(skin.tangents.clone()) as u32);
+
+        let vertex_range = self.allocate_vertex_buffer_range(buffer_builder.current_offset());
+        let dst_core = vertex_range.start as u32 + core_offset;
+        let dst_tangent = tangent_offset.map_or(0, |offset| vertex_range.start as u32 + offset);
+
+        let mut vertex_buffer = self.vertex_buffer.lock();
+        buffer_builder
+            .upload(
+                self.device.as_ref(),
+                Arc::get_mut(&mut *vertex_buffer).expect("refs may not be retained"),
+                vertex_range.start,
+            )
+            .map_err(|err| self.device.report_error(err))
+            .unwrap();
+
+        let mesh_buffer_dst = unsafe {
+            let mut mesh_buffer = self.mesh_buffer.lock();
+            let mesh_buffer = Arc::get_mut(&mut *mesh_buffer).expect("refs may not be retained");
+            let mesh_buffer_dst =
+                mesh_buffer.allocation.mapped_ptr().unwrap().as_ptr() as *mut GpuMesh;
+            std::slice::from_raw_parts_mut(mesh_buffer_dst, MAX_GPU_MESHES)
+        };
+
+        let src = mesh_buffer_dst[skinned_mesh.0];
+        mesh_buffer_dst[mesh_idx] = GpuMesh {
+            vertex_core_offset: dst_core,
+            vertex_tangent_offset: if dst_tangent != 0 {
+                dst_tangent
+            } else {
+                src.vertex_tangent_offset
+            },
+            ..src
+        };
+
+        let blas_desc = if self.device.ray_tracing_enabled() {
+            let base_da = vertex_buffer.device_address(&self.device);
+            let desc = RayTracingBottomAccelerationDesc {
+                geometries: mesh_rt_geometries(
+                    base_da + dst_core as u64,
+                    base_da + src.index_offset as u64,
+                    index_count,
+                    opaque_index_count,
+                    max_vertex,
+                ),
+                allow_update: true,
+            };
+
+            let blas = self
+                .device
+                .create_ray_tracing_bottom_acceleration(&desc)
+                .expect("blas");
+            set_or_push(&mut self.mesh_blas, mesh_idx, Some(Arc::new(blas)));
+
+            Some(desc)
+        } else {
+            None
+        };
+        drop(vertex_buffer);
+
+        let uploaded = self.meshes[skinned_mesh.0].clone();
+        set_or_push(&mut self.meshes, mesh_idx, uploaded);
+
+        let bounds = self.mesh_bounds[skinned_mesh.0];
+        set_or_push(&mut self.mesh_bounds, mesh_idx, bounds);
+
+        set_or_push(&mut self.mesh_vertex_ranges, mesh_idx, Some(vertex_range));
+        set_or_push(
+            &mut self.mesh_lights,
+            mesh_idx,
+            MeshLightSet {
+                lights: Vec::new(),
+                light_materials: Vec::new(),
+                use_lights: false,
+            },
+        );
+        set_or_push(&mut self.mesh_materials, mesh_idx, Vec::new());
+
+        SkinnedInstance {
+            skinned_mesh,
+            deformed_mesh: MeshHandle(mesh_idx),
+            offsets: SkinnedVertexOffsets {
+                vertex_count: vertex_count as u32,
+                src_core: src.vertex_core_offset,
+                src_tangent: src.vertex_tangent_offset,
+                dst_core,
+                dst_tangent,
+                joints: joint_offset,
+                weights: weight_offset,
+            },
+            blas_desc,
+            pose: vec![affine_to_row_major_3x4(&Affine3A::IDENTITY); joint_count],
+            pose_dirty: false,
+        }
+    }
+
+    /// Removes the mesh, along with all instances, instance batches and crowds of it. Instances
+    /// using it as their ray tracing proxy go back to tracing their own mesh. Like `remove_crowd`,
+    /// this stalls until the GPU is done with the buffers of removed crowds.
+    ///
+    /// Its vertex buffer range, materials, BLAS and slot in the mesh buffer are reclaimed once frames
+    /// in flight are done with them; the handle may then be returned by `add_mesh` again,
+    /// so it must not be used after this. Textures of the mesh are not freed.
+    pub fn remove_mesh(&mut self, mesh: MeshHandle) {
+        let vertex_range = self
+            .mesh_vertex_ranges
+            .get_mut(mesh.0)
+            .and_then(Option::take)
+            .expect("no such mesh");
+
+        let stale_instances: Vec<InstanceHandle> = self
+            .instance_handles
+            .iter()
+            .zip(&self.instances)
+            .filter(|(_, inst)| inst.mesh == mesh)
+            .map(|(handle, _)| *handle)
+            .chain(
+                self.skinned_instances
+                    .iter()
+                    .filter(|(_, skinned)| skinned.skinned_mesh == mesh)
+                    .map(|(handle, _)| *handle),
+            )
+            .collect();
+        for inst in stale_instances {
+            self.remove_instance(inst);
+        }
+        self.dynamic_meshes.remove(&mesh);
+        self.mesh_skins.remove(&mesh);
+        self.static_mesh_rt_geometry.remove(&mesh);
+        self.mesh_hit_groups.remove(&mesh);
+
+        for inst in &mut self.instances {
+            if inst.ray_tracing_proxy == Some(mesh) {
+                inst.ray_tracing_proxy = None;
+            }
+        }
+
+        // Crowds own their batches, and expand into them every frame.
+        let stale_crowds: Vec<CrowdHandle> = self
+            .crowds
+            .iter()
+            .filter(|(_, crowd)| {
+                self.instance_batches[self.instance_batch_handle_to_index[&crowd.batch]].mesh
+                    == mesh
+            })
+            .map(|(handle, _)| *handle)
+            .collect();
+        for crowd in stale_crowds {
+            self.remove_crowd(crowd);
+        }
+
+        let stale_batches: Vec<InstanceBatchHandle> = self
+            .instance_batch_handles
+            .iter()
+            .zip(&self.instance_batches)
+            .filter(|(_, batch)| batch.mesh == mesh)
+            .map(|(handle, _)| *handle)
+            .collect();
+        for batch in stale_batches {
+            self.remove_instance_batch(batch);
+        }
+
+        self.dirty_materials
+            .retain(|material| material.mesh != mesh);
+
+        self.meshes[mesh.0] = UploadedTriMesh {
+            index_buffer_offset: 0,
+            index_count: 0,
+        };
+        self.mesh_lights[mesh.0] = MeshLightSet {
+            lights: Vec::new(),
+            light_materials: Vec::new(),
+            use_lights: false,
+        };
+        let materials = std::mem::take(&mut self.mesh_materials[mesh.0]);
+        let material_range = materials.first().map_or(0..0, |first| {
+            let start = first.material_buffer_index as u64;
+            start..start + materials.len() as u64
+        });
+
+        self.pending_mesh_releases.push(PendingMeshRelease {
+            mesh_idx: mesh.0,
+            vertex_range,
+            material_range,
+            blas: self.mesh_blas.get_mut(mesh.0).and_then(Option::take),
+            frames_left: MESH_RELEASE_DELAY_FRAMES,
+        });
+    }
+
+    // Reclaims the resources of removed meshes which are no longer used by any frame in flight.
+    fn release_removed_meshes(&mut self) {
+        for pending in &mut self.pending_mesh_releases {
+            pending.frames_left = pending.frames_left.saturating_sub(1);
+        }
+
+        let (released, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut self.pending_mesh_releases)
+            .into_iter()
+            .partition(|pending| pending.frames_left == 0);
+        self.pending_mesh_releases = pending;
+
+        for released in released {
+            self.vertex_buffer_allocator.free(released.vertex_range);
+            self.material_buffer_allocator.free(released.material_range);
+            self.free_mesh_slots.push(released.mesh_idx);
+
+            if let Some(blas) = released.blas {
+                match Arc::try_unwrap(blas) {
+                    Ok(blas) => self.device.immediate_destroy_ray_tracing_acceleration(blas),
+                    Err(_) => warn!("BLAS of a removed mesh is still referenced; leaking it"),
+                }
+            }
+        }
+    }
+
+    // Instances of skinned meshes take a slot each, for their deformed copy.
+    fn allocate_mesh_slot(&mut self) -> usize {
+        let mesh_idx = self.free_mesh_slots.pop().unwrap_or(self.meshes.len());
+        assert!(
+            mesh_idx < MAX_GPU_MESHES,
+            "out of mesh slots: at most {} meshes, including one per instance of a skinned mesh, can exist at once",
+            MAX_GPU_MESHES
+        );
+        mesh_idx
+    }
+
+    pub(super) fn allocate_vertex_buffer_range(&mut self, size: u64) -> std::ops::Range<u64> {
+        let size = (size + VERTEX_BUFFER_ALIGNMENT - 1) & !(VERTEX_BUFFER_ALIGNMENT - 1);
+        self.vertex_buffer_allocator.allocate(size)
+    }
+
+    /// `transform` may include non-uniform scale (and mirroring); normals and emissive
+    /// triangle lights are transformed accordingly.
+    ///
+    /// Instances of skinned meshes get their own copy of its vertices; see `add_skinned_mesh`.
+    pub fn add_instance(&mut self, mesh: MeshHandle, transform: Affine3A) -> InstanceHandle {
+        let handle = InstanceHandle(self.next_instance_handle);
+        self.add_instance_with_handle(handle, mesh, transform);
+        handle
+    }
+
+    /// `add_instance` with a given, unused handle; see `apply_scene_diff`.
+    /// Later handles are allocated after it.
+    pub(super) fn add_instance_with_handle(
+        &mut self,
+        handle: InstanceHandle,
+        mesh: MeshHandle,
+        transform: Affine3A,
+    ) {
+        assert!(
+            !self.instance_handle_to_index.contains_key(&handle),
+            "instance handle already in use"
+        );
+        assert!(
+            self.instances.len() + self.instance_batches.len() < MAX_INSTANCES,
+            "too many instances: at most {} are supported, counting each batch as one",
+            MAX_INSTANCES
+        );
+        self.next_instance_handle = self.next_instance_handle.max(handle.0 + 1);
+
+        let skinned = self
+            .mesh_skins
+            .contains_key(&mesh)
+            .then(|| self.add_deformed_mesh(mesh));
+        let mesh = skinned
+            .as_ref()
+            .map_or(mesh, |skinned| skinned.deformed_mesh);
+
+        let index = self.instances.len();
+
+        self.instances.push(MeshInstance {
+            transform,
+            prev_transform: transform,
+            mesh,
+            ray_tracing_proxy: None,
+            dynamic_parameters: InstanceDynamicParameters::default(),
+            shutter_transforms: None,
+        });
+        self.instance_handles.push(handle);
+
+        assert_eq!(self.instances.len(), self.instance_handles.len());
+
+        self.instance_handle_to_index.insert(handle, index);
+
+        if let Some(skinned) = skinned {
+            self.skinned_instances.insert(handle, skinned);
+        }
+    }
+
+    /// The mesh the instance was added with. Instances of skinned meshes draw a deformed
+    /// copy of it, which is what `MeshInstance::mesh` refers to.
+    pub(super) fn instance_source_mesh(&self, inst: InstanceHandle) -> MeshHandle {
+        if let Some(skinned) = self.skinned_instances.get(&inst) {
+            skinned.skinned_mesh
+        } else {
+            self.instances[self.instance_handle_to_index[&inst]].mesh
+        }
+    }
+
+    pub fn remove_instance(&mut self, inst: InstanceHandle) {
+        let index = self
+            .instance_handle_to_index
+            .remove(&inst)
+            .expect("no such instance");
+        self.instances.swap_remove(index);
+        self.instance_handles.swap_remove(index);
+        self.static_instances.remove(&inst);
+
+        // A new instance could have been moved into this slot in the vec.
+        // Make sure `instance_handle_to_index` reflects this.
+        if let Some(new_handle) = self.instance_handles.get(index).copied() {
+            self.instance_handle_to_index.insert(new_handle, index);
+        }
+
+        if let Some(skinned) = self.skinned_instances.remove(&inst) {
+            self.remove_mesh(skinned.deformed_mesh);
+        }
+    }
+
+    /// Poses an instance of a skinned mesh. Each of `joint_transforms` takes vertices from
+    /// the bind pose to the posed object space of the instance, i.e. it's the joint's transform
+    /// relative to the instance, times its inverse bind matrix.
+    ///
+    /// Vertices are skinned, and the BLAS of the instance refit, in the next frame.
+    /// They're overwritten in place, with no copy of the previous pose kept, so motion
+    /// vectors only follow the instance transform, not the animation.
+    pub fn set_instance_pose(&mut self, inst: InstanceHandle, joint_transforms: &[Mat4]) {
+        let skinned = self
+            .skinned_instances
+            .get_mut(&inst)
+            .expect("not an instance of a skinned mesh");
+        assert!(
+            joint_transforms.len() >= self.mesh_skins[&skinned.skinned_mesh].joint_count,
+            "a transform is needed for every joint of the skin"
+        );
+
+        skinned.pose = joint_transforms
+            .iter()
+            .map(|xform| affine_to_row_major_3x4(&Affine3A::from_mat4(*xform)))
+            .collect();
+        skinned.pose_dirty = true;
+    }
+
+    pub fn set_instance_transform(&mut self, inst: InstanceHandle, transform: Affine3A) {
+        let index = self.instance_handle_to_index[&inst];
+        self.instances[index].transform = transform;
+    }
+
+    /// Like `set_instance_transform`, but with an explicit previous-frame transform used
+    /// for velocity and motion blur, for engines which interpolate their own transforms
+    /// (e.g. fixed-timestep physics). Otherwise the previous frame's `transform` is used.
+    ///
+    /// Only affects the next rendered frame; call it every frame to keep control of it.
+    pub fn set_instance_transform_with_previous(
+        &mut self,
+        inst: InstanceHandle,
+        transform: Affine3A,
+        prev_transform: Affine3A,
+    ) {
+        let index = self.instance_handle_to_index[&inst];
+        self.instances[index].transform = transform;
+        self.instances[index].prev_transform = prev_transform;
+    }
+
+    /// Motion-blurs the instance along its movement between `shutter_open` and `shutter_close`,
+    /// rather than since the previous frame. Useful for fast objects (e.g. wheels and rotors)
+    /// whose sub-frame motion is poorly approximated by the frame-to-frame one.
+    /// Velocities used for temporal reprojection are unaffected.
+    ///
+    /// Only affects the next rendered frame; call it every frame to keep control of it.
+    /// Instance batches and crowds don't support shutter transforms.
+    pub fn set_instance_shutter_transforms(
+        &mut self,
+        inst: InstanceHandle,
+        shutter_open: Affine3A,
+        shutter_close: Affine3A,
+    ) {
+        let index = self.instance_handle_to_index[&inst];
+        self.instances[index].shutter_transforms = Some([shutter_open, shutter_close]);
+    }
+
+    /// Traces shadow and diffuse GI rays against `proxy` instead of the instance's own mesh,
+    /// cutting the cost of the most numerous rays for high-poly assets. Reflections and other
+    /// rays still see the mesh itself. The proxy goes into the TLAS as a separate instance,
+    /// so it should roughly match the silhouette of the mesh to avoid self-shadowing artifacts,
+    /// and use compatible materials, as it's what GI will bounce off.
+    ///
+    /// Instances with a proxy aren't merged into the combined BLAS of static instances.
+    /// Pass `None` to go back to tracing the mesh itself.
+    pub fn set_instance_ray_tracing_proxy(
+        &mut self,
+        inst: InstanceHandle,
+        proxy: Option<MeshHandle>,
+    ) {
+        let index = self.instance_handle_to_index[&inst];
+        self.instances[index].ray_tracing_proxy = proxy;
+    }
+
+    pub fn get_instance_dynamic_parameters(
+        &self,
+        inst: InstanceHandle,
+    ) -> &InstanceDynamicParameters {
+        let index = self.instance_handle_to_index[&inst];
+        &self.instances[index].dynamic_parameters
+    }
+
+    pub fn get_instance_dynamic_parameters_mut(
+        &mut self,
+        inst: InstanceHandle,
+    ) -> &mut InstanceDynamicParameters {
+        let index = self.instance_handle_to_index[&inst];
+        &mut self.instances[index].dynamic_parameters
+    }
+
+    pub fn mesh_material_count(&self, mesh: MeshHandle) -> usize {
+        self.mesh_materials[mesh.0].len()
+    }
+
+    /// The material as currently rendered: as authored, or as replaced by the active
+    /// material variant, and with any edits since. Maps are bindless image handles,
+    /// as returned by `add_image`. Excludes the emissive multiplier and transmission override.
+    pub fn get_material(&self, material: MaterialHandle) -> &MeshMaterial {
+        &self.mesh_materials[material.mesh.0][material.material_index as usize].active
+    }
+
+    /// Edits a material for all instances of its mesh, e.g. its base color factor, roughness,
+    /// metalness, or map bindings. Changes are written to the material buffer by the next
+    /// `prepare_render_graph`, without touching the mesh itself, so this is cheap enough
+    /// to call every frame.
+    ///
+    /// Edits last until the material is switched to another variant. Emission still only
+    /// has triangle lights if the mesh was baked with them. Unlike `set_material`,
+    /// GI history is not shortened.
+    pub fn get_material_mut(&mut self, material: MaterialHandle) -> &mut MeshMaterial {
+        self.material_modified(material);
+        &mut self.mesh_materials[material.mesh.0][material.material_index as usize].active
+    }
+
+    /// Replaces a material for all instances of its mesh; see `get_material_mut`.
+    /// GI history is shortened in proportion to the change in emission.
+    pub fn set_material(&mut self, material: MaterialHandle, mut record: MeshMaterial) {
+        let uploaded = &mut self.mesh_materials[material.mesh.0][material.material_index as usize];
+
+        // Whether emission is sampled via triangle lights is up to `add_mesh`.
+        let light_flag = MeshMaterialFlags::MESH_MATERIAL_FLAG_EMISSIVE_USED_AS_LIGHT;
+        record.flags = (record.flags & !light_flag) | (uploaded.active.flags & light_flag);
+
+        let prev_emissive =
+            (Vec3::from(uploaded.active.emissive) * uploaded.emissive_multiplier).max_element();
+        let emissive = (Vec3::from(record.emissive) * uploaded.emissive_multiplier).max_element();
+
+        uploaded.active = record;
+        self.emissive_changed(prev_emissive, emissive);
+        self.material_modified(material);
+    }
+
+    pub fn get_material_emissive_multiplier(&self, material: MaterialHandle) -> Vec3 {
+        self.mesh_materials[material.mesh.0][material.material_index as usize].emissive_multiplier
+    }
+
+    /// Scales the emission of a material for all instances of its mesh, e.g. to animate
+    /// blinking lights or screens. Combines with the per-instance `emissive_multiplier`.
+    ///
+    /// The material's triangle lights follow along, and GI history is shortened
+    /// in proportion to the change, so that indirect lighting keeps up with it.
+    pub fn set_material_emissive_multiplier(&mut self, material: MaterialHandle, multiplier: Vec3) {
+        let uploaded = &mut self.mesh_materials[material.mesh.0][material.material_index as usize];
+        if uploaded.emissive_multiplier == multiplier {
+            return;
+        }
+
+        let active_emissive = Vec3::from(uploaded.active.emissive);
+        let prev_emissive = (active_emissive * uploaded.emissive_multiplier).max_element();
+        let emissive = (active_emissive * multiplier).max_element();
+
+        uploaded.emissive_multiplier = multiplier;
+        self.emissive_changed(prev_emissive, emissive);
+        self.material_modified(material);
+    }
+
+    // Shortens GI history in proportion to a change in the peak emission of a material
+    pub(super) fn emissive_changed(&mut self, prev_emissive: f32, emissive: f32) {
+        if prev_emissive.max(emissive) > 0.0 {
+            self.material_change = self
+                .material_change
+                .max((emissive - prev_emissive).abs() / prev_emissive.max(emissive));
+        }
+    }
+
+    pub fn add_light(&mut self, light: PointLight) -> LightHandle {
+        let handle = LightHandle(self.next_light_handle);
+        self.add_light_with_handle(handle, light);
+        handle
+    }
+
+    /// `add_light` with a given, unused handle; see `apply_scene_diff`.
+    pub(super) fn add_light_with_handle(&mut self, handle: LightHandle, light: PointLight) {
+        assert!(
+            !self.point_lights.contains_key(&handle),
+            "light handle already in use"
+        );
+        self.next_light_handle = self.next_light_handle.max(handle.0 + 1);
+
+        self.emissive_changed(0.0, light.peak_intensity(self.world_units_per_meter));
+        self.point_lights.insert(handle, light);
+    }
+
+    pub fn get_light(&self, handle: LightHandle) -> PointLight {
+        *self.point_lights.get(&handle).expect("no such light")
+    }
+
+    /// Moves or changes a light. GI history is shortened accordingly, as with emissive materials.
+    pub fn set_light(&mut self, handle: LightHandle, light: PointLight) {
+        let prev = self
+            .point_lights
+            .insert(handle, light)
+            .expect("no such light");
+
+        if prev == light {
+            return;
+        }
+
+        self.emissive_changed(
+            prev.peak_intensity(self.world_units_per_meter),
+            light.peak_intensity(self.world_units_per_meter),
+        );
+
+        // Moving or aiming a light shifts its bounce lighting around, even if its intensity
+        // is the same. Only shorten the history, so that lights carried around don't make
+        // GI too noisy.
+        if prev.position != light.position || prev.spot != light.spot {
+            self.material_change = self.material_change.max(0.5);
+        }
+    }
+
+    pub fn remove_light(&mut self, handle: LightHandle) {
+        let prev = self.point_lights.remove(&handle).expect("no such light");
+
+        self.emissive_changed(prev.peak_intensity(self.world_units_per_meter), 0.0);
+    }
+
+    pub fn add_rect_light(&mut self, light: RectLight) -> RectLightHandle {
+        let handle = RectLightHandle(self.next_light_handle);
+        self.add_rect_light_with_handle(handle, light);
+        handle
+    }
+
+    /// `add_rect_light` with a given, unused handle; see `apply_scene_diff`.
+    pub(super) fn add_rect_light_with_handle(&mut self, handle: RectLightHandle, light: RectLight) {
+        assert!(
+            !self.rect_lights.contains_key(&handle),
+            "light handle already in use"
+        );
+        self.next_light_handle = self.next_light_handle.max(handle.0 + 1);
+
+        self.rect_lights.insert(handle, light);
+    }
+
+    pub fn get_rect_light(&self, handle: RectLightHandle) -> RectLight {
+        *self.rect_lights.get(&handle).expect("no such light")
+    }
+
+    pub fn set_rect_light(&mut self, handle: RectLightHandle, light: RectLight) {
+        *self.rect_lights.get_mut(&handle).expect("no such light") = light;
+    }
+
+    pub fn remove_rect_light(&mut self, handle: RectLightHandle) {
+        self.rect_lights.remove(&handle).expect("no such light");
+    }
+
+    /// Lights the scene with a lat-long HDR image instead of the procedural sky.
+    /// The image must be `SAMPLED`. See `IblRenderer` for rotation and intensity.
+    pub fn set_environment_map(&mut self, image: Arc<Image>) {
+        self.ibl.set_texture(image);
+        self.reset_reference_accumulation = true;
+    }
+
+    /// Goes back to the procedural sky.
+    pub fn clear_environment_map(&mut self) {
+        self.ibl.unload_image();
+        self.reset_reference_accumulation = true;
+    }
+
+    /// Adds Gaussian splats, such as a capture loaded with
+    /// `kajiya_asset::gaussian_splats::load_gaussian_splats`; see `GaussianSplatRenderer`.
+    pub fn add_gaussian_splats(
+        &mut self,
+        splats: &GaussianSplats,
+        transform: Affine3A,
+    ) -> anyhow::Result<GaussianSplatHandle> {
+        anyhow::ensure!(!splats.is_empty(), "no Gaussian splats to add");
+
+        Ok(self
+            .gaussian_splats
+            .add(self.device.as_ref(), splats, transform)?)
+    }
+
+    pub fn set_gaussian_splats_transform(
+        &mut self,
+        handle: GaussianSplatHandle,
+        transform: Affine3A,
+    ) {
+        self.gaussian_splats.set_transform(handle, transform);
+    }
+
+    pub fn remove_gaussian_splats(&mut self, handle: GaussianSplatHandle) {
+        self.gaussian_splats.remove(handle);
+    }
+
+    pub fn get_material_transmission(&self, material: MaterialHandle) -> MaterialTransmission {
+        let uploaded = &self.mesh_materials[material.mesh.0][material.material_index as usize];
+        uploaded.transmission.unwrap_or(MaterialTransmission {
+            factor: uploaded.active.transmission,
+            thickness_map: None,
+        })
+    }
+
+    /// Makes a material let diffuse light through from its back side, for all instances
+    /// of its mesh. Backlit foliage then glows instead of going black, both on screen,
+    /// and as seen by GI and reflections. Overrides the authored transmission,
+    /// including that of material variants.
+    pub fn set_material_transmission(
+        &mut self,
+        material: MaterialHandle,
+        transmission: MaterialTransmission,
+    ) {
+        let uploaded = &mut self.mesh_materials[material.mesh.0][material.material_index as usize];
+        if uploaded.transmission == Some(transmission) {
+            return;
+        }
+
+        uploaded.transmission = Some(transmission);
+        self.material_modified(material);
+    }
+
+    pub fn get_material_category(&self, material: MaterialHandle) -> MaterialCategory {
+        let uploaded = &self.mesh_materials[material.mesh.0][material.material_index as usize];
+        uploaded
+            .category
+            .unwrap_or_else(|| uploaded.active.category())
+    }
 
-            sun_size_multiplier: 1.0, // Sun as seen from Earth
-            sun_color_multiplier: Vec3::ONE,
-            sky_ambient: Vec3::ZERO,
+    /// Assigns a material to a category, whose `material_categories` settings then apply
+    /// to all instances of its mesh. Overrides the authored category, including that
+    /// of material variants.
+    pub fn set_material_category(&mut self, material: MaterialHandle, category: MaterialCategory) {
+        let uploaded = &mut self.mesh_materials[material.mesh.0][material.material_index as usize];
+        if uploaded.category == Some(category) {
+            return;
+        }
 
-            render_overrides: Default::default(),
+        uploaded.category = Some(category);
+        self.material_modified(material);
+    }
 
-            exposure_state: Default::default(),
-        })
+    /// Switches all meshes to a material variant authored at bake time, or back to
+    /// their base materials with `None`. Variants are glTF materials named after the base
+    /// material and the variant, e.g. `Window@night`; see `MATERIAL_VARIANT_SEPARATOR`.
+    /// Materials without the variant use their base version.
+    pub fn set_material_variant(&mut self, variant: Option<&str>) {
+        let meshes: Vec<MeshHandle> = (0..self.mesh_materials.len()).map(MeshHandle).collect();
+        self.set_material_variant_for_meshes(&meshes, variant);
     }
 
-    fn write_descriptor_set_buffer(
-        device: &kajiya_backend::ash::Device,
-        set: vk::DescriptorSet,
-        dst_binding: u32,
-        buffer: &Buffer,
+    /// Like `set_material_variant`, but only affects the specified meshes.
+    pub fn set_material_variant_for_meshes(
+        &mut self,
+        meshes: &[MeshHandle],
+        variant: Option<&str>,
     ) {
-        let buffer_info = vk::DescriptorBufferInfo::builder()
-            .buffer(buffer.raw)
-            .range(vk::WHOLE_SIZE)
-            .build();
+        let variant_id = variant.map(material_variant_id);
+
+        for &mesh in meshes {
+            for material_index in 0..self.mesh_materials[mesh.0].len() {
+                let materials = &mut self.mesh_materials[mesh.0];
+                let replacement = variant_id.and_then(|variant_id| {
+                    materials[material_index]
+                        .variants
+                        .iter()
+                        .find(|variant| variant.variant_id == variant_id)
+                        .map(|variant| variant.replacement)
+                });
 
-        let write_descriptor_set = vk::WriteDescriptorSet::builder()
-            .dst_set(set)
-            .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
-            .dst_binding(dst_binding)
-            .buffer_info(std::slice::from_ref(&buffer_info))
-            .build();
+                if replacement == materials[material_index].active_variant {
+                    continue;
+                }
 
-        unsafe {
-            device.update_descriptor_sets(std::slice::from_ref(&write_descriptor_set), &[]);
+                materials[material_index].active =
+                    materials[replacement.map_or(material_index, |idx| idx as usize)].authored;
+                materials[material_index].active_variant = replacement;
+
+                // Anything could have changed; drop GI history.
+                self.material_change = 1.0;
+                self.material_modified(MaterialHandle::new(mesh, material_index as u32));
+            }
         }
     }
 
-    fn add_bindless_image_view(&mut self, view: ImageView) -> BindlessImageHandle {
-        let handle = BindlessImageHandle(self.next_bindless_image_id as _);
-        self.next_bindless_image_id += 1;
+    // Schedules the triangle lights and GPU record of the material for an update
+    fn material_modified(&mut self, material: MaterialHandle) {
+        self.dirty_materials.insert(material);
+    }
 
-        let image_info = vk::DescriptorImageInfo::builder()
-            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-            .image_view(view)
-            .build();
+    // Updates triangle lights of modified materials, and patches their records in `material_buffer`
+    fn upload_dirty_materials(&mut self, rg: &mut rg::TemporalRenderGraph) {
+        if self.dirty_materials.is_empty() {
+            return;
+        }
 
-        let write_descriptor_set = vk::WriteDescriptorSet::builder()
-            .dst_set(self.bindless_descriptor_set)
-            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
-            .dst_binding(BINDLESS_TEXURES_BINDING_INDEX as _)
-            .dst_array_element(handle.0 as _)
-            .image_info(std::slice::from_ref(&image_info))
-            .build();
+        let dirty_materials = std::mem::take(&mut self.dirty_materials);
 
-        unsafe {
-            self.device
-                .raw
-                .update_descriptor_sets(std::slice::from_ref(&write_descriptor_set), &[]);
-        }
+        for material in &dirty_materials {
+            let uploaded = &self.mesh_materials[material.mesh.0][material.material_index as usize];
+            let radiance = Vec3::from(uploaded.active.emissive) * uploaded.emissive_multiplier;
 
-        handle
-    }
+            let light_set = &mut self.mesh_lights[material.mesh.0];
+            for (light, &light_material) in light_set
+                .lights
+                .iter_mut()
+                .zip(light_set.light_materials.iter())
+            {
+                if light_material == material.material_index {
+                    light.radiance = radiance.into();
+                }
+            }
+        }
 
-    pub fn add_image_lut(&mut self, computer: impl ComputeImageLut + 'static, id: usize) {
-        self.image_luts
-            .push(ImageLut::new(self.device.as_ref(), Box::new(computer)));
+        let records: Vec<(u32, MeshMaterial)> = dirty_materials
+            .into_iter()
+            .map(|material| {
+                let uploaded =
+                    &self.mesh_materials[material.mesh.0][material.material_index as usize];
+                (uploaded.material_buffer_index, uploaded.gpu_record())
+            })
+            .collect();
 
-        let handle = self.add_bindless_image_view(
-            self.image_luts
-                .last()
-                .unwrap()
-                .backing_image()
-                .view(self.device.as_ref(), &ImageViewDesc::default())
-                .unwrap(),
+        // Frames still in flight read the same buffer, so the records are copied in on the GPU
+        // timeline rather than written through the mapping, which would change their materials
+        // half-way through.
+        let mut material_buffer = rg.import(
+            self.material_buffer.lock().clone(),
+            AccessType::AnyShaderReadOther,
         );
 
-        assert_eq!(handle.0 as usize, id);
-    }
-
-    pub fn add_image(&mut self, image: Arc<Image>) -> BindlessImageHandle {
-        let image_size: [f32; 4] = image.desc.extent_inv_extent_2d();
+        let mut pass = rg.add_pass("upload materials");
+        let material_buffer_ref = pass.write(&mut material_buffer, AccessType::TransferWrite);
 
-        let handle = self.add_bindless_image_view(
-            image
-                .view(self.device.as_ref(), &ImageViewDesc::default())
-                .unwrap(),
-        );
+        pass.render(move |api| {
+            let record_size = size_of::<MeshMaterial>() as u64;
+            let src_offset = api
+                .resources
+                .dynamic_constants
+                .push_from_iter(records.iter().map(|&(_, record)| record))
+                as u64;
+
+            let regions: Vec<vk::BufferCopy> = records
+                .iter()
+                .enumerate()
+                .map(|(i, &(index, _))| vk::BufferCopy {
+                    src_offset: src_offset + i as u64 * record_size,
+                    dst_offset: index as u64 * record_size,
+                    size: record_size,
+                })
+                .collect();
 
-        self.bindless_images.push(image);
+            let src_buffer = api.resources.dynamic_constants.buffer.raw;
+            let dst_buffer = api.resources.buffer(material_buffer_ref).raw;
 
-        bytemuck::checked::cast_slice_mut::<u8, [f32; 4]>(
-            self.bindless_texture_sizes
-                .allocation
-                .mapped_slice_mut()
-                .unwrap(),
-        )[handle.0 as usize] = image_size;
+            unsafe {
+                api.device()
+                    .raw
+                    .cmd_copy_buffer(api.cb.raw, src_buffer, dst_buffer, &regions);
+            }
 
-        handle
-    }
+            Ok(())
+        });
 
-    pub fn add_mesh(
-        &mut self,
-        mesh: &'static PackedTriMesh::Flat,
-        opts: AddMeshOptions,
-    ) -> MeshHandle {
-        let mesh_idx = self.meshes.len();
-        let mut unique_images: Vec<AssetRef<GpuImage::Flat>> = mesh.maps.as_slice().to_vec();
-        unique_images.sort();
-        unique_images.dedup();
+        // Shaders read the records through the bindless descriptor set, which the graph
+        // doesn't track; this makes the copy visible to them.
+        let mut pass = rg.add_pass("materials uploaded");
+        let _material_buffer_ref = pass.read(&material_buffer, AccessType::AnyShaderReadOther);
 
-        let loaded_images = {
-            let device = self.device.clone();
-            easy_parallel::Parallel::new()
-                .each(unique_images.iter(), |&asset| {
-                    load_gpu_image_asset(device, asset)
-                })
-                .run()
-        };
-        /*let loaded_images = {
-            let device = self.device.clone();
-            unique_images
-                .iter()
-                .map(|&asset| load_gpu_image_asset(device.clone(), asset))
-                .collect::<Vec<_>>()
-        };*/
-        let loaded_images = loaded_images.into_iter().map(|img| self.add_image(img));
+        if self.render_mode == RenderMode::Reference {
+            self.reset_reference_accumulation = true;
+        }
+    }
 
-        let material_map_to_image: HashMap<AssetRef<GpuImage::Flat>, BindlessImageHandle> =
-            unique_images.into_iter().zip(loaded_images).collect();
+    // Uploads new vertices of dynamic meshes, and deforms the vertices of instances posed
+    // since the last frame; then refits the BLAS of both.
+    //
+    // Both overwrite the vertices in place. That's safe against the previous frame, whose
+    // passes, including async compute ones joined back into it, come earlier on the universal
+    // queue, and the barrier from the import covers them. It does mean there are no previous
+    // positions though; see `set_instance_pose`.
+    fn update_deformed_meshes(&mut self, rg: &mut rg::TemporalRenderGraph) {
+        let any_vertex_updates = self
+            .dynamic_meshes
+            .values()
+            .any(|dynamic| dynamic.pending_verts.is_some());
+        let any_pose_updates = self
+            .skinned_instances
+            .values()
+            .any(|skinned| skinned.pose_dirty);
+
+        if !any_vertex_updates && !any_pose_updates {
+            return;
+        }
 
-        let mut materials = mesh.materials.as_slice().to_vec();
-        {
-            let mesh_map_gpu_ids: Vec<BindlessImageHandle> = mesh
-                .maps
-                .as_slice()
-                .iter()
-                .map(|map| material_map_to_image[map])
-                .collect();
+        let mut vertex_buffer = rg.import(
+            self.vertex_buffer.lock().clone(),
+            AccessType::AnyShaderReadOther,
+        );
 
-            for mat in &mut materials {
-                for m in &mut mat.maps {
-                    *m = mesh_map_gpu_ids[*m as usize].0;
+        let mut refits = Vec::new();
+        self.vertex_staging.begin_frame();
+        for (mesh, dynamic) in &mut self.dynamic_meshes {
+            // Ones which don't fit in the staging buffer wait for the next frame.
+            let fits = dynamic.pending_verts.as_ref().map_or(false, |verts| {
+                std::mem::size_of_val(verts.as_slice()) <= self.vertex_staging.remaining_bytes()
+            });
+
+            if fits {
+                let verts = dynamic.pending_verts.take().unwrap();
+                self.vertex_staging.upload_vertices(
+                    &self.device,
+                    rg,
+                    &mut vertex_buffer,
+                    dynamic.vertex_core_offset,
+                    &verts,
+                );
+
+                if let Some(desc) = &dynamic.blas_desc {
+                    let blas = self.mesh_blas[mesh.0].clone().expect("dynamic mesh BLAS");
+                    refits.push((desc.clone(), blas));
                 }
             }
         }
 
-        // If using emissives as lights, flag it in the material parameters
-        if opts.use_lights {
-            for mat in materials.iter_mut() {
-                mat.flags |= MeshMaterialFlags::MESH_MATERIAL_FLAG_EMISSIVE_USED_AS_LIGHT;
+        for skinned in self
+            .skinned_instances
+            .values_mut()
+            .filter(|skinned| skinned.pose_dirty)
+        {
+            skinning::skin_vertices(
+                rg,
+                &mut vertex_buffer,
+                skinned.offsets,
+                skinned.pose.clone(),
+            );
+
+            if let Some(desc) = &skinned.blas_desc {
+                let blas = self.mesh_blas[skinned.deformed_mesh.0]
+                    .clone()
+                    .expect("skinned instance BLAS");
+                refits.push((desc.clone(), blas));
             }
+
+            skinned.pose_dirty = false;
         }
 
-        let vertex_data_offset = self.vertex_buffer_written as u32;
+        mesh_update::refit_blas(rg, &vertex_buffer, refits, self.accel_scratch.clone());
 
-        let mut buffer_builder = BufferBuilder::new();
-        let vertex_index_offset =
-            buffer_builder.append(mesh.indices.as_slice()) as u32 + vertex_data_offset;
-        let vertex_core_offset =
-            buffer_builder.append(mesh.verts.as_slice()) as u32 + vertex_data_offset;
-        let vertex_uv_offset =
-            buffer_builder.append(mesh.uvs.as_slice()) as u32 + vertex_data_offset;
-        let vertex_mat_offset =
-            buffer_builder.append(mesh.material_ids.as_slice()) as u32 + vertex_data_offset;
-        let vertex_aux_offset =
-            buffer_builder.append(mesh.colors.as_slice()) as u32 + vertex_data_offset;
-        let vertex_tangent_offset =
-            buffer_builder.append(mesh.tangents.as_slice()) as u32 + vertex_data_offset;
-        let mat_data_offset = buffer_builder.append(materials) as u32 + vertex_data_offset;
+        if self.render_mode == RenderMode::Reference {
+            self.reset_reference_accumulation = true;
+        }
+    }
 
-        let total_buffer_size = buffer_builder.current_offset();
-        let mut vertex_buffer = self.vertex_buffer.lock();
-        buffer_builder
-            .upload(
-                self.device.as_ref(),
-                Arc::get_mut(&mut *vertex_buffer).expect("refs may not be retained"),
-                self.vertex_buffer_written,
+    // Writes records straight to the mapped `material_buffer`. Only for ranges which no frame
+    // in flight uses, i.e. those of newly added meshes; see `upload_dirty_materials`.
+    fn write_material_records(&self, records: impl Iterator<Item = (u32, MeshMaterial)>) {
+        let mut material_buffer = self.material_buffer.lock();
+        let material_buffer =
+            Arc::get_mut(&mut *material_buffer).expect("refs may not be retained");
+
+        let dst = unsafe {
+            std::slice::from_raw_parts_mut(
+                material_buffer.allocation.mapped_ptr().unwrap().as_ptr() as *mut MeshMaterial,
+                MAX_GPU_MATERIALS,
             )
-            .map_err(|err| self.device.report_error(err))
-            .unwrap();
-        self.vertex_buffer_written += total_buffer_size;
-
-        let mesh_buffer_dst = unsafe {
-            let mut mesh_buffer = self.mesh_buffer.lock();
-            let mesh_buffer = Arc::get_mut(&mut *mesh_buffer).expect("refs may not be retained");
-            let mesh_buffer_dst =
-                mesh_buffer.allocation.mapped_ptr().unwrap().as_ptr() as *mut GpuMesh;
-            std::slice::from_raw_parts_mut(mesh_buffer_dst, MAX_GPU_MESHES)
         };
 
-        if self.device.ray_tracing_enabled() {
-            let base_da = vertex_buffer.device_address(&self.device);
-            let vertex_buffer_da = base_da + vertex_core_offset as u64;
-            let index_buffer_da = base_da + vertex_index_offset as u64;
+        for (index, record) in records {
+            dst[index as usize] = record;
+        }
+    }
 
-            let blas = self
-                .device
-                .create_ray_tracing_bottom_acceleration(&RayTracingBottomAccelerationDesc {
-                    geometries: vec![RayTracingGeometryDesc {
-                        geometry_type: RayTracingGeometryType::Triangle,
-                        vertex_buffer: vertex_buffer_da,
-                        index_buffer: index_buffer_da,
-                        vertex_format: vk::Format::R32G32B32_SFLOAT,
-                        vertex_stride: size_of::<PackedVertex>(),
-                        parts: vec![RayTracingGeometryPart {
-                            index_count: mesh.indices.len(),
-                            index_offset: 0,
-                            max_vertex: mesh
-                                .indices
-                                .as_slice()
-                                .iter()
-                                .copied()
-                                .max()
-                                .expect("mesh must not be empty"),
-                        }],
-                    }],
-                })
-                .expect("blas");
+    /// Adds many instances of the same mesh at once. Cheaper than individual
+    /// instances, but they can only be moved together, and share dynamic parameters.
+    /// Batched instances don't contribute triangle lights.
+    pub fn add_instance_batch(
+        &mut self,
+        mesh: MeshHandle,
+        transforms: &[Affine3A],
+    ) -> InstanceBatchHandle {
+        let handle = InstanceBatchHandle(self.next_instance_batch_handle);
+        self.next_instance_batch_handle += 1;
+
+        let transform_offset = self.instance_batch_transforms.len();
+        assert!(
+            transform_offset + transforms.len() <= MAX_BATCHED_INSTANCES,
+            "too many batched instances: at most {} are supported",
+            MAX_BATCHED_INSTANCES
+        );
+        assert!(
+            self.instances.len() + self.instance_batches.len() < MAX_INSTANCES,
+            "too many instances: at most {} are supported, counting each batch as one",
+            MAX_INSTANCES
+        );
 
-            self.mesh_blas.push(Arc::new(blas));
-        }
+        self.instance_batch_transforms.extend_from_slice(transforms);
+        self.write_instance_batch_transforms(transform_offset, transforms, transforms);
 
-        mesh_buffer_dst[mesh_idx] = GpuMesh {
-            vertex_core_offset,
-            vertex_uv_offset,
-            vertex_mat_offset,
-            vertex_aux_offset,
-            vertex_tangent_offset,
-            mat_data_offset,
-            index_offset: vertex_index_offset,
-        };
+        let index = self.instance_batches.len();
 
-        self.meshes.push(UploadedTriMesh {
-            index_buffer_offset: vertex_index_offset as u64,
-            index_count: mesh.indices.len() as _,
+        self.instance_batches.push(InstanceBatch {
+            mesh,
+            dynamic_parameters: InstanceDynamicParameters::default(),
+            transform_offset: transform_offset as u32,
+            instance_count: transforms.len() as u32,
+            moved: false,
         });
+        self.instance_batch_handles.push(handle);
 
-        let mesh_lights = if opts.use_lights {
-            let emissive_materials = mesh
-                .materials
-                .iter()
-                .map(|mat| mat.emissive[0] > 0.0 || mat.emissive[1] > 0.0 || mat.emissive[2] > 0.0)
-                .collect::<Vec<bool>>();
+        assert_eq!(
+            self.instance_batches.len(),
+            self.instance_batch_handles.len()
+        );
 
-            let mut mesh_lights: Vec<TriangleLight> = Vec::new();
-            for indices in mesh.indices.as_slice().chunks_exact(3) {
-                let mat_idx = mesh.material_ids[indices[0] as usize] as usize;
-                if !emissive_materials[mat_idx] {
-                    continue;
-                }
+        self.instance_batch_handle_to_index.insert(handle, index);
 
-                let v0 = mesh.verts[indices[0] as usize].pos;
-                let v1 = mesh.verts[indices[1] as usize].pos;
-                let v2 = mesh.verts[indices[2] as usize].pos;
-                let radiance = mesh.materials[mat_idx].emissive;
+        handle
+    }
 
-                mesh_lights.push(TriangleLight {
-                    verts: [v0, v1, v2],
-                    radiance,
-                });
+    /// Batches of crowds must be removed with `remove_crowd` instead.
+    pub fn remove_instance_batch(&mut self, batch: InstanceBatchHandle) {
+        assert!(
+            self.crowds.values().all(|crowd| crowd.batch != batch),
+            "the instance batch belongs to a crowd; remove it with `remove_crowd`"
+        );
+
+        let index = self
+            .instance_batch_handle_to_index
+            .remove(&batch)
+            .expect("no such instance batch");
+        // Keep the batches in transform order, so that their TLAS instances
+        // and dynamic parameters stay in the same order as their transforms.
+        let removed = self.instance_batches.remove(index);
+        self.instance_batch_handles.remove(index);
+
+        for (index, handle) in self.instance_batch_handles.iter().enumerate().skip(index) {
+            self.instance_batch_handle_to_index.insert(*handle, index);
+        }
+
+        // Compact the transforms, and re-upload everything after the hole.
+        let removed_start = removed.transform_offset as usize;
+        let removed_end = removed_start + removed.instance_count as usize;
+        self.instance_batch_transforms
+            .drain(removed_start..removed_end);
+
+        for batch in &mut self.instance_batches {
+            if batch.transform_offset as usize >= removed_end {
+                batch.transform_offset -= removed.instance_count;
+                batch.moved = false;
             }
+        }
+
+        let tail = self.instance_batch_transforms[removed_start..].to_vec();
+        self.write_instance_batch_transforms(removed_start, &tail, &tail);
+    }
+
+    /// Replaces the transforms of all instances in the batch. The instance count must not change.
+    pub fn set_instance_batch_transforms(
+        &mut self,
+        batch: InstanceBatchHandle,
+        transforms: &[Affine3A],
+    ) {
+        let prev_transforms = self.get_instance_batch_transforms(batch).to_vec();
+        self.set_instance_batch_transforms_with_previous(batch, transforms, &prev_transforms);
+    }
+
+    /// Batch counterpart of `set_instance_transform_with_previous`.
+    pub fn set_instance_batch_transforms_with_previous(
+        &mut self,
+        batch: InstanceBatchHandle,
+        transforms: &[Affine3A],
+        prev_transforms: &[Affine3A],
+    ) {
+        let index = self.instance_batch_handle_to_index[&batch];
+        let batch = &mut self.instance_batches[index];
+        assert_eq!(
+            batch.instance_count as usize,
+            transforms.len(),
+            "instance count of a batch can't change"
+        );
+        assert_eq!(transforms.len(), prev_transforms.len());
+        batch.moved = true;
+
+        let range = batch.transform_offset as usize
+            ..batch.transform_offset as usize + batch.instance_count as usize;
+        self.instance_batch_transforms[range.clone()].copy_from_slice(transforms);
+        self.write_instance_batch_transforms(range.start, transforms, prev_transforms);
+    }
 
-            mesh_lights
+    pub fn get_instance_batch_transforms(&self, batch: InstanceBatchHandle) -> &[Affine3A] {
+        let batch = &self.instance_batches[self.instance_batch_handle_to_index[&batch]];
+        let start = batch.transform_offset as usize;
+        &self.instance_batch_transforms[start..start + batch.instance_count as usize]
+    }
+
+    pub fn get_instance_batch_dynamic_parameters_mut(
+        &mut self,
+        batch: InstanceBatchHandle,
+    ) -> &mut InstanceDynamicParameters {
+        let index = self.instance_batch_handle_to_index[&batch];
+        &mut self.instance_batches[index].dynamic_parameters
+    }
+
+    /// Access type to import `instance_batch_transform_buffer` with in the frame being prepared.
+    pub(super) fn instance_batch_transforms_import_access(&self) -> vk_sync::AccessType {
+        if self.instance_batch_transforms_written_on_gpu {
+            vk_sync::AccessType::ComputeShaderWrite
         } else {
-            Vec::new()
+            vk_sync::AccessType::AnyShaderReadOther
+        }
+    }
+
+    fn write_instance_batch_transforms(
+        &mut self,
+        offset: usize,
+        transforms: &[Affine3A],
+        prev_transforms: &[Affine3A],
+    ) {
+        self.gpu_instance_batch_transforms
+            .resize(self.instance_batch_transforms.len(), Default::default());
+        self.instance_batch_transforms_generation += 1;
+
+        for ((dst, transform), prev_transform) in self.gpu_instance_batch_transforms[offset..]
+            .iter_mut()
+            .zip(transforms)
+            .zip(prev_transforms)
+        {
+            *dst = [
+                affine_to_row_major_3x4(transform),
+                affine_to_row_major_3x4(prev_transform),
+            ];
+        }
+    }
+
+    // Makes `instance_batch_transform_buffer` hold the latest transforms. The GPU may still be
+    // reading the previous frames' buffers, so changes go to one it's done with.
+    fn update_instance_batch_transform_buffer(&mut self) {
+        let current = self.instance_batch_transform_buffer_index;
+        if self.instance_batch_transform_buffers[current].generation
+            == self.instance_batch_transforms_generation
+        {
+            return;
+        }
+
+        let buffer_index = (0..INSTANCE_BATCH_TRANSFORM_BUFFER_COUNT)
+            .filter(|&idx| idx != current)
+            .min_by_key(|&idx| self.instance_batch_transform_buffers[idx].gpu_done_timeline_value)
+            .unwrap();
+        let buffer = &mut self.instance_batch_transform_buffers[buffer_index];
+
+        if let Err(err) = self
+            .device
+            .wait_for_queue(&self.device.universal_queue, buffer.gpu_done_timeline_value)
+        {
+            error!(
+                "Failed to wait for the GPU to release the instance batch transforms: {:?}",
+                err
+            );
+        }
+
+        let capacity = buffer.buffer.desc.size / size_of::<GpuInstanceBatchTransform>();
+        if capacity < self.gpu_instance_batch_transforms.len() {
+            let capacity = self
+                .gpu_instance_batch_transforms
+                .len()
+                .next_power_of_two()
+                .min(MAX_BATCHED_INSTANCES);
+            let new_buffer = create_instance_batch_transform_buffer(&self.device, capacity)
+                .expect("create_buffer");
+
+            // The GPU is done with it, and nothing else holds on to it past the frame.
+            let old_buffer = std::mem::replace(&mut buffer.buffer, Arc::new(new_buffer));
+            if let Ok(old_buffer) = Arc::try_unwrap(old_buffer) {
+                self.device.immediate_destroy_buffer(old_buffer);
+            }
+        }
+
+        let dst = unsafe {
+            std::slice::from_raw_parts_mut(
+                buffer.buffer.allocation.mapped_ptr().unwrap().as_ptr()
+                    as *mut GpuInstanceBatchTransform,
+                self.gpu_instance_batch_transforms.len(),
+            )
         };
+        dst.copy_from_slice(&self.gpu_instance_batch_transforms);
+        buffer.generation = self.instance_batch_transforms_generation;
+
+        self.instance_batch_transform_buffer_index = buffer_index;
+        *self.instance_batch_transform_buffer.lock() = buffer.buffer.clone();
+    }
 
-        self.mesh_lights.push(MeshLightSet {
-            lights: mesh_lights,
-        });
+    // Called once the frame has been submitted.
+    fn retire_instance_batch_transform_buffer(&mut self) {
+        self.instance_batch_transform_buffers[self.instance_batch_transform_buffer_index]
+            .gpu_done_timeline_value = self.device.universal_queue.last_submitted_value();
+    }
 
-        MeshHandle(mesh_idx)
+    /// Index of the instance in the TLAS, as seen by `InstanceIndex()` in shaders.
+    /// Only valid until instances or batches are added or removed.
+    pub fn tlas_instance_index(&self, inst: InstanceHandle) -> Option<u32> {
+        self.instance_handle_to_index
+            .get(&inst)
+            .map(|&index| index as u32)
     }
 
-    pub fn add_instance(&mut self, mesh: MeshHandle, transform: Affine3A) -> InstanceHandle {
-        let handle = self.next_instance_handle;
-        self.next_instance_handle += 1;
-        let handle = InstanceHandle(handle);
+    /// Range of TLAS instance indices occupied by the batch, in the order of its transforms.
+    /// Only valid until instances or batches are added or removed.
+    pub fn tlas_instance_batch_range(
+        &self,
+        batch: InstanceBatchHandle,
+    ) -> Option<std::ops::Range<u32>> {
+        let batch = &self.instance_batches[*self.instance_batch_handle_to_index.get(&batch)?];
+        let start = self.instances.len() as u32 + batch.transform_offset;
+        Some(start..start + batch.instance_count)
+    }
 
-        let index = self.instances.len();
+    /// Inverse of `tlas_instance_index` and `tlas_instance_batch_range`, e.g. for
+    /// interpreting hits reported by application ray tracing passes.
+    pub fn instance_from_tlas_index(&self, index: u32) -> Option<TlasInstance> {
+        let index = index as usize;
+        if let Some(&inst) = self.instance_handles.get(index) {
+            return Some(TlasInstance::Instance(inst));
+        }
 
-        self.instances.push(MeshInstance {
-            transform,
-            prev_transform: transform,
-            mesh,
-            dynamic_parameters: InstanceDynamicParameters::default(),
-        });
-        self.instance_handles.push(handle);
+        let transform_index = (index - self.instances.len()) as u32;
+        let batch_instance = self
+            .instance_batches
+            .iter()
+            .zip(&self.instance_batch_handles)
+            .find(|(batch, _)| {
+                (batch.transform_offset..batch.transform_offset + batch.instance_count)
+                    .contains(&transform_index)
+            })
+            .map(|(batch, &handle)| {
+                TlasInstance::BatchInstance(handle, transform_index - batch.transform_offset)
+            });
+        if batch_instance.is_some() {
+            return batch_instance;
+        }
 
-        assert_eq!(self.instances.len(), self.instance_handles.len());
+        // Ray tracing proxies follow the batches, and report the instance they stand in for.
+        let proxy_index = index.checked_sub(self.expanded_instance_count())?;
+        self.ray_tracing_proxy_instances()
+            .nth(proxy_index)
+            .map(|(index, _)| TlasInstance::Instance(self.instance_handles[index]))
+    }
 
-        self.instance_handle_to_index.insert(handle, index);
+    /// Queues an off-screen capture of `instances`, framed to fit the view. See `ThumbnailRenderer`.
+    ///
+    /// The thumbnail takes a number of frames to converge, and is only rendered
+    /// when ray tracing is enabled; poll `take_thumbnail` for the result.
+    pub fn capture_thumbnail(
+        &mut self,
+        extent: [u32; 2],
+        instances: &[InstanceHandle],
+    ) -> ThumbnailHandle {
+        let mut bounds = [Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)];
+        for inst in instances {
+            let inst = &self.instances[self.instance_handle_to_index[inst]];
+            let [mesh_min, mesh_max] = self.mesh_bounds[inst.mesh.0];
+
+            for corner in 0..8 {
+                let pick =
+                    |bit: usize, min: f32, max: f32| if corner & bit != 0 { max } else { min };
+                let corner = Vec3::new(
+                    pick(1, mesh_min.x, mesh_max.x),
+                    pick(2, mesh_min.y, mesh_max.y),
+                    pick(4, mesh_min.z, mesh_max.z),
+                );
+                let corner = inst.transform.transform_point3(corner);
+                bounds = [bounds[0].min(corner), bounds[1].max(corner)];
+            }
+        }
 
-        handle
+        if instances.is_empty() {
+            bounds = [Vec3::splat(-1.0), Vec3::ONE];
+        }
+
+        self.thumbnails
+            .capture(extent, instances, bounds[0], bounds[1])
     }
 
-    pub fn remove_instance(&mut self, inst: InstanceHandle) {
-        let index = self
-            .instance_handle_to_index
-            .remove(&inst)
-            .expect("no such instance");
-        self.instances.swap_remove(index);
-        self.instance_handles.swap_remove(index);
+    /// Returns the thumbnail once it's been read back, and forgets about it.
+    pub fn take_thumbnail(&mut self, handle: ThumbnailHandle) -> Option<ThumbnailImage> {
+        self.thumbnails.take(handle)
+    }
 
-        // A new instance could have been moved into this slot in the vec.
-        // Make sure `instance_handle_to_index` reflects this.
-        if let Some(new_handle) = self.instance_handles.get(index).copied() {
-            self.instance_handle_to_index.insert(new_handle, index);
+    pub(super) fn record_scene_ray_tracing_passes(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        tlas: &rg::Handle<RayTracingAcceleration>,
+    ) {
+        self.ray_queries
+            .trace(rg, tlas, self.bindless_descriptor_set);
+        self.thumbnails
+            .render(rg, tlas, self.bindless_descriptor_set);
+
+        let data = SceneRayTracingData {
+            tlas,
+            bindless_descriptor_set: self.bindless_descriptor_set,
+        };
+
+        for pass in &mut self.scene_ray_tracing_passes {
+            pass(rg, &data);
         }
     }
 
-    pub fn set_instance_transform(&mut self, inst: InstanceHandle, transform: Affine3A) {
-        let index = self.instance_handle_to_index[&inst];
-        self.instances[index].transform = transform;
+    pub(crate) fn instance_triangle_lights(&self, inst: &MeshInstance) -> &[TriangleLight] {
+        let light_set = &self.mesh_lights[inst.mesh.0];
+        if inst.dynamic_parameters.flags & InstanceFlags::VIEWMODEL != 0 {
+            // Rasterized away from where the lights would be
+            &[]
+        } else if light_set.use_lights
+            || inst.dynamic_parameters.flags & InstanceFlags::EMISSIVE_ONLY != 0
+        {
+            &light_set.lights
+        } else {
+            &[]
+        }
     }
 
-    pub fn get_instance_dynamic_parameters(
-        &self,
-        inst: InstanceHandle,
-    ) -> &InstanceDynamicParameters {
-        let index = self.instance_handle_to_index[&inst];
-        &self.instances[index].dynamic_parameters
+    fn mesh_blas(&self, mesh: MeshHandle) -> &Arc<RayTracingAcceleration> {
+        self.mesh_blas[mesh.0].as_ref().expect("mesh was removed")
     }
 
-    pub fn get_instance_dynamic_parameters_mut(
-        &mut self,
-        inst: InstanceHandle,
-    ) -> &mut InstanceDynamicParameters {
-        let index = self.instance_handle_to_index[&inst];
-        &mut self.instances[index].dynamic_parameters
+    /// Total number of instances, including batched ones, and the TLAS instance count
+    /// without the combined BLAS of static instances.
+    pub(crate) fn expanded_instance_count(&self) -> usize {
+        self.instances.len() + self.instance_batch_transforms.len()
+    }
+
+    /// Regular instances with a ray tracing proxy, by index, along with the proxy.
+    /// Each proxy gets its own TLAS instance and dynamic parameters, after the batches.
+    fn ray_tracing_proxy_instances(&self) -> impl Iterator<Item = (usize, MeshHandle)> + '_ {
+        self.instances
+            .iter()
+            .enumerate()
+            .filter_map(|(index, inst)| Some((index, inst.ray_tracing_proxy?)))
     }
 
     pub(crate) fn build_ray_tracing_top_level_acceleration(&mut self) {
@@ -843,9 +3854,12 @@ impl WorldRenderer {
                         .instances
                         .iter()
                         .map(|inst| RayTracingInstanceDesc {
-                            blas: self.mesh_blas[inst.mesh.0].clone(),
+                            blas: Some(self.mesh_blas(inst.mesh).clone()),
                             transformation: inst.transform,
                             mesh_index: inst.mesh.0 as u32,
+                            mask: TLAS_MASK_SCENE,
+                            hit_group_offset: self.mesh_hit_group_offset(inst.mesh),
+                            opaque: false,
                         })
                         .collect::<Vec<_>>(),
                     preallocate_bytes: TLAS_PREALLOCATE_BYTES,
@@ -857,6 +3871,82 @@ impl WorldRenderer {
         self.tlas = Some(Arc::new(tlas));
     }
 
+    /// Restarts reference accumulation at full resolution, e.g. once a preview looks right.
+    pub fn restart_reference_at_full_resolution(&mut self) {
+        self.reference_preview_scale = 1.0;
+        self.reset_reference_accumulation = true;
+    }
+
+    /// Samples per pixel accumulated in reference mode since it last restarted.
+    pub fn reference_sample_count(&self) -> u32 {
+        self.reference_sample_count
+    }
+
+    /// Whether reference accumulation reached `ReferencePathTraceConfig::target_sample_count`,
+    /// and stopped.
+    pub fn is_reference_complete(&self) -> bool {
+        self.reference_config
+            .target_sample_count
+            .map_or(false, |target| self.reference_sample_count >= target)
+    }
+
+    /// Reads back the reference accumulation and its albedo and normal AOVs as linear floats,
+    /// e.g. to denoise them offline, on the next frame rendered in `RenderMode::Reference`.
+    /// Poll `take_reference_readback` for the result. Stalls the GPU for that frame.
+    pub fn request_reference_readback(&mut self) {
+        self.reference_readback_requested = true;
+    }
+
+    pub fn take_reference_readback(&mut self) -> Option<ReferenceReadback> {
+        self.finished_reference_readback.take()
+    }
+
+    /// GPU time of every render graph pass, in milliseconds, in the order they were recorded.
+    /// Timestamps are read back once their frame has finished, so these lag behind
+    /// the frame being prepared by the frames in flight. Empty until the first readback.
+    ///
+    /// Passes that overlap on the GPU are each timed in full, so the durations add up
+    /// to at least the GPU frame time; they match it when the passes run back to back.
+    pub fn last_frame_gpu_timings(&self) -> &[(String, f32)] {
+        &self.gpu_timings
+    }
+
+    // Refreshes `gpu_timings` from the last profiler report, reusing the pass name allocations.
+    fn update_gpu_timings(&mut self) {
+        let report = if let Some(report) = gpu_profiler::profiler().last_report() {
+            report
+        } else {
+            return;
+        };
+
+        self.gpu_timings
+            .resize_with(report.scopes.len(), Default::default);
+
+        for ((name, ms), scope) in self.gpu_timings.iter_mut().zip(report.scopes.iter()) {
+            name.clone_from(&scope.name);
+            *ms = scope.duration.ms() as f32;
+        }
+    }
+
+    /// Writes the reference accumulation to an EXR file on the next frame rendered in
+    /// `RenderMode::Reference`, to be resumed with `load_reference_state`, e.g. after a restart.
+    /// See `reference_state::write_reference_state` for the format. Stalls the GPU for that frame.
+    pub fn save_reference_state(&mut self, path: impl Into<PathBuf>) {
+        self.reference_save_request = Some(path.into());
+    }
+
+    /// Resumes reference accumulation from a file written by `save_reference_state`,
+    /// on the next frame rendered in `RenderMode::Reference`.
+    ///
+    /// The scene, camera and `reference_config` are assumed to match the saved render;
+    /// they aren't stored. A file whose resolution doesn't match the accumulation's
+    /// at that point is ignored, with an error logged.
+    pub fn load_reference_state(&mut self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        self.pending_reference_restore =
+            Some(crate::reference_state::read_reference_state(path.as_ref())?);
+        Ok(())
+    }
+
     #[allow(dead_code)]
     pub fn reset_frame_idx(&mut self) {
         self.frame_idx = 0;
@@ -871,35 +3961,255 @@ impl WorldRenderer {
             vk_sync::AccessType::AnyShaderReadOther,
         );
 
-        let instances = self
-            .instances
+        // Instances being captured by `thumbnails` are additionally visible to its rays.
+        let thumbnail_instances: HashSet<usize> = self
+            .thumbnails
+            .begin_frame(rg.device())
             .iter()
-            .map(|inst| RayTracingInstanceDesc {
-                blas: self.mesh_blas[inst.mesh.0].clone(),
-                transformation: inst.transform,
-                mesh_index: inst.mesh.0 as u32,
-            })
+            .filter_map(|inst| self.instance_handle_to_index.get(inst).copied())
+            .collect();
+
+        // Regular instances come first, followed by all the batches, then the ray tracing
+        // proxies, in the same order as in `instance_dynamic_parameters`. The combined BLAS
+        // of static instances goes last, since it has no dynamic parameters of its own.
+        let batch_instance_end = self.expanded_instance_count();
+        let proxy_instances = self.ray_tracing_proxy_instances().collect::<Vec<_>>();
+        let proxy_instance_end = batch_instance_end + proxy_instances.len();
+        let aggregate_instances = self
+            .instance_aggregate
+            .iter()
+            .map(InstanceAggregate::tlas_instance)
             .collect::<Vec<_>>();
+        let instance_count = proxy_instance_end + aggregate_instances.len();
+
+        // Persists across frames, so that mostly static scenes only upload the instances
+        // which were added, removed or moved. Grows in powers of two, dropping the contents.
+        let capacity = instance_count.max(1).next_power_of_two();
+        let mut instance_buffer = rg
+            .get_or_create_temporal(
+                "tlas.instances",
+                BufferDesc::new_gpu_only(
+                    capacity * RAY_TRACING_INSTANCE_SIZE,
+                    vk::BufferUsageFlags::STORAGE_BUFFER
+                        | vk::BufferUsageFlags::TRANSFER_DST
+                        | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+                        | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR,
+                ),
+            )
+            .unwrap();
+
+        let prev_slots: &[Option<TlasInstanceSlot>] = if self.tlas_instance_slots.len() == capacity
+        {
+            &self.tlas_instance_slots
+        } else {
+            &[]
+        };
+
+        let mut uploaded_slots = Vec::new();
+        let mut dirty_instances = Vec::new();
+
+        let is_stale = |index: usize, slot: &TlasInstanceSlot| {
+            prev_slots
+                .get(index)
+                .map_or(true, |prev| prev.as_ref() != Some(slot))
+        };
+
+        let scene_mask = |index: usize, inst: &MeshInstance| {
+            // Aggregated instances are traced through the combined BLAS instead.
+            let aggregated = self
+                .instance_aggregate
+                .as_ref()
+                .map_or(false, |aggregate| aggregate.contains(index));
+
+            if aggregated || inst.dynamic_parameters.flags & InstanceFlags::VIEWMODEL != 0 {
+                0
+            } else if inst.dynamic_parameters.flags & InstanceFlags::NO_GI_CONTRIBUTION != 0 {
+                TLAS_MASK_SCENE & !TLAS_MASK_GI
+            } else {
+                TLAS_MASK_SCENE
+            }
+        };
+
+        let instance_slots = self.instances.iter().enumerate().map(|(index, inst)| {
+            let mut mask = scene_mask(index, inst);
+            if inst.ray_tracing_proxy.is_some() {
+                mask &= TLAS_MASK_REFLECTION;
+            }
+            if thumbnail_instances.contains(&index) {
+                mask |= TLAS_MASK_THUMBNAIL;
+            }
+
+            (index, inst, inst.mesh, mask)
+        });
+
+        // Proxies are only seen by shadows and GI, and never by thumbnails.
+        let proxy_slots =
+            proxy_instances
+                .iter()
+                .enumerate()
+                .map(|(i, &(instance_index, proxy))| {
+                    let inst = &self.instances[instance_index];
+                    let mask = scene_mask(instance_index, inst) & !TLAS_MASK_REFLECTION;
+                    (batch_instance_end + i, inst, proxy, mask)
+                });
+
+        for (index, inst, mesh, mask) in instance_slots.chain(proxy_slots) {
+            let blas = (mask != 0).then(|| self.mesh_blas(mesh));
+            let hit_group_offset = self.mesh_hit_group_offset(mesh);
+            let slot = TlasInstanceSlot {
+                blas_address: blas.map_or(0, |blas| blas.device_address(&self.device)),
+                transform: inst.transform,
+                mesh_index: mesh.0 as u32,
+                mask,
+                hit_group_offset,
+            };
+
+            if is_stale(index, &slot) {
+                uploaded_slots.push((index, slot));
+                dirty_instances.push(RayTracingInstanceDesc {
+                    blas: blas.cloned(),
+                    transformation: slot.transform,
+                    mesh_index: slot.mesh_index,
+                    mask,
+                    hit_group_offset,
+                    // Opacity is up to the BLAS geometries; see `mesh_rt_geometries`.
+                    opaque: false,
+                });
+            }
+        }
+
+        for (i, desc) in aggregate_instances.into_iter().enumerate() {
+            let index = proxy_instance_end + i;
+            let slot = TlasInstanceSlot {
+                blas_address: desc
+                    .blas
+                    .as_ref()
+                    .map_or(0, |blas| blas.device_address(&self.device)),
+                transform: desc.transformation,
+                mesh_index: desc.mesh_index,
+                mask: desc.mask,
+                hit_group_offset: desc.hit_group_offset,
+            };
+
+            if is_stale(index, &slot) {
+                uploaded_slots.push((index, slot));
+                dirty_instances.push(desc);
+            }
+        }
+
+        if !dirty_instances.is_empty() {
+            // Consecutive slots are copied together.
+            let mut regions: Vec<vk::BufferCopy> = Vec::new();
+            for (src_index, &(dst_index, _)) in uploaded_slots.iter().enumerate() {
+                let src_offset = (src_index * RAY_TRACING_INSTANCE_SIZE) as u64;
+                let dst_offset = (dst_index * RAY_TRACING_INSTANCE_SIZE) as u64;
+
+                match regions.last_mut() {
+                    Some(region)
+                        if region.src_offset + region.size == src_offset
+                            && region.dst_offset + region.size == dst_offset =>
+                    {
+                        region.size += RAY_TRACING_INSTANCE_SIZE as u64;
+                    }
+                    _ => regions.push(vk::BufferCopy {
+                        src_offset,
+                        dst_offset,
+                        size: RAY_TRACING_INSTANCE_SIZE as u64,
+                    }),
+                }
+            }
+
+            let mut pass = rg.add_pass("upload tlas instances");
+            let instance_buffer_ref = pass.write(&mut instance_buffer, AccessType::TransferWrite);
+
+            pass.render(move |api| {
+                let resources = &mut api.resources;
+                let src_offset = resources.dynamic_constants.current_offset() as u64;
+                resources
+                    .execution_params
+                    .device
+                    .fill_ray_tracing_instance_buffer(
+                        resources.dynamic_constants,
+                        &dirty_instances,
+                    );
+
+                let src_buffer = api.resources.dynamic_constants.buffer.raw;
+                let dst_buffer = api.resources.buffer(instance_buffer_ref).raw;
+
+                for region in &mut regions {
+                    region.src_offset += src_offset;
+                }
+
+                unsafe {
+                    api.device()
+                        .raw
+                        .cmd_copy_buffer(api.cb.raw, src_buffer, dst_buffer, &regions);
+                }
+
+                Ok(())
+            });
+        }
+
+        self.pending_tlas_instance_slots = Some(PendingTlasInstanceSlots {
+            capacity,
+            uploaded: uploaded_slots,
+            gpu_written: self.instances.len()..batch_instance_end,
+        });
+
+        if !self.instance_batches.is_empty() {
+            let batch_transforms = rg.import(
+                self.instance_batch_transform_buffer.lock().clone(),
+                self.instance_batch_transforms_import_access(),
+            );
+
+            let mut dst_instance_offset = self.instances.len() as u32;
+            for batch in &self.instance_batches {
+                let blas_address = self.mesh_blas(batch.mesh).device_address(&self.device);
+                let instance_id_and_mask =
+                    (batch.mesh.0 as u32 & 0x00ffffff) | ((TLAS_MASK_SCENE as u32) << 24);
+                // No instance flags; opacity is up to the BLAS geometries.
+                let instance_sbt_offset_and_flags =
+                    self.mesh_hit_group_offset(batch.mesh) & 0x00ffffff;
+
+                rg::SimpleRenderPass::new_compute(
+                    rg.add_pass("batch tlas instances"),
+                    "/shaders/rt/instance_batch_tlas_instances.hlsl",
+                )
+                .read(&batch_transforms)
+                .write(&mut instance_buffer)
+                .constants((
+                    batch.transform_offset,
+                    batch.instance_count,
+                    dst_instance_offset,
+                    instance_id_and_mask,
+                    instance_sbt_offset_and_flags,
+                    blas_address as u32,
+                    (blas_address >> 32) as u32,
+                ))
+                .dispatch([batch.instance_count, 1, 1]);
+
+                dst_instance_offset += batch.instance_count;
+            }
+        }
 
         let mut pass = rg.add_pass("rebuild tlas");
+        let instance_buffer_ref = pass.read(&instance_buffer, AccessType::AnyShaderReadOther);
         let tlas_ref = pass.write(&mut tlas, AccessType::TransferWrite);
 
         let accel_scratch = self.accel_scratch.clone();
 
         pass.render(move |api| {
-            //let device = &api.device().raw;
-            let resources = &mut api.resources;
-            let instance_buffer_address = resources
-                .execution_params
-                .device
-                .fill_ray_tracing_instance_buffer(resources.dynamic_constants, &instances);
+            let instance_buffer_address = api
+                .resources
+                .buffer(instance_buffer_ref)
+                .device_address(api.device());
             let tlas = api.resources.rt_acceleration(tlas_ref);
 
             let cb = api.cb;
             api.device().rebuild_ray_tracing_top_acceleration(
                 cb.raw,
                 instance_buffer_address,
-                instances.len(),
+                instance_count,
                 tlas,
                 &accel_scratch,
             );
@@ -910,17 +4220,38 @@ impl WorldRenderer {
         tlas
     }
 
+    fn retire_tlas_instance_slots(&mut self) {
+        if let Some(pending) = self.pending_tlas_instance_slots.take() {
+            if self.tlas_instance_slots.len() != pending.capacity {
+                self.tlas_instance_slots = vec![None; pending.capacity];
+            }
+
+            for (index, slot) in pending.uploaded {
+                self.tlas_instance_slots[index] = Some(slot);
+            }
+
+            self.tlas_instance_slots[pending.gpu_written].fill(None);
+        }
+    }
+
     fn store_prev_mesh_transforms(&mut self) {
         for inst in &mut self.instances {
             inst.prev_transform = inst.transform;
+            inst.shutter_transforms = None;
+        }
+
+        for batch in &mut self.instance_batches {
+            batch.moved = false;
         }
     }
 
     fn update_pre_exposure(&mut self) {
         let dt = 1.0 / 60.0; // TODO
 
-        self.dynamic_exposure.update(-self.post.image_log2_lum, dt);
-        let ev_mult = (self.ev_shift + self.dynamic_exposure.ev_smoothed()).exp2();
+        self.dynamic_exposure
+            .update(&self.auto_exposure, -self.post.image_log2_lum, dt);
+        let ev_mult =
+            (self.ev_shift + self.dynamic_exposure.ev_smoothed(&self.auto_exposure)).exp2();
 
         let exposure_state = &mut self.exposure_state[self.render_mode as usize];
 
@@ -956,7 +4287,21 @@ impl WorldRenderer {
         rg: &mut rg::TemporalRenderGraph,
         frame_desc: &WorldFrameDesc,
     ) -> rg::Handle<Image> {
+        self.prepare_render_graph_with_depth(rg, frame_desc).color
+    }
+
+    /// `prepare_render_graph`, also returning the depth to composite external content with.
+    pub fn prepare_render_graph_with_depth(
+        &mut self,
+        rg: &mut rg::TemporalRenderGraph,
+        frame_desc: &WorldFrameDesc,
+    ) -> WorldRenderOutput {
         self.update_pre_exposure();
+        self.update_gpu_timings();
+        self.pending_reference_readback = None;
+        self.release_removed_meshes();
+        self.release_stale_instance_aggregates();
+        self.update_instance_aggregate();
 
         rg.predefined_descriptor_set_layouts.insert(
             1,
@@ -964,12 +4309,21 @@ impl WorldRenderer {
                 bindings: BINDLESS_DESCRIPTOR_SET_LAYOUT.clone(),
             },
         );
+        rg.predefined_shader_defines = self.material_features.shader_defines();
+        rg.predefined_hit_groups = self.predefined_hit_groups();
+        rg.predefined_any_hit = Some(ShaderSource::hlsl(DEFAULT_ANY_HIT));
 
         for image_lut in self.image_luts.iter_mut() {
             image_lut.compute_if_needed(rg);
         }
 
-        match self.render_mode {
+        self.upload_dirty_materials(rg);
+        self.update_deformed_meshes(rg);
+        self.update_instance_batch_transform_buffer();
+        self.instance_batch_transforms_written_on_gpu = self.expand_crowds(rg);
+        self.update_video_textures(rg);
+
+        let output = match self.render_mode {
             RenderMode::Standard => {
                 if USE_TAA_JITTER {
                     self.taa.current_supersample_offset = self.supersample_offsets
@@ -983,6 +4337,11 @@ impl WorldRenderer {
                     self.dlss.current_supersample_offset = self.taa.current_supersample_offset;
                 }
 
+                #[cfg(feature = "fsr2")]
+                {
+                    self.fsr2.current_supersample_offset = self.taa.current_supersample_offset;
+                }
+
                 self.prepare_render_graph_standard(rg, frame_desc)
             }
             RenderMode::Reference => {
@@ -993,9 +4352,33 @@ impl WorldRenderer {
                     self.dlss.current_supersample_offset = self.taa.current_supersample_offset;
                 }
 
+                #[cfg(feature = "fsr2")]
+                {
+                    self.fsr2.current_supersample_offset = self.taa.current_supersample_offset;
+                }
+
                 self.prepare_render_graph_reference(rg, frame_desc)
             }
-        }
+        };
+
+        self.image_metrics.render(rg, &output.color, self.frame_idx);
+
+        // A frame which fails to prepare is never retired, so the request stays until one is,
+        // and any stale capture is replaced.
+        self.pending_frame_dump = self
+            .frame_dump_request
+            .clone()
+            .map(|dir| (dir, rg.capture_all_resources(), rg.describe_graph()));
+
+        output
+    }
+
+    /// Writes every render graph resource of the next frame into `dir` once it's rendered,
+    /// for offline inspection and bug reports; see `frame_dump::write_frame_capture`.
+    /// The structure of the graph goes along, in `render_graph.dot` and `render_graph.json`.
+    /// Stalls the GPU for that frame.
+    pub fn dump_frame(&mut self, dir: impl Into<PathBuf>) {
+        self.frame_dump_request = Some(dir.into());
     }
 
     pub fn prepare_frame_constants(
@@ -1010,6 +4393,7 @@ impl WorldRenderer {
                 .unwrap_or(frame_desc.camera_matrices),
             frame_desc.render_extent,
         )
+        .physical_camera(frame_desc.physical_camera)
         .build();
 
         // Re-shuffle the jitter sequence if we've just used it up
@@ -1037,19 +4421,14 @@ impl WorldRenderer {
             .instances
             .iter()
             .flat_map(|inst| {
-                let (_scale, rotation, translation) =
-                    inst.transform.to_scale_rotation_translation();
-                let inst_position = translation;
-                let inst_rotation = rotation;
-
+                let transform = inst.transform;
                 let emissive_multiplier = Vec3::splat(inst.dynamic_parameters.emissive_multiplier);
 
-                self.mesh_lights[inst.mesh.0]
-                    .lights
+                self.instance_triangle_lights(inst)
                     .iter()
                     .map(move |light: &TriangleLight| {
                         light
-                            .transform(inst_position, inst_rotation)
+                            .transform(&transform)
                             .scale_radiance(emissive_multiplier)
                     })
             })
@@ -1061,21 +4440,39 @@ impl WorldRenderer {
             Default::default();
 
         self.ircache
-            .update_eye_position(view_constants.eye_position());
+            .update_eye_position(view_constants.eye_position(), self.world_units_per_meter);
 
         // Actually set the cascade constants we're using
         for (i, c) in self.ircache.constants().iter().copied().enumerate() {
             ircache_cascades[i] = c;
         }
 
+        let mut render_overrides = self.render_overrides;
+        render_overrides.set_flag(
+            RenderOverrideFlags::NO_IRCACHE,
+            !self.frame_graph_config.ircache
+                || self
+                    .render_overrides
+                    .has_flag(RenderOverrideFlags::NO_IRCACHE),
+        );
+
+        self.update_gi_history_scale(frame_desc.sun_direction, delta_time_seconds);
+        self.wind_state
+            .advance(&self.wind, self.world_units_per_meter, delta_time_seconds);
+        self.advance_video_textures(delta_time_seconds);
+        self.translucent_emissives.update_gi_lights();
+
         let real_sun_angular_radius = 0.53f32.to_radians() * 0.5;
+        let proxy_instances = self.ray_tracing_proxy_instances().collect::<Vec<_>>();
 
         let globals_offset = dynamic_constants.push(&FrameConstants {
             view_constants,
             sun_direction: frame_desc.sun_direction.extend(0.0),
             frame_index: self.frame_idx,
             delta_time_seconds,
-            sun_angular_radius_cos: (self.sun_size_multiplier * real_sun_angular_radius).cos(),
+            sun_angular_radius_cos: (frame_desc.sun_size_multiplier.max(0.0)
+                * real_sun_angular_radius)
+                .cos(),
 
             sun_color_multiplier: self.sun_color_multiplier.extend(0.0),
             sky_ambient: self.sky_ambient.extend(0.0),
@@ -1084,35 +4481,234 @@ impl WorldRenderer {
             pre_exposure: self.exposure_state().pre_mult,
             pre_exposure_prev: self.exposure_state().pre_mult_prev,
             pre_exposure_delta: self.exposure_state().pre_mult_delta,
-            pad0: 0.0,
+            gi_history_scale: self.gi_history_scale,
+
+            render_overrides,
 
-            render_overrides: self.render_overrides,
+            point_light_count: (self.point_lights.len()
+                + self.translucent_emissives.gi_lights().len()) as _,
+            world_units_per_meter: self.world_units_per_meter,
+            environment_map_rotation: self.ibl.rotation,
+            environment_map_intensity: self.ibl.intensity,
 
             ircache_grid_center: self.ircache.grid_center().extend(1.0),
             ircache_cascades,
+
+            material_categories: self.material_categories.map(|category| {
+                MaterialCategoryConstants {
+                    gi_strength: category.gi_strength.max(0.0),
+                    transmission_scale: category.transmission_scale.max(0.0),
+                    sheen: category.sheen.max(0.0),
+                    pad: 0,
+                }
+            }),
+
+            tonemapper: self.tonemapper as u32,
+
+            wind_gust_texture: self.wind.gust_texture.map_or(!0, |handle| handle.0),
+            wind_gust_strength: self.wind.gust_strength.max(0.0),
+            wind_gust_scale: self.wind.gust_scale.max(1e-3),
+            wind_direction_speed: WindState::direction_speed(&self.wind),
+            wind_offset_time: self.wind_state.offset_time(),
+
+            instance_count: self.instances.len() as u32,
+            instance_batch_count: (self.instance_batches.len() + proxy_instances.len()) as u32,
+            pad1: [0; 2],
         });
 
-        let instance_dynamic_parameters_offset = dynamic_constants
-            .push_from_iter(self.instances.iter().map(|inst| inst.dynamic_parameters));
+        // One entry per regular instance, followed by one per batch, and one per ray tracing
+        // proxy, which follow the batches in the TLAS as if they were batches of one instance.
+        // Ray tracing finds the batch of an `InstanceIndex()` past the regular instances
+        // in `instance_batch_ends_dyn`; see `instance_dynamic_parameters_index` in `frame_constants.hlsl`.
+        let batch_transform_count = self.instance_batch_transforms.len() as u32;
+
+        let instance_dynamic_parameters_offset = dynamic_constants.push_from_iter(
+            self.instances
+                .iter()
+                .map(|inst| inst.dynamic_parameters)
+                .chain(
+                    self.instance_batches
+                        .iter()
+                        .map(|batch| batch.dynamic_parameters),
+                )
+                .chain(
+                    proxy_instances
+                        .iter()
+                        .map(|&(index, _)| self.instances[index].dynamic_parameters),
+                ),
+        );
+        let instance_batch_ends_offset = dynamic_constants.push_from_iter(
+            self.instance_batches
+                .iter()
+                .map(|batch| batch.transform_offset + batch.instance_count)
+                .chain((1..=proxy_instances.len() as u32).map(|i| batch_transform_count + i)),
+        );
 
-        let triangle_lights_offset: u32 =
-            dynamic_constants.push_from_iter(triangle_lights.into_iter());
+        let triangle_lights_offset: u32 = dynamic_constants
+            .push_from_iter(build_triangle_light_alias_table(triangle_lights).into_iter());
+
+        // Lights standing in for translucent emissives go after the regular ones.
+        let point_lights_offset: u32 = dynamic_constants.push_from_iter(
+            self.point_lights
+                .values()
+                .chain(self.translucent_emissives.gi_lights())
+                .map(|light| {
+                    GpuPointLight::new(
+                        light,
+                        self.world_units_per_meter,
+                        self.lighting.point_light_cutoff,
+                    )
+                }),
+        );
 
         self.prev_camera_matrices = Some(frame_desc.camera_matrices);
+        self.prev_sun_direction = Some(frame_desc.sun_direction);
 
         rg::renderer::FrameConstantsLayout {
             globals_offset,
             instance_dynamic_parameters_offset,
             triangle_lights_offset,
+            point_lights_offset,
+            instance_batch_ends_offset,
+        }
+    }
+
+    fn update_gi_history_scale(&mut self, sun_direction: Vec3, delta_time_seconds: f32) {
+        let material_change = std::mem::take(&mut self.material_change);
+
+        if delta_time_seconds <= 0.0 {
+            return;
         }
+
+        let sun_angle_delta = self
+            .prev_sun_direction
+            .map_or(0.0, |prev| prev.dot(sun_direction).clamp(-1.0, 1.0).acos());
+        let sun_speed_deg = sun_angle_delta.to_degrees() / delta_time_seconds;
+        let target = 1.0 / (1.0 + sun_speed_deg / GI_HISTORY_HALVING_SUN_SPEED_DEG);
+
+        // Shorten the history quickly, but lengthen it slowly, so that variations
+        // in the frame rate or sun speed don't make the GI pulse.
+        let rate = if target < self.gi_history_scale {
+            20.0
+        } else {
+            2.0
+        };
+        let t = 1.0 - (-rate * delta_time_seconds).exp();
+        self.gi_history_scale =
+            (self.gi_history_scale + (target - self.gi_history_scale) * t).clamp(0.05, 1.0);
+
+        // Materials tend to change abruptly (blinking lights, screens, variant switches),
+        // so shorten the history right away instead of easing into it.
+        self.gi_history_scale = self.gi_history_scale.min(1.0 - 0.95 * material_change);
     }
 
     pub fn retire_frame(&mut self) {
+        self.retire_instance_batch_transform_buffer();
+        self.ray_queries
+            .retire(self.device.universal_queue.last_submitted_value());
+        self.thumbnails
+            .retire(self.device.universal_queue.last_submitted_value());
         self.frame_idx = self.frame_idx.overflowing_add(1).0;
         self.store_prev_mesh_transforms();
+        self.retire_crowds();
+        self.retire_video_textures();
+        self.retire_tlas_instance_slots();
+
+        if let Some(pending) = self.pending_reference_readback.take() {
+            if pending.for_application {
+                self.reference_readback_requested = false;
+            }
+            if pending.save_to.is_some() {
+                self.reference_save_request = None;
+            }
+
+            // The copies are only complete once the frame has finished on the GPU.
+            if let Err(err) = unsafe { self.device.raw.device_wait_idle() } {
+                error!("Failed to wait for the reference readback: {:?}", err);
+            } else if let Some(readback) =
+                unpack_reference_readback(&pending.buffer, pending.extent, pending.sample_count)
+            {
+                #[cfg(feature = "oidn")]
+                if pending.for_denoiser {
+                    self.oidn.denoise(readback.clone());
+                }
+
+                if let Some(path) = pending.save_to {
+                    match crate::reference_state::write_reference_state(&readback, &path) {
+                        Ok(()) => info!(
+                            "Saved the reference state at {} samples to {:?}",
+                            readback.sample_count, path
+                        ),
+                        Err(err) => {
+                            error!(
+                                "Failed to save the reference state to {:?}: {:#}",
+                                path, err
+                            )
+                        }
+                    }
+                }
+
+                if pending.for_application {
+                    self.finished_reference_readback = Some(readback);
+                }
+            }
+        }
+
+        if let Some((dir, capture, graph)) = self.pending_frame_dump.take() {
+            self.frame_dump_request = None;
+
+            // The copies are only complete once the frame has finished on the GPU.
+            if let Err(err) = unsafe { self.device.raw.device_wait_idle() } {
+                error!("Failed to wait for the frame to dump: {:?}", err);
+                return;
+            }
+
+            match crate::frame_dump::write_frame_capture(&capture, &dir)
+                .and_then(|()| crate::frame_dump::write_graph_description(&graph, &dir))
+            {
+                Ok(()) => info!(
+                    "Dumped {} frame resources to {:?} ({} skipped)",
+                    capture.resources.len(),
+                    dir,
+                    capture.skipped.len()
+                ),
+                Err(err) => error!("Failed to dump the frame to {:?}: {:#}", dir, err),
+            }
+        }
     }
 }
 
+pub(crate) fn affine_to_row_major_3x4(transform: &Affine3A) -> [f32; 12] {
+    [
+        transform.x_axis.x,
+        transform.y_axis.x,
+        transform.z_axis.x,
+        transform.translation.x,
+        transform.x_axis.y,
+        transform.y_axis.y,
+        transform.z_axis.y,
+        transform.translation.y,
+        transform.x_axis.z,
+        transform.y_axis.z,
+        transform.z_axis.z,
+        transform.translation.z,
+    ]
+}
+
+fn create_instance_batch_transform_buffer(
+    device: &device::Device,
+    capacity: usize,
+) -> Result<Buffer, BackendError> {
+    device.create_buffer(
+        BufferDesc::new_cpu_to_gpu(
+            capacity * size_of::<GpuInstanceBatchTransform>(),
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+        ),
+        "instance batch transforms",
+        None,
+    )
+}
+
 fn radical_inverse(mut n: u32, base: u32) -> f32 {
     let mut val = 0.0f32;
     let inv_base = 1.0f32 / base as f32;
@@ -1127,3 +4723,148 @@ fn radical_inverse(mut n: u32, base: u32) -> f32 {
 
     val
 }
+
+// Writes a per-mesh item, either into a reused slot, or a new one at the end.
+// Object-space bounding box, as `[min, max]`
+fn vertex_bounds(verts: &[PackedVertex]) -> [Vec3; 2] {
+    verts.iter().fold(
+        [Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)],
+        |[min, max], vert| {
+            let pos = Vec3::from(vert.pos);
+            [min.min(pos), max.max(pos)]
+        },
+    )
+}
+
+// BLAS geometries of a mesh: its leading `opaque_index_count` indices, built opaque, and the rest,
+// which run any-hit shaders. `PrimitiveIndex()` restarts at zero in the second geometry, so hit
+// shaders add `GpuMesh::opaque_triangle_count` to it there; see `RtHitInstance::primitive_offset`.
+fn mesh_rt_geometries(
+    vertex_buffer: vk::DeviceAddress,
+    index_buffer: vk::DeviceAddress,
+    index_count: usize,
+    opaque_index_count: usize,
+    max_vertex: u32,
+) -> Vec<RayTracingGeometryDesc> {
+    [
+        (0, opaque_index_count, true),
+        (opaque_index_count, index_count - opaque_index_count, false),
+    ]
+    .iter()
+    .copied()
+    .filter(|&(_, index_count, _)| index_count > 0)
+    .map(
+        |(first_index, index_count, opaque)| RayTracingGeometryDesc {
+            geometry_type: RayTracingGeometryType::Triangle,
+            vertex_buffer,
+            // `index_offset` of the parts isn't used by BLAS builds.
+            index_buffer: index_buffer + (first_index * size_of::<u32>()) as u64,
+            vertex_format: vk::Format::R32G32B32_SFLOAT,
+            vertex_stride: size_of::<PackedVertex>(),
+            parts: vec![RayTracingGeometryPart {
+                index_count,
+                index_offset: 0,
+                max_vertex,
+            }],
+            transform_buffer: None,
+            opaque,
+        },
+    )
+    .collect()
+}
+
+fn set_or_push<T>(items: &mut Vec<T>, index: usize, item: T) {
+    if index < items.len() {
+        items[index] = item;
+    } else {
+        assert_eq!(index, items.len());
+        items.push(item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Probability of each light being picked as in `select_triangle_light` in `lights/triangle.hlsl`
+    fn alias_table_pmf(table: &[GpuTriangleLight]) -> Vec<f32> {
+        let bucket_prob = 1.0 / table.len() as f32;
+        let mut pmf = vec![0.0; table.len()];
+        for (i, entry) in table.iter().enumerate() {
+            pmf[i] += bucket_prob * entry.alias_prob;
+            pmf[entry.alias_idx as usize] += bucket_prob * (1.0 - entry.alias_prob);
+        }
+        pmf
+    }
+
+    fn light_with_power(power: f32) -> TriangleLight {
+        // Unit right triangle, with an area of 0.5, and a gray radiance.
+        TriangleLight {
+            verts: [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+            radiance: [power * 2.0; 3],
+        }
+    }
+
+    #[test]
+    fn triangle_light_alias_table_reproduces_powers() {
+        // Includes lights without any power, which must never be picked.
+        let mut state = 0x2545f491u32;
+        let powers: Vec<f32> = (0..1000)
+            .map(|i| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                if i % 7 == 0 {
+                    0.0
+                } else {
+                    (state % 1000) as f32 * 0.01
+                }
+            })
+            .collect();
+
+        let table = build_triangle_light_alias_table(
+            powers.iter().copied().map(light_with_power).collect(),
+        );
+        let total_power: f32 = powers.iter().sum();
+
+        for ((picked, power), entry) in alias_table_pmf(&table).iter().zip(&powers).zip(&table) {
+            let expected = power / total_power;
+            assert!(
+                (picked - expected).abs() < 1e-5,
+                "{} != {}",
+                picked,
+                expected
+            );
+            assert!((entry.selection_pmf - expected).abs() < 1e-5);
+
+            if *power == 0.0 {
+                assert_eq!(*picked, 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn triangle_light_alias_table_skips_lights_without_power() {
+        // The total power is rounded up with each of the small lights added to the large one,
+        // leaving the alias table short of more than a whole bucket, which the light without
+        // any power would otherwise end up with.
+        let powers: Vec<f32> = [0.0, 16777216.0]
+            .into_iter()
+            .chain(std::iter::repeat(1.5).take(8000))
+            .collect();
+
+        let table = build_triangle_light_alias_table(
+            powers.iter().copied().map(light_with_power).collect(),
+        );
+        assert_eq!(alias_table_pmf(&table)[0], 0.0);
+    }
+
+    #[test]
+    fn triangle_light_alias_table_is_uniform_without_power() {
+        let table = build_triangle_light_alias_table(vec![light_with_power(0.0); 4]);
+        for (picked, entry) in alias_table_pmf(&table).iter().zip(&table) {
+            assert_eq!(*picked, 0.25);
+            assert_eq!(entry.selection_pmf, 0.25);
+        }
+    }
+}
@@ -9,6 +9,30 @@ pub struct CameraMatrices {
     pub view_to_world: Mat4,
 }
 
+/// Thin lens of a physical camera, used for depth of field. All lengths are in scene units.
+#[derive(PartialEq, Clone, Copy)]
+#[repr(C)]
+pub struct PhysicalCameraParams {
+    pub focal_length: f32,
+
+    /// Diameter of the aperture; the focal length divided by the f-number.
+    /// Zero makes a pinhole camera, with everything in focus.
+    pub aperture_diameter: f32,
+
+    /// Distance from the camera to the plane in focus.
+    pub focus_distance: f32,
+}
+
+impl Default for PhysicalCameraParams {
+    fn default() -> Self {
+        Self {
+            focal_length: 0.05,
+            aperture_diameter: 0.0,
+            focus_distance: 10.0,
+        }
+    }
+}
+
 impl CameraMatrices {
     pub fn eye_position(&self) -> Vec3 {
         (self.view_to_world * Vec4::new(0.0, 0.0, 0.0, 1.0)).truncate()
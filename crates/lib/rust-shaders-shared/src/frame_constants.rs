@@ -2,6 +2,7 @@ use crate::{render_overrides::RenderOverrides, view_constants::ViewConstants};
 use macaw::{IVec4, Vec4};
 
 pub const IRCACHE_CASCADE_COUNT: usize = 12;
+pub const MATERIAL_CATEGORY_COUNT: usize = 4;
 
 #[repr(C, align(16))]
 #[derive(Copy, Clone, Default)]
@@ -10,6 +11,29 @@ pub struct IrcacheCascadeConstants {
     pub voxels_scrolled_this_frame: IVec4,
 }
 
+/// Tuning shared by all materials of a category, indexed by `MaterialCategory`.
+#[repr(C, align(16))]
+#[derive(Copy, Clone)]
+pub struct MaterialCategoryConstants {
+    pub gi_strength: f32,
+    pub transmission_scale: f32,
+    pub sheen: f32,
+    pub pad: u32,
+}
+
+impl Default for MaterialCategoryConstants {
+    fn default() -> Self {
+        Self {
+            gi_strength: 1.0,
+            transmission_scale: 1.0,
+            sheen: 0.0,
+            pad: 0,
+        }
+    }
+}
+
+// Changes must bump `CONSTANTS_LAYOUT_VERSION` in kajiya's `constants_layout.rs`,
+// and be listed in `frame_constants_layout` there.
 #[repr(C, align(16))]
 #[derive(Copy, Clone)]
 pub struct FrameConstants {
@@ -28,10 +52,33 @@ pub struct FrameConstants {
     pub pre_exposure: f32,
     pub pre_exposure_prev: f32,
     pub pre_exposure_delta: f32,
-    pub pad0: f32,
+    pub gi_history_scale: f32,
 
     pub render_overrides: RenderOverrides,
 
+    pub point_light_count: u32,
+    pub world_units_per_meter: f32,
+    pub environment_map_rotation: f32,
+    pub environment_map_intensity: f32,
+
     pub ircache_grid_center: Vec4,
     pub ircache_cascades: [IrcacheCascadeConstants; IRCACHE_CASCADE_COUNT],
+
+    pub material_categories: [MaterialCategoryConstants; MATERIAL_CATEGORY_COUNT],
+
+    /// See `Tonemapper` in `world_renderer.rs`
+    pub tonemapper: u32,
+
+    // See `Wind` in kajiya's `wind.rs`, and `inc/wind.hlsl`
+    pub wind_gust_texture: u32,
+    pub wind_gust_strength: f32,
+    pub wind_gust_scale: f32,
+    pub wind_direction_speed: Vec4,
+    pub wind_offset_time: Vec4,
+
+    // Entries of `instance_dynamic_parameters_dyn`: one per regular instance, then one per batch,
+    // and one per ray tracing proxy, which are counted as batches of a single instance.
+    pub instance_count: u32,
+    pub instance_batch_count: u32,
+    pub pad1: [u32; 2],
 }
@@ -19,6 +19,7 @@ pub struct GbufferData {
     pub normal: Vec3,
     pub roughness: f32,
     pub metalness: f32,
+    pub transmission: f32,
 }
 
 pub fn roughness_to_perceptual_roughness(r: f32) -> f32 {
@@ -39,10 +40,10 @@ impl GbufferData {
             v: UVec4::new(
                 pack_color_888(self.albedo),
                 pack_normal_11_10_11(self.normal).to_bits(),
-                vec2_to_f16x2(vec2(
-                    roughness_to_perceptual_roughness(self.roughness),
-                    self.metalness,
-                )),
+                (vec2_to_f16x2(vec2(roughness_to_perceptual_roughness(self.roughness), 0.0))
+                    & 0xffff)
+                    | (((self.metalness.clamp(0.0, 1.0) * 255.0 + 0.5) as u32) << 16)
+                    | (((self.transmission.clamp(0.0, 1.0) * 255.0 + 0.5) as u32) << 24),
                 float3_to_rgb9e5(self.emissive),
             ),
         }
@@ -52,16 +53,17 @@ impl GbufferData {
 impl GbufferDataPacked {
     pub fn unpack(&self) -> GbufferData {
         #[cfg(not(target_arch = "spirv"))]
-        let roughness_metalness: Vec2 = Vec2::new(0.0, 0.0);
+        let perceptual_roughness: f32 = 0.0;
         #[cfg(target_arch = "spirv")]
-        let roughness_metalness: Vec2 = f16x2_to_vec2(self.v.z);
+        let perceptual_roughness: f32 = f16x2_to_vec2(self.v.z & 0xffff).x;
 
         GbufferData {
             albedo: self.unpack_albedo(),
             emissive: rgb9e5_to_float3(self.v.w),
             normal: self.unpack_normal(),
-            roughness: perceptual_roughness_to_roughness(roughness_metalness.x),
-            metalness: roughness_metalness.y,
+            roughness: perceptual_roughness_to_roughness(perceptual_roughness),
+            metalness: ((self.v.z >> 16) & 0xff) as f32 / 255.0,
+            transmission: (self.v.z >> 24) as f32 / 255.0,
         }
     }
 
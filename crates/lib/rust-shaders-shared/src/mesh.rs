@@ -9,14 +9,20 @@ pub struct MeshDescriptor {
     pub vertex_mat_offset: u32,
     pub vertex_aux_offset: u32,
     pub vertex_tangent_offset: u32,
-    pub mat_data_offset: u32,
+    pub material_offset: u32,
     pub index_offset: u32,
+    pub ray_bias: f32,
+    pub opaque_triangle_count: u32,
 }
 
 #[repr(C, align(16))]
 #[derive(Copy, Clone)]
 pub struct InstanceDynamicConstants {
     pub emissive_multiplier: f32,
+    pub mip_bias: f32,
+    pub max_anisotropy: u32,
+    pub flags: u32,
+    pub gi_reception: f32,
 }
 
 #[derive(Clone, Copy)]
@@ -85,11 +91,15 @@ impl TextureMapsBuilder {
 pub struct MaterialDescriptor {
     pub base_color_mult: Vec4,
     pub maps: TextureMaps,
+    pub transmission_map: u32,
     pub roughness_mult: f32,
     pub metalness_factor: f32,
     pub emissive: Vec4,
     pub flags: u32,
     pub map_transforms: [[f32; 6]; 4],
+    pub ao_strength: f32,
+    pub map_samplers: u32,
+    pub transmission: f32,
 }
 
 impl MaterialDescriptor {
@@ -102,20 +112,28 @@ impl MaterialDescriptor {
             data[offset + 6],
             data[offset + 7],
         ));
-        let roughness_mult = f32::from_bits(data[offset + 8]);
-        let metalness_factor = f32::from_bits(data[offset + 9]);
-        let emissive = load_vec4(data, offset + 10);
-        let flags = data[offset + 15];
-        let map_transforms = load_map_transforms(data, offset + 16);
+        let transmission_map = data[offset + 8];
+        let roughness_mult = f32::from_bits(data[offset + 9]);
+        let metalness_factor = f32::from_bits(data[offset + 10]);
+        let emissive = load_vec4(data, offset + 11);
+        let flags = data[offset + 16];
+        let map_transforms = load_map_transforms(data, offset + 17);
+        let ao_strength = f32::from_bits(data[offset + 41]);
+        let map_samplers = data[offset + 42];
+        let transmission = f32::from_bits(data[offset + 43]);
 
         Self {
             base_color_mult,
             maps,
+            transmission_map,
             roughness_mult,
             metalness_factor,
             emissive,
             flags,
             map_transforms,
+            ao_strength,
+            map_samplers,
+            transmission,
         }
     }
     pub fn transform_uv(&self, uv: Vec2, map_idx: usize) -> Vec2 {
@@ -4,6 +4,7 @@ pub mod RenderOverrideFlags {
     pub const NO_NORMAL_MAPS: u32 = 1 << 1;
     pub const FLIP_NORMAL_MAP_YZ: u32 = 1 << 2;
     pub const NO_METAL: u32 = 1 << 3;
+    pub const NO_IRCACHE: u32 = 1 << 4;
 }
 
 #[repr(C, align(16))]
@@ -1,4 +1,4 @@
-use crate::camera::CameraMatrices;
+use crate::camera::{CameraMatrices, PhysicalCameraParams};
 use macaw::{Mat4, UVec2, Vec2, Vec3};
 
 #[derive(Clone, Copy)]
@@ -20,6 +20,12 @@ pub struct ViewConstants {
 
     pub sample_offset_pixels: Vec2,
     pub sample_offset_clip: Vec2,
+
+    // See `PhysicalCameraParams`
+    pub focal_length: f32,
+    pub aperture_diameter: f32,
+    pub focus_distance: f32,
+    pub _pad: f32,
 }
 
 impl ViewConstants {
@@ -33,6 +39,7 @@ impl ViewConstants {
             camera_matrices: camera_matrices.into(),
             prev_camera_matrices: prev_camera_matrices.into(),
             pixel_offset: Vec2::ZERO,
+            physical_camera: Default::default(),
         }
     }
 
@@ -80,6 +87,7 @@ pub struct VieportConstantBuilder {
     camera_matrices: CameraMatrices,
     prev_camera_matrices: CameraMatrices,
     pixel_offset: Vec2,
+    physical_camera: PhysicalCameraParams,
 }
 
 impl VieportConstantBuilder {
@@ -89,6 +97,11 @@ impl VieportConstantBuilder {
         self
     }
 
+    pub fn physical_camera(mut self, v: PhysicalCameraParams) -> Self {
+        self.physical_camera = v;
+        self
+    }
+
     pub fn build(self) -> ViewConstants {
         let clip_to_prev_clip = self.prev_camera_matrices.view_to_clip
             * self.prev_camera_matrices.world_to_view
@@ -112,6 +125,11 @@ impl VieportConstantBuilder {
 
             sample_offset_pixels: Vec2::ZERO,
             sample_offset_clip: Vec2::ZERO,
+
+            focal_length: self.physical_camera.focal_length,
+            aperture_diameter: self.physical_camera.aperture_diameter,
+            focus_distance: self.physical_camera.focus_distance,
+            _pad: 0.0,
         };
 
         res.set_pixel_offset(self.pixel_offset, self.render_extent);
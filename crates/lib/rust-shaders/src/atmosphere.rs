@@ -19,6 +19,8 @@ const C_MIE: Vec3 = const_vec3!([3.996 * 1e-6, 3.996 * 1e-6, 3.996 * 1e-6]);
 const C_OZONE: Vec3 = const_vec3!([0.650 * 1e-6, 1.881 * 1e-6, 0.085 * 1e-6]);
 
 const ATMOSPHERE_DENSITY: f32 = 1.0;
+// Solar illuminance in radiance units: `SOLAR_ILLUMINANCE_LUX / NITS_PER_RADIANCE_UNIT`
+// from `kajiya::units`
 const EXPOSURE: f32 = 20.0;
 
 /// Optical depth is a unitless measurement of the amount of absorption of a participating medium (such as the atmosphere).
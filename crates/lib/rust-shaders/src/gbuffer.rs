@@ -9,6 +9,7 @@ pub struct GBufferData {
     pub normal: Vec3,
     pub roughness: f32,
     pub metalness: f32,
+    pub transmission: f32,
 }
 
 impl GBufferData {
@@ -18,12 +19,12 @@ impl GBufferData {
         res.x = util::pack_color_888(self.albedo);
         res.y = util::pack_normal_11_10_11(self.normal) as u32;
 
-        let roughness_metalness = Vec2::new(
-            util::roughness_to_perceptual_roughness(self.roughness),
-            self.metalness,
-        );
+        let roughness = Vec2::new(util::roughness_to_perceptual_roughness(self.roughness), 0.0);
 
-        res.z = spirv_std::float::vec2_to_f16x2(roughness_metalness);
+        // Roughness gets 16 bits of half float; metalness and transmission get 8 bits of unorm each.
+        res.z = (spirv_std::float::vec2_to_f16x2(roughness) & 0xffff)
+            | (((self.metalness.clamp(0.0, 1.0) * 255.0 + 0.5) as u32) << 16)
+            | (((self.transmission.clamp(0.0, 1.0) * 255.0 + 0.5) as u32) << 24);
         res.w = util::float3_to_rgb9e5(self.emissive);
 
         res